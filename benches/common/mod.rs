@@ -0,0 +1,67 @@
+//! Helpers of the benchmarks: generated libraries and running zik on them.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const LIBRARY_SIZES: [usize; 2] = [500, 2000];
+
+pub fn zik(data_dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_zik-rust"))
+        .arg("--data-dir")
+        .arg(data_dir)
+        .args(args)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success(), "zik {:?} failed", args);
+}
+
+/// A generated library and a data folder, removed when dropped.
+pub struct BenchDir {
+    path: PathBuf,
+}
+
+impl BenchDir {
+    pub fn new(files: usize) -> BenchDir {
+        let path = env::temp_dir().join(format!("zik-bench-{}-{}", std::process::id(), files));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        let bench_dir = BenchDir { path };
+        zik(
+            &bench_dir.data(),
+            &[
+                "bench",
+                "generate",
+                bench_dir.library().to_str().unwrap(),
+                "--files",
+                &files.to_string(),
+            ],
+        );
+        bench_dir
+    }
+
+    pub fn library(&self) -> PathBuf {
+        self.path.join("library")
+    }
+
+    pub fn data(&self) -> PathBuf {
+        self.path.join("data")
+    }
+
+    /// Scans the library into a new database.
+    pub fn scan(&self) {
+        let _ = fs::remove_dir_all(self.data());
+        zik(&self.data(), &["scan", self.library().to_str().unwrap()]);
+    }
+}
+
+impl Drop for BenchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
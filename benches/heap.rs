@@ -0,0 +1,60 @@
+//! The heap allocations of scans, per file: `cargo bench --bench heap --features heap-profile`.
+//!
+//! Allocations aren't timed so criterion isn't used, the counts are printed to be compared
+//! from commit to commit. See `src/heap.rs`.
+
+use std::process::{Command, Stdio};
+
+mod common;
+
+use common::{BenchDir, LIBRARY_SIZES};
+
+/// Runs zik, returning its allocations, bytes allocated and peak bytes in use.
+fn zik_heap(bench_dir: &BenchDir, args: &[&str]) -> (u64, u64, u64) {
+    let output = Command::new(env!("CARGO_BIN_EXE_zik-rust"))
+        .arg("--data-dir")
+        .arg(bench_dir.data())
+        .args(args)
+        .stdout(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "zik {:?} failed", args);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let counts: Vec<u64> = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("heap: "))
+        .expect("no heap report, build with --features heap-profile")
+        .split(' ')
+        .map(|count| count.parse().unwrap())
+        .collect();
+
+    (counts[0], counts[1], counts[2])
+}
+
+fn main() {
+    for files in LIBRARY_SIZES {
+        let bench_dir = BenchDir::new(files);
+        let library = bench_dir.library();
+
+        // The first scan inserts every track, the second one finds them all again
+        for (name, fresh) in [("scan", true), ("rescan", false)] {
+            if fresh {
+                let _ = std::fs::remove_dir_all(bench_dir.data());
+            }
+            let (allocations, bytes, peak) =
+                zik_heap(&bench_dir, &["scan", library.to_str().unwrap()]);
+
+            println!(
+                "{}/{}: {} allocations ({} per file), {} bytes ({} per file), peak {} bytes",
+                name,
+                files,
+                allocations,
+                allocations / files as u64,
+                bytes,
+                bytes / files as u64,
+                peak
+            );
+        }
+    }
+}
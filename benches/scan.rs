@@ -0,0 +1,111 @@
+//! Benchmarks of the tag parsers, of scans into the database and of full-text searches.
+//!
+//! The libraries are generated with `zik bench generate`, run with `cargo bench`.
+
+use std::io::Cursor;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+mod common;
+
+use common::{BenchDir, LIBRARY_SIZES};
+
+// The Ogg fixture has no parser outside of the crate
+#[allow(dead_code)]
+#[path = "../src/fixtures.rs"]
+mod fixtures;
+
+fn tag_parsing(c: &mut Criterion) {
+    let flac = fixtures::flac(
+        240_000,
+        &[
+            ("ARTIST", "Artist"),
+            ("ALBUM", "Album"),
+            ("DATE", "1999"),
+            ("TITLE", "Title"),
+            ("TRACKNUMBER", "1/10"),
+            ("GENRE", "Rock"),
+            ("COMMENT", "live acoustic session"),
+        ],
+    );
+    let mp3 = fixtures::mp3(&[
+        ("TPE1", "Artist"),
+        ("TALB", "Album"),
+        ("TYER", "1999"),
+        ("TIT2", "Title"),
+        ("TRCK", "1/10"),
+        ("TCON", "Rock"),
+        ("TLEN", "240000"),
+    ]);
+    let m4a = fixtures::mp4(
+        240_000,
+        &[
+            ("\u{a9}ART", "Artist"),
+            ("\u{a9}alb", "Album"),
+            ("\u{a9}day", "1999"),
+            ("\u{a9}nam", "Title"),
+            ("trkn", "1/10"),
+            ("\u{a9}gen", "Rock"),
+            ("\u{a9}cmt", "live acoustic session"),
+        ],
+    );
+
+    let mut group = c.benchmark_group("tag parsing");
+    group.bench_function("flac", |b| {
+        b.iter(|| metaflac::Tag::read_from(&mut Cursor::new(black_box(&flac))).unwrap())
+    });
+    group.bench_function("mp3", |b| {
+        b.iter(|| id3::Tag::read_from(&mut Cursor::new(black_box(&mp3))).unwrap())
+    });
+    group.bench_function("m4a", |b| {
+        b.iter(|| mp4parse::read_mp4(&mut Cursor::new(black_box(&m4a))).unwrap())
+    });
+    group.finish();
+}
+
+/// Scans, most of the time of which is inserting the tracks.
+fn scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(10);
+
+    for files in LIBRARY_SIZES {
+        let bench_dir = BenchDir::new(files);
+        group.throughput(Throughput::Elements(files as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(files),
+            &bench_dir,
+            |b, bench_dir| b.iter(|| bench_dir.scan()),
+        );
+    }
+    group.finish();
+}
+
+/// Searches of the track comments, like `zik note search` does.
+fn comment_search(c: &mut Criterion) {
+    let bench_dir = BenchDir::new(LIBRARY_SIZES[LIBRARY_SIZES.len() - 1]);
+    bench_dir.scan();
+    let db = rusqlite::Connection::open(bench_dir.data().join("data.db")).unwrap();
+
+    let mut group = c.benchmark_group("comment search");
+    for query in ["live", "acoustic session", "rare OR outtake", "remaster*"] {
+        let mut stmt = db
+            .prepare(
+                "SELECT track.id
+                 FROM track_comment_fts
+                 JOIN track ON track.id = track_comment_fts.rowid
+                 WHERE track_comment_fts MATCH $query
+                 ORDER BY track_comment_fts.rank",
+            )
+            .unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(query), query, |b, query| {
+            b.iter(|| {
+                let rows = stmt.query_map([query], |row| row.get::<_, i64>(0)).unwrap();
+                rows.count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tag_parsing, scan, comment_search);
+criterion_main!(benches);
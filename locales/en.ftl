@@ -0,0 +1,655 @@
+# Messages of the command line, in English. Every message is here, the other catalogs fall
+# back to this one for those they don't have. See src/i18n.rs for the syntax understood.
+
+sqlite-error = SQLite error, { $err }
+io-error = I/O error, { $err }
+data-folder-not-found = data folder for Zik not found
+init-database-error = unable to initialize the database, err: { $err }
+init-password-key-error = unable to encrypt the passwords of the users, err: { $err }
+no-library = no library configured, set it with `zik config library <path>`
+no-inbox = no inbox configured, set it with `zik config inbox <path>`
+artist-not-found = no artist named "{ $name }"
+track-not-found = no track at "{ $path }"
+track-id-not-found = no track with id or path "{ $value }"
+album-id-not-found = no album with id or name "{ $value }"
+playlist-not-found = no playlist with id "{ $value }"
+collection-not-found = no collection named "{ $name }"
+user-not-found = no user named "{ $name }"
+invalid-workers = number of workers "{ $value }" is invalid
+invalid-limit = limit "{ $value }" is invalid
+invalid-period = period "{ $value }" is invalid, expected a number of days (90d), weeks (4w), months (6m) or years (1y)
+
+## Configuration
+
+config-invalid-key = key name `{ $key }` is invalid
+config-secret = `{ $key }` is a secret, set it with `zik secrets set { $key }` which keeps it in the keyring
+config-no-value = no value for key name `{ $key }`
+config-library-path = could not resolve library path: { $err }
+config-invalid-value = `{ $key }` value "{ $value }" is invalid
+config-invalid-value-reason = `{ $key }` value is invalid, { $err }
+config-invalid-transcode-format = `transcode_format` value "{ $value }" is invalid, expected opus, mp3, aac or vorbis
+config-invalid-server-address = `server_address` value "{ $value }" is invalid, expected an address like 127.0.0.1:4533
+config-invalid-spoken-word-folder = `spoken_word` folder "{ $value }" is invalid, expected a path relative to the library
+config-invalid-bool = `{ $key }` value "{ $value }" is invalid, expected true or false
+config-invalid-sort-locale = `sort_locale` value "{ $value }" is invalid, expected a locale like sv or en_US
+config-invalid-url = `{ $key }` value "{ $value }" is invalid, expected an http or https URL
+config-invalid-replay-gain-mode = `replay_gain_mode` value "{ $value }" is invalid, expected off, track or album
+config-invalid-snapcast-format = `snapcast_format` value "{ $value }" is invalid, expected rate:bits:channels like 48000:16:2
+config-invalid-rip-format = `rip_format` value "{ $value }" is invalid, expected flac or mp3
+config-invalid-language = `language` value "{ $value }" is invalid, expected one of { $languages }
+doctor-invalid-severity-rule = "{ $value }" isn't like rule=severity
+doctor-unknown-rule = unknown rule "{ $name }", expected one of { $rules }
+doctor-invalid-severity = severity "{ $severity }" of "{ $name }" is invalid, expected error, warn or ignore
+source-invalid-rule = "{ $value }" isn't like pattern=source
+tags-no-keys = no keys for "{ $mapping }", expected name=KEY
+tags-no-name = no name for "{ $mapping }"
+tags-empty-key = empty key "{ $value }"
+tags-invalid-id3-frame = "{ $key }" is not an ID3 frame ID
+tags-unknown-prefix = unknown prefix "{ $prefix }", expected VORBIS, TXXX, ID3 or MP4
+
+library-path-not-found = path "{ $path }" does not exist
+library-path-not-a-directory = path "{ $path }" is not a directory
+library-path-io = unable to access path, err: { $err }
+storage-unsupported = "{ $url }" is not a supported library URL, expected sftp://, webdav://, webdavs:// or s3://
+storage-samba = Samba shares can't be listed through curl, mount the share and use its path
+library-invalid-root = root "{ $value }" is invalid, expected an absolute path
+library-paths = { $paths ->
+    [one] { $paths } path
+   *[other] { $paths } paths
+}
+library-dry-run = dry run, nothing changed
+library-not-mounted = "{ $path }" isn't mounted here, scan once it is
+
+## Scan
+
+scan-invalid-throttle = --{ $option } value "{ $value }" is invalid, expected a positive number
+scan-library = scanning library "{ $library }"
+scan-file = file { $path }
+moves-hash = unable to hash file, err: { $err }
+netfs-retry = unable to read "{ $path }", retrying in { $backoff }ms, err: { $err }
+netfs-timeout = no answer after { $seconds }s
+netfs-list-timeout = listing "{ $path }" gave no answer after { $seconds }s
+archive-no-file = no file "{ $name }" in the archive
+scan-read-error = unable to open or read file, err: { $err }
+scan-track = artist="{ $artist }" (id={ $artist_id }), album="{ $album }" (id={ $album_id }), album artist="{ $album_artist }", year={ $year }, track="{ $track }", track number={ $number }, genre="{ $genre }"
+scan-same-file = same file as track { $id }, recorded as another path of it
+scan-archive = archive of { $tracks } tracks
+scan-unsupported = not a supported audio file
+scan-permission-denied = permission denied, the track already indexed is kept
+scan-folder-permission-denied = folder { $path } can't be listed, permission denied
+scan-video-indexed = video file, indexed apart from the tracks
+scan-video-skipped = video file, skipped
+scan-moved-track = moved from a missing file, keeping the track
+scan-spoken-word = spoken word, { $chapters } chapters
+scan-mix = mix of { $chapters } tracks
+scan-moved = { $moved } files were moved since the last scan
+scan-purged = { $purged } tracks missing for too long were purged
+scan-summary = { $indexed } files indexed, { $added } added, { $updated } updated, { $removed } removed
+
+## Listings
+
+list-year = Year
+list-artist = Artist
+list-album = Album
+list-type = Type
+list-tracks = Tracks
+list-sidecars = Sidecars
+list-invalid-year = year filter "{ $value }" is invalid, expected a year (1994), a decade (1990s) or a range (1990-1995)
+list-invalid-energy = energy filter "{ $value }" is invalid, expected a level from 1 to 10 (7) or a range (5-8)
+list-unknown-type = unknown
+list-album-line = { $release_type }, { $tracks ->
+    [one] { $tracks } track
+   *[other] { $tracks } tracks
+}
+list-genre-tracks = { $total ->
+    [one] { $total } track
+   *[other] { $total } tracks
+}
+list-genre-tracks-partial = { $total } tracks, { $count } without a subgenre
+list-number = #
+list-title = Title
+list-genre = Genre
+
+## Disk usage
+
+du-remote-io = unable to list the remote library, err: { $err }
+du-no-library = no library configured, set it with `zik config library <path>`
+du-root = (root)
+du-outside = (outside the library)
+du-empty = no tracks in the library
+du-row = { $size }  { $tracks } tracks  { $duration }  { $name }
+du-total = total
+du-unknown-sizes = { $count } tracks couldn't be read, their size isn't counted
+
+## Doctor
+
+doctor-invalid-rule = invalid rule, { $err }
+doctor-missing-album-artist = { $artists } artists, { $tracks } tracks without an album artist
+doctor-year = year { $year }
+doctor-mixed-formats = tracks in { $formats }
+doctor-track-numbers = disc { $disc }: track numbers { $numbers }
+doctor-clean = no issues found
+doctor-summary = { $errors } errors, { $warnings } warnings
+
+## Verify
+
+verify-no-streaminfo = no FLAC STREAMINFO
+verify-streaminfo-error = unable to read STREAMINFO, err: { $err }
+verify-md5-mismatch = MD5 of the samples doesn't match STREAMINFO
+verify-up-to-date = every track is verified already
+verify-start = verifying { $tracks } tracks with { $workers } workers
+verify-done = verified { $verified } tracks, { $failed } failed
+verify-report = { $verified } tracks verified, { $failed } failed, { $pending } to verify with `zik verify --decode`
+
+## Import
+
+import-io = unable to read or write file, err: { $err }
+import-walkdir = unable to walk directory, err: { $err }
+import-remote-library = the library "{ $url }" is remote, files can only be imported into a local one
+import-inside-library = "{ $path }" is already in the library, scan it instead
+import-not-in-inbox = "{ $value }" is not a folder of the inbox
+import-unzip-not-found = unzip not found, install it to import zips
+import-unzip = unable to unzip, err: { $err }
+import-search-error = unable to search MusicBrainz, err: { $err }
+import-fingerprint-error = unable to look up the fingerprint, err: { $err }
+import-release-error = unable to fetch release { $id }, err: { $err }
+import-field-album-artist = album artist
+import-field-album = album
+import-field-year = year
+import-no-match = no match on MusicBrainz, the tags are kept
+import-candidate = { $artist } - { $album }{ $year }, { $tracks } tracks, { $url }
+import-prompt = [a]ccept, [e]dit, [s]kip, [q]uit, [t]ags as they are or a candidate number:
+import-unknown-answer = unknown answer "{ $answer }"
+import-tags-unwritable = tags of { $path } can't be written, kept as they are
+import-tags-error = unable to write the tags of { $path }, err: { $err }
+import-exists = { $path } is already in the library, skipped
+import-group = { $path } ({ $files } files)
+import-ripped = { $tracks } tracks ripped
+import-imported = { $files } files imported
+import-nothing-staged = nothing staged to approve
+import-approved = { $files } files approved
+import-no-audio = no audio files in "{ $path }"
+
+## HTTP
+
+http-curl-not-found = curl not found, install it to fetch data
+http-io = unable to run curl, err: { $err }
+http-failed = request failed, err: { $err }
+http-invalid-json = unexpected response, { $err }
+
+## Enrich
+
+enrich-unknown-source = unknown source "{ $source }", expected lastfm or wikidata
+enrich-missing-api-key = Last.fm needs an API key, set it with `zik secrets set lastfm_api_key`
+enrich-found = { $name }: found on { $source }
+enrich-not-found = { $name }: not found on { $source }
+enrich-artists-done = enriched { $artists } artists
+enrich-links-done = found { $found } of { $albums } albums on Apple Music
+info-no-artist-info = no information, fetch it with `zik enrich artists`
+info-image = image: { $url }
+info-similar = similar: { $artists }
+info-unknown = unknown
+info-path = path: { $path }
+info-source = source: { $source }
+info-first-seen = first seen: { $date }
+info-lyrics-lrc = in a .lrc file
+info-lyrics-none = none
+info-lyrics = lyrics: { $lyrics }
+
+## Jobs
+
+jobs-fpcalc-not-found = fpcalc not found, install chromaprint to fingerprint tracks
+jobs-fpcalc-error = fpcalc failed, err: { $err }
+jobs-no-path = no file to work on
+jobs-no-cover = no cover file or embedded cover found
+jobs-invalid-kind = job kind "{ $value }" is invalid, expected one of { $kinds }
+jobs-invalid-priority = priority "{ $value }" is invalid
+jobs-requeued-interrupted = requeued { $jobs } jobs left running by an interrupted run
+jobs-running = running { $jobs } jobs with { $workers } workers
+jobs-failed = { $job } failed, err: { $err }
+jobs-failed-for-good = { $job } failed for good, err: { $err }
+jobs-none-pending = no pending jobs
+jobs-none = no jobs
+jobs-kind = kind
+jobs-pending = pending
+jobs-running-column = running
+jobs-failed-column = failed
+jobs-failed-after = { $kind } { $target } failed after { $attempts } attempts, err: { $err }
+jobs-queued = queued { $jobs } { $kind } jobs
+jobs-requeued-failed = requeued { $jobs } failed jobs
+
+## Daemon
+
+daemon-listen = unable to listen on { $address }, err: { $err }
+daemon-already-running = a daemon is already listening on "{ $path }"
+daemon-not-running = no daemon is listening on "{ $path }", start one with `zik daemon`
+daemon-error = daemon: { $err }
+daemon-list-library = daemon: unable to list the library, err: { $err }
+daemon-control-connection = daemon: control connection error, err: { $err }
+daemon-control-accept = daemon: unable to accept control connection, err: { $err }
+daemon-scan-failed = daemon: scan failed, err: { $err }
+daemon-library-changed = daemon: library changed
+daemon-inbox-scan-failed = daemon: inbox scan failed, err: { $err }
+daemon-notify-systemd = daemon: unable to notify systemd, err: { $err }
+daemon-extra-socket = daemon: ignoring extra socket "{ $name }" passed by systemd
+daemon-serving-socket = daemon: serving on { $address }, checking the library every { $interval }s, control socket "{ $path }"
+daemon-serving = daemon: serving on { $address }, checking the library every { $interval }s, stop it with Ctrl-C
+daemon-stopped = daemon: stopped
+
+## Users
+
+user-key-io = unable to read the password key "{ $path }", err: { $err }
+user-key-invalid = the password key "{ $path }" isn't a key, remove it and set the passwords again
+user-key-wrong = the password of "{ $name }" can't be decrypted with the password key, set it again with `zik user passwd`
+user-read-password = unable to read password, err: { $err }
+user-already-exists = user "{ $name }" already exists
+user-empty-password = password can't be empty
+user-password-prompt = password:
+user-added = added user "{ $name }"
+user-removed = removed user "{ $name }"
+user-admin = admin
+user-only = only { $collections }
+user-only-nothing = only nothing
+
+## Snapshots
+
+snapshot-already-exists = snapshot "{ $name }" already exists
+snapshot-not-found = "{ $name }" is neither a snapshot nor a database file
+snapshot-too-old = "{ $name }" was made by a version of zik without track paths, it can't be compared
+snapshot-created = created snapshot "{ $name }" at "{ $path }"
+snapshot-none = no snapshots
+snapshot-removed = removed snapshot "{ $name }"
+snapshot-diff-summary = albums: { $albums_added } added, { $albums_removed } removed, { $albums_changed } changed; tracks: { $tracks_added } added, { $tracks_removed } removed, { $tracks_changed } changed
+
+## JSON-RPC
+
+rpc-connection = rpc: connection error, err: { $err }
+rpc-accept = rpc: unable to accept connection, err: { $err }
+rpc-listening = rpc: listening on "{ $path }"
+
+## Subsonic
+
+subsonic-failed = subsonic: { $endpoint } failed, err: { $err }
+
+## Rip
+
+rip-io = unable to read or write file, err: { $err }
+rip-ripper-not-found = cdparanoia not found, install it or cd-paranoia from libcdio to rip discs
+rip-read-disc = unable to read the disc, err: { $err }
+rip-no-audio-tracks = no audio tracks on the disc
+rip-lookup = unable to look the disc up on MusicBrainz, err: { $err }
+rip-fetch-release = unable to fetch release { $id }, err: { $err }
+rip-track = ripping track { $number } of { $total }
+rip-looking-up = { $tracks ->
+    [one] { $tracks } audio track, looking the disc up on MusicBrainz
+   *[other] { $tracks } audio tracks, looking the disc up on MusicBrainz
+}
+
+## Query
+
+query-no-database-path = the database has no file
+
+## Export
+
+export-invalid-feed-days = number of days "{ $value }" is invalid
+export-album-not-found = no album with id "{ $value }" and a folder of its own
+export-invalid-piece-length = piece length "{ $value }" is invalid, expected a power of two of at least 16 KiB
+export-tracks = exported { $tracks ->
+    [one] { $tracks } track
+   *[other] { $tracks } tracks
+} to "{ $path }"
+export-entries = exported { $entries ->
+    [one] { $entries } entry
+   *[other] { $entries } entries
+} to "{ $path }"
+export-site = exported { $artists } artists, { $albums } albums and { $tracks } tracks to "{ $path }"
+export-torrent = exported the files of { $albums ->
+    [one] { $albums } album
+   *[other] { $albums } albums
+} to "{ $path }"
+
+## Aliases and filters
+
+alias-invalid = alias "{ $value }" has unbalanced quotes
+alias-not-found = no alias named "{ $name }"
+alias-invalid-filter-name = filter name "{ $name }" is invalid, add, remove and list are commands
+alias-invalid-filter = filter "{ $expression }" is invalid, { $err }
+alias-filter-not-found = no filter named "{ $name }"
+
+## Lyrics
+
+lyrics-write = unable to write { $path }, err: { $err }
+lyrics-error = { $name }: { $err }
+lyrics-not-found = { $name }: not found
+lyrics-synced = { $name }: synced lyrics
+lyrics-instrumental = { $name }: instrumental
+lyrics-plain = { $name }: plain lyrics
+lyrics-summary = found lyrics for { $found } of { $tracks ->
+    [one] { $tracks } track
+   *[other] { $tracks } tracks
+}
+
+## History
+
+history-invalid-gap = gap "{ $value }" is invalid, expected a number of seconds (90s), minutes (30m) or hours (2h)
+history-session = { $plays ->
+    [one] { $plays } track
+   *[other] { $plays } tracks
+}, { $duration }	mostly { $artist }
+history-unknown-artists = unknown artists
+
+## Genres
+
+genre-invalid-alias = alias must contain at least one letter or digit
+genre-alias-not-found = no genre alias named "{ $alias }"
+genre-parent-not-found = "{ $genre }" isn't a subgenre of "{ $parent }"
+genre-parent-cycle = "{ $genre }" can't be a subgenre of "{ $parent }", which is one of its subgenres
+genre-tracks = { $count ->
+    [one] { $count } track
+   *[other] { $count } tracks
+}
+
+## Top
+
+top-count-of-tracks = tracks can't be ranked by their number of tracks
+top-no-plays = no plays recorded for this period
+
+## Tags
+
+tag-user-required = there are several users, choose one with --user or `tag_stats_user`
+tag-id3-error = ID3 error, { $err }
+tag-flac-error = FLAC error, { $err }
+tag-would-update = would update { $path }: { $rating }, { $plays ->
+    [one] { $plays } play
+   *[other] { $plays } plays
+}
+tag-updated = updated { $path }: { $rating }, { $plays ->
+    [one] { $plays } play
+   *[other] { $plays } plays
+}
+tag-write-failed = unable to write the tags of { $path }, err: { $err }
+tag-summary = { $updated } files updated, { $up_to_date } up to date, { $unsupported } not supported, { $failed } failed
+tag-summary-dry-run = { $updated } files to update, { $up_to_date } up to date, { $unsupported } not supported, { $failed } failed
+
+## Spoken word
+
+spoken-read-chapters = unable to read chapters, err: { $err }
+spoken-invalid-position = position "{ $value }" is invalid, expected seconds or a time like 1:02:03
+spoken-chapters = { $chapters ->
+    [one] { $chapters } chapter
+   *[other] { $chapters } chapters
+}
+spoken-resume-at = resume at { $position }
+spoken-no-position = no resume position
+
+## Secrets
+
+secrets-read = unable to read the secret, err: { $err }
+secrets-empty-value = the secret can't be empty
+secrets-keyring = unable to use the keyring, err: { $err }
+secrets-stored = stored "{ $name }" in the keyring
+secrets-removed = removed "{ $name }" from the keyring
+
+## Play
+
+play-pipe = unable to write to the pipe, err: { $err }
+play-waiting = waiting for snapserver to read "{ $fifo }"
+play-invalid-crossfade = crossfade "{ $value }" is invalid, expected a number of seconds
+play-playing = playing { $artist } - { $name }
+
+## Notes
+
+note-empty = note can't be empty
+note-none = no note
+note-on-album = note on album { $name }: { $text }
+note-on-track = note on track { $name }: { $text }
+note-comment = comment on track { $name }: { $comment }
+
+## Collections
+
+collection-empty-name = collection name can't be empty
+collection-counts = { $albums ->
+    [one] { $albums } album
+   *[other] { $albums } albums
+}, { $tracks ->
+    [one] { $tracks } track
+   *[other] { $tracks } tracks
+}
+
+## Wrapped
+
+wrapped-invalid-year = year "{ $value }" is invalid
+wrapped-no-plays = no plays recorded in { $year }
+wrapped-title = Your { $year } in music
+wrapped-plays = { $plays ->
+    [one] { $plays } play
+   *[other] { $plays } plays
+}, { $duration } of music
+wrapped-tracks = { $tracks } different tracks by { $artists ->
+    [one] { $artists } artist
+   *[other] { $artists } artists
+}
+wrapped-top-artists = Top artists
+wrapped-top-albums = Top albums
+wrapped-top-tracks = Top tracks
+wrapped-discoveries = New discoveries
+wrapped-entry-plays = { $plays ->
+    [one] { $plays } play
+   *[other] { $plays } plays
+}
+wrapped-genres = Genres
+
+## Genre inference
+
+infer-missing-api-key = Last.fm needs an API key, set it with `zik secrets set lastfm_api_key`
+infer-cleared = cleared the inferred genre of { $tracks ->
+    [one] { $tracks } track
+   *[other] { $tracks } tracks
+}
+infer-not-found = { $name }: no genre found on { $source }
+infer-summary = looked up { $artists ->
+    [one] { $artists } artist
+   *[other] { $artists } artists
+}, { $tracks ->
+    [one] { $tracks } track has an inferred genre
+   *[other] { $tracks } tracks have an inferred genre
+}
+
+## Encryption
+
+encryption-io = unable to read the database, err: { $err }
+encryption-no-passphrase = no passphrase for the database, set it in ZIK_DATABASE_KEY or the keyring
+encryption-wrong-passphrase = wrong passphrase for the database
+encryption-already-encrypted = the database is already encrypted
+encryption-no-database-path = an in-memory database can't be encrypted
+encryption-passphrase-prompt = passphrase of the database:
+encryption-done = encrypted the database "{ $path }"
+
+## Database
+
+db-encryption-not-built = zik was built without encryption, rebuild it with `--features encryption`
+db-violations = { $rows ->
+    [one] { $rows } row references rows which don't exist, see `PRAGMA foreign_key_check`
+   *[other] { $rows } rows reference rows which don't exist, see `PRAGMA foreign_key_check`
+}
+
+## Play queue
+
+queue-none = no play queue saved
+queue-saved = saved { $at } by { $client }
+queue-unknown-client = an unknown client
+queue-at = at { $position }
+
+## Moods
+
+mood-empty = mood can't be empty
+mood-cleared = the next scan reads the moods of the track from its tags again
+mood-tracks = { $count ->
+    [one] { $count } track
+   *[other] { $count } tracks
+}
+mood-none = no track has a mood
+
+## Labels
+
+label-empty = label can't be empty
+label-not-found = no label named "{ $name }"
+label-tracks = { $count ->
+    [one] { $count } track
+   *[other] { $count } tracks
+}
+
+## Upgrades
+
+upgrades-invalid-bitrate = bitrate "{ $value }" is invalid, expected a number of kbit/s
+upgrades-none = no album to upgrade
+upgrades-plays = { $plays } plays
+
+## Unreadable files
+
+unreadable-none = no unreadable files
+unreadable-none-unsupported = no unsupported files
+
+## Streaming
+
+stream-get-track = stream: unable to get track { $id }, err: { $err }
+stream-get-gain = stream: unable to get the gain of track { $id }, err: { $err }
+stream-transcode = stream: unable to transcode track { $id }, err: { $err }
+stream-connection = stream: unable to get a database connection, err: { $err }
+stream-auth = stream: unable to authenticate, err: { $err }
+
+## Links
+
+links-unknown-service = "{ $url }" isn't a Spotify, Apple Music or Bandcamp URL
+links-none = no links
+
+## Inbox
+
+inbox-staged = { $files ->
+    [one] { $files } file staged in the inbox
+   *[other] { $files } files staged in the inbox
+}
+inbox-files = { $files ->
+    [one] { $files } file
+   *[other] { $files } files
+}
+inbox-unknown = Unknown
+inbox-untagged = { $files ->
+    [one] { $files } file missing tags
+   *[other] { $files } files missing tags
+}
+inbox-empty = the inbox is empty
+
+## Missing tracks
+
+tombstones-missing-since = missing since { $since }
+tombstones-none = no missing tracks
+tombstones-restored = { $tracks ->
+    [one] { $tracks } track restored, the next scan marks it missing again if its file is still gone
+   *[other] { $tracks } tracks restored, the next scan marks them missing again if their file is still gone
+}
+
+## Custom tag fields
+
+tags-tracks = { $count ->
+    [one] { $count } track
+   *[other] { $count } tracks
+}
+tags-no-field = no track has a { $name } field
+tags-no-custom-fields = no custom fields, map them with `zik config tag_fields`
+
+## Picker
+
+pick-io = unable to draw the picker, err: { $err }
+pick-no-terminal = no terminal to pick on, use --filter instead, err: { $err }
+
+## Metrics
+
+metrics-connection = metrics: unable to get a database connection, err: { $err }
+metrics-read = metrics: unable to read the database, err: { $err }
+metrics-auth = metrics: unable to authenticate, err: { $err }
+
+## Artwork
+
+artwork-find-image = unable to find the image of artist #{ $id }, err: { $err }
+artwork-no-image = no image found for { $name }, add an artist.jpg next to their albums and rescan
+
+## Years
+
+years-tracks = { $tracks ->
+    [one] { $tracks } track
+   *[other] { $tracks } tracks
+}
+years-none = no album has tracks of different years
+
+## Throttling
+
+throttle-failed = unable to lower the priority, { $program } { $status }
+throttle-error = unable to lower the priority, { $program } err: { $err }
+
+## ffmpeg
+
+ffmpeg-not-found = ffmpeg not found, install it to analyze or transcode tracks
+ffmpeg-io = unable to run ffmpeg, err: { $err }
+ffmpeg-failed = ffmpeg failed, err: { $err }
+ffmpeg-unknown-format = unknown transcode format "{ $format }"
+
+## Feed
+
+feed-connection = feed: unable to get a database connection, err: { $err }
+feed-auth = feed: unable to authenticate, err: { $err }
+feed-serve = feed: unable to serve { $path }, err: { $err }
+
+## Site
+
+site-no-ffmpeg = ffmpeg not found, copying the covers without scaling them
+site-scale = unable to scale "{ $path }", err: { $err }
+
+## Server
+
+server-connection = server: connection error, err: { $err }
+server-accept = server: unable to accept connection, err: { $err }
+
+## Notifications
+
+notify-failed = notify: unable to notify { $url }, err: { $err }
+notify-read = notify: unable to read `{ $target }`, err: { $err }
+
+## Incomplete albums
+
+incomplete-tracks = has { $tracks } of { $expected ->
+    [one] { $expected } track
+   *[other] { $expected } tracks
+}, missing { $missing }
+incomplete-disc = disc { $disc }: { $problem }
+incomplete-discs = has { $discs } of { $expected ->
+    [one] { $expected } disc
+   *[other] { $expected } discs
+}, missing { $missing }
+
+## Benchmarks
+
+bench-not-empty = "{ $path }" isn't empty, generate the library in a new folder
+bench-invalid-files = --files value "{ $value }" is invalid, expected a positive number
+bench-invalid-audio = --audio value "{ $value }" is invalid, expected a number of KiB
+bench-generated = { $files } files generated in "{ $path }", scan them with `zik --database :memory: scan { $path }`
+
+## Casting
+
+cast-searching = searching for devices on the local network
+cast-connected = connected to { $device }
+cast-controls = controls: p to pause or resume, n next, b back, + and - for the volume, q to stop, then Enter
+cast-volume = volume at { $percent }%
+cast-no-volume = { $device } doesn't support changing the volume
+cast-track-failed = unable to play "{ $name }", reason: { $reason }
+cast-closed = { $device } was taken over by another sender, stopping
+cast-discovery = unable to search for devices, err: { $err }
+cast-no-devices = no Chromecast or AirPlay device found on the local network
+cast-device-not-found = no device named "{ $name }", found: { $devices }
+cast-invalid-server-address = server_address "{ $address }" is invalid, expected an address like 0.0.0.0:4533
+cast-loopback-server = the server listens on { $address }, which { $device } can't reach, set server_address to an address of the local network
+cast-server-not-running = no server answering on { $address }, start it with `zik daemon`
+cast-user-required = { $device } streams through the server, which has users, pass one with --user
+cast-io = unable to talk to { $device }, err: { $err }
+cast-refused = { $device } refused the request with status { $status }
+cast-not-launched = { $device } didn't start its media receiver
@@ -0,0 +1,724 @@
+# Messages de la ligne de commande, en français. Ceux qui manquent sont pris du catalogue
+# anglais, en.ftl.
+
+sqlite-error = erreur SQLite, { $err }
+io-error = erreur d'entrée/sortie, { $err }
+data-folder-not-found = dossier de données de Zik introuvable
+init-database-error = impossible d'initialiser la base de données, erreur : { $err }
+init-password-key-error = impossible de chiffrer les mots de passe des utilisateurs, erreur : { $err }
+no-library = pas de bibliothèque configurée, indiquez-la avec `zik config library <chemin>`
+no-inbox = pas de boîte de réception configurée, indiquez-la avec `zik config inbox <chemin>`
+artist-not-found = aucun artiste nommé « { $name } »
+track-not-found = aucun morceau à « { $path } »
+track-id-not-found = aucun morceau avec l'identifiant ou le chemin « { $value } »
+album-id-not-found = aucun album avec l'identifiant ou le nom « { $value } »
+playlist-not-found = aucune playlist avec l'identifiant « { $value } »
+collection-not-found = aucune collection nommée « { $name } »
+user-not-found = aucun utilisateur nommé « { $name } »
+invalid-workers = le nombre de workers « { $value } » n'est pas valide
+invalid-limit = la limite « { $value } » n'est pas valide
+invalid-period = la période « { $value } » n'est pas valide, un nombre de jours (90d), semaines (4w), mois (6m) ou années (1y) attendu
+
+## Configuration
+
+config-invalid-key = la clé `{ $key }` n'existe pas
+config-secret = `{ $key }` est un secret, indiquez-le avec `zik secrets set { $key }` qui le garde dans le trousseau
+config-no-value = pas de valeur pour la clé `{ $key }`
+config-library-path = impossible de trouver le dossier de la bibliothèque : { $err }
+config-invalid-value = la valeur « { $value } » de `{ $key }` n'est pas valide
+config-invalid-value-reason = la valeur de `{ $key }` n'est pas valide, { $err }
+config-invalid-transcode-format = la valeur « { $value } » de `transcode_format` n'est pas valide, opus, mp3, aac ou vorbis attendu
+config-invalid-server-address = la valeur « { $value } » de `server_address` n'est pas valide, une adresse comme 127.0.0.1:4533 attendue
+config-invalid-spoken-word-folder = le dossier « { $value } » de `spoken_word` n'est pas valide, un chemin relatif à la bibliothèque attendu
+config-invalid-bool = la valeur « { $value } » de `{ $key }` n'est pas valide, true ou false attendu
+config-invalid-sort-locale = la valeur « { $value } » de `sort_locale` n'est pas valide, une locale comme sv ou fr_FR attendue
+config-invalid-url = la valeur « { $value } » de `{ $key }` n'est pas valide, une URL http ou https attendue
+config-invalid-replay-gain-mode = la valeur « { $value } » de `replay_gain_mode` n'est pas valide, off, track ou album attendu
+config-invalid-snapcast-format = la valeur « { $value } » de `snapcast_format` n'est pas valide, fréquence:bits:canaux comme 48000:16:2 attendu
+config-invalid-rip-format = la valeur « { $value } » de `rip_format` n'est pas valide, flac ou mp3 attendu
+config-invalid-language = la valeur « { $value } » de `language` n'est pas valide, une langue parmi { $languages } attendue
+doctor-invalid-severity-rule = « { $value } » n'est pas de la forme règle=gravité
+doctor-unknown-rule = règle « { $name } » inconnue, une règle parmi { $rules } attendue
+doctor-invalid-severity = la gravité « { $severity } » de « { $name } » n'est pas valide, error, warn ou ignore attendu
+source-invalid-rule = « { $value } » n'est pas de la forme motif=source
+tags-no-keys = pas de clés pour « { $mapping } », nom=CLÉ attendu
+tags-no-name = pas de nom pour « { $mapping } »
+tags-empty-key = clé « { $value } » vide
+tags-invalid-id3-frame = « { $key } » n'est pas un identifiant de frame ID3
+tags-unknown-prefix = préfixe « { $prefix } » inconnu, VORBIS, TXXX, ID3 ou MP4 attendu
+
+library-path-not-found = le chemin « { $path } » n'existe pas
+library-path-not-a-directory = le chemin « { $path } » n'est pas un dossier
+library-path-io = impossible d'accéder au chemin, erreur : { $err }
+storage-unsupported = « { $url } » n'est pas une URL de bibliothèque prise en charge, sftp://, webdav://, webdavs:// ou s3:// attendu
+storage-samba = les partages Samba ne peuvent pas être listés avec curl, montez le partage et utilisez son chemin
+library-invalid-root = la racine « { $value } » n'est pas valide, un chemin absolu attendu
+library-paths = { $paths ->
+    [one] { $paths } chemin
+   *[other] { $paths } chemins
+}
+library-dry-run = essai à blanc, rien n'a changé
+library-not-mounted = « { $path } » n'est pas monté ici, lancez une analyse une fois qu'il le sera
+
+## Scan
+
+scan-invalid-throttle = la valeur « { $value } » de --{ $option } n'est pas valide, un nombre positif attendu
+scan-library = analyse de la bibliothèque « { $library } »
+scan-file = fichier { $path }
+moves-hash = impossible de hacher le fichier, erreur : { $err }
+netfs-retry = impossible de lire « { $path } », nouvel essai dans { $backoff } ms, erreur : { $err }
+netfs-timeout = pas de réponse après { $seconds } s
+netfs-list-timeout = la liste de « { $path } » n'a pas répondu après { $seconds } s
+archive-no-file = aucun fichier « { $name } » dans l'archive
+scan-read-error = impossible d'ouvrir ou de lire le fichier, erreur : { $err }
+scan-track = artiste="{ $artist }" (id={ $artist_id }), album="{ $album }" (id={ $album_id }), artiste de l'album="{ $album_artist }", année={ $year }, morceau="{ $track }", numéro={ $number }, genre="{ $genre }"
+scan-same-file = même fichier que le morceau { $id }, enregistré comme un autre chemin de celui-ci
+scan-archive = { $tracks ->
+    [one] archive de { $tracks } morceau
+   *[other] archive de { $tracks } morceaux
+}
+scan-unsupported = pas un fichier audio reconnu
+scan-permission-denied = permission refusée, le morceau déjà indexé est conservé
+scan-folder-permission-denied = le dossier { $path } ne peut pas être listé, permission refusée
+scan-video-indexed = fichier vidéo, indexé à part des morceaux
+scan-video-skipped = fichier vidéo, ignoré
+scan-moved-track = déplacé depuis un fichier manquant, le morceau est conservé
+scan-spoken-word = livre audio, { $chapters ->
+    [one] { $chapters } chapitre
+   *[other] { $chapters } chapitres
+}
+scan-mix = mix de { $chapters ->
+    [one] { $chapters } morceau
+   *[other] { $chapters } morceaux
+}
+scan-moved = { $moved ->
+    [one] { $moved } fichier a été déplacé depuis la dernière analyse
+   *[other] { $moved } fichiers ont été déplacés depuis la dernière analyse
+}
+scan-purged = { $purged ->
+    [one] { $purged } morceau manquant depuis trop longtemps a été supprimé
+   *[other] { $purged } morceaux manquants depuis trop longtemps ont été supprimés
+}
+scan-summary = { $indexed ->
+    [one] { $indexed } fichier indexé
+   *[other] { $indexed } fichiers indexés
+}, { $added ->
+    [one] { $added } ajouté
+   *[other] { $added } ajoutés
+}, { $updated } mis à jour, { $removed ->
+    [one] { $removed } supprimé
+   *[other] { $removed } supprimés
+}
+
+## Listes
+
+list-year = Année
+list-artist = Artiste
+list-album = Album
+list-type = Type
+list-tracks = Morceaux
+list-sidecars = Annexes
+list-invalid-year = le filtre d'année « { $value } » n'est pas valide, une année (1994), une décennie (1990s) ou une plage (1990-1995) attendue
+list-invalid-energy = le filtre d'énergie « { $value } » n'est pas valide, un niveau de 1 à 10 (7) ou une plage (5-8) attendu
+list-unknown-type = inconnu
+list-album-line = { $release_type }, { $tracks ->
+    [one] { $tracks } morceau
+   *[other] { $tracks } morceaux
+}
+list-genre-tracks = { $total ->
+    [one] { $total } morceau
+   *[other] { $total } morceaux
+}
+list-genre-tracks-partial = { $total } morceaux, dont { $count } sans sous-genre
+list-number = N°
+list-title = Titre
+list-genre = Genre
+
+## Espace disque
+
+du-remote-io = impossible de lister la bibliothèque distante, erreur : { $err }
+du-no-library = pas de bibliothèque configurée, indiquez-la avec `zik config library <chemin>`
+du-root = (racine)
+du-outside = (hors de la bibliothèque)
+du-empty = aucun morceau dans la bibliothèque
+du-row = { $size }  { $tracks } morceaux  { $duration }  { $name }
+du-total = total
+du-unknown-sizes = { $count ->
+    [one] { $count } morceau n'a pas pu être lu, sa taille n'est pas comptée
+   *[other] { $count } morceaux n'ont pas pu être lus, leur taille n'est pas comptée
+}
+
+## Doctor
+
+doctor-invalid-rule = règle invalide, { $err }
+doctor-missing-album-artist = { $artists } artistes, { $tracks ->
+    [one] { $tracks } morceau sans artiste de l'album
+   *[other] { $tracks } morceaux sans artiste de l'album
+}
+doctor-year = année { $year }
+doctor-mixed-formats = morceaux en { $formats }
+doctor-track-numbers = disque { $disc } : numéros { $numbers }
+doctor-clean = aucun problème trouvé
+doctor-summary = { $errors ->
+    [one] { $errors } erreur
+   *[other] { $errors } erreurs
+}, { $warnings ->
+    [one] { $warnings } avertissement
+   *[other] { $warnings } avertissements
+}
+
+## Vérification
+
+verify-no-streaminfo = pas de STREAMINFO FLAC
+verify-streaminfo-error = impossible de lire le STREAMINFO, erreur : { $err }
+verify-md5-mismatch = le MD5 des échantillons ne correspond pas au STREAMINFO
+verify-up-to-date = tous les morceaux sont déjà vérifiés
+verify-start = vérification de { $tracks } morceaux avec { $workers } workers
+verify-done = { $verified } morceaux vérifiés, { $failed } en échec
+verify-report = { $verified } morceaux vérifiés, { $failed } en échec, { $pending } à vérifier avec `zik verify --decode`
+
+## Import
+
+import-io = impossible de lire ou d'écrire le fichier, erreur : { $err }
+import-walkdir = impossible de parcourir le dossier, erreur : { $err }
+import-remote-library = la bibliothèque « { $url } » est distante, les fichiers ne peuvent être importés que dans une bibliothèque locale
+import-inside-library = « { $path } » est déjà dans la bibliothèque, analysez-la plutôt
+import-not-in-inbox = « { $value } » n'est pas un dossier de la boîte de réception
+import-unzip-not-found = unzip introuvable, installez-le pour importer des zips
+import-unzip = impossible de décompresser, erreur : { $err }
+import-search-error = impossible de chercher sur MusicBrainz, erreur : { $err }
+import-fingerprint-error = impossible de chercher l'empreinte, erreur : { $err }
+import-release-error = impossible de récupérer la sortie { $id }, erreur : { $err }
+import-field-album-artist = artiste de l'album
+import-field-album = album
+import-field-year = année
+import-no-match = aucune correspondance sur MusicBrainz, les tags sont conservés
+import-candidate = { $artist } - { $album }{ $year }, { $tracks ->
+    [one] { $tracks } morceau
+   *[other] { $tracks } morceaux
+}, { $url }
+import-prompt = [a]ccepter, [e]diter, [s]auter, [q]uitter, [t]ags tels quels ou un numéro de candidat :
+import-unknown-answer = réponse inconnue « { $answer } »
+import-tags-unwritable = les tags de { $path } ne peuvent pas être écrits, ils sont conservés tels quels
+import-tags-error = impossible d'écrire les tags de { $path }, erreur : { $err }
+import-exists = { $path } est déjà dans la bibliothèque, ignoré
+import-group = { $path } ({ $files ->
+    [one] { $files } fichier
+   *[other] { $files } fichiers
+})
+import-ripped = { $tracks ->
+    [one] { $tracks } morceau extrait
+   *[other] { $tracks } morceaux extraits
+}
+import-imported = { $files ->
+    [one] { $files } fichier importé
+   *[other] { $files } fichiers importés
+}
+import-nothing-staged = rien à approuver
+import-approved = { $files ->
+    [one] { $files } fichier approuvé
+   *[other] { $files } fichiers approuvés
+}
+import-no-audio = aucun fichier audio dans « { $path } »
+
+## HTTP
+
+http-curl-not-found = curl introuvable, installez-le pour récupérer des données
+http-io = impossible de lancer curl, erreur : { $err }
+http-failed = la requête a échoué, erreur : { $err }
+http-invalid-json = réponse inattendue, { $err }
+
+## Enrichissement
+
+enrich-unknown-source = source « { $source } » inconnue, lastfm ou wikidata attendu
+enrich-missing-api-key = Last.fm a besoin d'une clé d'API, indiquez-la avec `zik secrets set lastfm_api_key`
+enrich-found = { $name } : trouvé sur { $source }
+enrich-not-found = { $name } : introuvable sur { $source }
+enrich-artists-done = { $artists ->
+    [one] { $artists } artiste enrichi
+   *[other] { $artists } artistes enrichis
+}
+enrich-links-done = { $found } albums sur { $albums } trouvés sur Apple Music
+info-no-artist-info = aucune information, récupérez-la avec `zik enrich artists`
+info-image = image : { $url }
+info-similar = similaires : { $artists }
+info-unknown = inconnu
+info-path = chemin : { $path }
+info-source = source : { $source }
+info-first-seen = vu pour la première fois : { $date }
+info-lyrics-lrc = dans un fichier .lrc
+info-lyrics-none = aucune
+info-lyrics = paroles : { $lyrics }
+
+## Tâches
+
+jobs-fpcalc-not-found = fpcalc introuvable, installez chromaprint pour calculer les empreintes des morceaux
+jobs-fpcalc-error = fpcalc a échoué, erreur : { $err }
+jobs-no-path = aucun fichier à traiter
+jobs-no-cover = aucun fichier de pochette ni pochette intégrée trouvé
+jobs-invalid-kind = le type de tâche « { $value } » n'est pas valide, un type parmi { $kinds } attendu
+jobs-invalid-priority = la priorité « { $value } » n'est pas valide
+jobs-requeued-interrupted = { $jobs ->
+    [one] { $jobs } tâche laissée en cours par une exécution interrompue remise en file
+   *[other] { $jobs } tâches laissées en cours par une exécution interrompue remises en file
+}
+jobs-running = exécution de { $jobs ->
+    [one] { $jobs } tâche
+   *[other] { $jobs } tâches
+} avec { $workers } workers
+jobs-failed = { $job } a échoué, erreur : { $err }
+jobs-failed-for-good = { $job } a échoué définitivement, erreur : { $err }
+jobs-none-pending = aucune tâche en attente
+jobs-none = aucune tâche
+jobs-kind = type
+jobs-pending = attente
+jobs-running-column = en cours
+jobs-failed-column = échec
+jobs-failed-after = { $kind } { $target } a échoué après { $attempts ->
+    [one] { $attempts } tentative
+   *[other] { $attempts } tentatives
+}, erreur : { $err }
+jobs-queued = { $jobs ->
+    [one] { $jobs } tâche { $kind } mise en file
+   *[other] { $jobs } tâches { $kind } mises en file
+}
+jobs-requeued-failed = { $jobs ->
+    [one] { $jobs } tâche en échec remise en file
+   *[other] { $jobs } tâches en échec remises en file
+}
+
+## Démon
+
+daemon-listen = impossible d'écouter sur { $address }, erreur : { $err }
+daemon-already-running = un démon écoute déjà sur « { $path } »
+daemon-not-running = aucun démon n'écoute sur « { $path } », lancez-en un avec `zik daemon`
+daemon-error = démon : { $err }
+daemon-list-library = démon : impossible de lister la bibliothèque, erreur : { $err }
+daemon-control-connection = démon : erreur de la connexion de contrôle, erreur : { $err }
+daemon-control-accept = démon : impossible d'accepter la connexion de contrôle, erreur : { $err }
+daemon-scan-failed = démon : échec de l'analyse, erreur : { $err }
+daemon-library-changed = démon : la bibliothèque a changé
+daemon-inbox-scan-failed = démon : échec de l'analyse de la boîte de réception, erreur : { $err }
+daemon-notify-systemd = démon : impossible de notifier systemd, erreur : { $err }
+daemon-extra-socket = démon : socket supplémentaire « { $name } » passé par systemd ignoré
+daemon-serving-socket = démon : en service sur { $address }, vérification de la bibliothèque toutes les { $interval } s, socket de contrôle « { $path } »
+daemon-serving = démon : en service sur { $address }, vérification de la bibliothèque toutes les { $interval } s, arrêtez-le avec Ctrl-C
+daemon-stopped = démon : arrêté
+
+## Utilisateurs
+
+user-key-io = impossible de lire la clé des mots de passe « { $path } », erreur : { $err }
+user-key-invalid = la clé des mots de passe « { $path } » n'est pas une clé, supprimez-la et définissez à nouveau les mots de passe
+user-key-wrong = le mot de passe de « { $name } » ne peut pas être déchiffré avec la clé des mots de passe, définissez-le à nouveau avec `zik user passwd`
+user-read-password = impossible de lire le mot de passe, erreur : { $err }
+user-already-exists = l'utilisateur « { $name } » existe déjà
+user-empty-password = le mot de passe ne peut pas être vide
+user-password-prompt = mot de passe :
+user-added = utilisateur « { $name } » ajouté
+user-removed = utilisateur « { $name } » supprimé
+user-admin = admin
+user-only = seulement { $collections }
+user-only-nothing = rien
+
+## Instantanés
+
+snapshot-already-exists = l'instantané « { $name } » existe déjà
+snapshot-not-found = « { $name } » n'est ni un instantané ni une base de données
+snapshot-too-old = « { $name } » a été créé par une version de zik sans chemins de morceaux, il ne peut pas être comparé
+snapshot-created = instantané « { $name } » créé dans « { $path } »
+snapshot-none = aucun instantané
+snapshot-removed = instantané « { $name } » supprimé
+snapshot-diff-summary = albums : { $albums_added } ajoutés, { $albums_removed } supprimés, { $albums_changed } modifiés ; morceaux : { $tracks_added } ajoutés, { $tracks_removed } supprimés, { $tracks_changed } modifiés
+
+## JSON-RPC
+
+rpc-connection = rpc : erreur de connexion, erreur : { $err }
+rpc-accept = rpc : impossible d'accepter la connexion, erreur : { $err }
+rpc-listening = rpc : en écoute sur « { $path } »
+
+## Subsonic
+
+subsonic-failed = subsonic : échec de { $endpoint }, erreur : { $err }
+
+## Extraction
+
+rip-io = impossible de lire ou d'écrire le fichier, erreur : { $err }
+rip-ripper-not-found = cdparanoia introuvable, installez-le ou cd-paranoia de libcdio pour extraire les disques
+rip-read-disc = impossible de lire le disque, erreur : { $err }
+rip-no-audio-tracks = aucune piste audio sur le disque
+rip-lookup = impossible de chercher le disque sur MusicBrainz, erreur : { $err }
+rip-fetch-release = impossible de récupérer la sortie { $id }, erreur : { $err }
+rip-track = extraction de la piste { $number } sur { $total }
+rip-looking-up = { $tracks ->
+    [one] { $tracks } piste audio, recherche du disque sur MusicBrainz
+   *[other] { $tracks } pistes audio, recherche du disque sur MusicBrainz
+}
+
+## Requêtes
+
+query-no-database-path = la base de données n'a pas de fichier
+
+## Export
+
+export-invalid-feed-days = le nombre de jours « { $value } » n'est pas valide
+export-album-not-found = aucun album avec l'identifiant « { $value } » et un dossier à lui
+export-invalid-piece-length = la taille de pièce « { $value } » n'est pas valide, une puissance de deux d'au moins 16 Kio attendue
+export-tracks = export de { $tracks ->
+    [one] { $tracks } morceau
+   *[other] { $tracks } morceaux
+} dans « { $path } »
+export-entries = export de { $entries ->
+    [one] { $entries } entrée
+   *[other] { $entries } entrées
+} dans « { $path } »
+export-site = export de { $artists } artistes, { $albums } albums et { $tracks } morceaux dans « { $path } »
+export-torrent = export des fichiers de { $albums ->
+    [one] { $albums } album
+   *[other] { $albums } albums
+} dans « { $path } »
+
+## Alias et filtres
+
+alias-invalid = les guillemets de l'alias « { $value } » ne sont pas équilibrés
+alias-not-found = aucun alias nommé « { $name } »
+alias-invalid-filter-name = le nom de filtre « { $name } » n'est pas valide, add, remove et list sont des commandes
+alias-invalid-filter = le filtre « { $expression } » n'est pas valide, { $err }
+alias-filter-not-found = aucun filtre nommé « { $name } »
+
+## Paroles
+
+lyrics-write = impossible d'écrire { $path }, erreur : { $err }
+lyrics-error = { $name } : { $err }
+lyrics-not-found = { $name } : introuvables
+lyrics-synced = { $name } : paroles synchronisées
+lyrics-instrumental = { $name } : instrumental
+lyrics-plain = { $name } : paroles simples
+lyrics-summary = paroles trouvées pour { $found } { $tracks ->
+    [one] morceau sur { $tracks }
+   *[other] morceaux sur { $tracks }
+}
+
+## Historique
+
+history-invalid-gap = l'écart « { $value } » n'est pas valide, un nombre de secondes (90s), minutes (30m) ou heures (2h) attendu
+history-session = { $plays ->
+    [one] { $plays } morceau
+   *[other] { $plays } morceaux
+}, { $duration }	surtout { $artist }
+history-unknown-artists = artistes inconnus
+
+## Genres
+
+genre-invalid-alias = l'alias doit contenir au moins une lettre ou un chiffre
+genre-alias-not-found = aucun alias de genre nommé « { $alias } »
+genre-parent-not-found = « { $genre } » n'est pas un sous-genre de « { $parent } »
+genre-parent-cycle = « { $genre } » ne peut pas être un sous-genre de « { $parent } », qui est l'un de ses sous-genres
+genre-tracks = { $count ->
+    [one] { $count } morceau
+   *[other] { $count } morceaux
+}
+
+## Classement
+
+top-count-of-tracks = les morceaux ne peuvent pas être classés par leur nombre de morceaux
+top-no-plays = aucune écoute enregistrée pour cette période
+
+## Tags
+
+tag-user-required = il y a plusieurs utilisateurs, choisissez-en un avec --user ou `tag_stats_user`
+tag-id3-error = erreur ID3, { $err }
+tag-flac-error = erreur FLAC, { $err }
+tag-would-update = { $path } serait mis à jour : { $rating }, { $plays ->
+    [one] { $plays } écoute
+   *[other] { $plays } écoutes
+}
+tag-updated = { $path } mis à jour : { $rating }, { $plays ->
+    [one] { $plays } écoute
+   *[other] { $plays } écoutes
+}
+tag-write-failed = impossible d'écrire les tags de { $path }, erreur : { $err }
+tag-summary = { $updated } fichiers mis à jour, { $up_to_date } à jour, { $unsupported } non pris en charge, { $failed } en échec
+tag-summary-dry-run = { $updated } fichiers à mettre à jour, { $up_to_date } à jour, { $unsupported } non pris en charge, { $failed } en échec
+
+## Livres audio
+
+spoken-read-chapters = impossible de lire les chapitres, erreur : { $err }
+spoken-invalid-position = la position « { $value } » n'est pas valide, des secondes ou une durée comme 1:02:03 attendues
+spoken-chapters = { $chapters ->
+    [one] { $chapters } chapitre
+   *[other] { $chapters } chapitres
+}
+spoken-resume-at = reprise à { $position }
+spoken-no-position = aucune position de reprise
+
+## Secrets
+
+secrets-read = impossible de lire le secret, erreur : { $err }
+secrets-empty-value = le secret ne peut pas être vide
+secrets-keyring = impossible d'utiliser le trousseau, erreur : { $err }
+secrets-stored = « { $name } » enregistré dans le trousseau
+secrets-removed = « { $name } » supprimé du trousseau
+
+## Lecture
+
+play-pipe = impossible d'écrire dans le tube, erreur : { $err }
+play-waiting = attente de la lecture de « { $fifo } » par snapserver
+play-invalid-crossfade = le fondu « { $value } » n'est pas valide, un nombre de secondes attendu
+play-playing = lecture de { $artist } - { $name }
+
+## Notes
+
+note-empty = la note ne peut pas être vide
+note-none = aucune note
+note-on-album = note sur l'album { $name } : { $text }
+note-on-track = note sur le morceau { $name } : { $text }
+note-comment = commentaire sur le morceau { $name } : { $comment }
+
+## Collections
+
+collection-empty-name = le nom de la collection ne peut pas être vide
+collection-counts = { $albums ->
+    [one] { $albums } album
+   *[other] { $albums } albums
+}, { $tracks ->
+    [one] { $tracks } morceau
+   *[other] { $tracks } morceaux
+}
+
+## Bilan
+
+wrapped-invalid-year = l'année « { $value } » n'est pas valide
+wrapped-no-plays = aucune écoute enregistrée en { $year }
+wrapped-title = Votre année { $year } en musique
+wrapped-plays = { $plays ->
+    [one] { $plays } écoute
+   *[other] { $plays } écoutes
+}, { $duration } de musique
+wrapped-tracks = { $tracks } morceaux différents de { $artists ->
+    [one] { $artists } artiste
+   *[other] { $artists } artistes
+}
+wrapped-top-artists = Artistes les plus écoutés
+wrapped-top-albums = Albums les plus écoutés
+wrapped-top-tracks = Morceaux les plus écoutés
+wrapped-discoveries = Découvertes
+wrapped-entry-plays = { $plays ->
+    [one] { $plays } écoute
+   *[other] { $plays } écoutes
+}
+wrapped-genres = Genres
+
+## Déduction des genres
+
+infer-missing-api-key = Last.fm a besoin d'une clé d'API, indiquez-la avec `zik secrets set lastfm_api_key`
+infer-cleared = genre déduit effacé pour { $tracks ->
+    [one] { $tracks } morceau
+   *[other] { $tracks } morceaux
+}
+infer-not-found = { $name } : aucun genre trouvé sur { $source }
+infer-summary = { $artists ->
+    [one] { $artists } artiste cherché
+   *[other] { $artists } artistes cherchés
+}, { $tracks ->
+    [one] { $tracks } morceau a un genre déduit
+   *[other] { $tracks } morceaux ont un genre déduit
+}
+
+## Chiffrement
+
+encryption-io = impossible de lire la base de données, erreur : { $err }
+encryption-no-passphrase = pas de phrase secrète pour la base de données, indiquez-la dans ZIK_DATABASE_KEY ou le trousseau
+encryption-wrong-passphrase = mauvaise phrase secrète pour la base de données
+encryption-already-encrypted = la base de données est déjà chiffrée
+encryption-no-database-path = une base de données en mémoire ne peut pas être chiffrée
+encryption-passphrase-prompt = phrase secrète de la base de données :
+encryption-done = base de données « { $path } » chiffrée
+
+## Base de données
+
+db-encryption-not-built = zik a été compilé sans chiffrement, recompilez-le avec `--features encryption`
+db-violations = { $rows ->
+    [one] { $rows } ligne référence des lignes qui n'existent pas, voir `PRAGMA foreign_key_check`
+   *[other] { $rows } lignes référencent des lignes qui n'existent pas, voir `PRAGMA foreign_key_check`
+}
+
+## File de lecture
+
+queue-none = aucune file de lecture enregistrée
+queue-saved = enregistrée le { $at } par { $client }
+queue-unknown-client = un client inconnu
+queue-at = à { $position }
+
+## Ambiances
+
+mood-empty = l'ambiance ne peut pas être vide
+mood-cleared = la prochaine analyse relira les ambiances du morceau depuis ses tags
+mood-tracks = { $count ->
+    [one] { $count } morceau
+   *[other] { $count } morceaux
+}
+mood-none = aucun morceau n'a d'ambiance
+
+## Étiquettes
+
+label-empty = l'étiquette ne peut pas être vide
+label-not-found = aucune étiquette nommée « { $name } »
+label-tracks = { $count ->
+    [one] { $count } morceau
+   *[other] { $count } morceaux
+}
+
+## Améliorations
+
+upgrades-invalid-bitrate = le débit « { $value } » n'est pas valide, un nombre de kbit/s attendu
+upgrades-none = aucun album à améliorer
+upgrades-plays = { $plays } écoutes
+
+## Fichiers illisibles
+
+unreadable-none = aucun fichier illisible
+unreadable-none-unsupported = aucun fichier non pris en charge
+
+## Streaming
+
+stream-get-track = stream : impossible de récupérer le morceau { $id }, erreur : { $err }
+stream-get-gain = stream : impossible de récupérer le gain du morceau { $id }, erreur : { $err }
+stream-transcode = stream : impossible de transcoder le morceau { $id }, erreur : { $err }
+stream-connection = stream : impossible d'obtenir une connexion à la base de données, erreur : { $err }
+stream-auth = stream : impossible d'authentifier, erreur : { $err }
+
+## Liens
+
+links-unknown-service = « { $url } » n'est pas une URL Spotify, Apple Music ou Bandcamp
+links-none = aucun lien
+
+## Boîte de réception
+
+inbox-staged = { $files ->
+    [one] { $files } fichier mis en attente dans la boîte de réception
+   *[other] { $files } fichiers mis en attente dans la boîte de réception
+}
+inbox-files = { $files ->
+    [one] { $files } fichier
+   *[other] { $files } fichiers
+}
+inbox-unknown = Inconnu
+inbox-untagged = { $files ->
+    [one] { $files } fichier sans tags
+   *[other] { $files } fichiers sans tags
+}
+inbox-empty = la boîte de réception est vide
+
+## Morceaux manquants
+
+tombstones-missing-since = manquant depuis { $since }
+tombstones-none = aucun morceau manquant
+tombstones-restored = { $tracks ->
+    [one] { $tracks } morceau restauré, la prochaine analyse le marquera de nouveau manquant si son fichier n'est toujours pas là
+   *[other] { $tracks } morceaux restaurés, la prochaine analyse les marquera de nouveau manquants si leurs fichiers ne sont toujours pas là
+}
+
+## Champs de tags personnalisés
+
+tags-tracks = { $count ->
+    [one] { $count } morceau
+   *[other] { $count } morceaux
+}
+tags-no-field = aucun morceau n'a de champ { $name }
+tags-no-custom-fields = aucun champ personnalisé, associez-les avec `zik config tag_fields`
+
+## Sélecteur
+
+pick-io = impossible d'afficher le sélecteur, erreur : { $err }
+pick-no-terminal = aucun terminal pour choisir, utilisez plutôt --filter, erreur : { $err }
+
+## Métriques
+
+metrics-connection = métriques : impossible d'obtenir une connexion à la base de données, erreur : { $err }
+metrics-read = métriques : impossible de lire la base de données, erreur : { $err }
+metrics-auth = métriques : impossible d'authentifier, erreur : { $err }
+
+## Illustrations
+
+artwork-find-image = impossible de trouver l'image de l'artiste n°{ $id }, erreur : { $err }
+artwork-no-image = aucune image trouvée pour { $name }, ajoutez un artist.jpg à côté de ses albums et relancez l'analyse
+
+## Années
+
+years-tracks = { $tracks ->
+    [one] { $tracks } morceau
+   *[other] { $tracks } morceaux
+}
+years-none = aucun album n'a de morceaux d'années différentes
+
+## Limitation
+
+throttle-failed = impossible de baisser la priorité, { $program } { $status }
+throttle-error = impossible de baisser la priorité, { $program } erreur : { $err }
+
+## ffmpeg
+
+ffmpeg-not-found = ffmpeg introuvable, installez-le pour analyser ou transcoder les morceaux
+ffmpeg-io = impossible de lancer ffmpeg, erreur : { $err }
+ffmpeg-failed = échec de ffmpeg, erreur : { $err }
+ffmpeg-unknown-format = format de transcodage « { $format } » inconnu
+
+## Flux
+
+feed-connection = flux : impossible d'obtenir une connexion à la base de données, erreur : { $err }
+feed-auth = flux : impossible d'authentifier, erreur : { $err }
+feed-serve = flux : impossible de servir { $path }, erreur : { $err }
+
+## Site
+
+site-no-ffmpeg = ffmpeg introuvable, copie des pochettes sans les redimensionner
+site-scale = impossible de redimensionner « { $path } », erreur : { $err }
+
+## Serveur
+
+server-connection = serveur : erreur de connexion, erreur : { $err }
+server-accept = serveur : impossible d'accepter la connexion, erreur : { $err }
+
+## Notifications
+
+notify-failed = notification : impossible de notifier { $url }, erreur : { $err }
+notify-read = notification : impossible de lire `{ $target }`, erreur : { $err }
+
+## Albums incomplets
+
+incomplete-tracks = a { $tracks } { $expected ->
+    [one] morceau sur { $expected }
+   *[other] morceaux sur { $expected }
+}, il manque { $missing }
+incomplete-disc = disque { $disc } : { $problem }
+incomplete-discs = a { $discs } { $expected ->
+    [one] disque sur { $expected }
+   *[other] disques sur { $expected }
+}, il manque { $missing }
+
+## Mesures de performances
+
+bench-not-empty = « { $path } » n'est pas vide, générez la bibliothèque dans un nouveau dossier
+bench-invalid-files = la valeur « { $value } » de --files n'est pas valide, un nombre positif attendu
+bench-invalid-audio = la valeur « { $value } » de --audio n'est pas valide, un nombre de Kio attendu
+bench-generated = { $files } fichiers générés dans « { $path } », analysez-les avec `zik --database :memory: scan { $path }`
+
+## Diffusion
+
+cast-searching = recherche des appareils du réseau local
+cast-connected = connecté à { $device }
+cast-controls = commandes : p pour mettre en pause ou reprendre, n suivant, b précédent, + et - pour le volume, q pour arrêter, puis Entrée
+cast-volume = volume à { $percent } %
+cast-no-volume = { $device } ne permet pas de changer le volume
+cast-track-failed = impossible de lire « { $name } », raison : { $reason }
+cast-closed = { $device } a été repris par un autre émetteur, arrêt
+cast-discovery = impossible de rechercher les appareils, erreur : { $err }
+cast-no-devices = aucun appareil Chromecast ou AirPlay trouvé sur le réseau local
+cast-device-not-found = aucun appareil nommé « { $name } », trouvés : { $devices }
+cast-invalid-server-address = server_address « { $address } » n'est pas valide, une adresse comme 0.0.0.0:4533 attendue
+cast-loopback-server = le serveur écoute sur { $address }, que { $device } ne peut pas joindre, réglez server_address sur une adresse du réseau local
+cast-server-not-running = aucun serveur ne répond sur { $address }, démarrez-le avec `zik daemon`
+cast-user-required = { $device } lit depuis le serveur, qui a des utilisateurs, indiquez-en un avec --user
+cast-io = impossible de communiquer avec { $device }, erreur : { $err }
+cast-refused = { $device } a refusé la requête avec le statut { $status }
+cast-not-launched = { $device } n'a pas démarré son lecteur multimédia
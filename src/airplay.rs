@@ -0,0 +1,241 @@
+//! Casting to an AirPlay receiver with the HTTP requests of AirPlay video.
+//!
+//! `POST /play` gives the receiver a URL, `/rate` pauses and resumes it and `/stop` ends it.
+//! `GET /playback-info` answers a property list with the duration and position, which tells
+//! when the track is over. The session lives as long as the connection, so every request goes
+//! through the same one.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cast::{Media, Receiver, ReceiverError, Status};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The duration, position and rate of what's playing, from `/playback-info`.
+#[derive(Debug, Default, PartialEq)]
+struct PlaybackInfo {
+    duration: Option<f64>,
+    position: Option<f64>,
+    rate: Option<f64>,
+}
+
+/// Returns the real number following `<key>name</key>` in a property list.
+fn plist_real(plist: &str, name: &str) -> Option<f64> {
+    let key = format!("<key>{}</key>", name);
+    let rest = plist[plist.find(&key)? + key.len()..].trim_start();
+    let rest = rest
+        .strip_prefix("<real>")
+        .or_else(|| rest.strip_prefix("<integer>"))?;
+    rest[..rest.find('<')?].trim().parse().ok()
+}
+
+impl PlaybackInfo {
+    fn parse(plist: &str) -> PlaybackInfo {
+        PlaybackInfo {
+            duration: plist_real(plist, "duration"),
+            position: plist_real(plist, "position"),
+            rate: plist_real(plist, "rate"),
+        }
+    }
+}
+
+pub struct AirPlay {
+    host: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    session: String,
+    /// Whether the track playing was seen with a duration, receivers forget it at its end.
+    started: bool,
+    paused: bool,
+}
+
+impl AirPlay {
+    pub fn connect(address: SocketAddr) -> Result<AirPlay, ReceiverError> {
+        let writer = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        writer.set_read_timeout(Some(READ_TIMEOUT))?;
+        let reader = BufReader::new(writer.try_clone()?);
+
+        // Any unique ID, receivers only compare it
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let session = format!("{:016x}-{:08x}", now.as_nanos() as u64, std::process::id());
+
+        Ok(AirPlay {
+            host: address.to_string(),
+            reader,
+            writer,
+            session,
+            started: false,
+            paused: false,
+        })
+    }
+
+    /// Sends a request, returning the status and body of the response.
+    fn request(
+        &mut self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+    ) -> io::Result<(u16, String)> {
+        let body = body.unwrap_or_default();
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: MediaControl/1.0\r\nX-Apple-Session-ID: {}\r\nContent-Length: {}\r\n",
+            method,
+            path,
+            self.host,
+            self.session,
+            body.len()
+        );
+        if !body.is_empty() {
+            request.push_str("Content-Type: text/parameters\r\n");
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        self.writer.write_all(request.as_bytes())?;
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid HTTP response");
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let status: u16 = line
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(invalid)?;
+
+        let mut len = 0;
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    len = value.trim().parse().map_err(|_| invalid())?;
+                }
+            }
+        }
+
+        let mut body = vec![0; len];
+        self.reader.read_exact(&mut body)?;
+
+        Ok((status, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Sends a request which must succeed.
+    fn command(
+        &mut self,
+        method: &str,
+        path: &str,
+        body: Option<&str>,
+    ) -> Result<(), ReceiverError> {
+        match self.request(method, path, body)? {
+            (200..=299, _) => Ok(()),
+            (status, _) => Err(ReceiverError::Refused(status)),
+        }
+    }
+}
+
+impl Receiver for AirPlay {
+    fn load(&mut self, media: &Media) -> Result<(), ReceiverError> {
+        let body = format!("Content-Location: {}\nStart-Position: 0\n", media.url);
+        self.command("POST", "/play", Some(&body))?;
+        self.started = false;
+        self.paused = false;
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), ReceiverError> {
+        self.command("POST", "/rate?value=0.000000", None)?;
+        self.paused = true;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), ReceiverError> {
+        self.command("POST", "/rate?value=1.000000", None)?;
+        self.paused = false;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), ReceiverError> {
+        self.command("POST", "/stop", None)
+    }
+
+    fn change_volume(&mut self, _delta: f64) -> Result<Option<f64>, ReceiverError> {
+        Ok(None)
+    }
+
+    fn poll(&mut self) -> Result<Status, ReceiverError> {
+        let info = match self.request("GET", "/playback-info", None)? {
+            (200, body) => PlaybackInfo::parse(&body),
+            (status, _) => return Err(ReceiverError::Refused(status)),
+        };
+
+        let status = match info {
+            PlaybackInfo {
+                duration: Some(duration),
+                position: Some(position),
+                rate,
+            } if duration > 0.0 => {
+                self.started = true;
+                if rate == Some(0.0) && position >= duration - 1.0 {
+                    Status::Finished
+                } else if self.paused {
+                    Status::Paused
+                } else {
+                    Status::Playing
+                }
+            }
+            // Receivers answer without a duration once the track is over
+            _ if self.started => Status::Finished,
+            _ => Status::Loading,
+        };
+        if let Status::Finished = status {
+            self.started = false;
+        }
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_playback_info() {
+        let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>duration</key>
+	<real>245.5</real>
+	<key>loadedTimeRanges</key>
+	<array/>
+	<key>position</key>
+	<real>12.25</real>
+	<key>rate</key>
+	<integer>1</integer>
+	<key>readyToPlay</key>
+	<true/>
+</dict>
+</plist>"#;
+        assert_eq!(
+            PlaybackInfo::parse(plist),
+            PlaybackInfo {
+                duration: Some(245.5),
+                position: Some(12.25),
+                rate: Some(1.0),
+            }
+        );
+        assert_eq!(PlaybackInfo::parse("<plist/>"), PlaybackInfo::default());
+    }
+}
@@ -0,0 +1,383 @@
+//! Shortcuts for daily use: aliases of commands and saved filters of tracks.
+//!
+//! An alias is the start of a command line, `zik alias add jz list tracks --genre Jazz` makes
+//! `zik jz --json` run `zik list tracks --genre Jazz --json`. Aliases are expanded once, before
+//! the arguments are parsed, and never shadow a command of zik.
+//!
+//! A saved filter is a condition on the columns of `v_tracks`, like
+//! `zik filter add jazz60s "genre = 'Jazz' AND year BETWEEN 1960 AND 1969"`, run with
+//! `zik filter jazz60s` or an alias of it: `zik alias add f filter` then `zik f jazz60s`.
+
+use std::ffi::OsString;
+use std::fmt;
+
+use crate::i18n::tr;
+use crate::query::{self, CommandQueryError};
+
+pub enum CommandAliasError {
+    SQLite(rusqlite::Error),
+    Query(CommandQueryError),
+    InvalidAlias(String),
+    AliasNotFound(String),
+    InvalidFilterName(String),
+    InvalidFilter(String, rusqlite::Error),
+    FilterNotFound(String),
+}
+impl From<rusqlite::Error> for CommandAliasError {
+    fn from(err: rusqlite::Error) -> CommandAliasError {
+        CommandAliasError::SQLite(err)
+    }
+}
+impl From<CommandQueryError> for CommandAliasError {
+    fn from(err: CommandQueryError) -> CommandAliasError {
+        CommandAliasError::Query(err)
+    }
+}
+impl fmt::Display for CommandAliasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandAliasError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandAliasError::Query(err) => write!(f, "{}", err),
+            CommandAliasError::InvalidAlias(value) => {
+                f.write_str(&tr!("alias-invalid", value = value))
+            }
+            CommandAliasError::AliasNotFound(name) => {
+                f.write_str(&tr!("alias-not-found", name = name))
+            }
+            CommandAliasError::InvalidFilterName(name) => {
+                f.write_str(&tr!("alias-invalid-filter-name", name = name))
+            }
+            CommandAliasError::InvalidFilter(expression, err) => f.write_str(&tr!(
+                "alias-invalid-filter",
+                expression = expression,
+                err = err
+            )),
+            CommandAliasError::FilterNotFound(name) => {
+                f.write_str(&tr!("alias-filter-not-found", name = name))
+            }
+        }
+    }
+}
+
+/// The names `zik filter` can't give a filter, its commands.
+const FILTER_COMMANDS: [&str; 3] = ["add", "remove", "list"];
+
+/// Splits a command line into words like a shell, with single and double quotes and
+/// backslashes; none if a quote isn't closed.
+fn split_words(value: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => word.push(chars.next()?),
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Some(words)
+}
+
+/// Quotes a word for `split_words` if needed.
+fn quote_word(word: &str) -> String {
+    if !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'))
+    {
+        return word.to_owned();
+    }
+
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Returns the index of the command in `args`, past the program and the global options.
+fn command_index(args: &[OsString]) -> Option<usize> {
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        let arg = arg.to_str()?;
+        match arg {
+            "--data-dir" | "--database" => i += 2,
+            _ if arg.starts_with('-') => i += 1,
+            _ => return Some(i),
+        }
+    }
+
+    None
+}
+
+fn find_alias(args: &[OsString], name: &str) -> Option<String> {
+    // The database the command line is for
+    let value_of = |option: &str| {
+        let prefix = format!("{}=", option);
+        args.iter()
+            .zip(args.iter().skip(1))
+            .find_map(|(arg, next)| {
+                let arg = arg.to_str()?;
+                if arg == option {
+                    next.to_str().map(str::to_owned)
+                } else {
+                    arg.strip_prefix(&prefix).map(str::to_owned)
+                }
+            })
+    };
+    if let Some(data_dir) = value_of("--data-dir") {
+        crate::set_data_dir(std::path::Path::new(&data_dir));
+    }
+    if let Some(database) = value_of("--database") {
+        *crate::DATABASE.lock().unwrap() = Some(database);
+    }
+
+    // Without the table, the database is older than aliases and has none
+    let db = crate::open_database().ok()?;
+    db.query_row(
+        "SELECT expansion FROM command_alias WHERE name = $name",
+        [name],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// Replaces an alias at the place of the command in `args` by what it stands for; the
+/// arguments are returned as they are without one.
+pub fn expand_args(app: &clap::Command, mut args: Vec<OsString>) -> Vec<OsString> {
+    let index = match command_index(&args) {
+        Some(index) => index,
+        None => return args,
+    };
+    let name = args[index].to_string_lossy().into_owned();
+    if app.find_subcommand(&name).is_some() || name == "help" {
+        return args;
+    }
+
+    let words = match find_alias(&args, &name).and_then(|value| split_words(&value)) {
+        Some(words) => words,
+        None => return args,
+    };
+    args.splice(index..=index, words.into_iter().map(OsString::from));
+
+    args
+}
+
+//
+// "alias" command
+//
+
+fn cmd_alias_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    let name = args.value_of("name").unwrap();
+    let words: Vec<&str> = args.values_of("command").unwrap().collect();
+
+    // A single word is a whole command line already
+    let expansion = match words.as_slice() {
+        [line] => line.to_string(),
+        words => words
+            .iter()
+            .map(|word| quote_word(word))
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    if split_words(&expansion).is_none() {
+        return Err(CommandAliasError::InvalidAlias(expansion));
+    }
+
+    db.execute(
+        "INSERT INTO command_alias(name, expansion) VALUES($name, $expansion)
+         ON CONFLICT(name) DO UPDATE SET expansion = excluded.expansion",
+        [name, &expansion],
+    )?;
+
+    println!("{} = {}", name, expansion);
+
+    Ok(())
+}
+
+fn cmd_alias_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    let name = args.value_of("name").unwrap();
+
+    let n = db.execute("DELETE FROM command_alias WHERE name = $name", [name])?;
+    if n == 0 {
+        return Err(CommandAliasError::AliasNotFound(name.to_string()));
+    }
+
+    Ok(())
+}
+
+fn cmd_alias_list(db: &mut rusqlite::Connection) -> Result<(), CommandAliasError> {
+    let mut stmt = db.prepare("SELECT name, expansion FROM command_alias ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let expansion: String = row.get(1)?;
+
+        println!("{} = {}", name, expansion);
+    }
+
+    Ok(())
+}
+
+pub fn cmd_alias(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    match args.subcommand() {
+        Some(("add", sub_args)) => cmd_alias_add(db, sub_args),
+        Some(("remove", sub_args)) => cmd_alias_remove(db, sub_args),
+        _ => cmd_alias_list(db),
+    }
+}
+
+//
+// "filter" command
+//
+
+/// Returns the query of the tracks matching `expression`.
+fn filter_query(expression: &str, columns: &str) -> String {
+    format!(
+        "SELECT {} FROM v_tracks WHERE ({})
+         ORDER BY artist COLLATE natural_sort, album COLLATE natural_sort, disc_number,
+                  track_number",
+        columns, expression
+    )
+}
+
+fn cmd_filter_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    let name = args.value_of("name").unwrap();
+    let expression = args.value_of("expression").unwrap().trim();
+
+    if FILTER_COMMANDS.contains(&name) {
+        return Err(CommandAliasError::InvalidFilterName(name.to_string()));
+    }
+    // Checked now rather than when it's run
+    if let Err(err) = db.prepare(&filter_query(expression, "id")) {
+        return Err(CommandAliasError::InvalidFilter(
+            expression.to_string(),
+            err,
+        ));
+    }
+
+    db.execute(
+        "INSERT INTO saved_filter(name, expression) VALUES($name, $expression)
+         ON CONFLICT(name) DO UPDATE SET expression = excluded.expression",
+        [name, expression],
+    )?;
+
+    println!("{} = {}", name, expression);
+
+    Ok(())
+}
+
+fn cmd_filter_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    let name = args.value_of("name").unwrap();
+
+    let n = db.execute("DELETE FROM saved_filter WHERE name = $name", [name])?;
+    if n == 0 {
+        return Err(CommandAliasError::FilterNotFound(name.to_string()));
+    }
+
+    Ok(())
+}
+
+fn cmd_filter_list(db: &mut rusqlite::Connection) -> Result<(), CommandAliasError> {
+    let mut stmt = db.prepare("SELECT name, expression FROM saved_filter ORDER BY name")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let expression: String = row.get(1)?;
+
+        println!("{} = {}", name, expression);
+    }
+
+    Ok(())
+}
+
+fn cmd_filter_run(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    let name = args.value_of("name").unwrap();
+
+    let expression: String = match db.query_row(
+        "SELECT expression FROM saved_filter WHERE name = $name",
+        [name],
+        |row| row.get(0),
+    ) {
+        Ok(expression) => expression,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(CommandAliasError::FilterNotFound(name.to_string()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Saved filters are run like `zik query`, they can't change anything either
+    let db = query::open_read_only(db)?;
+    let columns = if args.is_present("ids") {
+        "id"
+    } else if args.is_present("paths") {
+        "path"
+    } else {
+        "id, artist, album, track_number, title, year, genre"
+    };
+    let format = match args.value_of("format") {
+        Some(format) => format,
+        // Without a header, for `zik play $(zik filter jazz60s --ids)`
+        None if args.is_present("ids") || args.is_present("paths") => "lines",
+        None => "table",
+    };
+
+    let mut stmt = db.prepare(&filter_query(&expression, columns))?;
+    query::print_rows(&mut stmt, format)?;
+
+    Ok(())
+}
+
+pub fn cmd_filter(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandAliasError> {
+    match args.subcommand() {
+        Some(("add", sub_args)) => cmd_filter_add(db, sub_args),
+        Some(("remove", sub_args)) => cmd_filter_remove(db, sub_args),
+        Some(("list", _)) => cmd_filter_list(db),
+        _ => cmd_filter_run(db, args),
+    }
+}
@@ -0,0 +1,406 @@
+//! Zip archives scanned as folders of tracks when `scan_archives` is set.
+//!
+//! A track inside an archive is stored at a path like `/music/Album.zip!01 Song.flac`. Its
+//! tags are read from the archive without extracting it, and it's streamed as it is; ffmpeg
+//! can't read inside archives, so it's neither transcoded nor analyzed. Only stored and
+//! deflated entries of archives without the zip64 extensions are supported, which covers what
+//! common tools write for archives under 4 GiB.
+//!
+//! The sizes of the headers are checked against the length of the archive before anything is
+//! read, inflating stops at the size announced and files are checked against their CRC-32: a
+//! corrupt archive is skipped instead of taking the memory of the scan.
+
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::i18n::tr;
+use crate::{subsonic, tags, Metadata, MetadataReadError};
+
+/// What separates the path of an archive from the path of a file inside it.
+pub const SEPARATOR: char = '!';
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+
+/// The end of central directory record is followed by a comment of at most 64 KiB.
+const MAX_END_RECORD_SEARCH: u64 = 22 + 65535;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// A file inside an archive.
+pub struct Entry {
+    pub name: String,
+    method: u16,
+    crc: u32,
+    compressed_size: u64,
+    pub size: u64,
+    offset: u64,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+fn u16_at(data: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([data[i], data[i + 1]])
+}
+
+fn u32_at(data: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]])
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// Returns the path of a file inside an archive.
+pub fn entry_path(archive: &Path, name: &str) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(SEPARATOR.to_string());
+    path.push(name);
+    PathBuf::from(path)
+}
+
+/// Splits the path of a file inside an archive into the archive and the name of the file.
+pub fn split(path: &Path) -> Option<(PathBuf, String)> {
+    let value = path.to_str()?;
+    let end = value
+        .to_ascii_lowercase()
+        .find(&format!(".zip{}", SEPARATOR))?
+        + ".zip".len();
+
+    Some((PathBuf::from(&value[..end]), value[end + 1..].to_owned()))
+}
+
+/// Lists the files of an archive, folders left out.
+pub fn list(path: &Path) -> io::Result<Vec<Entry>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    // The end record is the last thing in the file, before its comment
+    let tail_len = len.min(MAX_END_RECORD_SEARCH);
+    file.seek(io::SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact(&mut tail)?;
+
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == END_OF_CENTRAL_DIRECTORY)
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let count = u16_at(&tail, end + 10) as usize;
+    let directory_size = u32_at(&tail, end + 12) as u64;
+    let directory_offset = u32_at(&tail, end + 16) as u64;
+    if directory_offset == 0xffffffff {
+        return Err(invalid("zip64 archives aren't supported"));
+    }
+    if directory_offset + directory_size > len {
+        return Err(invalid("central directory past the end of the archive"));
+    }
+
+    file.seek(io::SeekFrom::Start(directory_offset))?;
+    let mut directory = vec![0; directory_size as usize];
+    file.read_exact(&mut directory)?;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut i = 0;
+    while i + 46 <= directory.len() && u32_at(&directory, i) == CENTRAL_DIRECTORY_HEADER {
+        let method = u16_at(&directory, i + 10);
+        let crc = u32_at(&directory, i + 16);
+        let compressed_size = u32_at(&directory, i + 20) as u64;
+        let size = u32_at(&directory, i + 24) as u64;
+        let name_len = u16_at(&directory, i + 28) as usize;
+        let extra_len = u16_at(&directory, i + 30) as usize;
+        let comment_len = u16_at(&directory, i + 32) as usize;
+        let offset = u32_at(&directory, i + 42) as u64;
+
+        let name = directory
+            .get(i + 46..i + 46 + name_len)
+            .ok_or_else(|| invalid("truncated central directory"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        i += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') || [compressed_size, size, offset].contains(&0xffffffff) {
+            continue;
+        }
+        entries.push(Entry {
+            name,
+            method,
+            crc,
+            compressed_size,
+            size,
+            offset,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Reads a file of an archive whole.
+pub fn read(path: &Path, entry: &Entry) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    // The sizes of the name and extra field can differ from the central directory's
+    file.seek(io::SeekFrom::Start(entry.offset))?;
+    let mut header = [0; 30];
+    file.read_exact(&mut header)?;
+    if u32_at(&header, 0) != LOCAL_FILE_HEADER {
+        return Err(invalid("invalid local file header"));
+    }
+    let skip = u16_at(&header, 26) as u64 + u16_at(&header, 28) as u64;
+    if entry.offset + 30 + skip + entry.compressed_size > len {
+        return Err(invalid("file past the end of the archive"));
+    }
+    file.seek(io::SeekFrom::Current(skip as i64))?;
+
+    let mut data = vec![0; entry.compressed_size as usize];
+    file.read_exact(&mut data)?;
+
+    let data = match entry.method {
+        STORED if entry.size == entry.compressed_size => data,
+        STORED => return Err(invalid("stored file with two sizes")),
+        DEFLATED => inflate(&data, entry.size)?,
+        _ => return Err(invalid("unsupported compression method")),
+    };
+
+    let mut crc = flate2::Crc::new();
+    crc.update(&data);
+    if crc.sum() != entry.crc {
+        return Err(invalid("CRC-32 mismatch"));
+    }
+
+    Ok(data)
+}
+
+/// Decompresses a raw DEFLATE stream, as found in zip archives, which must give `size` bytes.
+fn inflate(data: &[u8], size: u64) -> io::Result<Vec<u8>> {
+    // A byte more than announced is enough to know it's wrong
+    let mut out = Vec::new();
+    flate2::read::DeflateDecoder::new(data)
+        .take(size + 1)
+        .read_to_end(&mut out)?;
+    if out.len() as u64 != size {
+        return Err(invalid("inflated size differs from the archive's"));
+    }
+
+    Ok(out)
+}
+
+/// Reads the file `name` of an archive whole.
+pub fn read_file(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+    match list(path)?.iter().find(|entry| entry.name == name) {
+        Some(entry) => read(path, entry),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            tr!("archive-no-file", name = name),
+        )),
+    }
+}
+
+/// Reads the tracks of an archive, by path, skipping the files which aren't audio.
+pub fn read_tracks(
+    path: &Path,
+    fields: &tags::CustomFields,
+) -> Result<Vec<(PathBuf, Metadata)>, MetadataReadError> {
+    let mut entries = list(path)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut tracks = Vec::new();
+    for entry in entries {
+        let track_path = entry_path(path, &entry.name);
+        if !subsonic::content_type_for(&track_path).starts_with("audio/") {
+            continue;
+        }
+
+        let reader = io::Cursor::new(read(path, &entry)?);
+        match Metadata::read_from_reader(reader, &track_path, fields)? {
+            Some(md) if !md.video => tracks.push((track_path, md)),
+            _ => (),
+        }
+    }
+
+    Ok(tracks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    struct File {
+        name: &'static str,
+        method: u16,
+        data: Vec<u8>,
+        size: u32,
+        crc: u32,
+    }
+
+    fn file(name: &'static str, method: u16, content: &[u8]) -> File {
+        let data = if method == DEFLATED {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(content).unwrap();
+            encoder.finish().unwrap()
+        } else {
+            content.to_vec()
+        };
+        let mut crc = flate2::Crc::new();
+        crc.update(content);
+
+        File {
+            name,
+            method,
+            data,
+            size: content.len() as u32,
+            crc: crc.sum(),
+        }
+    }
+
+    fn zip(files: &[File]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for file in files {
+            let offset = out.len() as u32;
+            for (header, signature) in [
+                (&mut out, LOCAL_FILE_HEADER),
+                (&mut directory, CENTRAL_DIRECTORY_HEADER),
+            ] {
+                header.extend(signature.to_le_bytes());
+                if signature == CENTRAL_DIRECTORY_HEADER {
+                    header.extend(20u16.to_le_bytes());
+                }
+                header.extend([20, 0, 0, 0]);
+                header.extend(file.method.to_le_bytes());
+                header.extend([0; 4]);
+                header.extend(file.crc.to_le_bytes());
+                header.extend((file.data.len() as u32).to_le_bytes());
+                header.extend(file.size.to_le_bytes());
+                header.extend((file.name.len() as u16).to_le_bytes());
+                header.extend([0; 2]);
+                if signature == CENTRAL_DIRECTORY_HEADER {
+                    header.extend([0; 10]);
+                    header.extend(offset.to_le_bytes());
+                }
+                header.extend(file.name.as_bytes());
+            }
+            out.extend(&file.data);
+        }
+
+        let directory_offset = out.len() as u32;
+        out.extend(&directory);
+        out.extend(END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((files.len() as u16).to_le_bytes());
+        out.extend((directory.len() as u32).to_le_bytes());
+        out.extend(directory_offset.to_le_bytes());
+        out.extend([0; 2]);
+        out
+    }
+
+    /// Offset of the first header of the central directory.
+    fn directory(archive: &[u8]) -> usize {
+        u32_at(archive, archive.len() - 22 + 16) as usize
+    }
+
+    fn patch(archive: &mut [u8], i: usize, value: u32) {
+        archive[i..i + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn read_all(name: &str, archive: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+        let path = env::temp_dir().join(format!("zik-archive-{}-{}.zip", process::id(), name));
+        fs::write(&path, archive).unwrap();
+        let files = list(&path).and_then(|entries| {
+            entries
+                .into_iter()
+                .map(|entry| Ok((entry.name.clone(), read(&path, &entry)?)))
+                .collect()
+        });
+        fs::remove_file(&path).unwrap();
+        files
+    }
+
+    #[test]
+    fn stored_and_deflated() {
+        let text = b"Alpha Beta Gamma ".repeat(200);
+        let archive = zip(&[
+            file("cover.jpg", STORED, b"\xff\xd8\xff\xe0"),
+            file("01 Song.txt", DEFLATED, &text),
+            file("empty", DEFLATED, b""),
+        ]);
+
+        let files = read_all("ok", &archive).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                ("cover.jpg".to_string(), b"\xff\xd8\xff\xe0".to_vec()),
+                ("01 Song.txt".to_string(), text),
+                ("empty".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn sizes_past_the_archive() {
+        let archive = zip(&[file("a", DEFLATED, &b"a".repeat(1000))]);
+        let end = archive.len() - 22;
+
+        let mut directory_size = archive.clone();
+        patch(&mut directory_size, end + 12, 0xfffff000);
+        assert!(read_all("directory", &directory_size).is_err());
+
+        let mut compressed_size = archive.clone();
+        patch(&mut compressed_size, directory(&archive) + 20, 0xfffff000);
+
+        assert!(read_all("compressed", &compressed_size).is_err());
+
+        // Inflating stops at the size announced, whether it's too small or too large
+        let i = directory(&archive) + 24;
+        for (name, size) in [("small", 999), ("large", 0xfffff000)] {
+            let mut archive = archive.clone();
+            patch(&mut archive, i, size);
+            assert!(read_all(name, &archive).is_err());
+        }
+    }
+
+    #[test]
+    fn stored_with_two_sizes() {
+        let mut archive = zip(&[file("a", STORED, b"abcd")]);
+        let i = directory(&archive) + 24;
+        patch(&mut archive, i, 3);
+        assert!(read_all("two-sizes", &archive).is_err());
+    }
+
+    #[test]
+    fn crc_mismatch() {
+        for method in [STORED, DEFLATED] {
+            let mut archive = zip(&[file("a", method, b"abcd")]);
+            let i = directory(&archive) + 16;
+            patch(&mut archive, i, 0x12345678);
+            let error = read_all("crc", &archive).unwrap_err();
+            assert_eq!(error.to_string(), "CRC-32 mismatch");
+        }
+    }
+
+    #[test]
+    fn truncated_and_invalid_streams() {
+        let mut truncated = file(
+            "a",
+            DEFLATED,
+            &(0..=255).cycle().take(5000).collect::<Vec<u8>>(),
+        );
+        truncated.data.truncate(truncated.data.len() / 2);
+        assert!(read_all("truncated", &zip(&[truncated])).is_err());
+
+        // Block type 3 is reserved
+        let mut invalid = file("a", DEFLATED, b"abcd");
+        invalid.data = vec![0b111, 0, 0, 0];
+        assert!(read_all("invalid", &zip(&[invalid])).is_err());
+    }
+}
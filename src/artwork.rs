@@ -0,0 +1,336 @@
+//! Album artwork and artist images, either next to the audio files or embedded in their tags.
+//!
+//! Artist images come from an `artist.jpg` in the folder of one of the artist's albums or in
+//! the folder above it, or from the artist picture (type 8) embedded in their tracks. Scans
+//! find them for the artists without one, `zik cover artist <name>` prints where it is.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Seek;
+use std::path::{Path, PathBuf};
+
+use crate::i18n::tr;
+
+/// File names, without extension, commonly used for the cover of the album in a folder.
+const COVER_FILE_NAMES: [&str; 4] = ["cover", "folder", "front", "album"];
+/// File names, without extension, used for the image of the artist.
+const ARTIST_FILE_NAMES: [&str; 1] = ["artist"];
+const IMAGE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Looks for a cover image in `dir`, ignoring case.
+pub fn find_cover_file(dir: &Path) -> io::Result<Option<PathBuf>> {
+    find_image_file(dir, &COVER_FILE_NAMES)
+}
+
+/// Looks for an image named after one of `names` in `dir`, ignoring case, the first name
+/// winning.
+fn find_image_file(dir: &Path, names: &[&str]) -> io::Result<Option<PathBuf>> {
+    let mut candidates = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_lowercase());
+        let extension = path.extension().map(|s| s.to_string_lossy().to_lowercase());
+
+        if let (Some(stem), Some(extension)) = (stem, extension) {
+            let rank = names.iter().position(|name| *name == stem);
+            if rank.is_some() && IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+                candidates.push((rank, path));
+            }
+        }
+    }
+
+    candidates.sort();
+
+    Ok(candidates.into_iter().next().map(|(_, path)| path))
+}
+
+fn extension_for(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG") {
+        "png"
+    } else {
+        "jpg"
+    }
+}
+
+/// Reads the front cover embedded in an audio file, falling back to any embedded picture.
+pub fn read_embedded_cover(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let file = fs::File::open(path)?;
+    let mut reader = io::BufReader::new(file);
+
+    if let Ok(tag) = metaflac::Tag::read_from(&mut reader) {
+        let mut pictures: Vec<&metaflac::block::Picture> = tag.pictures().collect();
+        pictures.sort_by_key(|picture| {
+            picture.picture_type != metaflac::block::PictureType::CoverFront
+        });
+        return Ok(pictures.first().map(|picture| picture.data.clone()));
+    }
+
+    reader.seek(io::SeekFrom::Start(0))?;
+
+    if let Ok(tag) = id3::Tag::read_from(&mut reader) {
+        let mut pictures: Vec<&id3::frame::Picture> = tag.pictures().collect();
+        pictures.sort_by_key(|picture| picture.picture_type != id3::frame::PictureType::CoverFront);
+        return Ok(pictures.first().map(|picture| picture.data.clone()));
+    }
+
+    reader.seek(io::SeekFrom::Start(0))?;
+
+    if let Ok(root) = mp4parse::read_mp4(&mut reader) {
+        let cover = root
+            .userdata
+            .and_then(|result| result.ok())
+            .and_then(|user_data| user_data.meta)
+            .and_then(|metadata| metadata.cover_art)
+            .and_then(|covers| covers.first().map(|cover| cover.to_vec()));
+        return Ok(cover);
+    }
+
+    Ok(None)
+}
+
+/// Finds the cover of the album `track_path` belongs to.
+///
+/// A cover file in the track's folder wins; otherwise the embedded cover is extracted into
+/// `covers_dir`. Returns the path of the cover image, if there is one.
+pub fn find_album_cover(
+    track_path: &Path,
+    covers_dir: &Path,
+    album_id: i64,
+) -> io::Result<Option<PathBuf>> {
+    if let Some(dir) = track_path.parent() {
+        if let Some(path) = find_cover_file(dir)? {
+            return Ok(Some(path));
+        }
+    }
+
+    match read_embedded_cover(track_path)? {
+        Some(data) => {
+            fs::create_dir_all(covers_dir)?;
+
+            let path = covers_dir.join(format!("{}.{}", album_id, extension_for(&data)));
+            fs::write(&path, data)?;
+
+            Ok(Some(path))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Reads the artist picture embedded in an audio file, MP4 files have none.
+pub fn read_embedded_artist_image(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let file = fs::File::open(path)?;
+    let mut reader = io::BufReader::new(file);
+
+    if let Ok(tag) = metaflac::Tag::read_from(&mut reader) {
+        return Ok(tag
+            .pictures()
+            .find(|picture| picture.picture_type == metaflac::block::PictureType::Artist)
+            .map(|picture| picture.data.clone()));
+    }
+
+    reader.seek(io::SeekFrom::Start(0))?;
+
+    if let Ok(tag) = id3::Tag::read_from(&mut reader) {
+        return Ok(tag
+            .pictures()
+            .find(|picture| picture.picture_type == id3::frame::PictureType::Artist)
+            .map(|picture| picture.data.clone()));
+    }
+
+    Ok(None)
+}
+
+/// Finds the image of an artist from the folders of their albums and their tracks.
+///
+/// An `artist.jpg` in an album folder or the folder above it wins; otherwise an embedded artist
+/// picture is extracted into `images_dir`, when there is one.
+fn find_artist_image(
+    album_dirs: &[PathBuf],
+    track_paths: &[PathBuf],
+    images_dir: Option<&Path>,
+    artist_id: i64,
+) -> io::Result<Option<PathBuf>> {
+    for dir in album_dirs {
+        for dir in dir.ancestors().take(2) {
+            // Unreadable folders are reported by the scan itself
+            if let Ok(Some(path)) = find_image_file(dir, &ARTIST_FILE_NAMES) {
+                return Ok(Some(path));
+            }
+        }
+    }
+
+    let images_dir = match images_dir {
+        Some(images_dir) => images_dir,
+        None => return Ok(None),
+    };
+    for track_path in track_paths {
+        if let Some(data) = read_embedded_artist_image(track_path)? {
+            fs::create_dir_all(images_dir)?;
+
+            let path = images_dir.join(format!("{}.{}", artist_id, extension_for(&data)));
+            fs::write(&path, data)?;
+
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Looks for the image of the artists which don't have one, run at the end of a scan.
+///
+/// An artist where nothing was found is only looked at again once they get new tracks, those
+/// with the ID above `last_track_id`. Returns how many images were found.
+pub fn update_artist_images(
+    db: &rusqlite::Connection,
+    last_track_id: i64,
+) -> rusqlite::Result<usize> {
+    // Images deleted since, or nothing found for artists who got new tracks
+    let mut stmt = db.prepare("SELECT artist_id, library_path(path) FROM artist_image")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    for row in rows {
+        let (artist_id, path): (i64, Option<String>) = row?;
+        if matches!(&path, Some(path) if !Path::new(path).exists()) {
+            db.execute(
+                "DELETE FROM artist_image WHERE artist_id = $id",
+                [artist_id],
+            )?;
+        }
+    }
+    db.execute(
+        "DELETE FROM artist_image
+         WHERE path IS NULL
+           AND artist_id IN (
+             SELECT artist_id FROM track WHERE id > $last_track_id
+             UNION
+             SELECT album.artist_id FROM album JOIN track ON track.album_id = album.id
+             WHERE track.id > $last_track_id
+           )",
+        [last_track_id],
+    )?;
+
+    let artist_ids = {
+        let mut stmt = db.prepare(
+            "SELECT id FROM artist WHERE id NOT IN (SELECT artist_id FROM artist_image)",
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<i64>>>()?
+    };
+
+    let images_dir = crate::get_data_dir().map(|data_dir| data_dir.join("artists"));
+    let mut album_dirs_stmt = db.prepare(
+        "SELECT DISTINCT library_path(rtrim(track.path, replace(track.path, '/', '')))
+         FROM track
+         JOIN album ON album.id = track.album_id
+         WHERE album.artist_id = $id AND track.path IS NOT NULL AND track.missing_since IS NULL",
+    )?;
+    // One track of each album is enough, the artist picture is the same on all of them
+    let mut track_paths_stmt = db.prepare(
+        "SELECT library_path(MIN(path))
+         FROM track
+         WHERE artist_id = $id AND path IS NOT NULL AND missing_since IS NULL
+         GROUP BY album_id",
+    )?;
+
+    let mut found = 0;
+    for artist_id in artist_ids {
+        let album_dirs = album_dirs_stmt
+            .query_map([artist_id], |row| row.get::<_, String>(0))?
+            .map(|dir| dir.map(PathBuf::from))
+            .collect::<rusqlite::Result<Vec<PathBuf>>>()?;
+        let track_paths = track_paths_stmt
+            .query_map([artist_id], |row| row.get::<_, String>(0))?
+            .map(|path| path.map(PathBuf::from))
+            .collect::<rusqlite::Result<Vec<PathBuf>>>()?;
+
+        let path =
+            match find_artist_image(&album_dirs, &track_paths, images_dir.as_deref(), artist_id) {
+                Ok(path) => path,
+                Err(err) => {
+                    println!("{}", tr!("artwork-find-image", id = artist_id, err = err));
+                    continue;
+                }
+            };
+        if path.is_some() {
+            found += 1;
+        }
+
+        db.execute(
+            "INSERT INTO artist_image(artist_id, path) VALUES($id, $path)",
+            rusqlite::params![artist_id, path.map(|path| crate::library::relative(&path))],
+        )?;
+    }
+
+    Ok(found)
+}
+
+//
+// "cover" command
+//
+
+pub enum CommandCoverError {
+    SQLite(rusqlite::Error),
+    ArtistNotFound(String),
+    NoImage(String),
+}
+impl From<rusqlite::Error> for CommandCoverError {
+    fn from(err: rusqlite::Error) -> CommandCoverError {
+        CommandCoverError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandCoverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandCoverError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandCoverError::ArtistNotFound(name) => {
+                f.write_str(&tr!("artist-not-found", name = name))
+            }
+            CommandCoverError::NoImage(name) => f.write_str(&tr!("artwork-no-image", name = name)),
+        }
+    }
+}
+
+fn cmd_cover_artist(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCoverError> {
+    let name = args.value_of("name").unwrap();
+
+    let (artist_name, path): (String, Option<String>) = match db.query_row(
+        "SELECT artist.name, library_path(artist_image.path)
+         FROM artist
+         LEFT JOIN artist_image ON artist_image.artist_id = artist.id
+         WHERE artist.name = $name COLLATE NOCASE
+         ORDER BY artist_image.path IS NULL
+         LIMIT 1",
+        [name],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ) {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(CommandCoverError::ArtistNotFound(name.to_owned()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    match path {
+        Some(path) => {
+            println!("{}", path);
+            Ok(())
+        }
+        None => Err(CommandCoverError::NoImage(artist_name)),
+    }
+}
+
+pub fn cmd_cover(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCoverError> {
+    match args.subcommand() {
+        Some(("artist", args)) => cmd_cover_artist(db, args),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,191 @@
+//! `zik bench generate`: a fake library of thousands of tiny tagged files, to measure scans
+//! and queries on something the size of a real library. See also `benches/`.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fixtures;
+use crate::i18n::tr;
+
+const DEFAULT_FILES: usize = 5000;
+const TRACKS_PER_ALBUM: usize = 10;
+const ALBUMS_PER_ARTIST: usize = 5;
+
+const GENRES: [&str; 8] = [
+    "Rock",
+    "Jazz",
+    "hiphop",
+    "Electronic",
+    "Classical",
+    "(17)",
+    "Alt Rock",
+    "Folk",
+];
+
+/// Words of the comments, for the full-text search to find.
+const WORDS: [&str; 16] = [
+    "live", "remaster", "demo", "acoustic", "session", "bonus", "mono", "stereo", "radio", "edit",
+    "vinyl", "rip", "rare", "outtake", "cover", "version",
+];
+
+pub enum CommandBenchError {
+    IO(io::Error),
+    NotEmpty(PathBuf),
+    InvalidFiles(String),
+    InvalidAudio(String),
+}
+impl From<io::Error> for CommandBenchError {
+    fn from(err: io::Error) -> CommandBenchError {
+        CommandBenchError::IO(err)
+    }
+}
+impl fmt::Display for CommandBenchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandBenchError::IO(err) => f.write_str(&tr!("io-error", err = err)),
+            CommandBenchError::NotEmpty(path) => {
+                f.write_str(&tr!("bench-not-empty", path = path.display()))
+            }
+            CommandBenchError::InvalidFiles(value) => {
+                f.write_str(&tr!("bench-invalid-files", value = value))
+            }
+            CommandBenchError::InvalidAudio(value) => {
+                f.write_str(&tr!("bench-invalid-audio", value = value))
+            }
+        }
+    }
+}
+
+/// A linear congruential generator, so the same count always gives the same library.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % bound as u64) as usize
+    }
+}
+
+/// Writes `files` tracks into `dir`, a quarter each FLAC, MP3, M4A and Ogg Vorbis, with
+/// `audio` bytes of silence each. Returns how many were written.
+pub fn generate(dir: &Path, files: usize, audio: usize) -> io::Result<usize> {
+    let mut random = Random(files as u64);
+
+    for i in 0..files {
+        let number = i % TRACKS_PER_ALBUM + 1;
+        let album = i / TRACKS_PER_ALBUM;
+        let artist = album / ALBUMS_PER_ARTIST;
+
+        let artist_name = format!("Artist {:04}", artist);
+        let album_name = format!("Album {:05}", album);
+        let year = (1960 + album * 7 % 60).to_string();
+        let title = format!("Track {:06}", i);
+        let genre = GENRES[album % GENRES.len()];
+        let track_number = format!("{}/{}", number, TRACKS_PER_ALBUM);
+        let comment = (0..4)
+            .map(|_| WORDS[random.next(WORDS.len())])
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let folder = dir
+            .join(&artist_name)
+            .join(format!("{} ({})", album_name, year));
+        fs::create_dir_all(&folder)?;
+
+        // Every album is in one format, like real ones
+        let duration_ms = (120 + random.next(300)) as u64 * 1000;
+        let vorbis_comments = [
+            ("ARTIST", artist_name.as_str()),
+            ("ALBUM", &album_name),
+            ("DATE", &year),
+            ("TITLE", &title),
+            ("TRACKNUMBER", &track_number),
+            ("GENRE", genre),
+            ("COMMENT", &comment),
+        ];
+        let (data, extension) = match album % 4 {
+            0 => (fixtures::flac(duration_ms, &vorbis_comments), "flac"),
+            1 => {
+                let duration = duration_ms.to_string();
+                let data = fixtures::mp3(&[
+                    ("TPE1", &artist_name),
+                    ("TALB", &album_name),
+                    ("TYER", &year),
+                    ("TIT2", &title),
+                    ("TRCK", &track_number),
+                    ("TCON", genre),
+                    ("TLEN", &duration),
+                ]);
+                (data, "mp3")
+            }
+            2 => {
+                let data = fixtures::mp4(
+                    duration_ms,
+                    &[
+                        ("\u{a9}ART", &artist_name),
+                        ("\u{a9}alb", &album_name),
+                        ("\u{a9}day", &year),
+                        ("\u{a9}nam", &title),
+                        ("trkn", &track_number),
+                        ("\u{a9}gen", genre),
+                        ("\u{a9}cmt", &comment),
+                    ],
+                );
+                (data, "m4a")
+            }
+            _ => (fixtures::ogg(duration_ms, &vorbis_comments), "ogg"),
+        };
+        fs::write(
+            folder.join(format!("{:02} {}.{}", number, title, extension)),
+            fixtures::with_audio(data, audio),
+        )?;
+    }
+
+    Ok(files)
+}
+
+//
+// "bench" command
+//
+
+fn cmd_bench_generate(args: &clap::ArgMatches) -> Result<(), CommandBenchError> {
+    let dir = PathBuf::from(args.value_of("dir").unwrap());
+    let files: usize = match args.value_of("files") {
+        Some(value) => match value.parse() {
+            Ok(files) if files > 0 => files,
+            _ => return Err(CommandBenchError::InvalidFiles(value.to_owned())),
+        },
+        None => DEFAULT_FILES,
+    };
+    let audio: usize = match args.value_of("audio") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(kib) => kib * 1024,
+            _ => return Err(CommandBenchError::InvalidAudio(value.to_owned())),
+        },
+        None => 0,
+    };
+
+    if dir.exists() && fs::read_dir(&dir)?.next().is_some() {
+        return Err(CommandBenchError::NotEmpty(dir));
+    }
+
+    let written = generate(&dir, files, audio)?;
+    println!(
+        "{}",
+        tr!("bench-generated", files = written, path = dir.display())
+    );
+
+    Ok(())
+}
+
+pub fn cmd_bench(args: &clap::ArgMatches) -> Result<(), CommandBenchError> {
+    match args.subcommand() {
+        Some(("generate", sub_args)) => cmd_bench_generate(sub_args),
+        _ => Ok(()),
+    }
+}
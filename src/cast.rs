@@ -0,0 +1,449 @@
+//! `zik play --to <device>`: casting tracks to a Chromecast or an AirPlay receiver found on the
+//! local network.
+//!
+//! The device fetches the tracks from the `/stream` endpoint of the server, which must run and
+//! listen on an address of the local network, so `server_address` must not be a loopback one.
+//! When the server has users, the URLs carry a Subsonic token of the user given with `--user`,
+//! never the password. Formats the device can't play are transcoded by the endpoint.
+//!
+//! Tracks are loaded one at a time, the next one when the device says the current one is
+//! finished. Lines typed on the standard input control the playback, see `Command`.
+//!
+//! AirPlay receivers are the ones playing media URLs, like an Apple TV: speakers only speaking
+//! RAOP aren't found, and receivers asking for a pairing refuse to play.
+
+use std::fmt;
+use std::io::{self, BufRead};
+use std::net::{SocketAddr, TcpStream, UdpSocket};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::airplay::AirPlay;
+use crate::chromecast::Chromecast;
+use crate::daemon;
+use crate::ffmpeg;
+use crate::i18n::tr;
+use crate::mdns;
+use crate::play::Track;
+use crate::subsonic;
+use crate::user::{self, KeyError};
+
+const CHROMECAST_SERVICE: &str = "_googlecast._tcp.local";
+const AIRPLAY_SERVICE: &str = "_airplay._tcp.local";
+
+/// How long devices have to answer the discovery.
+const DISCOVERY_WAIT: Duration = Duration::from_secs(3);
+/// How often the device is asked how the playback goes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const VOLUME_STEP: f64 = 0.1;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Chromecast,
+    AirPlay,
+}
+
+impl Kind {
+    /// Returns the extensions of the files the device plays as they are.
+    fn plays(self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let extensions: &[&str] = match self {
+            Kind::Chromecast => &["mp3", "flac", "ogg", "oga", "opus", "m4a", "aac", "wav"],
+            Kind::AirPlay => &["mp3", "m4a", "aac", "wav", "aif", "aiff"],
+        };
+        extensions.contains(&extension.as_str())
+    }
+
+    /// Returns the format of the transcodes for the device, and its bitrate.
+    fn transcode(self) -> (&'static str, usize) {
+        match self {
+            Kind::Chromecast => ("mp3", 320),
+            Kind::AirPlay => ("aac", 256),
+        }
+    }
+}
+
+pub struct Device {
+    pub name: String,
+    pub kind: Kind,
+    pub address: SocketAddr,
+}
+
+/// A track as the device fetches it.
+pub struct Media<'a> {
+    pub url: String,
+    pub content_type: &'a str,
+    pub title: &'a str,
+    pub artist: &'a str,
+}
+
+#[derive(Clone)]
+pub enum Status {
+    Loading,
+    Playing,
+    Paused,
+    Finished,
+    /// The device couldn't play the track.
+    Failed(String),
+    /// Another sender took the device over.
+    Closed,
+}
+
+pub enum ReceiverError {
+    IO(io::Error),
+    /// The device answered with this HTTP status.
+    Refused(u16),
+    /// The device didn't start its media receiver.
+    NotLaunched,
+}
+impl From<io::Error> for ReceiverError {
+    fn from(err: io::Error) -> ReceiverError {
+        ReceiverError::IO(err)
+    }
+}
+
+/// A device playing media URLs.
+pub trait Receiver {
+    /// Plays `media`, replacing what was playing.
+    fn load(&mut self, media: &Media) -> Result<(), ReceiverError>;
+    fn pause(&mut self) -> Result<(), ReceiverError>;
+    fn resume(&mut self) -> Result<(), ReceiverError>;
+    fn stop(&mut self) -> Result<(), ReceiverError>;
+    /// Changes the volume by `delta`, returning the new one from 0 to 1. None if the device
+    /// has no volume control.
+    fn change_volume(&mut self, delta: f64) -> Result<Option<f64>, ReceiverError>;
+    /// Returns how the playback goes, waiting a little for news.
+    fn poll(&mut self) -> Result<Status, ReceiverError>;
+}
+
+pub enum CastError {
+    SQLite(rusqlite::Error),
+    Key(KeyError),
+    Discovery(io::Error),
+    NoDevices,
+    DeviceNotFound(String, Vec<String>),
+    InvalidServerAddress(String),
+    LoopbackServer(String, String),
+    ServerNotRunning(String),
+    UserRequired(String),
+    UserNotFound(String),
+    Receiver(String, ReceiverError),
+}
+impl From<rusqlite::Error> for CastError {
+    fn from(err: rusqlite::Error) -> CastError {
+        CastError::SQLite(err)
+    }
+}
+impl From<KeyError> for CastError {
+    fn from(err: KeyError) -> CastError {
+        CastError::Key(err)
+    }
+}
+impl fmt::Display for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CastError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CastError::Key(err) => write!(f, "{}", err),
+            CastError::Discovery(err) => f.write_str(&tr!("cast-discovery", err = err)),
+            CastError::NoDevices => f.write_str(&tr!("cast-no-devices")),
+            CastError::DeviceNotFound(name, found) => f.write_str(&tr!(
+                "cast-device-not-found",
+                name = name,
+                devices = found.join(", ")
+            )),
+            CastError::InvalidServerAddress(address) => {
+                f.write_str(&tr!("cast-invalid-server-address", address = address))
+            }
+            CastError::LoopbackServer(address, device) => f.write_str(&tr!(
+                "cast-loopback-server",
+                address = address,
+                device = device
+            )),
+            CastError::ServerNotRunning(address) => {
+                f.write_str(&tr!("cast-server-not-running", address = address))
+            }
+            CastError::UserRequired(device) => {
+                f.write_str(&tr!("cast-user-required", device = device))
+            }
+            CastError::UserNotFound(name) => f.write_str(&tr!("user-not-found", name = name)),
+            CastError::Receiver(device, ReceiverError::IO(err)) => {
+                f.write_str(&tr!("cast-io", device = device, err = err))
+            }
+            CastError::Receiver(device, ReceiverError::Refused(status)) => {
+                f.write_str(&tr!("cast-refused", device = device, status = status))
+            }
+            CastError::Receiver(device, ReceiverError::NotLaunched) => {
+                f.write_str(&tr!("cast-not-launched", device = device))
+            }
+        }
+    }
+}
+
+/// Returns the casting devices of the local network.
+pub fn discover() -> io::Result<Vec<Device>> {
+    let services = mdns::browse(&[CHROMECAST_SERVICE, AIRPLAY_SERVICE], DISCOVERY_WAIT)?;
+
+    let mut devices = Vec::new();
+    for service in services {
+        let (kind, name) = if service.service == CHROMECAST_SERVICE {
+            // The instance name of a Chromecast is its model and ID, "fn" the name given to it
+            let name = service.txt("fn").unwrap_or(&service.name).to_owned();
+            (Kind::Chromecast, name)
+        } else {
+            (Kind::AirPlay, service.name)
+        };
+        devices.push(Device {
+            name,
+            kind,
+            address: service.address,
+        });
+    }
+
+    Ok(devices)
+}
+
+/// A line of the standard input.
+enum Command {
+    /// "p"
+    TogglePause,
+    /// "n"
+    Next,
+    /// "b"
+    Previous,
+    /// "+" and "-"
+    Volume(f64),
+    /// "q"
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str) -> Option<Command> {
+        match line.trim() {
+            "p" => Some(Command::TogglePause),
+            "n" => Some(Command::Next),
+            "b" => Some(Command::Previous),
+            "+" => Some(Command::Volume(VOLUME_STEP)),
+            "-" => Some(Command::Volume(-VOLUME_STEP)),
+            "q" => Some(Command::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the commands typed on the standard input, until it's closed.
+fn read_commands() -> mpsc::Receiver<Command> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if let Some(command) = Command::parse(&line) {
+                if sender.send(command).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/// Returns the address of the server the device can reach, checking that it's running.
+fn server_address(db: &rusqlite::Connection, device: &Device) -> Result<SocketAddr, CastError> {
+    let value = crate::get_config_value(db, "server_address")?
+        .unwrap_or_else(|| daemon::DEFAULT_SERVER_ADDRESS.to_owned());
+    let mut address: SocketAddr = match value.parse() {
+        Ok(address) => address,
+        Err(_) => return Err(CastError::InvalidServerAddress(value)),
+    };
+    if address.ip().is_loopback() {
+        return Err(CastError::LoopbackServer(value, device.name.clone()));
+    }
+
+    if address.ip().is_unspecified() {
+        // The address of the interface the device is reached through
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).and_then(|socket| {
+            socket.connect(device.address)?;
+            socket.local_addr()
+        });
+        match socket {
+            Ok(socket) => address.set_ip(socket.ip()),
+            Err(err) => {
+                return Err(CastError::Receiver(
+                    device.name.clone(),
+                    ReceiverError::IO(err),
+                ))
+            }
+        }
+    }
+
+    if TcpStream::connect_timeout(&address, Duration::from_secs(2)).is_err() {
+        return Err(CastError::ServerNotRunning(value));
+    }
+
+    Ok(address)
+}
+
+/// Returns the query string authenticating the device with the server.
+fn credentials(
+    db: &rusqlite::Connection,
+    user: Option<&str>,
+    device: &Device,
+) -> Result<Vec<String>, CastError> {
+    if !user::has_users(db)? {
+        return Ok(Vec::new());
+    }
+
+    let name = match user {
+        Some(name) => name,
+        None => return Err(CastError::UserRequired(device.name.clone())),
+    };
+    match user::token_params(db, name)? {
+        Some(params) => Ok(params),
+        None => Err(CastError::UserNotFound(name.to_owned())),
+    }
+}
+
+/// Plays `tracks` on the device named `name`, as `user` of the server.
+pub fn play(
+    db: &rusqlite::Connection,
+    name: &str,
+    user: Option<&str>,
+    tracks: &[&Track],
+) -> Result<(), CastError> {
+    println!("{}", tr!("cast-searching"));
+    let devices = discover().map_err(CastError::Discovery)?;
+    if devices.is_empty() {
+        return Err(CastError::NoDevices);
+    }
+    let device = match devices
+        .iter()
+        .find(|device| device.name.eq_ignore_ascii_case(name))
+    {
+        Some(device) => device,
+        None => {
+            let found = devices.iter().map(|device| device.name.clone()).collect();
+            return Err(CastError::DeviceNotFound(name.to_owned(), found));
+        }
+    };
+
+    let server = server_address(db, device)?;
+    let credentials = credentials(db, user, device)?;
+
+    let receiver_error = |err| CastError::Receiver(device.name.clone(), err);
+    let mut receiver: Box<dyn Receiver> = match device.kind {
+        Kind::Chromecast => Box::new(Chromecast::connect(device.address).map_err(receiver_error)?),
+        Kind::AirPlay => Box::new(AirPlay::connect(device.address).map_err(receiver_error)?),
+    };
+    println!("{}", tr!("cast-connected", device = device.name.as_str()));
+    println!("{}", tr!("cast-controls"));
+
+    let commands = read_commands();
+    let mut index = 0;
+    let mut paused = false;
+    let mut load = true;
+    while index < tracks.len() {
+        let track = tracks[index];
+        if load {
+            println!(
+                "{}",
+                tr!(
+                    "play-playing",
+                    artist = track.artist.as_deref().unwrap_or_default(),
+                    name = track.name.as_deref().unwrap_or_default()
+                )
+            );
+
+            let mut params = credentials.clone();
+            let path = Path::new(&track.path);
+            let content_type = if device.kind.plays(path) {
+                subsonic::content_type_for(path)
+            } else {
+                let (format, bitrate) = device.kind.transcode();
+                params.push(format!("format={}", format));
+                params.push(format!("bitrate={}", bitrate));
+                ffmpeg::stream_content_type(format).unwrap_or("audio/mpeg")
+            };
+            let mut url = format!("http://{}/stream/{}", server, track.id);
+            if !params.is_empty() {
+                url.push('?');
+                url.push_str(&params.join("&"));
+            }
+
+            receiver
+                .load(&Media {
+                    url,
+                    content_type,
+                    title: track.name.as_deref().unwrap_or_default(),
+                    artist: track.artist.as_deref().unwrap_or_default(),
+                })
+                .map_err(receiver_error)?;
+            paused = false;
+            load = false;
+        }
+
+        match commands.recv_timeout(POLL_INTERVAL) {
+            Ok(Command::TogglePause) if paused => {
+                receiver.resume().map_err(receiver_error)?;
+                paused = false;
+            }
+            Ok(Command::TogglePause) => {
+                receiver.pause().map_err(receiver_error)?;
+                paused = true;
+            }
+            Ok(Command::Next) => {
+                index += 1;
+                load = true;
+                continue;
+            }
+            Ok(Command::Previous) => {
+                index = index.saturating_sub(1);
+                load = true;
+                continue;
+            }
+            Ok(Command::Volume(delta)) => match receiver.change_volume(delta) {
+                Ok(Some(volume)) => {
+                    let percent = (volume * 100.0).round() as i64;
+                    println!("{}", tr!("cast-volume", percent = percent));
+                }
+                Ok(None) => println!("{}", tr!("cast-no-volume", device = device.name.as_str())),
+                Err(err) => return Err(receiver_error(err)),
+            },
+            Ok(Command::Quit) => break,
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // Without a standard input the tracks play to the end
+            Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(POLL_INTERVAL),
+        }
+
+        match receiver.poll().map_err(receiver_error)? {
+            Status::Finished => {
+                index += 1;
+                load = true;
+            }
+            Status::Failed(reason) => {
+                println!(
+                    "{}",
+                    tr!(
+                        "cast-track-failed",
+                        name = track.name.as_deref().unwrap_or_default(),
+                        reason = reason
+                    )
+                );
+                index += 1;
+                load = true;
+            }
+            Status::Closed => {
+                println!("{}", tr!("cast-closed", device = device.name.as_str()));
+                return Ok(());
+            }
+            Status::Loading | Status::Playing | Status::Paused => {}
+        }
+    }
+
+    receiver.stop().map_err(receiver_error)?;
+
+    Ok(())
+}
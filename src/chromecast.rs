@@ -0,0 +1,490 @@
+//! Casting to a Chromecast with its CASTV2 protocol.
+//!
+//! Messages are protobuf `CastMessage`s prefixed with their length, over TLS on the port the
+//! device announces. Their payloads are JSON, each in a namespace: the connection, the
+//! heartbeat the device pings and expects answered, the receiver which launches apps, and the
+//! media one of the Default Media Receiver app, which plays a URL.
+//!
+//! Chromecasts have certificates of Google's device CA, which no root store has, and senders
+//! don't check them: neither does this.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cast::{Media, Receiver, ReceiverError, Status};
+use crate::json::{self, Value};
+
+const SENDER: &str = "sender-0";
+const RECEIVER: &str = "receiver-0";
+
+const NS_CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+const NS_HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+const NS_RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+const NS_MEDIA: &str = "urn:x-cast:com.google.cast.media";
+
+/// The Default Media Receiver.
+const APP_ID: &str = "CC1AD845";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(20);
+/// How long a poll waits for messages.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A message of a namespace, between a sender and a receiver.
+#[derive(Debug, PartialEq)]
+struct Message {
+    source: String,
+    destination: String,
+    namespace: String,
+    payload: String,
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_string(buf: &mut Vec<u8>, field: u64, value: &str) {
+    write_varint(buf, field << 3 | 2);
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+impl Message {
+    /// Encodes the message, with its length first.
+    fn encode(&self) -> Vec<u8> {
+        let mut message = Vec::new();
+        // protocol_version CASTV2_1_0
+        write_varint(&mut message, 1 << 3);
+        write_varint(&mut message, 0);
+        write_string(&mut message, 2, &self.source);
+        write_string(&mut message, 3, &self.destination);
+        write_string(&mut message, 4, &self.namespace);
+        // payload_type STRING
+        write_varint(&mut message, 5 << 3);
+        write_varint(&mut message, 0);
+        write_string(&mut message, 6, &self.payload);
+
+        let mut buf = (message.len() as u32).to_be_bytes().to_vec();
+        buf.extend(message);
+        buf
+    }
+
+    /// Decodes a message without its length, none if it's not valid.
+    fn decode(mut data: &[u8]) -> Option<Message> {
+        let mut message = Message {
+            source: String::new(),
+            destination: String::new(),
+            namespace: String::new(),
+            payload: String::new(),
+        };
+
+        while !data.is_empty() {
+            let key = read_varint(&mut data)?;
+            let len = match key & 7 {
+                0 => {
+                    read_varint(&mut data)?;
+                    continue;
+                }
+                1 => 8,
+                2 => read_varint(&mut data)? as usize,
+                5 => 4,
+                _ => return None,
+            };
+            if data.len() < len {
+                return None;
+            }
+            let (value, rest) = data.split_at(len);
+            data = rest;
+
+            let value = String::from_utf8_lossy(value).into_owned();
+            match key >> 3 {
+                2 => message.source = value,
+                3 => message.destination = value,
+                4 => message.namespace = value,
+                6 => message.payload = value,
+                _ => {}
+            }
+        }
+
+        Some(message)
+    }
+}
+
+/// Accepts the certificate of any device, see the module documentation.
+struct AnyCertificate;
+
+impl rustls::client::ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+pub struct Chromecast {
+    stream: rustls::StreamOwned<rustls::ClientConnection, TcpStream>,
+    /// What was read of messages not read whole yet.
+    inbox: Vec<u8>,
+    request_id: i64,
+    /// The receiver of the app, and its session.
+    transport: String,
+    session: String,
+    media_session: Option<i64>,
+    volume: f64,
+    status: Status,
+}
+
+impl Chromecast {
+    /// Connects to the Chromecast at `address` and launches the Default Media Receiver.
+    pub fn connect(address: SocketAddr) -> Result<Chromecast, ReceiverError> {
+        let socket = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        socket.set_read_timeout(Some(READ_TIMEOUT))?;
+        socket.set_nodelay(true)?;
+
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(AnyCertificate))
+            .with_no_client_auth();
+        let connection = rustls::ClientConnection::new(
+            Arc::new(config),
+            rustls::ServerName::IpAddress(address.ip()),
+        )
+        .map_err(io::Error::other)?;
+
+        let mut chromecast = Chromecast {
+            stream: rustls::StreamOwned::new(connection, socket),
+            inbox: Vec::new(),
+            request_id: 0,
+            transport: String::new(),
+            session: String::new(),
+            media_session: None,
+            volume: 1.0,
+            status: Status::Loading,
+        };
+
+        chromecast.send(
+            RECEIVER,
+            NS_CONNECTION,
+            &[("type", json::string("CONNECT"))],
+        )?;
+        chromecast.request(
+            RECEIVER,
+            NS_RECEIVER,
+            &[
+                ("type", json::string("LAUNCH")),
+                ("appId", json::string(APP_ID)),
+            ],
+        )?;
+
+        let deadline = Instant::now() + LAUNCH_TIMEOUT;
+        while chromecast.transport.is_empty() {
+            if Instant::now() >= deadline {
+                return Err(ReceiverError::NotLaunched);
+            }
+            chromecast.receive()?;
+        }
+
+        let transport = chromecast.transport.clone();
+        chromecast.send(
+            &transport,
+            NS_CONNECTION,
+            &[("type", json::string("CONNECT"))],
+        )?;
+
+        Ok(chromecast)
+    }
+
+    fn send(
+        &mut self,
+        destination: &str,
+        namespace: &str,
+        payload: &[(&str, String)],
+    ) -> io::Result<()> {
+        let message = Message {
+            source: SENDER.to_owned(),
+            destination: destination.to_owned(),
+            namespace: namespace.to_owned(),
+            payload: json::object(payload),
+        };
+        self.stream.write_all(&message.encode())?;
+        self.stream.flush()
+    }
+
+    /// Sends a message expecting an answer, which all carry a request ID.
+    fn request(
+        &mut self,
+        destination: &str,
+        namespace: &str,
+        payload: &[(&str, String)],
+    ) -> io::Result<()> {
+        self.request_id += 1;
+        let mut payload = payload.to_vec();
+        payload.push(("requestId", self.request_id.to_string()));
+        self.send(destination, namespace, &payload)
+    }
+
+    /// Sends a media message about the track playing, if one is.
+    fn media_request(&mut self, kind: &str) -> io::Result<()> {
+        let media_session = match self.media_session {
+            Some(media_session) => media_session,
+            None => return Ok(()),
+        };
+        let transport = self.transport.clone();
+        self.request(
+            &transport,
+            NS_MEDIA,
+            &[
+                ("type", json::string(kind)),
+                ("mediaSessionId", media_session.to_string()),
+            ],
+        )
+    }
+
+    /// Reads the messages the device sent, waiting for them a little, and handles them.
+    fn receive(&mut self) -> io::Result<()> {
+        let mut buf = [0; 16 * 1024];
+        match self.stream.read(&mut buf) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(len) => self.inbox.extend_from_slice(&buf[..len]),
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+
+        while self.inbox.len() >= 4 {
+            let len = u32::from_be_bytes(self.inbox[..4].try_into().unwrap()) as usize;
+            if self.inbox.len() < 4 + len {
+                break;
+            }
+            let frame: Vec<u8> = self.inbox.drain(..4 + len).skip(4).collect();
+            if let Some(message) = Message::decode(&frame) {
+                self.handle(message)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, message: Message) -> io::Result<()> {
+        let payload = match json::parse(&message.payload) {
+            Ok(payload) => payload,
+            Err(_) => return Ok(()),
+        };
+        let kind = payload
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        match (message.namespace.as_str(), kind) {
+            (NS_HEARTBEAT, "PING") => {
+                self.send(
+                    &message.source,
+                    NS_HEARTBEAT,
+                    &[("type", json::string("PONG"))],
+                )?;
+            }
+            (NS_CONNECTION, "CLOSE") if message.source == self.transport => {
+                self.status = Status::Closed;
+            }
+            (NS_RECEIVER, "RECEIVER_STATUS") => {
+                let status = payload.get("status");
+                if let Some(Value::Number(level)) = status
+                    .and_then(|status| status.get("volume"))
+                    .and_then(|volume| volume.get("level"))
+                {
+                    self.volume = *level;
+                }
+
+                let app = status
+                    .and_then(|status| status.get("applications"))
+                    .map(Value::as_array)
+                    .unwrap_or_default()
+                    .iter()
+                    .find(|app| app.get("appId").and_then(Value::as_str) == Some(APP_ID));
+                match app {
+                    Some(app) if self.transport.is_empty() => {
+                        let field = |name| app.get(name).and_then(Value::as_str);
+                        self.transport = field("transportId").unwrap_or_default().to_owned();
+                        self.session = field("sessionId").unwrap_or_default().to_owned();
+                    }
+                    Some(_) => {}
+                    // Another app was launched
+                    None if !self.transport.is_empty() => self.status = Status::Closed,
+                    None => {}
+                }
+            }
+            (NS_MEDIA, "MEDIA_STATUS") => {
+                let status = match payload.get("status").map(Value::as_array) {
+                    Some([status, ..]) => status,
+                    _ => return Ok(()),
+                };
+                if let Some(media_session) = status.get("mediaSessionId").and_then(Value::as_i64) {
+                    self.media_session = Some(media_session);
+                }
+
+                let field = |name| status.get(name).and_then(Value::as_str);
+                match (field("playerState"), field("idleReason")) {
+                    (Some("PLAYING"), _) => self.status = Status::Playing,
+                    (Some("PAUSED"), _) => self.status = Status::Paused,
+                    (Some("BUFFERING"), _) => self.status = Status::Loading,
+                    (Some("IDLE"), Some("FINISHED")) => self.status = Status::Finished,
+                    (Some("IDLE"), Some("ERROR")) => {
+                        self.status = Status::Failed("ERROR".to_owned())
+                    }
+                    // Interrupted by the next track, or idle before the first one
+                    _ => {}
+                }
+            }
+            (NS_MEDIA, "LOAD_FAILED" | "LOAD_CANCELLED" | "INVALID_REQUEST") => {
+                let reason = payload.get("reason").and_then(Value::as_str);
+                self.status = Status::Failed(reason.unwrap_or(kind).to_owned());
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+impl Receiver for Chromecast {
+    fn load(&mut self, media: &Media) -> Result<(), ReceiverError> {
+        let metadata = json::object(&[
+            // A music track
+            ("metadataType", "3".to_owned()),
+            ("title", json::string(media.title)),
+            ("artist", json::string(media.artist)),
+        ]);
+        let content = json::object(&[
+            ("contentId", json::string(&media.url)),
+            ("contentType", json::string(media.content_type)),
+            ("streamType", json::string("BUFFERED")),
+            ("metadata", metadata),
+        ]);
+
+        let transport = self.transport.clone();
+        let session = json::string(&self.session);
+        self.request(
+            &transport,
+            NS_MEDIA,
+            &[
+                ("type", json::string("LOAD")),
+                ("sessionId", session),
+                ("media", content),
+                ("autoplay", "true".to_owned()),
+                ("currentTime", "0".to_owned()),
+            ],
+        )?;
+        self.media_session = None;
+        self.status = Status::Loading;
+
+        Ok(())
+    }
+
+    fn pause(&mut self) -> Result<(), ReceiverError> {
+        Ok(self.media_request("PAUSE")?)
+    }
+
+    fn resume(&mut self) -> Result<(), ReceiverError> {
+        Ok(self.media_request("PLAY")?)
+    }
+
+    fn stop(&mut self) -> Result<(), ReceiverError> {
+        self.media_request("STOP")?;
+        // Closes the app, the device goes back to its backdrop
+        let session = json::string(&self.session);
+        self.request(
+            RECEIVER,
+            NS_RECEIVER,
+            &[("type", json::string("STOP")), ("sessionId", session)],
+        )?;
+
+        Ok(())
+    }
+
+    fn change_volume(&mut self, delta: f64) -> Result<Option<f64>, ReceiverError> {
+        let level = (self.volume + delta).clamp(0.0, 1.0);
+        self.request(
+            RECEIVER,
+            NS_RECEIVER,
+            &[
+                ("type", json::string("SET_VOLUME")),
+                ("volume", json::object(&[("level", level.to_string())])),
+            ],
+        )?;
+        self.volume = level;
+
+        Ok(Some(level))
+    }
+
+    fn poll(&mut self) -> Result<Status, ReceiverError> {
+        self.receive()?;
+
+        // The end of a track is told once
+        Ok(match &self.status {
+            Status::Finished | Status::Failed(_) => {
+                std::mem::replace(&mut self.status, Status::Loading)
+            }
+            status => status.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_message() {
+        let message = Message {
+            source: SENDER.to_owned(),
+            destination: RECEIVER.to_owned(),
+            namespace: NS_HEARTBEAT.to_owned(),
+            payload: r#"{"type":"PING"}"#.to_owned(),
+        };
+        let data = message.encode();
+
+        let len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        assert_eq!(len, data.len() - 4);
+        // protocol_version, then source_id
+        assert_eq!(&data[4..8], &[0x08, 0x00, 0x12, 0x08]);
+        assert_eq!(&data[8..16], b"sender-0");
+
+        assert_eq!(Message::decode(&data[4..]), Some(message));
+        assert_eq!(Message::decode(&data[4..20]), None);
+    }
+
+    #[test]
+    fn varints() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, [0xac, 0x02]);
+        assert_eq!(read_varint(&mut &buf[..]), Some(300));
+        assert_eq!(read_varint(&mut &[0x80][..]), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! The `natural_sort` collation, to sort listings the way people expect.
+//!
+//! Numbers sort by value, so "Track 2" comes before "Track 10", and letters sort by their
+//! base letter whatever their case or accents, so "Émilie" is among the other E's. The
+//! `sort_locale` configuration key adds the rules of a language, like Swedish sorting "Ö"
+//! after "Z".
+//!
+//! It's only known to zik's own connections, never use it in the schema.
+//!
+//! Artists and albums are sorted on their `sort_name`, their name without a leading article
+//! from the `ignored_articles` configuration key, so "The National" is listed under N.
+
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Clone, Copy)]
+pub enum Locale {
+    Root,
+    /// Danish and Norwegian: Æ, Ø and Å after Z.
+    Danish,
+    /// Swedish and Finnish: Å, Ä and Ö after Z.
+    Swedish,
+    /// Spanish: Ñ after N.
+    Spanish,
+}
+
+impl Locale {
+    /// Parses a locale like "sv" or "en_US".
+    ///
+    /// Languages without rules of their own sort like the root locale.
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let (language, region) = match tag.split_once(['_', '-']) {
+            Some((language, region)) => (language, Some(region)),
+            None => (tag, None),
+        };
+
+        let valid_language =
+            (2..=3).contains(&language.len()) && language.bytes().all(|b| b.is_ascii_alphabetic());
+        let valid_region = match region {
+            Some(region) => region.len() == 2 && region.bytes().all(|b| b.is_ascii_alphabetic()),
+            None => true,
+        };
+        if !valid_language || !valid_region {
+            return None;
+        }
+
+        Some(match language.to_ascii_lowercase().as_str() {
+            "da" | "nb" | "nn" | "no" => Locale::Danish,
+            "sv" | "fi" => Locale::Swedish,
+            "es" => Locale::Spanish,
+            _ => Locale::Root,
+        })
+    }
+
+    /// Returns the locale of the `sort_locale` configuration key.
+    pub fn load(db: &rusqlite::Connection) -> Locale {
+        // A new database has no config table yet
+        crate::get_config_value(db, "sort_locale")
+            .ok()
+            .flatten()
+            .and_then(|tag| Locale::from_tag(&tag))
+            .unwrap_or(Locale::Root)
+    }
+}
+
+/// Returns the letter `c` is an accented or special form of.
+fn base_letter(c: char) -> char {
+    match c {
+        'À'..='Æ' | 'à'..='æ' | '\u{100}'..='\u{105}' => 'a',
+        'Ç' | 'ç' | '\u{106}'..='\u{10d}' => 'c',
+        'Ð' | 'ð' | '\u{10e}'..='\u{111}' => 'd',
+        'È'..='Ë' | 'è'..='ë' | '\u{112}'..='\u{11b}' => 'e',
+        '\u{11c}'..='\u{123}' => 'g',
+        '\u{124}'..='\u{127}' => 'h',
+        'Ì'..='Ï' | 'ì'..='ï' | '\u{128}'..='\u{133}' => 'i',
+        '\u{134}'..='\u{135}' => 'j',
+        '\u{136}'..='\u{138}' => 'k',
+        '\u{139}'..='\u{142}' => 'l',
+        'Ñ' | 'ñ' | '\u{143}'..='\u{14b}' => 'n',
+        'Ò'..='Ö' | 'Ø' | 'ò'..='ö' | 'ø' | '\u{14c}'..='\u{153}' => 'o',
+        '\u{154}'..='\u{159}' => 'r',
+        'ß' | '\u{15a}'..='\u{161}' | '\u{17f}' => 's',
+        'Þ' | 'þ' | '\u{162}'..='\u{167}' => 't',
+        'Ù'..='Ü' | 'ù'..='ü' | '\u{168}'..='\u{173}' => 'u',
+        '\u{174}'..='\u{175}' => 'w',
+        'Ý' | 'ý' | 'ÿ' | '\u{176}'..='\u{178}' => 'y',
+        '\u{179}'..='\u{17e}' => 'z',
+        c => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+/// Returns the primary weight of `c`: letters a language sorts on their own come right
+/// after the letter they follow in its alphabet.
+fn weight(locale: Locale, c: char) -> u32 {
+    let after = |letter: char, rank: u32| ((letter as u32) << 8) + rank;
+
+    let lower = c.to_lowercase().next().unwrap_or(c);
+    match (locale, lower) {
+        (Locale::Danish, 'æ' | 'ä') => after('z', 1),
+        (Locale::Danish, 'ø' | 'ö') => after('z', 2),
+        (Locale::Danish, 'å') => after('z', 3),
+        (Locale::Swedish, 'å') => after('z', 1),
+        (Locale::Swedish, 'ä' | 'æ') => after('z', 2),
+        (Locale::Swedish, 'ö' | 'ø') => after('z', 3),
+        (Locale::Spanish, 'ñ') => after('n', 1),
+        _ => after(base_letter(c), 0),
+    }
+}
+
+/// Consumes a run of digits.
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+    number
+}
+
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+/// Compares ignoring case and accents.
+fn compare_primary(locale: Locale, a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        let (ca, cb) = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) => (*ca, *cb),
+        };
+
+        let ordering = if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            compare_numbers(&take_number(&mut a), &take_number(&mut b))
+        } else {
+            a.next();
+            b.next();
+            weight(locale, ca).cmp(&weight(locale, cb))
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Returns the letter `c` is listed under in an index, like "E" for "É".
+pub fn index_letter(locale: Locale, c: char) -> String {
+    if weight(locale, c) & 0xff != 0 {
+        c.to_uppercase().collect()
+    } else {
+        base_letter(c).to_uppercase().collect()
+    }
+}
+
+fn compare(locale: Locale, a: &str, b: &str) -> Ordering {
+    compare_primary(locale, a, b)
+        .then_with(|| a.to_lowercase().cmp(&b.to_lowercase()))
+        .then_with(|| a.cmp(b))
+}
+
+/// The articles ignored when sorting, unless `ignored_articles` is set.
+pub const DEFAULT_IGNORED_ARTICLES: &str = "The A An Le La Les L' Die Der Das El Los Las";
+
+pub struct IgnoredArticles {
+    articles: Vec<String>,
+}
+
+impl IgnoredArticles {
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<IgnoredArticles> {
+        let value = crate::get_config_value(db, "ignored_articles")?;
+        let value = value.as_deref().unwrap_or(DEFAULT_IGNORED_ARTICLES);
+
+        Ok(IgnoredArticles {
+            articles: value.split_whitespace().map(str::to_owned).collect(),
+        })
+    }
+
+    /// Returns the list as Subsonic's `ignoredArticles`.
+    pub fn to_subsonic(&self) -> String {
+        self.articles.join(" ")
+    }
+
+    /// Returns `name` without its leading article.
+    pub fn sort_name<'a>(&self, name: &'a str) -> &'a str {
+        for article in &self.articles {
+            let prefix = match name.get(..article.len()) {
+                Some(prefix) if prefix.to_lowercase() == article.to_lowercase() => prefix,
+                _ => continue,
+            };
+            let rest = &name[prefix.len()..];
+
+            // "L'" is glued to the next word, the other articles aren't
+            let rest = if article.ends_with('\'') {
+                rest
+            } else if rest.starts_with(' ') {
+                rest.trim_start()
+            } else {
+                continue;
+            };
+            if !rest.is_empty() {
+                return rest;
+            }
+        }
+
+        name
+    }
+}
+
+/// Computes the sort name of every artist and album.
+pub fn update_sort_names(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let articles = IgnoredArticles::load(db)?;
+
+    for table in ["artist", "album"] {
+        let names: Vec<(i64, Option<String>)> = {
+            let mut stmt = db.prepare(&format!("SELECT id, name FROM {}", table))?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let mut stmt = db.prepare(&format!(
+            "UPDATE {} SET sort_name = $sort_name WHERE id = $id",
+            table
+        ))?;
+        for (id, name) in names {
+            let sort_name = name.as_deref().map(|name| articles.sort_name(name));
+            stmt.execute(rusqlite::params![sort_name, id])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Registers the `natural_sort` collation on `db`, with the rules of `sort_locale`.
+pub fn register(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let locale = Locale::load(db);
+    db.create_collation("natural_sort", move |a, b| compare(locale, a, b))
+}
@@ -0,0 +1,280 @@
+//! Collections, named subsets of the library like "vinyl" or "kids" grouping any albums and
+//! tracks.
+//!
+//! A collection holds albums, all their tracks including those added later, and single
+//! tracks; `v_collection_tracks` lists the tracks of each. Listings, `zik play`, M3U exports
+//! and saved filters take a collection to only use its tracks, and the Subsonic server shows
+//! every collection as a music folder so that clients can browse it as a library of its own.
+//! Users of the server can be restricted to some collections, see `user`.
+
+use std::fmt;
+
+use crate::i18n::tr;
+use crate::TrackID;
+
+/// The condition on `$collection` of the queries filtering tracks by collection, none if it's
+/// NULL.
+pub const TRACK_FILTER: &str = "($collection IS NULL OR track.id IN (
+    SELECT track_id FROM v_collection_tracks WHERE collection = $collection
+))";
+
+/// The condition on `$collection` of the queries filtering albums by collection, which has
+/// the albums it holds and those of the tracks it holds.
+pub const ALBUM_FILTER: &str = "($collection IS NULL OR album.id IN (
+    SELECT t.album_id FROM v_collection_tracks
+    JOIN track t ON t.id = v_collection_tracks.track_id
+    WHERE collection = $collection
+))";
+
+pub enum CommandCollectionError {
+    SQLite(rusqlite::Error),
+    EmptyName,
+    AlbumNotFound(String),
+    TrackNotFound(String),
+    CollectionNotFound(String),
+}
+impl From<rusqlite::Error> for CommandCollectionError {
+    fn from(err: rusqlite::Error) -> CommandCollectionError {
+        CommandCollectionError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandCollectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandCollectionError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandCollectionError::EmptyName => f.write_str(&tr!("collection-empty-name")),
+            CommandCollectionError::AlbumNotFound(value) => {
+                f.write_str(&tr!("album-id-not-found", value = value))
+            }
+            CommandCollectionError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+            CommandCollectionError::CollectionNotFound(name) => {
+                f.write_str(&tr!("collection-not-found", name = name))
+            }
+        }
+    }
+}
+
+/// Returns the id of the collection `name`.
+pub fn find_id(db: &rusqlite::Connection, name: &str) -> rusqlite::Result<Option<i64>> {
+    match db.query_row(
+        "SELECT id FROM collection WHERE name = $name",
+        [name],
+        |row| row.get(0),
+    ) {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the IDs of the tracks of the collection `id`, in library order.
+pub fn track_ids(db: &rusqlite::Connection, id: i64) -> rusqlite::Result<Vec<TrackID>> {
+    let mut stmt = db.prepare(
+        "SELECT track.id
+         FROM v_collection_tracks
+         JOIN track ON track.id = v_collection_tracks.track_id
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         WHERE v_collection_tracks.collection_id = $id
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                  track.disc_number, track.number",
+    )?;
+    let ids = stmt.query_map([id], |row| row.get(0))?;
+
+    ids.collect()
+}
+
+/// Returns the albums or tracks `args` names, as the table holding them in collections, its
+/// column of their ids and their ids.
+fn find_items(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(&'static str, &'static str, Vec<i64>), CommandCollectionError> {
+    let mut ids = Vec::new();
+    for value in args.values_of("item").unwrap() {
+        let id = if args.is_present("track") {
+            crate::find_track_id(db, value)?
+                .ok_or_else(|| CommandCollectionError::TrackNotFound(value.to_owned()))?
+        } else {
+            crate::find_album_id(db, value)?
+                .ok_or_else(|| CommandCollectionError::AlbumNotFound(value.to_owned()))?
+        };
+        ids.push(id as i64);
+    }
+
+    if args.is_present("track") {
+        Ok(("collection_track", "track_id", ids))
+    } else {
+        Ok(("collection_album", "album_id", ids))
+    }
+}
+
+//
+// "collection" command
+//
+
+fn cmd_collection_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCollectionError> {
+    let name = args.value_of("name").unwrap().trim();
+    if name.is_empty() {
+        return Err(CommandCollectionError::EmptyName);
+    }
+    let (table, column, ids) = find_items(db, args)?;
+
+    let savepoint = db.savepoint()?;
+
+    savepoint.execute(
+        "INSERT OR IGNORE INTO collection(name) VALUES($name)",
+        [name],
+    )?;
+    let query = format!(
+        "INSERT OR IGNORE INTO {}(collection_id, {})
+         SELECT id, $id FROM collection WHERE name = $name",
+        table, column
+    );
+    for id in ids {
+        savepoint.execute(&query, rusqlite::params![id, name])?;
+    }
+
+    savepoint.commit()?;
+
+    Ok(())
+}
+
+fn cmd_collection_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCollectionError> {
+    let name = args.value_of("name").unwrap().trim();
+    let collection_id = find_id(db, name)?
+        .ok_or_else(|| CommandCollectionError::CollectionNotFound(name.to_owned()))?;
+    let (table, column, ids) = find_items(db, args)?;
+
+    // Emptied collections stay, they're only gone with `delete`
+    let query = format!(
+        "DELETE FROM {} WHERE collection_id = $collection_id AND {} = $id",
+        table, column
+    );
+    for id in ids {
+        db.execute(&query, [collection_id, id])?;
+    }
+
+    Ok(())
+}
+
+fn cmd_collection_delete(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCollectionError> {
+    let name = args.value_of("name").unwrap().trim();
+
+    let n = db.execute("DELETE FROM collection WHERE name = $name", [name])?;
+    if n == 0 {
+        return Err(CommandCollectionError::CollectionNotFound(name.to_owned()));
+    }
+
+    Ok(())
+}
+
+fn cmd_collection_list(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCollectionError> {
+    let name = match args.value_of("name") {
+        Some(name) => name.trim(),
+        None => {
+            let mut stmt = db.prepare(
+                "SELECT collection.name,
+                        (SELECT COUNT(*) FROM collection_album
+                         WHERE collection_album.collection_id = collection.id),
+                        (SELECT COUNT(*) FROM v_collection_tracks
+                         WHERE v_collection_tracks.collection_id = collection.id)
+                 FROM collection
+                 ORDER BY collection.name COLLATE natural_sort",
+            )?;
+            let mut rows = stmt.query([])?;
+
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                let albums: i64 = row.get(1)?;
+                let tracks: i64 = row.get(2)?;
+
+                println!(
+                    "{} ({})",
+                    name,
+                    tr!("collection-counts", albums = albums, tracks = tracks)
+                );
+            }
+
+            return Ok(());
+        }
+    };
+    let collection_id = find_id(db, name)?
+        .ok_or_else(|| CommandCollectionError::CollectionNotFound(name.to_owned()))?;
+
+    // Its albums, then the tracks it holds on their own
+    let mut stmt = db.prepare(
+        "SELECT album.id, artist.name, album.name
+         FROM collection_album
+         JOIN album ON album.id = collection_album.album_id
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE collection_album.collection_id = $id
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort",
+    )?;
+    let mut rows = stmt.query([collection_id])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let artist: Option<String> = row.get(1)?;
+        let album: Option<String> = row.get(2)?;
+
+        println!(
+            "album {}\t{} - {}",
+            id,
+            artist.unwrap_or_default(),
+            album.unwrap_or_default()
+        );
+    }
+
+    let mut stmt = db.prepare(
+        "SELECT track.id, artist.name, track.name
+         FROM collection_track
+         JOIN track ON track.id = collection_track.track_id
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         WHERE collection_track.collection_id = $id
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                  track.disc_number, track.number",
+    )?;
+    let mut rows = stmt.query([collection_id])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let artist: Option<String> = row.get(1)?;
+        let track: Option<String> = row.get(2)?;
+
+        println!(
+            "track {}\t{} - {}",
+            id,
+            artist.unwrap_or_default(),
+            track.unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+pub fn cmd_collection(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandCollectionError> {
+    match args.subcommand() {
+        Some(("add", sub_args)) => cmd_collection_add(db, sub_args),
+        Some(("remove", sub_args)) => cmd_collection_remove(db, sub_args),
+        Some(("delete", sub_args)) => cmd_collection_delete(db, sub_args),
+        Some(("list", sub_args)) => cmd_collection_list(db, sub_args),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,582 @@
+//! `zik daemon`: the library watcher, the job workers and the server in one long-lived process.
+//!
+//! A running daemon is controlled through a Unix socket in the data folder; `zik ctl` sends
+//! one command per connection and prints the reply. It also serves the RPC interface.
+//!
+//! Without Unix sockets, on Windows, the daemon has no control socket and no RPC socket: it's
+//! stopped with Ctrl-C, and neither `zik ctl` nor `zik rpc` exist.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+#[cfg(unix)]
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(unix)]
+use std::net::Shutdown;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::i18n::tr;
+use crate::ignore::IgnoreRules;
+use crate::{inbox, jobs, library, metrics, netfs, notify, server, storage, throttle};
+#[cfg(unix)]
+use crate::{rpc, systemd};
+
+pub const DEFAULT_SERVER_ADDRESS: &str = "127.0.0.1:4533";
+const DEFAULT_WATCH_INTERVAL: usize = 60;
+#[cfg(unix)]
+const SOCKET_NAME: &str = "daemon.sock";
+
+/// How long the main loop sleeps when there's nothing to do.
+const IDLE_SLEEP: Duration = Duration::from_secs(1);
+
+pub enum DaemonError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    Listen(String, io::Error),
+    Jobs(jobs::CommandJobsError),
+    #[cfg(unix)]
+    DataFolderNotFound,
+    #[cfg(unix)]
+    AlreadyRunning(PathBuf),
+    #[cfg(unix)]
+    NotRunning(PathBuf),
+}
+impl From<rusqlite::Error> for DaemonError {
+    fn from(err: rusqlite::Error) -> DaemonError {
+        DaemonError::SQLite(err)
+    }
+}
+impl From<io::Error> for DaemonError {
+    fn from(err: io::Error) -> DaemonError {
+        DaemonError::IO(err)
+    }
+}
+impl From<jobs::CommandJobsError> for DaemonError {
+    fn from(err: jobs::CommandJobsError) -> DaemonError {
+        DaemonError::Jobs(err)
+    }
+}
+impl fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DaemonError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            DaemonError::IO(err) => f.write_str(&tr!("io-error", err = err)),
+            DaemonError::Listen(address, err) => {
+                f.write_str(&tr!("daemon-listen", address = address, err = err))
+            }
+            DaemonError::Jobs(err) => write!(f, "{}", err),
+            #[cfg(unix)]
+            DaemonError::DataFolderNotFound => f.write_str(&tr!("data-folder-not-found")),
+            #[cfg(unix)]
+            DaemonError::AlreadyRunning(path) => {
+                f.write_str(&tr!("daemon-already-running", path = path.display()))
+            }
+            #[cfg(unix)]
+            DaemonError::NotRunning(path) => {
+                f.write_str(&tr!("daemon-not-running", path = path.display()))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn socket_path() -> Result<PathBuf, DaemonError> {
+    match crate::get_data_dir() {
+        Some(data_dir) => Ok(data_dir.join(SOCKET_NAME)),
+        None => Err(DaemonError::DataFolderNotFound),
+    }
+}
+
+/// What the daemon is doing, shared with the control socket.
+struct State {
+    stop: Arc<AtomicBool>,
+    rescan: AtomicBool,
+    activity: Mutex<&'static str>,
+    #[cfg(unix)]
+    started_at: Instant,
+    server_address: String,
+    scans: AtomicUsize,
+    jobs_run: AtomicUsize,
+}
+
+impl State {
+    fn set_activity(&self, activity: &'static str) {
+        let mut current = self.activity.lock().unwrap();
+        if *current != activity {
+            *current = activity;
+            notify(&format!("STATUS={}", activity));
+        }
+    }
+
+    #[cfg(unix)]
+    fn describe(&self) -> String {
+        format!(
+            "activity: {}\nuptime: {}s\nserver: {}\nscans: {}\njobs run: {}\n",
+            self.activity.lock().unwrap(),
+            self.started_at.elapsed().as_secs(),
+            self.server_address,
+            self.scans.load(Ordering::Relaxed),
+            self.jobs_run.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Summarizes the files of the library so that any added, removed or modified file changes
+/// the result.
+///
+/// Only the directory entries are looked at, this is much cheaper than a scan. With
+/// `size_only`, modification times are ignored since some network filesystems change them.
+fn library_signature(library: &Path, size_only: bool, ignore_rules: &IgnoreRules) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    let walker = walkdir::WalkDir::new(library)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !ignore_rules.is_ignored(entry));
+    for entry in walker.filter_map(|result| result.ok()) {
+        entry.path().hash(&mut hasher);
+
+        if let Ok(metadata) = entry.metadata() {
+            metadata.len().hash(&mut hasher);
+            if size_only {
+                continue;
+            }
+            metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+/// Summarizes a remote library from its listing, none when it can't be listed for now.
+fn remote_signature(db: &rusqlite::Connection, url: &str) -> rusqlite::Result<Option<u64>> {
+    let storage = match storage::load(db, url)? {
+        Ok(storage) => storage,
+        Err(err) => {
+            println!("{}", tr!("daemon-error", err = err));
+            return Ok(None);
+        }
+    };
+
+    match storage.list_files() {
+        Ok(files) => Ok(Some(storage::signature(&files))),
+        Err(err) => {
+            println!("{}", tr!("daemon-list-library", err = err));
+            Ok(None)
+        }
+    }
+}
+
+fn current_signature(
+    db: &rusqlite::Connection,
+    library: &str,
+    size_only: bool,
+    ignore_rules: &IgnoreRules,
+) -> rusqlite::Result<Option<u64>> {
+    if storage::is_remote(library) {
+        return remote_signature(db, library);
+    }
+    Ok(Some(library_signature(
+        Path::new(library),
+        size_only,
+        ignore_rules,
+    )))
+}
+
+#[cfg(unix)]
+fn handle_control(stream: UnixStream, state: &State) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let reply = match line.trim() {
+        "status" => state.describe(),
+        "rescan" => {
+            state.rescan.store(true, Ordering::Relaxed);
+            "rescan requested\n".to_owned()
+        }
+        "stop" => {
+            state.stop.store(true, Ordering::Relaxed);
+            "stopping\n".to_owned()
+        }
+        command => format!("unknown command \"{}\"\n", command),
+    };
+
+    let mut stream = stream;
+    stream.write_all(reply.as_bytes())?;
+    stream.flush()
+}
+
+/// Answers the commands sent by `zik ctl` until the daemon stops.
+#[cfg(unix)]
+fn serve_control(listener: UnixListener, state: Arc<State>) -> io::Result<()> {
+    // Same as the HTTP server: poll so the loop notices `stop`
+    listener.set_nonblocking(true)?;
+
+    while !state.stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+                if let Err(err) = handle_control(stream, &state) {
+                    println!("{}", tr!("daemon-control-connection", err = err));
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(err) => println!("{}", tr!("daemon-control-accept", err = err)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Scans when asked to or when the library changed, and runs the queued jobs in between.
+fn run(
+    db: &mut rusqlite::Connection,
+    state: &State,
+    watch_interval: Duration,
+    workers: usize,
+) -> Result<(), DaemonError> {
+    let mut signature: Option<u64> = None;
+    let mut inbox_signature: Option<u64> = None;
+    let mut last_check = Instant::now();
+
+    while !state.stop.load(Ordering::Relaxed) {
+        if state.rescan.swap(false, Ordering::Relaxed) {
+            state.set_activity("scanning");
+
+            let started_at = Instant::now();
+            match crate::scan_library(db, &mut throttle::Throttle::default()) {
+                Ok(summary) => {
+                    state.scans.fetch_add(1, Ordering::Relaxed);
+                    metrics::record_scan(started_at.elapsed(), Some(summary.indexed));
+                    notify::scan_completed(db, &summary);
+                }
+                Err(err) => {
+                    println!("{}", tr!("daemon-scan-failed", err = err));
+                    metrics::record_scan(started_at.elapsed(), None);
+                }
+            }
+
+            let library = library::location(db)?;
+            let size_only = netfs::IoOptions::load(db)?.size_only;
+            let ignore_rules = IgnoreRules::load(db)?;
+            signature = match library {
+                Some(library) => current_signature(db, &library, size_only, &ignore_rules)?,
+                None => None,
+            };
+            inbox_signature = None;
+            last_check = Instant::now();
+        }
+
+        state.set_activity("running jobs");
+        let n = jobs::run_pending_jobs(db, workers, &state.stop)?;
+        state.jobs_run.fetch_add(n, Ordering::Relaxed);
+        state.set_activity("idle");
+
+        if last_check.elapsed() >= watch_interval {
+            let size_only = netfs::IoOptions::load(db)?.size_only;
+            let ignore_rules = IgnoreRules::load(db)?;
+            // The library may have been set or relocated meanwhile
+            library::load_root(db)?;
+            if let Some(library) = library::location(db)? {
+                let new_signature = current_signature(db, &library, size_only, &ignore_rules)?;
+                if new_signature.is_some() && signature != new_signature {
+                    println!("{}", tr!("daemon-library-changed"));
+                    state.rescan.store(true, Ordering::Relaxed);
+                }
+            }
+            // A change in the inbox only needs the inbox scanned
+            if let Some(inbox) = inbox::path(db)? {
+                let new_signature = library_signature(&inbox, size_only, &ignore_rules);
+                if inbox_signature != Some(new_signature) {
+                    if let Err(err) = inbox::scan(db) {
+                        println!("{}", tr!("daemon-inbox-scan-failed", err = err));
+                    }
+                    inbox_signature = Some(new_signature);
+                }
+            }
+            last_check = Instant::now();
+        }
+
+        if n == 0 && !state.rescan.load(Ordering::Relaxed) {
+            thread::sleep(IDLE_SLEEP);
+        }
+    }
+
+    Ok(())
+}
+
+/// Binds a Unix socket at `path`, replacing a socket left over by a process that didn't stop
+/// cleanly.
+///
+/// Fails with `AddrInUse` if another process still answers on it.
+#[cfg(unix)]
+pub fn bind_socket(path: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "another process is listening on this socket",
+        ));
+    }
+    let _ = fs::remove_file(path);
+
+    UnixListener::bind(path)
+}
+
+/// Sends `state` to systemd, if the daemon runs under it.
+#[cfg(unix)]
+fn notify(state: &str) {
+    if let Err(err) = systemd::notify(state) {
+        println!("{}", tr!("daemon-notify-systemd", err = err));
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
+
+/// The sockets passed by systemd: the HTTP listener, the control socket and the RPC socket.
+#[cfg(unix)]
+fn activated_sockets() -> (
+    Option<TcpListener>,
+    Option<UnixListener>,
+    Option<UnixListener>,
+) {
+    let mut activated_listener = None;
+    let mut activated_control = None;
+    let mut activated_rpc = None;
+    for (name, fd) in systemd::listen_fds() {
+        // SAFETY: systemd hands over these descriptors to this process and nothing else uses them
+        match name.as_str() {
+            "control" => activated_control = Some(unsafe { UnixListener::from_raw_fd(fd) }),
+            "rpc" => activated_rpc = Some(unsafe { UnixListener::from_raw_fd(fd) }),
+            _ if activated_listener.is_none() => {
+                activated_listener = Some(unsafe { TcpListener::from_raw_fd(fd) })
+            }
+            _ => println!("{}", tr!("daemon-extra-socket", name = name)),
+        }
+    }
+
+    (activated_listener, activated_control, activated_rpc)
+}
+
+pub fn cmd_daemon(
+    db: &mut rusqlite::Connection,
+    _args: &clap::ArgMatches,
+) -> Result<(), DaemonError> {
+    #[cfg(unix)]
+    let socket_path = socket_path()?;
+
+    let server_address = crate::get_config_value(db, "server_address")?
+        .unwrap_or_else(|| DEFAULT_SERVER_ADDRESS.to_owned());
+    let watch_interval = crate::get_config_usize(db, "watch_interval")?
+        .filter(|n| *n > 0)
+        .unwrap_or(DEFAULT_WATCH_INTERVAL);
+    let workers = jobs::default_workers(db)?;
+
+    // Sockets passed by systemd are already bound, and the socket unit owns them
+    #[cfg(unix)]
+    let (activated_listener, activated_control, activated_rpc) = activated_sockets();
+    #[cfg(not(unix))]
+    let activated_listener = None;
+
+    let listener = match activated_listener {
+        Some(listener) => listener,
+        None => match TcpListener::bind(&server_address) {
+            Ok(listener) => listener,
+            Err(err) => return Err(DaemonError::Listen(server_address, err)),
+        },
+    };
+    let server_address = match listener.local_addr() {
+        Ok(address) => address.to_string(),
+        Err(_) => server_address,
+    };
+
+    #[cfg(unix)]
+    let owns_socket = activated_control.is_none();
+    #[cfg(unix)]
+    let control = match activated_control {
+        Some(control) => control,
+        None => match bind_socket(&socket_path) {
+            Ok(control) => control,
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+                return Err(DaemonError::AlreadyRunning(socket_path))
+            }
+            Err(err) => return Err(DaemonError::Listen(socket_path.display().to_string(), err)),
+        },
+    };
+
+    #[cfg(unix)]
+    let rpc_path = match rpc::socket_path() {
+        Some(path) => path,
+        None => return Err(DaemonError::DataFolderNotFound),
+    };
+    #[cfg(unix)]
+    let owns_rpc_socket = activated_rpc.is_none();
+    #[cfg(unix)]
+    let rpc_listener = match activated_rpc {
+        Some(rpc_listener) => rpc_listener,
+        None => match bind_socket(&rpc_path) {
+            Ok(rpc_listener) => rpc_listener,
+            Err(err) => return Err(DaemonError::Listen(rpc_path.display().to_string(), err)),
+        },
+    };
+
+    let state = Arc::new(State {
+        stop: Arc::new(AtomicBool::new(false)),
+        // Pick up whatever changed while the daemon wasn't running
+        rescan: AtomicBool::new(true),
+        activity: Mutex::new("starting"),
+        #[cfg(unix)]
+        started_at: Instant::now(),
+        server_address,
+        scans: AtomicUsize::new(0),
+        jobs_run: AtomicUsize::new(0),
+    });
+
+    #[cfg(unix)]
+    println!(
+        "{}",
+        tr!(
+            "daemon-serving-socket",
+            address = state.server_address,
+            interval = watch_interval,
+            path = socket_path.display()
+        )
+    );
+    #[cfg(not(unix))]
+    println!(
+        "{}",
+        tr!(
+            "daemon-serving",
+            address = state.server_address,
+            interval = watch_interval
+        )
+    );
+
+    let server_handle = {
+        let stop = Arc::clone(&state.stop);
+        thread::spawn(move || server::serve(listener, stop))
+    };
+    #[cfg(unix)]
+    let control_handle = {
+        let state = Arc::clone(&state);
+        thread::spawn(move || serve_control(control, state))
+    };
+    #[cfg(unix)]
+    let rpc_handle = {
+        let stop = Arc::clone(&state.stop);
+
+        // Scans requested over RPC are run by the main loop like any other
+        let scan_state = Arc::clone(&state);
+        let request_scan: rpc::RequestScan =
+            Arc::new(move || scan_state.rescan.store(true, Ordering::Relaxed));
+
+        thread::spawn(move || rpc::serve(rpc_listener, stop, Some(request_scan)))
+    };
+
+    // A scan can take much longer than any sensible watchdog timeout, so the pings come from
+    // their own thread; they stop if the process hangs or dies.
+    #[cfg(unix)]
+    if let Some(interval) = systemd::watchdog_interval() {
+        let stop = Arc::clone(&state.stop);
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                notify("WATCHDOG=1");
+                thread::sleep(interval);
+            }
+        });
+    }
+
+    notify(&format!(
+        "READY=1\nSTATUS=serving on {}",
+        state.server_address
+    ));
+
+    let result = run(
+        db,
+        &state,
+        Duration::from_secs(watch_interval as u64),
+        workers,
+    );
+
+    notify("STOPPING=1");
+
+    state.stop.store(true, Ordering::Relaxed);
+    #[cfg(unix)]
+    let handles = [server_handle, control_handle, rpc_handle];
+    #[cfg(not(unix))]
+    let handles = [server_handle];
+    for handle in handles {
+        if let Ok(Err(err)) = handle.join() {
+            println!("{}", tr!("daemon-error", err = err));
+        }
+    }
+    #[cfg(unix)]
+    if owns_socket {
+        let _ = fs::remove_file(&socket_path);
+    }
+    #[cfg(unix)]
+    if owns_rpc_socket {
+        let _ = fs::remove_file(&rpc_path);
+    }
+
+    println!("{}", tr!("daemon-stopped"));
+
+    result
+}
+
+//
+// "ctl" command
+//
+
+#[cfg(unix)]
+pub fn cmd_ctl(args: &clap::ArgMatches) -> Result<(), DaemonError> {
+    let command = match args.subcommand() {
+        Some((command, _)) => command,
+        None => return Ok(()),
+    };
+
+    let socket_path = socket_path()?;
+    let mut stream = match UnixStream::connect(&socket_path) {
+        Ok(stream) => stream,
+        Err(err)
+            if err.kind() == io::ErrorKind::NotFound
+                || err.kind() == io::ErrorKind::ConnectionRefused =>
+        {
+            return Err(DaemonError::NotRunning(socket_path))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    stream.write_all(command.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    print!("{}", reply);
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Settings of every connection to the database: write-ahead logging so the server reads while
+//! a scan writes, a busy timeout instead of failing right away on a lock, and foreign keys.
+//!
+//! `zik db pragmas` prints what SQLite actually uses, a database on a file system without
+//! shared memory for example stays in the rollback journal mode.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::i18n::tr;
+
+/// How long a connection waits for another one to release its lock.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub enum CommandDbError {
+    SQLite(rusqlite::Error),
+    #[cfg(feature = "encryption")]
+    Encryption(crate::encryption::EncryptionError),
+    #[cfg(not(feature = "encryption"))]
+    EncryptionNotBuilt,
+}
+impl From<rusqlite::Error> for CommandDbError {
+    fn from(err: rusqlite::Error) -> CommandDbError {
+        CommandDbError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandDbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandDbError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            #[cfg(feature = "encryption")]
+            CommandDbError::Encryption(err) => write!(f, "{}", err),
+            #[cfg(not(feature = "encryption"))]
+            CommandDbError::EncryptionNotBuilt => f.write_str(&tr!("db-encryption-not-built")),
+        }
+    }
+}
+
+/// Sets the pragmas of a new connection.
+pub fn configure(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    db.busy_timeout(BUSY_TIMEOUT)?;
+    // In-memory databases answer "memory", they have no journal file
+    db.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    // Safe with WAL, a power loss can only lose the last transactions
+    db.pragma_update(None, "synchronous", "NORMAL")?;
+    db.pragma_update(None, "foreign_keys", true)?;
+
+    Ok(())
+}
+
+//
+// "db" command
+//
+
+fn cmd_db_pragmas(db: &rusqlite::Connection) -> Result<(), CommandDbError> {
+    let journal_mode: String = db.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+    let synchronous: i64 = db.query_row("PRAGMA synchronous", [], |row| row.get(0))?;
+    let foreign_keys: bool = db.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+    let busy_timeout: i64 = db.query_row("PRAGMA busy_timeout", [], |row| row.get(0))?;
+    let violations: i64 =
+        db.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+            row.get(0)
+        })?;
+
+    println!("journal_mode = {}", journal_mode);
+    println!(
+        "synchronous = {}",
+        match synchronous {
+            0 => "OFF",
+            1 => "NORMAL",
+            2 => "FULL",
+            _ => "EXTRA",
+        }
+    );
+    println!("foreign_keys = {}", if foreign_keys { "ON" } else { "OFF" });
+    println!("busy_timeout = {}ms", busy_timeout);
+    if violations > 0 {
+        println!("{}", tr!("db-violations", rows = violations));
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+fn cmd_db_encrypt(db: &rusqlite::Connection) -> Result<(), CommandDbError> {
+    crate::encryption::encrypt(db).map_err(CommandDbError::Encryption)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn cmd_db_encrypt(_db: &rusqlite::Connection) -> Result<(), CommandDbError> {
+    Err(CommandDbError::EncryptionNotBuilt)
+}
+
+pub fn cmd_db(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandDbError> {
+    match args.subcommand() {
+        Some(("pragmas", _)) => cmd_db_pragmas(db),
+        Some(("encrypt", _)) => cmd_db_encrypt(db),
+        _ => Ok(()),
+    }
+}
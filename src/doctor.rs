@@ -0,0 +1,370 @@
+//! Data quality checks of the library, `zik doctor`, each rule reporting at its own severity.
+//!
+//! The severities are set like `zik config doctor_rules "mixed_formats=ignore"` and
+//! overridden with `--rule`. The exit code is that of the worst severity reported, 0 when
+//! clean, 1 for warnings and 2 for errors, so that a library can be checked like code in CI.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use crate::i18n::tr;
+use crate::json;
+
+pub enum CommandDoctorError {
+    SQLite(rusqlite::Error),
+    InvalidRules(String),
+}
+impl From<rusqlite::Error> for CommandDoctorError {
+    fn from(err: rusqlite::Error) -> CommandDoctorError {
+        CommandDoctorError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandDoctorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandDoctorError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandDoctorError::InvalidRules(err) => {
+                f.write_str(&tr!("doctor-invalid-rule", err = err))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ignore,
+    Warn,
+    Error,
+}
+
+impl Severity {
+    fn parse(value: &str) -> Option<Severity> {
+        match value {
+            "ignore" => Some(Severity::Ignore),
+            "warn" => Some(Severity::Warn),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Severity::Ignore => "ignore",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Severity::Ignore => 0,
+            Severity::Warn => 1,
+            Severity::Error => 2,
+        }
+    }
+}
+
+/// Something wrong found by a rule, about an album or a track.
+struct Issue {
+    subject: String,
+    message: String,
+}
+
+struct Rule {
+    name: &'static str,
+    severity: Severity,
+    check: fn(&rusqlite::Connection) -> rusqlite::Result<Vec<Issue>>,
+}
+
+const RULES: [Rule; 4] = [
+    Rule {
+        name: "missing_album_artist",
+        severity: Severity::Warn,
+        check: missing_album_artist,
+    },
+    Rule {
+        name: "year_out_of_range",
+        severity: Severity::Warn,
+        check: year_out_of_range,
+    },
+    Rule {
+        name: "mixed_formats",
+        severity: Severity::Warn,
+        check: mixed_formats,
+    },
+    Rule {
+        name: "non_sequential_track_numbers",
+        severity: Severity::Warn,
+        check: non_sequential_track_numbers,
+    },
+];
+
+/// The earliest year a recording can be from.
+const EARLIEST_YEAR: i64 = 1860;
+
+/// Parses severities like "mixed_formats=ignore,year_out_of_range=error".
+pub fn parse_rules(value: &str) -> Result<HashMap<&'static str, Severity>, String> {
+    let mut severities = HashMap::new();
+    for part in value
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let (name, severity) = match part.split_once('=') {
+            Some((name, severity)) => (name.trim(), severity.trim()),
+            None => return Err(tr!("doctor-invalid-severity-rule", value = part)),
+        };
+        let rule = match RULES.iter().find(|rule| rule.name == name) {
+            Some(rule) => rule,
+            None => {
+                let names: Vec<&str> = RULES.iter().map(|rule| rule.name).collect();
+                return Err(tr!(
+                    "doctor-unknown-rule",
+                    name = name,
+                    rules = names.join(", ")
+                ));
+            }
+        };
+        let severity = match Severity::parse(severity) {
+            Some(severity) => severity,
+            None => {
+                return Err(tr!(
+                    "doctor-invalid-severity",
+                    severity = severity,
+                    name = name
+                ))
+            }
+        };
+        severities.insert(rule.name, severity);
+    }
+
+    Ok(severities)
+}
+
+fn album_subject(artist: Option<String>, album: Option<String>) -> String {
+    format!(
+        "{} - {}",
+        artist.unwrap_or_default(),
+        album.unwrap_or_default()
+    )
+}
+
+/// Albums of several artists without an album artist on all their tracks, which players
+/// split into one album per artist.
+fn missing_album_artist(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Issue>> {
+    let mut stmt = db.prepare(
+        "SELECT artist.name, album.name, COUNT(DISTINCT track.artist_id),
+                COUNT(*) - COUNT(NULLIF(track.album_artist, ''))
+         FROM track
+         JOIN album ON album.id = track.album_id
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE track.missing_since IS NULL
+         GROUP BY album.id
+         HAVING COUNT(DISTINCT track.artist_id) > 1
+            AND COUNT(NULLIF(track.album_artist, '')) < COUNT(*)
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let artists: i64 = row.get(2)?;
+        let tracks: i64 = row.get(3)?;
+        Ok(Issue {
+            subject: album_subject(row.get(0)?, row.get(1)?),
+            message: tr!(
+                "doctor-missing-album-artist",
+                artists = artists,
+                tracks = tracks
+            ),
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Tracks from before recordings existed or from after next year, usually a typo.
+fn year_out_of_range(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Issue>> {
+    let mut stmt = db.prepare(
+        "SELECT library_path(path), release_year
+         FROM track
+         WHERE missing_since IS NULL
+           AND release_year IS NOT NULL
+           AND (release_year < $earliest
+                OR release_year > CAST(strftime('%Y', 'now') AS INTEGER) + 1)
+         ORDER BY path",
+    )?;
+    let rows = stmt.query_map([EARLIEST_YEAR], |row| {
+        let year: i64 = row.get(1)?;
+        Ok(Issue {
+            subject: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+            message: tr!("doctor-year", year = year),
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Albums whose tracks are in different formats, like a FLAC rip completed with MP3s.
+fn mixed_formats(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Issue>> {
+    let mut stmt = db.prepare(
+        "SELECT album.id, artist.name, album.name, library_path(track.path)
+         FROM track
+         JOIN album ON album.id = track.album_id
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE track.missing_since IS NULL AND track.path IS NOT NULL
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                  album.id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut albums: Vec<(i64, String, BTreeSet<String>)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let album_id: i64 = row.get(0)?;
+        let path: String = row.get(3)?;
+
+        if albums.last().map(|(id, _, _)| *id) != Some(album_id) {
+            albums.push((
+                album_id,
+                album_subject(row.get(1)?, row.get(2)?),
+                BTreeSet::new(),
+            ));
+        }
+        if let Some(extension) = Path::new(&path).extension() {
+            let (_, _, formats) = albums.last_mut().unwrap();
+            formats.insert(extension.to_string_lossy().to_lowercase());
+        }
+    }
+
+    Ok(albums
+        .into_iter()
+        .filter(|(_, _, formats)| formats.len() > 1)
+        .map(|(_, subject, formats)| Issue {
+            subject,
+            message: tr!(
+                "doctor-mixed-formats",
+                formats = formats.into_iter().collect::<Vec<_>>().join(", ")
+            ),
+        })
+        .collect())
+}
+
+/// Discs whose track numbers don't go from 1 up one by one, with gaps or duplicates.
+fn non_sequential_track_numbers(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Issue>> {
+    let mut stmt = db.prepare(
+        "SELECT album.id, artist.name, album.name, COALESCE(track.disc_number, 1), track.number
+         FROM track
+         JOIN album ON album.id = track.album_id
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE track.missing_since IS NULL AND track.number IS NOT NULL
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                  album.id, 4, track.number",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut discs: Vec<((i64, i64), String, Vec<i64>)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let key = (row.get(0)?, row.get(3)?);
+        let number: i64 = row.get(4)?;
+
+        if discs.last().map(|(last, _, _)| *last) != Some(key) {
+            discs.push((key, album_subject(row.get(1)?, row.get(2)?), Vec::new()));
+        }
+        let (_, _, numbers) = discs.last_mut().unwrap();
+        numbers.push(number);
+    }
+
+    Ok(discs
+        .into_iter()
+        .filter(|(_, _, numbers)| numbers.iter().zip(1..).any(|(number, i)| *number != i))
+        .map(|((_, disc), subject, numbers)| Issue {
+            subject,
+            message: tr!(
+                "doctor-track-numbers",
+                disc = disc,
+                numbers = numbers
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        })
+        .collect())
+}
+
+//
+// "doctor" command
+//
+
+/// Runs the rules and returns the worst severity reported.
+pub fn cmd_doctor(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<Severity, CommandDoctorError> {
+    let mut severities = match crate::get_config_value(db, "doctor_rules")? {
+        Some(value) => parse_rules(&value).map_err(CommandDoctorError::InvalidRules)?,
+        None => HashMap::new(),
+    };
+    for value in args.values_of("rule").into_iter().flatten() {
+        severities.extend(parse_rules(value).map_err(CommandDoctorError::InvalidRules)?);
+    }
+    let json = args.is_present("json");
+
+    let mut worst = Severity::Ignore;
+    let (mut errors, mut warnings) = (0, 0);
+    let mut values = Vec::new();
+
+    for rule in &RULES {
+        let severity = severities.get(rule.name).copied().unwrap_or(rule.severity);
+        if severity == Severity::Ignore {
+            continue;
+        }
+
+        for issue in (rule.check)(db)? {
+            worst = worst.max(severity);
+            match severity {
+                Severity::Error => errors += 1,
+                _ => warnings += 1,
+            }
+
+            if json {
+                values.push(json::object(&[
+                    ("rule", json::string(rule.name)),
+                    ("severity", json::string(severity.as_str())),
+                    ("subject", json::string(&issue.subject)),
+                    ("message", json::string(&issue.message)),
+                ]));
+            } else {
+                println!(
+                    "{:<5}  {}  {}: {}",
+                    severity.as_str(),
+                    rule.name,
+                    issue.subject,
+                    issue.message
+                );
+            }
+        }
+    }
+
+    if json {
+        println!(
+            "{}",
+            json::object(&[
+                ("issues", json::array(&values)),
+                ("errors", errors.to_string()),
+                ("warnings", warnings.to_string()),
+                (
+                    "worst",
+                    json::opt_string(Some(worst.as_str()).filter(|_| worst != Severity::Ignore)),
+                ),
+            ])
+        );
+    } else if errors + warnings == 0 {
+        println!("{}", tr!("doctor-clean"));
+    } else {
+        println!(
+            "{}",
+            tr!("doctor-summary", errors = errors, warnings = warnings)
+        );
+    }
+
+    Ok(worst)
+}
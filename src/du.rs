@@ -0,0 +1,213 @@
+//! What the library is made of, per top-level folder: `zik du` lists the tracks, size on disk
+//! and duration of each, the biggest first, to see what's worth moving elsewhere.
+//!
+//! Sizes aren't stored, they're those of the files now: an archive is counted once in full,
+//! and the files of a remote library are listed once for theirs.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+use crate::i18n::tr;
+use crate::{archive, storage, top};
+
+pub enum CommandDuError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    Storage(storage::StorageError),
+    NoLibrary,
+}
+impl From<rusqlite::Error> for CommandDuError {
+    fn from(err: rusqlite::Error) -> CommandDuError {
+        CommandDuError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandDuError {
+    fn from(err: io::Error) -> CommandDuError {
+        CommandDuError::IO(err)
+    }
+}
+impl From<storage::StorageError> for CommandDuError {
+    fn from(err: storage::StorageError) -> CommandDuError {
+        CommandDuError::Storage(err)
+    }
+}
+impl fmt::Display for CommandDuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandDuError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandDuError::IO(err) => f.write_str(&tr!("du-remote-io", err = err)),
+            CommandDuError::Storage(err) => write!(f, "{}", err),
+            CommandDuError::NoLibrary => f.write_str(&tr!("du-no-library")),
+        }
+    }
+}
+
+/// The message of the group of the files directly in the library.
+const ROOT: &str = "du-root";
+/// The message of the group of the tracks scanned from somewhere else.
+const OUTSIDE: &str = "du-outside";
+
+#[derive(Default)]
+struct Usage {
+    tracks: usize,
+    size: u64,
+    duration_ms: i64,
+}
+
+/// Formats a number of bytes like "1.5 GiB".
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Returns the top-level folder of the local `path` in the library at `root`.
+fn local_group(root: &Path, path: &Path) -> String {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative,
+        Err(_) => return tr!(OUTSIDE),
+    };
+
+    let mut components = relative.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(folder)), Some(_)) => folder.to_string_lossy().into_owned(),
+        _ => tr!(ROOT),
+    }
+}
+
+/// Returns the top-level folder of the track at `url` in the remote library at `root`.
+fn remote_group(root: &str, url: &str) -> String {
+    let root = root.trim_end_matches('/');
+    match url
+        .strip_prefix(root)
+        .and_then(|rest| rest.strip_prefix('/'))
+    {
+        Some(relative) => match relative.split_once('/') {
+            Some((folder, _)) => folder.to_owned(),
+            None => tr!(ROOT),
+        },
+        None => tr!(OUTSIDE),
+    }
+}
+
+//
+// "du" command
+//
+
+pub fn cmd_du(
+    db: &mut rusqlite::Connection,
+    _args: &clap::ArgMatches,
+) -> Result<(), CommandDuError> {
+    let library = match crate::library::location(db)? {
+        Some(library) => library,
+        None => return Err(CommandDuError::NoLibrary),
+    };
+
+    // The sizes of a remote library come from a single listing
+    let remote_sizes: Option<HashMap<String, u64>> = if storage::is_remote(&library) {
+        let files = storage::load(db, &library)??.list_files()?;
+        Some(
+            files
+                .into_iter()
+                .filter_map(|file| Some((file.url, file.size?)))
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    let mut stmt = db.prepare(
+        "SELECT library_path(path), duration_ms
+         FROM track WHERE path IS NOT NULL AND missing_since IS NULL",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut usages: HashMap<String, Usage> = HashMap::new();
+    let mut archives: HashSet<String> = HashSet::new();
+    let mut unknown_sizes = 0;
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(0)?;
+        let duration_ms: Option<i64> = row.get(1)?;
+
+        let (group, size) = match &remote_sizes {
+            Some(sizes) if storage::is_remote(&path) => {
+                (remote_group(&library, &path), sizes.get(&path).copied())
+            }
+            _ => {
+                let group = local_group(Path::new(&library), Path::new(&path));
+                let size = match archive::split(Path::new(&path)) {
+                    // The other tracks of the archive are in its size already
+                    Some((archive, _)) if !archives.insert(archive.to_string_lossy().into()) => {
+                        Some(0)
+                    }
+                    Some((archive, _)) => fs::metadata(archive).ok().map(|md| md.len()),
+                    None => fs::metadata(&path).ok().map(|md| md.len()),
+                };
+                (group, size)
+            }
+        };
+
+        let usage = usages.entry(group).or_default();
+        usage.tracks += 1;
+        usage.duration_ms += duration_ms.unwrap_or(0);
+        match size {
+            Some(size) => usage.size += size,
+            None => unknown_sizes += 1,
+        }
+    }
+
+    if usages.is_empty() {
+        println!("{}", tr!("du-empty"));
+        return Ok(());
+    }
+
+    let mut usages: Vec<(String, Usage)> = usages.into_iter().collect();
+    usages.sort_by(|(a_name, a), (b_name, b)| b.size.cmp(&a.size).then(a_name.cmp(b_name)));
+
+    let mut total = Usage::default();
+    for (name, usage) in &usages {
+        println!(
+            "{}",
+            tr!(
+                "du-row",
+                size = format!("{:>10}", format_size(usage.size)),
+                tracks = format!("{:>6}", usage.tracks),
+                duration = format!("{:>9}", top::format_duration(usage.duration_ms)),
+                name = name
+            )
+        );
+        total.tracks += usage.tracks;
+        total.size += usage.size;
+        total.duration_ms += usage.duration_ms;
+    }
+    println!(
+        "{}",
+        tr!(
+            "du-row",
+            size = format!("{:>10}", format_size(total.size)),
+            tracks = format!("{:>6}", total.tracks),
+            duration = format!("{:>9}", top::format_duration(total.duration_ms)),
+            name = tr!("du-total")
+        )
+    );
+
+    if unknown_sizes > 0 {
+        println!("{}", tr!("du-unknown-sizes", count = unknown_sizes));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! Encryption of the database with SQLCipher, built with `--features encryption`.
+//!
+//! A new database is encrypted when there's a passphrase at hand, from `ZIK_DATABASE_KEY` or
+//! the keyring with `zik secrets set database_key`; an existing one is encrypted with
+//! `zik db encrypt`. Opening an encrypted database without either asks for the passphrase,
+//! once per process, if there's a terminal to ask it on.
+//!
+//! The SQLite of SQLCipher can be older than the one zik is built with otherwise, what it
+//! lacks is added to every connection.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::functions::FunctionFlags;
+
+use crate::i18n::tr;
+use crate::secrets;
+
+/// The first bytes of a database which isn't encrypted.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// The passphrase once found, every connection of the process needs it.
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    NoPassphrase,
+    WrongPassphrase,
+    AlreadyEncrypted,
+    NoDatabasePath,
+}
+impl From<rusqlite::Error> for EncryptionError {
+    fn from(err: rusqlite::Error) -> EncryptionError {
+        EncryptionError::SQLite(err)
+    }
+}
+impl From<io::Error> for EncryptionError {
+    fn from(err: io::Error) -> EncryptionError {
+        EncryptionError::IO(err)
+    }
+}
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncryptionError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            EncryptionError::IO(err) => f.write_str(&tr!("encryption-io", err = err)),
+            EncryptionError::NoPassphrase => f.write_str(&tr!("encryption-no-passphrase")),
+            EncryptionError::WrongPassphrase => f.write_str(&tr!("encryption-wrong-passphrase")),
+            EncryptionError::AlreadyEncrypted => f.write_str(&tr!("encryption-already-encrypted")),
+            EncryptionError::NoDatabasePath => f.write_str(&tr!("encryption-no-database-path")),
+        }
+    }
+}
+
+/// Returns true if the file at `path` is an encrypted database, false if it's a plain one or
+/// doesn't exist yet.
+fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    match fs::File::open(path) {
+        Ok(file) => file
+            .take(SQLITE_HEADER.len() as u64)
+            .read_to_end(&mut header)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    Ok(!header.is_empty() && header != SQLITE_HEADER)
+}
+
+/// Returns the passphrase of the database, asking for it on the terminal with `ask`.
+fn passphrase(ask: bool) -> Result<Option<String>, EncryptionError> {
+    if let Some(passphrase) = PASSPHRASE.lock().unwrap().clone() {
+        return Ok(Some(passphrase));
+    }
+
+    let mut passphrase = env::var("ZIK_DATABASE_KEY")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .or_else(|| secrets::from_keyring("database_key"));
+
+    if passphrase.is_none() && ask && io::stdin().is_terminal() {
+        print!("{} ", tr!("encryption-passphrase-prompt"));
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        passphrase =
+            Some(line.trim_end_matches(['\r', '\n']).to_owned()).filter(|value| !value.is_empty());
+    }
+
+    *PASSPHRASE.lock().unwrap() = passphrase.clone();
+    Ok(passphrase)
+}
+
+/// Gives its passphrase to a new connection to the database at `path`, before anything else
+/// reads it.
+pub fn unlock(db: &rusqlite::Connection, path: &Path) -> Result<(), EncryptionError> {
+    let passphrase = if is_encrypted(path)? {
+        passphrase(true)?.ok_or(EncryptionError::NoPassphrase)?
+    } else if fs::metadata(path).map_or(0, |metadata| metadata.len()) == 0 {
+        // A new database is only encrypted if there's a passphrase without asking
+        match passphrase(false)? {
+            Some(passphrase) => passphrase,
+            None => return Ok(()),
+        }
+    } else {
+        return Ok(());
+    };
+
+    db.pragma_update(None, "key", &passphrase)?;
+
+    // A wrong passphrase only shows when reading
+    if db
+        .query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .is_err()
+    {
+        *PASSPHRASE.lock().unwrap() = None;
+        return Err(EncryptionError::WrongPassphrase);
+    }
+
+    Ok(())
+}
+
+/// Adds `unixepoch()`, from SQLite 3.38, if the connection doesn't have it.
+pub fn register_functions(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    if rusqlite::version_number() >= 3038000 {
+        return Ok(());
+    }
+
+    db.create_scalar_function("unixepoch", -1, FunctionFlags::SQLITE_UTF8, |ctx| {
+        if ctx.is_empty() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            return Ok(Some(now as i64));
+        }
+
+        // The same as strftime('%s') with the same arguments
+        let args = (0..ctx.len())
+            .map(|i| ctx.get::<rusqlite::types::Value>(i))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let sql = format!(
+            "SELECT CAST(strftime('%s'{}) AS INTEGER)",
+            ", ?".repeat(args.len())
+        );
+        let db = rusqlite::Connection::open_in_memory()?;
+        db.query_row(&sql, rusqlite::params_from_iter(args), |row| {
+            row.get::<_, Option<i64>>(0)
+        })
+    })
+}
+
+/// Encrypts the plain database of `db` in place.
+pub fn encrypt(db: &rusqlite::Connection) -> Result<(), EncryptionError> {
+    let path = db.path().ok_or(EncryptionError::NoDatabasePath)?;
+    if is_encrypted(path)? {
+        return Err(EncryptionError::AlreadyEncrypted);
+    }
+    let passphrase = passphrase(true)?.ok_or(EncryptionError::NoPassphrase)?;
+
+    let encrypted_path = path.with_extension("db.encrypted");
+    match fs::remove_file(&encrypted_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+        _ => (),
+    }
+
+    db.execute(
+        "ATTACH DATABASE $path AS encrypted KEY $key",
+        rusqlite::params![encrypted_path.to_string_lossy(), passphrase],
+    )?;
+    db.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    // The export leaves out the number of migrations applied
+    let version: i64 = db.query_row("PRAGMA main.user_version", [], |row| row.get(0))?;
+    db.pragma_update(
+        Some(rusqlite::DatabaseName::Attached("encrypted")),
+        "user_version",
+        version,
+    )?;
+    db.execute("DETACH DATABASE encrypted", [])?;
+
+    // Nothing of the plain database must be left behind in its WAL
+    db.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    fs::rename(&encrypted_path, path)?;
+
+    println!("{}", tr!("encryption-done", path = path.display()));
+
+    Ok(())
+}
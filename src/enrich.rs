@@ -0,0 +1,790 @@
+//! Artist bios, images and links fetched from Last.fm or Wikidata, and links of albums to
+//! Apple Music found with its search API.
+//!
+//! `zik info track` shows what the library knows of a track itself, like its source.
+//!
+//! Results are cached in `artist_info` by artist name, artist IDs change with every scan,
+//! and fetched again once older than `enrich_ttl` days. Artists nothing was found for are
+//! cached too, so that they aren't looked up again on every run.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::http::{self, HttpError};
+use crate::i18n::tr;
+use crate::json;
+use crate::links::Service;
+
+const DEFAULT_TTL_DAYS: usize = 30;
+
+/// Both services ask to not be hammered; one artist per second is well within their limits.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The iTunes search API allows about 20 requests a minute.
+const APPLE_MUSIC_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The image Last.fm returns for every artist since it stopped serving artist images.
+const LASTFM_PLACEHOLDER: &str = "2a96cbd8b46e442fc41c2b86b821562f";
+
+/// Words in the Wikidata description of an entity that tell it's a musician or a band, to
+/// not pick a namesake.
+const MUSIC_WORDS: &[&str] = &[
+    "band",
+    "musician",
+    "singer",
+    "rapper",
+    "composer",
+    "songwriter",
+    "group",
+    "duo",
+    "dj",
+    "producer",
+    "orchestra",
+    "ensemble",
+    "music",
+];
+
+pub enum CommandEnrichError {
+    SQLite(rusqlite::Error),
+    Http(HttpError),
+    UnknownSource(String),
+    MissingApiKey,
+    ArtistNotFound(String),
+    TrackNotFound(String),
+}
+impl From<rusqlite::Error> for CommandEnrichError {
+    fn from(err: rusqlite::Error) -> CommandEnrichError {
+        CommandEnrichError::SQLite(err)
+    }
+}
+impl From<HttpError> for CommandEnrichError {
+    fn from(err: HttpError) -> CommandEnrichError {
+        CommandEnrichError::Http(err)
+    }
+}
+impl fmt::Display for CommandEnrichError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandEnrichError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandEnrichError::Http(err) => write!(f, "{}", err),
+            CommandEnrichError::UnknownSource(source) => {
+                f.write_str(&tr!("enrich-unknown-source", source = source))
+            }
+            CommandEnrichError::MissingApiKey => f.write_str(&tr!("enrich-missing-api-key")),
+            CommandEnrichError::ArtistNotFound(name) => {
+                f.write_str(&tr!("artist-not-found", name = name))
+            }
+            CommandEnrichError::TrackNotFound(path) => {
+                f.write_str(&tr!("track-not-found", path = path))
+            }
+        }
+    }
+}
+
+enum Source {
+    LastFm(String),
+    Wikidata,
+}
+
+impl Source {
+    fn name(&self) -> &'static str {
+        match self {
+            Source::LastFm(_) => "lastfm",
+            Source::Wikidata => "wikidata",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ArtistInfo {
+    pub bio: Option<String>,
+    pub image_url: Option<String>,
+    pub musicbrainz_id: Option<String>,
+    /// Links to the artist on other sites, by kind: "wikipedia", "lastfm", "website"...
+    pub links: Vec<(String, String)>,
+    /// Names of similar artists, only known with Last.fm.
+    pub similar: Vec<String>,
+}
+
+/// Removes the HTML tags of a Last.fm bio, which ends with a "Read more" link.
+fn strip_html(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => buf.push(c),
+            _ => (),
+        }
+    }
+
+    let buf = buf.trim();
+    buf.strip_suffix("Read more on Last.fm")
+        .unwrap_or(buf)
+        .trim_end()
+        .to_owned()
+}
+
+fn non_empty(value: Option<&json::Value>) -> Option<String> {
+    value
+        .and_then(json::Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_owned())
+}
+
+fn fetch_lastfm(api_key: &str, name: &str) -> Result<Option<ArtistInfo>, HttpError> {
+    let url = format!(
+        "https://ws.audioscrobbler.com/2.0/?method=artist.getinfo&autocorrect=1&format=json&artist={}&api_key={}",
+        http::percent_encode(name),
+        http::percent_encode(api_key),
+    );
+    let response = http::get_json(&url)?;
+
+    // Unknown artists are an error 6 in a successful response
+    let artist = match response.get("artist") {
+        Some(artist) => artist,
+        None => return Ok(None),
+    };
+
+    let mut info = ArtistInfo {
+        bio: artist
+            .get("bio")
+            .and_then(|bio| non_empty(bio.get("summary")))
+            .map(|summary| strip_html(&summary))
+            .filter(|bio| !bio.is_empty()),
+        musicbrainz_id: non_empty(artist.get("mbid")),
+        ..Default::default()
+    };
+
+    // The sizes go from small to mega, the last one is the biggest
+    info.image_url = artist
+        .get("image")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|image| non_empty(image.get("#text")))
+        .rfind(|url| !url.contains(LASTFM_PLACEHOLDER));
+
+    if let Some(url) = non_empty(artist.get("url")) {
+        info.links.push(("lastfm".to_owned(), url));
+    }
+    if let Some(mbid) = &info.musicbrainz_id {
+        let url = format!("https://musicbrainz.org/artist/{}", mbid);
+        info.links.push(("musicbrainz".to_owned(), url));
+    }
+
+    info.similar = artist
+        .get("similar")
+        .and_then(|similar| similar.get("artist"))
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|similar| non_empty(similar.get("name")))
+        .collect();
+
+    Ok(Some(info))
+}
+
+/// Returns the value of the first statement of `property` in a Wikidata entity.
+fn wikidata_claim(entity: &json::Value, property: &str) -> Option<String> {
+    let claims = entity.get("claims")?.get(property)?.as_array();
+    non_empty(
+        claims
+            .first()?
+            .get("mainsnak")?
+            .get("datavalue")?
+            .get("value"),
+    )
+}
+
+/// Finds the Wikidata entity of a musician or band named `name`.
+fn search_wikidata(name: &str) -> Result<Option<String>, HttpError> {
+    let url = format!(
+        "https://www.wikidata.org/w/api.php?action=wbsearchentities&type=item&language=en&limit=10&format=json&search={}",
+        http::percent_encode(name),
+    );
+    let response = http::get_json(&url)?;
+
+    let id = response
+        .get("search")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .find(|result| {
+            let description = non_empty(result.get("description"))
+                .unwrap_or_default()
+                .to_lowercase();
+            description
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|word| MUSIC_WORDS.contains(&word))
+        })
+        .and_then(|result| non_empty(result.get("id")));
+
+    Ok(id)
+}
+
+fn fetch_wikidata(name: &str) -> Result<Option<ArtistInfo>, HttpError> {
+    let id = match search_wikidata(name)? {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    let url = format!(
+        "https://www.wikidata.org/wiki/Special:EntityData/{}.json",
+        http::percent_encode(&id)
+    );
+    let response = http::get_json(&url)?;
+    let entity = match response
+        .get("entities")
+        .and_then(|entities| entities.get(&id))
+    {
+        Some(entity) => entity,
+        None => return Ok(None),
+    };
+
+    let mut info = ArtistInfo {
+        musicbrainz_id: wikidata_claim(entity, "P434"),
+        ..Default::default()
+    };
+
+    info.links.push((
+        "wikidata".to_owned(),
+        format!("https://www.wikidata.org/wiki/{}", id),
+    ));
+    if let Some(mbid) = &info.musicbrainz_id {
+        let url = format!("https://musicbrainz.org/artist/{}", mbid);
+        info.links.push(("musicbrainz".to_owned(), url));
+    }
+    if let Some(website) = wikidata_claim(entity, "P856") {
+        info.links.push(("website".to_owned(), website));
+    }
+
+    // The image is a file name on Wikimedia Commons
+    if let Some(file) = wikidata_claim(entity, "P18") {
+        info.image_url = Some(format!(
+            "https://commons.wikimedia.org/wiki/Special:FilePath/{}?width=500",
+            http::percent_encode(&file.replace(' ', "_"))
+        ));
+    }
+
+    // The bio is the introduction of the English Wikipedia article, if there's one
+    let title = entity
+        .get("sitelinks")
+        .and_then(|sitelinks| sitelinks.get("enwiki"))
+        .and_then(|enwiki| non_empty(enwiki.get("title")));
+    if let Some(title) = title {
+        thread::sleep(REQUEST_INTERVAL);
+
+        let url = format!(
+            "https://en.wikipedia.org/api/rest_v1/page/summary/{}",
+            http::percent_encode(&title.replace(' ', "_"))
+        );
+        let summary = http::get_json(&url)?;
+
+        info.bio = non_empty(summary.get("extract"));
+        if info.image_url.is_none() {
+            info.image_url = summary
+                .get("thumbnail")
+                .and_then(|thumbnail| non_empty(thumbnail.get("source")));
+        }
+        let page = summary
+            .get("content_urls")
+            .and_then(|urls| urls.get("desktop"))
+            .and_then(|desktop| non_empty(desktop.get("page")));
+        if let Some(page) = page {
+            info.links.push(("wikipedia".to_owned(), page));
+        }
+    }
+
+    if info.bio.is_none() {
+        info.bio = non_empty(
+            entity
+                .get("descriptions")
+                .and_then(|d| d.get("en"))
+                .and_then(|en| en.get("value")),
+        );
+    }
+
+    Ok(Some(info))
+}
+
+/// Returns a name without what's between brackets and punctuation, to compare the names
+/// of albums like "Kind of Blue (Legacy Edition)" and "Kind Of Blue".
+fn normalize_name(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = (depth - 1).max(0),
+            c if depth == 0 && c.is_alphanumeric() => buf.extend(c.to_lowercase()),
+            _ => (),
+        }
+    }
+    buf
+}
+
+/// Removes the affiliate parameter of iTunes URLs.
+fn clean_apple_music_url(url: &str) -> String {
+    url.replace("?uo=4", "").replace("&uo=4", "")
+}
+
+/// The links of an album and of its tracks, by disc and number.
+struct AlbumLinks {
+    url: String,
+    tracks: Vec<(i64, i64, String)>,
+}
+
+/// Searches Apple Music for the album `album` of `artist`.
+fn search_apple_music(artist: &str, album: &str) -> Result<Option<AlbumLinks>, HttpError> {
+    let url = format!(
+        "https://itunes.apple.com/search?media=music&entity=album&limit=10&term={}",
+        http::percent_encode(&format!("{} {}", artist, album)),
+    );
+    let response = http::get_json(&url)?;
+
+    let (artist, album) = (normalize_name(artist), normalize_name(album));
+    let found = response
+        .get("results")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .find(|result| {
+            non_empty(result.get("artistName")).is_some_and(|name| normalize_name(&name) == artist)
+                && non_empty(result.get("collectionName"))
+                    .is_some_and(|name| normalize_name(&name) == album)
+        });
+    let (id, url) = match found.and_then(|found| {
+        Some((
+            found.get("collectionId")?.as_i64()?,
+            non_empty(found.get("collectionViewUrl"))?,
+        ))
+    }) {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    thread::sleep(APPLE_MUSIC_INTERVAL);
+    let response = http::get_json(&format!(
+        "https://itunes.apple.com/lookup?entity=song&id={}",
+        id
+    ))?;
+    let tracks = response
+        .get("results")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|track| {
+            Some((
+                track.get("discNumber")?.as_i64()?,
+                track.get("trackNumber")?.as_i64()?,
+                clean_apple_music_url(&non_empty(track.get("trackViewUrl"))?),
+            ))
+        })
+        .collect();
+
+    Ok(Some(AlbumLinks {
+        url: clean_apple_music_url(&url),
+        tracks,
+    }))
+}
+
+fn save_info(
+    db: &rusqlite::Connection,
+    name: &str,
+    source: &Source,
+    info: Option<&ArtistInfo>,
+) -> rusqlite::Result<()> {
+    let links = info.map(|info| {
+        let fields: Vec<(&str, String)> = info
+            .links
+            .iter()
+            .map(|(kind, url)| (kind.as_str(), json::string(url)))
+            .collect();
+        json::object(&fields)
+    });
+    let similar = info.map(|info| {
+        let names: Vec<String> = info.similar.iter().map(|name| json::string(name)).collect();
+        json::array(&names)
+    });
+
+    db.execute(
+        "INSERT INTO artist_info(artist_name, source, found, bio, image_url, musicbrainz_id, links, similar)
+         VALUES($name, $source, $found, $bio, $image_url, $musicbrainz_id, $links, $similar)
+         ON CONFLICT(artist_name) DO UPDATE SET
+           source = excluded.source,
+           found = excluded.found,
+           bio = excluded.bio,
+           image_url = excluded.image_url,
+           musicbrainz_id = excluded.musicbrainz_id,
+           links = excluded.links,
+           similar = excluded.similar,
+           fetched_at = unixepoch()",
+        rusqlite::params![
+            name,
+            source.name(),
+            info.is_some(),
+            info.and_then(|info| info.bio.as_deref()),
+            info.and_then(|info| info.image_url.as_deref()),
+            info.and_then(|info| info.musicbrainz_id.as_deref()),
+            links,
+            similar,
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Returns what was fetched about the artist `name`, if anything was found.
+pub fn get_info(db: &rusqlite::Connection, name: &str) -> rusqlite::Result<Option<ArtistInfo>> {
+    let result = db.query_row(
+        "SELECT bio, image_url, musicbrainz_id, links, similar FROM artist_info
+         WHERE artist_name = $name AND found",
+        [name],
+        |row| {
+            let links: Option<String> = row.get(3)?;
+            let similar: Option<String> = row.get(4)?;
+            Ok((
+                ArtistInfo {
+                    bio: row.get(0)?,
+                    image_url: row.get(1)?,
+                    musicbrainz_id: row.get(2)?,
+                    ..Default::default()
+                },
+                links,
+                similar,
+            ))
+        },
+    );
+    let (mut info, links, similar) = match result {
+        Ok(result) => result,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    if let Some(json::Value::Object(fields)) = links.and_then(|links| json::parse(&links).ok()) {
+        info.links = fields
+            .into_iter()
+            .filter_map(|(kind, url)| url.as_str().map(|url| (kind, url.to_owned())))
+            .collect();
+    }
+    if let Some(similar) = similar.and_then(|similar| json::parse(&similar).ok()) {
+        info.similar = similar
+            .as_array()
+            .iter()
+            .filter_map(|name| name.as_str().map(|name| name.to_owned()))
+            .collect();
+    }
+
+    Ok(Some(info))
+}
+
+fn get_source(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<Source, CommandEnrichError> {
+    let api_key = crate::secrets::get(db, "lastfm_api_key")?;
+
+    match (args.value_of("source"), api_key) {
+        (Some("lastfm") | None, Some(api_key)) => Ok(Source::LastFm(api_key)),
+        (Some("lastfm"), None) => Err(CommandEnrichError::MissingApiKey),
+        (Some("wikidata") | None, _) => Ok(Source::Wikidata),
+        (Some(source), _) => Err(CommandEnrichError::UnknownSource(source.to_owned())),
+    }
+}
+
+//
+// "enrich" command
+//
+
+fn cmd_enrich_artists(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandEnrichError> {
+    let source = get_source(db, args)?;
+    let ttl_days = crate::get_config_usize(db, "enrich_ttl")?.unwrap_or(DEFAULT_TTL_DAYS);
+    let force = args.is_present("force");
+
+    let mut names: Vec<String> = {
+        let mut stmt = db.prepare(
+            "SELECT artist.name FROM artist
+             LEFT JOIN artist_info ON artist_info.artist_name = artist.name
+             WHERE artist.name <> 'Unknown'
+               AND ($force OR artist_info.fetched_at IS NULL
+                    OR artist_info.fetched_at < unixepoch() - $ttl * 86400)
+             ORDER BY artist.sort_name COLLATE natural_sort",
+        )?;
+        let names = stmt
+            .query_map(rusqlite::params![force, ttl_days], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        names
+    };
+
+    if let Some(only) = args.values_of("artist") {
+        let only: Vec<String> = only.map(|name| name.to_lowercase()).collect();
+        names.retain(|name| only.contains(&name.to_lowercase()));
+    }
+
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            thread::sleep(REQUEST_INTERVAL);
+        }
+
+        let result = match &source {
+            Source::LastFm(api_key) => fetch_lastfm(api_key, name),
+            Source::Wikidata => fetch_wikidata(name),
+        };
+
+        match result {
+            Ok(info) => {
+                save_info(db, name, &source, info.as_ref())?;
+                match info {
+                    Some(_) => println!(
+                        "{}",
+                        tr!("enrich-found", name = name, source = source.name())
+                    ),
+                    None => println!(
+                        "{}",
+                        tr!("enrich-not-found", name = name, source = source.name())
+                    ),
+                }
+            }
+            Err(err @ HttpError::NotFound) => return Err(err.into()),
+            Err(err) => println!("{}: {}", name, err),
+        }
+    }
+
+    println!("{}", tr!("enrich-artists-done", artists = names.len()));
+
+    Ok(())
+}
+
+fn cmd_enrich_links(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandEnrichError> {
+    let column = Service::AppleMusic.column();
+
+    let mut albums: Vec<(i64, String, String)> = {
+        let mut stmt = db.prepare(&format!(
+            "SELECT album.id, artist.name, album.name FROM album
+             JOIN artist ON artist.id = album.artist_id
+             WHERE album.{} IS NULL AND album.name IS NOT NULL AND artist.name <> 'Unknown'
+             ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort",
+            column
+        ))?;
+        let albums = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        albums
+    };
+
+    if let Some(only) = args.values_of("artist") {
+        let only: Vec<String> = only.map(|name| name.to_lowercase()).collect();
+        albums.retain(|(_, artist, _)| only.contains(&artist.to_lowercase()));
+    }
+
+    let mut found = 0;
+    for (i, (id, artist, album)) in albums.iter().enumerate() {
+        if i > 0 {
+            thread::sleep(APPLE_MUSIC_INTERVAL);
+        }
+
+        let name = format!("{} - {}", artist, album);
+        let links = match search_apple_music(artist, album) {
+            Ok(Some(links)) => links,
+            Ok(None) => {
+                println!(
+                    "{}",
+                    tr!("enrich-not-found", name = name, source = "Apple Music")
+                );
+                continue;
+            }
+            Err(err @ HttpError::NotFound) => return Err(err.into()),
+            Err(err) => {
+                println!("{}: {}", name, err);
+                continue;
+            }
+        };
+
+        db.execute(
+            &format!("UPDATE album SET {} = $url WHERE id = $id", column),
+            rusqlite::params![links.url, id],
+        )?;
+        // Links set by hand are kept
+        for (disc, number, url) in &links.tracks {
+            db.execute(
+                &format!(
+                    "UPDATE track SET {column} = $url
+                     WHERE album_id = $id AND coalesce(disc_number, 1) = $disc
+                       AND number = $number AND {column} IS NULL",
+                    column = column
+                ),
+                rusqlite::params![url, id, disc, number],
+            )?;
+        }
+        println!("{}: {}", name, links.url);
+        found += 1;
+    }
+
+    println!(
+        "{}",
+        tr!("enrich-links-done", found = found, albums = albums.len())
+    );
+
+    Ok(())
+}
+
+pub fn cmd_enrich(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandEnrichError> {
+    match args.subcommand() {
+        Some(("artists", sub_args)) => cmd_enrich_artists(db, sub_args),
+        Some(("links", sub_args)) => cmd_enrich_links(db, sub_args),
+        _ => Ok(()),
+    }
+}
+
+//
+// "info" command
+//
+
+fn cmd_info_artist(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandEnrichError> {
+    let value = args.value_of("name").unwrap();
+
+    let name: String = match db.query_row(
+        "SELECT name FROM artist WHERE name = $name COLLATE NOCASE",
+        [value],
+        |row| row.get(0),
+    ) {
+        Ok(name) => name,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(CommandEnrichError::ArtistNotFound(value.to_owned()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    println!("{}", name);
+
+    let info = match get_info(db, &name)? {
+        Some(info) => info,
+        None => {
+            println!("{}", tr!("info-no-artist-info"));
+            return Ok(());
+        }
+    };
+
+    if let Some(bio) = &info.bio {
+        println!("\n{}\n", bio);
+    }
+    if let Some(image_url) = &info.image_url {
+        println!("{}", tr!("info-image", url = image_url));
+    }
+    for (kind, url) in &info.links {
+        println!("{}: {}", kind, url);
+    }
+    if !info.similar.is_empty() {
+        println!("{}", tr!("info-similar", artists = info.similar.join(", ")));
+    }
+
+    Ok(())
+}
+
+fn cmd_info_track(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandEnrichError> {
+    let value = args.value_of("path").unwrap();
+    // Tracks are stored by their path in the library
+    let path = match fs::canonicalize(value) {
+        Ok(path) => crate::library::relative(&path),
+        Err(_) => value.to_owned(),
+    };
+
+    let track = db.query_row(
+        "SELECT track.name, artist.name, album.name, library_path(track.path), track.source,
+                datetime(track.first_seen_at, 'unixepoch', 'localtime'),
+                CASE
+                  WHEN track.lyrics IS NOT NULL THEN 'in the tags'
+                  WHEN track_lyrics.synced IS NOT NULL THEN 'synced, from LRCLIB'
+                  WHEN track_lyrics.plain IS NOT NULL THEN 'plain, from LRCLIB'
+                  WHEN track_lyrics.instrumental THEN 'none, instrumental'
+                END
+         FROM track
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         LEFT JOIN track_lyrics ON track_lyrics.track_id = track.id
+         WHERE track.path = $path",
+        [&path],
+        |row| {
+            Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+            ))
+        },
+    );
+    let (name, artist, album, path, source, first_seen_at, lyrics) = match track {
+        Ok(track) => track,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(CommandEnrichError::TrackNotFound(value.to_owned()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    println!(
+        "{} - {} - {}",
+        artist.unwrap_or_default(),
+        album.unwrap_or_default(),
+        name.unwrap_or_default()
+    );
+    let unknown = tr!("info-unknown");
+    println!("{}", tr!("info-path", path = path));
+    println!(
+        "{}",
+        tr!(
+            "info-source",
+            source = source.as_deref().unwrap_or(&unknown)
+        )
+    );
+    println!(
+        "{}",
+        tr!(
+            "info-first-seen",
+            date = first_seen_at.as_deref().unwrap_or(&unknown)
+        )
+    );
+    // A .lrc file is used over the rest
+    let lyrics = if Path::new(&path).with_extension("lrc").exists() {
+        tr!("info-lyrics-lrc")
+    } else {
+        lyrics.unwrap_or_else(|| tr!("info-lyrics-none"))
+    };
+    println!("{}", tr!("info-lyrics", lyrics = lyrics));
+
+    Ok(())
+}
+
+pub fn cmd_info(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandEnrichError> {
+    match args.subcommand() {
+        Some(("artist", sub_args)) => cmd_info_artist(db, sub_args),
+        Some(("track", sub_args)) => cmd_info_track(db, sub_args),
+        _ => Ok(()),
+    }
+}
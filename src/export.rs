@@ -0,0 +1,475 @@
+//! Exports of the library for other tools.
+//!
+//! The flat SQLite export has one table per view of the library, without foreign keys to
+//! follow, which is what tools like Datasette or Metabase are best at. It's written next
+//! to its destination then renamed over it, so a dashboard reading it never sees half an
+//! export.
+//!
+//! The HTML export is a static website of the library, see `site`.
+//!
+//! The M3U export is a playlist of the library, of one playlist or of a collection, where the songs of a
+//! mix get an entry each, with VLC options to start and stop at the right time. It can be
+//! shuffled, see `shuffle`.
+//!
+//! The torrent export lists the files of albums with their sizes and SHA-1, see `torrent`.
+
+use std::ffi::OsString;
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::collection;
+use crate::feed;
+use crate::i18n::tr;
+use crate::json;
+use crate::shuffle::{self, Shuffle, Weight};
+use crate::site;
+use crate::torrent;
+
+pub enum CommandExportError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    PlaylistNotFound(String),
+    CollectionNotFound(String),
+    InvalidFeedDays(String),
+    AlbumNotFound(String),
+    InvalidPieceLength(String),
+}
+impl From<rusqlite::Error> for CommandExportError {
+    fn from(err: rusqlite::Error) -> CommandExportError {
+        CommandExportError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandExportError {
+    fn from(err: io::Error) -> CommandExportError {
+        CommandExportError::IO(err)
+    }
+}
+impl fmt::Display for CommandExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandExportError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandExportError::IO(err) => f.write_str(&tr!("io-error", err = err)),
+            CommandExportError::PlaylistNotFound(value) => {
+                f.write_str(&tr!("playlist-not-found", value = value))
+            }
+            CommandExportError::CollectionNotFound(name) => {
+                f.write_str(&tr!("collection-not-found", name = name))
+            }
+            CommandExportError::InvalidFeedDays(value) => {
+                f.write_str(&tr!("export-invalid-feed-days", value = value))
+            }
+            CommandExportError::AlbumNotFound(value) => {
+                f.write_str(&tr!("export-album-not-found", value = value))
+            }
+            CommandExportError::InvalidPieceLength(value) => {
+                f.write_str(&tr!("export-invalid-piece-length", value = value))
+            }
+        }
+    }
+}
+
+/// The tables of the flat export and how to fill them.
+const FLAT_TABLES: &[(&str, &str)] = &[
+    (
+        "tracks",
+        "SELECT v_tracks.*,
+                (SELECT group_concat(label.name, ', ')
+                 FROM track_label
+                 JOIN label ON label.id = track_label.label_id
+                 WHERE track_label.track_id = v_tracks.id) AS labels
+         FROM main.v_tracks",
+    ),
+    ("albums", "SELECT * FROM main.v_albums"),
+    ("artists", "SELECT * FROM main.v_artists"),
+    (
+        "user_tracks",
+        "SELECT user.name AS user, user_track.track_id, user_track.play_count,
+                user_track.rating,
+                datetime(user_track.last_played_at, 'unixepoch') AS last_played_at,
+                datetime(user_track.starred_at, 'unixepoch') AS starred_at
+         FROM main.user_track
+         JOIN main.user ON user.id = user_track.user_id",
+    ),
+    (
+        "export",
+        "SELECT datetime('now') AS exported_at,
+                (SELECT user_version FROM main.pragma_user_version) AS schema_version",
+    ),
+];
+
+const FLAT_INDEXES: &[&str] = &[
+    "CREATE INDEX flat.tracks_artist ON tracks(artist)",
+    "CREATE INDEX flat.tracks_album ON tracks(album)",
+    "CREATE INDEX flat.tracks_genre ON tracks(genre)",
+    "CREATE INDEX flat.tracks_year ON tracks(year)",
+    "CREATE INDEX flat.user_tracks_track_id ON user_tracks(track_id)",
+];
+
+/// Fills the database attached as `flat` and returns how many tracks it has.
+fn fill_flat(db: &mut rusqlite::Connection) -> rusqlite::Result<i64> {
+    // One transaction for the whole export so every table comes from the same state
+    let savepoint = db.savepoint()?;
+
+    for (table, query) in FLAT_TABLES {
+        savepoint.execute(&format!("CREATE TABLE flat.{} AS {}", table, query), [])?;
+    }
+    for ddl in FLAT_INDEXES {
+        savepoint.execute(ddl, [])?;
+    }
+    // The views have the paths as stored, relative to the library
+    savepoint.execute("UPDATE flat.tracks SET path = library_path(path)", [])?;
+    savepoint.execute(
+        "UPDATE flat.albums SET cover_path = library_path(cover_path)",
+        [],
+    )?;
+    let tracks = savepoint.query_row("SELECT COUNT(*) FROM flat.tracks", [], |row| row.get(0))?;
+
+    savepoint.commit()?;
+
+    Ok(tracks)
+}
+
+fn write_flat(db: &mut rusqlite::Connection, path: &Path) -> Result<i64, CommandExportError> {
+    db.execute("ATTACH DATABASE $path AS flat", [path.to_string_lossy()])?;
+    let result = fill_flat(db);
+    db.execute("DETACH DATABASE flat", [])?;
+
+    Ok(result?)
+}
+
+/// Formats milliseconds as seconds for M3U.
+fn m3u_seconds(ms: i64) -> String {
+    if ms % 1000 == 0 {
+        (ms / 1000).to_string()
+    } else {
+        format!("{:.3}", ms as f64 / 1000.0)
+    }
+}
+
+fn m3u_title(artist: Option<&str>, title: Option<&str>) -> String {
+    match (artist, title) {
+        (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+        (None, Some(title)) => title.to_owned(),
+        (Some(artist), None) => artist.to_owned(),
+        (None, None) => String::new(),
+    }
+}
+
+/// A track of an M3U export.
+struct M3uTrack {
+    id: i64,
+    path: String,
+    artist: Option<String>,
+    name: Option<String>,
+}
+
+/// Builds an M3U playlist of every track, or of the playlist `playlist_id` or the collection
+/// `collection_id`, in order or shuffled.
+fn build_m3u(
+    db: &rusqlite::Connection,
+    playlist_id: Option<i64>,
+    collection_id: Option<i64>,
+    order: Option<(Shuffle, Weight)>,
+) -> Result<(String, usize), CommandExportError> {
+    if let Some(id) = playlist_id {
+        let exists: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM playlist WHERE id = $id)",
+            [id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(CommandExportError::PlaylistNotFound(id.to_string()));
+        }
+    }
+
+    let query = "
+        SELECT track.id, library_path(track.path), artist.name, track.name, track.artist_id, track.album_id,
+               (SELECT AVG(rating) FROM user_track WHERE user_track.track_id = track.id),
+               (SELECT SUM(play_count) FROM user_track WHERE user_track.track_id = track.id)
+        FROM track
+        LEFT JOIN artist ON artist.id = track.artist_id
+        LEFT JOIN album ON album.id = track.album_id
+        LEFT JOIN playlist_track
+          ON playlist_track.track_id = track.id AND playlist_track.playlist_id = $playlist_id
+        WHERE track.path IS NOT NULL
+          AND ($playlist_id IS NULL OR playlist_track.playlist_id IS NOT NULL)
+          AND ($collection_id IS NULL OR track.id IN (
+            SELECT track_id FROM v_collection_tracks WHERE collection_id = $collection_id
+          ))
+        ORDER BY playlist_track.position, artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort, track.disc_number,
+                 track.number";
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query([playlist_id, collection_id])?;
+
+    let mut tracks = Vec::new();
+    while let Some(row) = rows.next()? {
+        let track = M3uTrack {
+            id: row.get(0)?,
+            path: row.get(1)?,
+            artist: row.get(2)?,
+            name: row.get(3)?,
+        };
+        let play_count: Option<i64> = row.get(7)?;
+        tracks.push(shuffle::Entry {
+            value: track,
+            artist_id: row.get(4)?,
+            album_id: row.get(5)?,
+            rating: row.get(6)?,
+            play_count: play_count.unwrap_or(0),
+        });
+    }
+    if let Some((order, weight)) = order {
+        tracks = shuffle::shuffle(tracks, order, weight);
+    }
+
+    let mut chapters_stmt = db.prepare(
+        "SELECT start_ms, end_ms, title, artist FROM chapter WHERE track_id = $id ORDER BY number",
+    )?;
+
+    let mut buf = String::from("#EXTM3U\n");
+    let mut entries = 0;
+
+    for entry in tracks {
+        let M3uTrack {
+            id,
+            path,
+            artist,
+            name,
+        } = entry.value;
+
+        let mut chapters = chapters_stmt.query([id])?;
+        let mut has_chapters = false;
+        while let Some(chapter) = chapters.next()? {
+            let start_ms: i64 = chapter.get(0)?;
+            let end_ms: Option<i64> = chapter.get(1)?;
+            let title: Option<String> = chapter.get(2)?;
+            let chapter_artist: Option<String> = chapter.get(3)?;
+
+            let duration = end_ms.map_or("-1".to_owned(), |end_ms| {
+                ((end_ms - start_ms) / 1000).to_string()
+            });
+            let title = m3u_title(
+                chapter_artist.as_deref().or(artist.as_deref()),
+                title.as_deref(),
+            );
+
+            let _ = writeln!(buf, "#EXTINF:{},{}", duration, title);
+            let _ = writeln!(buf, "#EXTVLCOPT:start-time={}", m3u_seconds(start_ms));
+            if let Some(end_ms) = end_ms {
+                let _ = writeln!(buf, "#EXTVLCOPT:stop-time={}", m3u_seconds(end_ms));
+            }
+            let _ = writeln!(buf, "{}", path);
+
+            has_chapters = true;
+            entries += 1;
+        }
+
+        if !has_chapters {
+            let title = m3u_title(artist.as_deref(), name.as_deref());
+            let _ = writeln!(buf, "#EXTINF:-1,{}", title);
+            let _ = writeln!(buf, "{}", path);
+            entries += 1;
+        }
+    }
+
+    Ok((buf, entries))
+}
+
+//
+// "export" command
+//
+
+/// Where an export to `path` is written before being renamed.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = OsString::from(path.as_os_str());
+    tmp_path.push(".tmp");
+    PathBuf::from(tmp_path)
+}
+
+fn export_sqlite_flat(
+    db: &mut rusqlite::Connection,
+    path: &Path,
+) -> Result<(), CommandExportError> {
+    let tmp_path = tmp_path(path);
+
+    // Left over by an export that didn't finish
+    if tmp_path.exists() {
+        fs::remove_file(&tmp_path)?;
+    }
+
+    let tracks = match write_flat(db, &tmp_path) {
+        Ok(tracks) => tracks,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err);
+        }
+    };
+    fs::rename(&tmp_path, path)?;
+
+    println!(
+        "{}",
+        tr!("export-tracks", tracks = tracks, path = path.display())
+    );
+
+    Ok(())
+}
+
+fn export_m3u(
+    db: &rusqlite::Connection,
+    path: &Path,
+    playlist_id: Option<i64>,
+    collection_id: Option<i64>,
+    order: Option<(Shuffle, Weight)>,
+) -> Result<(), CommandExportError> {
+    let (content, entries) = build_m3u(db, playlist_id, collection_id, order)?;
+
+    let tmp_path = tmp_path(path);
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)?;
+
+    println!(
+        "{}",
+        tr!("export-entries", entries = entries, path = path.display())
+    );
+
+    Ok(())
+}
+
+fn export_html(
+    db: &rusqlite::Connection,
+    dir: &Path,
+    feed_days: i64,
+    base_url: Option<&str>,
+) -> Result<(), CommandExportError> {
+    let summary = site::write_site(db, dir, feed_days, base_url)?;
+
+    println!(
+        "{}",
+        tr!(
+            "export-site",
+            artists = summary.artists,
+            albums = summary.albums,
+            tracks = summary.tracks,
+            path = dir.display()
+        )
+    );
+
+    Ok(())
+}
+
+fn export_torrent(
+    db: &rusqlite::Connection,
+    path: &Path,
+    album_ids: Option<Vec<i64>>,
+    piece_length: Option<usize>,
+) -> Result<(), CommandExportError> {
+    let selected = album_ids.is_some();
+    let album_ids = match album_ids {
+        Some(album_ids) => album_ids,
+        None => {
+            let mut stmt = db.prepare(
+                "SELECT DISTINCT album_id FROM track
+                 WHERE album_id IS NOT NULL AND missing_since IS NULL
+                 ORDER BY album_id",
+            )?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<Vec<i64>>>()?
+        }
+    };
+
+    let mut listings = Vec::with_capacity(album_ids.len());
+    for album_id in album_ids {
+        match torrent::album_listing(db, album_id, piece_length)? {
+            Some(listing) => listings.push(listing),
+            // Albums in archives or remote libraries have no folder to list
+            None if selected => {
+                return Err(CommandExportError::AlbumNotFound(album_id.to_string()))
+            }
+            None => continue,
+        }
+    }
+
+    let tmp_path = tmp_path(path);
+    fs::write(&tmp_path, json::array(&listings))?;
+    fs::rename(&tmp_path, path)?;
+
+    println!(
+        "{}",
+        tr!(
+            "export-torrent",
+            albums = listings.len(),
+            path = path.display()
+        )
+    );
+
+    Ok(())
+}
+
+pub fn cmd_export(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandExportError> {
+    if let Some(path) = args.value_of("m3u") {
+        let playlist_id = match args.value_of("playlist") {
+            Some(value) => match value.parse() {
+                Ok(id) => Some(id),
+                Err(_) => return Err(CommandExportError::PlaylistNotFound(value.to_owned())),
+            },
+            None => None,
+        };
+        let collection_id = match args.value_of("collection") {
+            Some(name) => match collection::find_id(db, name)? {
+                Some(id) => Some(id),
+                None => return Err(CommandExportError::CollectionNotFound(name.to_owned())),
+            },
+            None => None,
+        };
+        let weight = args
+            .value_of("weight")
+            .and_then(Weight::parse)
+            .unwrap_or(Weight::Even);
+        let order = args
+            .value_of("shuffle")
+            .and_then(Shuffle::parse)
+            .map(|order| (order, weight));
+        return export_m3u(db, Path::new(path), playlist_id, collection_id, order);
+    }
+    if let Some(dir) = args.value_of("html") {
+        let feed_days = match args.value_of("feed-days") {
+            Some(value) => match value.parse() {
+                Ok(days) if days > 0 => days,
+                _ => return Err(CommandExportError::InvalidFeedDays(value.to_owned())),
+            },
+            None => feed::DEFAULT_DAYS,
+        };
+        return export_html(db, Path::new(dir), feed_days, args.value_of("base-url"));
+    }
+
+    if let Some(path) = args.value_of("torrent") {
+        let album_ids = match args.values_of("album") {
+            Some(values) => Some(
+                values
+                    .map(|value| {
+                        value
+                            .parse()
+                            .map_err(|_| CommandExportError::AlbumNotFound(value.to_owned()))
+                    })
+                    .collect::<Result<Vec<i64>, _>>()?,
+            ),
+            None => None,
+        };
+        // In KiB, like mktorrent
+        let piece_length = match args.value_of("piece-length") {
+            Some(value) => match value.parse::<usize>() {
+                Ok(kib) if kib >= 16 && kib.is_power_of_two() => Some(kib * 1024),
+                _ => return Err(CommandExportError::InvalidPieceLength(value.to_owned())),
+            },
+            None => None,
+        };
+        return export_torrent(db, Path::new(path), album_ids, piece_length);
+    }
+
+    export_sqlite_flat(db, Path::new(args.value_of("sqlite-flat").unwrap()))
+}
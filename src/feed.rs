@@ -0,0 +1,215 @@
+//! An Atom feed of the albums recently added to the library.
+//!
+//! The server serves it at `/feed.atom`, with the covers at `/covers/<album uid>`, both
+//! behind the same authentication as the rest of the API. The HTML export writes it next
+//! to the site, linking to the pages of the albums.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::i18n::tr;
+use crate::pool;
+use crate::server::{Request, Response};
+use crate::subsonic::{self, xml_escape};
+use crate::user;
+
+/// How far back the feed goes, unless told otherwise.
+pub const DEFAULT_DAYS: i64 = 30;
+
+pub struct Album {
+    pub id: i64,
+    pub uid: String,
+    name: String,
+    artist: Option<String>,
+    year: Option<i64>,
+    tracks: i64,
+    has_cover: bool,
+    /// When the album was added, in RFC 3339.
+    added_at: String,
+}
+
+fn escape(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    xml_escape(value, &mut buf);
+    buf
+}
+
+/// Returns the albums added in the last `days` days, the most recent first.
+pub fn recent_albums(db: &rusqlite::Connection, days: i64) -> rusqlite::Result<Vec<Album>> {
+    let mut stmt = db.prepare(
+        "SELECT album.id, album.uid, album.name, artist.name, album.release_year,
+                (SELECT COUNT(*) FROM track WHERE track.album_id = album.id),
+                strftime('%Y-%m-%dT%H:%M:%SZ', album.added_at, 'unixepoch'),
+                album.cover_path IS NOT NULL
+         FROM album
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE album.added_at >= unixepoch() - $days * 86400
+         ORDER BY album.added_at DESC, album.id DESC",
+    )?;
+    let rows = stmt.query_map([days], |row| {
+        let name: Option<String> = row.get(2)?;
+        Ok(Album {
+            id: row.get(0)?,
+            uid: row.get(1)?,
+            name: name.unwrap_or_default(),
+            artist: row.get(3)?,
+            year: row.get(4)?,
+            tracks: row.get(5)?,
+            added_at: row.get(6)?,
+            has_cover: row.get(7)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Builds the feed of `albums`.
+///
+/// `links` returns the page and the cover of an album, relative to `base` when there is one.
+pub fn atom<F>(albums: &[Album], base: Option<&str>, links: F) -> String
+where
+    F: Fn(&Album) -> (Option<String>, Option<String>),
+{
+    let updated = albums
+        .first()
+        .map_or("1970-01-01T00:00:00Z", |album| album.added_at.as_str());
+
+    let mut buf = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    let _ = write!(buf, "<feed xmlns=\"http://www.w3.org/2005/Atom\"");
+    if let Some(base) = base {
+        let _ = write!(buf, " xml:base=\"{}\"", escape(base));
+    }
+    let _ = writeln!(
+        buf,
+        ">\n<id>urn:zik:recently-added</id>\n<title>Recently added albums</title>\n<updated>{}</updated>\n<author><name>zik</name></author>",
+        updated
+    );
+
+    for album in albums {
+        let (page, cover) = links(album);
+        let title = match &album.artist {
+            Some(artist) => format!("{} - {}", artist, album.name),
+            None => album.name.clone(),
+        };
+
+        let mut content = String::new();
+        if let Some(cover) = &cover {
+            let _ = write!(content, "<p><img src=\"{}\" alt=\"\"></p>", escape(cover));
+        }
+        let details: Vec<String> = [
+            album.year.map(|year| year.to_string()),
+            Some(format!("{} tracks", album.tracks)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let _ = write!(content, "<p>{}</p>", details.join(", "));
+
+        let _ = writeln!(
+            buf,
+            "<entry>\n<id>urn:uuid:{}</id>\n<title>{}</title>\n<updated>{}</updated>",
+            album.uid,
+            escape(&title),
+            album.added_at
+        );
+        if let Some(page) = page {
+            let _ = writeln!(
+                buf,
+                "<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>",
+                escape(&page)
+            );
+        }
+        let _ = writeln!(
+            buf,
+            "<content type=\"html\">{}</content>\n</entry>",
+            escape(&content)
+        );
+    }
+
+    buf.push_str("</feed>\n");
+    buf
+}
+
+//
+// HTTP handlers
+//
+
+/// Serves the feed, or the cover of an album at `cover`.
+pub fn handle(cover: Option<&str>, request: &Request) -> Response {
+    let db = match pool::get() {
+        Ok(db) => db,
+        Err(err) => {
+            println!("{}", tr!("feed-connection", err = err));
+            return pool::error_response(&err);
+        }
+    };
+
+    match user::authenticate(&db, request) {
+        Ok(_) => (),
+        Err(err @ (user::AuthError::SQLite(_) | user::AuthError::Key(_))) => {
+            println!("{}", tr!("feed-auth", err = err));
+            return Response::text(500, "internal error");
+        }
+        Err(err) => {
+            return Response::text(401, &err.to_string())
+                .header("WWW-Authenticate", "Basic realm=\"zik\"")
+        }
+    }
+
+    let result = match cover {
+        Some(uid) => serve_cover(&db, uid),
+        None => serve_feed(&db, request),
+    };
+    match result {
+        Ok(response) => response,
+        Err(err) => {
+            println!("{}", tr!("feed-serve", path = request.path, err = err));
+            Response::text(500, "internal error")
+        }
+    }
+}
+
+fn serve_feed(db: &rusqlite::Connection, request: &Request) -> rusqlite::Result<Response> {
+    let days = match request.param("days").map(|value| value.parse::<i64>()) {
+        Some(Ok(days)) if days > 0 => days,
+        Some(_) => return Ok(Response::text(400, "invalid days")),
+        None => DEFAULT_DAYS,
+    };
+
+    let albums = recent_albums(db, days)?;
+    let base = request
+        .header("Host")
+        .map(|host| format!("http://{}/", host));
+    let feed = atom(&albums, base.as_deref(), |album| {
+        let cover = Some(format!("covers/{}", album.uid)).filter(|_| album.has_cover);
+        (None, cover)
+    });
+
+    Ok(Response::new(
+        200,
+        "application/atom+xml; charset=utf-8",
+        feed.into_bytes(),
+    ))
+}
+
+fn serve_cover(db: &rusqlite::Connection, uid: &str) -> rusqlite::Result<Response> {
+    let path: Option<String> = match db.query_row(
+        "SELECT library_path(cover_path) FROM album WHERE uid = $uid",
+        [uid],
+        |row| row.get(0),
+    ) {
+        Ok(path) => path,
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(err) => return Err(err),
+    };
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(Response::not_found()),
+    };
+    match fs::read(&path) {
+        Ok(data) => Ok(Response::new(200, subsonic::content_type_for(&path), data)),
+        Err(_) => Ok(Response::not_found()),
+    }
+}
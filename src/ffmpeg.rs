@@ -0,0 +1,375 @@
+//! Audio analysis and transcoding, delegated to the `ffmpeg` binary.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use crate::gapless;
+use crate::i18n::tr;
+
+pub enum FfmpegError {
+    NotFound,
+    IO(io::Error),
+    Failed(String),
+    UnknownFormat(String),
+}
+impl From<io::Error> for FfmpegError {
+    fn from(err: io::Error) -> FfmpegError {
+        FfmpegError::IO(err)
+    }
+}
+impl fmt::Display for FfmpegError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FfmpegError::NotFound => f.write_str(&tr!("ffmpeg-not-found")),
+            FfmpegError::IO(err) => f.write_str(&tr!("ffmpeg-io", err = err)),
+            FfmpegError::Failed(err) => f.write_str(&tr!("ffmpeg-failed", err = err)),
+            FfmpegError::UnknownFormat(format) => {
+                f.write_str(&tr!("ffmpeg-unknown-format", format = format))
+            }
+        }
+    }
+}
+
+/// The transcode formats, with the ffmpeg encoder and file extension used for each.
+const FORMATS: [(&str, &str, &str); 4] = [
+    ("opus", "libopus", "opus"),
+    ("mp3", "libmp3lame", "mp3"),
+    ("aac", "aac", "m4a"),
+    ("vorbis", "libvorbis", "ogg"),
+];
+
+/// The ffmpeg muxer and content type used when a format is streamed through a pipe.
+///
+/// MP4 needs to seek back into its output, so AAC is streamed as raw ADTS frames.
+const STREAM_FORMATS: [(&str, &str, &str); 4] = [
+    ("opus", "opus", "audio/ogg; codecs=opus"),
+    ("mp3", "mp3", "audio/mpeg"),
+    ("aac", "adts", "audio/aac"),
+    ("vorbis", "ogg", "audio/ogg"),
+];
+
+/// Returns the content type of a transcode format when streamed.
+pub fn stream_content_type(format: &str) -> Option<&'static str> {
+    STREAM_FORMATS
+        .iter()
+        .find(|(name, _, _)| *name == format)
+        .map(|(_, _, content_type)| *content_type)
+}
+
+/// Returns the ffmpeg encoder and file extension of a transcode format.
+pub fn codec_for(format: &str) -> Option<(&'static str, &'static str)> {
+    FORMATS
+        .iter()
+        .find(|(name, _, _)| *name == format)
+        .map(|(_, codec, extension)| (*codec, *extension))
+}
+
+fn run(args: &[&std::ffi::OsStr]) -> Result<process::Output, FfmpegError> {
+    let output = match process::Command::new("ffmpeg")
+        .arg("-hide_banner")
+        .arg("-nostdin")
+        .args(args)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(FfmpegError::NotFound),
+        Err(err) => return Err(FfmpegError::IO(err)),
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().last().unwrap_or_default();
+        return Err(FfmpegError::Failed(last_line.to_owned()));
+    }
+
+    Ok(output)
+}
+
+/// Measures the EBU R128 integrated loudness of a file, in LUFS.
+pub fn integrated_loudness(path: &Path) -> Result<f64, FfmpegError> {
+    let output = run(&[
+        "-nostats".as_ref(),
+        "-i".as_ref(),
+        path.as_os_str(),
+        "-af".as_ref(),
+        "ebur128".as_ref(),
+        "-f".as_ref(),
+        "null".as_ref(),
+        "-".as_ref(),
+    ])?;
+
+    // The summary printed at the end contains a line like "    I:         -14.2 LUFS"
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let loudness = stderr
+        .lines()
+        .rev()
+        .filter_map(|line| line.trim().strip_prefix("I:"))
+        .find_map(|value| value.trim().trim_end_matches("LUFS").trim().parse().ok());
+
+    match loudness {
+        Some(loudness) => Ok(loudness),
+        None => Err(FfmpegError::Failed("no loudness in output".to_owned())),
+    }
+}
+
+/// Decodes the whole of `input`, failing with the first error of the decoder. With `md5_bits`,
+/// returns the MD5 of the samples as signed little-endian integers of that many bits, the way
+/// the STREAMINFO of a FLAC file has it.
+pub fn decode_check(input: &Path, md5_bits: Option<usize>) -> Result<Option<String>, FfmpegError> {
+    let codec = md5_bits.map(|bits| match bits {
+        8 => "pcm_s8".to_owned(),
+        bits => format!("pcm_s{}le", bits),
+    });
+
+    let mut args: Vec<&std::ffi::OsStr> = vec![
+        "-v".as_ref(),
+        "error".as_ref(),
+        "-i".as_ref(),
+        input.as_os_str(),
+        "-map".as_ref(),
+        "0:a".as_ref(),
+    ];
+    match &codec {
+        Some(codec) => args.extend::<[&std::ffi::OsStr; 6]>([
+            "-c:a".as_ref(),
+            codec.as_ref(),
+            "-f".as_ref(),
+            "hash".as_ref(),
+            "-hash".as_ref(),
+            "md5".as_ref(),
+        ]),
+        None => args.extend::<[&std::ffi::OsStr; 2]>(["-f".as_ref(), "null".as_ref()]),
+    }
+    args.push("-".as_ref());
+    let output = run(&args)?;
+
+    // Errors of the decoder are only logged, the decoding goes on
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if let Some(line) = stderr.lines().find(|line| !line.trim().is_empty()) {
+        return Err(FfmpegError::Failed(line.trim().to_owned()));
+    }
+
+    if codec.is_none() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("MD5="))
+    {
+        Some(md5) => Ok(Some(md5.to_owned())),
+        None => Err(FfmpegError::Failed("no MD5 in output".to_owned())),
+    }
+}
+
+/// Transcodes `input` into `output`, which is only created once the transcode succeeded.
+pub fn transcode(
+    input: &Path,
+    output: &Path,
+    format: &str,
+    bitrate: usize,
+) -> Result<(), FfmpegError> {
+    let (codec, extension) = match codec_for(format) {
+        Some(codec) => codec,
+        None => return Err(FfmpegError::UnknownFormat(format.to_owned())),
+    };
+
+    let tmp_output = output.with_extension(format!("tmp.{}", extension));
+    let bitrate = format!("{}k", bitrate);
+
+    let result = run(&[
+        "-y".as_ref(),
+        "-i".as_ref(),
+        input.as_os_str(),
+        "-map".as_ref(),
+        "0:a".as_ref(),
+        "-c:a".as_ref(),
+        codec.as_ref(),
+        "-b:a".as_ref(),
+        bitrate.as_ref(),
+        tmp_output.as_os_str(),
+    ]);
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_output);
+        return Err(err);
+    }
+
+    fs::rename(&tmp_output, output)?;
+
+    Ok(())
+}
+
+/// Encodes `input` into the FLAC file `output`, which is only created once the encode
+/// succeeded.
+pub fn encode_flac(input: &Path, output: &Path) -> Result<(), FfmpegError> {
+    let tmp_output = output.with_extension("tmp.flac");
+
+    let result = run(&[
+        "-y".as_ref(),
+        "-i".as_ref(),
+        input.as_os_str(),
+        "-map".as_ref(),
+        "0:a".as_ref(),
+        "-c:a".as_ref(),
+        "flac".as_ref(),
+        "-compression_level".as_ref(),
+        "8".as_ref(),
+        tmp_output.as_os_str(),
+    ]);
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_output);
+        return Err(err);
+    }
+
+    fs::rename(&tmp_output, output)?;
+
+    Ok(())
+}
+
+/// Scales the image `input` down to fit in a `size` pixels square, as a JPEG.
+pub fn thumbnail(input: &Path, output: &Path, size: usize) -> Result<(), FfmpegError> {
+    let tmp_output = output.with_extension("tmp.jpg");
+    let scale = format!("scale={0}:{0}:force_original_aspect_ratio=decrease", size);
+
+    let result = run(&[
+        "-y".as_ref(),
+        "-i".as_ref(),
+        input.as_os_str(),
+        "-vf".as_ref(),
+        scale.as_ref(),
+        "-frames:v".as_ref(),
+        "1".as_ref(),
+        tmp_output.as_os_str(),
+    ]);
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_output);
+        return Err(err);
+    }
+
+    fs::rename(&tmp_output, output)?;
+
+    Ok(())
+}
+
+/// The peak a normalized transcode is limited to, -1 dBFS, so a gain above 0 dB doesn't clip.
+const GAIN_LIMIT: f64 = 0.891;
+
+/// Returns the filters changing the volume by `gain_db`, with a limiter against clipping.
+fn gain_filter(gain_db: f64) -> String {
+    // The limiter mustn't level the volume back up itself
+    format!(
+        "volume={:.2}dB,alimiter=limit={}:level=0",
+        gain_db, GAIN_LIMIT
+    )
+}
+
+/// Starts transcoding `input`, the result being written to the standard output of the
+/// returned process. `gain_db` changes its volume, with a limiter against clipping.
+pub fn transcode_stream(
+    input: &Path,
+    format: &str,
+    bitrate: usize,
+    gain_db: Option<f64>,
+) -> Result<process::Child, FfmpegError> {
+    let (codec, muxer) = match (
+        codec_for(format),
+        STREAM_FORMATS.iter().find(|(name, _, _)| *name == format),
+    ) {
+        (Some((codec, _)), Some((_, muxer, _))) => (codec, *muxer),
+        _ => return Err(FfmpegError::UnknownFormat(format.to_owned())),
+    };
+
+    let mut command = process::Command::new("ffmpeg");
+    command
+        .arg("-hide_banner")
+        .arg("-nostdin")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-i")
+        .arg(input);
+    if let Some(gain_db) = gain_db {
+        command.arg("-af").arg(gain_filter(gain_db));
+    }
+    let child = command
+        .args(["-map", "0:a", "-c:a", codec, "-b:a"])
+        .arg(format!("{}k", bitrate))
+        .args(["-f", muxer, "-"])
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null())
+        .spawn();
+
+    match child {
+        Ok(child) => Ok(child),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(FfmpegError::NotFound),
+        Err(err) => Err(FfmpegError::IO(err)),
+    }
+}
+
+/// Starts decoding `input` into raw little-endian PCM, written to the standard output of the
+/// returned process at the pace it's read. `trim` cuts its encoder delay and padding.
+pub fn decode_pcm(
+    input: &Path,
+    rate: usize,
+    bits: usize,
+    channels: usize,
+    gain_db: Option<f64>,
+    trim: Option<&gapless::Trim>,
+) -> Result<process::Child, FfmpegError> {
+    let mut command = process::Command::new("ffmpeg");
+    command
+        .arg("-hide_banner")
+        .arg("-nostdin")
+        .arg("-loglevel")
+        .arg("error");
+    if let Some(trim) = trim {
+        // Left to atrim, the decoder and the MP4 demuxer would cut what they know of it
+        command.args(["-flags2", "+skip_manual"]);
+        if trim.edit_list {
+            command.args(["-ignore_editlist", "1"]);
+        }
+    }
+    command.arg("-i").arg(input);
+
+    let mut filters = Vec::new();
+    if let Some(trim) = trim {
+        filters.push(format!(
+            "atrim=start_sample={}:end_sample={},asetpts=N/SR/TB",
+            trim.start, trim.end
+        ));
+    }
+    if let Some(gain_db) = gain_db {
+        filters.push(gain_filter(gain_db));
+    }
+    if !filters.is_empty() {
+        command.arg("-af").arg(filters.join(","));
+    }
+    let child = command
+        .args(["-map", "0:a", "-f"])
+        .arg(format!("s{}le", bits))
+        .arg("-ar")
+        .arg(rate.to_string())
+        .arg("-ac")
+        .arg(channels.to_string())
+        .arg("-")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .spawn();
+
+    match child {
+        Ok(child) => Ok(child),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(FfmpegError::NotFound),
+        Err(err) => Err(FfmpegError::IO(err)),
+    }
+}
+
+/// Waits for a process started by `decode_pcm`, which fails if ffmpeg did.
+pub fn wait(child: &mut process::Child) -> Result<(), FfmpegError> {
+    match child.wait()? {
+        status if status.success() => Ok(()),
+        status => Err(FfmpegError::Failed(status.to_string())),
+    }
+}
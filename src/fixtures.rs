@@ -0,0 +1,367 @@
+//! Tiny but valid audio files, for the integration tests, the benchmarks and
+//! `zik bench generate`.
+//!
+//! They have tags and no audio, which is all a scan reads. `with_audio` pads them to the size
+//! of real files, for measuring what a scan reads of them. The tests and benchmarks include
+//! this file with `#[path]`, so it must not depend on the rest of the crate.
+
+fn block_header(last: bool, block_type: u8, length: usize) -> Vec<u8> {
+    let length = (length as u32).to_be_bytes();
+    vec![
+        (last as u8) << 7 | block_type,
+        length[1],
+        length[2],
+        length[3],
+    ]
+}
+
+/// A FLAC file with no audio frames: a STREAMINFO block for `duration_ms` at 44.1kHz and a
+/// VORBIS_COMMENT block with `comments`.
+pub fn flac(duration_ms: u64, comments: &[(&str, &str)]) -> Vec<u8> {
+    let sample_rate: u64 = 44100;
+    let total_samples = sample_rate * duration_ms / 1000;
+
+    let mut streaminfo = Vec::new();
+    streaminfo.extend_from_slice(&4096u16.to_be_bytes());
+    streaminfo.extend_from_slice(&4096u16.to_be_bytes());
+    streaminfo.extend_from_slice(&[0; 6]);
+    // Sample rate on 20 bits, channels - 1 on 3, bits per sample - 1 on 5, samples on 36
+    let packed = sample_rate << 44 | 1 << 41 | 15 << 36 | total_samples;
+    streaminfo.extend_from_slice(&packed.to_be_bytes());
+    streaminfo.extend_from_slice(&[0; 16]);
+
+    let vendor = b"zik";
+    let mut vorbis_comment = Vec::new();
+    vorbis_comment.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    vorbis_comment.extend_from_slice(vendor);
+    vorbis_comment.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (key, value) in comments {
+        let comment = format!("{}={}", key, value);
+        vorbis_comment.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        vorbis_comment.extend_from_slice(comment.as_bytes());
+    }
+
+    let mut data = b"fLaC".to_vec();
+    data.extend(block_header(false, 0, streaminfo.len()));
+    data.extend(streaminfo);
+    data.extend(block_header(true, 4, vorbis_comment.len()));
+    data.extend(vorbis_comment);
+    data
+}
+
+/// An MP3 file made of an ID3v2.3 tag with the text `frames` and one silent MPEG frame.
+pub fn mp3(frames: &[(&str, &str)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (id, value) in frames {
+        // Latin-1 encoding
+        let content = [&[0u8], value.as_bytes()].concat();
+        body.extend_from_slice(id.as_bytes());
+        body.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        body.extend_from_slice(&[0, 0]);
+        body.extend(content);
+    }
+
+    // The size of the tag is synchsafe, 7 bits per byte
+    let size = body.len() as u32;
+    let mut data = b"ID3\x03\x00\x00".to_vec();
+    data.extend_from_slice(&[
+        (size >> 21 & 0x7f) as u8,
+        (size >> 14 & 0x7f) as u8,
+        (size >> 7 & 0x7f) as u8,
+        (size & 0x7f) as u8,
+    ]);
+    data.extend(body);
+
+    // MPEG-1 Layer III, 128kbps, 44.1kHz: 417 bytes per frame
+    data.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+    data.extend_from_slice(&[0; 413]);
+    data
+}
+
+/// The CRC-32 of Ogg pages, MSB first with the polynomial 0x04c11db7.
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                crc << 1 ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Appends the pages carrying `packet` to `data`, `flags` marks the first or last packet of
+/// the stream.
+fn ogg_packet(data: &mut Vec<u8>, sequence: &mut u32, packet: &[u8], granule: u64, flags: u8) {
+    // A packet is cut in 255 bytes segments, the last one shorter even if empty
+    let mut segments = vec![255u8; packet.len() / 255];
+    segments.push((packet.len() % 255) as u8);
+
+    let mut offset = 0;
+    let pages: Vec<&[u8]> = segments.chunks(255).collect();
+    for (i, page_segments) in pages.iter().enumerate() {
+        let len: usize = page_segments.iter().map(|&len| len as usize).sum();
+        let last = i == pages.len() - 1;
+
+        let mut page = b"OggS\x00".to_vec();
+        // Continued packet, first page of the stream, last page of the stream
+        let continued = if i > 0 { 0x01 } else { 0 };
+        let first = if i == 0 { flags & 0x02 } else { 0 };
+        let end = if last { flags & 0x04 } else { 0 };
+        page.push(continued | first | end);
+        // Pages where no packet ends have no granule position
+        let granule = if last { granule } else { u64::MAX };
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&1u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(page_segments.len() as u8);
+        page.extend_from_slice(page_segments);
+        page.extend_from_slice(&packet[offset..offset + len]);
+        offset += len;
+
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        data.extend(page);
+        *sequence += 1;
+    }
+}
+
+/// An Ogg Vorbis file with no audio packets: the identification header for `duration_ms` at
+/// 44.1kHz, the comment header with `comments` and an empty last page.
+pub fn ogg(duration_ms: u64, comments: &[(&str, &str)]) -> Vec<u8> {
+    let sample_rate: u32 = 44100;
+    let total_samples = sample_rate as u64 * duration_ms / 1000;
+
+    let mut identification = b"\x01vorbis".to_vec();
+    identification.extend_from_slice(&0u32.to_le_bytes());
+    identification.push(2);
+    identification.extend_from_slice(&sample_rate.to_le_bytes());
+    identification.extend_from_slice(&[0; 12]);
+    // Block sizes of 256 and 2048 samples, then the framing bit
+    identification.extend_from_slice(&[0xb8, 0x01]);
+
+    let vendor = b"zik";
+    let mut comment = b"\x03vorbis".to_vec();
+    comment.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    comment.extend_from_slice(vendor);
+    comment.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for (key, value) in comments {
+        let field = format!("{}={}", key, value);
+        comment.extend_from_slice(&(field.len() as u32).to_le_bytes());
+        comment.extend_from_slice(field.as_bytes());
+    }
+    comment.push(0x01);
+
+    let mut data = Vec::new();
+    let mut sequence = 0;
+    ogg_packet(&mut data, &mut sequence, &identification, 0, 0x02);
+    ogg_packet(&mut data, &mut sequence, &comment, 0, 0);
+    ogg_packet(&mut data, &mut sequence, &[], total_samples, 0x04);
+    data
+}
+
+/// An MP4 atom of `kind` around `content`.
+fn atom(kind: &[u8], content: &[u8]) -> Vec<u8> {
+    let mut atom = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(content);
+    atom
+}
+
+/// An atom with a version and flags first.
+fn full_atom(kind: &[u8], flags: u32, content: &[u8]) -> Vec<u8> {
+    atom(kind, &[&flags.to_be_bytes()[..], content].concat())
+}
+
+/// The `ilst` item of `key`, `©` as its Latin-1 byte like iTunes. Track and disc numbers are
+/// written "3/12", freeform keys "----:mean:name".
+fn mp4_item(key: &str, value: &str) -> Vec<u8> {
+    if let Some(freeform) = key.strip_prefix("----:") {
+        let (mean, name) = freeform
+            .split_once(':')
+            .unwrap_or(("com.apple.iTunes", freeform));
+        let content = [
+            full_atom(b"mean", 0, mean.as_bytes()),
+            full_atom(b"name", 0, name.as_bytes()),
+            atom(
+                b"data",
+                &[&1u32.to_be_bytes()[..], &[0; 4], value.as_bytes()].concat(),
+            ),
+        ];
+        return atom(b"----", &content.concat());
+    }
+
+    let kind: Vec<u8> = key.chars().map(|c| c as u32 as u8).collect();
+    let data = if key == "trkn" || key == "disk" {
+        let (number, total) = value.split_once('/').unwrap_or((value, "0"));
+        let number: u16 = number.parse().unwrap_or(0);
+        let total: u16 = total.parse().unwrap_or(0);
+        // Binary data: padding, number, total, padding
+        let pair = [[0; 2], number.to_be_bytes(), total.to_be_bytes(), [0; 2]].concat();
+        [&0u32.to_be_bytes()[..], &[0; 4], &pair].concat()
+    } else {
+        // UTF-8 text
+        [&1u32.to_be_bytes()[..], &[0; 4], value.as_bytes()].concat()
+    };
+    atom(&kind, &atom(b"data", &data))
+}
+
+/// An M4A file with no audio samples: an AAC track of `duration_ms` at 44.1kHz and the iTunes
+/// metadata `items`, keyed like "©nam" or "trkn".
+pub fn mp4(duration_ms: u64, items: &[(&str, &str)]) -> Vec<u8> {
+    let sample_rate: u32 = 44100;
+    let duration = (sample_rate as u64 * duration_ms / 1000) as u32;
+    let matrix: Vec<u8> = [0x10000u32, 0, 0, 0, 0x10000, 0, 0, 0, 0x4000_0000]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect();
+
+    let ftyp = atom(b"ftyp", b"M4A \x00\x00\x00\x00M4A isommp42");
+
+    let mut mvhd = Vec::new();
+    mvhd.extend_from_slice(&[0; 8]);
+    mvhd.extend_from_slice(&sample_rate.to_be_bytes());
+    mvhd.extend_from_slice(&duration.to_be_bytes());
+    mvhd.extend_from_slice(&0x10000u32.to_be_bytes());
+    mvhd.extend_from_slice(&0x100u16.to_be_bytes());
+    mvhd.extend_from_slice(&[0; 10]);
+    mvhd.extend_from_slice(&matrix);
+    mvhd.extend_from_slice(&[0; 24]);
+    mvhd.extend_from_slice(&2u32.to_be_bytes());
+
+    let mut tkhd = Vec::new();
+    tkhd.extend_from_slice(&[0; 8]);
+    tkhd.extend_from_slice(&1u32.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 4]);
+    tkhd.extend_from_slice(&duration.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 12]);
+    tkhd.extend_from_slice(&0x100u16.to_be_bytes());
+    tkhd.extend_from_slice(&[0; 2]);
+    tkhd.extend_from_slice(&matrix);
+    tkhd.extend_from_slice(&[0; 8]);
+
+    let mut mdhd = Vec::new();
+    mdhd.extend_from_slice(&[0; 8]);
+    mdhd.extend_from_slice(&sample_rate.to_be_bytes());
+    mdhd.extend_from_slice(&duration.to_be_bytes());
+    // "und" packed on 5 bits per letter
+    mdhd.extend_from_slice(&0x55c4u16.to_be_bytes());
+    mdhd.extend_from_slice(&[0; 2]);
+
+    // AAC LC, 44.1kHz, stereo
+    let decoder_specific = [0x05, 0x02, 0x12, 0x10];
+    let decoder_config = [
+        &[0x04, 13 + decoder_specific.len() as u8, 0x40, 0x15][..],
+        &[0; 11],
+        &decoder_specific,
+    ]
+    .concat();
+    let es = [
+        &[0x03, 3 + decoder_config.len() as u8 + 3, 0, 1, 0][..],
+        &decoder_config,
+        &[0x06, 0x01, 0x02],
+    ]
+    .concat();
+
+    let mut mp4a = Vec::new();
+    mp4a.extend_from_slice(&[0; 6]);
+    mp4a.extend_from_slice(&1u16.to_be_bytes());
+    mp4a.extend_from_slice(&[0; 8]);
+    mp4a.extend_from_slice(&2u16.to_be_bytes());
+    mp4a.extend_from_slice(&16u16.to_be_bytes());
+    mp4a.extend_from_slice(&[0; 4]);
+    mp4a.extend_from_slice(&(sample_rate << 16).to_be_bytes());
+    mp4a.extend(full_atom(b"esds", 0, &es));
+
+    let empty_table = 0u32.to_be_bytes();
+    let stbl = [
+        full_atom(
+            b"stsd",
+            0,
+            &[&1u32.to_be_bytes()[..], &atom(b"mp4a", &mp4a)].concat(),
+        ),
+        full_atom(b"stts", 0, &empty_table),
+        full_atom(b"stsc", 0, &empty_table),
+        full_atom(b"stsz", 0, &[0; 8]),
+        full_atom(b"stco", 0, &empty_table),
+    ];
+    let dref = [&1u32.to_be_bytes()[..], &full_atom(b"url ", 1, &[])].concat();
+    let minf = [
+        full_atom(b"smhd", 0, &[0; 4]),
+        atom(b"dinf", &full_atom(b"dref", 0, &dref)),
+        atom(b"stbl", &stbl.concat()),
+    ];
+    let mdia = [
+        full_atom(b"mdhd", 0, &mdhd),
+        full_atom(b"hdlr", 0, &[&[0; 4][..], b"soun", &[0; 13]].concat()),
+        atom(b"minf", &minf.concat()),
+    ];
+    let trak = [full_atom(b"tkhd", 7, &tkhd), atom(b"mdia", &mdia.concat())];
+
+    let ilst: Vec<u8> = items
+        .iter()
+        .flat_map(|(key, value)| mp4_item(key, value))
+        .collect();
+    let meta = [
+        full_atom(b"hdlr", 0, &[&[0; 4][..], b"mdirappl", &[0; 9]].concat()),
+        atom(b"ilst", &ilst),
+    ];
+    let udta = atom(b"udta", &full_atom(b"meta", 0, &meta.concat()));
+
+    let moov = [
+        full_atom(b"mvhd", 0, &mvhd),
+        atom(b"trak", &trak.concat()),
+        udta,
+    ];
+
+    let mut data = ftyp;
+    data.extend(atom(b"moov", &moov.concat()));
+    data.extend(atom(b"mdat", &[]));
+    data
+}
+
+/// Adds `audio` bytes of silence to a file made by the functions above, where its format has
+/// the audio: after the tags of FLAC and MP3 files, in pages before the last one of Ogg files,
+/// and in an `mdat` before `moov` in MP4 files, like ffmpeg writes them.
+pub fn with_audio(mut data: Vec<u8>, audio: usize) -> Vec<u8> {
+    if audio == 0 {
+        return data;
+    }
+
+    if data.starts_with(b"OggS") {
+        let start = data.windows(4).rposition(|magic| magic == b"OggS").unwrap();
+        let mut last = data.split_off(start);
+        let granule = u64::from_le_bytes(last[6..14].try_into().unwrap());
+        let mut sequence = u32::from_le_bytes(last[18..22].try_into().unwrap());
+        ogg_packet(&mut data, &mut sequence, &vec![0; audio], granule, 0);
+
+        // The last page comes after the audio ones
+        last[18..22].copy_from_slice(&sequence.to_le_bytes());
+        last[22..26].copy_from_slice(&[0; 4]);
+        let crc = ogg_crc(&last);
+        last[22..26].copy_from_slice(&crc.to_le_bytes());
+        data.extend(last);
+    } else if data.get(4..8) == Some(b"ftyp") {
+        let ftyp_len = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        // Without the empty mdat at the end
+        data.truncate(data.len() - 8);
+        let moov = data.split_off(ftyp_len);
+        data.extend(atom(b"mdat", &vec![0; audio]));
+        data.extend(moov);
+    } else if data.starts_with(b"ID3") {
+        // More of the silent MPEG frame
+        let frame = data[data.len() - 417..].to_vec();
+        while data.len() < audio {
+            data.extend_from_slice(&frame);
+        }
+    } else {
+        data.resize(data.len() + audio, 0);
+    }
+
+    data
+}
@@ -0,0 +1,222 @@
+//! The encoder delay and padding of lossy files, the silence encoders add before and after the
+//! audio, which `zik play` cuts so the tracks of an album follow each other without a gap.
+//!
+//! MP3 encoders write them in the LAME tag, after the Xing or Info header of the first frame.
+//! AAC encoders write an `iTunSMPB` comment, " 00000000 00000840 000001CA 0000000000FFD2F6 ...":
+//! the delay, the padding and the length of the audio in samples, in hexadecimal. Other
+//! formats have none, or their decoder leaves nothing to cut.
+
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use crate::mp4meta;
+
+/// The samples an MP3 decoder outputs before the first one encoded, which the LAME delay
+/// doesn't count.
+const MP3_DECODER_DELAY: u64 = 529;
+
+/// How much of an MP3 file is read to find its first frame after the ID3v2 tag.
+const MP3_FRAME_SEARCH: usize = 8192;
+
+/// Where the audio of a file is, in the samples its decoder outputs when it cuts nothing.
+#[derive(Debug, PartialEq)]
+pub struct Trim {
+    /// The first sample of the audio.
+    pub start: u64,
+    /// The sample after the last one.
+    pub end: u64,
+    /// Whether the file is MP4, whose edit list can cut the delay already.
+    pub edit_list: bool,
+}
+
+/// Returns where the audio of `path` is, none if its format or its encoder doesn't say.
+pub fn read(path: &Path) -> Option<Trim> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "mp3" => read_mp3(path).ok().flatten(),
+        "m4a" | "m4b" | "mp4" => {
+            let mut file = fs::File::open(path).ok()?;
+            let tags = mp4meta::read_freeform(&mut file).ok()?;
+            parse_itunsmpb(tags.get(&["iTunSMPB"])?)
+        }
+        _ => None,
+    }
+}
+
+fn read_mp3(path: &Path) -> io::Result<Option<Trim>> {
+    let mut file = fs::File::open(path)?;
+
+    let mut header = [0; 10];
+    file.read_exact(&mut header)?;
+    if &header[..3] == b"ID3" {
+        let size = header[6..10]
+            .iter()
+            .fold(0, |size, &byte| (size << 7) | (byte & 0x7f) as u64);
+        let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+        file.seek(io::SeekFrom::Start(10 + size + footer))?;
+    } else {
+        file.rewind()?;
+    }
+
+    let mut data = Vec::with_capacity(MP3_FRAME_SEARCH);
+    file.take(MP3_FRAME_SEARCH as u64).read_to_end(&mut data)?;
+
+    Ok((0..data.len().saturating_sub(4))
+        .find(|&i| data[i] == 0xff && data[i + 1] & 0xe0 == 0xe0)
+        .and_then(|i| parse_lame(&data[i..])))
+}
+
+/// Parses the LAME tag of `frame`, the first frame of an MP3 file.
+fn parse_lame(frame: &[u8]) -> Option<Trim> {
+    let mpeg1 = frame.get(1)? & 0x18 == 0x18;
+    let layer3 = frame[1] & 0x06 == 0x02;
+    let mono = frame.get(3)? & 0xc0 == 0xc0;
+    if !layer3 {
+        return None;
+    }
+
+    let side_info = match (mpeg1, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = 4 + side_info;
+    if !matches!(frame.get(xing..xing + 4)?, b"Xing" | b"Info") {
+        return None;
+    }
+
+    let flags = u32::from_be_bytes(frame.get(xing + 4..xing + 8)?.try_into().ok()?);
+    // Without the frame count the end isn't known
+    if flags & 0x1 == 0 {
+        return None;
+    }
+    let frames = u32::from_be_bytes(frame.get(xing + 8..xing + 12)?.try_into().ok()?) as u64;
+    let mut i = xing + 12;
+    for (flag, size) in [(0x2, 4), (0x4, 100), (0x8, 4)] {
+        if flags & flag != 0 {
+            i += size;
+        }
+    }
+
+    // The tag starts with the name of the encoder, "LAME3.100", "Lavc58.91"...
+    let encoder = frame.get(i..i + 4)?;
+    if !encoder.iter().all(u8::is_ascii_alphanumeric) {
+        return None;
+    }
+    let bytes = frame.get(i + 21..i + 24)?;
+    let delay = ((bytes[0] as u64) << 4) | (bytes[1] as u64 >> 4);
+    let padding = ((bytes[1] as u64 & 0x0f) << 8) | bytes[2] as u64;
+
+    let samples_per_frame = if mpeg1 { 1152 } else { 576 };
+    let start = delay + MP3_DECODER_DELAY;
+    // The decoder delay pushes the end of the padding past the last frame
+    let end =
+        (frames * samples_per_frame).checked_sub(padding.saturating_sub(MP3_DECODER_DELAY))?;
+    if start >= end {
+        return None;
+    }
+
+    Some(Trim {
+        start,
+        end,
+        edit_list: false,
+    })
+}
+
+/// Parses an `iTunSMPB` comment.
+fn parse_itunsmpb(value: &str) -> Option<Trim> {
+    let mut fields = value
+        .split_whitespace()
+        .map(|field| u64::from_str_radix(field, 16));
+    let delay = fields.nth(1)?.ok()?;
+    let _padding = fields.next()?.ok()?;
+    let length = fields.next()?.ok()?;
+    if length == 0 {
+        return None;
+    }
+
+    Some(Trim {
+        start: delay,
+        end: delay + length,
+        edit_list: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the first frame of an MP3 file, with a Xing header and a LAME tag.
+    fn lame_frame(
+        header: [u8; 4],
+        side_info: usize,
+        frames: u32,
+        delay_padding: [u8; 3],
+    ) -> Vec<u8> {
+        let mut frame = header.to_vec();
+        frame.extend(vec![0; side_info]);
+        frame.extend(b"Info");
+        frame.extend(0x0fu32.to_be_bytes());
+        frame.extend(frames.to_be_bytes());
+        frame.extend([0; 4 + 100 + 4]);
+        frame.extend(b"LAME3.100");
+        frame.extend([0; 12]);
+        frame.extend(delay_padding);
+        frame.extend([0; 64]);
+        frame
+    }
+
+    #[test]
+    fn lame_tag() {
+        // MPEG 1 layer III joint stereo, 576 samples of delay and 1324 of padding
+        let frame = lame_frame([0xff, 0xfb, 0x90, 0x64], 32, 100, [0x24, 0x05, 0x2c]);
+        assert_eq!(
+            parse_lame(&frame),
+            Some(Trim {
+                start: 576 + 529,
+                end: 100 * 1152 - (1324 - 529),
+                edit_list: false,
+            })
+        );
+
+        // MPEG 2 mono
+        let frame = lame_frame([0xff, 0xf3, 0x90, 0xc4], 9, 100, [0x24, 0x05, 0x2c]);
+        assert_eq!(parse_lame(&frame).unwrap().end, 100 * 576 - (1324 - 529));
+    }
+
+    #[test]
+    fn no_lame_tag() {
+        let mut frame = lame_frame([0xff, 0xfb, 0x90, 0x64], 32, 100, [0x24, 0x05, 0x2c]);
+        // The side information of a stereo frame isn't where a mono frame has its Xing header
+        frame[3] = 0xc4;
+        assert_eq!(parse_lame(&frame), None);
+
+        let mut frame = lame_frame([0xff, 0xfb, 0x90, 0x64], 32, 100, [0x24, 0x05, 0x2c]);
+        frame[36 + 120..36 + 124].copy_from_slice(&[0; 4]);
+        assert_eq!(parse_lame(&frame), None);
+
+        // More delay than audio
+        let frame = lame_frame([0xff, 0xfb, 0x90, 0x64], 32, 1, [0x24, 0x05, 0x2c]);
+        assert_eq!(parse_lame(&frame), None);
+
+        assert_eq!(parse_lame(&[0xff, 0xfb]), None);
+    }
+
+    #[test]
+    fn itunsmpb() {
+        assert_eq!(
+            parse_itunsmpb(
+                " 00000000 00000840 000001CA 0000000000FFD2F6 00000000 00000000 00000000 00000000"
+            ),
+            Some(Trim {
+                start: 2112,
+                end: 2112 + 0xffd2f6,
+                edit_list: true,
+            })
+        );
+        assert_eq!(parse_itunsmpb(" 00000000 00000840 000001CA 0"), None);
+        assert_eq!(parse_itunsmpb("00000000 00000840"), None);
+        assert_eq!(parse_itunsmpb("garbage"), None);
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::i18n::tr;
+
+/// The standard ID3v1 genres, used when a tag references a genre by number
+/// (ID3v2 `(17)` style references or the MP4 `gnre` atom).
+const ID3V1_GENRES: [&str; 80] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "Alternative Rock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native US",
+    "Cabaret",
+    "New Wave",
+    "Psychadelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+];
+
+/// Returns the name of the ID3v1 genre with the given index.
+pub fn id3v1_genre(index: usize) -> Option<&'static str> {
+    ID3V1_GENRES.get(index).copied()
+}
+
+/// Folds a genre string into the key used to look up aliases.
+///
+/// Case and everything that isn't alphanumeric is ignored, so that "Hip Hop",
+/// "hip-hop" and "HIPHOP" all share the same alias.
+pub fn alias_key(genre: &str) -> String {
+    genre
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// In-memory copy of the `genre_alias` table, loaded once per scan.
+pub struct GenreAliases {
+    aliases: HashMap<String, String>,
+}
+
+impl GenreAliases {
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<GenreAliases> {
+        let mut stmt = db.prepare("SELECT key, genre FROM genre_alias")?;
+        let mut rows = stmt.query([])?;
+
+        let mut aliases = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(0)?;
+            let genre: String = row.get(1)?;
+            aliases.insert(key, genre);
+        }
+
+        Ok(GenreAliases { aliases })
+    }
+
+    /// Returns the canonical genre for a raw tag value.
+    ///
+    /// Numeric ID3v1 references like `(17)` are expanded first, then the alias table is
+    /// consulted. Genres without an alias are returned trimmed but otherwise untouched.
+    pub fn resolve(&self, raw: &str) -> Option<String> {
+        let mut genre = raw.trim();
+        if genre.is_empty() {
+            return None;
+        }
+
+        if let Some(reference) = genre.strip_prefix('(') {
+            if let Some((number, rest)) = reference.split_once(')') {
+                if let Some(name) = number.parse().ok().and_then(id3v1_genre) {
+                    genre = if rest.trim().is_empty() {
+                        name
+                    } else {
+                        rest.trim()
+                    };
+                }
+            }
+        }
+
+        match self.aliases.get(&alias_key(genre)) {
+            Some(canonical) => Some(canonical.clone()),
+            None => Some(genre.to_owned()),
+        }
+    }
+}
+
+/// Returns the genres known to be genres, by alias key: those of the library's tags, the alias
+/// targets and the ID3v1 genres, with the spelling to use for each.
+pub fn known_genres(db: &rusqlite::Connection) -> rusqlite::Result<HashMap<String, String>> {
+    let mut genres: HashMap<String, String> = ID3V1_GENRES
+        .iter()
+        .map(|genre| (alias_key(genre), (*genre).to_owned()))
+        .collect();
+
+    // The spelling of the library wins over the standard one
+    let mut stmt = db.prepare(
+        "SELECT genre FROM track WHERE genre IS NOT NULL AND NOT genre_inferred
+         UNION
+         SELECT genre FROM genre_alias",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let genre: String = row.get(0)?;
+        genres.insert(alias_key(&genre), genre);
+    }
+
+    Ok(genres)
+}
+
+/// The condition on `$genre` of the queries filtering tracks by genre, which includes its
+/// subgenres: "Rock" matches "Shoegaze" through "Alternative Rock". Names are compared
+/// ignoring case, and cycles end since a genre is only visited once.
+pub const GENRE_FILTER: &str = "($genre IS NULL OR track.genre COLLATE NOCASE IN (
+    WITH RECURSIVE subgenre(name) AS (
+        SELECT $genre
+        UNION
+        SELECT genre_parent.genre
+        FROM genre_parent
+        JOIN subgenre ON genre_parent.parent = subgenre.name
+    )
+    SELECT name FROM subgenre
+))";
+
+/// Returns the pairs of genre and parent of the hierarchy, a genre can have several parents.
+pub fn load_parents(db: &rusqlite::Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = db.prepare("SELECT genre, parent FROM genre_parent ORDER BY genre, parent")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    rows.collect()
+}
+
+//
+// "genre" command
+//
+
+pub enum CommandGenreError {
+    SQLite(rusqlite::Error),
+    EmptyAlias,
+    AliasNotFound(String),
+    ParentNotFound(String, String),
+    ParentCycle(String, String),
+}
+impl From<rusqlite::Error> for CommandGenreError {
+    fn from(err: rusqlite::Error) -> CommandGenreError {
+        CommandGenreError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandGenreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandGenreError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandGenreError::EmptyAlias => f.write_str(&tr!("genre-invalid-alias")),
+            CommandGenreError::AliasNotFound(alias) => {
+                f.write_str(&tr!("genre-alias-not-found", alias = alias))
+            }
+            CommandGenreError::ParentNotFound(genre, parent) => f.write_str(&tr!(
+                "genre-parent-not-found",
+                genre = genre,
+                parent = parent
+            )),
+            CommandGenreError::ParentCycle(genre, parent) => {
+                f.write_str(&tr!("genre-parent-cycle", genre = genre, parent = parent))
+            }
+        }
+    }
+}
+
+fn cmd_genre_alias_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandGenreError> {
+    let alias = args.value_of("alias").unwrap();
+    let genre = args.value_of("genre").unwrap().trim();
+
+    let key = alias_key(alias);
+    if key.is_empty() {
+        return Err(CommandGenreError::EmptyAlias);
+    }
+
+    let query = "INSERT INTO genre_alias(key, alias, genre) VALUES($key, $alias, $genre) ON CONFLICT(key) DO UPDATE SET alias = excluded.alias, genre = excluded.genre";
+
+    db.execute(query, rusqlite::params![key, alias, genre])?;
+
+    println!("\"{}\" -> \"{}\"", alias, genre);
+
+    Ok(())
+}
+
+fn cmd_genre_alias_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandGenreError> {
+    let alias = args.value_of("alias").unwrap();
+
+    let n = db.execute(
+        "DELETE FROM genre_alias WHERE key = $key",
+        [alias_key(alias)],
+    )?;
+    if n == 0 {
+        return Err(CommandGenreError::AliasNotFound(alias.to_string()));
+    }
+
+    Ok(())
+}
+
+fn cmd_genre_alias_list(db: &mut rusqlite::Connection) -> Result<(), CommandGenreError> {
+    let mut stmt = db.prepare("SELECT alias, genre FROM genre_alias ORDER BY genre, alias")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let alias: String = row.get(0)?;
+        let genre: String = row.get(1)?;
+
+        println!("\"{}\" -> \"{}\"", alias, genre);
+    }
+
+    Ok(())
+}
+
+fn cmd_genre_parent_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandGenreError> {
+    let genre = args.value_of("genre").unwrap().trim();
+    let parent = args.value_of("parent").unwrap().trim();
+
+    // The parent can't be the genre or one of its subgenres
+    let is_cycle: bool = db.query_row(
+        "WITH RECURSIVE subgenre(name) AS (
+             SELECT $genre
+             UNION
+             SELECT genre_parent.genre
+             FROM genre_parent
+             JOIN subgenre ON genre_parent.parent = subgenre.name
+         )
+         SELECT EXISTS (SELECT 1 FROM subgenre WHERE name = $parent COLLATE NOCASE)",
+        [genre, parent],
+        |row| row.get(0),
+    )?;
+    if is_cycle {
+        return Err(CommandGenreError::ParentCycle(
+            genre.to_string(),
+            parent.to_string(),
+        ));
+    }
+
+    db.execute(
+        "INSERT OR IGNORE INTO genre_parent(genre, parent) VALUES($genre, $parent)",
+        [genre, parent],
+    )?;
+
+    println!("\"{}\" -> \"{}\"", genre, parent);
+
+    Ok(())
+}
+
+fn cmd_genre_parent_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandGenreError> {
+    let genre = args.value_of("genre").unwrap();
+    let parent = args.value_of("parent").unwrap();
+
+    let n = db.execute(
+        "DELETE FROM genre_parent WHERE genre = $genre AND parent = $parent",
+        [genre.trim(), parent.trim()],
+    )?;
+    if n == 0 {
+        return Err(CommandGenreError::ParentNotFound(
+            genre.to_string(),
+            parent.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn cmd_genre_parent_list(db: &mut rusqlite::Connection) -> Result<(), CommandGenreError> {
+    for (genre, parent) in load_parents(db)? {
+        println!("\"{}\" -> \"{}\"", genre, parent);
+    }
+
+    Ok(())
+}
+
+/// Returns the genres of the tracks no alias leads to, with their number of tracks, the most
+/// common first.
+fn unmapped_genres(db: &rusqlite::Connection) -> rusqlite::Result<Vec<(String, i64)>> {
+    let query = "
+        SELECT genre, COUNT(*)
+        FROM track
+        WHERE genre IS NOT NULL
+          AND genre NOT IN (SELECT genre FROM genre_alias)
+        GROUP BY genre
+        ORDER BY COUNT(*) DESC, genre";
+
+    let mut stmt = db.prepare(query)?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    rows.collect()
+}
+
+fn cmd_genre_unmapped(db: &mut rusqlite::Connection) -> Result<(), CommandGenreError> {
+    for (genre, count) in unmapped_genres(db)? {
+        println!("\"{}\" ({})", genre, tr!("genre-tracks", count = count));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_genre(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandGenreError> {
+    match args.subcommand() {
+        Some(("alias", alias_args)) => match alias_args.subcommand() {
+            Some(("add", sub_args)) => cmd_genre_alias_add(db, sub_args),
+            Some(("remove", sub_args)) => cmd_genre_alias_remove(db, sub_args),
+            _ => cmd_genre_alias_list(db),
+        },
+        Some(("parent", parent_args)) => match parent_args.subcommand() {
+            Some(("add", sub_args)) => cmd_genre_parent_add(db, sub_args),
+            Some(("remove", sub_args)) => cmd_genre_parent_remove(db, sub_args),
+            _ => cmd_genre_parent_list(db),
+        },
+        Some(("unmapped", _)) => cmd_genre_unmapped(db),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases() -> (rusqlite::Connection, GenreAliases) {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+        crate::init_database(&mut db).unwrap();
+        let aliases = GenreAliases::load(&db).unwrap();
+        (db, aliases)
+    }
+
+    #[test]
+    fn aliases_ignore_case_and_punctuation() {
+        let (_, aliases) = aliases();
+        for raw in ["Hip Hop", "hip-hop", "HIPHOP", "  hiphop  "] {
+            assert_eq!(aliases.resolve(raw).as_deref(), Some("Hip-Hop"));
+        }
+        assert_eq!(
+            aliases.resolve("Drum 'n' Bass").as_deref(),
+            Some("Drum & Bass")
+        );
+        assert_eq!(
+            aliases.resolve("alt. rock").as_deref(),
+            Some("Alternative Rock")
+        );
+
+        // Without an alias, only trimmed
+        assert_eq!(aliases.resolve(" Shoegaze ").as_deref(), Some("Shoegaze"));
+        assert_eq!(
+            aliases.resolve("Alternative").as_deref(),
+            Some("Alternative")
+        );
+        assert_eq!(aliases.resolve("   "), None);
+    }
+
+    #[test]
+    fn id3v1_references() {
+        let (_, aliases) = aliases();
+        assert_eq!(aliases.resolve("(17)").as_deref(), Some("Rock"));
+        assert_eq!(aliases.resolve("(20)").as_deref(), Some("Alternative"));
+        assert_eq!(aliases.resolve("(40)").as_deref(), Some("Alternative Rock"));
+        // The text after the reference refines it
+        assert_eq!(aliases.resolve("(7)Hip Hop").as_deref(), Some("Hip-Hop"));
+        assert_eq!(
+            aliases.resolve("(17) Post-Rock").as_deref(),
+            Some("Post-Rock")
+        );
+        // Out of the table or not a number, left as it is
+        assert_eq!(aliases.resolve("(200)").as_deref(), Some("(200)"));
+        assert_eq!(aliases.resolve("(Remix)").as_deref(), Some("(Remix)"));
+
+        assert_eq!(id3v1_genre(0), Some("Blues"));
+        assert_eq!(id3v1_genre(79), Some("Hard Rock"));
+        assert_eq!(id3v1_genre(80), None);
+    }
+
+    #[test]
+    fn unmapped_genres_report() {
+        let (db, _) = aliases();
+        for genre in [
+            Some("Shoegaze"),
+            Some("Shoegaze"),
+            Some("Alternative Rock"),
+            Some("Ambient"),
+            Some("Hip-Hop"),
+            None,
+        ] {
+            db.execute("INSERT INTO track(genre) VALUES($genre)", [genre])
+                .unwrap();
+        }
+
+        // The targets of aliases are mapped already
+        assert_eq!(
+            unmapped_genres(&db).unwrap(),
+            vec![("Shoegaze".to_owned(), 2), ("Ambient".to_owned(), 1)]
+        );
+    }
+}
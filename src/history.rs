@@ -0,0 +1,338 @@
+//! The play history, grouped into listening sessions.
+//!
+//! A session ends when nothing was played for a while after the end of the last track:
+//! 30 minutes unless `--gap` says otherwise. Sessions are computed when listing, so
+//! changing the gap regroups the whole history.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::i18n::tr;
+use crate::json;
+use crate::query::csv_field;
+use crate::top::{format_duration, parse_since};
+
+pub enum CommandHistoryError {
+    SQLite(rusqlite::Error),
+    InvalidSince(String),
+    InvalidGap(String),
+    UserNotFound(String),
+}
+impl From<rusqlite::Error> for CommandHistoryError {
+    fn from(err: rusqlite::Error) -> CommandHistoryError {
+        CommandHistoryError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandHistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandHistoryError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandHistoryError::InvalidSince(value) => {
+                f.write_str(&tr!("invalid-period", value = value))
+            }
+            CommandHistoryError::InvalidGap(value) => {
+                f.write_str(&tr!("history-invalid-gap", value = value))
+            }
+            CommandHistoryError::UserNotFound(name) => {
+                f.write_str(&tr!("user-not-found", name = name))
+            }
+        }
+    }
+}
+
+struct Play {
+    user: String,
+    played_at: i64,
+    /// `played_at` as a local date and time.
+    time: String,
+    /// When the track ended, as a local date and time.
+    end_time: String,
+    track_id: i64,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    duration_ms: Option<i64>,
+    session: usize,
+}
+
+struct Session {
+    number: usize,
+    user: String,
+    start: String,
+    end: String,
+    plays: usize,
+    duration_ms: i64,
+    top_artist: Option<String>,
+}
+
+/// Parses a gap like "30m" into seconds.
+fn parse_gap(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let number: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+
+    let seconds = match unit {
+        's' => number,
+        'm' => number * 60,
+        'h' => number * 3600,
+        _ => return None,
+    };
+    if seconds <= 0 {
+        return None;
+    }
+
+    Some(seconds)
+}
+
+/// Loads the plays in the order they happened, numbering their sessions.
+fn load_plays(
+    db: &rusqlite::Connection,
+    since: Option<i64>,
+    user: Option<&str>,
+    gap: i64,
+) -> rusqlite::Result<Vec<Play>> {
+    let query = "
+        SELECT user.name, play.played_at, datetime(play.played_at, 'unixepoch', 'localtime'),
+               track.id, track.name, artist.name, album.name, track.duration_ms,
+               datetime(play.played_at + coalesce(track.duration_ms, 0) / 1000, 'unixepoch',
+                        'localtime')
+        FROM play
+        JOIN user ON user.id = play.user_id
+        JOIN track ON track.id = play.track_id
+        LEFT JOIN artist ON artist.id = track.artist_id
+        LEFT JOIN album ON album.id = track.album_id
+        WHERE ($since IS NULL OR play.played_at >= unixepoch() - $since)
+          AND ($user IS NULL OR user.name = $user)
+        ORDER BY play.played_at, play.id";
+
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query(rusqlite::params![since, user])?;
+
+    let mut plays = Vec::new();
+    // The session of each user and when their last track ended
+    let mut current: HashMap<String, (usize, i64)> = HashMap::new();
+    let mut sessions = 0;
+
+    while let Some(row) = rows.next()? {
+        let user: String = row.get(0)?;
+        let played_at: i64 = row.get(1)?;
+        let duration_ms: Option<i64> = row.get(7)?;
+
+        let session = match current.get(&user) {
+            Some((session, end)) if played_at - end <= gap => *session,
+            _ => {
+                sessions += 1;
+                sessions
+            }
+        };
+        let end = played_at + duration_ms.unwrap_or(0) / 1000;
+        current.insert(user.clone(), (session, end));
+
+        plays.push(Play {
+            user,
+            played_at,
+            time: row.get(2)?,
+            end_time: row.get(8)?,
+            track_id: row.get(3)?,
+            title: row.get(4)?,
+            artist: row.get(5)?,
+            album: row.get(6)?,
+            duration_ms,
+            session,
+        });
+    }
+
+    Ok(plays)
+}
+
+fn summarize(plays: &[Play]) -> Vec<Session> {
+    let mut sessions: Vec<Session> = Vec::new();
+    let mut artists: Vec<HashMap<&str, usize>> = Vec::new();
+
+    for play in plays {
+        // Sessions are numbered in the order they started
+        if play.session > sessions.len() {
+            sessions.push(Session {
+                number: play.session,
+                user: play.user.clone(),
+                start: play.time.clone(),
+                end: String::new(),
+                plays: 0,
+                duration_ms: 0,
+                top_artist: None,
+            });
+            artists.push(HashMap::new());
+        }
+
+        let session = &mut sessions[play.session - 1];
+        session.end = play.end_time.clone();
+        session.plays += 1;
+        session.duration_ms += play.duration_ms.unwrap_or(0);
+
+        if let Some(artist) = &play.artist {
+            *artists[play.session - 1].entry(artist).or_default() += 1;
+        }
+    }
+
+    for (session, artists) in sessions.iter_mut().zip(artists) {
+        session.top_artist = artists
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(artist, _)| artist.to_owned());
+    }
+
+    sessions
+}
+
+//
+// "history" command
+//
+
+fn print_sessions(plays: &[Play], format: &str) {
+    let sessions = summarize(plays);
+
+    match format {
+        "json" => {
+            let values: Vec<String> = sessions
+                .iter()
+                .map(|session| {
+                    json::object(&[
+                        ("session", session.number.to_string()),
+                        ("user", json::string(&session.user)),
+                        ("start", json::string(&session.start)),
+                        ("end", json::string(&session.end)),
+                        ("plays", session.plays.to_string()),
+                        ("duration_ms", session.duration_ms.to_string()),
+                        (
+                            "top_artist",
+                            json::opt_string(session.top_artist.as_deref()),
+                        ),
+                    ])
+                })
+                .collect();
+            println!("{}", json::array(&values));
+        }
+        "csv" => {
+            println!("session,user,start,end,plays,duration_ms,top_artist");
+            for session in &sessions {
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    session.number,
+                    csv_field(&session.user),
+                    session.start,
+                    session.end,
+                    session.plays,
+                    session.duration_ms,
+                    csv_field(session.top_artist.as_deref().unwrap_or_default()),
+                );
+            }
+        }
+        _ => {
+            for session in &sessions {
+                println!(
+                    "{}\t{}\t{}",
+                    session.start,
+                    session.user,
+                    tr!(
+                        "history-session",
+                        plays = session.plays,
+                        duration = format_duration(session.duration_ms),
+                        artist = session
+                            .top_artist
+                            .clone()
+                            .unwrap_or_else(|| tr!("history-unknown-artists")),
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn print_plays(plays: &[Play], format: &str) {
+    match format {
+        "json" => {
+            let values: Vec<String> = plays
+                .iter()
+                .map(|play| {
+                    json::object(&[
+                        ("played_at", play.played_at.to_string()),
+                        ("time", json::string(&play.time)),
+                        ("session", play.session.to_string()),
+                        ("user", json::string(&play.user)),
+                        ("track_id", play.track_id.to_string()),
+                        ("title", json::opt_string(play.title.as_deref())),
+                        ("artist", json::opt_string(play.artist.as_deref())),
+                        ("album", json::opt_string(play.album.as_deref())),
+                        ("duration_ms", json::opt_number(play.duration_ms)),
+                    ])
+                })
+                .collect();
+            println!("{}", json::array(&values));
+        }
+        _ => {
+            println!("played_at,time,session,user,track_id,title,artist,album,duration_ms");
+            for play in plays {
+                println!(
+                    "{},{},{},{},{},{},{},{},{}",
+                    play.played_at,
+                    play.time,
+                    play.session,
+                    csv_field(&play.user),
+                    play.track_id,
+                    csv_field(play.title.as_deref().unwrap_or_default()),
+                    csv_field(play.artist.as_deref().unwrap_or_default()),
+                    csv_field(play.album.as_deref().unwrap_or_default()),
+                    play.duration_ms.map_or(String::new(), |ms| ms.to_string()),
+                );
+            }
+        }
+    }
+}
+
+pub fn cmd_history(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandHistoryError> {
+    let (name, sub_args) = match args.subcommand() {
+        Some(subcommand) => subcommand,
+        None => return Ok(()),
+    };
+
+    let since = match sub_args.value_of("since") {
+        Some(value) => match parse_since(value) {
+            Some(seconds) => Some(seconds),
+            None => return Err(CommandHistoryError::InvalidSince(value.to_owned())),
+        },
+        None => None,
+    };
+    let gap = match sub_args.value_of("gap") {
+        Some(value) => match parse_gap(value) {
+            Some(seconds) => seconds,
+            None => return Err(CommandHistoryError::InvalidGap(value.to_owned())),
+        },
+        None => 30 * 60,
+    };
+
+    let user = sub_args.value_of("user");
+    if let Some(name) = user {
+        let exists: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM user WHERE name = $name)",
+            [name],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(CommandHistoryError::UserNotFound(name.to_owned()));
+        }
+    }
+
+    let plays = load_plays(db, since, user, gap)?;
+    let format = sub_args.value_of("format").unwrap_or_default();
+
+    match name {
+        "sessions" => print_sessions(&plays, format),
+        "export" => print_plays(&plays, format),
+        _ => (),
+    }
+
+    Ok(())
+}
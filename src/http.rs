@@ -0,0 +1,116 @@
+//! Requests to web services, delegated to the `curl` binary.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{self, Stdio};
+
+use crate::i18n::tr;
+use crate::json;
+
+const USER_AGENT: &str = concat!(
+    "zik/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/vrischmann/zik-rust)"
+);
+
+pub enum HttpError {
+    NotFound,
+    IO(io::Error),
+    Failed(String),
+    InvalidJson(json::ParseError),
+}
+impl From<io::Error> for HttpError {
+    fn from(err: io::Error) -> HttpError {
+        HttpError::IO(err)
+    }
+}
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpError::NotFound => f.write_str(&tr!("http-curl-not-found")),
+            HttpError::IO(err) => f.write_str(&tr!("http-io", err = err)),
+            HttpError::Failed(err) => f.write_str(&tr!("http-failed", err = err)),
+            HttpError::InvalidJson(err) => f.write_str(&tr!("http-invalid-json", err = err)),
+        }
+    }
+}
+
+/// Encodes a query string component.
+pub fn percent_encode(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                buf.push(b as char)
+            }
+            b => buf.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    buf
+}
+
+/// Fetches `url` and parses the response as JSON.
+pub fn get_json(url: &str) -> Result<json::Value, HttpError> {
+    let output = match process::Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", "30"])
+        .args(["--user-agent", USER_AGENT])
+        .arg(url)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(HttpError::NotFound),
+        Err(err) => return Err(err.into()),
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(HttpError::Failed(stderr.trim().to_owned()));
+    }
+
+    let body = String::from_utf8_lossy(&output.stdout);
+    json::parse(&body).map_err(HttpError::InvalidJson)
+}
+
+/// Posts `body` to `url`, with extra `headers`.
+pub fn post(
+    url: &str,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<(), HttpError> {
+    let mut command = process::Command::new("curl");
+    command
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", "30"])
+        .args(["--user-agent", USER_AGENT])
+        .arg("--header")
+        .arg(format!("Content-Type: {}", content_type));
+    for (name, value) in headers {
+        command.arg("--header").arg(format!("{}: {}", name, value));
+    }
+    // The body goes through stdin, it could be too long for the command line
+    command
+        .args(["--data-binary", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(HttpError::NotFound),
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(body.as_bytes())?;
+    }
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(HttpError::Failed(stderr.trim().to_owned()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,526 @@
+//! Translations of the messages of the command line, in the Fluent files of `locales`.
+//!
+//! The language is the `language` config key, or the one of `LC_ALL`, `LC_MESSAGES` or `LANG`
+//! when it's not set, English if zik has no catalog for it. A message missing from a catalog
+//! is taken from the English one.
+//!
+//! Only the part of Fluent zik needs is read: messages, possibly on several lines, variables
+//! like `{ $count }` and selectors on a number, `{ $count -> [one] ... *[other] ... }`, with
+//! one variant per line.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Mutex;
+
+/// The languages with a catalog, English first since it's the fallback.
+pub const LANGUAGES: [&str; 2] = ["en", "fr"];
+
+const CATALOGS: [&str; 2] = [
+    include_str!("../locales/en.ftl"),
+    include_str!("../locales/fr.ftl"),
+];
+
+/// The catalogs of the language in use, loaded once per process.
+static CATALOG: Mutex<Option<Catalog>> = Mutex::new(None);
+
+enum Element {
+    Text(String),
+    Variable(String),
+    Select {
+        variable: String,
+        variants: Vec<(String, Vec<Element>)>,
+        default: usize,
+    },
+}
+
+struct Catalog {
+    language: &'static str,
+    messages: HashMap<String, Vec<Element>>,
+    fallback: HashMap<String, Vec<Element>>,
+}
+
+/// Returns the language with a catalog `value` is for, like "fr" for "fr_FR.UTF-8".
+pub fn parse_language(value: &str) -> Option<&'static str> {
+    let language = value
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    LANGUAGES.iter().find(|known| **known == language).copied()
+}
+
+fn language_from_env() -> &'static str {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| parse_language(&value))
+        .unwrap_or(LANGUAGES[0])
+}
+
+/// Selects the language of the messages, from the environment without `language`.
+pub fn init(language: Option<&str>) {
+    let language = language
+        .and_then(parse_language)
+        .unwrap_or_else(language_from_env);
+
+    let mut catalog = CATALOG.lock().unwrap();
+    if catalog
+        .as_ref()
+        .is_some_and(|catalog| catalog.language == language)
+    {
+        return;
+    }
+    *catalog = Some(Catalog::load(language));
+}
+
+impl Catalog {
+    fn load(language: &'static str) -> Catalog {
+        let index = LANGUAGES
+            .iter()
+            .position(|known| *known == language)
+            .unwrap_or(0);
+
+        Catalog {
+            language,
+            messages: parse(CATALOGS[index]),
+            fallback: if index == 0 {
+                HashMap::new()
+            } else {
+                parse(CATALOGS[0])
+            },
+        }
+    }
+
+    /// Returns the plural category of `n`, "one" or "other".
+    fn plural_category(&self, n: i64) -> &'static str {
+        let is_one = match self.language {
+            // Zero is singular in French
+            "fr" => n == 0 || n == 1,
+            _ => n == 1,
+        };
+        if is_one {
+            "one"
+        } else {
+            "other"
+        }
+    }
+
+    fn format(&self, elements: &[Element], args: &HashMap<&str, String>, out: &mut String) {
+        for element in elements {
+            match element {
+                Element::Text(text) => out.push_str(text),
+                Element::Variable(name) => match args.get(name.as_str()) {
+                    Some(value) => out.push_str(value),
+                    None => {
+                        out.push_str("{$");
+                        out.push_str(name);
+                        out.push('}');
+                    }
+                },
+                Element::Select {
+                    variable,
+                    variants,
+                    default,
+                } => {
+                    let value = args.get(variable.as_str()).map_or("", String::as_str);
+                    let category = value.parse().ok().map(|n| self.plural_category(n));
+                    let variant = variants
+                        .iter()
+                        .find(|(key, _)| key == value || Some(key.as_str()) == category)
+                        .unwrap_or(&variants[*default]);
+                    self.format(&variant.1, args, out);
+                }
+            }
+        }
+    }
+
+    fn text(&self, id: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+        let elements = match self.messages.get(id).or_else(|| self.fallback.get(id)) {
+            Some(elements) => elements,
+            None => return id.to_owned(),
+        };
+        let args: HashMap<&str, String> = args
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect();
+
+        let mut out = String::new();
+        self.format(elements, &args, &mut out);
+        out
+    }
+}
+
+/// Returns the message `id` in the language in use, with the values of its variables.
+///
+/// Unknown messages are returned as their id, so that a typo shows instead of nothing.
+pub fn text(id: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    // Values are formatted before taking the catalog, errors translate their messages too
+    let values: Vec<String> = args.iter().map(|(_, value)| value.to_string()).collect();
+    let args: Vec<(&str, &dyn fmt::Display)> = args
+        .iter()
+        .zip(&values)
+        .map(|((name, _), value)| (*name, value as &dyn fmt::Display))
+        .collect();
+
+    let mut catalog = CATALOG.lock().unwrap();
+    catalog
+        .get_or_insert_with(|| Catalog::load(language_from_env()))
+        .text(id, &args)
+}
+
+/// Formats a message like `format!`, `tr!("scan-summary", indexed = n, added = m)`.
+macro_rules! tr {
+    ($id:expr $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::i18n::text(
+            $id,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+pub(crate) use tr;
+
+/// Parses a catalog, skipping the messages it can't read.
+fn parse(source: &str) -> HashMap<String, Vec<Element>> {
+    let mut messages = HashMap::new();
+
+    let mut current: Option<(String, String)> = None;
+    for line in source.lines() {
+        // The lines of a message after the first are indented, but for the end of a selector
+        if line.starts_with([' ', '\t', '}']) || (line.is_empty() && current.is_some()) {
+            if let Some((_, value)) = &mut current {
+                value.push('\n');
+                value.push_str(line.trim_start());
+            }
+            continue;
+        }
+
+        if let Some((id, value)) = current.take() {
+            if let Some(elements) = parse_pattern(&mut value.trim().chars().peekable(), false) {
+                messages.insert(id, elements);
+            }
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        if let Some((id, value)) = line.split_once('=') {
+            current = Some((id.trim().to_owned(), value.trim_start().to_owned()));
+        }
+    }
+    if let Some((id, value)) = current {
+        if let Some(elements) = parse_pattern(&mut value.trim().chars().peekable(), false) {
+            messages.insert(id, elements);
+        }
+    }
+
+    messages
+}
+
+/// Parses text and placeables, up to the end of the line and before a closing brace for the
+/// variant of a selector.
+fn parse_pattern(chars: &mut Peekable<Chars>, in_variant: bool) -> Option<Vec<Element>> {
+    let mut elements = Vec::new();
+    let mut text = String::new();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' | '}' if in_variant => break,
+            '{' => {
+                chars.next();
+                if !text.is_empty() {
+                    elements.push(Element::Text(std::mem::take(&mut text)));
+                }
+                elements.push(parse_placeable(chars)?);
+            }
+            _ => {
+                text.push(c);
+                chars.next();
+            }
+        }
+    }
+    if !text.is_empty() {
+        elements.push(Element::Text(text));
+    }
+
+    Some(elements)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// Parses what's between braces, the opening one read already.
+fn parse_placeable(chars: &mut Peekable<Chars>) -> Option<Element> {
+    skip_whitespace(chars);
+    chars.next_if_eq(&'$')?;
+    let mut variable = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '-') {
+        variable.push(c);
+    }
+    skip_whitespace(chars);
+
+    if chars.next_if_eq(&'}').is_some() {
+        return Some(Element::Variable(variable));
+    }
+    chars.next_if_eq(&'-')?;
+    chars.next_if_eq(&'>')?;
+
+    let mut variants = Vec::new();
+    let mut default = None;
+    loop {
+        skip_whitespace(chars);
+        if chars.next_if_eq(&'}').is_some() {
+            break;
+        }
+        if chars.next_if_eq(&'*').is_some() {
+            default = Some(variants.len());
+        }
+        chars.next_if_eq(&'[')?;
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != ']') {
+            key.push(c);
+        }
+        chars.next_if_eq(&']')?;
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+
+        let mut value = parse_pattern(chars, true)?;
+        // Like in Fluent, the spaces before the end of a variant aren't part of it
+        if let Some(Element::Text(text)) = value.last_mut() {
+            text.truncate(text.trim_end().len());
+        }
+        variants.push((key.trim().to_owned(), value));
+    }
+
+    Some(Element::Select {
+        variable,
+        variants,
+        default: default?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    fn catalog(language: &'static str, source: &str) -> Catalog {
+        Catalog {
+            language,
+            messages: parse(source),
+            fallback: parse("fallback = only in English, { $name }"),
+        }
+    }
+
+    /// Returns the ids of the messages of a catalog, by the lines starting them.
+    fn ids(source: &str) -> Vec<&str> {
+        source
+            .lines()
+            .filter(|line| !line.starts_with([' ', '\t', '}', '#']))
+            .filter_map(|line| line.split_once(" = "))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns the variables used by a message, in the order they appear.
+    fn variables(source: &str, id: &str) -> Vec<String> {
+        let mut variables = Vec::new();
+        let mut in_message = false;
+        for line in source.lines() {
+            if !line.starts_with([' ', '\t', '}']) {
+                in_message = line.starts_with(&format!("{} = ", id));
+            }
+            if in_message {
+                for part in line.split("{ $").skip(1) {
+                    let name: String = part
+                        .chars()
+                        .take_while(|c| c.is_alphanumeric() || *c == '_')
+                        .collect();
+                    if !variables.contains(&name) {
+                        variables.push(name);
+                    }
+                }
+            }
+        }
+        variables.sort();
+        variables
+    }
+
+    #[test]
+    fn parse_messages() {
+        let catalog = catalog(
+            "en",
+            "# A comment = not a message
+simple = Hello
+with-variable = file {$path} at { $path } of { $missing }
+not-a-variable = { path }
+multi-line = first line
+    second line
+
+    after an empty line
+next = after",
+        );
+
+        assert_eq!(catalog.text("simple", &[]), "Hello");
+        assert_eq!(
+            catalog.text("with-variable", &[("path", &"a.flac")]),
+            "file a.flac at a.flac of {$missing}"
+        );
+        assert_eq!(catalog.text("not-a-variable", &[]), "not-a-variable");
+        assert_eq!(
+            catalog.text("multi-line", &[]),
+            "first line\nsecond line\n\nafter an empty line"
+        );
+        assert_eq!(catalog.text("next", &[]), "after");
+        assert_eq!(catalog.text("# A comment", &[]), "# A comment");
+        assert_eq!(catalog.text("unknown", &[]), "unknown");
+    }
+
+    #[test]
+    fn parse_selectors() {
+        let catalog = catalog(
+            "en",
+            "tracks = { $count ->
+    [0] no tracks
+    [one] { $count } track
+   *[other] { $count } tracks
+} in { $album }
+no-default = { $count ->
+    [one] one
+    [other] many
+}
+unclosed = { $count
+after = still parsed",
+        );
+
+        let tracks = |count: &dyn fmt::Display| {
+            catalog.text("tracks", &[("count", count), ("album", &"Album")])
+        };
+        assert_eq!(tracks(&0), "no tracks in Album");
+        assert_eq!(tracks(&1), "1 track in Album");
+        assert_eq!(tracks(&12), "12 tracks in Album");
+        // The default variant for what's not a number
+        assert_eq!(tracks(&"some"), "some tracks in Album");
+
+        // Messages zik can't read are skipped, not the ones after them
+        assert_eq!(catalog.text("no-default", &[("count", &1)]), "no-default");
+        assert_eq!(catalog.text("unclosed", &[]), "unclosed");
+        assert_eq!(catalog.text("after", &[]), "still parsed");
+    }
+
+    #[test]
+    fn french_plurals() {
+        let source = "fichiers = { $count ->
+    [one] { $count } fichier
+   *[other] { $count } fichiers
+}";
+        let french = catalog("fr", source);
+        assert_eq!(french.text("fichiers", &[("count", &0)]), "0 fichier");
+        assert_eq!(french.text("fichiers", &[("count", &1)]), "1 fichier");
+        assert_eq!(french.text("fichiers", &[("count", &2)]), "2 fichiers");
+
+        // Zero is plural in English
+        let english = catalog("en", source);
+        assert_eq!(english.text("fichiers", &[("count", &0)]), "0 fichiers");
+    }
+
+    #[test]
+    fn english_fallback() {
+        let french = catalog("fr", "bonjour = Bonjour");
+        assert_eq!(french.text("bonjour", &[]), "Bonjour");
+        assert_eq!(
+            french.text("fallback", &[("name", &"zik")]),
+            "only in English, zik"
+        );
+
+        let french = Catalog::load("fr");
+        assert!(!french.fallback.is_empty());
+        assert_eq!(french.text("du-total", &[]), "total");
+        assert!(Catalog::load("en").fallback.is_empty());
+    }
+
+    #[test]
+    fn translated_values() {
+        struct Translated;
+        impl fmt::Display for Translated {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&text("du-total", &[]))
+            }
+        }
+
+        assert!(!text("user-not-found", &[("name", &Translated)]).is_empty());
+    }
+
+    #[test]
+    fn parse_languages() {
+        assert_eq!(parse_language("fr_FR.UTF-8"), Some("fr"));
+        assert_eq!(parse_language("fr-CA"), Some("fr"));
+        assert_eq!(parse_language("EN"), Some("en"));
+        assert_eq!(parse_language("C"), None);
+        assert_eq!(parse_language(""), None);
+    }
+
+    #[test]
+    fn catalogs_are_complete() {
+        let english = ids(CATALOGS[0]);
+        for (language, source) in LANGUAGES.iter().zip(CATALOGS) {
+            // A message zik can't read would be missing
+            let ids = ids(source);
+            let messages = parse(source);
+            for id in &ids {
+                assert!(
+                    messages.contains_key(*id),
+                    "{}: {} not parsed",
+                    language,
+                    id
+                );
+            }
+            assert_eq!(messages.len(), ids.len(), "{}: duplicate ids", language);
+
+            // Every message is in English, with the same variables
+            for id in &ids {
+                assert!(english.contains(id), "{}: {} not in English", language, id);
+                assert_eq!(
+                    variables(source, id),
+                    variables(CATALOGS[0], id),
+                    "{}: variables of {}",
+                    language,
+                    id
+                );
+            }
+            assert_eq!(ids.len(), english.len(), "{}: missing messages", language);
+        }
+    }
+
+    #[test]
+    fn used_messages_exist() {
+        let english = parse(CATALOGS[0]);
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for entry in std::fs::read_dir(src).unwrap() {
+            let path = entry.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+            for (start, _) in source.match_indices("tr!(") {
+                // Not include_str!( or this string
+                if source[..start].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '"')
+                {
+                    continue;
+                }
+                let call = &source[start + 4..];
+                let id = match call.trim_start().strip_prefix('"') {
+                    Some(rest) => &rest[..rest.find('"').unwrap()],
+                    None => continue,
+                };
+                assert!(
+                    english.contains_key(id),
+                    "{}: {} not in English",
+                    path.display(),
+                    id
+                );
+            }
+        }
+    }
+}
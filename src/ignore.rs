@@ -0,0 +1,66 @@
+//! Files and folders never read by a scan: hidden files, the `._*` AppleDouble files and
+//! `.DS_Store` of macOS, the `@eaDir` thumbnails of Synology NASes, recycle bins and such.
+//!
+//! The `scan_ignore` config key replaces the default patterns, separated by colons; a pattern
+//! matches a file or folder name ignoring case, with `*` and `?` wildcards, and `default`
+//! stands for the default patterns: `zik config scan_ignore "default:*.cue"`.
+
+const DEFAULT_SCAN_IGNORE: &str =
+    ".*:@eaDir:#recycle:@Recycle:$RECYCLE.BIN:#snapshot:Thumbs.db:desktop.ini:System Volume Information:lost+found";
+
+/// Whether `name` matches `pattern`, both lowercase.
+pub fn matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+pub struct IgnoreRules {
+    patterns: Vec<Vec<char>>,
+}
+
+impl IgnoreRules {
+    fn parse(value: &str) -> IgnoreRules {
+        let patterns = value
+            .split(':')
+            .map(str::trim)
+            .flat_map(|pattern| match pattern {
+                "default" => DEFAULT_SCAN_IGNORE.split(':').collect(),
+                _ => vec![pattern],
+            })
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| pattern.to_lowercase().chars().collect())
+            .collect();
+
+        IgnoreRules { patterns }
+    }
+
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<IgnoreRules> {
+        let value = crate::get_config_value(db, "scan_ignore")?;
+        Ok(IgnoreRules::parse(
+            value.as_deref().unwrap_or(DEFAULT_SCAN_IGNORE),
+        ))
+    }
+
+    /// Whether a walked entry is skipped, with everything under it for a folder. The root
+    /// of the walk never is, a library can be in a hidden folder.
+    pub fn is_ignored(&self, entry: &walkdir::DirEntry) -> bool {
+        if entry.depth() == 0 {
+            return false;
+        }
+
+        let name: Vec<char> = entry
+            .file_name()
+            .to_string_lossy()
+            .to_lowercase()
+            .chars()
+            .collect();
+        self.patterns.iter().any(|pattern| matches(pattern, &name))
+    }
+}
@@ -0,0 +1,1048 @@
+//! Importing albums into the library, the way beets does: the files of each folder are
+//! matched against MusicBrainz, the best match is shown for review, then the files are
+//! tagged, copied or moved into the library and indexed.
+//!
+//! Candidates are searched by the album and artist tags, and by the AcoustID fingerprint of
+//! the first track when `acoustid_api_key` is set and fpcalc is installed. Files land in
+//! `<album artist>/<album> (<year>)/<number> <title>.<extension>`, the number prefixed with
+//! the disc for albums with several. MP4 files are placed but keep their tags, they can't be
+//! written.
+//!
+//! With `--profile`, downloads from a store are imported as they are, without review: zips are
+//! unpacked with `unzip`, and tags the store left empty are filled from its file names.
+//!
+//! The imported tracks get the source given with `--source`, or the name of the profile, see
+//! `source`.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::i18n::tr;
+use crate::ignore::IgnoreRules;
+use crate::musicbrainz::{self, Release};
+use crate::{inbox, jobs, notify, sidecar, source, tags, throttle, Metadata};
+
+/// How many candidates are looked up and shown.
+const MAX_CANDIDATES: usize = 5;
+
+pub enum CommandImportError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    WalkDir(walkdir::Error),
+    MetadataRead(crate::MetadataReadError),
+    Scan(crate::CommandScanError),
+    NoLibrary,
+    RemoteLibrary(String),
+    InsideLibrary(PathBuf),
+    NoInbox,
+    NotInInbox(String),
+    UnzipNotFound,
+    Unzip(String),
+}
+impl From<rusqlite::Error> for CommandImportError {
+    fn from(err: rusqlite::Error) -> CommandImportError {
+        CommandImportError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandImportError {
+    fn from(err: io::Error) -> CommandImportError {
+        CommandImportError::IO(err)
+    }
+}
+impl From<walkdir::Error> for CommandImportError {
+    fn from(err: walkdir::Error) -> CommandImportError {
+        CommandImportError::WalkDir(err)
+    }
+}
+impl From<crate::MetadataReadError> for CommandImportError {
+    fn from(err: crate::MetadataReadError) -> CommandImportError {
+        CommandImportError::MetadataRead(err)
+    }
+}
+impl From<crate::CommandScanError> for CommandImportError {
+    fn from(err: crate::CommandScanError) -> CommandImportError {
+        CommandImportError::Scan(err)
+    }
+}
+impl fmt::Display for CommandImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandImportError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandImportError::IO(err) => f.write_str(&tr!("import-io", err = err)),
+            CommandImportError::WalkDir(err) => f.write_str(&tr!("import-walkdir", err = err)),
+            CommandImportError::MetadataRead(err) => write!(f, "{}", err),
+            CommandImportError::Scan(err) => write!(f, "{}", err),
+            CommandImportError::NoLibrary => f.write_str(&tr!("no-library")),
+            CommandImportError::RemoteLibrary(url) => {
+                f.write_str(&tr!("import-remote-library", url = url))
+            }
+            CommandImportError::InsideLibrary(path) => {
+                f.write_str(&tr!("import-inside-library", path = path.display()))
+            }
+            CommandImportError::NoInbox => f.write_str(&tr!("no-inbox")),
+            CommandImportError::NotInInbox(value) => {
+                f.write_str(&tr!("import-not-in-inbox", value = value))
+            }
+            CommandImportError::UnzipNotFound => f.write_str(&tr!("import-unzip-not-found")),
+            CommandImportError::Unzip(err) => f.write_str(&tr!("import-unzip", err = err)),
+        }
+    }
+}
+
+struct Item {
+    path: PathBuf,
+    md: Metadata,
+}
+
+/// The tags a file is given.
+#[derive(Clone)]
+struct Track {
+    title: String,
+    artist: String,
+    number: usize,
+    track_total: Option<usize>,
+    disc: usize,
+}
+
+/// What an album is tagged and filed as.
+#[derive(Clone)]
+struct Proposal {
+    artist: String,
+    album: String,
+    year: Option<String>,
+    release_type: Option<String>,
+    musicbrainz_id: Option<String>,
+    disc_total: usize,
+    /// One per file, in the same order.
+    tracks: Vec<Track>,
+}
+
+//
+// Matching
+//
+
+fn normalize(value: &str) -> Vec<char> {
+    value
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// How close two names are, from 0.0 to 1.0, ignoring case, spaces and punctuation.
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+
+    // Levenshtein distance, one row at a time
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+
+    1.0 - row[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+fn tag_album(items: &[Item]) -> (String, String) {
+    let first = &items[0].md;
+    let artist = first
+        .album_artist
+        .clone()
+        .or_else(|| first.artist.clone())
+        .unwrap_or_default();
+    (artist, first.album.clone().unwrap_or_default())
+}
+
+/// Scores a release against the files, from 0 to 100.
+fn score(items: &[Item], release: &Release) -> i64 {
+    let (artist, album) = tag_album(items);
+
+    let paired = items.len().min(release.tracks.len());
+    let titles = items
+        .iter()
+        .zip(&release.tracks)
+        .map(|(item, track)| similarity(item.md.track_name.as_deref().unwrap_or(""), &track.title))
+        .sum::<f64>()
+        / paired.max(1) as f64;
+    let counts = paired as f64 / items.len().max(release.tracks.len()).max(1) as f64;
+
+    let mut total = similarity(&album, &release.title) * 3.0
+        + similarity(&artist, &release.artist) * 2.0
+        + titles * 3.0
+        + counts * 2.0;
+    let mut weights = 10.0;
+
+    let lengths: Vec<bool> = items
+        .iter()
+        .zip(&release.tracks)
+        .filter_map(|(item, track)| Some((item.md.duration_ms?, track.length_ms?)))
+        .map(|(a, b)| (a - b).abs() <= 5000)
+        .collect();
+    if !lengths.is_empty() {
+        total += lengths.iter().filter(|&&close| close).count() as f64 / lengths.len() as f64;
+        weights += 1.0;
+    }
+
+    (total / weights * 100.0).round() as i64
+}
+
+/// Finds the releases the files could be, the best first.
+fn find_candidates(items: &[Item], acoustid_api_key: Option<&str>) -> Vec<(Release, i64)> {
+    let (artist, album) = tag_album(items);
+
+    let mut ids: Vec<String> = Vec::new();
+    if !album.is_empty() {
+        match musicbrainz::search_releases(&artist, &album) {
+            Ok(found) => ids.extend(found),
+            Err(err) => println!("{}", tr!("import-search-error", err = err)),
+        }
+    }
+    if let Some(api_key) = acoustid_api_key {
+        let item = &items[0];
+        let found = jobs::fingerprint(&item.path)
+            .map_err(|err| err.to_string())
+            .and_then(|fingerprint| {
+                musicbrainz::acoustid_releases(
+                    api_key,
+                    &fingerprint,
+                    item.md.duration_ms.unwrap_or_default(),
+                )
+                .map_err(|err| err.to_string())
+            });
+        match found {
+            Ok(found) => {
+                for id in found {
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+            }
+            Err(err) => println!("{}", tr!("import-fingerprint-error", err = err)),
+        }
+    }
+
+    let mut candidates = Vec::new();
+    for id in ids.iter().take(MAX_CANDIDATES) {
+        match musicbrainz::lookup_release(id) {
+            Ok(release) => {
+                let score = score(items, &release);
+                candidates.push((release, score));
+            }
+            Err(err) => println!("{}", tr!("import-release-error", id = id, err = err)),
+        }
+    }
+    candidates.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    candidates
+}
+
+fn tag_track(md: &Metadata, fallback_artist: &str) -> Track {
+    Track {
+        title: md.track_name.clone().unwrap_or_default(),
+        artist: md
+            .artist
+            .clone()
+            .unwrap_or_else(|| fallback_artist.to_owned()),
+        number: md.track_number,
+        track_total: md.track_total,
+        disc: md.disc_number.unwrap_or(1),
+    }
+}
+
+/// Keeps the files as they're tagged.
+fn proposal_from_tags(items: &[Item]) -> Proposal {
+    let (artist, album) = tag_album(items);
+    let first = &items[0].md;
+
+    Proposal {
+        year: first.year.clone(),
+        release_type: first.release_type.clone(),
+        musicbrainz_id: None,
+        disc_total: first.disc_total.unwrap_or(1),
+        tracks: items
+            .iter()
+            .map(|item| tag_track(&item.md, &artist))
+            .collect(),
+        artist,
+        album,
+    }
+}
+
+/// Tags the files as the tracks of `release`, in order; extra files keep their tags.
+fn proposal_from_release(items: &[Item], release: &Release) -> Proposal {
+    let tracks = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match release.tracks.get(i) {
+            Some(track) => Track {
+                title: track.title.clone(),
+                artist: track.artist.clone(),
+                number: track.number,
+                track_total: Some(release.track_total(track.disc)),
+                disc: track.disc,
+            },
+            None => tag_track(&item.md, &release.artist),
+        })
+        .collect();
+
+    Proposal {
+        artist: release.artist.clone(),
+        album: release.title.clone(),
+        year: release.year().map(|year| year.to_owned()),
+        release_type: release.release_type.clone(),
+        musicbrainz_id: Some(release.id.clone()),
+        disc_total: release.disc_total.max(1),
+        tracks,
+    }
+}
+
+//
+// Review
+//
+
+/// Asks a question, returning None at the end of the input.
+fn prompt(question: &str) -> io::Result<Option<String>> {
+    print!("{}", question);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(line.trim().to_owned()))
+}
+
+fn print_proposal(items: &[Item], proposal: &Proposal) {
+    println!(
+        "\n  {} - {}{}",
+        proposal.artist,
+        proposal.album,
+        proposal
+            .year
+            .as_ref()
+            .map_or(String::new(), |year| format!(" ({})", year))
+    );
+    for (item, track) in items.iter().zip(&proposal.tracks) {
+        let number = if proposal.disc_total > 1 {
+            format!("{}-{:02}", track.disc, track.number)
+        } else {
+            format!("{:02}", track.number)
+        };
+        let current = item.md.track_name.as_deref().unwrap_or_default();
+        if current == track.title {
+            println!("    {} {}", number, track.title);
+        } else {
+            println!("    {} {} -> {}", number, current, track.title);
+        }
+    }
+}
+
+/// Asks for new album fields, an empty answer keeping the current value.
+fn edit(proposal: &mut Proposal) -> io::Result<()> {
+    let ask = |name: &str, current: &str| -> io::Result<Option<String>> {
+        let answer = prompt(&format!("{} [{}]: ", name, current))?;
+        Ok(answer.filter(|answer| !answer.is_empty()))
+    };
+
+    if let Some(artist) = ask(&tr!("import-field-album-artist"), &proposal.artist)? {
+        for track in proposal.tracks.iter_mut() {
+            if track.artist == proposal.artist {
+                track.artist = artist.clone();
+            }
+        }
+        proposal.artist = artist;
+    }
+    if let Some(album) = ask(&tr!("import-field-album"), &proposal.album)? {
+        proposal.album = album;
+    }
+    if let Some(year) = ask(
+        &tr!("import-field-year"),
+        proposal.year.as_deref().unwrap_or_default(),
+    )? {
+        proposal.year = Some(year);
+    }
+
+    Ok(())
+}
+
+enum Review {
+    Import(Proposal),
+    Skip,
+    Quit,
+}
+
+fn review(items: &[Item], candidates: &[(Release, i64)]) -> io::Result<Review> {
+    if candidates.is_empty() {
+        println!("  {}", tr!("import-no-match"));
+    }
+    for (i, (release, score)) in candidates.iter().enumerate() {
+        println!(
+            "  {}. [{}%] {}",
+            i + 1,
+            score,
+            tr!(
+                "import-candidate",
+                artist = release.artist,
+                album = release.title,
+                year = release
+                    .year()
+                    .map_or(String::new(), |year| format!(" ({})", year)),
+                tracks = release.tracks.len(),
+                url = release.url()
+            )
+        );
+    }
+
+    let mut proposal = match candidates.first() {
+        Some((release, _)) => proposal_from_release(items, release),
+        None => proposal_from_tags(items),
+    };
+
+    loop {
+        print_proposal(items, &proposal);
+
+        let answer = match prompt(&format!("{} ", tr!("import-prompt")))? {
+            Some(answer) => answer.to_lowercase(),
+            None => return Ok(Review::Quit),
+        };
+
+        match answer.as_str() {
+            "a" | "" => return Ok(Review::Import(proposal)),
+            "e" => edit(&mut proposal)?,
+            "s" => return Ok(Review::Skip),
+            "q" => return Ok(Review::Quit),
+            "t" => proposal = proposal_from_tags(items),
+            _ => match answer
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| candidates.get(n.wrapping_sub(1)))
+            {
+                Some((release, _)) => proposal = proposal_from_release(items, release),
+                None => println!("{}", tr!("import-unknown-answer", answer = answer)),
+            },
+        }
+    }
+}
+
+//
+// Placing the files
+//
+
+/// Makes a name safe to use as a file name.
+fn sanitize(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    let value = value.trim().trim_start_matches('.').trim();
+    if value.is_empty() {
+        "Unknown".to_owned()
+    } else {
+        value.to_owned()
+    }
+}
+
+fn write_id3_tags(path: &Path, proposal: &Proposal, track: &Track) -> Result<(), id3::Error> {
+    let mut tag = id3::Tag::read_from_path(path).unwrap_or_else(|_| id3::Tag::new());
+
+    tag.set_title(track.title.as_str());
+    tag.set_artist(track.artist.as_str());
+    tag.set_album(proposal.album.as_str());
+    tag.set_album_artist(proposal.artist.as_str());
+    if let Some(year) = proposal.year.as_deref().and_then(|year| year.parse().ok()) {
+        tag.set_year(year);
+    }
+    tag.set_track(track.number as u32);
+    if let Some(total) = track.track_total {
+        tag.set_total_tracks(total as u32);
+    }
+    tag.set_disc(track.disc as u32);
+    tag.set_total_discs(proposal.disc_total as u32);
+    if let Some(release_type) = &proposal.release_type {
+        tag.remove_extended_text(Some("RELEASETYPE"), None);
+        tag.add_extended_text("RELEASETYPE", release_type.to_lowercase());
+    }
+    if let Some(id) = &proposal.musicbrainz_id {
+        tag.remove_extended_text(Some("MusicBrainz Album Id"), None);
+        tag.add_extended_text("MusicBrainz Album Id", id.as_str());
+    }
+
+    tag.write_to_path(path, id3::Version::Id3v24)
+}
+
+fn write_flac_tags(path: &Path, proposal: &Proposal, track: &Track) -> Result<(), metaflac::Error> {
+    let mut tag = metaflac::Tag::read_from_path(path)?;
+
+    let mut set = |key: &str, value: String| tag.set_vorbis(key, vec![value]);
+    set("TITLE", track.title.clone());
+    set("ARTIST", track.artist.clone());
+    set("ALBUM", proposal.album.clone());
+    set("ALBUMARTIST", proposal.artist.clone());
+    if let Some(year) = &proposal.year {
+        set("DATE", year.clone());
+    }
+    set("TRACKNUMBER", track.number.to_string());
+    if let Some(total) = track.track_total {
+        set("TRACKTOTAL", total.to_string());
+    }
+    set("DISCNUMBER", track.disc.to_string());
+    set("DISCTOTAL", proposal.disc_total.to_string());
+    if let Some(release_type) = &proposal.release_type {
+        set("RELEASETYPE", release_type.to_lowercase());
+    }
+    if let Some(id) = &proposal.musicbrainz_id {
+        set("MUSICBRAINZ_ALBUMID", id.clone());
+    }
+
+    tag.save()
+}
+
+fn write_tags(path: &Path, proposal: &Proposal, track: &Track) {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    let result = match extension.as_deref() {
+        Some("mp3") => write_id3_tags(path, proposal, track).map_err(|err| err.to_string()),
+        Some("flac") => write_flac_tags(path, proposal, track).map_err(|err| err.to_string()),
+        _ => {
+            println!("{}", tr!("import-tags-unwritable", path = path.display()));
+            return;
+        }
+    };
+    if let Err(err) = result {
+        println!(
+            "{}",
+            tr!("import-tags-error", path = path.display(), err = err)
+        );
+    }
+}
+
+/// Copies or moves a file, copying when it's on another file system.
+fn transfer(from: &Path, to: &Path, move_files: bool) -> io::Result<()> {
+    if move_files && fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to)?;
+    if move_files {
+        fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+/// Files the album into the library, returning how many files were placed.
+fn place(
+    library: &Path,
+    source: &Path,
+    items: &[Item],
+    proposal: &Proposal,
+    move_files: bool,
+) -> io::Result<Vec<PathBuf>> {
+    let album_dir = match &proposal.year {
+        Some(year) => format!("{} ({})", proposal.album, year),
+        None => proposal.album.clone(),
+    };
+    let dir = library
+        .join(sanitize(&proposal.artist))
+        .join(sanitize(&album_dir));
+    fs::create_dir_all(&dir)?;
+
+    let mut placed = Vec::new();
+    for (item, track) in items.iter().zip(&proposal.tracks) {
+        let extension = item
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let number = if proposal.disc_total > 1 {
+            format!("{}-{:02}", track.disc, track.number)
+        } else {
+            format!("{:02}", track.number)
+        };
+        let path = dir.join(format!(
+            "{} {}.{}",
+            number,
+            sanitize(&track.title),
+            extension
+        ));
+
+        if path.exists() {
+            println!("{}", tr!("import-exists", path = path.display()));
+            continue;
+        }
+        // Found before the track is moved, they're named after it
+        let sidecars = sidecar::find_for_track(&item.path);
+        transfer(&item.path, &path, move_files)?;
+        write_tags(&path, proposal, track);
+        println!("{} -> {}", item.path.display(), path.display());
+
+        // Renamed like the track, so players still find its lyrics
+        for (_, sidecar) in sidecars {
+            let to = path.with_extension(sidecar.extension().unwrap_or_default());
+            if !to.exists() {
+                transfer(&sidecar, &to, move_files)?;
+            }
+        }
+        placed.push(path);
+    }
+
+    let tracks: Vec<PathBuf> = items.iter().map(|item| item.path.clone()).collect();
+    for (_, sidecar) in sidecar::find_for_album(source, &tracks) {
+        if let Some(name) = sidecar.file_name() {
+            let path = dir.join(name);
+            if !path.exists() {
+                transfer(&sidecar, &path, move_files)?;
+            }
+        }
+    }
+    if move_files {
+        // Only removed once nothing is left in it
+        let _ = fs::remove_dir(source);
+    }
+
+    Ok(placed)
+}
+
+//
+// Store profiles
+//
+
+/// A store whose downloads are laid out in a known way.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Profile {
+    /// Zips named "Artist - Album" of files named "Artist - Album - 01 Title".
+    Bandcamp,
+    /// Folders named "Artist - Album (2020) [FLAC] [24B-44.1kHz]" of files named "01. Title",
+    /// with a "Disc 1" folder per disc for albums with several.
+    Qobuz,
+}
+
+impl Profile {
+    pub const ALL: &'static [&'static str] = &["bandcamp", "qobuz"];
+
+    pub fn parse(value: &str) -> Option<Profile> {
+        match value {
+            "bandcamp" => Some(Profile::Bandcamp),
+            "qobuz" => Some(Profile::Qobuz),
+            _ => None,
+        }
+    }
+
+    /// Parses the name of an album folder or zip into its artist, album and year.
+    fn parse_album(self, name: &str) -> Option<(String, String, Option<String>)> {
+        let mut name = name.trim();
+        let mut year = None;
+        if self == Profile::Qobuz {
+            while let Some(rest) = name
+                .strip_suffix(']')
+                .and_then(|rest| rest.rsplit_once(" ["))
+            {
+                name = rest.0.trim_end();
+            }
+            if let Some((rest, value)) = name
+                .strip_suffix(')')
+                .and_then(|rest| rest.rsplit_once(" ("))
+            {
+                if value.len() == 4 && value.chars().all(|c| c.is_ascii_digit()) {
+                    year = Some(value.to_owned());
+                    name = rest.trim_end();
+                }
+            }
+        }
+
+        let (artist, album) = name.split_once(" - ")?;
+        Some((artist.trim().to_owned(), album.trim().to_owned(), year))
+    }
+
+    /// Parses the name of a track file, without its extension, into its number and title.
+    fn parse_track(self, name: &str, artist: &str, album: &str) -> Option<(usize, String)> {
+        let rest = match self {
+            Profile::Bandcamp => name
+                .strip_prefix(&format!("{} - {} - ", artist, album))
+                .unwrap_or(name),
+            Profile::Qobuz => name,
+        };
+
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let number = rest[..digits].parse().ok()?;
+        let title = rest[digits..].trim_start_matches(['.', '-', ' ']).trim();
+        if title.is_empty() {
+            return None;
+        }
+
+        Some((number, title.to_owned()))
+    }
+}
+
+/// Unpacks a zip into a new folder of `dir` named after it.
+fn unzip(zip: &Path, dir: &Path) -> Result<PathBuf, CommandImportError> {
+    let output_dir = dir.join(zip.file_stem().unwrap_or_default());
+    fs::create_dir_all(&output_dir)?;
+
+    let output = match process::Command::new("unzip")
+        .arg("-q")
+        .arg("-o")
+        .arg(zip)
+        .arg("-d")
+        .arg(&output_dir)
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(CommandImportError::UnzipNotFound)
+        }
+        Err(err) => return Err(CommandImportError::IO(err)),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(CommandImportError::Unzip(stderr.trim().to_owned()));
+    }
+
+    Ok(output_dir)
+}
+
+/// Keeps the files as they're tagged, filling what's missing from the file names.
+fn proposal_from_profile(profile: Profile, root: &Path, source: &Path, items: &[Item]) -> Proposal {
+    let mut proposal = proposal_from_tags(items);
+
+    // The folders of the discs aren't named after the album
+    let folder_name = |dir: &Path| -> Option<String> {
+        dir.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    };
+    let album = folder_name(source)
+        .and_then(|name| profile.parse_album(&name))
+        .or_else(|| folder_name(root).and_then(|name| profile.parse_album(&name)));
+
+    if let Some((artist, album, year)) = album {
+        if proposal.artist.is_empty() {
+            proposal.artist = artist;
+        }
+        if proposal.album.is_empty() {
+            proposal.album = album;
+        }
+        if proposal.year.is_none() {
+            proposal.year = year;
+        }
+    }
+
+    for (item, track) in items.iter().zip(proposal.tracks.iter_mut()) {
+        let stem = item
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (number, title) = match profile.parse_track(&stem, &proposal.artist, &proposal.album) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        if track.number == 0 {
+            track.number = number;
+        }
+        if track.title.is_empty() {
+            track.title = title;
+        }
+        if track.artist.is_empty() {
+            track.artist = proposal.artist.clone();
+        }
+    }
+
+    proposal
+}
+
+/// Files the downloads of a store into the library, returning the files placed.
+fn import_profile(
+    db: &rusqlite::Connection,
+    library: &Path,
+    profile: Profile,
+    path: &Path,
+    move_files: bool,
+) -> Result<Vec<PathBuf>, CommandImportError> {
+    let is_zip = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+
+    let tmp_dir = env::temp_dir().join(format!("zik-import-{}", process::id()));
+    let root = if is_zip {
+        unzip(path, &tmp_dir)?
+    } else {
+        path.to_path_buf()
+    };
+
+    let result = load_items(&root, &IgnoreRules::load(db)?).and_then(|groups| {
+        let mut imported = Vec::new();
+        for (source, items) in &groups {
+            println!(
+                "\n{}",
+                tr!("import-group", path = source.display(), files = items.len())
+            );
+
+            let proposal = proposal_from_profile(profile, &root, source, items);
+            imported.append(&mut place(
+                library,
+                source,
+                items,
+                &proposal,
+                is_zip || move_files,
+            )?);
+        }
+        Ok(imported)
+    });
+
+    if is_zip {
+        let _ = fs::remove_dir_all(&tmp_dir);
+    }
+
+    result
+}
+
+//
+// "import" command
+//
+
+/// Reads the audio files under `dir`, grouped by folder.
+fn load_items(
+    dir: &Path,
+    ignore_rules: &IgnoreRules,
+) -> Result<BTreeMap<PathBuf, Vec<Item>>, CommandImportError> {
+    let mut groups: BTreeMap<PathBuf, Vec<Item>> = BTreeMap::new();
+
+    let walker = walkdir::WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| !ignore_rules.is_ignored(entry));
+    for result in walker {
+        let entry = result?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let md = match Metadata::read_from_path(path, &tags::CustomFields::default())? {
+            Some(md) if !md.video => md,
+            _ => continue,
+        };
+        let parent = path.parent().unwrap_or(dir).to_path_buf();
+        groups.entry(parent).or_default().push(Item {
+            path: path.to_path_buf(),
+            md,
+        });
+    }
+
+    for items in groups.values_mut() {
+        items.sort_by(|a, b| {
+            (a.md.disc_number.unwrap_or(1), a.md.track_number, &a.path).cmp(&(
+                b.md.disc_number.unwrap_or(1),
+                b.md.track_number,
+                &b.path,
+            ))
+        });
+    }
+
+    Ok(groups)
+}
+
+fn library_path(db: &rusqlite::Connection) -> Result<PathBuf, CommandImportError> {
+    match crate::library::location(db)? {
+        Some(library) if crate::storage::is_remote(&library) => {
+            Err(CommandImportError::RemoteLibrary(library))
+        }
+        Some(library) => Ok(PathBuf::from(library)),
+        None => Err(CommandImportError::NoLibrary),
+    }
+}
+
+/// Files the albums into the library, after reviewing their best match on MusicBrainz when
+/// `match_releases` is set, returning the files placed.
+fn import_groups(
+    db: &rusqlite::Connection,
+    library: &Path,
+    groups: &BTreeMap<PathBuf, Vec<Item>>,
+    match_releases: bool,
+    move_files: bool,
+) -> Result<Vec<PathBuf>, CommandImportError> {
+    let acoustid_api_key = crate::secrets::get(db, "acoustid_api_key")?;
+
+    let mut imported = Vec::new();
+    for (source, items) in groups {
+        println!(
+            "\n{}",
+            tr!("import-group", path = source.display(), files = items.len())
+        );
+
+        let proposal = if match_releases {
+            let candidates = find_candidates(items, acoustid_api_key.as_deref());
+            match review(items, &candidates)? {
+                Review::Import(proposal) => proposal,
+                Review::Skip => continue,
+                Review::Quit => break,
+            }
+        } else {
+            proposal_from_tags(items)
+        };
+        imported.append(&mut place(library, source, items, &proposal, move_files)?);
+    }
+
+    Ok(imported)
+}
+
+/// Files the tracks ripped from a disc into `dir` as one of `releases`, after review,
+/// returning how many files were placed.
+pub fn import_ripped(
+    db: &mut rusqlite::Connection,
+    dir: &Path,
+    releases: Vec<Release>,
+) -> Result<usize, CommandImportError> {
+    let library = library_path(db)?;
+    let items = match load_items(dir, &IgnoreRules::load(db)?)?.remove(dir) {
+        Some(items) => items,
+        None => return Ok(0),
+    };
+
+    let mut candidates: Vec<(Release, i64)> = releases
+        .into_iter()
+        .map(|release| {
+            let score = score(&items, &release);
+            (release, score)
+        })
+        .collect();
+    candidates.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    println!("\n{}", tr!("import-ripped", tracks = items.len()));
+    let placed = match review(&items, &candidates)? {
+        Review::Import(proposal) => place(&library, dir, &items, &proposal, true)?,
+        Review::Skip | Review::Quit => Vec::new(),
+    };
+
+    println!("\n{}", tr!("import-imported", files = placed.len()));
+    if !placed.is_empty() {
+        let summary = crate::scan_library(db, &mut throttle::Throttle::default())?;
+        source::set_for_paths(db, &placed, source::CD_RIP)?;
+        notify::scan_completed(db, &summary);
+    }
+
+    Ok(placed.len())
+}
+
+fn cmd_import_approve(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandImportError> {
+    let library = library_path(db)?;
+    let inbox = match inbox::path(db)? {
+        Some(inbox) => inbox,
+        None => return Err(CommandImportError::NoInbox),
+    };
+
+    // Folders are relative to the inbox, or absolute
+    let mut folders = Vec::new();
+    if args.is_present("all") {
+        folders.push(inbox.clone());
+    }
+    for value in args.values_of("folder").into_iter().flatten() {
+        let folder = fs::canonicalize(inbox.join(value))
+            .map_err(|_| CommandImportError::NotInInbox(value.to_owned()))?;
+        if !folder.starts_with(&inbox) {
+            return Err(CommandImportError::NotInInbox(value.to_owned()));
+        }
+        folders.push(folder);
+    }
+
+    let ignore_rules = IgnoreRules::load(db)?;
+    let mut groups = BTreeMap::new();
+    for folder in &folders {
+        groups.append(&mut load_items(folder, &ignore_rules)?);
+    }
+    if groups.is_empty() {
+        println!("{}", tr!("import-nothing-staged"));
+        return Ok(());
+    }
+
+    let imported = import_groups(db, &library, &groups, args.is_present("match"), true)?;
+
+    println!("\n{}", tr!("import-approved", files = imported.len()));
+    inbox::scan(db)?;
+    if !imported.is_empty() {
+        let summary = crate::scan_library(db, &mut throttle::Throttle::default())?;
+        if let Some(value) = args.value_of("source") {
+            source::set_for_paths(db, &imported, value)?;
+        }
+        notify::scan_completed(db, &summary);
+    }
+
+    Ok(())
+}
+
+pub fn cmd_import(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandImportError> {
+    match args.subcommand() {
+        Some(("staged", _)) => {
+            let inbox = match inbox::path(db)? {
+                Some(inbox) => inbox,
+                None => return Err(CommandImportError::NoInbox),
+            };
+            inbox::print_staged(db, &inbox)?;
+            return Ok(());
+        }
+        Some(("approve", sub_args)) => return cmd_import_approve(db, sub_args),
+        _ => (),
+    }
+
+    let library = library_path(db)?;
+    let dir = fs::canonicalize(args.value_of("dir").unwrap())?;
+    if dir.starts_with(&library) {
+        return Err(CommandImportError::InsideLibrary(dir));
+    }
+
+    let profile = args.value_of("profile").and_then(Profile::parse);
+    let imported = match profile {
+        Some(profile) => import_profile(db, &library, profile, &dir, args.is_present("move"))?,
+        None => {
+            let groups = load_items(&dir, &IgnoreRules::load(db)?)?;
+            if groups.is_empty() {
+                println!("{}", tr!("import-no-audio", path = dir.display()));
+                return Ok(());
+            }
+
+            import_groups(db, &library, &groups, true, args.is_present("move"))?
+        }
+    };
+
+    println!("\n{}", tr!("import-imported", files = imported.len()));
+    if !imported.is_empty() {
+        let summary = crate::scan_library(db, &mut throttle::Throttle::default())?;
+        // A profile is named after its store
+        let value = args
+            .value_of("source")
+            .or_else(|| profile.and(args.value_of("profile")));
+        if let Some(value) = value {
+            source::set_for_paths(db, &imported, value)?;
+        }
+        notify::scan_completed(db, &summary);
+    }
+
+    Ok(())
+}
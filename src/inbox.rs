@@ -0,0 +1,120 @@
+//! The inbox: a folder for new downloads, scanned apart from the library.
+//!
+//! Its files are indexed in `staged_track`, never as tracks of the library, and only join it
+//! through `zik import approve`, so half-tagged downloads don't pollute the index.
+
+use std::path::{Path, PathBuf};
+
+use crate::i18n::tr;
+use crate::{ignore, netfs, CommandScanError, Metadata};
+
+/// Returns the inbox, if one is configured.
+pub fn path(db: &rusqlite::Connection) -> rusqlite::Result<Option<PathBuf>> {
+    Ok(crate::get_config_value(db, "inbox")?
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from))
+}
+
+/// Indexes the files of the inbox again, returning how many are staged.
+pub fn scan(db: &mut rusqlite::Connection) -> Result<usize, CommandScanError> {
+    let inbox = match path(db)? {
+        Some(inbox) => inbox,
+        None => return Ok(0),
+    };
+
+    let savepoint = db.savepoint()?;
+    savepoint.execute("DELETE FROM staged_track", [])?;
+
+    let io_options = netfs::IoOptions::load(&savepoint)?;
+    let ignore_rules = ignore::IgnoreRules::load(&savepoint)?;
+    let walker = walkdir::WalkDir::new(&inbox)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(move |entry| !ignore_rules.is_ignored(entry));
+
+    let mut staged = 0;
+    for result in netfs::prefetch(&io_options, &inbox, walker) {
+        let entry = result?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let file_path = entry.path();
+        let md = match Metadata::read_with_options(&io_options, &Default::default(), file_path)? {
+            Some(md) if !md.video => md,
+            _ => continue,
+        };
+        let folder = file_path.parent().unwrap_or(&inbox);
+
+        savepoint.execute(
+            "INSERT INTO staged_track(path, folder, name, artist, album, album_artist, year, number, genre)
+             VALUES($path, $folder, $name, $artist, $album, $album_artist, $year, $number, $genre)",
+            rusqlite::params![
+                file_path.to_string_lossy(),
+                folder.to_string_lossy(),
+                md.track_name,
+                md.artist,
+                md.album,
+                md.album_artist,
+                md.year,
+                md.track_number,
+                md.genre,
+            ],
+        )?;
+        staged += 1;
+    }
+
+    savepoint.commit()?;
+
+    println!("{}", tr!("inbox-staged", files = staged));
+
+    Ok(staged)
+}
+
+/// Lists the staged folders with what's missing from their tags.
+pub fn print_staged(db: &rusqlite::Connection, inbox: &Path) -> rusqlite::Result<()> {
+    let mut stmt = db.prepare(
+        "SELECT folder, COUNT(*),
+                coalesce(MAX(album_artist), MAX(artist)), MAX(album),
+                SUM(name IS NULL OR artist IS NULL OR album IS NULL OR number = 0)
+         FROM staged_track
+         GROUP BY folder
+         ORDER BY folder",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut folders = 0;
+    while let Some(row) = rows.next()? {
+        let folder: String = row.get(0)?;
+        let files: i64 = row.get(1)?;
+        let artist: Option<String> = row.get(2)?;
+        let album: Option<String> = row.get(3)?;
+        let untagged: i64 = row.get(4)?;
+        folders += 1;
+
+        let folder = Path::new(&folder);
+        let name = folder.strip_prefix(inbox).unwrap_or(folder);
+        let name = if name.as_os_str().is_empty() {
+            ".".to_owned()
+        } else {
+            name.display().to_string()
+        };
+        print!(
+            "{}\t{}\t{} - {}",
+            name,
+            tr!("inbox-files", files = files),
+            artist.unwrap_or_else(|| tr!("inbox-unknown")),
+            album.unwrap_or_else(|| tr!("inbox-unknown"))
+        );
+        if untagged > 0 {
+            print!("\t{}", tr!("inbox-untagged", files = untagged));
+        }
+        println!();
+    }
+
+    if folders == 0 {
+        println!("{}", tr!("inbox-empty"));
+    }
+
+    Ok(())
+}
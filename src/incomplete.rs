@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::i18n::tr;
+
+pub enum CommandIncompleteError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for CommandIncompleteError {
+    fn from(err: rusqlite::Error) -> CommandIncompleteError {
+        CommandIncompleteError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandIncompleteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandIncompleteError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Disc {
+    numbers: BTreeSet<usize>,
+    track_total: Option<usize>,
+}
+
+impl Disc {
+    /// The number of tracks the disc should have: the tagged total if there is one,
+    /// otherwise the highest track number seen, which still reveals gaps.
+    fn expected(&self) -> usize {
+        let highest = self.numbers.iter().next_back().copied().unwrap_or(0);
+        self.track_total.unwrap_or(0).max(highest)
+    }
+
+    fn missing(&self) -> Vec<usize> {
+        (1..=self.expected())
+            .filter(|n| !self.numbers.contains(n))
+            .collect()
+    }
+}
+
+struct Album {
+    name: String,
+    artist: String,
+    disc_total: Option<usize>,
+    discs: BTreeMap<usize, Disc>,
+}
+
+impl Album {
+    fn missing_discs(&self) -> Vec<usize> {
+        let expected = self.disc_total.unwrap_or(0);
+        (1..=expected)
+            .filter(|n| !self.discs.contains_key(n))
+            .collect()
+    }
+}
+
+fn format_numbers(numbers: &[usize]) -> String {
+    numbers
+        .iter()
+        .map(|n| format!("#{}", n))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn load_albums(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Album>> {
+    let query = "
+        SELECT album.id, album.name, artist.name, track.disc_number, track.disc_total, track.number, track.track_total
+        FROM track
+        INNER JOIN album ON album.id = track.album_id
+        LEFT JOIN artist ON artist.id = album.artist_id
+        ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort";
+
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query([])?;
+
+    let mut albums: Vec<Album> = Vec::new();
+    let mut last_album_id: Option<i64> = None;
+
+    while let Some(row) = rows.next()? {
+        let album_id: i64 = row.get(0)?;
+        let disc_number: Option<usize> = row.get(3)?;
+        let disc_total: Option<usize> = row.get(4)?;
+        let number: Option<usize> = row.get(5)?;
+        let track_total: Option<usize> = row.get(6)?;
+
+        if last_album_id != Some(album_id) {
+            let name: Option<String> = row.get(1)?;
+            let artist: Option<String> = row.get(2)?;
+
+            albums.push(Album {
+                name: name.unwrap_or_default(),
+                artist: artist.unwrap_or_default(),
+                disc_total: None,
+                discs: BTreeMap::new(),
+            });
+            last_album_id = Some(album_id);
+        }
+
+        let album = albums.last_mut().unwrap();
+        album.disc_total = album.disc_total.max(disc_total);
+
+        let disc = album.discs.entry(disc_number.unwrap_or(1)).or_default();
+        disc.track_total = disc.track_total.max(track_total);
+        if let Some(number) = number.filter(|n| *n > 0) {
+            disc.numbers.insert(number);
+        }
+    }
+
+    Ok(albums)
+}
+
+pub fn cmd_incomplete(
+    db: &mut rusqlite::Connection,
+    _args: &clap::ArgMatches,
+) -> Result<(), CommandIncompleteError> {
+    let albums = load_albums(db)?;
+
+    for album in albums {
+        let mut problems = Vec::new();
+
+        let multi_disc = album.discs.len() > 1 || album.disc_total.unwrap_or(1) > 1;
+
+        for (disc_number, disc) in album.discs.iter() {
+            let missing = disc.missing();
+            if missing.is_empty() {
+                continue;
+            }
+
+            let problem = tr!(
+                "incomplete-tracks",
+                tracks = disc.expected() - missing.len(),
+                expected = disc.expected(),
+                missing = format_numbers(&missing)
+            );
+            if multi_disc {
+                problems.push(tr!(
+                    "incomplete-disc",
+                    disc = disc_number,
+                    problem = problem
+                ));
+            } else {
+                problems.push(problem);
+            }
+        }
+
+        let missing_discs = album.missing_discs();
+        if !missing_discs.is_empty() {
+            problems.push(tr!(
+                "incomplete-discs",
+                discs = album.disc_total.unwrap_or(0) - missing_discs.len(),
+                expected = album.disc_total.unwrap_or(0),
+                missing = format_numbers(&missing_discs)
+            ));
+        }
+
+        for problem in problems {
+            println!("{} - {}: {}", album.artist, album.name, problem);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! Genres of untagged tracks, inferred from the genres of their artist on Last.fm or
+//! MusicBrainz.
+//!
+//! Nothing is inferred unless `zik infer genres` is run. The genre found for an artist is cached
+//! in `inferred_genre` by artist name, like `artist_info`, and given to their tracks without a
+//! GENRE tag, which get `genre_inferred` set. A genre in the tags always wins: scans put the
+//! tagged genre back and only give the inferred one to the tracks still without one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use crate::genre;
+use crate::http::{self, HttpError};
+use crate::i18n::tr;
+use crate::json;
+
+const DEFAULT_TTL_DAYS: usize = 30;
+
+/// MusicBrainz allows one request per second.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The lowest score of a MusicBrainz search result taken for the artist searched.
+const MIN_SEARCH_SCORE: i64 = 90;
+
+pub enum CommandInferError {
+    SQLite(rusqlite::Error),
+    Http(HttpError),
+    MissingApiKey,
+}
+impl From<rusqlite::Error> for CommandInferError {
+    fn from(err: rusqlite::Error) -> CommandInferError {
+        CommandInferError::SQLite(err)
+    }
+}
+impl From<HttpError> for CommandInferError {
+    fn from(err: HttpError) -> CommandInferError {
+        CommandInferError::Http(err)
+    }
+}
+impl fmt::Display for CommandInferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandInferError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandInferError::Http(err) => write!(f, "{}", err),
+            CommandInferError::MissingApiKey => f.write_str(&tr!("infer-missing-api-key")),
+        }
+    }
+}
+
+enum Source {
+    LastFm(String),
+    MusicBrainz,
+}
+
+impl Source {
+    fn name(&self) -> &'static str {
+        match self {
+            Source::LastFm(_) => "lastfm",
+            Source::MusicBrainz => "musicbrainz",
+        }
+    }
+}
+
+/// Gives the inferred genres to the tracks without a tagged one.
+pub fn apply_genres(db: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    db.execute(
+        "UPDATE track
+         SET genre = inferred.genre, genre_inferred = 1
+         FROM artist
+         JOIN inferred_genre inferred ON inferred.artist_name = artist.name
+         WHERE artist.id = track.artist_id
+           AND inferred.genre IS NOT NULL
+           AND (track.genre IS NULL OR track.genre_inferred)",
+        [],
+    )
+}
+
+/// Picks the first of `names`, most popular first, which is a known genre.
+fn pick_genre<'a>(
+    names: impl Iterator<Item = &'a str>,
+    aliases: &genre::GenreAliases,
+    known: &HashMap<String, String>,
+) -> Option<String> {
+    names
+        .filter_map(|name| aliases.resolve(name))
+        .find_map(|name| known.get(&genre::alias_key(&name)).cloned())
+}
+
+/// Returns the names of the top tags of an artist on Last.fm, most used first. Most of them are
+/// genres, the others like "seen live" aren't known genres.
+fn fetch_lastfm(api_key: &str, name: &str) -> Result<Vec<String>, HttpError> {
+    let url = format!(
+        "https://ws.audioscrobbler.com/2.0/?method=artist.gettoptags&autocorrect=1&format=json&artist={}&api_key={}",
+        http::percent_encode(name),
+        http::percent_encode(api_key),
+    );
+    let response = http::get_json(&url)?;
+
+    let tags = response
+        .get("toptags")
+        .and_then(|toptags| toptags.get("tag"))
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|tag| tag.get("name").and_then(json::Value::as_str))
+        .map(|name| name.to_owned())
+        .collect();
+
+    Ok(tags)
+}
+
+/// Returns the genres of an artist on MusicBrainz, most voted first. The artist is searched by
+/// name unless `zik enrich artists` found their MusicBrainz ID.
+fn fetch_musicbrainz(musicbrainz_id: Option<String>, name: &str) -> Result<Vec<String>, HttpError> {
+    let id = match musicbrainz_id {
+        Some(id) => id,
+        None => {
+            let query = format!("artist:\"{}\"", name.replace('"', ""));
+            let url = format!(
+                "https://musicbrainz.org/ws/2/artist?fmt=json&limit=1&query={}",
+                http::percent_encode(&query),
+            );
+            let response = http::get_json(&url)?;
+
+            let artist = response
+                .get("artists")
+                .map(json::Value::as_array)
+                .unwrap_or_default()
+                .first()
+                .filter(|artist| {
+                    let score = artist.get("score").and_then(json::Value::as_i64);
+                    score.unwrap_or_default() >= MIN_SEARCH_SCORE
+                });
+            match artist
+                .and_then(|artist| artist.get("id"))
+                .and_then(json::Value::as_str)
+            {
+                Some(id) => {
+                    thread::sleep(REQUEST_INTERVAL);
+                    id.to_owned()
+                }
+                None => return Ok(Vec::new()),
+            }
+        }
+    };
+
+    let url = format!(
+        "https://musicbrainz.org/ws/2/artist/{}?fmt=json&inc=genres",
+        http::percent_encode(&id),
+    );
+    let response = http::get_json(&url)?;
+
+    let mut genres: Vec<(i64, String)> = response
+        .get("genres")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|genre| {
+            let name = genre.get("name").and_then(json::Value::as_str)?;
+            let count = genre.get("count").and_then(json::Value::as_i64);
+            Some((count.unwrap_or_default(), name.to_owned()))
+        })
+        .collect();
+    genres.sort_by_key(|(count, _)| -count);
+
+    Ok(genres.into_iter().map(|(_, name)| name).collect())
+}
+
+fn save_genre(
+    db: &rusqlite::Connection,
+    name: &str,
+    source: &Source,
+    genre: Option<&str>,
+) -> rusqlite::Result<()> {
+    db.execute(
+        "INSERT INTO inferred_genre(artist_name, source, genre)
+         VALUES($name, $source, $genre)
+         ON CONFLICT(artist_name) DO UPDATE SET
+           source = excluded.source,
+           genre = excluded.genre,
+           fetched_at = unixepoch()",
+        rusqlite::params![name, source.name(), genre],
+    )?;
+
+    Ok(())
+}
+
+fn get_source(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<Source, CommandInferError> {
+    let api_key = crate::secrets::get(db, "lastfm_api_key")?;
+
+    match (args.value_of("source"), api_key) {
+        (Some("lastfm") | None, Some(api_key)) => Ok(Source::LastFm(api_key)),
+        (Some("lastfm"), None) => Err(CommandInferError::MissingApiKey),
+        _ => Ok(Source::MusicBrainz),
+    }
+}
+
+//
+// "infer" command
+//
+
+fn cmd_infer_genres_clear(db: &mut rusqlite::Connection) -> Result<(), CommandInferError> {
+    let savepoint = db.savepoint()?;
+    let cleared = savepoint.execute(
+        "UPDATE track SET genre = NULL, genre_inferred = 0 WHERE genre_inferred",
+        [],
+    )?;
+    savepoint.execute("DELETE FROM inferred_genre", [])?;
+    savepoint.commit()?;
+
+    println!("{}", tr!("infer-cleared", tracks = cleared));
+
+    Ok(())
+}
+
+fn cmd_infer_genres(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandInferError> {
+    if args.is_present("clear") {
+        return cmd_infer_genres_clear(db);
+    }
+
+    let source = get_source(db, args)?;
+    let ttl_days = crate::get_config_usize(db, "enrich_ttl")?.unwrap_or(DEFAULT_TTL_DAYS);
+    let force = args.is_present("force");
+
+    // Only the artists with untagged tracks
+    let mut names: Vec<String> = {
+        let mut stmt = db.prepare(
+            "SELECT DISTINCT artist.name
+             FROM track
+             JOIN artist ON artist.id = track.artist_id
+             LEFT JOIN inferred_genre ON inferred_genre.artist_name = artist.name
+             WHERE (track.genre IS NULL OR track.genre_inferred)
+               AND track.missing_since IS NULL
+               AND artist.name <> 'Unknown'
+               AND ($force OR inferred_genre.fetched_at IS NULL
+                    OR inferred_genre.fetched_at < unixepoch() - $ttl * 86400)
+             ORDER BY artist.sort_name COLLATE natural_sort",
+        )?;
+        let names = stmt
+            .query_map(rusqlite::params![force, ttl_days], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        names
+    };
+
+    if let Some(only) = args.values_of("artist") {
+        let only: Vec<String> = only.map(|name| name.to_lowercase()).collect();
+        names.retain(|name| only.contains(&name.to_lowercase()));
+    }
+
+    let aliases = genre::GenreAliases::load(db)?;
+    let known = genre::known_genres(db)?;
+
+    for (i, name) in names.iter().enumerate() {
+        if i > 0 {
+            thread::sleep(REQUEST_INTERVAL);
+        }
+
+        let result = match &source {
+            Source::LastFm(api_key) => fetch_lastfm(api_key, name),
+            Source::MusicBrainz => {
+                let musicbrainz_id =
+                    crate::enrich::get_info(db, name)?.and_then(|info| info.musicbrainz_id);
+                fetch_musicbrainz(musicbrainz_id, name)
+            }
+        };
+
+        match result {
+            Ok(names_found) => {
+                let genre = pick_genre(names_found.iter().map(String::as_str), &aliases, &known);
+                save_genre(db, name, &source, genre.as_deref())?;
+                match genre {
+                    Some(genre) => println!("{}: {}", name, genre),
+                    None => println!(
+                        "{}",
+                        tr!("infer-not-found", name = name, source = source.name())
+                    ),
+                }
+            }
+            Err(err @ HttpError::NotFound) => return Err(err.into()),
+            Err(err) => println!("{}: {}", name, err),
+        }
+    }
+
+    let applied = apply_genres(db)?;
+    println!(
+        "{}",
+        tr!("infer-summary", artists = names.len(), tracks = applied)
+    );
+
+    Ok(())
+}
+
+pub fn cmd_infer(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandInferError> {
+    match args.subcommand() {
+        Some(("genres", sub_args)) => cmd_infer_genres(db, sub_args),
+        _ => Ok(()),
+    }
+}
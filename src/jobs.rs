@@ -0,0 +1,673 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use rusqlite::types::Value;
+
+use crate::i18n::tr;
+use crate::{artwork, ffmpeg, hash};
+
+/// Expensive work that runs after a scan, outside of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JobKind {
+    Hash,
+    Fingerprint,
+    Analyze,
+    FetchCover,
+    Transcode,
+}
+
+/// What a job works on.
+#[derive(PartialEq)]
+enum JobTarget {
+    Track,
+    Album,
+}
+
+impl JobKind {
+    const ALL: [JobKind; 5] = [
+        JobKind::Hash,
+        JobKind::Fingerprint,
+        JobKind::Analyze,
+        JobKind::FetchCover,
+        JobKind::Transcode,
+    ];
+
+    /// The kinds of jobs queued for every scanned track.
+    const ON_SCAN: [JobKind; 2] = [JobKind::Hash, JobKind::Fingerprint];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::Hash => "hash",
+            JobKind::Fingerprint => "fingerprint",
+            JobKind::Analyze => "analyze",
+            JobKind::FetchCover => "fetch-cover",
+            JobKind::Transcode => "transcode",
+        }
+    }
+
+    pub fn from_str(value: &str) -> Option<JobKind> {
+        JobKind::ALL
+            .iter()
+            .find(|kind| kind.as_str() == value)
+            .copied()
+    }
+
+    fn target(&self) -> JobTarget {
+        match self {
+            JobKind::FetchCover => JobTarget::Album,
+            _ => JobTarget::Track,
+        }
+    }
+
+    /// The table and column filled by this kind of job.
+    fn column(&self) -> (&'static str, &'static str) {
+        match self {
+            JobKind::Hash => ("track", "hash"),
+            JobKind::Fingerprint => ("track", "fingerprint"),
+            JobKind::Analyze => ("track", "loudness"),
+            JobKind::FetchCover => ("album", "cover_path"),
+            JobKind::Transcode => ("track", "transcode_path"),
+        }
+    }
+
+    /// Higher priority jobs run first. Cheap jobs other features depend on go first.
+    pub fn default_priority(&self) -> i64 {
+        match self {
+            JobKind::Hash => 30,
+            JobKind::Fingerprint | JobKind::FetchCover => 20,
+            JobKind::Analyze => 10,
+            JobKind::Transcode => 0,
+        }
+    }
+}
+
+/// Queues a job of the given kind for every track or album missing its result.
+///
+/// Jobs already queued, including failed ones, are left untouched. Returns the number of
+/// jobs queued.
+pub fn enqueue_missing(
+    db: &rusqlite::Connection,
+    kind: JobKind,
+    priority: i64,
+) -> rusqlite::Result<usize> {
+    let (table, column) = kind.column();
+
+    let query = match kind.target() {
+        JobTarget::Track => format!(
+            "INSERT OR IGNORE INTO job(kind, track_id, priority)
+             SELECT $kind, id, $priority FROM {} WHERE {} IS NULL AND path IS NOT NULL",
+            table, column,
+        ),
+        JobTarget::Album => format!(
+            "INSERT OR IGNORE INTO job(kind, album_id, priority)
+             SELECT $kind, id, $priority FROM {} WHERE {} IS NULL",
+            table, column,
+        ),
+    };
+
+    db.execute(&query, rusqlite::params![kind.as_str(), priority])
+}
+
+/// Queues the jobs run on every scanned track, unless their result is already known.
+pub fn enqueue_for_track(db: &rusqlite::Connection, track_id: usize) -> rusqlite::Result<()> {
+    for kind in JobKind::ON_SCAN {
+        let query = format!(
+            "INSERT OR IGNORE INTO job(kind, track_id, priority)
+             SELECT $kind, id, $priority FROM track WHERE id = $id AND {} IS NULL",
+            kind.column().1,
+        );
+        db.execute(
+            &query,
+            rusqlite::params![kind.as_str(), kind.default_priority(), track_id],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub enum JobError {
+    IO(io::Error),
+    Ffmpeg(ffmpeg::FfmpegError),
+    FingerprinterNotFound,
+    Fingerprinter(String),
+    NoPath,
+    NoCover,
+}
+impl From<io::Error> for JobError {
+    fn from(err: io::Error) -> JobError {
+        JobError::IO(err)
+    }
+}
+impl From<ffmpeg::FfmpegError> for JobError {
+    fn from(err: ffmpeg::FfmpegError) -> JobError {
+        JobError::Ffmpeg(err)
+    }
+}
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobError::IO(err) => f.write_str(&tr!("import-io", err = err)),
+            JobError::Ffmpeg(err) => write!(f, "{}", err),
+            JobError::FingerprinterNotFound => f.write_str(&tr!("jobs-fpcalc-not-found")),
+            JobError::Fingerprinter(err) => f.write_str(&tr!("jobs-fpcalc-error", err = err)),
+            JobError::NoPath => f.write_str(&tr!("jobs-no-path")),
+            JobError::NoCover => f.write_str(&tr!("jobs-no-cover")),
+        }
+    }
+}
+
+/// Computes the Chromaprint fingerprint of a file by running `fpcalc`.
+pub fn fingerprint(path: &Path) -> Result<String, JobError> {
+    let output = match process::Command::new("fpcalc").arg(path).output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(JobError::FingerprinterNotFound)
+        }
+        Err(err) => return Err(JobError::IO(err)),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(JobError::Fingerprinter(stderr.trim().to_owned()));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("FINGERPRINT="))
+    {
+        Some(fingerprint) => Ok(fingerprint.to_owned()),
+        None => Err(JobError::Fingerprinter(
+            "no fingerprint in output".to_owned(),
+        )),
+    }
+}
+
+/// Settings shared by all the jobs of a run.
+struct JobContext {
+    data_dir: PathBuf,
+    transcode_format: String,
+    transcode_bitrate: usize,
+}
+
+struct Job {
+    id: i64,
+    kind: JobKind,
+    /// The id of the track or album the job works on.
+    target_id: i64,
+    /// The file the job works on; for album jobs, any of the album's tracks.
+    path: Option<PathBuf>,
+    attempts: i64,
+    max_attempts: i64,
+}
+
+impl Job {
+    fn run(&self, ctx: &JobContext) -> Result<Value, JobError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Err(JobError::NoPath),
+        };
+
+        match self.kind {
+            JobKind::Hash => Ok(Value::Text(hash::sha256_file(path)?)),
+            JobKind::Fingerprint => Ok(Value::Text(fingerprint(path)?)),
+            JobKind::Analyze => Ok(Value::Real(ffmpeg::integrated_loudness(path)?)),
+            JobKind::FetchCover => {
+                let covers_dir = ctx.data_dir.join("covers");
+                match artwork::find_album_cover(path, &covers_dir, self.target_id)? {
+                    Some(cover) => Ok(Value::Text(crate::library::relative(&cover))),
+                    None => Err(JobError::NoCover),
+                }
+            }
+            JobKind::Transcode => {
+                let (_, extension) = match ffmpeg::codec_for(&ctx.transcode_format) {
+                    Some(codec) => codec,
+                    None => {
+                        let format = ctx.transcode_format.clone();
+                        return Err(JobError::Ffmpeg(ffmpeg::FfmpegError::UnknownFormat(format)));
+                    }
+                };
+
+                let transcodes_dir = ctx.data_dir.join("transcodes");
+                fs::create_dir_all(&transcodes_dir)?;
+
+                let output = transcodes_dir.join(format!("{}.{}", self.target_id, extension));
+                ffmpeg::transcode(path, &output, &ctx.transcode_format, ctx.transcode_bitrate)?;
+
+                Ok(Value::Text(output.to_string_lossy().to_string()))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.path {
+            Some(path) => format!("{} {}", self.kind.as_str(), path.display()),
+            None => format!("{} #{}", self.kind.as_str(), self.target_id),
+        }
+    }
+}
+
+/// A job row, as claimed from the queue.
+struct ClaimedJob {
+    id: i64,
+    priority: i64,
+    kind: String,
+    track_id: Option<i64>,
+    album_id: Option<i64>,
+    attempts: i64,
+    max_attempts: i64,
+}
+
+/// Marks every pending job as running and returns them, highest priority first.
+fn claim_pending_jobs(db: &mut rusqlite::Connection) -> rusqlite::Result<VecDeque<Job>> {
+    let savepoint = db.savepoint()?;
+
+    let mut claimed = Vec::new();
+    {
+        let mut stmt = savepoint.prepare(
+            "UPDATE job SET state = 'running', updated_at = unixepoch()
+             WHERE state = 'pending'
+             RETURNING id, priority, kind, track_id, album_id, attempts, max_attempts",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            claimed.push(ClaimedJob {
+                id: row.get(0)?,
+                priority: row.get(1)?,
+                kind: row.get(2)?,
+                track_id: row.get(3)?,
+                album_id: row.get(4)?,
+                attempts: row.get(5)?,
+                max_attempts: row.get(6)?,
+            });
+        }
+    }
+
+    claimed.sort_by_key(|job| (-job.priority, job.id));
+
+    let mut jobs = VecDeque::with_capacity(claimed.len());
+    for job in claimed {
+        let kind = match JobKind::from_str(&job.kind) {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        let (target_id, path_result) = match (job.track_id, job.album_id) {
+            (Some(track_id), _) => (
+                track_id,
+                savepoint.query_row(
+                    "SELECT library_path(path) FROM track WHERE id = $id",
+                    [track_id],
+                    |row| row.get::<_, Option<String>>(0),
+                ),
+            ),
+            (None, Some(album_id)) => (
+                album_id,
+                savepoint.query_row(
+                    "SELECT library_path(path) FROM track
+                     WHERE album_id = $id AND path IS NOT NULL LIMIT 1",
+                    [album_id],
+                    |row| row.get::<_, Option<String>>(0),
+                ),
+            ),
+            (None, None) => continue,
+        };
+
+        let path = match path_result {
+            Ok(path) => path.map(PathBuf::from),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(err) => return Err(err),
+        };
+
+        jobs.push_back(Job {
+            id: job.id,
+            kind,
+            target_id,
+            path,
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+        });
+    }
+
+    savepoint.commit()?;
+
+    Ok(jobs)
+}
+
+fn record_success(db: &rusqlite::Connection, job: &Job, value: Value) -> rusqlite::Result<()> {
+    let (table, column) = job.kind.column();
+
+    let query = format!("UPDATE {} SET {} = $value WHERE id = $id", table, column);
+    db.execute(&query, rusqlite::params![value, job.target_id])?;
+    db.execute("DELETE FROM job WHERE id = $id", [job.id])?;
+
+    Ok(())
+}
+
+/// Records a failed attempt; the job goes back to the queue until it runs out of attempts.
+fn record_failure(db: &rusqlite::Connection, job: &Job, err: &JobError) -> rusqlite::Result<bool> {
+    let attempts = job.attempts + 1;
+    let failed = attempts >= job.max_attempts;
+
+    db.execute(
+        "UPDATE job SET state = $state, attempts = $attempts, last_error = $error, updated_at = unixepoch() WHERE id = $id",
+        rusqlite::params![
+            if failed { "failed" } else { "pending" },
+            attempts,
+            err.to_string(),
+            job.id,
+        ],
+    )?;
+
+    Ok(failed)
+}
+
+//
+// "jobs" command
+//
+
+pub enum CommandJobsError {
+    SQLite(rusqlite::Error),
+    InvalidWorkers(String),
+    InvalidKind(String),
+    InvalidPriority(String),
+    DataFolderNotFound,
+}
+impl From<rusqlite::Error> for CommandJobsError {
+    fn from(err: rusqlite::Error) -> CommandJobsError {
+        CommandJobsError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandJobsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandJobsError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandJobsError::InvalidWorkers(value) => {
+                f.write_str(&tr!("invalid-workers", value = value))
+            }
+            CommandJobsError::InvalidKind(value) => {
+                let kinds: Vec<&str> = JobKind::ALL.iter().map(|kind| kind.as_str()).collect();
+                f.write_str(&tr!(
+                    "jobs-invalid-kind",
+                    value = value,
+                    kinds = kinds.join(", ")
+                ))
+            }
+            CommandJobsError::InvalidPriority(value) => {
+                f.write_str(&tr!("jobs-invalid-priority", value = value))
+            }
+            CommandJobsError::DataFolderNotFound => f.write_str(&tr!("data-folder-not-found")),
+        }
+    }
+}
+
+fn parse_kind(value: &str) -> Result<JobKind, CommandJobsError> {
+    match JobKind::from_str(value) {
+        Some(kind) => Ok(kind),
+        None => Err(CommandJobsError::InvalidKind(value.to_string())),
+    }
+}
+
+/// The number of workers used when none is given explicitly.
+pub fn default_workers(db: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    match crate::get_config_usize(db, "jobs_parallelism")? {
+        Some(n) if n > 0 => Ok(n),
+        _ => Ok(thread::available_parallelism().map_or(1, |n| n.get())),
+    }
+}
+
+fn get_workers(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<usize, CommandJobsError> {
+    if let Some(value) = args.value_of("workers") {
+        return match value.parse() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(CommandJobsError::InvalidWorkers(value.to_string())),
+        };
+    }
+
+    Ok(default_workers(db)?)
+}
+
+/// Runs every pending job with `workers` threads, returning the number of jobs run.
+///
+/// The work itself is spread over the worker threads while all the database writes happen
+/// on the calling thread, so each result is committed as soon as it's available. Once `stop`
+/// is set the workers finish their current job and the rest goes back to the queue.
+pub fn run_pending_jobs(
+    db: &mut rusqlite::Connection,
+    workers: usize,
+    stop: &AtomicBool,
+) -> Result<usize, CommandJobsError> {
+    let ctx = JobContext {
+        data_dir: match crate::get_data_dir() {
+            Some(data_dir) => data_dir,
+            None => return Err(CommandJobsError::DataFolderNotFound),
+        },
+        transcode_format: crate::get_config_value(db, "transcode_format")?
+            .unwrap_or_else(|| "opus".to_owned()),
+        transcode_bitrate: crate::get_config_usize(db, "transcode_bitrate")?.unwrap_or(128),
+    };
+
+    // Jobs can only be left running if a previous run was interrupted
+    let n = db.execute(
+        "UPDATE job SET state = 'pending' WHERE state = 'running'",
+        [],
+    )?;
+    if n > 0 {
+        println!("{}", tr!("jobs-requeued-interrupted", jobs = n));
+    }
+
+    let jobs = claim_pending_jobs(db)?;
+    if jobs.is_empty() {
+        return Ok(0);
+    }
+
+    let workers = workers.min(jobs.len());
+    println!(
+        "{}",
+        tr!("jobs-running", jobs = jobs.len(), workers = workers)
+    );
+
+    let ctx = Arc::new(ctx);
+    let queue = Arc::new(Mutex::new(jobs));
+    let (results_tx, results_rx) = mpsc::channel();
+
+    thread::scope(|scope| -> Result<usize, CommandJobsError> {
+        for _ in 0..workers {
+            let ctx = Arc::clone(&ctx);
+            let queue = Arc::clone(&queue);
+            let results_tx = results_tx.clone();
+
+            scope.spawn(move || loop {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let job = match queue.lock().unwrap().pop_front() {
+                    Some(job) => job,
+                    None => break,
+                };
+
+                let result = job.run(&ctx);
+                if results_tx.send((job, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(results_tx);
+
+        let mut done = 0;
+        for (job, result) in results_rx {
+            done += 1;
+
+            match result {
+                Ok(value) => {
+                    record_success(db, &job, value)?;
+                    println!("{}", job.describe());
+                }
+                Err(err) => {
+                    let failed = record_failure(db, &job, &err)?;
+                    let id = if failed {
+                        "jobs-failed-for-good"
+                    } else {
+                        "jobs-failed"
+                    };
+                    println!("{}", tr!(id, job = job.describe(), err = err));
+                }
+            }
+        }
+
+        // Only left over if the run was stopped
+        for job in queue.lock().unwrap().drain(..) {
+            db.execute("UPDATE job SET state = 'pending' WHERE id = $id", [job.id])?;
+        }
+
+        Ok(done)
+    })
+}
+
+fn cmd_jobs_run(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandJobsError> {
+    let workers = get_workers(db, args)?;
+
+    let n = run_pending_jobs(db, workers, &AtomicBool::new(false))?;
+    if n == 0 {
+        println!("{}", tr!("jobs-none-pending"));
+    }
+
+    Ok(())
+}
+
+fn cmd_jobs_status(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandJobsError> {
+    let mut counts: BTreeMap<String, [i64; 3]> = BTreeMap::new();
+    {
+        let mut stmt = db.prepare("SELECT kind, state, COUNT(*) FROM job GROUP BY kind, state")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(0)?;
+            let state: String = row.get(1)?;
+            let count: i64 = row.get(2)?;
+
+            let index = match state.as_str() {
+                "pending" => 0,
+                "running" => 1,
+                _ => 2,
+            };
+            counts.entry(kind).or_default()[index] += count;
+        }
+    }
+
+    if counts.is_empty() {
+        println!("{}", tr!("jobs-none"));
+    } else {
+        println!(
+            "{:<12} {:>8} {:>8} {:>8}",
+            tr!("jobs-kind"),
+            tr!("jobs-pending"),
+            tr!("jobs-running-column"),
+            tr!("jobs-failed-column")
+        );
+        for (kind, [pending, running, failed]) in counts {
+            println!("{:<12} {:>8} {:>8} {:>8}", kind, pending, running, failed);
+        }
+    }
+
+    if args.is_present("failed") {
+        let query = "
+            SELECT job.kind, COALESCE(library_path(track.path), album.name), job.attempts, job.last_error
+            FROM job
+            LEFT JOIN track ON track.id = job.track_id
+            LEFT JOIN album ON album.id = job.album_id
+            WHERE job.state = 'failed'
+            ORDER BY job.kind, job.id";
+
+        let mut stmt = db.prepare(query)?;
+        let mut rows = stmt.query([])?;
+
+        println!();
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(0)?;
+            let target: Option<String> = row.get(1)?;
+            let attempts: i64 = row.get(2)?;
+            let last_error: Option<String> = row.get(3)?;
+
+            println!(
+                "{}",
+                tr!(
+                    "jobs-failed-after",
+                    kind = kind,
+                    target = target.unwrap_or_default(),
+                    attempts = attempts,
+                    err = last_error.unwrap_or_default()
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_jobs_enqueue(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandJobsError> {
+    let kind = parse_kind(args.value_of("kind").unwrap())?;
+
+    let priority = match args.value_of("priority") {
+        Some(value) => match value.parse() {
+            Ok(priority) => priority,
+            Err(_) => return Err(CommandJobsError::InvalidPriority(value.to_string())),
+        },
+        None => kind.default_priority(),
+    };
+
+    let n = enqueue_missing(db, kind, priority)?;
+    println!("{}", tr!("jobs-queued", jobs = n, kind = kind.as_str()));
+
+    Ok(())
+}
+
+fn cmd_jobs_retry(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandJobsError> {
+    let kind = match args.value_of("kind") {
+        Some(value) => Some(parse_kind(value)?.as_str()),
+        None => None,
+    };
+
+    let n = db.execute(
+        "UPDATE job SET state = 'pending', attempts = 0, updated_at = unixepoch()
+         WHERE state = 'failed' AND ($kind IS NULL OR kind = $kind)",
+        [kind],
+    )?;
+    println!("{}", tr!("jobs-requeued-failed", jobs = n));
+
+    Ok(())
+}
+
+pub fn cmd_jobs(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandJobsError> {
+    match args.subcommand() {
+        Some(("run", sub_args)) => cmd_jobs_run(db, sub_args),
+        Some(("status", sub_args)) => cmd_jobs_status(db, sub_args),
+        Some(("enqueue", sub_args)) => cmd_jobs_enqueue(db, sub_args),
+        Some(("retry", sub_args)) => cmd_jobs_retry(db, sub_args),
+        _ => Ok(()),
+    }
+}
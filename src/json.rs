@@ -0,0 +1,452 @@
+//! Just enough JSON to print machine readable listings and to read RPC requests.
+
+use std::fmt::Write;
+
+/// Returns `value` as a quoted JSON string.
+pub fn string(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len() + 2);
+    buf.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+/// Returns `value` as a JSON string, or `null`.
+pub fn opt_string(value: Option<&str>) -> String {
+    match value {
+        Some(value) => string(value),
+        None => "null".to_owned(),
+    }
+}
+
+/// Returns `value` as a JSON number, or `null`.
+pub fn opt_number(value: Option<i64>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "null".to_owned(),
+    }
+}
+
+/// Builds a JSON object from already encoded values.
+pub fn object(fields: &[(&str, String)]) -> String {
+    let mut buf = String::from("{");
+    for (i, (key, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        buf.push_str(&string(key));
+        buf.push(':');
+        buf.push_str(value);
+    }
+    buf.push('}');
+    buf
+}
+
+/// Builds a JSON array from already encoded values.
+pub fn array(values: &[String]) -> String {
+    format!("[{}]", values.join(","))
+}
+
+/// A parsed JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Returns the field `name` of an object.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Value] {
+        match self {
+            Value::Array(values) => values,
+            _ => &[],
+        }
+    }
+
+    /// Returns the value as an integer, if it's a number without a fractional part.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    /// Encodes the value back to JSON.
+    #[cfg(unix)]
+    pub fn encode(&self) -> String {
+        match self {
+            Value::Null => "null".to_owned(),
+            Value::Bool(value) => value.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::String(value) => string(value),
+            Value::Array(values) => {
+                let values: Vec<String> = values.iter().map(Value::encode).collect();
+                array(&values)
+            }
+            Value::Object(fields) => {
+                let fields: Vec<(&str, String)> = fields
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.encode()))
+                    .collect();
+                object(&fields)
+            }
+        }
+    }
+}
+
+/// The byte offset at which a document stopped being valid JSON.
+#[derive(Debug)]
+pub struct ParseError(pub usize);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid JSON at offset {}", self.0)
+    }
+}
+
+/// How deep arrays and objects can be nested, so that a document can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// The arrays and objects the parser is in.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self) -> Result<T, ParseError> {
+        Err(ParseError(self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), ParseError> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+
+        match self.bytes.get(self.pos) {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'[' | b'{') => {
+                if self.depth == MAX_DEPTH {
+                    return self.error();
+                }
+                self.depth += 1;
+                let value = if self.bytes[self.pos] == b'[' {
+                    self.array()
+                } else {
+                    self.object()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => self.error(),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or_default();
+        match text.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+            _ => Err(ParseError(start)),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = match self.bytes.get(self.pos..self.pos + 4) {
+            Some(digits) => digits,
+            None => return self.error(),
+        };
+        let text = std::str::from_utf8(digits).unwrap_or_default();
+        match u32::from_str_radix(text, 16) {
+            Ok(n) => {
+                self.pos += 4;
+                Ok(n)
+            }
+            Err(_) => self.error(),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        // Skip the opening quote
+        self.pos += 1;
+
+        let mut buf = Vec::new();
+        loop {
+            let b = match self.bytes.get(self.pos) {
+                Some(b) => *b,
+                None => return self.error(),
+            };
+            self.pos += 1;
+
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let escape = match self.bytes.get(self.pos) {
+                        Some(b) => *b,
+                        None => return self.error(),
+                    };
+                    self.pos += 1;
+
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut n = self.hex4()?;
+                            // A surrogate pair is written as two escapes
+                            if (0xd800..0xdc00).contains(&n) {
+                                self.expect("\\u")?;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return self.error();
+                                }
+                                n = 0x10000 + ((n - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(n).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return self.error(),
+                    };
+
+                    let mut encoded = [0; 4];
+                    buf.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+                }
+                b => buf.push(b),
+            }
+        }
+
+        match String::from_utf8(buf) {
+            Ok(value) => Ok(value),
+            Err(_) => self.error(),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        // Skip the opening bracket
+        self.pos += 1;
+
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return self.error(),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, ParseError> {
+        // Skip the opening brace
+        self.pos += 1;
+
+        let mut fields = Vec::new();
+
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(Value::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.bytes.get(self.pos) != Some(&b'"') {
+                return self.error();
+            }
+            let key = self.string()?;
+
+            self.skip_whitespace();
+            self.expect(":")?;
+
+            let value = self.value()?;
+            fields.push((key, value));
+
+            self.skip_whitespace();
+            match self.bytes.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(fields));
+                }
+                _ => return self.error(),
+            }
+        }
+    }
+}
+
+/// Parses a whole JSON document.
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+
+    let value = parser.value()?;
+
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return parser.error();
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse("null").unwrap(), Value::Null);
+        assert_eq!(parse(" true ").unwrap(), Value::Bool(true));
+        assert_eq!(parse("-12.5e1").unwrap(), Value::Number(-125.0));
+        assert_eq!(parse("[]").unwrap(), Value::Array(vec![]));
+        assert_eq!(parse("{}").unwrap(), Value::Object(vec![]));
+
+        let value = parse(r#"{"id": 3, "params": {"paths": ["a", "b"]}, "x": null}"#).unwrap();
+        assert_eq!(value.get("id").and_then(Value::as_i64), Some(3));
+        let paths = value.get("params").and_then(|params| params.get("paths"));
+        assert_eq!(paths.unwrap().as_array().len(), 2);
+        assert_eq!(value.get("x"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn parse_string_escapes() {
+        assert_eq!(
+            parse(r#""a\"b\\c\/d\n\t\u00e9""#).unwrap(),
+            Value::String("a\"b\\c/d\n\té".to_owned())
+        );
+        // U+1F3B5, as a surrogate pair
+        assert_eq!(
+            parse(r#""\ud83c\udfb5""#).unwrap(),
+            Value::String("\u{1f3b5}".to_owned())
+        );
+        assert_eq!(
+            parse(r#""\udfb5""#).unwrap(),
+            Value::String("\u{fffd}".to_owned())
+        );
+    }
+
+    #[test]
+    fn parse_malformed() {
+        for text in [
+            "",
+            " ",
+            "nul",
+            "truex",
+            "[1,]",
+            "[1 2]",
+            "{\"a\" 1}",
+            "{\"a\": 1,}",
+            "{a: 1}",
+            "\"unterminated",
+            "\"bad \\x escape\"",
+            "\"\\u12\"",
+            "\"\\ud83c\"",
+            "\"\\ud83c\\u0041\"",
+            "-",
+            "1-2",
+            "1e999",
+            "[",
+            "{",
+            "[] []",
+            "\"\\",
+        ] {
+            assert!(parse(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn parse_error_offset() {
+        assert_eq!(parse("[1, x]").unwrap_err().0, 4);
+        assert_eq!(parse("{} x").unwrap_err().0, 3);
+    }
+
+    #[test]
+    fn parse_nesting_limit() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1)).is_err());
+        // Deep enough to overflow the stack without the limit
+        assert!(parse(&"[{\"a\":".repeat(100_000)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn encode_round_trip() {
+        let text = r#"{"a":[1,"x\ny",null,true],"b":{"c":-2.5}}"#;
+        assert_eq!(parse(text).unwrap().encode(), text);
+    }
+}
@@ -0,0 +1,141 @@
+//! User-defined labels on tracks, like "workout" or "to check".
+
+use std::fmt;
+
+use crate::i18n::tr;
+
+pub enum CommandLabelError {
+    SQLite(rusqlite::Error),
+    EmptyLabel,
+    TrackNotFound(String),
+    LabelNotFound(String),
+}
+impl From<rusqlite::Error> for CommandLabelError {
+    fn from(err: rusqlite::Error) -> CommandLabelError {
+        CommandLabelError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandLabelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandLabelError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandLabelError::EmptyLabel => f.write_str(&tr!("label-empty")),
+            CommandLabelError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+            CommandLabelError::LabelNotFound(name) => {
+                f.write_str(&tr!("label-not-found", name = name))
+            }
+        }
+    }
+}
+
+fn get_track_id(
+    db: &rusqlite::Connection,
+    value: &str,
+) -> Result<crate::TrackID, CommandLabelError> {
+    match crate::find_track_id(db, value)? {
+        Some(id) => Ok(id),
+        None => Err(CommandLabelError::TrackNotFound(value.to_owned())),
+    }
+}
+
+fn cmd_label_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLabelError> {
+    let track_id = get_track_id(db, args.value_of("track").unwrap())?;
+    let label = args.value_of("label").unwrap().trim();
+    if label.is_empty() {
+        return Err(CommandLabelError::EmptyLabel);
+    }
+
+    let savepoint = db.savepoint()?;
+
+    savepoint.execute("INSERT OR IGNORE INTO label(name) VALUES($name)", [label])?;
+    savepoint.execute(
+        "INSERT OR IGNORE INTO track_label(track_id, label_id)
+         SELECT $track_id, id FROM label WHERE name = $name",
+        rusqlite::params![track_id, label],
+    )?;
+
+    savepoint.commit()?;
+
+    Ok(())
+}
+
+fn cmd_label_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLabelError> {
+    let value = args.value_of("track").unwrap();
+    let track_id = get_track_id(db, value)?;
+    let label = args.value_of("label").unwrap().trim();
+
+    let savepoint = db.savepoint()?;
+
+    let n = savepoint.execute(
+        "DELETE FROM track_label
+         WHERE track_id = $track_id AND label_id = (SELECT id FROM label WHERE name = $name)",
+        rusqlite::params![track_id, label],
+    )?;
+    if n == 0 {
+        return Err(CommandLabelError::LabelNotFound(label.to_owned()));
+    }
+
+    // Labels only exist as long as a track has them
+    savepoint.execute(
+        "DELETE FROM label WHERE id NOT IN (SELECT label_id FROM track_label)",
+        [],
+    )?;
+
+    savepoint.commit()?;
+
+    Ok(())
+}
+
+fn cmd_label_list(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLabelError> {
+    let track_id = match args.value_of("track") {
+        Some(value) => Some(get_track_id(db, value)?),
+        None => None,
+    };
+
+    let query = "
+        SELECT label.name, COUNT(*)
+        FROM label
+        JOIN track_label ON track_label.label_id = label.id
+        WHERE ($track_id IS NULL OR track_label.track_id = $track_id)
+        GROUP BY label.id
+        ORDER BY label.name COLLATE natural_sort";
+
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query([track_id])?;
+
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+
+        if track_id.is_some() {
+            println!("{}", name);
+        } else {
+            println!("{} ({})", name, tr!("label-tracks", count = count));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cmd_label(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLabelError> {
+    match args.subcommand() {
+        Some(("add", sub_args)) => cmd_label_add(db, sub_args),
+        Some(("remove", sub_args)) => cmd_label_remove(db, sub_args),
+        Some(("list", sub_args)) => cmd_label_list(db, sub_args),
+        _ => Ok(()),
+    }
+}
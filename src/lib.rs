@@ -0,0 +1,5324 @@
+//! zik, a database of a music library with a server to play it, and the command line
+//! running it, which the `zik-rust` binary calls. Scans of a folder into an in-memory
+//! database, for reports and tests, don't need the command line: see `scan_in_memory`.
+
+extern crate clap;
+extern crate directories;
+extern crate rusqlite;
+extern crate walkdir;
+
+extern crate id3;
+extern crate metaflac;
+extern crate mp4parse;
+
+use clap::{Arg, Command};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::result::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use i18n::tr;
+
+mod airplay;
+mod alias;
+mod archive;
+mod artwork;
+mod bench;
+mod cast;
+mod chromecast;
+mod collation;
+mod collection;
+mod daemon;
+mod db;
+mod doctor;
+mod du;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod enrich;
+mod export;
+mod feed;
+mod ffmpeg;
+mod fixtures;
+mod gapless;
+mod genre;
+mod hash;
+#[cfg(feature = "heap-profile")]
+mod heap;
+mod history;
+mod http;
+mod i18n;
+mod ignore;
+mod import;
+mod inbox;
+mod incomplete;
+mod infer;
+mod jobs;
+mod json;
+mod label;
+mod library;
+mod links;
+mod list;
+mod lyrics;
+mod mdns;
+mod metrics;
+mod mood;
+mod moves;
+mod mp4meta;
+mod musicbrainz;
+mod netfs;
+mod note;
+mod notify;
+mod ogg;
+mod pick;
+mod play;
+mod pool;
+mod probe;
+mod query;
+mod queue;
+mod ratings;
+mod release;
+mod replaygain;
+mod rip;
+#[cfg(unix)]
+mod rpc;
+mod schema;
+mod secrets;
+mod server;
+mod shuffle;
+mod sidecar;
+mod site;
+mod snapshot;
+mod source;
+mod spoken;
+mod storage;
+mod stream;
+mod subsonic;
+#[cfg(unix)]
+mod systemd;
+mod table;
+mod tag;
+mod tags;
+mod throttle;
+mod tombstones;
+mod top;
+mod torrent;
+mod tracklist;
+mod unreadable;
+mod upgrades;
+mod user;
+mod verify;
+mod vorbis;
+mod wrapped;
+mod years;
+
+#[cfg(feature = "heap-profile")]
+#[global_allocator]
+static ALLOCATOR: heap::CountingAllocator = heap::CountingAllocator;
+
+#[derive(Debug)]
+enum OpenDatabaseError {
+    IO(io::Error),
+    SQLite(rusqlite::Error),
+    DataFolderNotFound,
+    #[cfg(feature = "encryption")]
+    Encryption(encryption::EncryptionError),
+}
+impl fmt::Display for OpenDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpenDatabaseError::IO(err) => write!(f, "{}", err),
+            OpenDatabaseError::SQLite(err) => write!(f, "{}", err),
+            OpenDatabaseError::DataFolderNotFound => f.write_str(&tr!("data-folder-not-found")),
+            #[cfg(feature = "encryption")]
+            OpenDatabaseError::Encryption(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl From<io::Error> for OpenDatabaseError {
+    fn from(err: io::Error) -> OpenDatabaseError {
+        OpenDatabaseError::IO(err)
+    }
+}
+impl From<rusqlite::Error> for OpenDatabaseError {
+    fn from(err: rusqlite::Error) -> OpenDatabaseError {
+        OpenDatabaseError::SQLite(err)
+    }
+}
+#[cfg(feature = "encryption")]
+impl From<encryption::EncryptionError> for OpenDatabaseError {
+    fn from(err: encryption::EncryptionError) -> OpenDatabaseError {
+        OpenDatabaseError::Encryption(err)
+    }
+}
+
+/// The data folder set with `--data-dir`, which wins over `ZIK_DATA_DIR`.
+static DATA_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Returns the folder of the database and of everything zik stores: covers, transcodes,
+/// snapshots and sockets.
+///
+/// It's `--data-dir`, else `ZIK_DATA_DIR`, else the data folder of the platform which on Linux
+/// follows `XDG_DATA_HOME`.
+fn get_data_dir() -> Option<PathBuf> {
+    if let Some(data_dir) = DATA_DIR.lock().unwrap().clone() {
+        return Some(data_dir);
+    }
+    if let Some(data_dir) = std::env::var_os("ZIK_DATA_DIR").filter(|value| !value.is_empty()) {
+        return Some(absolute_path(Path::new(&data_dir)));
+    }
+
+    directories::ProjectDirs::from("fr", "rischmann", "zik")
+        .map(|project_directories| project_directories.data_dir().to_path_buf())
+}
+
+fn set_data_dir(path: &Path) {
+    *DATA_DIR.lock().unwrap() = Some(absolute_path(path));
+}
+
+/// Sockets and paths stored in the database must not depend on the working directory.
+fn absolute_path(path: &Path) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(current_dir) if path.is_relative() => current_dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The path a file is stored under: absolute, with the symlinks of its folders resolved but
+/// not the file's own. A file reached through a symlinked folder has a single path, whether
+/// it comes from a scan or the command line.
+///
+/// The folders which don't exist anymore are kept as they are, for the missing files.
+fn canonical_path(path: &Path) -> PathBuf {
+    let path = absolute_path(path);
+
+    let mut existing = path.as_path();
+    let mut names = Vec::new();
+    loop {
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                names.push(name);
+                existing = parent;
+            }
+            _ => return path.clone(),
+        }
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            return names
+                .iter()
+                .rev()
+                .fold(canonical, |path, name| path.join(name));
+        }
+    }
+}
+
+/// The database set with `--database`, a path or `:memory:`.
+static DATABASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Every connection of the process, like the ones of the server threads, opens the same
+/// in-memory database. It lives as long as one of them is open.
+const MEMORY_DATABASE_URI: &str = "file:zik?mode=memory&cache=shared";
+
+fn open_database() -> Result<rusqlite::Connection, OpenDatabaseError> {
+    let database = DATABASE.lock().unwrap().clone();
+
+    let db_path = match database.as_deref() {
+        Some(":memory:") => PathBuf::from(MEMORY_DATABASE_URI),
+        Some(path) => {
+            let path = absolute_path(Path::new(path));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            path
+        }
+        None => match get_data_dir() {
+            Some(data_dir) => {
+                fs::create_dir_all(&data_dir)?;
+                data_dir.join("data.db")
+            }
+            None => return Err(OpenDatabaseError::DataFolderNotFound),
+        },
+    };
+
+    let connection = rusqlite::Connection::open(&db_path)?;
+    #[cfg(feature = "encryption")]
+    if database.as_deref() != Some(":memory:") {
+        encryption::unlock(&connection, &db_path)?;
+    }
+    register_functions(&connection)?;
+
+    Ok(connection)
+}
+
+/// Configures a connection and registers the SQL functions and collations of zik.
+fn register_functions(connection: &rusqlite::Connection) -> rusqlite::Result<()> {
+    #[cfg(feature = "encryption")]
+    encryption::register_functions(connection)?;
+    db::configure(connection)?;
+    collation::register(connection)?;
+    library::register_functions(connection)
+}
+
+#[derive(Debug)]
+enum InitDatabaseError {
+    SQLite(rusqlite::Error),
+    PasswordKey(user::KeyError),
+}
+impl From<rusqlite::Error> for InitDatabaseError {
+    fn from(err: rusqlite::Error) -> InitDatabaseError {
+        InitDatabaseError::SQLite(err)
+    }
+}
+impl fmt::Display for InitDatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InitDatabaseError::SQLite(err) => f.write_str(&tr!("init-database-error", err = err)),
+            InitDatabaseError::PasswordKey(err) => {
+                f.write_str(&tr!("init-password-key-error", err = err))
+            }
+        }
+    }
+}
+
+/// An SQL expression generating a random (version 4) UUID.
+macro_rules! new_uuid_sql {
+    () => {
+        "lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' ||
+               substr(hex(randomblob(2)), 2) || '-' ||
+               substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2) || '-' ||
+               hex(randomblob(6)))"
+    };
+}
+
+/// Adds a `uid` column to `$table`, filled when a row is inserted.
+///
+/// Unlike the rowid, it stays the same across rescans so other tools can keep references
+/// to what's in the library.
+macro_rules! uid_migration {
+    ($table:literal) => {
+        [
+            concat!("ALTER TABLE ", $table, " ADD COLUMN uid TEXT"),
+            concat!("UPDATE ", $table, " SET uid = ", new_uuid_sql!()),
+            concat!("CREATE UNIQUE INDEX ", $table, "_uid ON ", $table, "(uid)"),
+            concat!(
+                "CREATE TRIGGER ",
+                $table,
+                "_uid AFTER INSERT ON ",
+                $table,
+                " WHEN NEW.uid IS NULL BEGIN UPDATE ",
+                $table,
+                " SET uid = ",
+                new_uuid_sql!(),
+                " WHERE id = NEW.id; END"
+            ),
+        ]
+    };
+}
+
+/// The database schema, one entry per version.
+///
+/// The number of migrations already applied is stored in `PRAGMA user_version`; never edit
+/// an existing entry, append a new one instead.
+const MIGRATIONS: &[&[&str]] = &[
+    &[
+        "CREATE TABLE IF NOT EXISTS config(
+          key TEXT UNIQUE,
+          value ANY
+        )",
+        "CREATE TABLE IF NOT EXISTS artist(
+          id INTEGER PRIMARY KEY,
+          name TEXT
+        ) STRICT",
+        "CREATE INDEX IF NOT EXISTS artist_name ON artist(name)",
+        "CREATE TABLE IF NOT EXISTS album(
+          id INTEGER PRIMARY KEY,
+          name TEXT,
+          artist_id INTEGER,
+          album_artist_id INTEGER,
+          year TEXT,
+
+          FOREIGN KEY(artist_id) REFERENCES artist(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE INDEX IF NOT EXISTS album_name ON album(name)",
+        "CREATE TABLE IF NOT EXISTS track(
+          id INTEGER PRIMARY KEY,
+          name TEXT UNIQUE,
+          artist_id INTEGER,
+          album_id INTEGER,
+          year TEXT,
+          number INTEGER,
+
+          FOREIGN KEY(artist_id) REFERENCES artist(id) ON DELETE CASCADE,
+          FOREIGN KEY(album_id) REFERENCES album(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN genre TEXT",
+        "CREATE INDEX track_genre ON track(genre)",
+        "CREATE TABLE genre_alias(
+          key TEXT PRIMARY KEY,
+          alias TEXT NOT NULL,
+          genre TEXT NOT NULL
+        ) STRICT",
+        "INSERT INTO genre_alias(key, alias, genre) VALUES
+          ('altrock', 'Alt Rock', 'Alternative Rock'),
+          ('hiphop', 'hiphop', 'Hip-Hop'),
+          ('rb', 'R&B', 'R&B'),
+          ('rnb', 'RnB', 'R&B'),
+          ('dnb', 'DnB', 'Drum & Bass'),
+          ('drumnbass', 'Drum n Bass', 'Drum & Bass'),
+          ('drumandbass', 'Drum and Bass', 'Drum & Bass')",
+    ],
+    &[
+        "ALTER TABLE album ADD COLUMN release_year INTEGER",
+        "ALTER TABLE track ADD COLUMN release_year INTEGER",
+        "UPDATE album SET release_year = CAST(substr(year, 1, 4) AS INTEGER)
+         WHERE year GLOB '[0-9][0-9][0-9][0-9]*'",
+        "UPDATE track SET release_year = CAST(substr(year, 1, 4) AS INTEGER)
+         WHERE year GLOB '[0-9][0-9][0-9][0-9]*'",
+        "CREATE INDEX album_release_year ON album(release_year)",
+        "CREATE INDEX track_release_year ON track(release_year)",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN track_total INTEGER",
+        "ALTER TABLE track ADD COLUMN disc_number INTEGER",
+        "ALTER TABLE track ADD COLUMN disc_total INTEGER",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN path TEXT",
+        "ALTER TABLE track ADD COLUMN hash TEXT",
+        "ALTER TABLE track ADD COLUMN fingerprint TEXT",
+        "CREATE INDEX track_hash ON track(hash)",
+        "CREATE TABLE job(
+          id INTEGER PRIMARY KEY,
+          track_id INTEGER NOT NULL,
+          kind TEXT NOT NULL,
+
+          UNIQUE(track_id, kind),
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    &[
+        "CREATE TABLE job_new(
+          id INTEGER PRIMARY KEY,
+          kind TEXT NOT NULL,
+          track_id INTEGER,
+          album_id INTEGER,
+          state TEXT NOT NULL DEFAULT 'pending',
+          priority INTEGER NOT NULL DEFAULT 0,
+          attempts INTEGER NOT NULL DEFAULT 0,
+          max_attempts INTEGER NOT NULL DEFAULT 3,
+          last_error TEXT,
+          created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+          updated_at INTEGER,
+
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE,
+          FOREIGN KEY(album_id) REFERENCES album(id) ON DELETE CASCADE
+        ) STRICT",
+        "INSERT INTO job_new(id, kind, track_id) SELECT id, kind, track_id FROM job",
+        "DROP TABLE job",
+        "ALTER TABLE job_new RENAME TO job",
+        "CREATE UNIQUE INDEX job_track ON job(kind, track_id) WHERE track_id IS NOT NULL",
+        "CREATE UNIQUE INDEX job_album ON job(kind, album_id) WHERE album_id IS NOT NULL",
+        "CREATE INDEX job_state ON job(state, priority)",
+        "ALTER TABLE track ADD COLUMN loudness REAL",
+        "ALTER TABLE track ADD COLUMN transcode_path TEXT",
+        "ALTER TABLE album ADD COLUMN cover_path TEXT",
+    ],
+    &[
+        "CREATE TABLE label(
+          id INTEGER PRIMARY KEY,
+          name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        ) STRICT",
+        "CREATE TABLE track_label(
+          track_id INTEGER NOT NULL,
+          label_id INTEGER NOT NULL,
+
+          PRIMARY KEY(track_id, label_id),
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE,
+          FOREIGN KEY(label_id) REFERENCES label(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE INDEX track_label_label ON track_label(label_id)",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN comment TEXT",
+        "CREATE TABLE note(
+          id INTEGER PRIMARY KEY,
+          album_id INTEGER UNIQUE,
+          track_id INTEGER UNIQUE,
+          text TEXT NOT NULL,
+          updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+
+          CHECK((album_id IS NULL) != (track_id IS NULL)),
+          FOREIGN KEY(album_id) REFERENCES album(id) ON DELETE CASCADE,
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+        // Full text indexes over the comment tags and the notes, kept in sync by triggers
+        "CREATE VIRTUAL TABLE track_comment_fts USING fts5(
+          comment, content='track', content_rowid='id'
+        )",
+        "CREATE TRIGGER track_comment_fts_insert AFTER INSERT ON track
+         WHEN new.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(rowid, comment) VALUES(new.id, new.comment);
+        END",
+        "CREATE TRIGGER track_comment_fts_delete AFTER DELETE ON track
+         WHEN old.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(track_comment_fts, rowid, comment)
+          VALUES('delete', old.id, old.comment);
+        END",
+        "CREATE TRIGGER track_comment_fts_update_old AFTER UPDATE OF comment ON track
+         WHEN old.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(track_comment_fts, rowid, comment)
+          VALUES('delete', old.id, old.comment);
+        END",
+        "CREATE TRIGGER track_comment_fts_update_new AFTER UPDATE OF comment ON track
+         WHEN new.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(rowid, comment) VALUES(new.id, new.comment);
+        END",
+        "CREATE VIRTUAL TABLE note_fts USING fts5(text, content='note', content_rowid='id')",
+        "CREATE TRIGGER note_fts_insert AFTER INSERT ON note BEGIN
+          INSERT INTO note_fts(rowid, text) VALUES(new.id, new.text);
+        END",
+        "CREATE TRIGGER note_fts_delete AFTER DELETE ON note BEGIN
+          INSERT INTO note_fts(note_fts, rowid, text) VALUES('delete', old.id, old.text);
+        END",
+        "CREATE TRIGGER note_fts_update AFTER UPDATE OF text ON note BEGIN
+          INSERT INTO note_fts(note_fts, rowid, text) VALUES('delete', old.id, old.text);
+          INSERT INTO note_fts(rowid, text) VALUES(new.id, new.text);
+        END",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN spoken_word INTEGER NOT NULL DEFAULT 0",
+        "CREATE TABLE chapter(
+          id INTEGER PRIMARY KEY,
+          track_id INTEGER NOT NULL,
+          number INTEGER NOT NULL,
+          start_ms INTEGER NOT NULL,
+          end_ms INTEGER,
+          title TEXT,
+
+          UNIQUE(track_id, number),
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE TABLE resume_position(
+          track_id INTEGER PRIMARY KEY,
+          position_ms INTEGER NOT NULL,
+          comment TEXT,
+          created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+          updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    &["CREATE TABLE video(
+          id INTEGER PRIMARY KEY,
+          path TEXT NOT NULL UNIQUE,
+          name TEXT,
+          artist TEXT,
+          year TEXT,
+          genre TEXT,
+          comment TEXT
+        ) STRICT"],
+    &[
+        "CREATE TABLE user(
+          id INTEGER PRIMARY KEY,
+          name TEXT NOT NULL UNIQUE,
+          password TEXT NOT NULL,
+          admin INTEGER NOT NULL DEFAULT 0,
+          created_at INTEGER NOT NULL DEFAULT (unixepoch())
+        ) STRICT",
+        "CREATE TABLE user_track(
+          user_id INTEGER NOT NULL,
+          track_id INTEGER NOT NULL,
+          play_count INTEGER NOT NULL DEFAULT 0,
+          last_played_at INTEGER,
+          rating INTEGER,
+          starred_at INTEGER,
+
+          PRIMARY KEY(user_id, track_id),
+          FOREIGN KEY(user_id) REFERENCES user(id) ON DELETE CASCADE,
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE TABLE playlist(
+          id INTEGER PRIMARY KEY,
+          user_id INTEGER NOT NULL,
+          name TEXT NOT NULL,
+          comment TEXT,
+          public INTEGER NOT NULL DEFAULT 0,
+          created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+          updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+
+          FOREIGN KEY(user_id) REFERENCES user(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE TABLE playlist_track(
+          playlist_id INTEGER NOT NULL,
+          position INTEGER NOT NULL,
+          track_id INTEGER NOT NULL,
+
+          PRIMARY KEY(playlist_id, position),
+          FOREIGN KEY(playlist_id) REFERENCES playlist(id) ON DELETE CASCADE,
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN lyrics TEXT",
+        "CREATE TABLE track_artist(
+          track_id INTEGER NOT NULL,
+          position INTEGER NOT NULL,
+          artist_id INTEGER NOT NULL,
+
+          PRIMARY KEY(track_id, position),
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE,
+          FOREIGN KEY(artist_id) REFERENCES artist(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    &["CREATE TABLE artist_info(
+          artist_name TEXT PRIMARY KEY,
+          source TEXT NOT NULL,
+          found INTEGER NOT NULL,
+          bio TEXT,
+          image_url TEXT,
+          musicbrainz_id TEXT,
+          links TEXT,
+          similar TEXT,
+          fetched_at INTEGER NOT NULL DEFAULT (unixepoch())
+        ) STRICT"],
+    &["ALTER TABLE album ADD COLUMN release_type TEXT
+          CHECK(release_type IN ('album', 'ep', 'single', 'live', 'compilation'))"],
+    &uid_migration!("artist"),
+    &uid_migration!("album"),
+    &uid_migration!("track"),
+    // Flat views for people browsing the database with other tools. A migration changing
+    // the columns they use must drop and recreate them.
+    &[
+        "CREATE VIEW v_tracks AS
+         SELECT track.id, track.uid, track.name AS title,
+                artist.name AS artist, album.name AS album, album_artist.name AS album_artist,
+                track.number AS track_number, track.track_total,
+                track.disc_number, track.disc_total,
+                track.release_year AS year, track.genre, album.release_type,
+                track.spoken_word, track.loudness, track.path,
+                lower(replace(track.path, rtrim(track.path, replace(track.path, '.', '')), ''))
+                  AS format
+         FROM track
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         LEFT JOIN artist album_artist ON album_artist.id = album.artist_id",
+        "CREATE VIEW v_albums AS
+         SELECT album.id, album.uid, album.name AS title, artist.name AS artist,
+                album.release_year AS year, album.release_type,
+                (SELECT COUNT(*) FROM track WHERE track.album_id = album.id) AS tracks,
+                album.cover_path
+         FROM album
+         LEFT JOIN artist ON artist.id = album.artist_id",
+        "CREATE VIEW v_artists AS
+         SELECT artist.id, artist.uid, artist.name,
+                (SELECT COUNT(*) FROM album WHERE album.artist_id = artist.id) AS albums,
+                (SELECT COUNT(*) FROM track WHERE track.artist_id = artist.id) AS tracks
+         FROM artist",
+    ],
+    &["ALTER TABLE chapter ADD COLUMN artist TEXT"],
+    &[
+        "ALTER TABLE artist ADD COLUMN sort_name TEXT",
+        "ALTER TABLE album ADD COLUMN sort_name TEXT",
+        "UPDATE artist SET sort_name = name",
+        "UPDATE album SET sort_name = name",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN duration_ms INTEGER",
+        // One row per play, user_track only has the totals
+        "CREATE TABLE play(
+          id INTEGER PRIMARY KEY,
+          user_id INTEGER NOT NULL,
+          track_id INTEGER NOT NULL,
+          played_at INTEGER NOT NULL,
+
+          FOREIGN KEY(user_id) REFERENCES user(id) ON DELETE CASCADE,
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE INDEX play_played_at ON play(played_at)",
+    ],
+    // Albums already in the library have none, they weren't just added
+    &["ALTER TABLE album ADD COLUMN added_at INTEGER"],
+    // Tracks aren't deleted when their file is, they're marked missing
+    &["ALTER TABLE track ADD COLUMN missing_since INTEGER"],
+    // Set once the ratings and play counts of the tags were seeded
+    &["ALTER TABLE user_track ADD COLUMN tags_imported INTEGER NOT NULL DEFAULT 0"],
+    // The files of the inbox, rebuilt by every scan of it
+    &["CREATE TABLE staged_track(
+          id INTEGER PRIMARY KEY,
+          path TEXT NOT NULL UNIQUE,
+          folder TEXT NOT NULL,
+          name TEXT,
+          artist TEXT,
+          album TEXT,
+          album_artist TEXT,
+          year TEXT,
+          number INTEGER,
+          genre TEXT
+        ) STRICT"],
+    // Foreign keys weren't enforced before, the rows referencing nothing are fixed or deleted
+    &[
+        "UPDATE album
+         SET artist_id = (
+           SELECT track.artist_id
+           FROM track
+           JOIN artist ON artist.id = track.artist_id
+           WHERE track.album_id = album.id
+           LIMIT 1
+         )
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('album'))",
+        "UPDATE track SET artist_id = NULL WHERE artist_id NOT IN (SELECT id FROM artist)",
+        "UPDATE track SET album_id = NULL WHERE album_id NOT IN (SELECT id FROM album)",
+        "DELETE FROM job WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('job'))",
+        "DELETE FROM track_label
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('track_label'))",
+        "DELETE FROM note WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('note'))",
+        "DELETE FROM chapter
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('chapter'))",
+        "DELETE FROM resume_position
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('resume_position'))",
+        "DELETE FROM user_track
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('user_track'))",
+        "DELETE FROM playlist
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('playlist'))",
+        "DELETE FROM playlist_track
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('playlist_track'))",
+        "DELETE FROM track_artist
+         WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('track_artist'))",
+        "DELETE FROM play WHERE rowid IN (SELECT rowid FROM pragma_foreign_key_check('play'))",
+    ],
+    // Tracks are identified by their file instead of their title, two songs named "Intro"
+    // overwrote each other. The table is rebuilt to drop the UNIQUE of the name.
+    &[
+        "CREATE TABLE track_new(
+          id INTEGER PRIMARY KEY,
+          path TEXT UNIQUE,
+          name TEXT,
+          artist_id INTEGER,
+          album_id INTEGER,
+          year TEXT,
+          release_year INTEGER,
+          number INTEGER,
+          track_total INTEGER,
+          disc_number INTEGER,
+          disc_total INTEGER,
+          genre TEXT,
+          comment TEXT,
+          lyrics TEXT,
+          duration_ms INTEGER,
+          spoken_word INTEGER NOT NULL DEFAULT 0,
+          hash TEXT,
+          fingerprint TEXT,
+          loudness REAL,
+          transcode_path TEXT,
+          uid TEXT,
+          missing_since INTEGER,
+
+          FOREIGN KEY(artist_id) REFERENCES artist(id) ON DELETE CASCADE,
+          FOREIGN KEY(album_id) REFERENCES album(id) ON DELETE CASCADE
+        ) STRICT",
+        "INSERT INTO track_new(id, path, name, artist_id, album_id, year, release_year, number,
+                               track_total, disc_number, disc_total, genre, comment, lyrics,
+                               duration_ms, spoken_word, hash, fingerprint, loudness,
+                               transcode_path, uid, missing_since)
+         SELECT id, path, name, artist_id, album_id, year, release_year, number,
+                track_total, disc_number, disc_total, genre, comment, lyrics,
+                duration_ms, spoken_word, hash, fingerprint, loudness,
+                transcode_path, uid, missing_since
+         FROM track",
+        "DROP TABLE track",
+        // The views using the table would fail the renaming otherwise
+        "PRAGMA legacy_alter_table = ON",
+        "ALTER TABLE track_new RENAME TO track",
+        "PRAGMA legacy_alter_table = OFF",
+        "CREATE INDEX track_name ON track(name)",
+        "CREATE INDEX track_genre ON track(genre)",
+        "CREATE INDEX track_release_year ON track(release_year)",
+        "CREATE INDEX track_hash ON track(hash)",
+        "CREATE UNIQUE INDEX track_uid ON track(uid)",
+        concat!(
+            "CREATE TRIGGER track_uid AFTER INSERT ON track WHEN NEW.uid IS NULL BEGIN ",
+            "UPDATE track SET uid = ",
+            new_uuid_sql!(),
+            " WHERE id = NEW.id; END"
+        ),
+        "CREATE TRIGGER track_comment_fts_insert AFTER INSERT ON track
+         WHEN new.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(rowid, comment) VALUES(new.id, new.comment);
+        END",
+        "CREATE TRIGGER track_comment_fts_delete AFTER DELETE ON track
+         WHEN old.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(track_comment_fts, rowid, comment)
+          VALUES('delete', old.id, old.comment);
+        END",
+        "CREATE TRIGGER track_comment_fts_update_old AFTER UPDATE OF comment ON track
+         WHEN old.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(track_comment_fts, rowid, comment)
+          VALUES('delete', old.id, old.comment);
+        END",
+        "CREATE TRIGGER track_comment_fts_update_new AFTER UPDATE OF comment ON track
+         WHEN new.comment IS NOT NULL BEGIN
+          INSERT INTO track_comment_fts(rowid, comment) VALUES(new.id, new.comment);
+        END",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN original_year INTEGER",
+        "ALTER TABLE album ADD COLUMN original_year INTEGER",
+    ],
+    // The other paths of a file reachable through symlinks or bind mounts, rebuilt by every scan
+    &["CREATE TABLE track_alias(
+          path TEXT PRIMARY KEY,
+          track_id INTEGER NOT NULL,
+
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT"],
+    // The image of each artist, a NULL path when none was found
+    &["CREATE TABLE artist_image(
+          artist_id INTEGER PRIMARY KEY,
+          path TEXT,
+
+          FOREIGN KEY(artist_id) REFERENCES artist(id) ON DELETE CASCADE
+        ) STRICT"],
+    // Genres looked up by `zik infer genres` for the tracks without one, by artist name
+    &[
+        "CREATE TABLE inferred_genre(
+          artist_name TEXT PRIMARY KEY,
+          source TEXT NOT NULL,
+          genre TEXT,
+          fetched_at INTEGER NOT NULL DEFAULT (unixepoch())
+        ) STRICT",
+        "ALTER TABLE track ADD COLUMN genre_inferred INTEGER NOT NULL DEFAULT 0",
+    ],
+    &[
+        "ALTER TABLE track ADD COLUMN musicbrainz_album_id TEXT",
+        "ALTER TABLE track ADD COLUMN musicbrainz_artist_id TEXT",
+        "ALTER TABLE track ADD COLUMN label TEXT",
+        "ALTER TABLE track ADD COLUMN replay_gain_track REAL",
+        "ALTER TABLE track ADD COLUMN replay_gain_album REAL",
+    ],
+    &["CREATE TABLE track_field(
+          track_id INTEGER NOT NULL REFERENCES track(id) ON DELETE CASCADE,
+          name TEXT NOT NULL,
+          value TEXT NOT NULL,
+          PRIMARY KEY(track_id, name, value)
+        ) STRICT"],
+    &[
+        "CREATE TABLE track_mood(
+          track_id INTEGER NOT NULL REFERENCES track(id) ON DELETE CASCADE,
+          mood TEXT NOT NULL COLLATE NOCASE,
+          manual INTEGER NOT NULL DEFAULT 0,
+          PRIMARY KEY(track_id, mood)
+        ) STRICT",
+        "CREATE INDEX track_mood_mood ON track_mood(mood)",
+        "ALTER TABLE track ADD COLUMN energy INTEGER",
+    ],
+    &[
+        "CREATE TABLE play_queue(
+          user_id INTEGER PRIMARY KEY,
+          current_track_id INTEGER,
+          position_ms INTEGER NOT NULL DEFAULT 0,
+          changed_by TEXT,
+          updated_at INTEGER NOT NULL DEFAULT (unixepoch()),
+
+          FOREIGN KEY(user_id) REFERENCES user(id) ON DELETE CASCADE,
+          FOREIGN KEY(current_track_id) REFERENCES track(id) ON DELETE SET NULL
+        ) STRICT",
+        "CREATE TABLE play_queue_track(
+          user_id INTEGER NOT NULL,
+          position INTEGER NOT NULL,
+          track_id INTEGER NOT NULL,
+
+          PRIMARY KEY(user_id, position),
+          FOREIGN KEY(user_id) REFERENCES play_queue(user_id) ON DELETE CASCADE,
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    // Checked by `zik doctor`, filled by the next scan
+    &["ALTER TABLE track ADD COLUMN album_artist TEXT"],
+    // The result of decoding the file with `zik verify --decode`, no error if it's fine
+    &[
+        "ALTER TABLE track ADD COLUMN verified_at INTEGER",
+        "ALTER TABLE track ADD COLUMN verify_error TEXT",
+    ],
+    // The SHA-1 of a file for torrent listings, valid while its size and mtime are the same
+    &["CREATE TABLE track_sha1(
+          track_id INTEGER PRIMARY KEY REFERENCES track(id) ON DELETE CASCADE,
+          size INTEGER NOT NULL,
+          modified_at INTEGER NOT NULL,
+          sha1 TEXT NOT NULL
+        ) STRICT"],
+    // Where a track comes from, see `source`, and when a scan first saw it. The tracks already
+    // there were first seen with their album.
+    &[
+        "ALTER TABLE track ADD COLUMN source TEXT",
+        "ALTER TABLE track ADD COLUMN first_seen_at INTEGER",
+        "UPDATE track SET first_seen_at = (SELECT added_at FROM album WHERE album.id = track.album_id)",
+        "DROP VIEW v_tracks",
+        "CREATE VIEW v_tracks AS
+         SELECT track.id, track.uid, track.name AS title,
+                artist.name AS artist, album.name AS album, album_artist.name AS album_artist,
+                track.number AS track_number, track.track_total,
+                track.disc_number, track.disc_total,
+                track.release_year AS year, track.genre, album.release_type,
+                track.spoken_word, track.loudness, track.path,
+                lower(replace(track.path, rtrim(track.path, replace(track.path, '.', '')), ''))
+                  AS format,
+                track.source, datetime(track.first_seen_at, 'unixepoch') AS first_seen_at
+         FROM track
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         LEFT JOIN artist album_artist ON album_artist.id = album.artist_id",
+    ],
+    // Parent genres, a genre can have several. Edited with `zik genre parent`.
+    &[
+        "CREATE TABLE genre_parent(
+          genre TEXT NOT NULL COLLATE NOCASE,
+          parent TEXT NOT NULL COLLATE NOCASE,
+
+          PRIMARY KEY(genre, parent)
+        ) STRICT",
+        "CREATE INDEX genre_parent_parent ON genre_parent(parent)",
+        "INSERT INTO genre_parent(genre, parent) VALUES
+          ('Alternative Rock', 'Rock'),
+          ('Indie Rock', 'Rock'),
+          ('Hard Rock', 'Rock'),
+          ('Classic Rock', 'Rock'),
+          ('Progressive Rock', 'Rock'),
+          ('Psychedelic Rock', 'Rock'),
+          ('Post-Rock', 'Rock'),
+          ('Punk', 'Rock'),
+          ('Post-Punk', 'Punk'),
+          ('Grunge', 'Alternative Rock'),
+          ('Shoegaze', 'Alternative Rock'),
+          ('Shoegaze', 'Dream Pop'),
+          ('Dream Pop', 'Pop'),
+          ('Indie Pop', 'Pop'),
+          ('Synth-pop', 'Pop'),
+          ('Metal', 'Rock'),
+          ('Heavy Metal', 'Metal'),
+          ('Thrash Metal', 'Metal'),
+          ('Death Metal', 'Metal'),
+          ('Black Metal', 'Metal'),
+          ('Doom Metal', 'Metal'),
+          ('House', 'Electronic'),
+          ('Deep House', 'House'),
+          ('Techno', 'Electronic'),
+          ('Trance', 'Electronic'),
+          ('Ambient', 'Electronic'),
+          ('Drum & Bass', 'Electronic'),
+          ('Dubstep', 'Electronic'),
+          ('IDM', 'Electronic'),
+          ('Trip-Hop', 'Electronic'),
+          ('Bebop', 'Jazz'),
+          ('Free Jazz', 'Jazz'),
+          ('Acid Jazz', 'Jazz'),
+          ('Fusion', 'Jazz'),
+          ('Rap', 'Hip-Hop'),
+          ('Trap', 'Hip-Hop'),
+          ('Soul', 'R&B'),
+          ('Funk', 'R&B'),
+          ('Bluegrass', 'Country'),
+          ('Delta Blues', 'Blues'),
+          ('Baroque', 'Classical'),
+          ('Opera', 'Classical')",
+    ],
+    // Shortcuts: aliases of commands and saved filters of tracks
+    &[
+        "CREATE TABLE command_alias(
+          name TEXT PRIMARY KEY,
+          expansion TEXT NOT NULL
+        ) STRICT",
+        "CREATE TABLE saved_filter(
+          name TEXT PRIMARY KEY,
+          expression TEXT NOT NULL
+        ) STRICT",
+    ],
+    // Lyrics fetched from LRCLIB, found is false for the tracks it doesn't know
+    &[
+        "CREATE TABLE track_lyrics(
+          track_id INTEGER PRIMARY KEY,
+          found INTEGER NOT NULL,
+          plain TEXT,
+          synced TEXT,
+          instrumental INTEGER NOT NULL DEFAULT 0,
+          fetched_at INTEGER NOT NULL DEFAULT (unixepoch()),
+
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    // Files going with a track or an album, like cue sheets and artwork
+    &[
+        "CREATE TABLE sidecar(
+          id INTEGER PRIMARY KEY,
+          path TEXT NOT NULL UNIQUE,
+          kind TEXT NOT NULL,
+          track_id INTEGER,
+          album_id INTEGER,
+
+          CHECK((track_id IS NULL) != (album_id IS NULL)),
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE,
+          FOREIGN KEY(album_id) REFERENCES album(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE INDEX sidecar_track ON sidecar(track_id)",
+        "CREATE INDEX sidecar_album ON sidecar(album_id)",
+    ],
+    // Links to the same music on streaming services and stores, see `links`
+    &[
+        "ALTER TABLE album ADD COLUMN spotify_url TEXT",
+        "ALTER TABLE album ADD COLUMN apple_music_url TEXT",
+        "ALTER TABLE album ADD COLUMN bandcamp_url TEXT",
+        "ALTER TABLE track ADD COLUMN spotify_url TEXT",
+        "ALTER TABLE track ADD COLUMN apple_music_url TEXT",
+        "ALTER TABLE track ADD COLUMN bandcamp_url TEXT",
+        "DROP VIEW v_albums",
+        "CREATE VIEW v_albums AS
+         SELECT album.id, album.uid, album.name AS title, artist.name AS artist,
+                album.release_year AS year, album.release_type,
+                (SELECT COUNT(*) FROM track WHERE track.album_id = album.id) AS tracks,
+                album.cover_path, album.spotify_url, album.apple_music_url, album.bandcamp_url
+         FROM album
+         LEFT JOIN artist ON artist.id = album.artist_id",
+        "DROP VIEW v_tracks",
+        "CREATE VIEW v_tracks AS
+         SELECT track.id, track.uid, track.name AS title,
+                artist.name AS artist, album.name AS album, album_artist.name AS album_artist,
+                track.number AS track_number, track.track_total,
+                track.disc_number, track.disc_total,
+                track.release_year AS year, track.genre, album.release_type,
+                track.spoken_word, track.loudness, track.path,
+                lower(replace(track.path, rtrim(track.path, replace(track.path, '.', '')), ''))
+                  AS format,
+                track.source, datetime(track.first_seen_at, 'unixepoch') AS first_seen_at,
+                track.spotify_url, track.apple_music_url, track.bandcamp_url
+         FROM track
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         LEFT JOIN artist album_artist ON album_artist.id = album.artist_id",
+    ],
+    // Collections of albums and tracks, see `collection`
+    &[
+        "CREATE TABLE collection(
+          id INTEGER PRIMARY KEY,
+          name TEXT NOT NULL UNIQUE
+        ) STRICT",
+        "CREATE TABLE collection_album(
+          collection_id INTEGER NOT NULL,
+          album_id INTEGER NOT NULL,
+
+          PRIMARY KEY(collection_id, album_id),
+          FOREIGN KEY(collection_id) REFERENCES collection(id) ON DELETE CASCADE,
+          FOREIGN KEY(album_id) REFERENCES album(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE TABLE collection_track(
+          collection_id INTEGER NOT NULL,
+          track_id INTEGER NOT NULL,
+
+          PRIMARY KEY(collection_id, track_id),
+          FOREIGN KEY(collection_id) REFERENCES collection(id) ON DELETE CASCADE,
+          FOREIGN KEY(track_id) REFERENCES track(id) ON DELETE CASCADE
+        ) STRICT",
+        "CREATE INDEX collection_album_album ON collection_album(album_id)",
+        "CREATE INDEX collection_track_track ON collection_track(track_id)",
+        "CREATE VIEW v_collection_tracks AS
+         SELECT collection.id AS collection_id, collection.name AS collection, track.id AS track_id
+         FROM collection
+         JOIN collection_album ON collection_album.collection_id = collection.id
+         JOIN track ON track.album_id = collection_album.album_id
+         UNION
+         SELECT collection.id, collection.name, collection_track.track_id
+         FROM collection
+         JOIN collection_track ON collection_track.collection_id = collection.id",
+    ],
+    // Users only seeing some collections, see `user`
+    &[
+        "ALTER TABLE user ADD COLUMN restricted INTEGER NOT NULL DEFAULT 0",
+        "CREATE TABLE user_collection(
+          user_id INTEGER NOT NULL,
+          collection_id INTEGER NOT NULL,
+
+          PRIMARY KEY(user_id, collection_id),
+          FOREIGN KEY(user_id) REFERENCES user(id) ON DELETE CASCADE,
+          FOREIGN KEY(collection_id) REFERENCES collection(id) ON DELETE CASCADE
+        ) STRICT",
+    ],
+    // Tracks found by path ignoring case, for libraries on case-insensitive file systems
+    &["CREATE INDEX track_path_nocase ON track(path COLLATE NOCASE)"],
+    // Files the last scan couldn't read, see `unreadable`
+    &["CREATE TABLE unreadable_file(
+          path TEXT PRIMARY KEY,
+          folder INTEGER NOT NULL,
+          reason TEXT NOT NULL
+        ) STRICT"],
+    // Passwords encrypted at rest, see `user`
+    &["ALTER TABLE user ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0"],
+    // One album per artist and name, the duplicates merged into the first one
+    &[
+        "CREATE TEMP TABLE album_duplicate AS
+         SELECT id, keep FROM (
+           SELECT id, MIN(id) OVER (PARTITION BY artist_id, name) AS keep
+           FROM album
+           WHERE artist_id IS NOT NULL AND name IS NOT NULL
+         )
+         WHERE id <> keep",
+        "UPDATE track SET album_id = (SELECT keep FROM album_duplicate WHERE id = track.album_id)
+         WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "UPDATE sidecar SET album_id = (SELECT keep FROM album_duplicate WHERE id = sidecar.album_id)
+         WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "UPDATE OR IGNORE collection_album
+         SET album_id = (SELECT keep FROM album_duplicate WHERE id = collection_album.album_id)
+         WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "UPDATE OR IGNORE note SET album_id = (SELECT keep FROM album_duplicate WHERE id = note.album_id)
+         WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "UPDATE OR IGNORE job SET album_id = (SELECT keep FROM album_duplicate WHERE id = job.album_id)
+         WHERE album_id IN (SELECT id FROM album_duplicate)",
+        // What was already on the first album, the foreign keys don't cascade while migrating
+        "DELETE FROM collection_album WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "DELETE FROM note WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "DELETE FROM job WHERE album_id IN (SELECT id FROM album_duplicate)",
+        "DELETE FROM album WHERE id IN (SELECT id FROM album_duplicate)",
+        "DROP TABLE album_duplicate",
+        "CREATE UNIQUE INDEX album_artist_name ON album(artist_id, name)",
+    ],
+    // Alternative is an ID3v1 genre of its own, the alias seeded before merged it into
+    // Alternative Rock
+    &["DELETE FROM genre_alias
+       WHERE key = 'alternative' AND alias = 'Alternative' AND genre = 'Alternative Rock'"],
+    // The root the paths were last stored relative to, see `library::store_relative`
+    &["CREATE TABLE relative_root(root TEXT NOT NULL) STRICT"],
+];
+
+fn init_database(db: &mut rusqlite::Connection) -> Result<(), InitDatabaseError> {
+    // Up to date almost every time, which only takes reading the header
+    let version: usize = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    // A migration rebuilding a table would delete everything referencing it
+    db.pragma_update(None, "foreign_keys", false)?;
+
+    let savepoint = db.savepoint()?;
+
+    // Again, another zik may have migrated it meanwhile
+    let version: usize = savepoint.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (i, ddls) in MIGRATIONS.iter().enumerate().skip(version) {
+        for ddl in ddls.iter() {
+            savepoint.execute(ddl, [])?;
+        }
+        savepoint.pragma_update(None, "user_version", i + 1)?;
+    }
+
+    savepoint.commit()?;
+
+    db.pragma_update(None, "foreign_keys", true)?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+enum Config {
+    Library(PathBuf),
+    ScanParallelism(usize),
+    JobsParallelism(usize),
+    TranscodeFormat(String),
+    TranscodeBitrate(usize),
+    ServerAddress(String),
+    WatchInterval(usize),
+    SpokenWord(String),
+    ShuffleSpokenWord(bool),
+    IndexVideos(bool),
+    EnrichTtl(usize),
+    SortLocale(String),
+    IgnoredArticles(String),
+    NotifyWebhook(String),
+    NotifyNtfy(String),
+    NotifyDiscord(String),
+    TagStatsUser(String),
+    Inbox(PathBuf),
+    IoTimeout(usize),
+    IoRetries(usize),
+    SizeOnlyChanges(bool),
+    TombstoneTtl(usize),
+    ScanBatchSize(usize),
+    ScanIgnore(String),
+    TagFields(String),
+    ReplayGainMode(String),
+    ReplayGainPreamp(f64),
+    SnapcastFifo(PathBuf),
+    SnapcastFormat(String),
+    RipFormat(String),
+    ScanArchives(bool),
+    S3Endpoint(String),
+    S3Region(String),
+    DoctorRules(String),
+    SourceRules(String),
+    Language(String),
+    MetricsPublic(bool),
+    Crossfade(usize),
+    Gapless(bool),
+}
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Config::Library(val) => write!(f, "{}", val.display()),
+            Config::ScanParallelism(val) => write!(f, "{}", val),
+            Config::JobsParallelism(val) => write!(f, "{}", val),
+            Config::TranscodeFormat(val) => write!(f, "{}", val),
+            Config::TranscodeBitrate(val) => write!(f, "{}", val),
+            Config::ServerAddress(val) => write!(f, "{}", val),
+            Config::WatchInterval(val) => write!(f, "{}", val),
+            Config::SpokenWord(val) => write!(f, "{}", val),
+            Config::ShuffleSpokenWord(val) => write!(f, "{}", val),
+            Config::IndexVideos(val) => write!(f, "{}", val),
+            Config::EnrichTtl(val) => write!(f, "{}", val),
+            Config::SortLocale(val) => write!(f, "{}", val),
+            Config::IgnoredArticles(val) => write!(f, "{}", val),
+            Config::NotifyWebhook(val) => write!(f, "{}", val),
+            Config::NotifyNtfy(val) => write!(f, "{}", val),
+            Config::NotifyDiscord(val) => write!(f, "{}", val),
+            Config::TagStatsUser(val) => write!(f, "{}", val),
+            Config::Inbox(val) => write!(f, "{}", val.display()),
+            Config::IoTimeout(val) => write!(f, "{}", val),
+            Config::IoRetries(val) => write!(f, "{}", val),
+            Config::SizeOnlyChanges(val) => write!(f, "{}", val),
+            Config::TombstoneTtl(val) => write!(f, "{}", val),
+            Config::ScanBatchSize(val) => write!(f, "{}", val),
+            Config::ScanIgnore(val) => write!(f, "{}", val),
+            Config::TagFields(val) => write!(f, "{}", val),
+            Config::ReplayGainMode(val) => write!(f, "{}", val),
+            Config::ReplayGainPreamp(val) => write!(f, "{}", val),
+            Config::SnapcastFifo(val) => write!(f, "{}", val.display()),
+            Config::SnapcastFormat(val) => write!(f, "{}", val),
+            Config::RipFormat(val) => write!(f, "{}", val),
+            Config::ScanArchives(val) => write!(f, "{}", val),
+            Config::S3Endpoint(val) => write!(f, "{}", val),
+            Config::S3Region(val) => write!(f, "{}", val),
+            Config::DoctorRules(val) => write!(f, "{}", val),
+            Config::SourceRules(val) => write!(f, "{}", val),
+            Config::Language(val) => write!(f, "{}", val),
+            Config::MetricsPublic(val) => write!(f, "{}", val),
+            Config::Crossfade(val) => write!(f, "{}", val),
+            Config::Gapless(val) => write!(f, "{}", val),
+        }
+    }
+}
+impl rusqlite::ToSql for Config {
+    fn to_sql(&self) -> Result<rusqlite::types::ToSqlOutput<'_>, rusqlite::Error> {
+        match self {
+            Config::Library(path) | Config::Inbox(path) | Config::SnapcastFifo(path) => {
+                let path_data = path.to_string_lossy().to_string();
+                Ok(rusqlite::types::ToSqlOutput::from(path_data))
+            }
+            Config::TranscodeFormat(value)
+            | Config::ServerAddress(value)
+            | Config::SpokenWord(value)
+            | Config::SortLocale(value)
+            | Config::IgnoredArticles(value)
+            | Config::NotifyWebhook(value)
+            | Config::NotifyNtfy(value)
+            | Config::NotifyDiscord(value)
+            | Config::TagStatsUser(value)
+            | Config::ScanIgnore(value)
+            | Config::TagFields(value)
+            | Config::ReplayGainMode(value)
+            | Config::SnapcastFormat(value)
+            | Config::RipFormat(value)
+            | Config::S3Endpoint(value)
+            | Config::S3Region(value)
+            | Config::DoctorRules(value)
+            | Config::SourceRules(value)
+            | Config::Language(value) => Ok(rusqlite::types::ToSqlOutput::from(value.as_str())),
+            Config::ScanParallelism(n)
+            | Config::JobsParallelism(n)
+            | Config::TranscodeBitrate(n)
+            | Config::WatchInterval(n)
+            | Config::EnrichTtl(n)
+            | Config::IoTimeout(n)
+            | Config::IoRetries(n)
+            | Config::TombstoneTtl(n)
+            | Config::ScanBatchSize(n)
+            | Config::Crossfade(n) => {
+                let new_n = *n as i64;
+                Ok(rusqlite::types::ToSqlOutput::from(new_n))
+            }
+            Config::ShuffleSpokenWord(value)
+            | Config::IndexVideos(value)
+            | Config::SizeOnlyChanges(value)
+            | Config::ScanArchives(value)
+            | Config::MetricsPublic(value)
+            | Config::Gapless(value) => Ok(rusqlite::types::ToSqlOutput::from(*value)),
+            Config::ReplayGainPreamp(value) => Ok(rusqlite::types::ToSqlOutput::from(*value)),
+        }
+    }
+}
+impl Config {
+    const VALID_KEYS: [&'static str; 42] = [
+        "library",
+        "scan_parallelism",
+        "jobs_parallelism",
+        "transcode_format",
+        "transcode_bitrate",
+        "server_address",
+        "watch_interval",
+        "spoken_word",
+        "shuffle_spoken_word",
+        "index_videos",
+        "lastfm_api_key",
+        "enrich_ttl",
+        "sort_locale",
+        "ignored_articles",
+        "notify_webhook",
+        "notify_ntfy",
+        "notify_discord",
+        "tag_stats_user",
+        "acoustid_api_key",
+        "inbox",
+        "io_timeout",
+        "io_retries",
+        "size_only_changes",
+        "tombstone_ttl",
+        "scan_batch_size",
+        "scan_ignore",
+        "tag_fields",
+        "replay_gain_mode",
+        "replay_gain_preamp",
+        "snapcast_fifo",
+        "snapcast_format",
+        "rip_format",
+        "scan_archives",
+        "library_credentials",
+        "s3_endpoint",
+        "s3_region",
+        "doctor_rules",
+        "source_rules",
+        "language",
+        "metrics_public",
+        "crossfade",
+        "gapless",
+    ];
+
+    fn is_valid_key(key: &str) -> bool {
+        Config::VALID_KEYS.contains(&key)
+    }
+}
+
+/// Returns the value of a configuration key as text, if it's set.
+fn get_config_value(db: &rusqlite::Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    let result = db.query_row(
+        "SELECT CAST(value AS TEXT) FROM config WHERE key = $key",
+        [key],
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the value of a boolean configuration key, false if it's not set.
+fn get_config_bool(db: &rusqlite::Connection, key: &str) -> rusqlite::Result<bool> {
+    Ok(get_config_value(db, key)?.as_deref() == Some("1"))
+}
+
+/// Returns the value of a numeric configuration key, if it's set to a valid number.
+fn get_config_usize(db: &rusqlite::Connection, key: &str) -> rusqlite::Result<Option<usize>> {
+    Ok(get_config_value(db, key)?.and_then(|value| value.parse().ok()))
+}
+
+enum CommandConfigError {
+    SQLite(rusqlite::Error),
+    InvalidKey(String),
+    NoValue(String),
+    GetLibraryPath(GetLibraryPathError),
+    InvalidScanParallelismValue(std::num::ParseIntError),
+    InvalidJobsParallelismValue(std::num::ParseIntError),
+    InvalidTranscodeFormat(String),
+    InvalidTranscodeBitrateValue(std::num::ParseIntError),
+    InvalidServerAddress(String),
+    InvalidWatchIntervalValue(std::num::ParseIntError),
+    InvalidSpokenWordFolder(String),
+    InvalidBoolValue(&'static str, String),
+    InvalidEnrichTtlValue(std::num::ParseIntError),
+    InvalidSortLocale(String),
+    InvalidNotifyUrl(String, String),
+    InvalidIoTimeoutValue(std::num::ParseIntError),
+    InvalidIoRetriesValue(std::num::ParseIntError),
+    InvalidTombstoneTtlValue(std::num::ParseIntError),
+    InvalidScanBatchSizeValue(std::num::ParseIntError),
+    InvalidTagFields(String),
+    InvalidReplayGainMode(String),
+    InvalidReplayGainPreampValue(std::num::ParseFloatError),
+    InvalidSnapcastFormat(String),
+    InvalidRipFormat(String),
+    InvalidLibraryUrl(storage::StorageError),
+    InvalidDoctorRules(String),
+    InvalidSourceRules(String),
+    InvalidLanguage(String),
+    InvalidCrossfadeValue(std::num::ParseIntError),
+    Secret(String),
+}
+impl From<rusqlite::Error> for CommandConfigError {
+    fn from(err: rusqlite::Error) -> CommandConfigError {
+        CommandConfigError::SQLite(err)
+    }
+}
+impl From<GetLibraryPathError> for CommandConfigError {
+    fn from(err: GetLibraryPathError) -> CommandConfigError {
+        CommandConfigError::GetLibraryPath(err)
+    }
+}
+impl fmt::Display for CommandConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            CommandConfigError::SQLite(err) => tr!("sqlite-error", err = err),
+            CommandConfigError::InvalidKey(key) => tr!("config-invalid-key", key = key),
+            CommandConfigError::NoValue(key) => tr!("config-no-value", key = key),
+            CommandConfigError::GetLibraryPath(err) => tr!("config-library-path", err = err),
+            CommandConfigError::InvalidScanParallelismValue(err) => {
+                tr!(
+                    "config-invalid-value",
+                    key = "scan_parallelism",
+                    value = err
+                )
+            }
+            CommandConfigError::InvalidJobsParallelismValue(err) => {
+                tr!(
+                    "config-invalid-value",
+                    key = "jobs_parallelism",
+                    value = err
+                )
+            }
+            CommandConfigError::InvalidTranscodeFormat(value) => {
+                tr!("config-invalid-transcode-format", value = value)
+            }
+            CommandConfigError::InvalidTranscodeBitrateValue(err) => {
+                tr!(
+                    "config-invalid-value",
+                    key = "transcode_bitrate",
+                    value = err
+                )
+            }
+            CommandConfigError::InvalidServerAddress(value) => {
+                tr!("config-invalid-server-address", value = value)
+            }
+            CommandConfigError::InvalidWatchIntervalValue(err) => {
+                tr!("config-invalid-value", key = "watch_interval", value = err)
+            }
+            CommandConfigError::InvalidSpokenWordFolder(value) => {
+                tr!("config-invalid-spoken-word-folder", value = value)
+            }
+            CommandConfigError::InvalidBoolValue(key, value) => {
+                tr!("config-invalid-bool", key = key, value = value)
+            }
+            CommandConfigError::InvalidEnrichTtlValue(err) => {
+                tr!("config-invalid-value", key = "enrich_ttl", value = err)
+            }
+            CommandConfigError::InvalidSortLocale(value) => {
+                tr!("config-invalid-sort-locale", value = value)
+            }
+            CommandConfigError::InvalidNotifyUrl(key, value) => {
+                tr!("config-invalid-url", key = key, value = value)
+            }
+            CommandConfigError::InvalidIoTimeoutValue(err) => {
+                tr!("config-invalid-value", key = "io_timeout", value = err)
+            }
+            CommandConfigError::InvalidIoRetriesValue(err) => {
+                tr!("config-invalid-value", key = "io_retries", value = err)
+            }
+            CommandConfigError::InvalidTombstoneTtlValue(err) => {
+                tr!("config-invalid-value", key = "tombstone_ttl", value = err)
+            }
+            CommandConfigError::InvalidScanBatchSizeValue(err) => {
+                tr!("config-invalid-value", key = "scan_batch_size", value = err)
+            }
+            CommandConfigError::InvalidTagFields(err) => {
+                tr!("config-invalid-value-reason", key = "tag_fields", err = err)
+            }
+            CommandConfigError::InvalidReplayGainMode(value) => {
+                tr!("config-invalid-replay-gain-mode", value = value)
+            }
+            CommandConfigError::InvalidReplayGainPreampValue(err) => {
+                tr!(
+                    "config-invalid-value",
+                    key = "replay_gain_preamp",
+                    value = err
+                )
+            }
+            CommandConfigError::InvalidSnapcastFormat(value) => {
+                tr!("config-invalid-snapcast-format", value = value)
+            }
+            CommandConfigError::InvalidRipFormat(value) => {
+                tr!("config-invalid-rip-format", value = value)
+            }
+            CommandConfigError::InvalidLibraryUrl(err) => {
+                tr!("config-invalid-value-reason", key = "library", err = err)
+            }
+            CommandConfigError::InvalidDoctorRules(err) => {
+                tr!(
+                    "config-invalid-value-reason",
+                    key = "doctor_rules",
+                    err = err
+                )
+            }
+            CommandConfigError::InvalidSourceRules(err) => {
+                tr!(
+                    "config-invalid-value-reason",
+                    key = "source_rules",
+                    err = err
+                )
+            }
+            CommandConfigError::InvalidLanguage(value) => tr!(
+                "config-invalid-language",
+                value = value,
+                languages = i18n::LANGUAGES.join(", ")
+            ),
+            CommandConfigError::InvalidCrossfadeValue(err) => {
+                tr!("config-invalid-value", key = "crossfade", value = err)
+            }
+            CommandConfigError::Secret(key) => tr!("config-secret", key = key),
+        };
+
+        f.write_str(&message)
+    }
+}
+
+enum GetLibraryPathError {
+    DoestNotExist(PathBuf),
+    NotADirectory(PathBuf),
+    IO(io::Error),
+}
+impl From<io::Error> for GetLibraryPathError {
+    fn from(err: io::Error) -> GetLibraryPathError {
+        GetLibraryPathError::IO(err)
+    }
+}
+impl fmt::Display for GetLibraryPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GetLibraryPathError::DoestNotExist(path) => {
+                f.write_str(&tr!("library-path-not-found", path = path.display()))
+            }
+            GetLibraryPathError::NotADirectory(path) => {
+                f.write_str(&tr!("library-path-not-a-directory", path = path.display()))
+            }
+            GetLibraryPathError::IO(err) => f.write_str(&tr!("library-path-io", err = err)),
+        }
+    }
+}
+
+fn get_library_path(value: &str) -> Result<PathBuf, GetLibraryPathError> {
+    let path = Path::new(value);
+    if !path.exists() {
+        return Err(GetLibraryPathError::DoestNotExist(path.to_path_buf()));
+    }
+    if !path.is_dir() {
+        return Err(GetLibraryPathError::NotADirectory(path.to_path_buf()));
+    }
+    path.metadata()?;
+
+    let canonicalized_path = fs::canonicalize(path)?;
+
+    Ok(canonicalized_path)
+}
+
+fn parse_config_bool(key: &'static str, value: &str) -> Result<bool, CommandConfigError> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(CommandConfigError::InvalidBoolValue(key, value.to_string())),
+    }
+}
+
+/// Validates `value` and stores it as the value of the configuration key `key`.
+fn set_config(db: &rusqlite::Connection, key: &str, value: &str) -> Result<(), CommandConfigError> {
+    // Given as an argument, a secret would end up in the shell history and the process list
+    if secrets::NAMES.contains(&key) {
+        return Err(CommandConfigError::Secret(key.to_string()));
+    }
+
+    let config: Config = match key {
+        "library" => {
+            if storage::is_remote(value) {
+                // Only checked to be a supported URL, the server can be away for now
+                if let Err(err) = storage::open(value, &storage::Options::default()) {
+                    return Err(CommandConfigError::InvalidLibraryUrl(err));
+                }
+                Config::Library(PathBuf::from(value))
+            } else {
+                let dir = get_library_path(value)?;
+                Config::Library(dir)
+            }
+        }
+        "scan_parallelism" => {
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidScanParallelismValue(err)),
+            };
+            Config::ScanParallelism(n)
+        }
+        "jobs_parallelism" => {
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidJobsParallelismValue(err)),
+            };
+            Config::JobsParallelism(n)
+        }
+        "transcode_format" => {
+            if ffmpeg::codec_for(value).is_none() {
+                return Err(CommandConfigError::InvalidTranscodeFormat(
+                    value.to_string(),
+                ));
+            }
+            Config::TranscodeFormat(value.to_string())
+        }
+        "transcode_bitrate" => {
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidTranscodeBitrateValue(err)),
+            };
+            Config::TranscodeBitrate(n)
+        }
+        "server_address" => {
+            if value.parse::<std::net::SocketAddr>().is_err() {
+                return Err(CommandConfigError::InvalidServerAddress(value.to_string()));
+            }
+            Config::ServerAddress(value.to_string())
+        }
+        "watch_interval" => {
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidWatchIntervalValue(err)),
+            };
+            Config::WatchInterval(n)
+        }
+        "spoken_word" => {
+            // Folders relative to the library, separated by colons; "." is the whole library
+            for folder in value.split(':') {
+                if Path::new(folder.trim()).is_absolute() {
+                    return Err(CommandConfigError::InvalidSpokenWordFolder(
+                        folder.to_string(),
+                    ));
+                }
+            }
+            Config::SpokenWord(value.to_string())
+        }
+        "shuffle_spoken_word" => {
+            Config::ShuffleSpokenWord(parse_config_bool("shuffle_spoken_word", value)?)
+        }
+        "index_videos" => Config::IndexVideos(parse_config_bool("index_videos", value)?),
+        "enrich_ttl" => {
+            // In days
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidEnrichTtlValue(err)),
+            };
+            Config::EnrichTtl(n)
+        }
+        "sort_locale" => {
+            if collation::Locale::from_tag(value).is_none() {
+                return Err(CommandConfigError::InvalidSortLocale(value.to_string()));
+            }
+            Config::SortLocale(value.to_string())
+        }
+        // Separated by spaces, empty to sort on the whole names
+        "ignored_articles" => Config::IgnoredArticles(value.to_string()),
+        // Empty to disable the notification
+        "notify_webhook" | "notify_ntfy" | "notify_discord" => {
+            if !value.is_empty() && !value.starts_with("http://") && !value.starts_with("https://")
+            {
+                return Err(CommandConfigError::InvalidNotifyUrl(
+                    key.to_string(),
+                    value.to_string(),
+                ));
+            }
+            match key {
+                "notify_webhook" => Config::NotifyWebhook(value.to_string()),
+                "notify_ntfy" => Config::NotifyNtfy(value.to_string()),
+                _ => Config::NotifyDiscord(value.to_string()),
+            }
+        }
+        "tag_stats_user" => Config::TagStatsUser(value.to_string()),
+        "inbox" => Config::Inbox(get_library_path(value)?),
+        "io_timeout" => {
+            // In seconds, 0 to wait forever
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidIoTimeoutValue(err)),
+            };
+            Config::IoTimeout(n)
+        }
+        "io_retries" => {
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidIoRetriesValue(err)),
+            };
+            Config::IoRetries(n)
+        }
+        "tombstone_ttl" => {
+            // In days, 0 to keep the missing tracks forever
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidTombstoneTtlValue(err)),
+            };
+            Config::TombstoneTtl(n)
+        }
+        "scan_batch_size" => {
+            // In files, 0 to scan in a single transaction
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidScanBatchSizeValue(err)),
+            };
+            Config::ScanBatchSize(n)
+        }
+        // Empty to scan every file
+        "scan_ignore" => Config::ScanIgnore(value.to_string()),
+        "tag_fields" => {
+            // Like "mood=MOOD,TXXX:MY_MOOD;energy=ENERGY", see `tags`
+            if let Err(err) = tags::CustomFields::parse(value) {
+                return Err(CommandConfigError::InvalidTagFields(err));
+            }
+            Config::TagFields(value.to_string())
+        }
+        "replay_gain_mode" => {
+            if replaygain::GainMode::parse(value).is_none() {
+                return Err(CommandConfigError::InvalidReplayGainMode(value.to_string()));
+            }
+            Config::ReplayGainMode(value.to_string())
+        }
+        "replay_gain_preamp" => {
+            // In dB, added to the gain of every normalized stream
+            let preamp: f64 = match value.parse() {
+                Ok(preamp) => preamp,
+                Err(err) => return Err(CommandConfigError::InvalidReplayGainPreampValue(err)),
+            };
+            Config::ReplayGainPreamp(preamp)
+        }
+        // Empty for /tmp/snapfifo
+        "snapcast_fifo" => Config::SnapcastFifo(PathBuf::from(value)),
+        "snapcast_format" => {
+            if play::SampleFormat::parse(value).is_none() {
+                return Err(CommandConfigError::InvalidSnapcastFormat(value.to_string()));
+            }
+            Config::SnapcastFormat(value.to_string())
+        }
+        "rip_format" => {
+            if !rip::FORMATS.contains(&value) {
+                return Err(CommandConfigError::InvalidRipFormat(value.to_string()));
+            }
+            Config::RipFormat(value.to_string())
+        }
+        "size_only_changes" => {
+            Config::SizeOnlyChanges(parse_config_bool("size_only_changes", value)?)
+        }
+        "scan_archives" => Config::ScanArchives(parse_config_bool("scan_archives", value)?),
+        "s3_endpoint" => {
+            if !value.starts_with("http://") && !value.starts_with("https://") {
+                return Err(CommandConfigError::InvalidNotifyUrl(
+                    "s3_endpoint".to_string(),
+                    value.to_string(),
+                ));
+            }
+            Config::S3Endpoint(value.to_string())
+        }
+        "s3_region" => Config::S3Region(value.to_string()),
+        "doctor_rules" => {
+            // Like "mixed_formats=ignore,year_out_of_range=error", see `doctor`
+            if let Err(err) = doctor::parse_rules(value) {
+                return Err(CommandConfigError::InvalidDoctorRules(err));
+            }
+            Config::DoctorRules(value.to_string())
+        }
+        "source_rules" => {
+            // Like "Vinyl/*=vinyl rip 24/96;Bandcamp/*=bandcamp", see `source`
+            if let Err(err) = source::SourceRules::parse(value) {
+                return Err(CommandConfigError::InvalidSourceRules(err));
+            }
+            Config::SourceRules(value.to_string())
+        }
+        "language" => match i18n::parse_language(value) {
+            Some(language) => Config::Language(language.to_string()),
+            None => return Err(CommandConfigError::InvalidLanguage(value.to_string())),
+        },
+        "metrics_public" => Config::MetricsPublic(parse_config_bool("metrics_public", value)?),
+        "crossfade" => {
+            // In seconds, 0 for none
+            let n: usize = match value.parse() {
+                Ok(n) => n,
+                Err(err) => return Err(CommandConfigError::InvalidCrossfadeValue(err)),
+            };
+            Config::Crossfade(n)
+        }
+        "gapless" => Config::Gapless(parse_config_bool("gapless", value)?),
+        _ => return Err(CommandConfigError::InvalidKey(key.to_string())),
+    };
+
+    let query = "INSERT INTO config(key, value) VALUES($key, $value) ON CONFLICT(key) DO UPDATE SET value = excluded.value";
+
+    db.execute(query, rusqlite::params![key, config])?;
+
+    if key == "ignored_articles" {
+        collation::update_sort_names(db)?;
+    }
+
+    Ok(())
+}
+
+fn cmd_config(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandConfigError> {
+    if args.is_present("key") {
+        if args.is_present("key") && args.is_present("value") {
+            let key = args.value_of("key").unwrap();
+            let value = args.value_of("value").unwrap();
+
+            set_config(db, key, value)?;
+        } else {
+            let key = args.value_of("key").unwrap();
+            if !Config::is_valid_key(key) {
+                return Err(CommandConfigError::InvalidKey(key.to_string()));
+            }
+
+            let value_result: rusqlite::Result<String> = db.query_row(
+                "SELECT CAST(value AS TEXT) FROM config WHERE key = $key",
+                [key],
+                |row| row.get(0),
+            );
+
+            let value = match value_result {
+                Ok(value) => value,
+                Err(err) => match err {
+                    rusqlite::Error::QueryReturnedNoRows => {
+                        return Err(CommandConfigError::NoValue(key.to_string()));
+                    }
+                    _ => return Err(CommandConfigError::SQLite(err)),
+                },
+            };
+
+            println!("{} = \"{}\"", key, secrets::mask(key, value));
+        }
+    } else {
+        let mut stmt = db.prepare("SELECT key, CAST(value AS TEXT) FROM config")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(0)?;
+            let value: String = row.get(1)?;
+
+            println!("{} = \"{}\"", key, secrets::mask(&key, value));
+        }
+    }
+
+    Ok(())
+}
+
+enum MetadataReadError {
+    IO(io::Error),
+}
+impl From<io::Error> for MetadataReadError {
+    fn from(err: io::Error) -> MetadataReadError {
+        MetadataReadError::IO(err)
+    }
+}
+impl fmt::Display for MetadataReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetadataReadError::IO(err) => f.write_str(&tr!("scan-read-error", err = err)),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Metadata {
+    artist: Option<String>,
+    /// Every artist of a track with multi-valued artist tags, the first one being `artist`.
+    artists: Vec<String>,
+    album: Option<String>,
+    album_artist: Option<String>,
+    year: Option<String>,
+    /// The ORIGINALDATE or TDOR tag of a reissue.
+    original_year: Option<String>,
+    track_name: Option<String>,
+    track_number: usize,
+    track_total: Option<usize>,
+    disc_number: Option<usize>,
+    disc_total: Option<usize>,
+    genre: Option<String>,
+    comment: Option<String>,
+    lyrics: Option<String>,
+    /// The RELEASETYPE or MusicBrainz album type tag, like "album; live".
+    release_type: Option<String>,
+    compilation: bool,
+    duration_ms: Option<i64>,
+    /// The rating other players wrote, in stars.
+    rating: Option<i64>,
+    play_count: Option<i64>,
+    musicbrainz_album_id: Option<String>,
+    musicbrainz_artist_id: Option<String>,
+    label: Option<String>,
+    /// The ReplayGain adjustments, in dB.
+    replay_gain_track: Option<f64>,
+    replay_gain_album: Option<f64>,
+    moods: Vec<String>,
+    /// The ENERGY tag, from 1 to 10.
+    energy: Option<i64>,
+    /// The fields of `tag_fields`, by name.
+    custom_fields: Vec<(String, String)>,
+    /// Set for MP4 files with a video track, which aren't tracks of the library.
+    video: bool,
+}
+/// The largest `moov` atom read on its own to get the tags of an MP4 file; it holds the sample
+/// tables too, which grow with the duration.
+const MP4_HEADER_BUDGET: u64 = 32 * 1024 * 1024;
+
+impl Metadata {
+    /// Reads the fields of a tag, whatever its format.
+    fn from_tag(tag: &dyn tags::TagReader, fields: &tags::CustomFields) -> Metadata {
+        let (track_number, track_total) =
+            tag.get_number_pair(tags::Field::TrackNumber, tags::Field::TrackTotal);
+        let (disc_number, disc_total) =
+            tag.get_number_pair(tags::Field::DiscNumber, tags::Field::DiscTotal);
+        let artists = tag.get_all(tags::Field::Artist);
+
+        Metadata {
+            artist: artists.first().cloned(),
+            artists,
+            album: tag.get(tags::Field::Album),
+            album_artist: tag.get(tags::Field::AlbumArtist),
+            year: tag.get(tags::Field::Date),
+            original_year: tag.get(tags::Field::OriginalDate),
+            track_name: tag.get(tags::Field::Title),
+            track_number: track_number.unwrap_or(0),
+            track_total,
+            disc_number,
+            disc_total,
+            genre: tag.get(tags::Field::Genre),
+            comment: tag.get(tags::Field::Comment),
+            lyrics: tag.get(tags::Field::Lyrics),
+            release_type: Some(tag.get_all(tags::Field::ReleaseType))
+                .filter(|values| !values.is_empty())
+                .map(|values| values.join(";")),
+            compilation: tag.get(tags::Field::Compilation).as_deref() == Some("1"),
+            duration_ms: tag.duration_ms(),
+            rating: tag.rating(),
+            play_count: tag.play_count(),
+            musicbrainz_album_id: tag.get(tags::Field::MusicBrainzAlbumId),
+            musicbrainz_artist_id: tag.get(tags::Field::MusicBrainzArtistId),
+            label: tag.get(tags::Field::Label),
+            replay_gain_track: tag
+                .get(tags::Field::ReplayGainTrack)
+                .and_then(|value| parse_replay_gain(&value)),
+            replay_gain_album: tag
+                .get(tags::Field::ReplayGainAlbum)
+                .and_then(|value| parse_replay_gain(&value)),
+            moods: mood::parse_moods(tag.get_all(tags::Field::Mood)),
+            energy: tag
+                .get(tags::Field::Energy)
+                .and_then(|value| mood::parse_energy(&value)),
+            custom_fields: fields.read(tag),
+            video: false,
+        }
+    }
+
+    /// Returns the duration of the first audio track of an MP4 file.
+    fn get_mp4_duration(root: &mp4parse::MediaContext) -> Option<i64> {
+        root.tracks
+            .iter()
+            .filter(|track| track.track_type == mp4parse::TrackType::Audio)
+            .find_map(|track| match (&track.duration, &track.timescale) {
+                (Some(duration), Some(timescale)) if timescale.0 > 0 => {
+                    Some((duration.0 * 1000 / timescale.0) as i64)
+                }
+                _ => None,
+            })
+    }
+
+    /// Returns true if an MP4 file has a video track.
+    ///
+    /// Audiobooks can have one for their chapter images, so the audio-only extensions are
+    /// trusted over the content.
+    fn is_mp4_video(path: &Path, root: &mp4parse::MediaContext) -> bool {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        if let Some("m4a" | "m4b" | "m4p") = extension.as_deref() {
+            return false;
+        }
+
+        root.tracks
+            .iter()
+            .any(|track| track.track_type == mp4parse::TrackType::Video)
+    }
+
+    /// Reads the metadata of a file maybe on a network filesystem, see `netfs::read`.
+    fn read_with_options(
+        options: &netfs::IoOptions,
+        fields: &Arc<tags::CustomFields>,
+        path: &Path,
+    ) -> Result<Option<Metadata>, MetadataReadError> {
+        let fields = Arc::clone(fields);
+        let md = netfs::read(options, path, move |path| {
+            Metadata::read_from_path(path, &fields).map_err(|MetadataReadError::IO(err)| err)
+        })?;
+        Ok(md)
+    }
+
+    fn read_from_path(
+        path: &Path,
+        fields: &tags::CustomFields,
+    ) -> Result<Option<Metadata>, MetadataReadError> {
+        let mut file = probe::CountingReader::new(fs::File::open(path)?);
+        // Before buffering, moving back to the start would drop the buffer and read it again
+        let format = probe::sniff(&mut file).unwrap_or(probe::Format::Unknown);
+        let mut reader = io::BufReader::new(file);
+
+        let md = Metadata::read_format(format, &mut reader, path, fields);
+        metrics::record_tag_bytes(reader.get_ref().count());
+
+        md
+    }
+
+    /// Reads the metadata of the file `path`, inside an archive maybe, from `reader`.
+    fn read_from_reader<R: io::Read + io::Seek>(
+        mut reader: R,
+        path: &Path,
+        fields: &tags::CustomFields,
+    ) -> Result<Option<Metadata>, MetadataReadError> {
+        let format = probe::sniff(&mut reader).unwrap_or(probe::Format::Unknown);
+        Metadata::read_format(format, reader, path, fields)
+    }
+
+    /// Reads the metadata of a file of the format `format` from `reader`, at its start.
+    fn read_format<R: io::Read + io::Seek>(
+        format: probe::Format,
+        mut reader: R,
+        path: &Path,
+        fields: &tags::CustomFields,
+    ) -> Result<Option<Metadata>, MetadataReadError> {
+        // Parsed as that format only, or as FLAC, then MP3, then MP4
+        if format == probe::Format::Ogg {
+            return Ok(ogg::read(&mut reader)?.map(|tag| Metadata::from_tag(&tag, fields)));
+        }
+
+        if let probe::Format::Flac | probe::Format::Unknown = format {
+            if let Ok(tag) = metaflac::Tag::read_from(&mut reader) {
+                return Ok(Some(Metadata::from_tag(&tag, fields)));
+            }
+            reader.seek(io::SeekFrom::Start(0))?;
+        }
+
+        if let probe::Format::Id3 | probe::Format::Unknown = format {
+            if let Ok(tag) = id3::Tag::read_from(&mut reader) {
+                return Ok(Some(Metadata::from_tag(&tag, fields)));
+            }
+            reader.seek(io::SeekFrom::Start(0))?;
+        }
+
+        if format == probe::Format::Mp4 {
+            // Files laid out unlike any tagger would are parsed whole below
+            if let Ok(Some(atoms)) = mp4meta::read_header_atoms(&mut reader, MP4_HEADER_BUDGET) {
+                return Metadata::read_mp4(io::Cursor::new(atoms), path, fields);
+            }
+            reader.seek(io::SeekFrom::Start(0))?;
+        }
+
+        if let probe::Format::Mp4 | probe::Format::Unknown = format {
+            return Metadata::read_mp4(reader, path, fields);
+        }
+
+        Ok(None)
+    }
+
+    fn read_mp4<R: io::Read + io::Seek>(
+        mut reader: R,
+        path: &Path,
+        fields: &tags::CustomFields,
+    ) -> Result<Option<Metadata>, MetadataReadError> {
+        let root = match mp4parse::read_mp4(&mut reader) {
+            Ok(root) => root,
+            Err(_) => return Ok(None),
+        };
+        let video = Metadata::is_mp4_video(path, &root);
+        let duration_ms = Metadata::get_mp4_duration(&root);
+
+        let metadata = match root.userdata {
+            Some(Ok(user_data)) => user_data.meta,
+            _ => None,
+        };
+        match metadata {
+            Some(metadata) => {
+                // A broken freeform atom only loses the freeform tags
+                reader.seek(io::SeekFrom::Start(0))?;
+                let freeform = mp4meta::read_freeform(&mut reader).unwrap_or_default();
+
+                let tag = tags::Mp4Tag::new(metadata, freeform, duration_ms);
+                Ok(Some(Metadata {
+                    video,
+                    ..Metadata::from_tag(&tag, fields)
+                }))
+            }
+            // Videos are rarely tagged but still worth telling apart
+            None if video => Ok(Some(Metadata {
+                duration_ms,
+                video,
+                ..Default::default()
+            })),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Parses a track or disc number tag, which is either a plain number or a "number/total" pair
+/// like "3/12".
+fn parse_number_pair(value: &str) -> (Option<usize>, Option<usize>) {
+    let (number, total) = match value.split_once('/') {
+        Some((number, total)) => (number, Some(total)),
+        None => (value, None),
+    };
+
+    (
+        number.trim().parse().ok(),
+        total.and_then(|total| total.trim().parse().ok()),
+    )
+}
+
+/// Parses a ReplayGain adjustment like "-7.32 dB".
+fn parse_replay_gain(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|gain: &f64| gain.is_finite())
+}
+
+/// Extracts the year out of a date tag.
+///
+/// Taggers write anything from "1994" to "1994-03-21T00:00:00Z" or "21/03/1994"; the first
+/// group of exactly four digits is taken as the year.
+fn parse_release_year(value: &str) -> Option<i64> {
+    value
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|part| part.len() == 4)
+        .and_then(|part| part.parse().ok())
+}
+
+//
+// Save functions
+//
+
+type ArtistID = usize;
+type AlbumID = usize;
+type TrackID = usize;
+
+/// Finds a track by id, or by the path of its file.
+fn find_track_id(db: &rusqlite::Connection, value: &str) -> rusqlite::Result<Option<TrackID>> {
+    let result = match value.parse::<i64>() {
+        Ok(id) => db.query_row("SELECT id FROM track WHERE id = $id", [id], |row| {
+            row.get(0)
+        }),
+        Err(_) => {
+            let path = canonical_path(Path::new(value));
+            db.query_row(
+                "SELECT id FROM track WHERE path = $path",
+                [library::relative(&path)],
+                |row| row.get(0),
+            )
+        }
+    };
+
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Finds an album by id, or by its name ignoring case.
+fn find_album_id(db: &rusqlite::Connection, value: &str) -> rusqlite::Result<Option<AlbumID>> {
+    let result = match value.parse::<i64>() {
+        Ok(id) => db.query_row("SELECT id FROM album WHERE id = $id", [id], |row| {
+            row.get(0)
+        }),
+        Err(_) => db.query_row(
+            "SELECT id FROM album WHERE name = $name COLLATE NOCASE",
+            [value],
+            |row| row.get(0),
+        ),
+    };
+
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+enum SaveArtistError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for SaveArtistError {
+    fn from(err: rusqlite::Error) -> SaveArtistError {
+        SaveArtistError::SQLite(err)
+    }
+}
+impl fmt::Display for SaveArtistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveArtistError::SQLite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+fn save_artist(
+    savepoint: &mut rusqlite::Savepoint,
+    artist: &str,
+) -> Result<ArtistID, SaveArtistError> {
+    let id_result = savepoint.query_row(
+        "SELECT id FROM artist WHERE name = $name",
+        [artist],
+        |row| {
+            let id = row.get(0)?;
+            Ok(id)
+        },
+    );
+
+    match id_result {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            match savepoint.execute("INSERT INTO artist(name) VALUES($name)", [artist]) {
+                Ok(_) => Ok(savepoint.last_insert_rowid() as usize),
+                Err(err) => Err(SaveArtistError::SQLite(err)),
+            }
+        }
+        Err(err) => Err(SaveArtistError::SQLite(err)),
+    }
+}
+
+enum SaveAlbumError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for SaveAlbumError {
+    fn from(err: rusqlite::Error) -> SaveAlbumError {
+        SaveAlbumError::SQLite(err)
+    }
+}
+impl fmt::Display for SaveAlbumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveAlbumError::SQLite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+fn save_album(
+    savepoint: &mut rusqlite::Savepoint,
+    artist_id: ArtistID,
+    album: &str,
+    year: Option<&str>,
+) -> Result<AlbumID, SaveArtistError> {
+    let id_result = savepoint.query_row(
+        "SELECT id FROM album WHERE artist_id = $artist_id AND name = $name",
+        rusqlite::params![artist_id, album],
+        |row| {
+            let id = row.get(0)?;
+            Ok(id)
+        },
+    );
+
+    match id_result {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            match savepoint.execute(
+                "INSERT INTO album(artist_id, name, year, release_year, added_at) VALUES($artist_id, $name, $year, $release_year, unixepoch())",
+                rusqlite::params![
+                    artist_id,
+                    album,
+                    year,
+                    year.and_then(parse_release_year)
+                ],
+            ) {
+                Ok(_) => Ok(savepoint.last_insert_rowid() as usize),
+                Err(err) => Err(SaveArtistError::SQLite(err)),
+            }
+        }
+        Err(err) => Err(SaveArtistError::SQLite(err)),
+    }
+}
+
+/// The IDs of the artists and albums a scan saved, so that the tracks of an album don't look
+/// them up again one by one.
+///
+/// Everything is forgotten when another connection committed since the last check, it could
+/// have merged or deleted them: `check` is called after every batch commit.
+struct SavedIds {
+    artists: HashMap<String, ArtistID>,
+    albums: HashMap<ArtistID, HashMap<String, AlbumID>>,
+    data_version: i64,
+}
+
+impl SavedIds {
+    fn new(db: &rusqlite::Connection) -> rusqlite::Result<SavedIds> {
+        Ok(SavedIds {
+            artists: HashMap::new(),
+            albums: HashMap::new(),
+            data_version: SavedIds::data_version(db)?,
+        })
+    }
+
+    /// Returns the version of the database, which changes when another connection commits.
+    fn data_version(db: &rusqlite::Connection) -> rusqlite::Result<i64> {
+        db.query_row("PRAGMA data_version", [], |row| row.get(0))
+    }
+
+    fn check(&mut self, db: &rusqlite::Connection) -> rusqlite::Result<()> {
+        let data_version = SavedIds::data_version(db)?;
+        if data_version != self.data_version {
+            self.artists.clear();
+            self.albums.clear();
+            self.data_version = data_version;
+        }
+
+        Ok(())
+    }
+
+    fn artist(
+        &mut self,
+        savepoint: &mut rusqlite::Savepoint,
+        artist: &str,
+    ) -> Result<ArtistID, SaveArtistError> {
+        if let Some(id) = self.artists.get(artist) {
+            return Ok(*id);
+        }
+
+        let id = save_artist(savepoint, artist)?;
+        self.artists.insert(artist.to_owned(), id);
+        Ok(id)
+    }
+
+    fn album(
+        &mut self,
+        savepoint: &mut rusqlite::Savepoint,
+        artist_id: ArtistID,
+        album: &str,
+        year: Option<&str>,
+    ) -> Result<AlbumID, SaveArtistError> {
+        let albums = self.albums.entry(artist_id).or_default();
+        if let Some(id) = albums.get(album) {
+            return Ok(*id);
+        }
+
+        let id = save_album(savepoint, artist_id, album, year)?;
+        albums.insert(album.to_owned(), id);
+        Ok(id)
+    }
+}
+
+enum SaveTrackError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for SaveTrackError {
+    fn from(err: rusqlite::Error) -> SaveTrackError {
+        SaveTrackError::SQLite(err)
+    }
+}
+impl fmt::Display for SaveTrackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveTrackError::SQLite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+fn save_track(
+    savepoint: &mut rusqlite::Savepoint,
+    artist_id: ArtistID,
+    album_id: AlbumID,
+    path: &Path,
+    metadata: &Metadata,
+    moved_track_id: Option<TrackID>,
+) -> Result<TrackID, SaveTrackError> {
+    // A moved file keeps its row, whatever its tags say now
+    if let Some(id) = moved_track_id {
+        let query = "
+            UPDATE OR REPLACE track SET
+              path = $path,
+              name = $name,
+              artist_id = $artist_id,
+              album_id = $album_id,
+              year = $year,
+              release_year = $release_year,
+              original_year = $original_year,
+              number = $number,
+              track_total = $track_total,
+              disc_number = $disc_number,
+              disc_total = $disc_total,
+              genre = $genre,
+              genre_inferred = 0,
+              comment = $comment,
+              lyrics = $lyrics,
+              duration_ms = $duration_ms,
+              musicbrainz_album_id = $musicbrainz_album_id,
+              musicbrainz_artist_id = $musicbrainz_artist_id,
+              label = $label,
+              replay_gain_track = $replay_gain_track,
+              replay_gain_album = $replay_gain_album,
+              energy = $energy,
+              album_artist = $album_artist
+            WHERE id = $id
+            RETURNING id";
+
+        let params = rusqlite::params![
+            library::relative(path),
+            metadata.track_name,
+            artist_id,
+            album_id,
+            metadata.year,
+            metadata.year.as_deref().and_then(parse_release_year),
+            metadata
+                .original_year
+                .as_deref()
+                .and_then(parse_release_year),
+            metadata.track_number,
+            metadata.track_total,
+            metadata.disc_number,
+            metadata.disc_total,
+            metadata.genre,
+            metadata.comment,
+            metadata.lyrics,
+            metadata.duration_ms,
+            metadata.musicbrainz_album_id,
+            metadata.musicbrainz_artist_id,
+            metadata.label,
+            metadata.replay_gain_track,
+            metadata.replay_gain_album,
+            metadata.energy,
+            metadata.album_artist,
+            id,
+        ];
+
+        return match savepoint.query_row(query, params, |row| row.get(0)) {
+            Ok(id) => Ok(id),
+            Err(err) => Err(SaveTrackError::SQLite(err)),
+        };
+    }
+
+    let query = "
+        INSERT INTO track(path, name, artist_id, album_id, year, release_year, original_year, number, track_total, disc_number, disc_total, genre, comment, lyrics, duration_ms,
+                          musicbrainz_album_id, musicbrainz_artist_id, label, replay_gain_track, replay_gain_album, energy, album_artist, first_seen_at)
+        VALUES(
+          $path,
+          $name,
+          $artist_id,
+          $album_id,
+          $year,
+          $release_year,
+          $original_year,
+          $number,
+          $track_total,
+          $disc_number,
+          $disc_total,
+          $genre,
+          $comment,
+          $lyrics,
+          $duration_ms,
+          $musicbrainz_album_id,
+          $musicbrainz_artist_id,
+          $label,
+          $replay_gain_track,
+          $replay_gain_album,
+          $energy,
+          $album_artist,
+          unixepoch()
+        )
+        ON CONFLICT(path)
+        DO UPDATE SET
+          name = excluded.name,
+          artist_id = excluded.artist_id,
+          album_id = excluded.album_id,
+          year = excluded.year,
+          release_year = excluded.release_year,
+          original_year = excluded.original_year,
+          number = excluded.number,
+          track_total = excluded.track_total,
+          disc_number = excluded.disc_number,
+          disc_total = excluded.disc_total,
+          genre = excluded.genre,
+          genre_inferred = 0,
+          comment = excluded.comment,
+          lyrics = excluded.lyrics,
+          duration_ms = excluded.duration_ms,
+          musicbrainz_album_id = excluded.musicbrainz_album_id,
+          musicbrainz_artist_id = excluded.musicbrainz_artist_id,
+          label = excluded.label,
+          replay_gain_track = excluded.replay_gain_track,
+          replay_gain_album = excluded.replay_gain_album,
+          energy = excluded.energy,
+          album_artist = excluded.album_artist
+        RETURNING id";
+
+    let params = rusqlite::params![
+        library::relative(path),
+        metadata.track_name,
+        artist_id,
+        album_id,
+        metadata.year,
+        metadata.year.as_deref().and_then(parse_release_year),
+        metadata
+            .original_year
+            .as_deref()
+            .and_then(parse_release_year),
+        metadata.track_number,
+        metadata.track_total,
+        metadata.disc_number,
+        metadata.disc_total,
+        metadata.genre,
+        metadata.comment,
+        metadata.lyrics,
+        metadata.duration_ms,
+        metadata.musicbrainz_album_id,
+        metadata.musicbrainz_artist_id,
+        metadata.label,
+        metadata.replay_gain_track,
+        metadata.replay_gain_album,
+        metadata.energy,
+        metadata.album_artist,
+    ];
+
+    match savepoint.query_row(query, params, |row| row.get(0)) {
+        Ok(id) => Ok(id),
+        Err(err) => Err(SaveTrackError::SQLite(err)),
+    }
+}
+
+/// Links a track to all its artists, in the order of its tags. A track with a single artist
+/// has no links, its artist is the track's own.
+fn save_track_artists(
+    savepoint: &mut rusqlite::Savepoint,
+    saved_ids: &mut SavedIds,
+    track_id: TrackID,
+    artists: &[String],
+) -> Result<(), SaveArtistError> {
+    savepoint.execute("DELETE FROM track_artist WHERE track_id = $id", [track_id])?;
+    if artists.len() < 2 {
+        return Ok(());
+    }
+
+    for (position, artist) in artists.iter().enumerate() {
+        let artist_id = saved_ids.artist(savepoint, artist)?;
+        savepoint.execute(
+            "INSERT INTO track_artist(track_id, position, artist_id) VALUES($track_id, $position, $artist_id)",
+            rusqlite::params![track_id, position, artist_id],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Replaces the fields of `tag_fields` of a track.
+fn save_track_fields(
+    savepoint: &rusqlite::Savepoint,
+    track_id: TrackID,
+    fields: &[(String, String)],
+) -> rusqlite::Result<()> {
+    savepoint.execute("DELETE FROM track_field WHERE track_id = $id", [track_id])?;
+
+    for (name, value) in fields {
+        savepoint.execute(
+            "INSERT OR IGNORE INTO track_field(track_id, name, value) VALUES($track_id, $name, $value)",
+            rusqlite::params![track_id, name, value],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Records another path a track's file was found at.
+fn save_track_alias(
+    savepoint: &rusqlite::Savepoint,
+    path: &Path,
+    track_id: TrackID,
+) -> rusqlite::Result<()> {
+    savepoint.execute(
+        "INSERT OR REPLACE INTO track_alias(path, track_id) VALUES($path, $track_id)",
+        rusqlite::params![library::relative(path), track_id],
+    )?;
+    savepoint.execute(
+        "INSERT OR IGNORE INTO temp.scanned_alias(path) VALUES($path)",
+        [library::relative(path)],
+    )?;
+
+    Ok(())
+}
+
+/// Saves a video file apart from the tracks, when `index_videos` is set.
+fn save_video(
+    savepoint: &mut rusqlite::Savepoint,
+    path: &Path,
+    metadata: &Metadata,
+) -> rusqlite::Result<()> {
+    savepoint.execute(
+        "INSERT INTO video(path, name, artist, year, genre, comment)
+         VALUES($path, $name, $artist, $year, $genre, $comment)
+         ON CONFLICT(path) DO UPDATE SET
+           name = excluded.name,
+           artist = excluded.artist,
+           year = excluded.year,
+           genre = excluded.genre,
+           comment = excluded.comment",
+        rusqlite::params![
+            library::relative(path),
+            metadata.track_name,
+            metadata.artist,
+            metadata.year,
+            metadata.genre,
+            metadata.comment,
+        ],
+    )?;
+
+    Ok(())
+}
+
+//
+// "scan" command
+//
+
+enum CommandScanError {
+    SQLite(rusqlite::Error),
+    WalkDir(walkdir::Error),
+    IO(io::Error),
+    MetadataRead(MetadataReadError),
+    SaveArtist(SaveArtistError),
+    SaveAlbum(SaveAlbumError),
+    SaveTrack(SaveTrackError),
+    Move(moves::MoveError),
+    SpokenWord(spoken::SpokenWordError),
+    Storage(storage::StorageError),
+    InvalidThrottleValue(&'static str, String),
+}
+impl From<rusqlite::Error> for CommandScanError {
+    fn from(err: rusqlite::Error) -> CommandScanError {
+        CommandScanError::SQLite(err)
+    }
+}
+impl From<walkdir::Error> for CommandScanError {
+    fn from(err: walkdir::Error) -> CommandScanError {
+        CommandScanError::WalkDir(err)
+    }
+}
+impl From<io::Error> for CommandScanError {
+    fn from(err: io::Error) -> CommandScanError {
+        CommandScanError::IO(err)
+    }
+}
+impl From<MetadataReadError> for CommandScanError {
+    fn from(err: MetadataReadError) -> CommandScanError {
+        CommandScanError::MetadataRead(err)
+    }
+}
+impl From<SaveArtistError> for CommandScanError {
+    fn from(err: SaveArtistError) -> CommandScanError {
+        CommandScanError::SaveArtist(err)
+    }
+}
+impl From<SaveAlbumError> for CommandScanError {
+    fn from(err: SaveAlbumError) -> CommandScanError {
+        CommandScanError::SaveAlbum(err)
+    }
+}
+impl From<SaveTrackError> for CommandScanError {
+    fn from(err: SaveTrackError) -> CommandScanError {
+        CommandScanError::SaveTrack(err)
+    }
+}
+impl From<moves::MoveError> for CommandScanError {
+    fn from(err: moves::MoveError) -> CommandScanError {
+        CommandScanError::Move(err)
+    }
+}
+impl From<spoken::SpokenWordError> for CommandScanError {
+    fn from(err: spoken::SpokenWordError) -> CommandScanError {
+        CommandScanError::SpokenWord(err)
+    }
+}
+impl fmt::Display for CommandScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandScanError::SQLite(err) => write!(f, "{}", err),
+            CommandScanError::WalkDir(err) => write!(f, "{}", err),
+            CommandScanError::IO(err) => write!(f, "{}", err),
+            CommandScanError::MetadataRead(err) => write!(f, "{}", err),
+            CommandScanError::SaveArtist(err) => write!(f, "{}", err),
+            CommandScanError::SaveAlbum(err) => write!(f, "{}", err),
+            CommandScanError::SaveTrack(err) => write!(f, "{}", err),
+            CommandScanError::Move(err) => write!(f, "{}", err),
+            CommandScanError::SpokenWord(err) => write!(f, "{}", err),
+            CommandScanError::Storage(err) => write!(f, "{}", err),
+            CommandScanError::InvalidThrottleValue(option, value) => f.write_str(&tr!(
+                "scan-invalid-throttle",
+                option = option,
+                value = value
+            )),
+        }
+    }
+}
+
+/// What a scan changed in the library.
+pub struct ScanSummary {
+    pub indexed: usize,
+    pub added: usize,
+    pub updated: usize,
+    /// Tracks whose file disappeared since the last scan.
+    pub removed: usize,
+    pub moved: usize,
+    /// The albums seen for the first time, as "artist - album".
+    pub new_albums: Vec<String>,
+}
+impl ScanSummary {
+    fn has_changes(&self) -> bool {
+        self.added > 0 || self.updated > 0 || self.removed > 0 || self.moved > 0
+    }
+}
+
+fn scan_library(
+    db: &mut rusqlite::Connection,
+    throttle: &mut throttle::Throttle,
+) -> Result<ScanSummary, CommandScanError> {
+    // The root on this machine, or the URL of a remote library
+    let library: PathBuf = match library::root() {
+        Some(root) => PathBuf::from(root),
+        None => db.query_row(
+            "SELECT value FROM config WHERE key = 'library'",
+            [],
+            |row| {
+                let value: String = row.get(0)?;
+                Ok(PathBuf::from(value))
+            },
+        )?,
+    };
+
+    scan_folder(db, &library, throttle)
+}
+
+/// Why `scan_in_memory` failed, with the message zik would print.
+pub struct ScanError(AppError);
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl<T: Into<AppError>> From<T> for ScanError {
+    fn from(err: T) -> ScanError {
+        ScanError(err.into())
+    }
+}
+
+/// Scans `folder` into a new in-memory database, returning it with what was indexed. Nothing
+/// of the user's database is read or written.
+pub fn scan_in_memory(folder: &Path) -> Result<(rusqlite::Connection, ScanSummary), ScanError> {
+    let mut db = rusqlite::Connection::open_in_memory().map_err(OpenDatabaseError::from)?;
+    register_functions(&db).map_err(OpenDatabaseError::from)?;
+    init_database(&mut db)?;
+
+    let summary = scan_folder(&mut db, folder, &mut throttle::Throttle::new(None, None))?;
+
+    Ok((db, summary))
+}
+
+/// A file to index, walked on disk or listed by the storage of a remote library.
+enum ScanEntry<'a> {
+    Local(walkdir::DirEntry),
+    Remote(&'a dyn storage::Storage, storage::RemoteFile),
+}
+
+/// What a file is found again by under another path: its device and inode on Unix. Elsewhere
+/// it's its canonical path, which follows symbolic links but not hard links.
+#[cfg(unix)]
+type FileID = (u64, u64);
+#[cfg(not(unix))]
+type FileID = PathBuf;
+
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &fs::Metadata) -> io::Result<FileID> {
+    use std::os::unix::fs::MetadataExt;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &fs::Metadata) -> io::Result<FileID> {
+    fs::canonicalize(path)
+}
+
+/// Files indexed by a scan between two commits, unless `scan_batch_size` says otherwise.
+const DEFAULT_SCAN_BATCH_SIZE: usize = 500;
+
+/// Scans `library` as if it was the library, the entry point of tests which scan a folder
+/// into an in-memory database.
+///
+/// The files are committed in batches of `scan_batch_size`: a scan which dies halfway keeps
+/// what it indexed, and marks nothing missing since that's only done once every file was seen.
+/// The aliases and unreadable files it didn't see again are forgotten then too. A folder
+/// other than the library only does so for what's under it, the rest of the library is left
+/// as it was.
+fn scan_folder(
+    db: &mut rusqlite::Connection,
+    library: &Path,
+    throttle: &mut throttle::Throttle,
+) -> Result<ScanSummary, CommandScanError> {
+    let library = &if storage::is_remote(&library.to_string_lossy()) {
+        library.to_path_buf()
+    } else {
+        fs::canonicalize(library).unwrap_or_else(|_| absolute_path(library))
+    };
+    println!("{}", tr!("scan-library", library = library.display()));
+    let scope = library::scan_scope(db, library)?;
+
+    let mut savepoint = db.savepoint()?;
+
+    // Rows are diffed against what's already indexed instead of being rebuilt, so their ids
+    // stay the same from one scan to the next
+    savepoint.execute("DROP TABLE IF EXISTS temp.previous_track", [])?;
+    savepoint.execute(
+        "CREATE TEMP TABLE previous_track AS
+         SELECT id, name, artist_id, album_id, year, number, disc_number, genre, comment, lyrics
+         FROM track",
+        [],
+    )?;
+    savepoint.execute("DROP TABLE IF EXISTS temp.scanned_track", [])?;
+    savepoint.execute(
+        "CREATE TEMP TABLE scanned_track(id INTEGER PRIMARY KEY)",
+        [],
+    )?;
+    savepoint.execute("DROP TABLE IF EXISTS temp.scanned_album", [])?;
+    savepoint.execute(
+        "CREATE TEMP TABLE scanned_album(id INTEGER PRIMARY KEY)",
+        [],
+    )?;
+    // The aliases and unreadable files of the previous scan are only forgotten at the end
+    savepoint.execute("DROP TABLE IF EXISTS temp.scanned_alias", [])?;
+    savepoint.execute("CREATE TEMP TABLE scanned_alias(path TEXT PRIMARY KEY)", [])?;
+    unreadable::begin(&savepoint)?;
+    savepoint.execute("DROP TABLE IF EXISTS temp.scanned_video", [])?;
+    savepoint.execute("CREATE TEMP TABLE scanned_video(path TEXT PRIMARY KEY)", [])?;
+    let (last_track_id, last_album_id): (i64, i64) = savepoint.query_row(
+        "SELECT (SELECT coalesce(MAX(id), 0) FROM track), (SELECT coalesce(MAX(id), 0) FROM album)",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut missing_tracks = moves::MissingTracks::load(&savepoint)?;
+    let mut saved_ids = SavedIds::new(&savepoint)?;
+
+    let genre_aliases = genre::GenreAliases::load(&savepoint)?;
+    let mut moved = 0;
+    let mut indexed = 0;
+    let spoken_word_folders = spoken::SpokenWordFolders::load(&savepoint, library)?;
+    let index_videos = get_config_bool(&savepoint, "index_videos")?;
+    let scan_archives = get_config_bool(&savepoint, "scan_archives")?;
+    let tag_stats_user =
+        get_config_value(&savepoint, "tag_stats_user")?.filter(|user| !user.is_empty());
+    let batch_size =
+        get_config_usize(&savepoint, "scan_batch_size")?.unwrap_or(DEFAULT_SCAN_BATCH_SIZE);
+    let mut in_batch = 0;
+    // The tracks by file, a file seen again is the same one under another path
+    let mut files: HashMap<FileID, (TrackID, PathBuf)> = HashMap::new();
+    let mut folder: Option<(PathBuf, PathBuf)> = None;
+
+    // The inbox can be inside the library, its files are only staged
+    let inbox = inbox::path(&savepoint)?;
+    let ignore_rules = ignore::IgnoreRules::load(&savepoint)?;
+    let io_options = netfs::IoOptions::load(&savepoint)?;
+    let custom_fields = Arc::new(tags::CustomFields::load(&savepoint)?);
+    let remote = match library.to_str().filter(|url| storage::is_remote(url)) {
+        Some(url) => Some(storage::load(&savepoint, url)?.map_err(CommandScanError::Storage)?),
+        None => None,
+    };
+    let case_insensitive = remote.is_none() && library::is_case_insensitive(library);
+    // Sorted so the same path of a file reachable through several is kept from scan to scan
+    let walker = walkdir::WalkDir::new(library)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(move |entry| {
+            let in_inbox = match &inbox {
+                Some(inbox) => entry.path().starts_with(inbox),
+                None => false,
+            };
+            !in_inbox && !ignore_rules.is_ignored(entry)
+        });
+    // A remote library is listed through its storage instead
+    let entries: Box<dyn Iterator<Item = io::Result<ScanEntry>>> = match &remote {
+        Some(storage) => Box::new(
+            storage
+                .list_files()?
+                .into_iter()
+                // Only the audio files are read
+                .filter(|file| {
+                    subsonic::content_type_for(Path::new(&file.url)).starts_with("audio/")
+                })
+                .map(|file| Ok(ScanEntry::Remote(storage.as_ref(), file))),
+        ),
+        None => Box::new(
+            netfs::prefetch(&io_options, library, walker)
+                .map(|result| result.map(ScanEntry::Local)),
+        ),
+    };
+    for result in entries {
+        let entry = match result {
+            // A folder zik may not list is skipped, its tracks are kept as they were
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                let folder = err
+                    .get_ref()
+                    .and_then(|err| err.downcast_ref::<walkdir::Error>())
+                    .and_then(|err| err.path());
+                let folder = match folder {
+                    Some(folder) => fs::canonicalize(folder).unwrap_or_else(|_| folder.to_owned()),
+                    None => return Err(err.into()),
+                };
+
+                println!(
+                    "{}",
+                    tr!("scan-folder-permission-denied", path = folder.display())
+                );
+                unreadable::record(
+                    &savepoint,
+                    &folder,
+                    true,
+                    unreadable::Reason::PermissionDenied,
+                )?;
+                unreadable::keep_tracks(&savepoint, &folder, true)?;
+                continue;
+            }
+            result => result?,
+        };
+
+        // Files inside archives or on a remote library have no inode, and ffmpeg can't read them
+        let (file_path, mut file_id, tracks) = match entry {
+            ScanEntry::Local(entry) => {
+                if entry.file_type().is_file() {
+                    throttle.wait();
+                }
+
+                let file_path = entry.path();
+                println!("{}", tr!("scan-file", path = file_path.display()));
+
+                // The files of a folder come one after the other, its path is resolved once
+                let parent = file_path.parent().unwrap_or(library);
+                let canonical_folder = match &folder {
+                    Some((walked, canonical)) if walked == parent => canonical.clone(),
+                    _ => {
+                        let canonical = fs::canonicalize(parent)?;
+                        folder = Some((parent.to_path_buf(), canonical.clone()));
+                        canonical
+                    }
+                };
+                let path = canonical_folder.join(entry.file_name());
+
+                let file_metadata = entry.metadata()?;
+                let file_id = file_id(file_path, &file_metadata)?;
+                if let Some((track_id, track_path)) = files.get(&file_id) {
+                    if path != *track_path {
+                        save_track_alias(&savepoint, file_path, *track_id)?;
+                        println!("{}", tr!("scan-same-file", id = track_id));
+                    }
+                    continue;
+                }
+
+                // An archive is a folder of tracks, read without extracting it
+                if scan_archives && archive::is_archive(file_path) {
+                    let tracks = archive::read_tracks(&path, &custom_fields)?;
+                    println!("{}", tr!("scan-archive", tracks = tracks.len()));
+                    (file_path.to_path_buf(), None, tracks)
+                } else {
+                    match Metadata::read_with_options(&io_options, &custom_fields, file_path) {
+                        Ok(Some(md)) => (file_path.to_path_buf(), Some(file_id), vec![(path, md)]),
+                        Ok(None) => {
+                            // Only audio files are worth listing, not covers or playlists
+                            if entry.file_type().is_file()
+                                && subsonic::content_type_for(file_path).starts_with("audio/")
+                            {
+                                unreadable::record(
+                                    &savepoint,
+                                    &path,
+                                    false,
+                                    unreadable::Reason::Unsupported,
+                                )?;
+                            }
+                            println!("{}", tr!("scan-unsupported"));
+                            continue;
+                        }
+                        Err(MetadataReadError::IO(err))
+                            if err.kind() == io::ErrorKind::PermissionDenied =>
+                        {
+                            // Folders are reported when the walk fails to list them
+                            if entry.file_type().is_file() {
+                                unreadable::record(
+                                    &savepoint,
+                                    &path,
+                                    false,
+                                    unreadable::Reason::PermissionDenied,
+                                )?;
+                                unreadable::keep_tracks(&savepoint, &path, false)?;
+                                println!("{}", tr!("scan-permission-denied"));
+                            }
+                            continue;
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+            ScanEntry::Remote(storage, file) => {
+                throttle.wait();
+                println!("{}", tr!("scan-file", path = file.url));
+
+                let path = PathBuf::from(&file.url);
+                // Only the parts holding the tags are fetched when the size of the file is known
+                let md = match file.size {
+                    Some(len) => Metadata::read_from_reader(
+                        storage::RangeReader::new(storage, &file.url, len),
+                        &path,
+                        &custom_fields,
+                    )?,
+                    None => {
+                        let data = io::Cursor::new(storage.read(&file.url)?);
+                        Metadata::read_from_reader(data, &path, &custom_fields)?
+                    }
+                };
+                match md {
+                    Some(md) => (path.clone(), None, vec![(path, md)]),
+                    None => {
+                        unreadable::record(
+                            &savepoint,
+                            &path,
+                            false,
+                            unreadable::Reason::Unsupported,
+                        )?;
+                        println!("{}", tr!("scan-unsupported"));
+                        continue;
+                    }
+                }
+            }
+        };
+        let file_path = file_path.as_path();
+        let on_disk = file_id.is_some();
+
+        for (path, mut md) in tracks {
+            if md.video {
+                if index_videos {
+                    save_video(&mut savepoint, &path, &md)?;
+                    savepoint.execute(
+                        "INSERT OR IGNORE INTO temp.scanned_video(path) VALUES($path)",
+                        [library::relative(&path)],
+                    )?;
+                    println!("{}", tr!("scan-video-indexed"));
+                } else {
+                    println!("{}", tr!("scan-video-skipped"));
+                }
+                continue;
+            }
+
+            md.genre = md
+                .genre
+                .as_deref()
+                .and_then(|raw| genre_aliases.resolve(raw));
+
+            let artist = md.artist.as_deref().unwrap_or("Unknown");
+            let artist_id = saved_ids.artist(&mut savepoint, artist)?;
+
+            let album = md.album.as_deref().unwrap_or("Unknown");
+            let album_id = saved_ids.album(&mut savepoint, artist_id, album, md.year.as_deref())?;
+            // The release type of an album is found again from its tags, or classified at the end
+            if savepoint.execute(
+                "INSERT OR IGNORE INTO temp.scanned_album(id) VALUES($id)",
+                [album_id],
+            )? > 0
+            {
+                savepoint.execute(
+                    "UPDATE album SET release_type = NULL WHERE id = $id",
+                    [album_id],
+                )?;
+            }
+
+            // Found again in another case, its path is only updated
+            let renamed_track_id = if case_insensitive {
+                library::find_track_ignoring_case(&savepoint, &path)?
+            } else {
+                None
+            };
+            let moved_track_id = if renamed_track_id.is_some() {
+                None
+            } else {
+                missing_tracks.find_move(&savepoint, &path, artist, album, &md)?
+            };
+            if moved_track_id.is_some() {
+                println!("{}", tr!("scan-moved-track"));
+                moved += 1;
+            }
+
+            let track_id = save_track(
+                &mut savepoint,
+                artist_id,
+                album_id,
+                &path,
+                &md,
+                moved_track_id.or(renamed_track_id),
+            )?;
+            let release_type = md
+                .release_type
+                .as_deref()
+                .and_then(release::ReleaseType::from_tag)
+                .or(md.compilation.then_some(release::ReleaseType::Compilation));
+            if let Some(release_type) = release_type {
+                savepoint.execute(
+                    "UPDATE album SET release_type = $release_type WHERE id = $id",
+                    rusqlite::params![release_type.as_str(), album_id],
+                )?;
+            }
+
+            save_track_artists(&mut savepoint, &mut saved_ids, track_id, &md.artists)?;
+            save_track_fields(&savepoint, track_id, &md.custom_fields)?;
+            mood::save_moods(&savepoint, track_id, &md.moods)?;
+            if md.rating.is_some() || md.play_count.is_some() {
+                ratings::seed(
+                    &savepoint,
+                    track_id,
+                    md.rating,
+                    md.play_count,
+                    tag_stats_user.as_deref(),
+                )?;
+            }
+            if on_disk {
+                jobs::enqueue_for_track(&savepoint, track_id)?;
+            }
+            savepoint.execute(
+                "INSERT OR IGNORE INTO temp.scanned_track(id) VALUES($id)",
+                [track_id],
+            )?;
+            if let Some(file_id) = file_id.take() {
+                if file_path != path {
+                    save_track_alias(&savepoint, file_path, track_id)?;
+                }
+                files.insert(file_id, (track_id, path));
+            }
+            savepoint.execute(
+                "UPDATE track SET missing_since = NULL WHERE id = $id AND missing_since IS NOT NULL",
+                [track_id],
+            )?;
+            indexed += 1;
+
+            if !on_disk {
+                // Chapters are only read from files
+            } else if spoken_word_folders.contains(file_path) {
+                let chapters = spoken::update_track(&savepoint, track_id, file_path, true)?;
+                println!("{}", tr!("scan-spoken-word", chapters = chapters));
+            } else {
+                let chapters = spoken::update_track(&savepoint, track_id, file_path, false)?;
+                if chapters > 0 {
+                    println!("{}", tr!("scan-mix", chapters = chapters));
+                }
+            }
+
+            println!(
+                "{}",
+                tr!(
+                    "scan-track",
+                    artist = artist,
+                    artist_id = artist_id,
+                    album = album,
+                    album_id = album_id,
+                    album_artist = md.album_artist.unwrap_or_default(),
+                    year = md.year.unwrap_or_default(),
+                    track = md.track_name.unwrap_or_default(),
+                    number = md.track_number,
+                    genre = md.genre.unwrap_or_default(),
+                )
+            );
+
+            in_batch += 1;
+            if in_batch == batch_size {
+                savepoint.commit()?;
+                savepoint = db.savepoint()?;
+                saved_ids.check(&savepoint)?;
+                in_batch = 0;
+            }
+        }
+    }
+
+    release::classify_albums(&savepoint)?;
+    collation::update_sort_names(&savepoint)?;
+    // The tags were read again, put back the genres inferred for the tracks still without one
+    infer::apply_genres(&savepoint)?;
+    source::apply_rules(&savepoint)?;
+
+    let added: usize = savepoint.query_row(
+        "SELECT COUNT(*) FROM track WHERE id > $last_track_id",
+        [last_track_id],
+        |row| row.get(0),
+    )?;
+    let updated: usize = savepoint.query_row(
+        "SELECT COUNT(*)
+         FROM track
+         JOIN temp.previous_track previous ON previous.id = track.id
+         WHERE track.id IN (SELECT id FROM temp.scanned_track)
+           AND (track.name IS NOT previous.name
+                OR track.artist_id IS NOT previous.artist_id
+                OR track.album_id IS NOT previous.album_id
+                OR track.year IS NOT previous.year
+                OR track.number IS NOT previous.number
+                OR track.disc_number IS NOT previous.disc_number
+                OR track.genre IS NOT previous.genre
+                OR track.comment IS NOT previous.comment
+                OR track.lyrics IS NOT previous.lyrics)",
+        [],
+        |row| row.get(0),
+    )?;
+    let removed = savepoint.execute(
+        "UPDATE track SET missing_since = unixepoch()
+         WHERE missing_since IS NULL AND id NOT IN (SELECT id FROM temp.scanned_track)
+           AND in_folder(library_path(path), $scope)",
+        [&scope],
+    )?;
+    let purged = tombstones::purge(&savepoint)?;
+    savepoint.execute(
+        "DELETE FROM video
+         WHERE path NOT IN (SELECT path FROM temp.scanned_video)
+           AND in_folder(library_path(path), $scope)",
+        [&scope],
+    )?;
+    savepoint.execute(
+        "DELETE FROM track_alias
+         WHERE path NOT IN (SELECT path FROM temp.scanned_alias)
+           AND in_folder(library_path(path), $scope)",
+        [&scope],
+    )?;
+    unreadable::forget_readable(&savepoint, scope.as_deref())?;
+    years::update_albums(&savepoint)?;
+    artwork::update_artist_images(&savepoint, last_track_id)?;
+    sidecar::update(&savepoint)?;
+    // The artists left without tracks or albums, e.g. after their tags were fixed
+    savepoint.execute(
+        "DELETE FROM artist
+         WHERE id NOT IN (SELECT artist_id FROM track WHERE artist_id IS NOT NULL)
+           AND id NOT IN (SELECT artist_id FROM album WHERE artist_id IS NOT NULL)
+           AND id NOT IN (SELECT artist_id FROM track_artist)",
+        [],
+    )?;
+
+    let new_albums = {
+        let mut stmt = savepoint.prepare(
+            "SELECT coalesce(artist.name || ' - ', '') || album.name
+             FROM album
+             LEFT JOIN artist ON artist.id = album.artist_id
+             WHERE album.id > $last_album_id
+             ORDER BY album.id",
+        )?;
+        let rows = stmt.query_map([last_album_id], |row| row.get(0))?;
+        rows.collect::<rusqlite::Result<Vec<String>>>()?
+    };
+
+    savepoint.execute("DROP TABLE temp.previous_track", [])?;
+    savepoint.execute("DROP TABLE temp.scanned_track", [])?;
+    savepoint.execute("DROP TABLE temp.scanned_album", [])?;
+    savepoint.execute("DROP TABLE temp.scanned_video", [])?;
+    savepoint.execute("DROP TABLE temp.scanned_alias", [])?;
+
+    savepoint.commit()?;
+
+    if moved > 0 {
+        println!("{}", tr!("scan-moved", moved = moved));
+    }
+    if purged > 0 {
+        println!("{}", tr!("scan-purged", purged = purged));
+    }
+    println!(
+        "{}",
+        tr!(
+            "scan-summary",
+            indexed = indexed,
+            added = added,
+            updated = updated,
+            removed = removed
+        )
+    );
+
+    Ok(ScanSummary {
+        indexed,
+        added,
+        updated,
+        removed,
+        moved,
+        new_albums,
+    })
+}
+
+fn cmd_scan(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandScanError> {
+    let max_rate = match args.value_of("max-rate") {
+        Some(value) => match value.parse::<f64>() {
+            Ok(rate) if rate > 0.0 && rate.is_finite() => Some(rate),
+            _ => {
+                return Err(CommandScanError::InvalidThrottleValue(
+                    "max-rate",
+                    value.to_owned(),
+                ))
+            }
+        },
+        None => None,
+    };
+    let sleep = match args.value_of("sleep") {
+        Some(value) => match value.parse::<u64>() {
+            Ok(ms) => Some(Duration::from_millis(ms)),
+            Err(_) => {
+                return Err(CommandScanError::InvalidThrottleValue(
+                    "sleep",
+                    value.to_owned(),
+                ))
+            }
+        },
+        None => None,
+    };
+    if args.is_present("throttle") {
+        throttle::lower_priority();
+    }
+    let mut throttle = throttle::Throttle::new(max_rate, sleep);
+
+    let summary = match args.value_of("dir") {
+        Some(dir) => scan_folder(db, &absolute_path(Path::new(dir)), &mut throttle)?,
+        None => scan_library(db, &mut throttle)?,
+    };
+    inbox::scan(db)?;
+    notify::scan_completed(db, &summary);
+
+    Ok(())
+}
+
+enum AppError {
+    OpenDatabase(OpenDatabaseError),
+    InitDatabase(InitDatabaseError),
+    CommandConfig(CommandConfigError),
+    CommandScan(CommandScanError),
+    CommandGenre(genre::CommandGenreError),
+    CommandList(list::CommandListError),
+    CommandIncomplete(incomplete::CommandIncompleteError),
+    CommandJobs(jobs::CommandJobsError),
+    Daemon(daemon::DaemonError),
+    #[cfg(unix)]
+    Rpc(rpc::RpcError),
+    CommandSnapshot(snapshot::CommandSnapshotError),
+    CommandLabel(label::CommandLabelError),
+    CommandMood(mood::CommandMoodError),
+    CommandQueue(queue::CommandQueueError),
+    CommandPlay(play::CommandPlayError),
+    CommandNote(note::CommandNoteError),
+    CommandSpoken(spoken::CommandSpokenError),
+    CommandUser(user::CommandUserError),
+    CommandEnrich(enrich::CommandEnrichError),
+    CommandQuery(query::CommandQueryError),
+    CommandExport(export::CommandExportError),
+    CommandTop(top::CommandTopError),
+    CommandWrapped(wrapped::CommandWrappedError),
+    CommandHistory(history::CommandHistoryError),
+    CommandTag(tag::CommandTagError),
+    CommandImport(import::CommandImportError),
+    CommandRip(rip::CommandRipError),
+    CommandSecrets(secrets::CommandSecretsError),
+    CommandLibrary(library::CommandLibraryError),
+    CommandBench(bench::CommandBenchError),
+    CommandSchema(schema::CommandSchemaError),
+    CommandRestore(tombstones::CommandRestoreError),
+    CommandDb(db::CommandDbError),
+    CommandYears(years::CommandYearsError),
+    CommandDu(du::CommandDuError),
+    CommandDoctor(doctor::CommandDoctorError),
+    CommandUpgrades(upgrades::CommandUpgradesError),
+    CommandVerify(verify::CommandVerifyError),
+    CommandPick(pick::CommandPickError),
+    CommandAlias(alias::CommandAliasError),
+    CommandLyrics(lyrics::CommandLyricsError),
+    CommandLinks(links::CommandLinksError),
+    CommandCollection(collection::CommandCollectionError),
+    CommandUnreadable(unreadable::CommandUnreadableError),
+    CommandCover(artwork::CommandCoverError),
+    CommandInfer(infer::CommandInferError),
+    CommandFields(tags::CommandFieldsError),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::OpenDatabase(err) => write!(f, "{}", err),
+            AppError::InitDatabase(err) => write!(f, "{}", err),
+            AppError::CommandConfig(err) => write!(f, "{}", err),
+            AppError::CommandScan(err) => write!(f, "{}", err),
+            AppError::CommandGenre(err) => write!(f, "{}", err),
+            AppError::CommandList(err) => write!(f, "{}", err),
+            AppError::CommandIncomplete(err) => write!(f, "{}", err),
+            AppError::CommandJobs(err) => write!(f, "{}", err),
+            AppError::Daemon(err) => write!(f, "{}", err),
+            #[cfg(unix)]
+            AppError::Rpc(err) => write!(f, "{}", err),
+            AppError::CommandSnapshot(err) => write!(f, "{}", err),
+            AppError::CommandLabel(err) => write!(f, "{}", err),
+            AppError::CommandMood(err) => write!(f, "{}", err),
+            AppError::CommandQueue(err) => write!(f, "{}", err),
+            AppError::CommandPlay(err) => write!(f, "{}", err),
+            AppError::CommandNote(err) => write!(f, "{}", err),
+            AppError::CommandSpoken(err) => write!(f, "{}", err),
+            AppError::CommandUser(err) => write!(f, "{}", err),
+            AppError::CommandEnrich(err) => write!(f, "{}", err),
+            AppError::CommandQuery(err) => write!(f, "{}", err),
+            AppError::CommandExport(err) => write!(f, "{}", err),
+            AppError::CommandTop(err) => write!(f, "{}", err),
+            AppError::CommandWrapped(err) => write!(f, "{}", err),
+            AppError::CommandHistory(err) => write!(f, "{}", err),
+            AppError::CommandTag(err) => write!(f, "{}", err),
+            AppError::CommandImport(err) => write!(f, "{}", err),
+            AppError::CommandRip(err) => write!(f, "{}", err),
+            AppError::CommandSecrets(err) => write!(f, "{}", err),
+            AppError::CommandLibrary(err) => write!(f, "{}", err),
+            AppError::CommandBench(err) => write!(f, "{}", err),
+            AppError::CommandSchema(err) => write!(f, "{}", err),
+            AppError::CommandRestore(err) => write!(f, "{}", err),
+            AppError::CommandDb(err) => write!(f, "{}", err),
+            AppError::CommandYears(err) => write!(f, "{}", err),
+            AppError::CommandDu(err) => write!(f, "{}", err),
+            AppError::CommandDoctor(err) => write!(f, "{}", err),
+            AppError::CommandUpgrades(err) => write!(f, "{}", err),
+            AppError::CommandVerify(err) => write!(f, "{}", err),
+            AppError::CommandPick(err) => write!(f, "{}", err),
+            AppError::CommandAlias(err) => write!(f, "{}", err),
+            AppError::CommandLyrics(err) => write!(f, "{}", err),
+            AppError::CommandLinks(err) => write!(f, "{}", err),
+            AppError::CommandCollection(err) => write!(f, "{}", err),
+            AppError::CommandUnreadable(err) => write!(f, "{}", err),
+            AppError::CommandCover(err) => write!(f, "{}", err),
+            AppError::CommandInfer(err) => write!(f, "{}", err),
+            AppError::CommandFields(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<OpenDatabaseError> for AppError {
+    fn from(err: OpenDatabaseError) -> AppError {
+        AppError::OpenDatabase(err)
+    }
+}
+impl From<InitDatabaseError> for AppError {
+    fn from(err: InitDatabaseError) -> AppError {
+        AppError::InitDatabase(err)
+    }
+}
+impl From<CommandConfigError> for AppError {
+    fn from(err: CommandConfigError) -> AppError {
+        AppError::CommandConfig(err)
+    }
+}
+impl From<CommandScanError> for AppError {
+    fn from(err: CommandScanError) -> AppError {
+        AppError::CommandScan(err)
+    }
+}
+impl From<genre::CommandGenreError> for AppError {
+    fn from(err: genre::CommandGenreError) -> AppError {
+        AppError::CommandGenre(err)
+    }
+}
+impl From<list::CommandListError> for AppError {
+    fn from(err: list::CommandListError) -> AppError {
+        AppError::CommandList(err)
+    }
+}
+impl From<incomplete::CommandIncompleteError> for AppError {
+    fn from(err: incomplete::CommandIncompleteError) -> AppError {
+        AppError::CommandIncomplete(err)
+    }
+}
+impl From<jobs::CommandJobsError> for AppError {
+    fn from(err: jobs::CommandJobsError) -> AppError {
+        AppError::CommandJobs(err)
+    }
+}
+impl From<daemon::DaemonError> for AppError {
+    fn from(err: daemon::DaemonError) -> AppError {
+        AppError::Daemon(err)
+    }
+}
+#[cfg(unix)]
+impl From<rpc::RpcError> for AppError {
+    fn from(err: rpc::RpcError) -> AppError {
+        AppError::Rpc(err)
+    }
+}
+impl From<snapshot::CommandSnapshotError> for AppError {
+    fn from(err: snapshot::CommandSnapshotError) -> AppError {
+        AppError::CommandSnapshot(err)
+    }
+}
+impl From<mood::CommandMoodError> for AppError {
+    fn from(err: mood::CommandMoodError) -> AppError {
+        AppError::CommandMood(err)
+    }
+}
+
+impl From<play::CommandPlayError> for AppError {
+    fn from(err: play::CommandPlayError) -> AppError {
+        AppError::CommandPlay(err)
+    }
+}
+
+impl From<queue::CommandQueueError> for AppError {
+    fn from(err: queue::CommandQueueError) -> AppError {
+        AppError::CommandQueue(err)
+    }
+}
+
+impl From<label::CommandLabelError> for AppError {
+    fn from(err: label::CommandLabelError) -> AppError {
+        AppError::CommandLabel(err)
+    }
+}
+impl From<note::CommandNoteError> for AppError {
+    fn from(err: note::CommandNoteError) -> AppError {
+        AppError::CommandNote(err)
+    }
+}
+impl From<spoken::CommandSpokenError> for AppError {
+    fn from(err: spoken::CommandSpokenError) -> AppError {
+        AppError::CommandSpoken(err)
+    }
+}
+impl From<user::CommandUserError> for AppError {
+    fn from(err: user::CommandUserError) -> AppError {
+        AppError::CommandUser(err)
+    }
+}
+
+impl From<enrich::CommandEnrichError> for AppError {
+    fn from(err: enrich::CommandEnrichError) -> AppError {
+        AppError::CommandEnrich(err)
+    }
+}
+
+impl From<query::CommandQueryError> for AppError {
+    fn from(err: query::CommandQueryError) -> AppError {
+        AppError::CommandQuery(err)
+    }
+}
+
+impl From<export::CommandExportError> for AppError {
+    fn from(err: export::CommandExportError) -> AppError {
+        AppError::CommandExport(err)
+    }
+}
+
+impl From<top::CommandTopError> for AppError {
+    fn from(err: top::CommandTopError) -> AppError {
+        AppError::CommandTop(err)
+    }
+}
+
+impl From<wrapped::CommandWrappedError> for AppError {
+    fn from(err: wrapped::CommandWrappedError) -> AppError {
+        AppError::CommandWrapped(err)
+    }
+}
+
+impl From<history::CommandHistoryError> for AppError {
+    fn from(err: history::CommandHistoryError) -> AppError {
+        AppError::CommandHistory(err)
+    }
+}
+
+impl From<tag::CommandTagError> for AppError {
+    fn from(err: tag::CommandTagError) -> AppError {
+        AppError::CommandTag(err)
+    }
+}
+
+impl From<import::CommandImportError> for AppError {
+    fn from(err: import::CommandImportError) -> AppError {
+        AppError::CommandImport(err)
+    }
+}
+
+impl From<rip::CommandRipError> for AppError {
+    fn from(err: rip::CommandRipError) -> AppError {
+        AppError::CommandRip(err)
+    }
+}
+impl From<secrets::CommandSecretsError> for AppError {
+    fn from(err: secrets::CommandSecretsError) -> AppError {
+        AppError::CommandSecrets(err)
+    }
+}
+
+impl From<library::CommandLibraryError> for AppError {
+    fn from(err: library::CommandLibraryError) -> AppError {
+        AppError::CommandLibrary(err)
+    }
+}
+
+impl From<bench::CommandBenchError> for AppError {
+    fn from(err: bench::CommandBenchError) -> AppError {
+        AppError::CommandBench(err)
+    }
+}
+
+impl From<schema::CommandSchemaError> for AppError {
+    fn from(err: schema::CommandSchemaError) -> AppError {
+        AppError::CommandSchema(err)
+    }
+}
+
+impl From<tombstones::CommandRestoreError> for AppError {
+    fn from(err: tombstones::CommandRestoreError) -> AppError {
+        AppError::CommandRestore(err)
+    }
+}
+
+impl From<tags::CommandFieldsError> for AppError {
+    fn from(err: tags::CommandFieldsError) -> AppError {
+        AppError::CommandFields(err)
+    }
+}
+
+impl From<db::CommandDbError> for AppError {
+    fn from(err: db::CommandDbError) -> AppError {
+        AppError::CommandDb(err)
+    }
+}
+
+impl From<years::CommandYearsError> for AppError {
+    fn from(err: years::CommandYearsError) -> AppError {
+        AppError::CommandYears(err)
+    }
+}
+impl From<du::CommandDuError> for AppError {
+    fn from(err: du::CommandDuError) -> AppError {
+        AppError::CommandDu(err)
+    }
+}
+impl From<doctor::CommandDoctorError> for AppError {
+    fn from(err: doctor::CommandDoctorError) -> AppError {
+        AppError::CommandDoctor(err)
+    }
+}
+impl From<upgrades::CommandUpgradesError> for AppError {
+    fn from(err: upgrades::CommandUpgradesError) -> AppError {
+        AppError::CommandUpgrades(err)
+    }
+}
+impl From<verify::CommandVerifyError> for AppError {
+    fn from(err: verify::CommandVerifyError) -> AppError {
+        AppError::CommandVerify(err)
+    }
+}
+impl From<pick::CommandPickError> for AppError {
+    fn from(err: pick::CommandPickError) -> AppError {
+        AppError::CommandPick(err)
+    }
+}
+impl From<alias::CommandAliasError> for AppError {
+    fn from(err: alias::CommandAliasError) -> AppError {
+        AppError::CommandAlias(err)
+    }
+}
+impl From<lyrics::CommandLyricsError> for AppError {
+    fn from(err: lyrics::CommandLyricsError) -> AppError {
+        AppError::CommandLyrics(err)
+    }
+}
+impl From<links::CommandLinksError> for AppError {
+    fn from(err: links::CommandLinksError) -> AppError {
+        AppError::CommandLinks(err)
+    }
+}
+impl From<collection::CommandCollectionError> for AppError {
+    fn from(err: collection::CommandCollectionError) -> AppError {
+        AppError::CommandCollection(err)
+    }
+}
+impl From<unreadable::CommandUnreadableError> for AppError {
+    fn from(err: unreadable::CommandUnreadableError) -> AppError {
+        AppError::CommandUnreadable(err)
+    }
+}
+
+impl From<artwork::CommandCoverError> for AppError {
+    fn from(err: artwork::CommandCoverError) -> AppError {
+        AppError::CommandCover(err)
+    }
+}
+
+impl From<infer::CommandInferError> for AppError {
+    fn from(err: infer::CommandInferError) -> AppError {
+        AppError::CommandInfer(err)
+    }
+}
+
+fn do_main(matches: &clap::ArgMatches) -> Result<(), AppError> {
+    if let Some(data_dir) = matches.value_of("data-dir") {
+        set_data_dir(Path::new(data_dir));
+    }
+    if let Some(database) = matches.value_of("database") {
+        *DATABASE.lock().unwrap() = Some(database.to_owned());
+    }
+    if matches.is_present("no-color") {
+        table::disable_color();
+    }
+
+    // Without a command there's nothing to open the database for
+    if matches.subcommand().is_none() {
+        return Ok(());
+    }
+    // Talking to a running daemon doesn't need the database
+    #[cfg(unix)]
+    if let Some(("ctl", sub_matches)) = matches.subcommand() {
+        daemon::cmd_ctl(sub_matches)?;
+        return Ok(());
+    }
+    if let Some(("bench", sub_matches)) = matches.subcommand() {
+        bench::cmd_bench(sub_matches)?;
+        return Ok(());
+    }
+    // The schema of this binary, whatever the database
+    if let Some(("schema", _)) = matches.subcommand() {
+        schema::cmd_schema()?;
+        return Ok(());
+    }
+
+    let mut database = open_database()?;
+    init_database(&mut database)?;
+    // Those of users added before passwords were encrypted
+    user::encrypt_passwords(&database).map_err(InitDatabaseError::PasswordKey)?;
+    // And those indexed before paths were relative to the library
+    library::load_root(&database).map_err(InitDatabaseError::SQLite)?;
+    library::store_relative(&database).map_err(InitDatabaseError::SQLite)?;
+    let language = get_config_value(&database, "language").map_err(InitDatabaseError::SQLite)?;
+    i18n::init(language.as_deref());
+
+    match matches.subcommand() {
+        Some(("config", sub_matches)) => {
+            cmd_config(&mut database, sub_matches)?;
+        }
+        Some(("scan", sub_matches)) => {
+            cmd_scan(&mut database, sub_matches)?;
+        }
+        Some(("genre", sub_matches)) => {
+            genre::cmd_genre(&mut database, sub_matches)?;
+        }
+        Some(("list", sub_matches)) => {
+            list::cmd_list(&mut database, sub_matches)?;
+        }
+        Some(("incomplete", sub_matches)) => {
+            incomplete::cmd_incomplete(&mut database, sub_matches)?;
+        }
+        Some(("years", sub_matches)) => {
+            years::cmd_years(&mut database, sub_matches)?;
+        }
+        Some(("du", sub_matches)) => {
+            du::cmd_du(&mut database, sub_matches)?;
+        }
+        Some(("upgrades", sub_matches)) => {
+            upgrades::cmd_upgrades(&mut database, sub_matches)?;
+        }
+        Some(("verify", sub_matches)) => {
+            verify::cmd_verify(&mut database, sub_matches)?;
+        }
+        Some(("doctor", sub_matches)) => {
+            let worst = doctor::cmd_doctor(&mut database, sub_matches)?;
+            if worst != doctor::Severity::Ignore {
+                drop(database);
+                std::process::exit(worst.exit_code());
+            }
+        }
+        Some(("pick", sub_matches)) => {
+            pick::cmd_pick(&mut database, sub_matches)?;
+        }
+        Some(("alias", sub_matches)) => {
+            alias::cmd_alias(&mut database, sub_matches)?;
+        }
+        Some(("filter", sub_matches)) => {
+            alias::cmd_filter(&mut database, sub_matches)?;
+        }
+        Some(("lyrics", sub_matches)) => {
+            lyrics::cmd_lyrics(&mut database, sub_matches)?;
+        }
+        Some(("links", sub_matches)) => {
+            links::cmd_links(&mut database, sub_matches)?;
+        }
+        Some(("collection", sub_matches)) => {
+            collection::cmd_collection(&mut database, sub_matches)?;
+        }
+        Some(("unreadable", sub_matches)) => {
+            unreadable::cmd_unreadable(&mut database, sub_matches)?;
+        }
+        Some(("cover", sub_matches)) => {
+            artwork::cmd_cover(&mut database, sub_matches)?;
+        }
+        Some(("fields", sub_matches)) => {
+            tags::cmd_fields(&mut database, sub_matches)?;
+        }
+        Some(("jobs", sub_matches)) => {
+            jobs::cmd_jobs(&mut database, sub_matches)?;
+        }
+        Some(("label", sub_matches)) => {
+            label::cmd_label(&mut database, sub_matches)?;
+        }
+        Some(("mood", sub_matches)) => {
+            mood::cmd_mood(&mut database, sub_matches)?;
+        }
+        Some(("note", sub_matches)) => {
+            note::cmd_note(&mut database, sub_matches)?;
+        }
+        Some(("search", sub_matches)) => {
+            note::cmd_search(&mut database, sub_matches)?;
+        }
+        Some(("spoken", sub_matches)) => {
+            spoken::cmd_spoken(&mut database, sub_matches)?;
+        }
+        Some(("play", sub_matches)) => {
+            play::cmd_play(&mut database, sub_matches)?;
+        }
+        Some(("queue", sub_matches)) => {
+            queue::cmd_queue(&mut database, sub_matches)?;
+        }
+        Some(("user", sub_matches)) => {
+            user::cmd_user(&mut database, sub_matches)?;
+        }
+        Some(("enrich", sub_matches)) => {
+            enrich::cmd_enrich(&mut database, sub_matches)?;
+        }
+        Some(("info", sub_matches)) => {
+            enrich::cmd_info(&mut database, sub_matches)?;
+        }
+        Some(("infer", sub_matches)) => {
+            infer::cmd_infer(&mut database, sub_matches)?;
+        }
+        Some(("query", sub_matches)) => {
+            query::cmd_query(&mut database, sub_matches)?;
+        }
+        Some(("export", sub_matches)) => {
+            export::cmd_export(&mut database, sub_matches)?;
+        }
+        Some(("top", sub_matches)) => {
+            top::cmd_top(&mut database, sub_matches)?;
+        }
+        Some(("wrapped", sub_matches)) => {
+            wrapped::cmd_wrapped(&mut database, sub_matches)?;
+        }
+        Some(("history", sub_matches)) => {
+            history::cmd_history(&mut database, sub_matches)?;
+        }
+        Some(("tag", sub_matches)) => {
+            tag::cmd_tag(&mut database, sub_matches)?;
+        }
+        Some(("import", sub_matches)) => {
+            import::cmd_import(&mut database, sub_matches)?;
+        }
+        Some(("rip", sub_matches)) => {
+            rip::cmd_rip(&mut database, sub_matches)?;
+        }
+        Some(("secrets", sub_matches)) => {
+            secrets::cmd_secrets(&mut database, sub_matches)?;
+        }
+        Some(("restore", sub_matches)) => {
+            tombstones::cmd_restore(&mut database, sub_matches)?;
+        }
+        Some(("library", sub_matches)) => {
+            library::cmd_library(&mut database, sub_matches)?;
+        }
+        Some(("db", sub_matches)) => {
+            db::cmd_db(&mut database, sub_matches)?;
+        }
+        Some(("snapshot", sub_matches)) => {
+            snapshot::cmd_snapshot(&mut database, sub_matches)?;
+        }
+        Some(("diff", sub_matches)) => {
+            snapshot::cmd_diff(&mut database, sub_matches)?;
+        }
+        Some(("daemon", sub_matches)) => {
+            daemon::cmd_daemon(&mut database, sub_matches)?;
+        }
+        #[cfg(unix)]
+        Some(("rpc", sub_matches)) => {
+            rpc::cmd_rpc(&mut database, sub_matches)?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Runs the command line with the arguments of the process.
+pub fn run() {
+    let app =
+        Command::new("zik")
+            .author("Vincent Rischmann <vincent@rischmann.fr>")
+            .version("1.0")
+            .about("Create a database of your music library")
+            .arg(
+                Arg::new("data-dir")
+                    .long("data-dir")
+                    .takes_value(true)
+                    .global(true)
+                    .help("Folder of the database, covers and transcodes, ZIK_DATA_DIR by default"),
+            )
+            .arg(
+                Arg::new("database")
+                    .long("database")
+                    .takes_value(true)
+                    .global(true)
+                    .help("Database file, or :memory: for one gone when zik exits"),
+            )
+            .arg(
+                Arg::new("no-color")
+                    .long("no-color")
+                    .global(true)
+                    .help("Print listings without colors, which NO_COLOR does too"),
+            )
+            .subcommand(
+                Command::new("config")
+                    .about("View or set the configuration")
+                    .arg(Arg::new("key").takes_value(true).required(false))
+                    .arg(
+                        Arg::new("value")
+                            .takes_value(true)
+                            .required(false)
+                            .allow_hyphen_values(true),
+                    ),
+            )
+            .subcommand(
+                Command::new("scan")
+                    .about("Scan your music library")
+                    .arg(
+                        Arg::new("dir")
+                            .takes_value(true)
+                            .help("Scan only this folder, in the library or not, leaving the rest as it is"),
+                    )
+                    .arg(
+                        Arg::new("throttle")
+                            .long("throttle")
+                            .help("Scan with the lowest CPU and I/O priority"),
+                    )
+                    .arg(
+                        Arg::new("max-rate")
+                            .long("max-rate")
+                            .takes_value(true)
+                            .help("Read at most this many files per second"),
+                    )
+                    .arg(
+                        Arg::new("sleep")
+                            .long("sleep")
+                            .takes_value(true)
+                            .help("Pause this many milliseconds after every file"),
+                    ),
+            )
+            .subcommand(
+                Command::new("genre")
+                    .about("Manage genre normalization")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("alias")
+                            .about("List or edit the genre aliases applied at scan time")
+                            .subcommand(
+                                Command::new("add")
+                                    .about("Map a genre spelling to its canonical name")
+                                    .arg(Arg::new("alias").takes_value(true).required(true))
+                                    .arg(Arg::new("genre").takes_value(true).required(true)),
+                            )
+                            .subcommand(
+                                Command::new("remove")
+                                    .about("Remove a genre alias")
+                                    .arg(Arg::new("alias").takes_value(true).required(true)),
+                            )
+                            .subcommand(Command::new("list").about("List the genre aliases")),
+                    )
+                    .subcommand(
+                        Command::new("parent")
+                            .about("List or edit the genre hierarchy used when filtering by genre")
+                            .subcommand(
+                                Command::new("add")
+                                    .about("Make a genre a subgenre of another, like Shoegaze of Rock")
+                                    .arg(Arg::new("genre").takes_value(true).required(true))
+                                    .arg(Arg::new("parent").takes_value(true).required(true)),
+                            )
+                            .subcommand(
+                                Command::new("remove")
+                                    .about("Remove a parent of a genre")
+                                    .arg(Arg::new("genre").takes_value(true).required(true))
+                                    .arg(Arg::new("parent").takes_value(true).required(true)),
+                            )
+                            .subcommand(
+                                Command::new("list").about("List the genres and their parents"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("unmapped")
+                            .about("List scanned genres that aren't the target of any alias"),
+                    ),
+            )
+            .subcommand(
+                Command::new("list")
+                    .about("List the content of your library")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("albums")
+                            .about("List albums")
+                            .arg(
+                                Arg::new("by-decade")
+                                    .long("by-decade")
+                                    .help("Count albums per decade instead of listing them"),
+                            )
+                            .arg(Arg::new("year").long("year").takes_value(true).help(
+                                "Only a year (1994), a decade (1990s) or a range (1990-1995)",
+                            ))
+                            .arg(
+                                Arg::new("type")
+                                    .long("type")
+                                    .takes_value(true)
+                                    .possible_values(release::ReleaseType::ALL)
+                                    .help("Only albums of this release type"),
+                            )
+                            .arg(
+                                Arg::new("sidecar")
+                                    .long("sidecar")
+                                    .takes_value(true)
+                                    .possible_values(sidecar::Kind::ALL)
+                                    .help("Only albums with this kind of sidecar file"),
+                            )
+                            .arg(
+                                Arg::new("no-sidecar")
+                                    .long("no-sidecar")
+                                    .takes_value(true)
+                                    .possible_values(sidecar::Kind::ALL)
+                                    .help("Only albums without this kind of sidecar file"),
+                            )
+                            .arg(
+                                Arg::new("collection")
+                                    .long("collection")
+                                    .takes_value(true)
+                                    .help("Only albums of this collection"),
+                            )
+                            .arg(
+                                Arg::new("json")
+                                    .long("json")
+                                    .help("Print the result as JSON"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("tracks")
+                            .about("List tracks")
+                            .arg(
+                                Arg::new("by-decade")
+                                    .long("by-decade")
+                                    .help("Count tracks per decade instead of listing them"),
+                            )
+                            .arg(Arg::new("year").long("year").takes_value(true).help(
+                                "Only a year (1994), a decade (1990s) or a range (1990-1995)",
+                            ))
+                            .arg(
+                                Arg::new("label")
+                                    .long("label")
+                                    .takes_value(true)
+                                    .help("Only tracks with this label"),
+                            )
+                            .arg(
+                                Arg::new("mood")
+                                    .long("mood")
+                                    .takes_value(true)
+                                    .help("Only tracks with this mood, ignoring case"),
+                            )
+                            .arg(
+                                Arg::new("energy")
+                                    .long("energy")
+                                    .takes_value(true)
+                                    .help("Only tracks with this energy (7) or in a range (5-8)"),
+                            )
+                            .arg(
+                                Arg::new("source")
+                                    .long("source")
+                                    .takes_value(true)
+                                    .help("Only tracks from this source, like \"bandcamp\""),
+                            )
+                            .arg(
+                                Arg::new("genre")
+                                    .long("genre")
+                                    .takes_value(true)
+                                    .help("Only tracks of this genre or its subgenres, ignoring case"),
+                            )
+                            .arg(
+                                Arg::new("collection")
+                                    .long("collection")
+                                    .takes_value(true)
+                                    .help("Only tracks of this collection"),
+                            )
+                            .arg(
+                                Arg::new("json")
+                                    .long("json")
+                                    .help("Print the result as JSON"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("genres")
+                            .about("List the genres with their number of tracks")
+                            .arg(
+                                Arg::new("tree")
+                                    .long("tree")
+                                    .help("Show the genres under their parents, counting their subgenres"),
+                            )
+                            .arg(
+                                Arg::new("json")
+                                    .long("json")
+                                    .help("Print the result as JSON"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("videos")
+                            .about("List the video files indexed when index_videos is set")
+                            .arg(
+                                Arg::new("json")
+                                    .long("json")
+                                    .help("Print the result as JSON"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("label")
+                    .about("Manage your own labels on tracks")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("add")
+                            .about("Add a label to a track")
+                            .arg(
+                                Arg::new("track")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Track id or path"),
+                            )
+                            .arg(Arg::new("label").takes_value(true).required(true)),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove a label from a track")
+                            .arg(
+                                Arg::new("track")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Track id or path"),
+                            )
+                            .arg(Arg::new("label").takes_value(true).required(true)),
+                    )
+                    .subcommand(
+                        Command::new("list")
+                            .about("List the labels, or the labels of a track")
+                            .arg(Arg::new("track").takes_value(true).help("Track id or path")),
+                    ),
+            )
+            .subcommand(
+                Command::new("mood")
+                    .about("Manage the moods of tracks")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("set")
+                            .about("Set the moods of a track, replacing the tagged ones")
+                            .arg(
+                                Arg::new("track")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Track id or path"),
+                            )
+                            .arg(
+                                Arg::new("mood")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .required(true),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("clear")
+                            .about("Go back to the tagged moods of a track")
+                            .arg(
+                                Arg::new("track")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Track id or path"),
+                            ),
+                    )
+                    .subcommand(Command::new("list").about("List the moods")),
+            )
+            .subcommand(
+                Command::new("note")
+                    .about("Show or write your note on an album or a track")
+                    .arg(
+                        Arg::new("target")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Album id or name, or track id or path with --track"),
+                    )
+                    .arg(
+                        Arg::new("text")
+                            .takes_value(true)
+                            .help("The note, replacing the previous one"),
+                    )
+                    .arg(
+                        Arg::new("track")
+                            .long("track")
+                            .help("The target is a track instead of an album"),
+                    )
+                    .arg(
+                        Arg::new("remove")
+                            .long("remove")
+                            .conflicts_with("text")
+                            .help("Remove the note"),
+                    ),
+            )
+            .subcommand(
+                Command::new("search")
+                    .about("Search the notes and the comment tags")
+                    .arg(
+                        Arg::new("query")
+                            .takes_value(true)
+                            .required(true)
+                            .help("SQLite full text query, like \"vinyl\" or \"24 NEAR 96\""),
+                    ),
+            )
+            .subcommand(
+                Command::new("spoken")
+                    .about("Browse audiobooks and podcasts")
+                    .subcommand_required(true)
+                    .subcommand(Command::new("list").about("List the spoken word tracks"))
+                    .subcommand(
+                        Command::new("chapters")
+                            .about("List the chapters of a track, or the songs of a mix")
+                            .arg(
+                                Arg::new("track")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Track id or path"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("resume")
+                            .about("Show or set where to resume a track")
+                            .arg(
+                                Arg::new("track")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Track id or path"),
+                            )
+                            .arg(
+                                Arg::new("position")
+                                    .takes_value(true)
+                                    .help("Seconds, or a time like 1:02:03"),
+                            )
+                            .arg(
+                                Arg::new("clear")
+                                    .long("clear")
+                                    .conflicts_with("position")
+                                    .help("Forget the resume position"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("incomplete").about("List albums with missing tracks or discs"),
+            )
+            .subcommand(
+                Command::new("years").about("List albums whose tracks disagree on the year"),
+            )
+            .subcommand(
+                Command::new("du").about("Show the tracks, size and duration per library folder"),
+            )
+            .subcommand(
+                Command::new("upgrades")
+                    .about("List albums mixing lossless and lossy files or only in low bitrate MP3")
+                    .arg(
+                        Arg::new("below")
+                            .long("below")
+                            .takes_value(true)
+                            .default_value("192")
+                            .help("The bitrate in kbit/s under which an MP3 album is listed"),
+                    )
+                    .arg(
+                        Arg::new("by")
+                            .long("by")
+                            .takes_value(true)
+                            .possible_values(["plays", "name"])
+                            .default_value("plays")
+                            .help("Sort by play count or by artist and album"),
+                    )
+                    .arg(Arg::new("limit").long("limit").takes_value(true))
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .help("Print the result as JSON"),
+                    ),
+            )
+            .subcommand(
+                Command::new("verify")
+                    .about("Decode the tracks to find corrupt files, or list those found")
+                    .arg(
+                        Arg::new("decode")
+                            .long("decode")
+                            .help("Decode the tracks, checking the MD5 of FLAC files"),
+                    )
+                    .arg(
+                        Arg::new("all")
+                            .long("all")
+                            .requires("decode")
+                            .help("Decode tracks already verified and unchanged since"),
+                    )
+                    .arg(
+                        Arg::new("workers")
+                            .long("workers")
+                            .takes_value(true)
+                            .requires("decode")
+                            .help("The number of files decoded at once, jobs_parallelism by default"),
+                    ),
+            )
+            .subcommand(
+                Command::new("doctor")
+                    .about("Check the tags of the library, exiting with 1 on warnings and 2 on errors")
+                    .arg(
+                        Arg::new("rule")
+                            .long("rule")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .help("Set the severity of rules, like mixed_formats=error"),
+                    )
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .help("Print the report as JSON"),
+                    ),
+            )
+            .subcommand(
+                Command::new("pick")
+                    .about("Pick tracks or albums with a fuzzy finder, printing their ids")
+                    .arg(
+                        Arg::new("kind")
+                            .takes_value(true)
+                            .possible_values(["tracks", "albums"])
+                            .default_value("tracks"),
+                    )
+                    .arg(
+                        Arg::new("paths")
+                            .long("paths")
+                            .help("Print the paths of the tracks picked instead of the ids"),
+                    )
+                    .arg(
+                        Arg::new("query")
+                            .long("query")
+                            .takes_value(true)
+                            .help("Start with this query"),
+                    )
+                    .arg(
+                        Arg::new("filter")
+                            .long("filter")
+                            .takes_value(true)
+                            .conflicts_with("query")
+                            .help("Print the matches of this query, best first, without asking"),
+                    ),
+            )
+            .subcommand(
+                Command::new("alias")
+                    .about("Manage shortcuts to commands, zik <alias> runs the command")
+                    .subcommand(
+                        Command::new("add")
+                            .about("Add or replace an alias")
+                            .arg(Arg::new("name").required(true).help("Name of the alias"))
+                            .arg(
+                                Arg::new("command")
+                                    .required(true)
+                                    .multiple_values(true)
+                                    .allow_hyphen_values(true)
+                                    .help("Command it stands for, like: list tracks --genre Jazz"),
+                            )
+                            .trailing_var_arg(true),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove an alias")
+                            .arg(Arg::new("name").required(true)),
+                    )
+                    .subcommand(Command::new("list").about("List the aliases")),
+            )
+            .subcommand(
+                Command::new("filter")
+                    .about("Manage and run saved filters of tracks")
+                    .args_conflicts_with_subcommands(true)
+                    .arg(Arg::new("name").help("Name of the filter to run"))
+                    .arg(
+                        Arg::new("format")
+                            .long("format")
+                            .takes_value(true)
+                            .possible_values(["table", "csv", "json", "lines"])
+                            .help("Output format, table by default"),
+                    )
+                    .arg(
+                        Arg::new("ids")
+                            .long("ids")
+                            .help("Print the ids of the tracks only"),
+                    )
+                    .arg(
+                        Arg::new("paths")
+                            .long("paths")
+                            .conflicts_with("ids")
+                            .help("Print the paths of the tracks only"),
+                    )
+                    .subcommand(
+                        Command::new("add")
+                            .about("Add or replace a filter")
+                            .arg(Arg::new("name").required(true).help("Name of the filter"))
+                            .arg(Arg::new("expression").required(true).help(
+                                "SQL condition on the columns of v_tracks, like: genre = 'Jazz'",
+                            )),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove a filter")
+                            .arg(Arg::new("name").required(true)),
+                    )
+                    .subcommand(Command::new("list").about("List the filters")),
+            )
+            .subcommand(
+                Command::new("lyrics")
+                    .about("Fetch lyrics from the web")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("fetch")
+                            .about("Fetch plain and synced lyrics from LRCLIB for tracks without any")
+                            .arg(
+                                Arg::new("artist")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .help("Only the tracks of these artists"),
+                            )
+                            .arg(
+                                Arg::new("lrc")
+                                    .long("lrc")
+                                    .help("Also write synced lyrics to a .lrc file next to the track"),
+                            )
+                            .arg(
+                                Arg::new("force")
+                                    .long("force")
+                                    .help("Fetch again the tracks already looked up"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("links")
+                    .about("Show or set the links of an album or a track to streaming services")
+                    .arg(
+                        Arg::new("target")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Album id or name, or track id or path with --track"),
+                    )
+                    .arg(
+                        Arg::new("url")
+                            .takes_value(true)
+                            .multiple_values(true)
+                            .help("Spotify, Apple Music or Bandcamp URLs, replacing the previous ones"),
+                    )
+                    .arg(
+                        Arg::new("track")
+                            .long("track")
+                            .help("The target is a track instead of an album"),
+                    )
+                    .arg(
+                        Arg::new("remove")
+                            .long("remove")
+                            .takes_value(true)
+                            .multiple_occurrences(true)
+                            .possible_values(links::Service::NAMES)
+                            .conflicts_with("url")
+                            .help("Remove the link to this service"),
+                    ),
+            )
+            .subcommand(
+                Command::new("collection")
+                    .about("Manage collections, named subsets of the library")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("add")
+                            .about("Add albums or tracks to a collection, creating it if needed")
+                            .arg(Arg::new("name").takes_value(true).required(true))
+                            .arg(
+                                Arg::new("item")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .required(true)
+                                    .help("Album ids or names, or track ids or paths with --track"),
+                            )
+                            .arg(
+                                Arg::new("track")
+                                    .long("track")
+                                    .help("Add tracks instead of albums"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove albums or tracks from a collection")
+                            .arg(Arg::new("name").takes_value(true).required(true))
+                            .arg(
+                                Arg::new("item")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .required(true)
+                                    .help("Album ids or names, or track ids or paths with --track"),
+                            )
+                            .arg(
+                                Arg::new("track")
+                                    .long("track")
+                                    .help("Remove tracks instead of albums"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("delete")
+                            .about("Delete a collection, leaving its albums and tracks in the library")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    )
+                    .subcommand(
+                        Command::new("list")
+                            .about("List the collections, or the albums and tracks of one")
+                            .arg(Arg::new("name").takes_value(true)),
+                    ),
+            )
+            .subcommand(
+                Command::new("cover")
+                    .about("Show artwork found by scans")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("artist")
+                            .about("Print the path of an artist's image")
+                            .arg(
+                                Arg::new("name")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("The name of the artist, ignoring case"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("fields")
+                    .about("List the fields read with tag_fields, or the values of one")
+                    .arg(
+                        Arg::new("name")
+                            .takes_value(true)
+                            .help("The name of the field"),
+                    ),
+            )
+            .subcommand(
+                Command::new("jobs")
+                    .about("Manage the background work queued by scans")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("run").about("Run the pending jobs").arg(
+                            Arg::new("workers").long("workers").takes_value(true).help(
+                                "Number of jobs to run in parallel (default: jobs_parallelism)",
+                            ),
+                        ),
+                    )
+                    .subcommand(
+                        Command::new("status")
+                            .about("Show the number of queued jobs per kind and state")
+                            .arg(
+                                Arg::new("failed")
+                                    .long("failed")
+                                    .help("Also list the failed jobs with their last error"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("enqueue")
+                            .about("Queue a kind of job for everything missing its result")
+                            .arg(
+                                Arg::new("kind")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("hash, fingerprint, analyze, fetch-cover or transcode"),
+                            )
+                            .arg(
+                                Arg::new("priority")
+                                    .long("priority")
+                                    .takes_value(true)
+                                    .help("Jobs with a higher priority run first"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("retry")
+                            .about("Queue the failed jobs again")
+                            .arg(
+                                Arg::new("kind")
+                                    .takes_value(true)
+                                    .help("Only retry jobs of this kind"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("snapshot")
+                    .about("Keep copies of the database to compare later scans against")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("create")
+                            .about("Save a copy of the database")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    )
+                    .subcommand(Command::new("list").about("List the snapshots"))
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove a snapshot")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    ),
+            )
+            .subcommand(
+                Command::new("diff")
+                    .about("List the albums and tracks added, removed or changed since a snapshot")
+                    .arg(
+                        Arg::new("from")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Snapshot name or database file to compare against"),
+                    )
+                    .arg(
+                        Arg::new("to")
+                            .takes_value(true)
+                            .help("Snapshot name or database file (default: the current database)"),
+                    ),
+            )
+            .subcommand(
+                Command::new("play")
+                    .about("Play tracks into the pipe of a Snapcast server, or on a Chromecast or AirPlay device")
+                    .arg(
+                        Arg::new("output")
+                            .long("output")
+                            .takes_value(true)
+                            .possible_values(["snapcast"])
+                            .required_unless_present("to")
+                            .help("Where to play, snapcast writes to snapcast_fifo"),
+                    )
+                    .arg(
+                        Arg::new("to")
+                            .long("to")
+                            .takes_value(true)
+                            .value_name("device")
+                            .conflicts_with("output")
+                            .help("Cast to the Chromecast or AirPlay device with this name, through the server"),
+                    )
+                    .arg(
+                        Arg::new("user")
+                            .long("user")
+                            .takes_value(true)
+                            .value_name("name")
+                            .requires("to")
+                            .help("The user of the server the device streams as"),
+                    )
+                    .arg(
+                        Arg::new("track")
+                            .takes_value(true)
+                            .multiple_values(true)
+                            .required_unless_present_any(["playlist", "collection"])
+                            .help("Track ids or paths"),
+                    )
+                    .arg(
+                        Arg::new("playlist")
+                            .long("playlist")
+                            .takes_value(true)
+                            .value_name("id")
+                            .conflicts_with_all(&["track", "collection"])
+                            .help("Play a playlist"),
+                    )
+                    .arg(
+                        Arg::new("collection")
+                            .long("collection")
+                            .takes_value(true)
+                            .value_name("name")
+                            .conflicts_with("track")
+                            .help("Play the tracks of a collection"),
+                    )
+                    .arg(
+                        Arg::new("shuffle")
+                            .long("shuffle")
+                            .takes_value(true)
+                            .possible_values(shuffle::Shuffle::ALL)
+                            .help("Shuffle the tracks, smart keeps the same artist or album from playing twice in a row"),
+                    )
+                    .arg(
+                        Arg::new("weight")
+                            .long("weight")
+                            .takes_value(true)
+                            .possible_values(shuffle::Weight::ALL)
+                            .requires("shuffle")
+                            .help("Put the best rated or the least played tracks first more often"),
+                    )
+                    .arg(
+                        Arg::new("crossfade")
+                            .long("crossfade")
+                            .takes_value(true)
+                            .value_name("seconds")
+                            .conflicts_with("to")
+                            .help("Mix the end of each track with the start of the next one (default: crossfade)"),
+                    )
+                    .arg(
+                        Arg::new("gapless")
+                            .long("gapless")
+                            .conflicts_with("to")
+                            .help("Cut the encoder delay and padding of lossy files (default: gapless)"),
+                    )
+                    .arg(
+                        Arg::new("no-gapless")
+                            .long("no-gapless")
+                            .conflicts_with_all(&["to", "gapless"])
+                            .help("Play the tracks as decoded, silence included"),
+                    ),
+            )
+            .subcommand(
+                Command::new("queue")
+                    .about("Show the play queue a user's clients saved")
+                    .arg(
+                        Arg::new("user")
+                            .takes_value(true)
+                            .required(true)
+                            .help("The name of the user"),
+                    )
+                    .arg(Arg::new("clear").long("clear").help("Delete the play queue")),
+            )
+            .subcommand(
+                Command::new("user")
+                    .about("Manage the users of the server")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("add")
+                            .about("Add a user, reading its password from the standard input")
+                            .arg(Arg::new("name").takes_value(true).required(true))
+                            .arg(
+                                Arg::new("admin")
+                                    .long("admin")
+                                    .help("Let the user edit the playlists of everyone"),
+                            )
+                            .arg(
+                                Arg::new("collection")
+                                    .long("collection")
+                                    .takes_value(true)
+                                    .multiple_occurrences(true)
+                                    .help("Only let the user see this collection"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove a user with their play counts, ratings and playlists")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    )
+                    .subcommand(
+                        Command::new("passwd")
+                            .about("Change the password of a user")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    )
+                    .subcommand(
+                        Command::new("restrict")
+                            .about("Only let a user see some collections of the library")
+                            .arg(Arg::new("name").takes_value(true).required(true))
+                            .arg(
+                                Arg::new("collection")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .required(true),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("unrestrict")
+                            .about("Let a user see the whole library again")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    )
+                    .subcommand(Command::new("list").about("List the users")),
+            )
+            .subcommand(
+                Command::new("enrich")
+                    .about("Fetch information about the library from the web")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("artists")
+                            .about("Fetch bios, images and links of artists from Last.fm or Wikidata")
+                            .arg(
+                                Arg::new("artist")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .help("Only these artists"),
+                            )
+                            .arg(
+                                Arg::new("source")
+                                    .long("source")
+                                    .takes_value(true)
+                                    .possible_values(["lastfm", "wikidata"])
+                                    .help("Where to fetch from, Last.fm if `lastfm_api_key` is set and Wikidata otherwise"),
+                            )
+                            .arg(
+                                Arg::new("force")
+                                    .long("force")
+                                    .help("Fetch again the artists fetched less than `enrich_ttl` days ago"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("links")
+                            .about("Search Apple Music for the albums without a link to it")
+                            .arg(
+                                Arg::new("artist")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .help("Only the albums of these artists"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("info")
+                    .about("Show what was fetched about the library")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("artist")
+                            .about("Show the bio, image and links of an artist")
+                            .arg(Arg::new("name").takes_value(true).required(true)),
+                    )
+                    .subcommand(
+                        Command::new("track")
+                            .about("Show the source of a track and when it was first seen")
+                            .arg(Arg::new("path").takes_value(true).required(true)),
+                    ),
+            )
+            .subcommand(
+                Command::new("infer")
+                    .about("Fill in missing tags from the web")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("genres")
+                            .about("Give the tracks without a genre the genre of their artist on Last.fm or MusicBrainz")
+                            .arg(
+                                Arg::new("artist")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .help("Only these artists"),
+                            )
+                            .arg(
+                                Arg::new("source")
+                                    .long("source")
+                                    .takes_value(true)
+                                    .possible_values(["lastfm", "musicbrainz"])
+                                    .help("Where to fetch from, Last.fm if `lastfm_api_key` is set and MusicBrainz otherwise"),
+                            )
+                            .arg(
+                                Arg::new("force")
+                                    .long("force")
+                                    .help("Fetch again the artists fetched less than `enrich_ttl` days ago"),
+                            )
+                            .arg(
+                                Arg::new("clear")
+                                    .long("clear")
+                                    .conflicts_with_all(&["artist", "source", "force"])
+                                    .help("Remove every inferred genre"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("query")
+                    .about("Run a read-only SQL query against the database")
+                    .arg(
+                        Arg::new("sql")
+                            .takes_value(true)
+                            .required_unless_present("schema")
+                            .help("A single statement, like \"SELECT genre, COUNT(*) FROM v_tracks GROUP BY genre\""),
+                    )
+                    .arg(
+                        Arg::new("format")
+                            .long("format")
+                            .takes_value(true)
+                            .possible_values(["table", "csv", "json"])
+                            .default_value("table")
+                            .help("How to print the rows"),
+                    )
+                    .arg(
+                        Arg::new("schema")
+                            .long("schema")
+                            .conflicts_with("sql")
+                            .help("Print the schema of the database instead"),
+                    ),
+            )
+            .subcommand(
+                Command::new("schema")
+                    .about("Print the tables, indexes and views of the database this zik creates"),
+            )
+            .subcommand(
+                Command::new("export")
+                    .about("Export the library for other tools")
+                    .arg(
+                        Arg::new("sqlite-flat")
+                            .long("sqlite-flat")
+                            .takes_value(true)
+                            .value_name("path")
+                            .required_unless_present_any(["m3u", "html", "torrent"])
+                            .conflicts_with_all(&["m3u", "html", "torrent"])
+                            .help("Write a denormalized SQLite database, for Datasette or Metabase"),
+                    )
+                    .arg(
+                        Arg::new("m3u")
+                            .long("m3u")
+                            .takes_value(true)
+                            .value_name("path")
+                            .conflicts_with_all(&["html", "torrent"])
+                            .help("Write an M3U playlist, with an entry per song of the mixes"),
+                    )
+                    .arg(
+                        Arg::new("html")
+                            .long("html")
+                            .takes_value(true)
+                            .value_name("dir")
+                            .conflicts_with("torrent")
+                            .help("Write a static website of the library, to browse with any web server"),
+                    )
+                    .arg(
+                        Arg::new("torrent")
+                            .long("torrent")
+                            .takes_value(true)
+                            .value_name("path")
+                            .help("Write the files of albums with their sizes and SHA-1, as JSON, for cross-seeding"),
+                    )
+                    .arg(
+                        Arg::new("album")
+                            .long("album")
+                            .takes_value(true)
+                            .value_name("id")
+                            .multiple_occurrences(true)
+                            .requires("torrent")
+                            .help("Only list this album instead of all of them"),
+                    )
+                    .arg(
+                        Arg::new("piece-length")
+                            .long("piece-length")
+                            .takes_value(true)
+                            .value_name("KiB")
+                            .requires("torrent")
+                            .help("Also list the SHA-1 of the pieces of this length of each album"),
+                    )
+                    .arg(
+                        Arg::new("feed-days")
+                            .long("feed-days")
+                            .takes_value(true)
+                            .requires("html")
+                            .help("How many days of added albums the feed of the website has"),
+                    )
+                    .arg(
+                        Arg::new("base-url")
+                            .long("base-url")
+                            .takes_value(true)
+                            .requires("html")
+                            .help("Where the website is hosted, for the links of its feed"),
+                    )
+                    .arg(
+                        Arg::new("playlist")
+                            .long("playlist")
+                            .takes_value(true)
+                            .value_name("id")
+                            .requires("m3u")
+                            .conflicts_with("collection")
+                            .help("Only export this playlist instead of the whole library"),
+                    )
+                    .arg(
+                        Arg::new("collection")
+                            .long("collection")
+                            .takes_value(true)
+                            .value_name("name")
+                            .requires("m3u")
+                            .help("Only export the tracks of this collection"),
+                    )
+                    .arg(
+                        Arg::new("shuffle")
+                            .long("shuffle")
+                            .takes_value(true)
+                            .possible_values(shuffle::Shuffle::ALL)
+                            .requires("m3u")
+                            .help("Shuffle the playlist, smart keeps the same artist or album from playing twice in a row"),
+                    )
+                    .arg(
+                        Arg::new("weight")
+                            .long("weight")
+                            .takes_value(true)
+                            .possible_values(shuffle::Weight::ALL)
+                            .requires("shuffle")
+                            .help("Put the best rated or the least played tracks first more often"),
+                    ),
+            )
+            .subcommand(
+                Command::new("top")
+                    .about("Show the most played artists, albums or tracks")
+                    .arg(
+                        Arg::new("kind")
+                            .takes_value(true)
+                            .required(true)
+                            .possible_values(["artists", "albums", "tracks"]),
+                    )
+                    .arg(
+                        Arg::new("by")
+                            .long("by")
+                            .takes_value(true)
+                            .possible_values(["playcount", "duration", "count"])
+                            .default_value("playcount")
+                            .help("Rank by plays, listening time or number of different tracks played"),
+                    )
+                    .arg(
+                        Arg::new("since")
+                            .long("since")
+                            .takes_value(true)
+                            .help("Only the plays of the last 90d, 4w, 6m or 1y"),
+                    )
+                    .arg(
+                        Arg::new("user")
+                            .long("user")
+                            .takes_value(true)
+                            .help("Only the plays of this user"),
+                    )
+                    .arg(
+                        Arg::new("limit")
+                            .long("limit")
+                            .takes_value(true)
+                            .default_value("10"),
+                    )
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .help("Print the result as JSON"),
+                    ),
+            )
+            .subcommand(
+                Command::new("wrapped")
+                    .about("Sum up a year of listening")
+                    .arg(Arg::new("year").takes_value(true).required(true))
+                    .arg(
+                        Arg::new("user")
+                            .long("user")
+                            .takes_value(true)
+                            .help("Only the plays of this user"),
+                    )
+                    .arg(
+                        Arg::new("format")
+                            .long("format")
+                            .takes_value(true)
+                            .possible_values(["text", "json", "html"])
+                            .default_value("text")
+                            .help("How to print the report"),
+                    ),
+            )
+            .subcommand(
+                Command::new("history")
+                    .about("Browse and export the play history")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("sessions")
+                            .about("List the listening sessions")
+                            .arg(
+                                Arg::new("since")
+                                    .long("since")
+                                    .takes_value(true)
+                                    .help("Only the plays of the last 90d, 4w, 6m or 1y"),
+                            )
+                            .arg(
+                                Arg::new("user")
+                                    .long("user")
+                                    .takes_value(true)
+                                    .help("Only the plays of this user"),
+                            )
+                            .arg(
+                                Arg::new("gap")
+                                    .long("gap")
+                                    .takes_value(true)
+                                    .default_value("30m")
+                                    .help("How long without playing ends a session, like 30m or 2h"),
+                            )
+                            .arg(
+                                Arg::new("format")
+                                    .long("format")
+                                    .takes_value(true)
+                                    .possible_values(["text", "csv", "json"])
+                                    .default_value("text"),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("export")
+                            .about("Export every play with its session")
+                            .arg(
+                                Arg::new("since")
+                                    .long("since")
+                                    .takes_value(true)
+                                    .help("Only the plays of the last 90d, 4w, 6m or 1y"),
+                            )
+                            .arg(
+                                Arg::new("user")
+                                    .long("user")
+                                    .takes_value(true)
+                                    .help("Only the plays of this user"),
+                            )
+                            .arg(
+                                Arg::new("gap")
+                                    .long("gap")
+                                    .takes_value(true)
+                                    .default_value("30m")
+                                    .help("How long without playing ends a session, like 30m or 2h"),
+                            )
+                            .arg(
+                                Arg::new("format")
+                                    .long("format")
+                                    .takes_value(true)
+                                    .possible_values(["csv", "json"])
+                                    .default_value("csv"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("tag")
+                    .about("Write to the tags of the files")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("sync-stats")
+                            .about("Write the ratings and play counts into the tags")
+                            .arg(
+                                Arg::new("user")
+                                    .long("user")
+                                    .takes_value(true)
+                                    .help("Whose stats, `tag_stats_user` or the only user by default"),
+                            )
+                            .arg(
+                                Arg::new("dry-run")
+                                    .long("dry-run")
+                                    .help("Show the files that would change without writing them"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("import")
+                    .about("Match albums against MusicBrainz and add them to the library")
+                    .args_conflicts_with_subcommands(true)
+                    .subcommand_negates_reqs(true)
+                    .arg(
+                        Arg::new("dir")
+                            .takes_value(true)
+                            .required(true)
+                            .help("Folder of the albums to import, one album per folder, or a zip with --profile"),
+                    )
+                    .arg(
+                        Arg::new("move")
+                            .long("move")
+                            .help("Move the files instead of copying them"),
+                    )
+                    .arg(
+                        Arg::new("profile")
+                            .long("profile")
+                            .takes_value(true)
+                            .possible_values(import::Profile::ALL)
+                            .help("Import the downloads of a store as they're tagged, without matching them"),
+                    )
+                    .arg(
+                        Arg::new("source")
+                            .long("source")
+                            .takes_value(true)
+                            .help("Where the files come from, like \"bandcamp\" or \"vinyl rip 24/96\""),
+                    )
+                    .subcommand(
+                        Command::new("staged").about("List the albums waiting in the inbox"),
+                    )
+                    .subcommand(
+                        Command::new("approve")
+                            .about("Move albums of the inbox into the library")
+                            .arg(
+                                Arg::new("folder")
+                                    .takes_value(true)
+                                    .multiple_values(true)
+                                    .required_unless_present("all")
+                                    .help("Folders relative to the inbox"),
+                            )
+                            .arg(
+                                Arg::new("all")
+                                    .long("all")
+                                    .help("Approve everything in the inbox"),
+                            )
+                            .arg(
+                                Arg::new("match")
+                                    .long("match")
+                                    .help("Review the matches on MusicBrainz instead of keeping the tags"),
+                            )
+                            .arg(
+                                Arg::new("source")
+                                    .long("source")
+                                    .takes_value(true)
+                                    .help("Where the albums come from, like \"bandcamp\" or \"vinyl rip 24/96\""),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("rip")
+                    .about("Rip an audio CD, match it on MusicBrainz and add it to the library")
+                    .arg(
+                        Arg::new("device")
+                            .long("device")
+                            .takes_value(true)
+                            .help("The drive to read, /dev/cdrom by default"),
+                    ),
+            )
+            .subcommand(
+                Command::new("secrets")
+                    .about("Keep API keys and credentials in the keyring of the OS")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("set")
+                            .about("Store a secret, reading it from the standard input")
+                            .arg(
+                                Arg::new("name")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .possible_values(secrets::NAMES),
+                            ),
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("Remove a secret from the keyring")
+                            .arg(
+                                Arg::new("name")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .possible_values(secrets::NAMES),
+                            ),
+                    )
+                    .subcommand(Command::new("list").about("Show where each secret is kept")),
+            )
+            .subcommand(
+                Command::new("restore")
+                    .about("List the missing tracks or keep them from being purged")
+                    .arg(
+                        Arg::new("path")
+                            .takes_value(true)
+                            .multiple_values(true)
+                            .help("Files or folders of the tracks to restore"),
+                    )
+                    .arg(
+                        Arg::new("all")
+                            .long("all")
+                            .conflicts_with("path")
+                            .help("Restore every missing track"),
+                    ),
+            )
+            .subcommand(
+                Command::new("unreadable")
+                    .about("List the files the last scan had no permission to read")
+                    .arg(
+                        Arg::new("unsupported")
+                            .long("unsupported")
+                            .help("List the audio files in a format zik can't read instead"),
+                    ),
+            )
+            .subcommand(
+                Command::new("library")
+                    .about("Manage the library itself")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("relocate")
+                            .about("Move the root after mounting the library somewhere else")
+                            .arg(
+                                Arg::new("root")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("The new root of the library, like /mnt/nas/music or M:\\"),
+                            )
+                            .arg(
+                                Arg::new("from")
+                                    .long("from")
+                                    .takes_value(true)
+                                    .help("The old root, the `library` config by default"),
+                            )
+                            .arg(
+                                Arg::new("dry-run")
+                                    .long("dry-run")
+                                    .help("Show what would change without changing it"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("db")
+                    .about("Inspect the database")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("pragmas")
+                            .about("Show the journal mode, foreign keys and other connection settings"),
+                    )
+                    .subcommand(
+                        Command::new("encrypt")
+                            .about("Encrypt the database, with zik built with the encryption feature"),
+                    ),
+            )
+            .subcommand(
+                Command::new("bench")
+                    .about("Tools to measure the performance of zik")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("generate")
+                            .about("Generate a fake library of tiny tagged files")
+                            .arg(
+                                Arg::new("dir")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .help("Where to generate it, a new or empty folder"),
+                            )
+                            .arg(
+                                Arg::new("files")
+                                    .long("files")
+                                    .takes_value(true)
+                                    .help("How many files, 5000 by default"),
+                            )
+                            .arg(
+                                Arg::new("audio")
+                                    .long("audio")
+                                    .takes_value(true)
+                                    .value_name("KiB")
+                                    .help("Pad every file with this much silent audio, none by default"),
+                            ),
+                    ),
+            )
+            .subcommand(
+                Command::new("daemon")
+                    .about("Watch the library, run the jobs and serve the API in one process"),
+            );
+
+    // The daemon is controlled and the RPC interface is served over Unix sockets
+    #[cfg(unix)]
+    let app = app
+        .subcommand(
+            Command::new("rpc").about("Serve the JSON-RPC interface without running the daemon"),
+        )
+        .subcommand(
+            Command::new("ctl")
+                .about("Control a running daemon")
+                .subcommand_required(true)
+                .subcommand(Command::new("status").about("Show what the daemon is doing"))
+                .subcommand(Command::new("rescan").about("Scan the library now"))
+                .subcommand(Command::new("stop").about("Stop the daemon")),
+        );
+
+    // Aliases are replaced before parsing, so they take any argument of what they stand for
+    let args = alias::expand_args(&app, std::env::args_os().collect());
+    let matches = app.get_matches_from(args);
+
+    if let Err(err) = do_main(&matches) {
+        println!("{}", err)
+    }
+
+    #[cfg(feature = "heap-profile")]
+    heap::report();
+}
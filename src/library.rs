@@ -0,0 +1,726 @@
+//! Moving the library to another root, like a NAS mounted at another path or a mapped drive
+//! on Windows, without rescanning it.
+//!
+//! The paths of the files in the library are stored relative to its root, with forward
+//! slashes, and resolved against the root configured on this machine when read: `ZIK_LIBRARY`,
+//! else the `library` configuration key. Queries read them with the `library_path()` SQL
+//! function. The same database works wherever the library is mounted, and relocating it only
+//! changes the root. Paths outside of it, like the transcodes or the inbox, stay absolute and
+//! relocating rewrites the ones under the old root.
+//!
+//! Roots are compared normalized: Windows verbatim prefixes (`\\?\C:\`,
+//! `\\?\UNC\server\share`) are removed, separators unified and drive letters and UNC paths
+//! compared ignoring case.
+//!
+//! Libraries on file systems ignoring case, the default ones of macOS and Windows, can give
+//! the path of a file in another case from one scan to the next, like after renaming `Foo.mp3`
+//! to `foo.mp3` or setting the library as `/volumes/music`. Scans find the track of such a
+//! path ignoring case instead of adding it again and marking the old one missing. Like
+//! SQLite's NOCASE, only ASCII letters are folded.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{PoisonError, RwLock};
+
+use rusqlite::functions::FunctionFlags;
+
+use crate::i18n::tr;
+use crate::{storage, TrackID};
+
+/// The columns holding paths of files, some of them maybe outside the library.
+const PATH_COLUMNS: [(&str, &str); 10] = [
+    ("track", "path"),
+    ("track_alias", "path"),
+    ("track", "transcode_path"),
+    ("album", "cover_path"),
+    ("artist_image", "path"),
+    ("video", "path"),
+    ("staged_track", "path"),
+    ("staged_track", "folder"),
+    ("sidecar", "path"),
+    ("unreadable_file", "path"),
+];
+
+/// The columns holding paths stored relative to the root when they're in the library.
+const RELATIVE_COLUMNS: [(&str, &str); 7] = [
+    ("track", "path"),
+    ("track_alias", "path"),
+    ("album", "cover_path"),
+    ("artist_image", "path"),
+    ("video", "path"),
+    ("sidecar", "path"),
+    ("unreadable_file", "path"),
+];
+
+/// The root of the library on this machine, unless it's a remote one.
+static ROOT: RwLock<Option<String>> = RwLock::new(None);
+
+pub enum CommandLibraryError {
+    SQLite(rusqlite::Error),
+    NoLibrary,
+    InvalidRoot(String),
+}
+impl From<rusqlite::Error> for CommandLibraryError {
+    fn from(err: rusqlite::Error) -> CommandLibraryError {
+        CommandLibraryError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandLibraryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandLibraryError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandLibraryError::NoLibrary => f.write_str(&tr!("no-library")),
+            CommandLibraryError::InvalidRoot(value) => {
+                f.write_str(&tr!("library-invalid-root", value = value))
+            }
+        }
+    }
+}
+
+/// Whether a path is a Windows one, with a drive letter or on a network share.
+fn is_windows(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+        || path.starts_with("\\\\")
+        || path.starts_with("//")
+}
+
+/// Whether a stored path is absolute, on this system or another one, or the URL of a file of
+/// a remote library.
+fn is_absolute(path: &str) -> bool {
+    path.starts_with('/') || is_windows(path) || path.contains("://")
+}
+
+/// Removes the verbatim prefix of a Windows path and uses forward slashes.
+fn normalize(path: &str) -> String {
+    let path = if let Some(rest) = path.strip_prefix("\\\\?\\UNC\\") {
+        format!("\\\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix("\\\\?\\") {
+        rest.to_owned()
+    } else {
+        path.to_owned()
+    };
+
+    path.replace('\\', "/")
+}
+
+/// Returns what's left of `path` under `root` with forward slashes, empty for the root itself.
+fn strip_root(path: &str, root: &str) -> Option<String> {
+    // Backslashes are only separators on Windows
+    let windows = is_windows(root);
+    let (path, root) = if windows {
+        (normalize(path), normalize(root))
+    } else {
+        (path.to_owned(), root.to_owned())
+    };
+    let root = root.trim_end_matches('/');
+
+    let head = path.get(..root.len())?;
+    let rest = &path[root.len()..];
+
+    // Windows paths are case insensitive
+    let same_root = if windows {
+        head.eq_ignore_ascii_case(root)
+    } else {
+        head == root
+    };
+    if !same_root || !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+
+    Some(rest.trim_start_matches('/').to_owned())
+}
+
+/// Returns `path` relative to `root`, if it's a file under it.
+fn relative_to(path: &str, root: &str) -> Option<String> {
+    strip_root(path, root).filter(|rest| !rest.is_empty())
+}
+
+/// Returns the stored `path` resolved against `root` if it's relative.
+fn resolve_against(path: &str, root: &str) -> String {
+    if path.is_empty() || is_absolute(path) {
+        return path.to_owned();
+    }
+
+    if is_windows(root) {
+        format!(
+            "{}\\{}",
+            root.trim_end_matches(['/', '\\']),
+            path.replace('/', "\\")
+        )
+    } else {
+        format!("{}/{}", root.trim_end_matches('/'), path)
+    }
+}
+
+/// Returns `path` moved under the root `to` if it's under the root `from`.
+fn relocate_path(path: &str, from: &str, to: &str) -> Option<String> {
+    let rest = strip_root(path, from)?;
+    if rest.is_empty() {
+        return Some(to.to_owned());
+    }
+
+    let separator = if is_windows(to) { "\\" } else { "/" };
+    Some(format!(
+        "{}{}{}",
+        to.trim_end_matches(['/', '\\']),
+        separator,
+        rest.replace('/', separator)
+    ))
+}
+
+/// Sets the root from `ZIK_LIBRARY`, else from the `library` configuration key.
+pub fn load_root(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let root = match env::var("ZIK_LIBRARY").ok().filter(|root| !root.is_empty()) {
+        Some(root) => Some(root),
+        None => crate::get_config_value(db, "library")?,
+    };
+    set_root(root);
+
+    Ok(())
+}
+
+pub fn set_root(root: Option<String>) {
+    // The files of a remote library are stored as URLs
+    let root = root.filter(|root| !storage::is_remote(root));
+    *ROOT.write().unwrap_or_else(PoisonError::into_inner) = root;
+}
+
+/// Returns the root of the library on this machine, if it's a local one.
+pub fn root() -> Option<String> {
+    ROOT.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Returns the root of the library on this machine, or the URL of a remote library.
+pub fn location(db: &rusqlite::Connection) -> rusqlite::Result<Option<String>> {
+    match root() {
+        Some(root) => Ok(Some(root)),
+        None => crate::get_config_value(db, "library"),
+    }
+}
+
+/// Returns how `path` is stored: relative to the root if it's in the library.
+pub fn relative(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    match root().and_then(|root| relative_to(&path, &root)) {
+        Some(relative) => relative,
+        None => path.into_owned(),
+    }
+}
+
+/// Returns the absolute path of the stored `path`.
+pub fn resolve(path: &str) -> String {
+    match root() {
+        Some(root) => resolve_against(path, &root),
+        None => path.to_owned(),
+    }
+}
+
+/// Returns the folder a scan of `folder` is limited to, none if it's the whole library: what's
+/// indexed outside of it isn't marked missing or forgotten. The folder is given resolved like
+/// `library_path()` resolves the paths under it.
+pub fn scan_scope(db: &rusqlite::Connection, folder: &Path) -> rusqlite::Result<Option<String>> {
+    let folder = folder.to_string_lossy();
+    if let Some(location) = location(db)? {
+        // Scans canonicalize the folder, the root may go through a symbolic link
+        let canonical = fs::canonicalize(&location)
+            .map(|root| root.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| location.clone());
+        let is_root = |root: &str| strip_root(&folder, root).is_some_and(|rest| rest.is_empty());
+        if is_root(&location) || is_root(&canonical) {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(resolve(&relative(Path::new(folder.as_ref())))))
+}
+
+/// Registers `library_path()`, which resolves a stored path like `resolve` does, and
+/// `in_folder()`, whether a resolved path is under a folder, or true without a folder.
+pub fn register_functions(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    db.create_scalar_function(
+        "library_path",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| Ok(ctx.get::<Option<String>>(0)?.map(|path| resolve(&path))),
+    )?;
+    db.create_scalar_function(
+        "in_folder",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let path: Option<String> = ctx.get(0)?;
+            let folder: Option<String> = ctx.get(1)?;
+            Ok(match (path, folder) {
+                (_, None) => true,
+                (Some(path), Some(folder)) => strip_root(&path, &folder).is_some(),
+                (None, Some(_)) => false,
+            })
+        },
+    )
+}
+
+/// Stores the absolute paths under the root relative to it, like the ones indexed before paths
+/// were relative or by a zik with another root. Only done when the root changed since the last
+/// time, the paths indexed in between are relative already.
+pub fn store_relative(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    match root() {
+        Some(root) => store_relative_to(db, &root),
+        None => Ok(()),
+    }
+}
+
+fn store_relative_to(db: &rusqlite::Connection, root: &str) -> rusqlite::Result<()> {
+    let last: Option<String> =
+        match db.query_row("SELECT root FROM relative_root", [], |row| row.get(0)) {
+            Ok(last) => Some(last),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(err) => return Err(err),
+        };
+    if last.as_deref() == Some(root) {
+        return Ok(());
+    }
+
+    // Only the paths starting with the root are looked at. The indexes on the paths find them,
+    // but the covers of albums and the images of artists have none and are read through.
+    let separator = if is_windows(root) { '\\' } else { '/' };
+    let trimmed = root.trim_end_matches(['/', '\\']);
+    let lower = format!("{}{}", trimmed, separator);
+    let upper = format!("{}{}", trimmed, (separator as u8 + 1) as char);
+
+    for (table, column) in RELATIVE_COLUMNS {
+        let changes: Vec<(i64, String)> = {
+            let mut stmt = db.prepare(&format!(
+                "SELECT rowid, {column} FROM {table} WHERE {column} >= $lower AND {column} < $upper",
+                table = table,
+                column = column
+            ))?;
+            let rows = stmt.query_map([&lower, &upper], |row| {
+                let path: String = row.get(1)?;
+                Ok((row.get(0)?, path))
+            })?;
+
+            let mut changes = Vec::new();
+            for row in rows {
+                let (rowid, path) = row?;
+                if let Some(relative) = relative_to(&path, root) {
+                    changes.push((rowid, relative));
+                }
+            }
+            changes
+        };
+
+        // A path stored both ways keeps its absolute row
+        for (rowid, path) in changes {
+            db.execute(
+                &format!(
+                    "UPDATE OR IGNORE {table} SET {column} = $path WHERE rowid = $rowid",
+                    table = table,
+                    column = column
+                ),
+                rusqlite::params![path, rowid],
+            )?;
+        }
+    }
+
+    db.execute("DELETE FROM relative_root", [])?;
+    db.execute("INSERT INTO relative_root(root) VALUES($root)", [root])?;
+
+    Ok(())
+}
+
+/// Returns true if the file system of `root` ignores case. An entry of `root` is looked up
+/// with the case of its name swapped, a folder without any entry to try is taken as case
+/// sensitive.
+pub fn is_case_insensitive(root: &Path) -> bool {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let swapped: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_lowercase() {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect();
+        if swapped == name {
+            continue;
+        }
+
+        // Another file with the swapped name means the file system tells them apart
+        return is_same_file(&entry.path(), &root.join(swapped));
+    }
+
+    false
+}
+
+/// Returns true if both paths are the same file, by device and inode.
+#[cfg(unix)]
+fn is_same_file(path: &Path, other: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(path), fs::symlink_metadata(other)) {
+        (Ok(path), Ok(other)) => path.dev() == other.dev() && path.ino() == other.ino(),
+        _ => false,
+    }
+}
+
+/// Returns true if both paths are the same file, by canonical path: Windows gives it in the
+/// case of the file on disk.
+#[cfg(not(unix))]
+fn is_same_file(path: &Path, other: &Path) -> bool {
+    match (fs::canonicalize(path), fs::canonicalize(other)) {
+        (Ok(path), Ok(other)) => path == other,
+        _ => false,
+    }
+}
+
+/// Returns the track at `path` in another case, when none is at `path` itself.
+pub fn find_track_ignoring_case(
+    db: &rusqlite::Connection,
+    path: &Path,
+) -> rusqlite::Result<Option<TrackID>> {
+    match db.query_row(
+        "SELECT id FROM track
+         WHERE path = $path COLLATE NOCASE
+           AND NOT EXISTS (SELECT 1 FROM track WHERE path = $path)
+         LIMIT 1",
+        [relative(path)],
+        |row| row.get(0),
+    ) {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+//
+// "library" command
+//
+
+fn cmd_library_relocate(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLibraryError> {
+    let to = args.value_of("root").unwrap();
+    if !(is_windows(to) || Path::new(to).is_absolute()) {
+        return Err(CommandLibraryError::InvalidRoot(to.to_owned()));
+    }
+    let from = match args.value_of("from") {
+        Some(from) => from.to_owned(),
+        None => match crate::get_config_value(db, "library")? {
+            Some(library) => library,
+            None => return Err(CommandLibraryError::NoLibrary),
+        },
+    };
+    let dry_run = args.is_present("dry-run");
+
+    let from = normalize(&from);
+    let from = from.trim_end_matches('/');
+
+    let savepoint = db.savepoint()?;
+
+    for (table, column) in PATH_COLUMNS {
+        let changes: Vec<(i64, String)> = {
+            let mut stmt = savepoint.prepare(&format!(
+                "SELECT rowid, {column} FROM {table} WHERE {column} IS NOT NULL",
+                table = table,
+                column = column
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                let path: String = row.get(1)?;
+                Ok((row.get(0)?, path))
+            })?;
+
+            let mut changes = Vec::new();
+            for row in rows {
+                let (rowid, path) = row?;
+                if let Some(new_path) = relocate_path(&path, from, to) {
+                    if new_path != path {
+                        changes.push((rowid, new_path));
+                    }
+                }
+            }
+            changes
+        };
+
+        for (rowid, path) in &changes {
+            savepoint.execute(
+                &format!(
+                    "UPDATE {table} SET {column} = $path WHERE rowid = $rowid",
+                    table = table,
+                    column = column
+                ),
+                rusqlite::params![path, rowid],
+            )?;
+        }
+        if !changes.is_empty() {
+            println!(
+                "{}.{}: {}",
+                table,
+                column,
+                tr!("library-paths", paths = changes.len())
+            );
+        }
+    }
+
+    for key in ["library", "inbox"] {
+        if let Some(value) = crate::get_config_value(&savepoint, key)? {
+            if let Some(new_value) = relocate_path(&value, from, to) {
+                savepoint.execute(
+                    "UPDATE config SET value = $value WHERE key = $key",
+                    rusqlite::params![new_value, key],
+                )?;
+                println!("{}: {} -> {}", key, value, new_value);
+            }
+        }
+    }
+
+    if dry_run {
+        println!("{}", tr!("library-dry-run"));
+        return Ok(());
+    }
+    savepoint.commit()?;
+
+    if !Path::new(to).is_dir() {
+        println!("{}", tr!("library-not-mounted", path = to));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_library(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLibraryError> {
+    match args.subcommand() {
+        Some(("relocate", sub_args)) => cmd_library_relocate(db, sub_args),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_windows_paths() {
+        assert_eq!(normalize("C:\\Music\\a.flac"), "C:/Music/a.flac");
+        assert_eq!(normalize("\\\\?\\C:\\Music"), "C:/Music");
+        assert_eq!(
+            normalize("\\\\?\\UNC\\server\\share\\Music"),
+            "//server/share/Music"
+        );
+        assert_eq!(normalize("\\\\server\\share"), "//server/share");
+        assert_eq!(normalize("/mnt/nas/music"), "/mnt/nas/music");
+    }
+
+    #[test]
+    fn relocate_unix_paths() {
+        assert_eq!(
+            relocate_path("/mnt/nas/music/a/b.flac", "/mnt/nas/music", "/media/music"),
+            Some("/media/music/a/b.flac".to_owned())
+        );
+        assert_eq!(
+            relocate_path("/mnt/nas/music", "/mnt/nas/music", "/media/music"),
+            Some("/media/music".to_owned())
+        );
+        // Only whole names of the root match
+        assert_eq!(
+            relocate_path("/mnt/nas/music2/a.flac", "/mnt/nas/music", "/media/music"),
+            None
+        );
+        // Unix paths are case sensitive
+        assert_eq!(
+            relocate_path("/mnt/NAS/music/a.flac", "/mnt/nas/music", "/media/music"),
+            None
+        );
+        assert_eq!(relocate_path("a/b.flac", "/mnt/nas/music", "/media"), None);
+    }
+
+    #[test]
+    fn relocate_windows_paths() {
+        assert_eq!(
+            relocate_path("c:\\music\\A\\b.flac", "C:/Music", "/mnt/music"),
+            Some("/mnt/music/A/b.flac".to_owned())
+        );
+        assert_eq!(
+            relocate_path("\\\\?\\C:\\Music\\a.flac", "C:/Music", "D:\\"),
+            Some("D:\\a.flac".to_owned())
+        );
+        assert_eq!(
+            relocate_path(
+                "\\\\Server\\Share\\Music\\a\\b.flac",
+                "//server/share/music",
+                "M:\\"
+            ),
+            Some("M:\\a\\b.flac".to_owned())
+        );
+        assert_eq!(
+            relocate_path(
+                "\\\\?\\UNC\\server\\share\\a.flac",
+                "//server/share",
+                "/mnt/share"
+            ),
+            Some("/mnt/share/a.flac".to_owned())
+        );
+        assert_eq!(
+            relocate_path("/mnt/music/a/b.flac", "/mnt/music", "\\\\nas\\music"),
+            Some("\\\\nas\\music\\a\\b.flac".to_owned())
+        );
+        assert_eq!(relocate_path("D:\\Music\\a.flac", "C:/Music", "/m"), None);
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_to("/mnt/music/a/b.flac", "/mnt/music/"),
+            Some("a/b.flac".to_owned())
+        );
+        assert_eq!(relative_to("/mnt/music", "/mnt/music"), None);
+        assert_eq!(relative_to("/mnt/musicals/a.flac", "/mnt/music"), None);
+        assert_eq!(relative_to("/cache/1.opus", "/mnt/music"), None);
+        assert_eq!(
+            relative_to("c:\\music\\a\\b.flac", "C:\\Music"),
+            Some("a/b.flac".to_owned())
+        );
+        assert_eq!(
+            relative_to("\\\\?\\UNC\\nas\\music\\a.flac", "\\\\NAS\\Music"),
+            Some("a.flac".to_owned())
+        );
+        // Backslashes are part of names on Unix
+        assert_eq!(
+            relative_to("/mnt/music/a\\b.flac", "/mnt/music"),
+            Some("a\\b.flac".to_owned())
+        );
+    }
+
+    #[test]
+    fn resolve_paths() {
+        assert_eq!(
+            resolve_against("a/b.flac", "/mnt/music/"),
+            "/mnt/music/a/b.flac"
+        );
+        assert_eq!(resolve_against("a/b.flac", "/"), "/a/b.flac");
+        assert_eq!(resolve_against("a/b.flac", "M:\\"), "M:\\a\\b.flac");
+        assert_eq!(
+            resolve_against("a/b.flac", "\\\\nas\\music"),
+            "\\\\nas\\music\\a\\b.flac"
+        );
+        // Absolute paths and URLs are stored as they are
+        assert_eq!(resolve_against("/cache/1.opus", "/mnt"), "/cache/1.opus");
+        assert_eq!(
+            resolve_against("C:\\cache\\1.opus", "/mnt"),
+            "C:\\cache\\1.opus"
+        );
+        assert_eq!(
+            resolve_against("s3://bucket/a.flac", "/mnt"),
+            "s3://bucket/a.flac"
+        );
+    }
+
+    #[test]
+    fn find_tracks_ignoring_case() {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+        crate::init_database(&mut db).unwrap();
+        for path in [
+            "/music/Artist/Foo.mp3",
+            "/music/Artist/Bar.mp3",
+            "/music/Émile.mp3",
+        ] {
+            db.execute("INSERT INTO track(path) VALUES($path)", [path])
+                .unwrap();
+        }
+        let id = |path: &str| -> TrackID {
+            db.query_row("SELECT id FROM track WHERE path = $path", [path], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        let find = |path: &str| find_track_ignoring_case(&db, Path::new(path)).unwrap();
+
+        assert_eq!(
+            find("/music/artist/foo.MP3"),
+            Some(id("/music/Artist/Foo.mp3"))
+        );
+        assert_eq!(
+            find("/MUSIC/ARTIST/BAR.MP3"),
+            Some(id("/music/Artist/Bar.mp3"))
+        );
+        assert_eq!(find("/music/Artist/Baz.mp3"), None);
+        // A track at the path itself is no other case of it
+        assert_eq!(find("/music/Artist/Foo.mp3"), None);
+        // Like NOCASE, only ASCII letters are folded
+        assert_eq!(find("/music/émile.mp3"), None);
+
+        // Found with the index, not by reading every track
+        let plan: String = db
+            .query_row(
+                "EXPLAIN QUERY PLAN SELECT id FROM track WHERE path = $path COLLATE NOCASE",
+                ["/music/artist/foo.mp3"],
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("track_path_nocase"), "{}", plan);
+    }
+
+    #[test]
+    fn store_relative_once_per_root() {
+        let mut db = rusqlite::Connection::open_in_memory().unwrap();
+        crate::init_database(&mut db).unwrap();
+        let insert = |path: &str| {
+            db.execute("INSERT INTO track(path) VALUES($path)", [path])
+                .unwrap();
+        };
+        let paths = || -> Vec<String> {
+            let mut stmt = db.prepare("SELECT path FROM track ORDER BY id").unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+            rows.collect::<rusqlite::Result<_>>().unwrap()
+        };
+
+        insert("/music/Artist/01.flac");
+        insert("/other/02.flac");
+        store_relative_to(&db, "/music").unwrap();
+        assert_eq!(paths(), ["Artist/01.flac", "/other/02.flac"]);
+
+        // Not looked at again with the same root
+        insert("/music/Artist/03.flac");
+        store_relative_to(&db, "/music").unwrap();
+        assert_eq!(
+            paths(),
+            ["Artist/01.flac", "/other/02.flac", "/music/Artist/03.flac"]
+        );
+
+        store_relative_to(&db, "/other").unwrap();
+        assert_eq!(
+            paths(),
+            ["Artist/01.flac", "02.flac", "/music/Artist/03.flac"]
+        );
+    }
+
+    #[test]
+    fn round_trip_between_machines() {
+        let stored = relative_to("/mnt/nas/music/Artist/Album/01.flac", "/mnt/nas/music").unwrap();
+        assert_eq!(stored, "Artist/Album/01.flac");
+        assert_eq!(
+            resolve_against(&stored, "M:\\"),
+            "M:\\Artist\\Album\\01.flac"
+        );
+        assert_eq!(
+            relative_to(&resolve_against(&stored, "M:\\"), "M:\\"),
+            Some(stored)
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Links from albums and tracks to their equivalent on streaming services and stores, for
+//! exports and the web UI to link to.
+//!
+//! Links are set by hand, `zik links <album> <url>` finds the service from the URL, or
+//! searched for with `zik enrich links`, for Apple Music which has a public search API.
+
+use std::fmt;
+
+use crate::i18n::tr;
+#[cfg(unix)]
+use crate::json;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Service {
+    Spotify,
+    AppleMusic,
+    Bandcamp,
+}
+
+impl Service {
+    pub const ALL: [Service; 3] = [Service::Spotify, Service::AppleMusic, Service::Bandcamp];
+    pub const NAMES: &'static [&'static str] = &["spotify", "apple-music", "bandcamp"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Service::Spotify => "spotify",
+            Service::AppleMusic => "apple-music",
+            Service::Bandcamp => "bandcamp",
+        }
+    }
+
+    /// Returns the name to show, like "Apple Music".
+    pub fn title(self) -> &'static str {
+        match self {
+            Service::Spotify => "Spotify",
+            Service::AppleMusic => "Apple Music",
+            Service::Bandcamp => "Bandcamp",
+        }
+    }
+
+    /// Returns the column of the link in `album` and `track`.
+    pub fn column(self) -> &'static str {
+        match self {
+            Service::Spotify => "spotify_url",
+            Service::AppleMusic => "apple_music_url",
+            Service::Bandcamp => "bandcamp_url",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Service> {
+        Service::ALL
+            .iter()
+            .find(|service| service.name() == value)
+            .copied()
+    }
+
+    /// Returns the service of a URL, from its host.
+    pub fn from_url(url: &str) -> Option<Service> {
+        let rest = url
+            .strip_prefix("https://")
+            .or_else(|| url.strip_prefix("http://"))?;
+        let host = rest
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match host.as_str() {
+            "open.spotify.com" => Some(Service::Spotify),
+            "music.apple.com" | "itunes.apple.com" => Some(Service::AppleMusic),
+            host if host == "bandcamp.com" || host.ends_with(".bandcamp.com") => {
+                Some(Service::Bandcamp)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads the links in the columns of the services, in their order, starting at `first`.
+pub fn read(row: &rusqlite::Row, first: usize) -> rusqlite::Result<Vec<(Service, String)>> {
+    let mut links = Vec::new();
+    for (i, service) in Service::ALL.iter().enumerate() {
+        if let Some(url) = row.get::<_, Option<String>>(first + i)? {
+            links.push((*service, url));
+        }
+    }
+
+    Ok(links)
+}
+
+/// Encodes links as a JSON object of URLs by service.
+#[cfg(unix)]
+pub fn to_json(links: &[(Service, String)]) -> String {
+    let fields: Vec<(&str, String)> = links
+        .iter()
+        .map(|(service, url)| (service.name(), json::string(url)))
+        .collect();
+    json::object(&fields)
+}
+
+/// Returns the links of the album or track `id`.
+pub fn get(
+    db: &rusqlite::Connection,
+    table: &str,
+    id: i64,
+) -> rusqlite::Result<Vec<(Service, String)>> {
+    let columns: Vec<&str> = Service::ALL
+        .iter()
+        .map(|service| service.column())
+        .collect();
+    let query = format!(
+        "SELECT {} FROM {} WHERE id = $id",
+        columns.join(", "),
+        table
+    );
+
+    db.query_row(&query, [id], |row| read(row, 0))
+}
+
+pub enum CommandLinksError {
+    SQLite(rusqlite::Error),
+    AlbumNotFound(String),
+    TrackNotFound(String),
+    UnknownService(String),
+}
+impl From<rusqlite::Error> for CommandLinksError {
+    fn from(err: rusqlite::Error) -> CommandLinksError {
+        CommandLinksError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandLinksError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandLinksError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandLinksError::AlbumNotFound(value) => {
+                f.write_str(&tr!("album-id-not-found", value = value))
+            }
+            CommandLinksError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+            CommandLinksError::UnknownService(url) => {
+                f.write_str(&tr!("links-unknown-service", url = url))
+            }
+        }
+    }
+}
+
+//
+// "links" command
+//
+
+pub fn cmd_links(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLinksError> {
+    let value = args.value_of("target").unwrap();
+
+    let (table, id) = if args.is_present("track") {
+        match crate::find_track_id(db, value)? {
+            Some(id) => ("track", id as i64),
+            None => return Err(CommandLinksError::TrackNotFound(value.to_owned())),
+        }
+    } else {
+        match crate::find_album_id(db, value)? {
+            Some(id) => ("album", id as i64),
+            None => return Err(CommandLinksError::AlbumNotFound(value.to_owned())),
+        }
+    };
+
+    if let Some(services) = args.values_of("remove") {
+        for service in services.filter_map(Service::parse) {
+            let query = format!(
+                "UPDATE {} SET {} = NULL WHERE id = $id",
+                table,
+                service.column()
+            );
+            db.execute(&query, [id])?;
+        }
+        return Ok(());
+    }
+
+    if let Some(urls) = args.values_of("url") {
+        for url in urls {
+            let url = url.trim();
+            let service = Service::from_url(url)
+                .ok_or_else(|| CommandLinksError::UnknownService(url.to_owned()))?;
+
+            let query = format!(
+                "UPDATE {} SET {} = $url WHERE id = $id",
+                table,
+                service.column()
+            );
+            db.execute(&query, rusqlite::params![url, id])?;
+        }
+        return Ok(());
+    }
+
+    let links = get(db, table, id)?;
+    if links.is_empty() {
+        println!("{}", tr!("links-none"));
+    }
+    for (service, url) in links {
+        println!("{}: {}", service.name(), url);
+    }
+
+    Ok(())
+}
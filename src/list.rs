@@ -0,0 +1,638 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::collection;
+use crate::genre;
+use crate::i18n::tr;
+use crate::json;
+use crate::mood;
+use crate::sidecar;
+use crate::table::{self, Color, Table};
+
+pub enum CommandListError {
+    SQLite(rusqlite::Error),
+    InvalidYear(String),
+    InvalidEnergy(String),
+}
+impl From<rusqlite::Error> for CommandListError {
+    fn from(err: rusqlite::Error) -> CommandListError {
+        CommandListError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandListError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandListError::InvalidYear(value) => {
+                f.write_str(&tr!("list-invalid-year", value = value))
+            }
+            CommandListError::InvalidEnergy(value) => {
+                f.write_str(&tr!("list-invalid-energy", value = value))
+            }
+        }
+    }
+}
+
+/// Parses a year filter into an inclusive range of years.
+///
+/// Accepts a single year (`1994`), a decade (`1990s`) or a range (`1990-1995`).
+pub fn parse_year_filter(value: &str) -> Option<(i64, i64)> {
+    let value = value.trim();
+
+    if let Some(decade) = value.strip_suffix('s') {
+        let from: i64 = decade.parse().ok()?;
+        if from % 10 != 0 {
+            return None;
+        }
+        return Some((from, from + 9));
+    }
+
+    if let Some((from, to)) = value.split_once('-') {
+        let from: i64 = from.trim().parse().ok()?;
+        let to: i64 = to.trim().parse().ok()?;
+        if from > to {
+            return None;
+        }
+        return Some((from, to));
+    }
+
+    let year: i64 = value.parse().ok()?;
+    Some((year, year))
+}
+
+fn year_range(args: &clap::ArgMatches) -> Result<(Option<i64>, Option<i64>), CommandListError> {
+    match args.value_of("year") {
+        Some(value) => match parse_year_filter(value) {
+            Some((from, to)) => Ok((Some(from), Some(to))),
+            None => Err(CommandListError::InvalidYear(value.to_string())),
+        },
+        None => Ok((None, None)),
+    }
+}
+
+fn decade_label(decade: Option<i64>) -> String {
+    match decade {
+        Some(decade) => format!("{}s", decade),
+        None => "unknown".to_owned(),
+    }
+}
+
+/// Prints the number of rows per decade of `table`.
+fn print_decades(
+    db: &rusqlite::Connection,
+    table: &str,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandListError> {
+    let (from, to) = year_range(args)?;
+
+    let query = format!(
+        "
+        SELECT (release_year / 10) * 10 AS decade, COUNT(*)
+        FROM {table}
+        WHERE ($from IS NULL OR release_year BETWEEN $from AND $to)
+        GROUP BY decade
+        ORDER BY decade",
+        table = table,
+    );
+
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params![from, to])?;
+
+    let mut facets = Vec::new();
+    while let Some(row) = rows.next()? {
+        let decade: Option<i64> = row.get(0)?;
+        let count: i64 = row.get(1)?;
+
+        facets.push((decade, count));
+    }
+
+    if args.is_present("json") {
+        let values: Vec<String> = facets
+            .iter()
+            .map(|(decade, count)| {
+                json::object(&[
+                    ("decade", json::opt_number(*decade)),
+                    ("label", json::string(&decade_label(*decade))),
+                    ("count", count.to_string()),
+                ])
+            })
+            .collect();
+
+        println!("{}", json::array(&values));
+    } else {
+        for (decade, count) in facets {
+            println!("{}\t{}", decade_label(decade), count);
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_list_albums(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandListError> {
+    if args.is_present("by-decade") {
+        return print_decades(db, "album", args);
+    }
+
+    let (from, to) = year_range(args)?;
+    let release_type = args.value_of("type");
+    let sidecar = args.value_of("sidecar");
+    let no_sidecar = args.value_of("no-sidecar");
+
+    // The sidecars of an album are its own and those of its tracks
+    let query = format!(
+        "
+        WITH album_sidecar(album_id, kind) AS (
+          SELECT album_id, kind FROM sidecar WHERE album_id IS NOT NULL
+          UNION
+          SELECT track.album_id, sidecar.kind FROM sidecar JOIN track ON track.id = sidecar.track_id
+        )
+        SELECT album.id, album.name, artist.name, album.release_year, COUNT(track.id),
+               album.release_type, album.uid, album.artist_id,
+               (SELECT group_concat(kind) FROM album_sidecar WHERE album_id = album.id)
+        FROM album
+        LEFT JOIN artist ON artist.id = album.artist_id
+        LEFT JOIN track ON track.album_id = album.id
+        WHERE ($from IS NULL OR album.release_year BETWEEN $from AND $to)
+          AND ($type IS NULL OR album.release_type = $type)
+          AND ($sidecar IS NULL OR EXISTS (
+            SELECT 1 FROM album_sidecar WHERE album_id = album.id AND kind = $sidecar
+          ))
+          AND ($no_sidecar IS NULL OR NOT EXISTS (
+            SELECT 1 FROM album_sidecar WHERE album_id = album.id AND kind = $no_sidecar
+          ))
+          AND {collection_filter}
+        GROUP BY album.id
+        ORDER BY album.release_year, artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort",
+        collection_filter = collection::ALBUM_FILTER,
+    );
+
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params![
+        from,
+        to,
+        release_type,
+        sidecar,
+        no_sidecar,
+        args.value_of("collection")
+    ])?;
+
+    let json = args.is_present("json");
+    let mut values = Vec::new();
+    let mut table = (!json && table::is_enabled()).then(|| {
+        Table::default()
+            .number_column(tr!("list-year"))
+            .column(tr!("list-artist"), Color::Cyan)
+            .column(tr!("list-album"), Color::Green)
+            .column(tr!("list-type"), Color::Dim)
+            .number_column(tr!("list-tracks"))
+            .column(tr!("list-sidecars"), Color::Dim)
+    });
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let artist: Option<String> = row.get(2)?;
+        let year: Option<i64> = row.get(3)?;
+        let tracks: i64 = row.get(4)?;
+        let release_type: Option<String> = row.get(5)?;
+        let uid: Option<String> = row.get(6)?;
+        let artist_id: Option<i64> = row.get(7)?;
+        let sidecars: Option<String> = row.get(8)?;
+        // In the order of the kinds rather than the one of the files
+        let sidecars: Vec<&str> = sidecar::Kind::ALL
+            .iter()
+            .copied()
+            .filter(|kind| {
+                sidecars
+                    .as_deref()
+                    .is_some_and(|sidecars| sidecars.split(',').any(|found| found == *kind))
+            })
+            .collect();
+
+        if json {
+            values.push(json::object(&[
+                ("id", id.to_string()),
+                ("uid", json::opt_string(uid.as_deref())),
+                ("name", json::opt_string(name.as_deref())),
+                ("artist_id", json::opt_number(artist_id)),
+                ("artist", json::opt_string(artist.as_deref())),
+                ("year", json::opt_number(year)),
+                ("tracks", tracks.to_string()),
+                ("type", json::opt_string(release_type.as_deref())),
+                (
+                    "sidecars",
+                    json::array(
+                        &sidecars
+                            .iter()
+                            .map(|kind| json::string(kind))
+                            .collect::<Vec<_>>(),
+                    ),
+                ),
+            ]));
+        } else if let Some(table) = &mut table {
+            table.add_row(vec![
+                year.map_or("????".to_owned(), |year| year.to_string()),
+                artist.unwrap_or_default(),
+                name.unwrap_or_default(),
+                release_type.unwrap_or_default(),
+                tracks.to_string(),
+                sidecars.join(", "),
+            ]);
+        } else {
+            println!(
+                "{}\t{} - {} ({})",
+                year.map_or("????".to_owned(), |year| year.to_string()),
+                artist.unwrap_or_default(),
+                name.unwrap_or_default(),
+                tr!(
+                    "list-album-line",
+                    release_type = release_type.unwrap_or_else(|| tr!("list-unknown-type")),
+                    tracks = tracks
+                ),
+            );
+        }
+    }
+
+    if json {
+        println!("{}", json::array(&values));
+    } else if let Some(table) = table {
+        table.print();
+    }
+
+    Ok(())
+}
+
+fn cmd_list_tracks(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandListError> {
+    if args.is_present("by-decade") {
+        return print_decades(db, "track", args);
+    }
+
+    let (from, to) = year_range(args)?;
+    let label = args.value_of("label");
+    let mood = args.value_of("mood");
+    let (energy_from, energy_to) = match args.value_of("energy") {
+        Some(value) => match mood::parse_energy_filter(value) {
+            Some((from, to)) => (Some(from), Some(to)),
+            None => return Err(CommandListError::InvalidEnergy(value.to_string())),
+        },
+        None => (None, None),
+    };
+
+    let query = format!(
+        "
+        SELECT track.id, track.name, artist.name, album.name, track.number, track.release_year,
+               track.uid, track.artist_id, track.album_id, track.source, track.first_seen_at
+        FROM track
+        LEFT JOIN artist ON artist.id = track.artist_id
+        LEFT JOIN album ON album.id = track.album_id
+        WHERE ($from IS NULL OR track.release_year BETWEEN $from AND $to)
+          AND ($label IS NULL OR track.id IN (
+            SELECT track_label.track_id
+            FROM track_label
+            JOIN label ON label.id = track_label.label_id
+            WHERE label.name = $label
+          ))
+          AND {mood_filter}
+          AND {energy_filter}
+          AND ($source IS NULL OR track.source = $source)
+          AND {genre_filter}
+          AND {collection_filter}
+        ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort, track.number",
+        mood_filter = mood::MOOD_FILTER,
+        energy_filter = mood::ENERGY_FILTER,
+        genre_filter = genre::GENRE_FILTER,
+        collection_filter = collection::TRACK_FILTER,
+    );
+
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params![
+        from,
+        to,
+        label,
+        mood,
+        energy_from,
+        energy_to,
+        args.value_of("source"),
+        args.value_of("genre"),
+        args.value_of("collection")
+    ])?;
+
+    let json = args.is_present("json");
+    let mut values = Vec::new();
+    let mut table = (!json && table::is_enabled()).then(|| {
+        Table::default()
+            .column(tr!("list-artist"), Color::Cyan)
+            .column(tr!("list-album"), Color::Green)
+            .number_column(tr!("list-number"))
+            .column(tr!("list-title"), Color::Default)
+            .number_column(tr!("list-year"))
+    });
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let artist: Option<String> = row.get(2)?;
+        let album: Option<String> = row.get(3)?;
+        let number: Option<i64> = row.get(4)?;
+        let year: Option<i64> = row.get(5)?;
+        let uid: Option<String> = row.get(6)?;
+        let artist_id: Option<i64> = row.get(7)?;
+        let album_id: Option<i64> = row.get(8)?;
+        let source: Option<String> = row.get(9)?;
+        let first_seen_at: Option<i64> = row.get(10)?;
+
+        if json {
+            values.push(json::object(&[
+                ("id", id.to_string()),
+                ("uid", json::opt_string(uid.as_deref())),
+                ("name", json::opt_string(name.as_deref())),
+                ("artist_id", json::opt_number(artist_id)),
+                ("artist", json::opt_string(artist.as_deref())),
+                ("album_id", json::opt_number(album_id)),
+                ("album", json::opt_string(album.as_deref())),
+                ("number", json::opt_number(number)),
+                ("year", json::opt_number(year)),
+                ("source", json::opt_string(source.as_deref())),
+                ("first_seen_at", json::opt_number(first_seen_at)),
+            ]));
+        } else if let Some(table) = &mut table {
+            table.add_row(vec![
+                artist.unwrap_or_default(),
+                album.unwrap_or_default(),
+                number.map_or(String::new(), |number| number.to_string()),
+                name.unwrap_or_default(),
+                year.map_or(String::new(), |year| year.to_string()),
+            ]);
+        } else {
+            println!(
+                "{} - {} - {:02}. {}",
+                artist.unwrap_or_default(),
+                album.unwrap_or_default(),
+                number.unwrap_or(0),
+                name.unwrap_or_default(),
+            );
+        }
+    }
+
+    if json {
+        println!("{}", json::array(&values));
+    } else if let Some(table) = table {
+        table.print();
+    }
+
+    Ok(())
+}
+
+/// The genres of the library and their parents, with the number of tracks of each.
+struct GenreTree {
+    names: HashMap<String, String>,
+    counts: HashMap<String, i64>,
+    children: HashMap<String, Vec<String>>,
+    has_parent: HashSet<String>,
+}
+
+impl GenreTree {
+    fn load(db: &rusqlite::Connection) -> rusqlite::Result<GenreTree> {
+        let mut tree = GenreTree {
+            names: HashMap::new(),
+            counts: HashMap::new(),
+            children: HashMap::new(),
+            has_parent: HashSet::new(),
+        };
+
+        for (genre, parent) in genre::load_parents(db)? {
+            let (genre_key, parent_key) = (genre.to_lowercase(), parent.to_lowercase());
+            tree.names.entry(genre_key.clone()).or_insert(genre);
+            tree.names.entry(parent_key.clone()).or_insert(parent);
+            tree.children
+                .entry(parent_key)
+                .or_default()
+                .push(genre_key.clone());
+            tree.has_parent.insert(genre_key);
+        }
+
+        let mut stmt = db.prepare(
+            "SELECT genre, COUNT(*) FROM track
+             WHERE genre IS NOT NULL AND missing_since IS NULL
+             GROUP BY genre COLLATE NOCASE",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let genre: String = row.get(0)?;
+            let key = genre.to_lowercase();
+            // The spelling of the library wins over that of the hierarchy
+            tree.names.insert(key.clone(), genre);
+            tree.counts.insert(key, row.get(1)?);
+        }
+
+        Ok(tree)
+    }
+
+    /// Returns the number of tracks of the genre and its subgenres, each counted once.
+    fn total(&self, key: &str) -> i64 {
+        let mut seen = HashSet::new();
+        let mut stack = vec![key];
+        while let Some(key) = stack.pop() {
+            if seen.insert(key) {
+                stack.extend(
+                    self.children
+                        .get(key)
+                        .into_iter()
+                        .flatten()
+                        .map(String::as_str),
+                );
+            }
+        }
+
+        seen.iter().filter_map(|key| self.counts.get(*key)).sum()
+    }
+
+    /// Returns the genres with tracks among `keys`, the biggest first.
+    fn sorted<'a>(&'a self, keys: impl Iterator<Item = &'a String>) -> Vec<(&'a str, i64)> {
+        let mut genres: Vec<(&str, i64)> = keys
+            .map(|key| (key.as_str(), self.total(key)))
+            .filter(|(_, total)| *total > 0)
+            .collect();
+        genres.sort_by(|(a, a_total), (b, b_total)| b_total.cmp(a_total).then(a.cmp(b)));
+        genres
+    }
+
+    fn print(&self, key: &str, total: i64, path: &mut Vec<String>) {
+        let name = &self.names[key];
+        let count = self.counts.get(key).copied().unwrap_or(0);
+        let indent = "  ".repeat(path.len());
+        if count == total || count == 0 {
+            println!(
+                "{}{} ({})",
+                indent,
+                name,
+                tr!("list-genre-tracks", total = total)
+            );
+        } else {
+            println!(
+                "{}{} ({})",
+                indent,
+                name,
+                tr!("list-genre-tracks-partial", total = total, count = count)
+            );
+        }
+
+        path.push(key.to_owned());
+        for (child, total) in self.sorted(self.children.get(key).into_iter().flatten()) {
+            // A genre can't be its own subgenre, but the hierarchy is edited by hand
+            if !path.iter().any(|key| key == child) {
+                self.print(child, total, path);
+            }
+        }
+        path.pop();
+    }
+
+    fn to_json(&self, key: &str, total: i64, path: &mut Vec<String>) -> String {
+        path.push(key.to_owned());
+        let mut subgenres = Vec::new();
+        for (child, total) in self.sorted(self.children.get(key).into_iter().flatten()) {
+            if !path.iter().any(|key| key == child) {
+                subgenres.push(self.to_json(child, total, path));
+            }
+        }
+        path.pop();
+
+        json::object(&[
+            ("genre", json::string(&self.names[key])),
+            (
+                "tracks",
+                self.counts.get(key).copied().unwrap_or(0).to_string(),
+            ),
+            ("total", total.to_string()),
+            ("subgenres", json::array(&subgenres)),
+        ])
+    }
+}
+
+fn cmd_list_genres(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandListError> {
+    let json = args.is_present("json");
+
+    if args.is_present("tree") {
+        let tree = GenreTree::load(db)?;
+        let roots = tree.sorted(
+            tree.names
+                .keys()
+                .filter(|key| !tree.has_parent.contains(*key)),
+        );
+
+        if json {
+            let values: Vec<String> = roots
+                .into_iter()
+                .map(|(key, total)| tree.to_json(key, total, &mut Vec::new()))
+                .collect();
+            println!("{}", json::array(&values));
+        } else {
+            for (key, total) in roots {
+                tree.print(key, total, &mut Vec::new());
+            }
+        }
+
+        return Ok(());
+    }
+
+    let mut stmt = db.prepare(
+        "SELECT genre, COUNT(*) FROM track
+         WHERE genre IS NOT NULL AND missing_since IS NULL
+         GROUP BY genre COLLATE NOCASE
+         ORDER BY COUNT(*) DESC, genre",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut values = Vec::new();
+    let mut table = (!json && table::is_enabled()).then(|| {
+        Table::default()
+            .column(tr!("list-genre"), Color::Yellow)
+            .number_column(tr!("list-tracks"))
+    });
+    while let Some(row) = rows.next()? {
+        let genre: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+
+        if json {
+            values.push(json::object(&[
+                ("genre", json::string(&genre)),
+                ("tracks", count.to_string()),
+            ]));
+        } else if let Some(table) = &mut table {
+            table.add_row(vec![genre, count.to_string()]);
+        } else {
+            println!("{}\t{}", genre, count);
+        }
+    }
+
+    if json {
+        println!("{}", json::array(&values));
+    } else if let Some(table) = table {
+        table.print();
+    }
+
+    Ok(())
+}
+
+fn cmd_list_videos(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandListError> {
+    let mut stmt =
+        db.prepare("SELECT id, library_path(path), name, artist, year FROM video ORDER BY path")?;
+    let mut rows = stmt.query([])?;
+
+    let json = args.is_present("json");
+    let mut values = Vec::new();
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let path: String = row.get(1)?;
+        let name: Option<String> = row.get(2)?;
+        let artist: Option<String> = row.get(3)?;
+        let year: Option<String> = row.get(4)?;
+
+        if json {
+            values.push(json::object(&[
+                ("id", id.to_string()),
+                ("path", json::string(&path)),
+                ("name", json::opt_string(name.as_deref())),
+                ("artist", json::opt_string(artist.as_deref())),
+                ("year", json::opt_string(year.as_deref())),
+            ]));
+        } else {
+            match name {
+                Some(name) => println!("{} ({})", path, name),
+                None => println!("{}", path),
+            }
+        }
+    }
+
+    if json {
+        println!("{}", json::array(&values));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_list(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandListError> {
+    match args.subcommand() {
+        Some(("albums", sub_args)) => cmd_list_albums(db, sub_args),
+        Some(("tracks", sub_args)) => cmd_list_tracks(db, sub_args),
+        Some(("genres", sub_args)) => cmd_list_genres(db, sub_args),
+        Some(("videos", sub_args)) => cmd_list_videos(db, sub_args),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,375 @@
+//! Lyrics of a track, from a `.lrc` file next to it, from its tags or fetched from LRCLIB.
+//!
+//! All can hold synced lyrics in the LRC format, `[mm:ss.xx]` timestamps in front of each
+//! line; anything else is taken as plain, unsynced text.
+//!
+//! `zik lyrics fetch` looks up the tracks with neither on LRCLIB and stores what it finds in
+//! `track_lyrics`, tracks nothing was found for too so that they aren't looked up again
+//! without `--force`. With `--lrc`, synced lyrics are also written next to the track.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::http::{self, HttpError};
+use crate::i18n::tr;
+use crate::json;
+
+/// LRCLIB has no stated limit, a short pause keeps a whole library from hammering it.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far the duration of a result can be from the track's, in seconds, for it to be the
+/// same recording.
+const MAX_DURATION_DIFFERENCE: f64 = 3.0;
+
+pub enum CommandLyricsError {
+    SQLite(rusqlite::Error),
+    Http(HttpError),
+    IO(PathBuf, io::Error),
+}
+impl From<rusqlite::Error> for CommandLyricsError {
+    fn from(err: rusqlite::Error) -> CommandLyricsError {
+        CommandLyricsError::SQLite(err)
+    }
+}
+impl From<HttpError> for CommandLyricsError {
+    fn from(err: HttpError) -> CommandLyricsError {
+        CommandLyricsError::Http(err)
+    }
+}
+impl fmt::Display for CommandLyricsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandLyricsError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandLyricsError::Http(err) => write!(f, "{}", err),
+            CommandLyricsError::IO(path, err) => {
+                f.write_str(&tr!("lyrics-write", path = path.display(), err = err))
+            }
+        }
+    }
+}
+
+pub struct Line {
+    /// When the line starts, in milliseconds. None for unsynced lyrics.
+    pub start: Option<i64>,
+    pub text: String,
+}
+
+pub struct Lyrics {
+    pub lang: Option<String>,
+    pub lines: Vec<Line>,
+}
+
+impl Lyrics {
+    pub fn synced(&self) -> bool {
+        self.lines.iter().any(|line| line.start.is_some())
+    }
+
+    /// Returns the lyrics as text, without timestamps.
+    pub fn plain_text(&self) -> String {
+        let lines: Vec<&str> = self.lines.iter().map(|line| line.text.as_str()).collect();
+        lines.join("\n")
+    }
+}
+
+/// Parses a `mm:ss.xx` timestamp into milliseconds.
+fn parse_timestamp(value: &str) -> Option<i64> {
+    let (minutes, seconds) = value.split_once(':')?;
+    let minutes: i64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+
+    Some(minutes * 60_000 + (seconds * 1000.0).round() as i64)
+}
+
+/// Parses LRC lyrics. A line can have several timestamps when it's sung more than once.
+///
+/// Returns None if no line has a timestamp.
+fn parse_lrc(content: &str) -> Option<Lyrics> {
+    let mut lang = None;
+    let mut offset = 0;
+    let mut lines = Vec::new();
+
+    for raw in content.lines() {
+        let mut rest = raw.trim();
+        let mut starts = Vec::new();
+
+        while let Some(tag) = rest.strip_prefix('[') {
+            let (tag, after) = match tag.split_once(']') {
+                Some(split) => split,
+                None => break,
+            };
+            rest = after;
+
+            if let Some(start) = parse_timestamp(tag) {
+                starts.push(start);
+                continue;
+            }
+
+            match tag.split_once(':') {
+                Some(("la", value)) => lang = Some(value.trim().to_owned()),
+                Some(("offset", value)) => offset = value.trim().parse().unwrap_or(0),
+                _ => (),
+            }
+        }
+
+        for start in starts {
+            lines.push(Line {
+                start: Some(start),
+                text: rest.trim().to_owned(),
+            });
+        }
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    // A positive offset shows the lyrics sooner
+    for line in &mut lines {
+        line.start = line.start.map(|start| (start - offset).max(0));
+    }
+    lines.sort_by_key(|line| line.start);
+
+    Some(Lyrics { lang, lines })
+}
+
+fn parse(content: &str) -> Option<Lyrics> {
+    if let Some(lyrics) = parse_lrc(content) {
+        return Some(lyrics);
+    }
+
+    let content = content.trim();
+    if content.is_empty() {
+        return None;
+    }
+
+    let lines = content
+        .lines()
+        .map(|line| Line {
+            start: None,
+            text: line.trim_end().to_owned(),
+        })
+        .collect();
+
+    Some(Lyrics { lang: None, lines })
+}
+
+/// Returns the lyrics of the track at `path`, preferring a `.lrc` file to the `embedded`
+/// lyrics read at scan time.
+pub fn for_track(path: &Path, embedded: Option<&str>) -> Option<Lyrics> {
+    let sidecar = fs::read_to_string(path.with_extension("lrc")).ok();
+
+    sidecar
+        .as_deref()
+        .and_then(parse)
+        .or_else(|| embedded.and_then(parse))
+}
+
+/// Lyrics found on LRCLIB.
+struct Fetched {
+    plain: Option<String>,
+    synced: Option<String>,
+    instrumental: bool,
+}
+
+fn non_empty(value: Option<&json::Value>) -> Option<String> {
+    value
+        .and_then(json::Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_owned())
+}
+
+/// Searches LRCLIB for the lyrics of a track, preferring synced ones. Results whose
+/// duration is too far from `duration_ms` are of another recording, like a live one.
+fn fetch_lrclib(
+    artist: &str,
+    title: &str,
+    album: Option<&str>,
+    duration_ms: Option<i64>,
+) -> Result<Option<Fetched>, HttpError> {
+    let mut url = format!(
+        "https://lrclib.net/api/search?artist_name={}&track_name={}",
+        http::percent_encode(artist),
+        http::percent_encode(title),
+    );
+    if let Some(album) = album {
+        url.push_str("&album_name=");
+        url.push_str(&http::percent_encode(album));
+    }
+    let response = http::get_json(&url)?;
+
+    let mut results: Vec<Fetched> = response
+        .as_array()
+        .iter()
+        .filter(|result| match (result.get("duration"), duration_ms) {
+            (Some(json::Value::Number(duration)), Some(duration_ms)) => {
+                (duration - duration_ms as f64 / 1000.0).abs() <= MAX_DURATION_DIFFERENCE
+            }
+            _ => true,
+        })
+        .map(|result| Fetched {
+            plain: non_empty(result.get("plainLyrics")),
+            synced: non_empty(result.get("syncedLyrics")),
+            instrumental: matches!(result.get("instrumental"), Some(json::Value::Bool(true))),
+        })
+        .filter(|fetched| {
+            fetched.instrumental || fetched.plain.is_some() || fetched.synced.is_some()
+        })
+        .collect();
+
+    match results.iter().position(|fetched| fetched.synced.is_some()) {
+        Some(index) => Ok(Some(results.swap_remove(index))),
+        None => Ok(results.into_iter().next()),
+    }
+}
+
+fn save_fetched(
+    db: &rusqlite::Connection,
+    track_id: i64,
+    fetched: Option<&Fetched>,
+) -> rusqlite::Result<()> {
+    db.execute(
+        "INSERT INTO track_lyrics(track_id, found, plain, synced, instrumental)
+         VALUES($track_id, $found, $plain, $synced, $instrumental)
+         ON CONFLICT(track_id) DO UPDATE SET
+           found = excluded.found,
+           plain = excluded.plain,
+           synced = excluded.synced,
+           instrumental = excluded.instrumental,
+           fetched_at = unixepoch()",
+        rusqlite::params![
+            track_id,
+            fetched.is_some(),
+            fetched.and_then(|fetched| fetched.plain.as_deref()),
+            fetched.and_then(|fetched| fetched.synced.as_deref()),
+            fetched.is_some_and(|fetched| fetched.instrumental),
+        ],
+    )?;
+
+    Ok(())
+}
+
+//
+// "lyrics" command
+//
+
+struct TrackToFetch {
+    id: i64,
+    path: String,
+    title: String,
+    artist: String,
+    album: Option<String>,
+    duration_ms: Option<i64>,
+}
+
+fn cmd_lyrics_fetch(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLyricsError> {
+    let force = args.is_present("force");
+    let write_lrc = args.is_present("lrc");
+
+    // Tracks without a title or an artist can't be looked up
+    let mut tracks: Vec<TrackToFetch> = {
+        let mut stmt = db.prepare(
+            "SELECT track.id, library_path(track.path), track.name, artist.name, album.name,
+                    track.duration_ms
+             FROM track
+             JOIN artist ON artist.id = track.artist_id
+             LEFT JOIN album ON album.id = track.album_id
+             LEFT JOIN track_lyrics ON track_lyrics.track_id = track.id
+             WHERE track.lyrics IS NULL AND track.missing_since IS NULL
+               AND track.path IS NOT NULL AND track.name IS NOT NULL
+               AND artist.name <> 'Unknown'
+               AND ($force OR track_lyrics.track_id IS NULL)
+             ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                      track.disc_number, track.number",
+        )?;
+        let tracks = stmt
+            .query_map([force], |row| {
+                Ok(TrackToFetch {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                    title: row.get(2)?,
+                    artist: row.get(3)?,
+                    album: row.get(4)?,
+                    duration_ms: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        tracks
+    };
+
+    if let Some(only) = args.values_of("artist") {
+        let only: Vec<String> = only.map(|name| name.to_lowercase()).collect();
+        tracks.retain(|track| only.contains(&track.artist.to_lowercase()));
+    }
+    // A .lrc file next to the track is used over anything fetched
+    tracks.retain(|track| !Path::new(&track.path).with_extension("lrc").exists());
+
+    let mut found = 0;
+    for (i, track) in tracks.iter().enumerate() {
+        if i > 0 {
+            thread::sleep(REQUEST_INTERVAL);
+        }
+
+        let name = format!("{} - {}", track.artist, track.title);
+        let fetched = match fetch_lrclib(
+            &track.artist,
+            &track.title,
+            track.album.as_deref(),
+            track.duration_ms,
+        ) {
+            Ok(fetched) => fetched,
+            Err(err @ HttpError::NotFound) => return Err(err.into()),
+            Err(err) => {
+                println!("{}", tr!("lyrics-error", name = name, err = err));
+                continue;
+            }
+        };
+        save_fetched(db, track.id, fetched.as_ref())?;
+
+        let fetched = match fetched {
+            Some(fetched) => fetched,
+            None => {
+                println!("{}", tr!("lyrics-not-found", name = name));
+                continue;
+            }
+        };
+        found += 1;
+
+        match (&fetched.synced, fetched.instrumental) {
+            (Some(synced), _) => {
+                println!("{}", tr!("lyrics-synced", name = name));
+                if write_lrc {
+                    let path = Path::new(&track.path).with_extension("lrc");
+                    fs::write(&path, format!("{}\n", synced))
+                        .map_err(|err| CommandLyricsError::IO(path, err))?;
+                }
+            }
+            (None, true) => println!("{}", tr!("lyrics-instrumental", name = name)),
+            (None, false) => println!("{}", tr!("lyrics-plain", name = name)),
+        }
+    }
+
+    println!(
+        "{}",
+        tr!("lyrics-summary", found = found, tracks = tracks.len())
+    );
+
+    Ok(())
+}
+
+pub fn cmd_lyrics(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandLyricsError> {
+    match args.subcommand() {
+        Some(("fetch", sub_args)) => cmd_lyrics_fetch(db, sub_args),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,294 @@
+//! Finding the services of the local network with multicast DNS, like casting devices.
+//!
+//! A PTR query for each service type is sent to the mDNS group from an ephemeral port, which
+//! responders answer with unicast (RFC 6762 6.7), so this works next to an mDNS daemon holding
+//! port 5353. Responders send the SRV and TXT records of their instances along with the PTR
+//! ones, and the instance is reached at the address the answer came from.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+const GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+/// Asks for a unicast answer, in the class of a question.
+const UNICAST_RESPONSE: u16 = 0x8000;
+const CLASS_IN: u16 = 1;
+
+/// An instance of a service.
+#[derive(Debug, PartialEq)]
+pub struct Service {
+    /// The service type, like "_googlecast._tcp.local".
+    pub service: String,
+    /// The name of the instance, without the service type.
+    pub name: String,
+    pub address: SocketAddr,
+    pub txt: Vec<(String, String)>,
+}
+
+impl Service {
+    pub fn txt(&self, key: &str) -> Option<&str> {
+        self.txt
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+}
+
+/// Returns a query for the instances of `services`.
+fn query(services: &[&str]) -> Vec<u8> {
+    let mut buf = vec![0; 12];
+    buf[4..6].copy_from_slice(&(services.len() as u16).to_be_bytes());
+    for service in services {
+        write_name(&mut buf, service);
+        buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        buf.extend_from_slice(&(CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+    }
+    buf
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        packet.get(pos..pos + 2)?.try_into().unwrap(),
+    ))
+}
+
+/// Reads the name at `pos`, following compression pointers. Returns it with the position after
+/// it.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Pointers only go backwards in valid packets, this stops the others
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        if len & 0xc0 == 0xc0 {
+            end.get_or_insert(pos + 2);
+            pos = (read_u16(packet, pos)? & 0x3fff) as usize;
+        } else if len == 0 {
+            return Some((labels.join("."), end.unwrap_or(pos + 1)));
+        } else {
+            let label = packet.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+    }
+
+    None
+}
+
+/// The records of a response, by owner name in lowercase.
+#[derive(Default)]
+struct Records {
+    pointers: Vec<(String, String)>,
+    /// The port of an instance.
+    ports: HashMap<String, u16>,
+    txt: HashMap<String, Vec<(String, String)>>,
+}
+
+fn read_records(packet: &[u8]) -> Option<Records> {
+    let questions = read_u16(packet, 4)?;
+    let records = [6, 8, 10]
+        .iter()
+        .map(|&pos| read_u16(packet, pos).map(usize::from))
+        .sum::<Option<usize>>()?;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(packet, pos)?.1 + 4;
+    }
+
+    let mut result = Records::default();
+    for _ in 0..records {
+        let (owner, next) = read_name(packet, pos)?;
+        let owner = owner.to_lowercase();
+        let kind = read_u16(packet, next)?;
+        let len = read_u16(packet, next + 8)? as usize;
+        let data_pos = next + 10;
+        let data = packet.get(data_pos..data_pos + len)?;
+
+        match kind {
+            TYPE_PTR => {
+                let (target, _) = read_name(packet, data_pos)?;
+                result.pointers.push((owner, target));
+            }
+            TYPE_SRV => {
+                result.ports.insert(owner, read_u16(data, 4)?);
+            }
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+                let mut rest = data;
+                while let Some((&len, tail)) = rest.split_first() {
+                    // Keeps the entries before a broken one, the device is still there
+                    let entry = match tail.get(..len as usize) {
+                        Some(entry) => String::from_utf8_lossy(entry),
+                        None => break,
+                    };
+                    if let Some((key, value)) = entry.split_once('=') {
+                        entries.push((key.to_owned(), value.to_owned()));
+                    }
+                    rest = &tail[len as usize..];
+                }
+                result.txt.insert(owner, entries);
+            }
+            _ => {}
+        }
+        pos = data_pos + len;
+    }
+
+    Some(result)
+}
+
+/// Returns the instances of `services` a response from `from` announces.
+fn parse_response(packet: &[u8], from: IpAddr, services: &[&str]) -> Vec<Service> {
+    let records = match read_records(packet) {
+        Some(records) => records,
+        None => return Vec::new(),
+    };
+
+    let mut found = Vec::new();
+    for (owner, instance) in &records.pointers {
+        let service = match services
+            .iter()
+            .find(|service| service.eq_ignore_ascii_case(owner))
+        {
+            Some(service) => service,
+            None => continue,
+        };
+        let key = instance.to_lowercase();
+        let port = match records.ports.get(&key) {
+            Some(port) => *port,
+            None => continue,
+        };
+
+        let name = instance[..instance.len().saturating_sub(service.len() + 1)].to_owned();
+        found.push(Service {
+            service: service.to_string(),
+            name,
+            address: SocketAddr::new(from, port),
+            txt: records.txt.get(&key).cloned().unwrap_or_default(),
+        });
+    }
+
+    found
+}
+
+/// Returns the instances of `services` answering within `wait`, like "_googlecast._tcp.local".
+pub fn browse(services: &[&str], wait: Duration) -> io::Result<Vec<Service>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    let query = query(services);
+    socket.send_to(&query, GROUP)?;
+
+    let start = Instant::now();
+    let mut asked_again = false;
+    let mut found: Vec<Service> = Vec::new();
+    let mut buf = [0; 9000];
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= wait {
+            break;
+        }
+        // Sent twice, a lost datagram would hide a device
+        if !asked_again && elapsed >= wait / 2 {
+            socket.send_to(&query, GROUP)?;
+            asked_again = true;
+        }
+
+        socket.set_read_timeout(Some((wait - elapsed).min(wait / 4)))?;
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
+
+        for service in parse_response(&buf[..len], from.ip(), services) {
+            if !found.contains(&service) {
+                found.push(service);
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(buf: &mut Vec<u8>, owner: &[u8], kind: u16, data: &[u8]) {
+        buf.extend_from_slice(owner);
+        buf.extend_from_slice(&kind.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&120u32.to_be_bytes());
+        buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(data);
+    }
+
+    #[test]
+    fn parse_compressed_response() {
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 2];
+        // The service type at 12, the instance points to it
+        let mut ptr = Vec::new();
+        write_name(&mut ptr, "_googlecast._tcp.local");
+        let mut instance = vec![11];
+        instance.extend_from_slice(b"Chromecast1");
+        instance.extend_from_slice(&[0xc0, 12]);
+        record(&mut packet, &ptr, TYPE_PTR, &instance);
+
+        let instance_pos = packet.len() - instance.len();
+        let pointer = [0xc0, instance_pos as u8];
+        record(
+            &mut packet,
+            &pointer,
+            TYPE_SRV,
+            &[0, 0, 0, 0, 0x1f, 0x49, 0],
+        );
+        record(
+            &mut packet,
+            &pointer,
+            TYPE_TXT,
+            b"\x07id=1234\x0efn=Living Room\x03bad\x09md",
+        );
+
+        let from = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        let services = parse_response(&packet, from, &["_googlecast._tcp.local"]);
+        assert_eq!(
+            services,
+            vec![Service {
+                service: "_googlecast._tcp.local".to_owned(),
+                name: "Chromecast1".to_owned(),
+                address: SocketAddr::new(from, 8009),
+                txt: vec![
+                    ("id".to_owned(), "1234".to_owned()),
+                    ("fn".to_owned(), "Living Room".to_owned())
+                ],
+            }]
+        );
+        assert_eq!(services[0].txt("FN"), Some("Living Room"));
+
+        assert!(parse_response(&packet, from, &["_airplay._tcp.local"]).is_empty());
+        assert!(parse_response(&packet[..40], from, &["_googlecast._tcp.local"]).is_empty());
+    }
+
+    #[test]
+    fn read_name_loop() {
+        let packet = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc0, 12];
+        assert_eq!(read_name(&packet, 12), None);
+    }
+}
@@ -0,0 +1,234 @@
+//! The `/metrics` endpoint, in the Prometheus text format.
+//!
+//! Counters live in statics updated by the daemon and the server; everything about the
+//! library itself is read from the database when the endpoint is scraped. Scrapers
+//! authenticate like any other client, unless `metrics_public` is set.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::i18n::tr;
+use crate::server::{Request, Response};
+use crate::{pool, user};
+
+static SCANS: AtomicU64 = AtomicU64::new(0);
+static SCAN_FAILURES: AtomicU64 = AtomicU64::new(0);
+static SCAN_MILLIS_TOTAL: AtomicU64 = AtomicU64::new(0);
+static LAST_SCAN_MILLIS: AtomicU64 = AtomicU64::new(0);
+static LAST_SCAN_FILES: AtomicU64 = AtomicU64::new(0);
+static TAG_BYTES_READ: AtomicU64 = AtomicU64::new(0);
+
+/// Requests served, by route and status.
+static HTTP_REQUESTS: Mutex<Vec<(&'static str, u16, u64)>> = Mutex::new(Vec::new());
+
+/// Records a scan that took `duration` and indexed `files`, or failed if None.
+pub fn record_scan(duration: Duration, files: Option<usize>) {
+    let millis = duration.as_millis() as u64;
+
+    SCANS.fetch_add(1, Ordering::Relaxed);
+    SCAN_MILLIS_TOTAL.fetch_add(millis, Ordering::Relaxed);
+    LAST_SCAN_MILLIS.store(millis, Ordering::Relaxed);
+
+    match files {
+        Some(files) => LAST_SCAN_FILES.store(files as u64, Ordering::Relaxed),
+        None => {
+            SCAN_FAILURES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Records `bytes` read from an audio file to get its tags.
+pub fn record_tag_bytes(bytes: u64) {
+    TAG_BYTES_READ.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn record_request(route: &'static str, status: u16) {
+    let mut requests = match HTTP_REQUESTS.lock() {
+        Ok(requests) => requests,
+        Err(_) => return,
+    };
+
+    match requests
+        .iter_mut()
+        .find(|(r, s, _)| *r == route && *s == status)
+    {
+        Some((_, _, count)) => *count += 1,
+        None => requests.push((route, status, 1)),
+    }
+}
+
+fn seconds(millis: &AtomicU64) -> f64 {
+    millis.load(Ordering::Relaxed) as f64 / 1000.0
+}
+
+/// Writes the HELP and TYPE lines of a metric.
+fn describe(buf: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(buf, "# HELP {} {}", name, help);
+    let _ = writeln!(buf, "# TYPE {} {}", name, kind);
+}
+
+fn write_library(db: &rusqlite::Connection, buf: &mut String) -> rusqlite::Result<()> {
+    describe(buf, "zik_library_items", "gauge", "Items in the library.");
+    for kind in ["artist", "album", "track", "video"] {
+        let query = format!("SELECT COUNT(*) FROM {}", kind);
+        let count: i64 = db.query_row(&query, [], |row| row.get(0))?;
+        let _ = writeln!(buf, "zik_library_items{{kind=\"{}\"}} {}", kind, count);
+    }
+
+    describe(buf, "zik_jobs", "gauge", "Background jobs by state.");
+    let mut stmt = db.prepare("SELECT state, COUNT(*) FROM job GROUP BY state ORDER BY state")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let state: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        let _ = writeln!(buf, "zik_jobs{{state=\"{}\"}} {}", state, count);
+    }
+
+    describe(
+        buf,
+        "zik_database_size_bytes",
+        "gauge",
+        "Size of the database.",
+    );
+    let size: i64 = db.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get(0),
+    )?;
+    let _ = writeln!(buf, "zik_database_size_bytes {}", size);
+
+    Ok(())
+}
+
+fn render(db: &rusqlite::Connection) -> rusqlite::Result<String> {
+    let mut buf = String::new();
+
+    describe(
+        &mut buf,
+        "zik_scans_total",
+        "counter",
+        "Scans run since the daemon started.",
+    );
+    let _ = writeln!(buf, "zik_scans_total {}", SCANS.load(Ordering::Relaxed));
+    describe(
+        &mut buf,
+        "zik_scan_failures_total",
+        "counter",
+        "Scans that failed.",
+    );
+    let _ = writeln!(
+        buf,
+        "zik_scan_failures_total {}",
+        SCAN_FAILURES.load(Ordering::Relaxed)
+    );
+    describe(
+        &mut buf,
+        "zik_scan_duration_seconds_total",
+        "counter",
+        "Time spent scanning.",
+    );
+    let _ = writeln!(
+        buf,
+        "zik_scan_duration_seconds_total {}",
+        seconds(&SCAN_MILLIS_TOTAL)
+    );
+    describe(
+        &mut buf,
+        "zik_last_scan_duration_seconds",
+        "gauge",
+        "Duration of the last scan.",
+    );
+    let _ = writeln!(
+        buf,
+        "zik_last_scan_duration_seconds {}",
+        seconds(&LAST_SCAN_MILLIS)
+    );
+    describe(
+        &mut buf,
+        "zik_last_scan_files_indexed",
+        "gauge",
+        "Audio files indexed by the last successful scan.",
+    );
+    let _ = writeln!(
+        buf,
+        "zik_last_scan_files_indexed {}",
+        LAST_SCAN_FILES.load(Ordering::Relaxed)
+    );
+    describe(
+        &mut buf,
+        "zik_scan_tag_bytes_read_total",
+        "counter",
+        "Bytes read from audio files to get their tags.",
+    );
+    let _ = writeln!(
+        buf,
+        "zik_scan_tag_bytes_read_total {}",
+        TAG_BYTES_READ.load(Ordering::Relaxed)
+    );
+
+    describe(
+        &mut buf,
+        "zik_http_requests_total",
+        "counter",
+        "HTTP requests served.",
+    );
+    if let Ok(requests) = HTTP_REQUESTS.lock() {
+        for (route, status, count) in requests.iter() {
+            let _ = writeln!(
+                buf,
+                "zik_http_requests_total{{route=\"{}\",status=\"{}\"}} {}",
+                route, status, count
+            );
+        }
+    }
+
+    write_library(db, &mut buf)?;
+
+    Ok(buf)
+}
+
+/// Answers a request to `/metrics`.
+pub fn handle(request: &Request) -> Response {
+    let db = match pool::get() {
+        Ok(db) => db,
+        Err(err) => {
+            println!("{}", tr!("metrics-connection", err = err));
+            return pool::error_response(&err);
+        }
+    };
+
+    let public = match crate::get_config_bool(&db, "metrics_public") {
+        Ok(public) => public,
+        Err(err) => {
+            println!("{}", tr!("metrics-read", err = err));
+            return Response::text(500, "internal error");
+        }
+    };
+    if !public {
+        match user::authenticate(&db, request) {
+            Ok(_) => (),
+            Err(err @ (user::AuthError::SQLite(_) | user::AuthError::Key(_))) => {
+                println!("{}", tr!("metrics-auth", err = err));
+                return Response::text(500, "internal error");
+            }
+            Err(err) => {
+                return Response::text(401, &err.to_string())
+                    .header("WWW-Authenticate", "Basic realm=\"zik\"")
+            }
+        }
+    }
+
+    match render(&db) {
+        Ok(body) => Response::new(
+            200,
+            "text/plain; version=0.0.4; charset=utf-8",
+            body.into_bytes(),
+        ),
+        Err(err) => {
+            println!("{}", tr!("metrics-read", err = err));
+            Response::text(500, "internal error")
+        }
+    }
+}
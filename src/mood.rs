@@ -0,0 +1,193 @@
+//! Moods and energy of tracks, to pick music for the moment.
+//!
+//! Scans read the MOOD tag, TMOO in ID3, into `track_mood` and the ENERGY tag of Mixed In Key,
+//! from 1 to 10, into `track.energy`. Moods set with `zik mood set` replace the tagged ones and
+//! are kept by scans until `zik mood clear`.
+
+use std::fmt;
+
+use crate::i18n::tr;
+
+/// The condition on `$mood` of the queries filtering tracks by mood, none if it's NULL.
+pub const MOOD_FILTER: &str = "($mood IS NULL OR EXISTS (
+    SELECT 1 FROM track_mood WHERE track_mood.track_id = track.id AND track_mood.mood = $mood
+))";
+
+/// The condition on `$energy_from` and `$energy_to` of the queries filtering tracks by energy.
+pub const ENERGY_FILTER: &str =
+    "($energy_from IS NULL OR track.energy BETWEEN $energy_from AND $energy_to)";
+
+pub enum CommandMoodError {
+    SQLite(rusqlite::Error),
+    EmptyMood,
+    TrackNotFound(String),
+}
+impl From<rusqlite::Error> for CommandMoodError {
+    fn from(err: rusqlite::Error) -> CommandMoodError {
+        CommandMoodError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandMoodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandMoodError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandMoodError::EmptyMood => f.write_str(&tr!("mood-empty")),
+            CommandMoodError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+        }
+    }
+}
+
+/// Splits the values of MOOD tags, which some taggers write as a single "Chill; Happy" one.
+pub fn parse_moods(values: Vec<String>) -> Vec<String> {
+    values
+        .iter()
+        .flat_map(|value| value.split(';'))
+        .map(|mood| mood.trim().to_owned())
+        .filter(|mood| !mood.is_empty())
+        .collect()
+}
+
+/// Parses an ENERGY tag, from 1 to 10.
+pub fn parse_energy(value: &str) -> Option<i64> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|energy| (1..=10).contains(energy))
+}
+
+/// Parses an energy filter into an inclusive range: a level (`7`) or a range (`5-8`).
+pub fn parse_energy_filter(value: &str) -> Option<(i64, i64)> {
+    let (from, to) = match value.split_once('-') {
+        Some((from, to)) => (parse_energy(from)?, parse_energy(to)?),
+        None => {
+            let energy = parse_energy(value)?;
+            (energy, energy)
+        }
+    };
+
+    Some((from, to)).filter(|(from, to)| from <= to)
+}
+
+/// Replaces the tagged moods of a track, unless it has some set by hand.
+pub fn save_moods(
+    db: &rusqlite::Connection,
+    track_id: crate::TrackID,
+    moods: &[String],
+) -> rusqlite::Result<()> {
+    let manual: bool = db.query_row(
+        "SELECT EXISTS (SELECT 1 FROM track_mood WHERE track_id = $id AND manual)",
+        [track_id],
+        |row| row.get(0),
+    )?;
+    if manual {
+        return Ok(());
+    }
+
+    db.execute("DELETE FROM track_mood WHERE track_id = $id", [track_id])?;
+    for mood in moods {
+        db.execute(
+            "INSERT OR IGNORE INTO track_mood(track_id, mood) VALUES($track_id, $mood)",
+            rusqlite::params![track_id, mood],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn get_track_id(
+    db: &rusqlite::Connection,
+    value: &str,
+) -> Result<crate::TrackID, CommandMoodError> {
+    match crate::find_track_id(db, value)? {
+        Some(id) => Ok(id),
+        None => Err(CommandMoodError::TrackNotFound(value.to_owned())),
+    }
+}
+
+//
+// "mood" command
+//
+
+fn cmd_mood_set(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandMoodError> {
+    let track_id = get_track_id(db, args.value_of("track").unwrap())?;
+    let moods: Vec<&str> = args
+        .values_of("mood")
+        .unwrap_or_default()
+        .map(str::trim)
+        .collect();
+    if moods.iter().any(|mood| mood.is_empty()) {
+        return Err(CommandMoodError::EmptyMood);
+    }
+
+    let savepoint = db.savepoint()?;
+
+    savepoint.execute("DELETE FROM track_mood WHERE track_id = $id", [track_id])?;
+    for mood in moods {
+        savepoint.execute(
+            "INSERT OR IGNORE INTO track_mood(track_id, mood, manual) VALUES($track_id, $mood, 1)",
+            rusqlite::params![track_id, mood],
+        )?;
+    }
+
+    savepoint.commit()?;
+
+    Ok(())
+}
+
+fn cmd_mood_clear(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandMoodError> {
+    let track_id = get_track_id(db, args.value_of("track").unwrap())?;
+
+    let cleared = db.execute(
+        "DELETE FROM track_mood WHERE track_id = $id AND manual",
+        [track_id],
+    )?;
+    if cleared > 0 {
+        println!("{}", tr!("mood-cleared"));
+    }
+
+    Ok(())
+}
+
+fn cmd_mood_list(db: &mut rusqlite::Connection) -> Result<(), CommandMoodError> {
+    let mut stmt = db.prepare(
+        "SELECT mood, COUNT(*) FROM track_mood
+         GROUP BY mood
+         ORDER BY COUNT(*) DESC, mood COLLATE natural_sort",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut n = 0;
+    while let Some(row) = rows.next()? {
+        let mood: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        println!("{} ({})", mood, tr!("mood-tracks", count = count));
+        n += 1;
+    }
+
+    if n == 0 {
+        println!("{}", tr!("mood-none"));
+    }
+
+    Ok(())
+}
+
+pub fn cmd_mood(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandMoodError> {
+    match args.subcommand() {
+        Some(("set", sub_args)) => cmd_mood_set(db, sub_args),
+        Some(("clear", sub_args)) => cmd_mood_clear(db, sub_args),
+        Some(("list", _)) => cmd_mood_list(db),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,176 @@
+//! Detection of files moved or renamed since the last scan.
+//!
+//! A track whose file is gone but whose hash shows up again at a new path is the same track:
+//! its row is kept and only its path changes, so everything attached to it survives.
+//!
+//! Tracks not hashed yet are matched on their tags instead: the same artist, album and title
+//! with about the same duration, for files gone since the previous scan.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::hash;
+use crate::i18n::tr;
+use crate::{Metadata, TrackID};
+
+/// How far apart the durations of a track and of its moved file can be, since they're not
+/// always computed the same way.
+const DURATION_TOLERANCE_MS: i64 = 1000;
+
+/// The tags identifying a track without its hash, case-insensitively.
+#[derive(PartialEq, Eq, Hash)]
+struct TagsKey {
+    artist: String,
+    album: String,
+    title: String,
+}
+
+impl TagsKey {
+    fn new(artist: &str, album: &str, title: &str) -> TagsKey {
+        TagsKey {
+            artist: artist.trim().to_lowercase(),
+            album: album.trim().to_lowercase(),
+            title: title.trim().to_lowercase(),
+        }
+    }
+}
+
+/// The tracks whose file disappeared, by hash or by tags for the unhashed ones.
+pub struct MissingTracks {
+    by_hash: HashMap<String, TrackID>,
+    by_tags: HashMap<TagsKey, Vec<(TrackID, Option<i64>)>>,
+}
+
+impl MissingTracks {
+    /// Finds the tracks whose file no longer exists.
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<MissingTracks> {
+        let mut stmt = db.prepare(
+            "SELECT track.id, library_path(track.path), track.hash, track.missing_since IS NULL,
+                    artist.name, album.name, track.name, track.duration_ms
+             FROM track
+             LEFT JOIN artist ON artist.id = track.artist_id
+             LEFT JOIN album ON album.id = track.album_id
+             WHERE track.path IS NOT NULL",
+        )?;
+        let mut rows = stmt.query([])?;
+
+        let mut by_hash = HashMap::new();
+        let mut by_tags: HashMap<TagsKey, Vec<(TrackID, Option<i64>)>> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let id: TrackID = row.get(0)?;
+            let path: String = row.get(1)?;
+            let hash: Option<String> = row.get(2)?;
+            let present_last_scan: bool = row.get(3)?;
+
+            if Path::new(&path).exists() {
+                continue;
+            }
+
+            match hash {
+                Some(hash) => {
+                    by_hash.insert(hash, id);
+                }
+                None if present_last_scan => {
+                    let artist: Option<String> = row.get(4)?;
+                    let album: Option<String> = row.get(5)?;
+                    let title: Option<String> = row.get(6)?;
+                    let duration_ms: Option<i64> = row.get(7)?;
+
+                    if let (Some(artist), Some(album), Some(title)) = (artist, album, title) {
+                        by_tags
+                            .entry(TagsKey::new(&artist, &album, &title))
+                            .or_default()
+                            .push((id, duration_ms));
+                    }
+                }
+                None => {}
+            }
+        }
+
+        Ok(MissingTracks { by_hash, by_tags })
+    }
+
+    /// Returns the unhashed track with the same tags, if there's only one.
+    fn find_by_tags(&mut self, artist: &str, album: &str, md: &Metadata) -> Option<TrackID> {
+        let title = md.track_name.as_deref()?;
+        let candidates = self.by_tags.get_mut(&TagsKey::new(artist, album, title))?;
+
+        let same_duration = |duration_ms: Option<i64>| match (duration_ms, md.duration_ms) {
+            (Some(a), Some(b)) => (a - b).abs() <= DURATION_TOLERANCE_MS,
+            (None, None) => true,
+            _ => false,
+        };
+        let matching: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, duration_ms))| same_duration(*duration_ms))
+            .map(|(i, _)| i)
+            .collect();
+
+        // Several copies of a track can't be told apart
+        match matching.as_slice() {
+            [i] => Some(candidates.remove(*i).0),
+            _ => None,
+        }
+    }
+
+    /// Returns the track `path` was moved from, if any.
+    ///
+    /// Only files not already in the database are hashed, and only while some hashed tracks
+    /// are missing, so a scan without moves doesn't read any file content.
+    pub fn find_move(
+        &mut self,
+        db: &rusqlite::Connection,
+        path: &Path,
+        artist: &str,
+        album: &str,
+        md: &Metadata,
+    ) -> Result<Option<TrackID>, MoveError> {
+        if self.by_hash.is_empty() && self.by_tags.is_empty() {
+            return Ok(None);
+        }
+
+        let known: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM track WHERE path = $path)",
+            [crate::library::relative(path)],
+            |row| row.get(0),
+        )?;
+        if known {
+            return Ok(None);
+        }
+
+        if !self.by_hash.is_empty() {
+            let hash = hash::sha256_file(path)?;
+            if let Some(id) = self.by_hash.remove(&hash) {
+                return Ok(Some(id));
+            }
+        }
+
+        Ok(self.find_by_tags(artist, album, md))
+    }
+}
+
+pub enum MoveError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+}
+impl From<rusqlite::Error> for MoveError {
+    fn from(err: rusqlite::Error) -> MoveError {
+        MoveError::SQLite(err)
+    }
+}
+impl From<io::Error> for MoveError {
+    fn from(err: io::Error) -> MoveError {
+        MoveError::IO(err)
+    }
+}
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::SQLite(err) => write!(f, "{}", err),
+            MoveError::IO(err) => f.write_str(&tr!("moves-hash", err = err)),
+        }
+    }
+}
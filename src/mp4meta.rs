@@ -0,0 +1,275 @@
+//! The freeform `----` atoms of MP4 files, which mp4parse skips.
+//!
+//! Taggers store everything the standard iTunes atoms have no room for in them, each with a
+//! `mean` of "com.apple.iTunes" and a `name`: MusicBrainz IDs, ReplayGain, the label... They
+//! are under `moov/udta/meta/ilst`, or `moov/meta/ilst` for some taggers.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The mean of the atoms written by iTunes and every tagger following it.
+const ITUNES_MEAN: &str = "com.apple.iTunes";
+
+/// The largest freeform value read, the others are images or private blobs.
+const MAX_VALUE_SIZE: u64 = 64 * 1024;
+
+/// The freeform atoms of a file, by lowercase name.
+#[derive(Default)]
+pub struct FreeformTags {
+    values: HashMap<String, Vec<String>>,
+}
+
+impl FreeformTags {
+    /// Returns the values of the first of `names` present, ignoring case.
+    pub fn get_all(&self, names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .filter_map(|name| self.values.get(&name.to_lowercase()))
+            .find(|values| !values.is_empty())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the first value of the first of `names` present, ignoring case.
+    pub fn get(&self, names: &[&str]) -> Option<&str> {
+        names
+            .iter()
+            .filter_map(|name| self.values.get(&name.to_lowercase()))
+            .find_map(|values| values.first())
+            .map(String::as_str)
+    }
+}
+
+/// Reads the header of the atom at the current position if there's one before `end`, returning
+/// its type and the end of its content.
+fn read_header<R: Read + Seek>(reader: &mut R, end: u64) -> io::Result<Option<([u8; 4], u64)>> {
+    let position = reader.stream_position()?;
+    if position + 8 > end {
+        return Ok(None);
+    }
+
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    let kind = [header[4], header[5], header[6], header[7]];
+
+    let (header_size, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]])
+    {
+        // The last atom of the file
+        0 => (8, end - position),
+        1 => {
+            let mut large_size = [0; 8];
+            reader.read_exact(&mut large_size)?;
+            (16, u64::from_be_bytes(large_size))
+        }
+        size => (8, size as u64),
+    };
+    if size < header_size || position + size > end {
+        return Ok(None);
+    }
+
+    Ok(Some((kind, position + size)))
+}
+
+/// Finds the next atom of type `kind` before `end` and moves to its content, returning the end
+/// of it.
+fn find_atom<R: Read + Seek>(reader: &mut R, kind: &[u8; 4], end: u64) -> io::Result<Option<u64>> {
+    while let Some((atom, atom_end)) = read_header(reader, end)? {
+        if &atom == kind {
+            return Ok(Some(atom_end));
+        }
+        reader.seek(SeekFrom::Start(atom_end))?;
+    }
+
+    Ok(None)
+}
+
+/// Returns the `ftyp` and `moov` atoms of an MP4 file, all mp4parse and the freeform tags
+/// need, seeking over the others like the audio in `mdat`. None if the file doesn't have both,
+/// or if one is larger than `budget`.
+pub fn read_header_atoms<R: Read + Seek>(
+    reader: &mut R,
+    budget: u64,
+) -> io::Result<Option<Vec<u8>>> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut ftyp = None;
+    loop {
+        let start = reader.stream_position()?;
+        let (kind, end) = match read_header(reader, file_end)? {
+            Some(atom) => atom,
+            None => return Ok(None),
+        };
+        if &kind == b"ftyp" || &kind == b"moov" {
+            if end - start > budget {
+                return Ok(None);
+            }
+
+            let mut atom = vec![0; (end - start) as usize];
+            reader.seek(SeekFrom::Start(start))?;
+            reader.read_exact(&mut atom)?;
+
+            if &kind == b"ftyp" {
+                ftyp = Some(atom);
+            } else {
+                return Ok(ftyp.map(|mut atoms| {
+                    atoms.extend(atom);
+                    atoms
+                }));
+            }
+        }
+        reader.seek(SeekFrom::Start(end))?;
+    }
+}
+
+/// Moves to the content of the `ilst` atom, returning the end of it.
+fn find_ilst<R: Read + Seek>(reader: &mut R) -> io::Result<Option<u64>> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+
+    let moov_end = match find_atom(reader, b"moov", file_end)? {
+        Some(end) => end,
+        None => return Ok(None),
+    };
+    let moov_start = reader.stream_position()?;
+
+    for path in [&[b"udta", b"meta"][..], &[b"meta"][..]] {
+        reader.seek(SeekFrom::Start(moov_start))?;
+
+        let mut end = Some(moov_end);
+        for kind in path {
+            end = match end {
+                Some(end) => find_atom(reader, kind, end)?,
+                None => None,
+            };
+        }
+        let meta_end = match end {
+            Some(end) => end,
+            None => continue,
+        };
+
+        // The meta atom of MP4 files has a version and flags, the QuickTime one goes right to
+        // its hdlr atom
+        let mut peek = [0; 8];
+        reader.read_exact(&mut peek)?;
+        let skip = if &peek[4..] == b"hdlr" { -8 } else { -4 };
+        reader.seek(SeekFrom::Current(skip))?;
+
+        if let Some(ilst_end) = find_atom(reader, b"ilst", meta_end)? {
+            return Ok(Some(ilst_end));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads the content of the atom at the current position, up to `end`.
+fn read_content<R: Read + Seek>(reader: &mut R, end: u64) -> io::Result<Option<Vec<u8>>> {
+    let size = end - reader.stream_position()?;
+    if size > MAX_VALUE_SIZE {
+        return Ok(None);
+    }
+
+    let mut content = vec![0; size as usize];
+    reader.read_exact(&mut content)?;
+    Ok(Some(content))
+}
+
+/// Reads the freeform atoms of an MP4 file.
+pub fn read_freeform<R: Read + Seek>(reader: &mut R) -> io::Result<FreeformTags> {
+    let mut tags = FreeformTags::default();
+
+    let ilst_end = match find_ilst(reader)? {
+        Some(end) => end,
+        None => return Ok(tags),
+    };
+
+    while let Some((kind, atom_end)) = read_header(reader, ilst_end)? {
+        if &kind != b"----" {
+            reader.seek(SeekFrom::Start(atom_end))?;
+            continue;
+        }
+
+        let mut mean = None;
+        let mut name = None;
+        let mut values = Vec::new();
+        while let Some((child, child_end)) = read_header(reader, atom_end)? {
+            if let Some(content) = read_content(reader, child_end)? {
+                // mean and name have a version and flags first, data a type and a locale
+                let text = |offset: usize| {
+                    content
+                        .get(offset..)
+                        .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+                };
+                match &child {
+                    b"mean" => mean = text(4),
+                    b"name" => name = text(4),
+                    b"data" => values.extend(text(8).filter(|value| !value.is_empty())),
+                    _ => (),
+                }
+            }
+            reader.seek(SeekFrom::Start(child_end))?;
+        }
+
+        if let (Some(ITUNES_MEAN), Some(name)) = (mean.as_deref(), name) {
+            tags.values
+                .entry(name.to_lowercase())
+                .or_default()
+                .extend(values);
+        }
+        reader.seek(SeekFrom::Start(atom_end))?;
+    }
+
+    Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn read_header_atoms_before_mdat() {
+        let data = fixtures::mp4(1000, &[("\u{a9}nam", "Title")]);
+        let atoms = read_header_atoms(&mut io::Cursor::new(&data), 1 << 20)
+            .unwrap()
+            .unwrap();
+
+        // Everything but the empty mdat atom
+        assert_eq!(atoms, data[..data.len() - 8]);
+        assert!(read_header_atoms(&mut io::Cursor::new(&data), 64)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn read_header_atoms_after_mdat() {
+        let data = fixtures::mp4(1000, &[("\u{a9}nam", "Title")]);
+        let with_audio = fixtures::with_audio(data.clone(), 1 << 20);
+        let atoms = read_header_atoms(&mut io::Cursor::new(&with_audio), 1 << 20)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(atoms, data[..data.len() - 8]);
+    }
+
+    #[test]
+    fn read_freeform_atoms() {
+        let data = fixtures::mp4(
+            1000,
+            &[
+                ("\u{a9}nam", "Title"),
+                ("trkn", "2/9"),
+                ("----:com.apple.iTunes:LABEL", "Label"),
+                ("----:com.apple.iTunes:ARTISTS", "One"),
+                ("----:com.apple.iTunes:ARTISTS", "Two"),
+                ("----:org.example:LABEL", "Other"),
+            ],
+        );
+        let tags = read_freeform(&mut io::Cursor::new(data)).unwrap();
+
+        assert_eq!(tags.get(&["label"]), Some("Label"));
+        assert_eq!(tags.get_all(&["Artists"]), vec!["One", "Two"]);
+        assert_eq!(tags.get(&["missing", "LABEL"]), Some("Label"));
+    }
+}
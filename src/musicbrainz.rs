@@ -0,0 +1,234 @@
+//! Releases looked up on MusicBrainz, found by their tags, by the AcoustID fingerprint of
+//! one of their tracks or by the table of contents of a disc.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::http::{self, HttpError};
+use crate::json;
+
+/// MusicBrainz allows one request per second and blocks clients going faster.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub struct Track {
+    pub title: String,
+    pub artist: String,
+    pub number: usize,
+    pub disc: usize,
+    pub length_ms: Option<i64>,
+}
+
+pub struct Release {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub date: Option<String>,
+    /// The primary type of the release group, like "Album" or "EP".
+    pub release_type: Option<String>,
+    pub disc_total: usize,
+    /// The tracks of every disc, in order.
+    pub tracks: Vec<Track>,
+}
+
+impl Release {
+    pub fn url(&self) -> String {
+        format!("https://musicbrainz.org/release/{}", self.id)
+    }
+
+    pub fn year(&self) -> Option<&str> {
+        self.date
+            .as_deref()
+            .and_then(|date| date.get(..4))
+            .filter(|year| year.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    pub fn track_total(&self, disc: usize) -> usize {
+        self.tracks
+            .iter()
+            .filter(|track| track.disc == disc)
+            .count()
+    }
+}
+
+fn get_json(url: &str) -> Result<json::Value, HttpError> {
+    {
+        let mut last_request = LAST_REQUEST.lock().unwrap();
+        if let Some(elapsed) = last_request.map(|instant| instant.elapsed()) {
+            if elapsed < REQUEST_INTERVAL {
+                thread::sleep(REQUEST_INTERVAL - elapsed);
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    http::get_json(url)
+}
+
+/// Joins the names of an artist credit, like "Simon & Garfunkel".
+fn artist_credit(value: Option<&json::Value>) -> String {
+    let mut buf = String::new();
+    for credit in value.map(json::Value::as_array).unwrap_or_default() {
+        if let Some(name) = credit.get("name").and_then(json::Value::as_str) {
+            buf.push_str(name);
+        }
+        if let Some(join) = credit.get("joinphrase").and_then(json::Value::as_str) {
+            buf.push_str(join);
+        }
+    }
+    buf
+}
+
+/// Quotes a phrase for a search query.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Searches the releases of an album, the best matches first.
+pub fn search_releases(artist: &str, album: &str) -> Result<Vec<String>, HttpError> {
+    let mut query = format!("release:{}", quote(album));
+    if !artist.is_empty() {
+        query.push_str(&format!(" AND artist:{}", quote(artist)));
+    }
+
+    let url = format!(
+        "https://musicbrainz.org/ws/2/release/?query={}&limit=5&fmt=json",
+        http::percent_encode(&query)
+    );
+    let response = get_json(&url)?;
+
+    Ok(response
+        .get("releases")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|release| release.get("id").and_then(json::Value::as_str))
+        .map(|id| id.to_owned())
+        .collect())
+}
+
+/// Fetches a release with its tracks.
+pub fn lookup_release(id: &str) -> Result<Release, HttpError> {
+    let url = format!(
+        "https://musicbrainz.org/ws/2/release/{}?inc=recordings+artist-credits+release-groups&fmt=json",
+        http::percent_encode(id)
+    );
+    let release = get_json(&url)?;
+
+    let media = release
+        .get("media")
+        .map(json::Value::as_array)
+        .unwrap_or_default();
+
+    let mut tracks = Vec::new();
+    for (i, medium) in media.iter().enumerate() {
+        let disc = medium
+            .get("position")
+            .and_then(json::Value::as_i64)
+            .map_or(i + 1, |n| n as usize);
+
+        for (j, track) in medium
+            .get("tracks")
+            .map(json::Value::as_array)
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+        {
+            tracks.push(Track {
+                title: track
+                    .get("title")
+                    .and_then(json::Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                artist: artist_credit(track.get("artist-credit")),
+                number: track
+                    .get("position")
+                    .and_then(json::Value::as_i64)
+                    .map_or(j + 1, |n| n as usize),
+                disc,
+                length_ms: track.get("length").and_then(json::Value::as_i64),
+            });
+        }
+    }
+
+    Ok(Release {
+        id: id.to_owned(),
+        title: release
+            .get("title")
+            .and_then(json::Value::as_str)
+            .unwrap_or_default()
+            .to_owned(),
+        artist: artist_credit(release.get("artist-credit")),
+        date: release
+            .get("date")
+            .and_then(json::Value::as_str)
+            .filter(|date| !date.is_empty())
+            .map(|date| date.to_owned()),
+        release_type: release
+            .get("release-group")
+            .and_then(|group| group.get("primary-type"))
+            .and_then(json::Value::as_str)
+            .map(|value| value.to_owned()),
+        disc_total: media.len(),
+        tracks,
+    })
+}
+
+/// Finds the releases of a disc by its table of contents, formatted as MusicBrainz expects:
+/// the first and last track, the lead-out then the offset of each track, joined by `+`.
+pub fn disc_releases(toc: &str) -> Result<Vec<String>, HttpError> {
+    // The "-" disc ID only matches the TOC, which also finds releases whose disc ID isn't known.
+    // The TOC is only digits and pluses, which MusicBrainz wants as they are.
+    let url = format!(
+        "https://musicbrainz.org/ws/2/discid/-?toc={}&cdstubs=no&fmt=json",
+        toc
+    );
+    let response = get_json(&url)?;
+
+    Ok(response
+        .get("releases")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|release| release.get("id").and_then(json::Value::as_str))
+        .map(|id| id.to_owned())
+        .collect())
+}
+
+/// Finds the releases a recording with this fingerprint is on, through AcoustID.
+pub fn acoustid_releases(
+    api_key: &str,
+    fingerprint: &str,
+    duration_ms: i64,
+) -> Result<Vec<String>, HttpError> {
+    let url = format!(
+        "https://api.acoustid.org/v2/lookup?client={}&meta=releaseids&duration={}&fingerprint={}",
+        http::percent_encode(api_key),
+        duration_ms / 1000,
+        http::percent_encode(fingerprint)
+    );
+    let response = http::get_json(&url)?;
+
+    let mut ids: Vec<String> = Vec::new();
+    for result in response
+        .get("results")
+        .map(json::Value::as_array)
+        .unwrap_or_default()
+    {
+        for release in result
+            .get("releases")
+            .map(json::Value::as_array)
+            .unwrap_or_default()
+        {
+            if let Some(id) = release.get("id").and_then(json::Value::as_str) {
+                if !ids.iter().any(|known| known == id) {
+                    ids.push(id.to_owned());
+                }
+            }
+        }
+    }
+
+    Ok(ids)
+}
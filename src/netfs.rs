@@ -0,0 +1,181 @@
+//! Scanning libraries on network filesystems like SMB or NFS, where every round trip is slow
+//! and a server that goes away for a moment fails the calls in between.
+//!
+//! Reads can time out and are retried with a backoff when the error looks transient, and the
+//! directories are listed ahead in a thread while the files already listed are read.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::i18n::tr;
+
+const DEFAULT_RETRIES: usize = 2;
+const FIRST_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How many entries the listing thread can be ahead of the scan.
+const PREFETCH_ENTRIES: usize = 1024;
+
+/// Errors a network filesystem returns while the server is unreachable, as Linux errno values.
+const TRANSIENT_OS_ERRORS: [i32; 7] = [
+    5,   // EIO
+    11,  // EAGAIN
+    100, // ENETDOWN
+    101, // ENETUNREACH
+    112, // EHOSTDOWN
+    113, // EHOSTUNREACH
+    116, // ESTALE
+];
+
+pub struct IoOptions {
+    /// How long a read can take before it fails, none to wait forever.
+    pub timeout: Option<Duration>,
+    pub retries: usize,
+    /// Whether a file only changed when its size did, for filesystems with unreliable mtimes.
+    pub size_only: bool,
+}
+
+impl IoOptions {
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<IoOptions> {
+        Ok(IoOptions {
+            timeout: crate::get_config_usize(db, "io_timeout")?
+                .filter(|n| *n > 0)
+                .map(|n| Duration::from_secs(n as u64)),
+            retries: crate::get_config_usize(db, "io_retries")?.unwrap_or(DEFAULT_RETRIES),
+            size_only: crate::get_config_bool(db, "size_only_changes")?,
+        })
+    }
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::TimedOut
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected => true,
+        _ => err
+            .raw_os_error()
+            .is_some_and(|code| TRANSIENT_OS_ERRORS.contains(&code)),
+    }
+}
+
+/// Runs `op` on `path` in a thread, failing if it takes longer than `timeout`.
+///
+/// A call stuck on a dead server can't be interrupted, so its thread is left behind.
+fn with_timeout<T, F>(path: &Path, timeout: Duration, op: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Path) -> io::Result<T> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let owned_path = path.to_path_buf();
+    thread::spawn(move || {
+        let _ = sender.send(op(&owned_path));
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            tr!("netfs-timeout", seconds = timeout.as_secs()),
+        )),
+    }
+}
+
+/// Runs `op` on `path` with the timeout of `options`, retrying it after transient errors.
+pub fn read<T, F>(options: &IoOptions, path: &Path, op: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: Fn(&Path) -> io::Result<T> + Clone + Send + 'static,
+{
+    let mut backoff = FIRST_BACKOFF;
+    let mut attempt = 0;
+
+    loop {
+        let result = match options.timeout {
+            Some(timeout) => with_timeout(path, timeout, op.clone()),
+            None => op(path),
+        };
+
+        match result {
+            Err(err) if attempt < options.retries && is_transient(&err) => {
+                println!(
+                    "{}",
+                    tr!(
+                        "netfs-retry",
+                        path = path.display(),
+                        backoff = backoff.as_millis(),
+                        err = err
+                    )
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The entries of a walk, listed ahead in a thread.
+pub struct Prefetch {
+    receiver: mpsc::Receiver<walkdir::Result<walkdir::DirEntry>>,
+    timeout: Option<Duration>,
+    root: PathBuf,
+}
+
+impl Iterator for Prefetch {
+    type Item = io::Result<walkdir::DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match self.timeout {
+            Some(timeout) => match self.receiver.recv_timeout(timeout) {
+                Ok(result) => result,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Some(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        tr!(
+                            "netfs-list-timeout",
+                            path = self.root.display(),
+                            seconds = timeout.as_secs()
+                        ),
+                    )))
+                }
+            },
+            None => self.receiver.recv().ok()?,
+        };
+
+        Some(result.map_err(io::Error::from))
+    }
+}
+
+/// Walks `walker` in a thread, stat'ing the entries there, so listing the directories
+/// overlaps with reading the files.
+pub fn prefetch<I>(options: &IoOptions, root: &Path, walker: I) -> Prefetch
+where
+    I: Iterator<Item = walkdir::Result<walkdir::DirEntry>> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::sync_channel(PREFETCH_ENTRIES);
+    thread::spawn(move || {
+        for result in walker {
+            // Warms the attributes cache of the filesystem for the scan
+            if let Ok(entry) = &result {
+                let _ = entry.metadata();
+            }
+            if sender.send(result).is_err() {
+                break;
+            }
+        }
+    });
+
+    Prefetch {
+        receiver,
+        timeout: options.timeout,
+        root: root.to_path_buf(),
+    }
+}
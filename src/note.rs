@@ -0,0 +1,179 @@
+//! Notes written by the user on albums and tracks, like "vinyl rip, 24/96".
+//!
+//! Notes and the comment tags read at scan time are both indexed for full text search.
+
+use std::fmt;
+
+use crate::i18n::tr;
+
+pub enum CommandNoteError {
+    SQLite(rusqlite::Error),
+    EmptyNote,
+    AlbumNotFound(String),
+    TrackNotFound(String),
+}
+impl From<rusqlite::Error> for CommandNoteError {
+    fn from(err: rusqlite::Error) -> CommandNoteError {
+        CommandNoteError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandNoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandNoteError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandNoteError::EmptyNote => f.write_str(&tr!("note-empty")),
+            CommandNoteError::AlbumNotFound(value) => {
+                f.write_str(&tr!("album-id-not-found", value = value))
+            }
+            CommandNoteError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+        }
+    }
+}
+
+/// What a note is attached to.
+enum Target {
+    Album(crate::AlbumID),
+    Track(crate::TrackID),
+}
+
+impl Target {
+    fn column(&self) -> &'static str {
+        match self {
+            Target::Album(_) => "album_id",
+            Target::Track(_) => "track_id",
+        }
+    }
+
+    fn id(&self) -> usize {
+        match self {
+            Target::Album(id) | Target::Track(id) => *id,
+        }
+    }
+}
+
+fn get_target(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<Target, CommandNoteError> {
+    let value = args.value_of("target").unwrap();
+
+    if args.is_present("track") {
+        match crate::find_track_id(db, value)? {
+            Some(id) => Ok(Target::Track(id)),
+            None => Err(CommandNoteError::TrackNotFound(value.to_owned())),
+        }
+    } else {
+        match crate::find_album_id(db, value)? {
+            Some(id) => Ok(Target::Album(id)),
+            None => Err(CommandNoteError::AlbumNotFound(value.to_owned())),
+        }
+    }
+}
+
+//
+// "note" command
+//
+
+pub fn cmd_note(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandNoteError> {
+    let target = get_target(db, args)?;
+
+    if args.is_present("remove") {
+        let query = format!("DELETE FROM note WHERE {} = $id", target.column());
+        db.execute(&query, [target.id()])?;
+        return Ok(());
+    }
+
+    match args.value_of("text") {
+        Some(text) => {
+            let text = text.trim();
+            if text.is_empty() {
+                return Err(CommandNoteError::EmptyNote);
+            }
+
+            let query = format!(
+                "INSERT INTO note({column}, text) VALUES($id, $text)
+                 ON CONFLICT({column}) DO UPDATE SET text = excluded.text, updated_at = unixepoch()",
+                column = target.column(),
+            );
+            db.execute(&query, rusqlite::params![target.id(), text])?;
+        }
+        None => {
+            let query = format!("SELECT text FROM note WHERE {} = $id", target.column());
+            match db.query_row(&query, [target.id()], |row| row.get::<_, String>(0)) {
+                Ok(text) => println!("{}", text),
+                Err(rusqlite::Error::QueryReturnedNoRows) => println!("{}", tr!("note-none")),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//
+// "search" command
+//
+
+pub fn cmd_search(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandNoteError> {
+    let query = args.value_of("query").unwrap();
+
+    let sql = "
+        SELECT
+          CASE WHEN note.album_id IS NOT NULL THEN 'album' ELSE 'track' END,
+          coalesce(album.name, library_path(track.path), track.name),
+          note.text
+        FROM note_fts
+        JOIN note ON note.id = note_fts.rowid
+        LEFT JOIN album ON album.id = note.album_id
+        LEFT JOIN track ON track.id = note.track_id
+        WHERE note_fts MATCH $query
+        ORDER BY note_fts.rank";
+
+    let mut stmt = db.prepare(sql)?;
+    let mut rows = stmt.query([query])?;
+    while let Some(row) = rows.next()? {
+        let kind: String = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let text: String = row.get(2)?;
+
+        let id = if kind == "album" {
+            "note-on-album"
+        } else {
+            "note-on-track"
+        };
+        println!("{}", tr!(id, name = name.unwrap_or_default(), text = text));
+    }
+
+    let sql = "
+        SELECT coalesce(library_path(track.path), track.name), track.comment
+        FROM track_comment_fts
+        JOIN track ON track.id = track_comment_fts.rowid
+        WHERE track_comment_fts MATCH $query
+        ORDER BY track_comment_fts.rank";
+
+    let mut stmt = db.prepare(sql)?;
+    let mut rows = stmt.query([query])?;
+    while let Some(row) = rows.next()? {
+        let name: Option<String> = row.get(0)?;
+        let comment: String = row.get(1)?;
+
+        println!(
+            "{}",
+            tr!(
+                "note-comment",
+                name = name.unwrap_or_default(),
+                comment = comment
+            )
+        );
+    }
+
+    Ok(())
+}
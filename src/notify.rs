@@ -0,0 +1,109 @@
+//! Notifications sent when a scan changed the library.
+//!
+//! Each target is enabled by setting its URL: `notify_webhook` receives the summary as JSON,
+//! `notify_ntfy` is an ntfy topic like https://ntfy.sh/my-music and `notify_discord` a
+//! Discord webhook. A scan that changed nothing notifies no one.
+
+use crate::http;
+use crate::i18n::tr;
+use crate::json;
+use crate::ScanSummary;
+
+/// How many new albums are listed in a message, the webhook gets all of them.
+const MAX_ALBUMS: usize = 10;
+
+fn message(summary: &ScanSummary) -> String {
+    let counts: Vec<String> = [
+        ("added", summary.added),
+        ("updated", summary.updated),
+        ("removed", summary.removed),
+        ("moved", summary.moved),
+    ]
+    .into_iter()
+    .filter(|(_, n)| *n > 0)
+    .map(|(what, n)| format!("{} tracks {}", n, what))
+    .collect();
+
+    let mut buf = counts.join(", ");
+    if !summary.new_albums.is_empty() {
+        buf.push_str("\n\nNew albums:");
+        for album in summary.new_albums.iter().take(MAX_ALBUMS) {
+            buf.push_str("\n- ");
+            buf.push_str(album);
+        }
+        if summary.new_albums.len() > MAX_ALBUMS {
+            buf.push_str(&format!(
+                "\nand {} more",
+                summary.new_albums.len() - MAX_ALBUMS
+            ));
+        }
+    }
+    buf
+}
+
+fn webhook_payload(summary: &ScanSummary, message: &str) -> String {
+    let albums: Vec<String> = summary
+        .new_albums
+        .iter()
+        .map(|album| json::string(album))
+        .collect();
+
+    json::object(&[
+        ("event", json::string("scan")),
+        ("indexed", summary.indexed.to_string()),
+        ("added", summary.added.to_string()),
+        ("updated", summary.updated.to_string()),
+        ("removed", summary.removed.to_string()),
+        ("moved", summary.moved.to_string()),
+        ("new_albums", json::array(&albums)),
+        ("message", json::string(message)),
+    ])
+}
+
+fn send(target: &str, url: &str, summary: &ScanSummary, message: &str) {
+    let result = match target {
+        "notify_webhook" => http::post(
+            url,
+            "application/json",
+            &[],
+            &webhook_payload(summary, message),
+        ),
+        "notify_ntfy" => http::post(
+            url,
+            "text/plain; charset=utf-8",
+            &[("Title", "zik library updated"), ("Tags", "musical_note")],
+            message,
+        ),
+        _ => http::post(
+            url,
+            "application/json",
+            &[],
+            &json::object(&[(
+                "content",
+                json::string(&format!("**zik library updated**\n{}", message)),
+            )]),
+        ),
+    };
+
+    if let Err(err) = result {
+        println!("{}", tr!("notify-failed", url = url, err = err));
+    }
+}
+
+/// Notifies the configured targets of what a scan changed.
+///
+/// Failures are only printed, the scan itself went fine.
+pub fn scan_completed(db: &rusqlite::Connection, summary: &ScanSummary) {
+    if !summary.has_changes() {
+        return;
+    }
+    let message = message(summary);
+
+    for target in ["notify_webhook", "notify_ntfy", "notify_discord"] {
+        match crate::get_config_value(db, target) {
+            Ok(Some(url)) if !url.is_empty() => send(target, &url, summary, &message),
+            Ok(_) => (),
+            Err(err) => println!("{}", tr!("notify-read", target = target, err = err)),
+        }
+    }
+}
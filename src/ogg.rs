@@ -0,0 +1,298 @@
+//! The Vorbis comments of Ogg Vorbis and Opus files.
+//!
+//! An Ogg file is a sequence of pages carrying the packets of its streams. The comments are
+//! the second packet of the first stream, right after its identification header, so only the
+//! first pages are read. The duration is the granule position of the last page of the stream,
+//! in samples, found in the end of the file.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::tags::{CustomKey, Field, TagReader};
+
+/// The most a comment packet is read of, cover art included.
+const MAX_PACKET: usize = 16 * 1024 * 1024;
+
+/// How much of the end of the file the last page is looked for in, a page is at most 64KiB.
+const TAIL: u64 = 65307;
+
+pub struct OggTag {
+    comments: Vec<(String, String)>,
+    duration_ms: Option<i64>,
+}
+
+struct Page {
+    serial: u32,
+    segments: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// Reads the next page, none at the end of the file or if it's not a page.
+fn read_page<R: Read>(reader: &mut R) -> io::Result<Option<Page>> {
+    let mut header = [0; 27];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    if &header[..4] != b"OggS" || header[4] != 0 {
+        return Ok(None);
+    }
+
+    let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+    let mut segments = vec![0; header[26] as usize];
+    reader.read_exact(&mut segments)?;
+    let mut data = vec![0; segments.iter().map(|&len| len as usize).sum()];
+    reader.read_exact(&mut data)?;
+
+    Ok(Some(Page {
+        serial,
+        segments,
+        data,
+    }))
+}
+
+/// The first two packets of the first stream, its headers.
+struct Headers {
+    serial: u32,
+    identification: Vec<u8>,
+    comments: Vec<u8>,
+}
+
+/// Returns the headers of the first stream.
+fn read_headers<R: Read>(reader: &mut R) -> io::Result<Option<Headers>> {
+    let mut serial = None;
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+
+    while packets.len() < 2 {
+        let page = match read_page(reader)? {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+        // Pages of other streams are interleaved with the ones of the first
+        if *serial.get_or_insert(page.serial) != page.serial {
+            continue;
+        }
+
+        // A packet ends with a segment shorter than 255 bytes
+        let mut offset = 0;
+        for &len in &page.segments {
+            packet.extend_from_slice(&page.data[offset..offset + len as usize]);
+            offset += len as usize;
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        if packet.len() > MAX_PACKET {
+            return Ok(None);
+        }
+    }
+
+    let comments = packets.swap_remove(1);
+    let identification = packets.swap_remove(0);
+    Ok(serial.map(|serial| Headers {
+        serial,
+        identification,
+        comments,
+    }))
+}
+
+/// Returns the granule position of the last page of the stream `serial`.
+fn read_last_granule<R: Read + Seek>(reader: &mut R, serial: u32) -> io::Result<Option<i64>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    let start = len.saturating_sub(TAIL);
+    reader.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    reader.take(len - start).read_to_end(&mut tail)?;
+
+    let mut end = tail.len();
+    while let Some(offset) = tail[..end].windows(4).rposition(|magic| magic == b"OggS") {
+        let header = &tail[offset..];
+        if header.len() >= 18 && u32::from_le_bytes(header[14..18].try_into().unwrap()) == serial {
+            let granule = i64::from_le_bytes(header[6..14].try_into().unwrap());
+            // -1 is for pages where no packet ends
+            if granule >= 0 {
+                return Ok(Some(granule));
+            }
+        }
+        end = offset;
+    }
+
+    Ok(None)
+}
+
+/// Parses the comment header after its magic: a vendor string, then `KEY=value` comments.
+fn parse_comments(mut data: &[u8]) -> Option<Vec<(String, String)>> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if data.len() < len {
+            return None;
+        }
+        let (head, tail) = data.split_at(len);
+        *data = tail;
+        Some(head)
+    }
+    fn take_u32(data: &mut &[u8]) -> Option<usize> {
+        let bytes = take(data, 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    }
+
+    let vendor_len = take_u32(&mut data)?;
+    take(&mut data, vendor_len)?;
+
+    let count = take_u32(&mut data)?;
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let len = take_u32(&mut data)?;
+        let comment = String::from_utf8_lossy(take(&mut data, len)?);
+        if let Some((key, value)) = comment.split_once('=') {
+            comments.push((key.to_uppercase(), value.to_owned()));
+        }
+    }
+
+    Some(comments)
+}
+
+/// Reads the comments and duration of an Ogg Vorbis or Opus file, none if it's neither.
+pub fn read<R: Read + Seek>(reader: &mut R) -> io::Result<Option<OggTag>> {
+    // Truncated files are no Ogg files, like for the other formats
+    let Headers {
+        serial,
+        identification,
+        comments,
+    } = match read_headers(reader) {
+        Ok(Some(headers)) => headers,
+        Ok(None) => return Ok(None),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    // Vorbis granules are in samples at the sample rate, Opus ones always at 48kHz after the
+    // pre-skip
+    let (comments, sample_rate, pre_skip) = if identification.starts_with(b"\x01vorbis")
+        && identification.len() >= 16
+        && comments.starts_with(b"\x03vorbis")
+    {
+        let sample_rate = u32::from_le_bytes(identification[12..16].try_into().unwrap());
+        (&comments[7..], sample_rate as i64, 0)
+    } else if identification.starts_with(b"OpusHead")
+        && identification.len() >= 12
+        && comments.starts_with(b"OpusTags")
+    {
+        let pre_skip = u16::from_le_bytes(identification[10..12].try_into().unwrap());
+        (&comments[8..], 48000, pre_skip as i64)
+    } else {
+        return Ok(None);
+    };
+
+    let comments = match parse_comments(comments) {
+        Some(comments) => comments,
+        None => return Ok(None),
+    };
+    let duration_ms = match read_last_granule(reader, serial)? {
+        Some(granule) if sample_rate > 0 => Some((granule - pre_skip).max(0) * 1000 / sample_rate),
+        _ => None,
+    };
+
+    Ok(Some(OggTag {
+        comments,
+        duration_ms,
+    }))
+}
+
+impl OggTag {
+    fn values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.comments
+            .iter()
+            .filter(move |(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl TagReader for OggTag {
+    /// Returns every value of the first of the field's keys with a value, like FLAC.
+    fn get_all(&self, field: Field) -> Vec<String> {
+        for key in crate::vorbis::keys(field) {
+            let values: Vec<String> = self
+                .values(key)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_owned())
+                .collect();
+            if !values.is_empty() {
+                return values;
+            }
+        }
+
+        Vec::new()
+    }
+
+    fn get_custom(&self, key: &CustomKey) -> Vec<String> {
+        let key = match key {
+            CustomKey::Any(key) | CustomKey::Vorbis(key) => key.to_uppercase(),
+            _ => return Vec::new(),
+        };
+        self.values(&key).map(|value| value.to_owned()).collect()
+    }
+
+    fn duration_ms(&self) -> Option<i64> {
+        self.duration_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn read_vorbis() {
+        let data = fixtures::ogg(
+            182_000,
+            &[
+                ("TITLE", "Fourth"),
+                ("artist", "Ogg Artist"),
+                ("TRACKNUMBER", "4"),
+                ("GENRE", "Rock"),
+                ("GENRE", "Jazz"),
+            ],
+        );
+        let tag = read(&mut io::Cursor::new(data)).unwrap().unwrap();
+
+        assert_eq!(tag.get(Field::Title).as_deref(), Some("Fourth"));
+        assert_eq!(tag.get(Field::Artist).as_deref(), Some("Ogg Artist"));
+        assert_eq!(tag.get(Field::TrackNumber).as_deref(), Some("4"));
+        assert_eq!(tag.get_all(Field::Genre), vec!["Rock", "Jazz"]);
+        assert_eq!(tag.duration_ms(), Some(182_000));
+    }
+
+    #[test]
+    fn read_long_comments() {
+        // Spans several pages, with a segment of exactly 255 bytes
+        let lyrics = "la ".repeat(40_000);
+        let data = fixtures::ogg(1000, &[("LYRICS", &lyrics), ("TITLE", "Long")]);
+        let tag = read(&mut io::Cursor::new(data)).unwrap().unwrap();
+
+        assert_eq!(tag.get(Field::Lyrics).as_deref(), Some(lyrics.trim()));
+        assert_eq!(tag.get(Field::Title).as_deref(), Some("Long"));
+        assert_eq!(tag.duration_ms(), Some(1000));
+    }
+
+    #[test]
+    fn read_with_audio() {
+        let data = fixtures::with_audio(fixtures::ogg(182_000, &[("TITLE", "Long")]), 1 << 20);
+        let tag = read(&mut io::Cursor::new(data)).unwrap().unwrap();
+
+        assert_eq!(tag.get(Field::Title).as_deref(), Some("Long"));
+        assert_eq!(tag.duration_ms(), Some(182_000));
+    }
+
+    #[test]
+    fn read_other_formats() {
+        let flac = fixtures::flac(1000, &[("TITLE", "Flac")]);
+        assert!(read(&mut io::Cursor::new(flac)).unwrap().is_none());
+
+        let mut truncated = fixtures::ogg(1000, &[("TITLE", "Cut")]);
+        truncated.truncate(40);
+        assert!(read(&mut io::Cursor::new(truncated)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,378 @@
+//! `zik pick`, an fzf-like fuzzy picker over the tracks or albums of the library, printing the
+//! ids of those picked, or their paths with `--paths`, so that it composes with other commands:
+//! `zik play $(zik pick tracks)`.
+//!
+//! The picker draws on the terminal itself, `/dev/tty`, leaving stdout to the result; the
+//! terminal is put in raw mode with `stty`. Typing filters, Up and Down or Ctrl-P and Ctrl-N
+//! move, Tab marks several items, Enter picks and Escape or Ctrl-C cancels. With `--filter`,
+//! the matches of a query are printed without asking anything, best first.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use crate::i18n::tr;
+
+pub enum CommandPickError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    NoTerminal(io::Error),
+}
+impl From<rusqlite::Error> for CommandPickError {
+    fn from(err: rusqlite::Error) -> CommandPickError {
+        CommandPickError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandPickError {
+    fn from(err: io::Error) -> CommandPickError {
+        CommandPickError::IO(err)
+    }
+}
+impl fmt::Display for CommandPickError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandPickError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandPickError::IO(err) => f.write_str(&tr!("pick-io", err = err)),
+            CommandPickError::NoTerminal(err) => f.write_str(&tr!("pick-no-terminal", err = err)),
+        }
+    }
+}
+
+/// Something to pick, shown as `label`.
+struct Item {
+    label: String,
+    id: i64,
+    paths: Vec<String>,
+}
+
+fn load_tracks(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Item>> {
+    let mut stmt = db.prepare(
+        "SELECT track.id, artist.name, album.name, track.number, track.name,
+                library_path(track.path)
+         FROM track
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         WHERE track.missing_since IS NULL
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                  track.disc_number, track.number",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let artist: Option<String> = row.get(1)?;
+        let album: Option<String> = row.get(2)?;
+        let number: Option<i64> = row.get(3)?;
+        let name: Option<String> = row.get(4)?;
+        Ok(Item {
+            label: format!(
+                "{} - {} - {:02}. {}",
+                artist.unwrap_or_default(),
+                album.unwrap_or_default(),
+                number.unwrap_or(0),
+                name.unwrap_or_default()
+            ),
+            id: row.get(0)?,
+            paths: row.get::<_, Option<String>>(5)?.into_iter().collect(),
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn load_albums(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Item>> {
+    let mut stmt = db.prepare(
+        "SELECT album.id, artist.name, album.name, album.release_year,
+                (SELECT group_concat(path, char(10)) FROM (
+                   SELECT library_path(track.path) AS path FROM track
+                   WHERE track.album_id = album.id AND track.missing_since IS NULL
+                     AND track.path IS NOT NULL
+                   ORDER BY track.disc_number, track.number
+                 ))
+         FROM album
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE EXISTS (
+           SELECT 1 FROM track WHERE track.album_id = album.id AND track.missing_since IS NULL
+         )
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let artist: Option<String> = row.get(1)?;
+        let album: Option<String> = row.get(2)?;
+        let year: Option<i64> = row.get(3)?;
+        let paths: Option<String> = row.get(4)?;
+        Ok(Item {
+            label: match year {
+                Some(year) => format!(
+                    "{} - {} ({})",
+                    artist.unwrap_or_default(),
+                    album.unwrap_or_default(),
+                    year
+                ),
+                None => format!(
+                    "{} - {}",
+                    artist.unwrap_or_default(),
+                    album.unwrap_or_default()
+                ),
+            },
+            id: row.get(0)?,
+            paths: paths
+                .map(|paths| paths.lines().map(str::to_owned).collect())
+                .unwrap_or_default(),
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Scores how well `label` matches `query`, every word of which has to be found in it, its
+/// characters in order; none if it doesn't match. Consecutive characters and those starting
+/// a word score higher.
+fn score(query: &str, label: &str) -> Option<i64> {
+    let label: Vec<char> = label.to_lowercase().chars().collect();
+
+    let mut total = 0;
+    for word in query.to_lowercase().split_whitespace() {
+        let mut position = 0;
+        let mut previous: Option<usize> = None;
+        for c in word.chars() {
+            let found = position + label[position..].iter().position(|l| *l == c)?;
+            total += 1;
+            if previous.is_some_and(|previous| previous + 1 == found) {
+                total += 4;
+            }
+            if found == 0 || !label[found - 1].is_alphanumeric() {
+                total += 2;
+            }
+            previous = Some(found);
+            position = found + 1;
+        }
+    }
+
+    Some(total)
+}
+
+/// Returns the indexes of the items matching `query`, best first.
+fn matches(items: &[Item], query: &str) -> Vec<usize> {
+    let mut matches: Vec<(usize, i64)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| Some((i, score(query, &item.label)?)))
+        .collect();
+    // Stable, items scoring the same stay in library order
+    matches.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    matches.into_iter().map(|(i, _)| i).collect()
+}
+
+/// The terminal in raw mode, set back as it was when dropped.
+struct Terminal {
+    tty: File,
+    saved: String,
+    rows: usize,
+    columns: usize,
+}
+
+fn stty(tty: &File, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(tty.try_clone()?))
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!("stty {} failed", args.join(" "))));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let tty = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")?;
+
+        let saved = stty(&tty, &["-g"])?;
+        // Terminals that don't know their size say 0
+        let size = stty(&tty, &["size"])?;
+        let (rows, columns) = size.split_once(' ').unwrap_or_default();
+        let rows = rows.parse().ok().filter(|rows| *rows > 0).unwrap_or(24);
+        let columns = columns
+            .parse()
+            .ok()
+            .filter(|columns| *columns > 0)
+            .unwrap_or(80);
+        stty(&tty, &["raw", "-echo"])?;
+
+        let mut terminal = Terminal {
+            tty,
+            saved,
+            rows,
+            columns,
+        };
+        // The alternate screen, to leave the shell as it was
+        terminal.tty.write_all(b"\x1b[?1049h")?;
+
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.tty.write_all(b"\x1b[?1049l");
+        let _ = stty(&self.tty, &[&self.saved]);
+    }
+}
+
+enum Key {
+    Char(char),
+    Backspace,
+    Clear,
+    Up,
+    Down,
+    Mark,
+    Enter,
+    Cancel,
+}
+
+/// Reads the next keys, a single read returns the whole of an escape sequence.
+fn read_keys(tty: &mut File) -> io::Result<Vec<Key>> {
+    let mut buf = [0; 64];
+    let n = tty.read(&mut buf)?;
+    let input = &buf[..n];
+
+    Ok(match input {
+        [] | [0x1b] | [0x03] | [0x07] => vec![Key::Cancel],
+        [0x1b, b'[', b'A'] | [0x1b, b'O', b'A'] => vec![Key::Up],
+        [0x1b, b'[', b'B'] | [0x1b, b'O', b'B'] => vec![Key::Down],
+        [0x1b, ..] => Vec::new(),
+        _ => String::from_utf8_lossy(input)
+            .chars()
+            .filter_map(|c| match c {
+                '\r' | '\n' => Some(Key::Enter),
+                '\t' => Some(Key::Mark),
+                '\x7f' | '\x08' => Some(Key::Backspace),
+                '\x15' => Some(Key::Clear),
+                '\x10' => Some(Key::Up),
+                '\x0e' => Some(Key::Down),
+                c if c.is_control() => None,
+                c => Some(Key::Char(c)),
+            })
+            .collect(),
+    })
+}
+
+/// Returns at most `width` characters of `value`.
+fn truncate(value: &str, width: usize) -> String {
+    value.chars().take(width).collect()
+}
+
+/// Lets the user pick items, returning their indexes, none if cancelled.
+fn run_picker(items: &[Item], query: &str) -> Result<Vec<usize>, CommandPickError> {
+    let mut terminal = Terminal::open().map_err(CommandPickError::NoTerminal)?;
+
+    let mut query = query.to_owned();
+    let mut matched = matches(items, &query);
+    let mut cursor: usize = 0;
+    let mut marked: Vec<usize> = Vec::new();
+
+    loop {
+        // The prompt, the counts, then as many items as fit
+        let visible = terminal.rows.saturating_sub(2);
+        let offset = cursor.saturating_sub(visible.saturating_sub(1));
+
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        screen.push_str(&format!("> {}\r\n", query));
+        screen.push_str(&format!(
+            "\x1b[2m  {}/{}{}\x1b[0m",
+            matched.len(),
+            items.len(),
+            if marked.is_empty() {
+                String::new()
+            } else {
+                format!(" ({} marked)", marked.len())
+            }
+        ));
+        for (row, index) in matched.iter().enumerate().skip(offset).take(visible) {
+            let mark = if marked.contains(index) { '*' } else { ' ' };
+            let line = truncate(
+                &format!("{} {}", mark, items[*index].label),
+                terminal.columns,
+            );
+            if row == cursor {
+                screen.push_str(&format!("\r\n\x1b[7m{}\x1b[0m", line));
+            } else {
+                screen.push_str(&format!("\r\n{}", line));
+            }
+        }
+        // The cursor back after the query
+        screen.push_str(&format!("\x1b[1;{}H", query.chars().count() + 3));
+        terminal.tty.write_all(screen.as_bytes())?;
+
+        for key in read_keys(&mut terminal.tty)? {
+            match key {
+                Key::Char(c) => query.push(c),
+                Key::Backspace => {
+                    query.pop();
+                }
+                Key::Clear => query.clear(),
+                Key::Up => cursor = cursor.saturating_sub(1),
+                Key::Down => cursor = (cursor + 1).min(matched.len().saturating_sub(1)),
+                Key::Mark => {
+                    if let Some(index) = matched.get(cursor) {
+                        match marked.iter().position(|marked| marked == index) {
+                            Some(position) => {
+                                marked.remove(position);
+                            }
+                            None => marked.push(*index),
+                        }
+                        cursor = (cursor + 1).min(matched.len().saturating_sub(1));
+                    }
+                }
+                Key::Enter => {
+                    if marked.is_empty() {
+                        return Ok(matched.get(cursor).copied().into_iter().collect());
+                    }
+                    return Ok(marked);
+                }
+                Key::Cancel => return Ok(Vec::new()),
+            }
+            if matches!(key, Key::Char(_) | Key::Backspace | Key::Clear) {
+                matched = matches(items, &query);
+                cursor = 0;
+            }
+        }
+    }
+}
+
+//
+// "pick" command
+//
+
+pub fn cmd_pick(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandPickError> {
+    let items = match args.value_of("kind") {
+        Some("albums") => load_albums(db)?,
+        _ => load_tracks(db)?,
+    };
+
+    let picked = match args.value_of("filter") {
+        Some(query) => matches(&items, query),
+        None => run_picker(&items, args.value_of("query").unwrap_or_default())?,
+    };
+
+    let paths = args.is_present("paths");
+    for index in picked {
+        let item = &items[index];
+        if paths {
+            for path in &item.paths {
+                println!("{}", path);
+            }
+        } else {
+            println!("{}", item.id);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,448 @@
+//! Playback of tracks into a Snapcast pipe, so zik can be the source of a multi-room setup, or
+//! on a casting device with `--to`, see `cast`.
+//!
+//! snapserver reads raw PCM from a named pipe, `/tmp/snapfifo` unless `snapcast_fifo` says
+//! otherwise, in the sample format of its `sampleformat` option, which `snapcast_format` must
+//! match: "48000:16:2" by default. Each track is decoded into it by ffmpeg, normalized like
+//! the streams if `replay_gain_mode` is set. The pipe blocks while nobody reads it, which
+//! paces the playback.
+//!
+//! The tracks follow each other without a gap: the next one is decoded ahead, and the encoder
+//! delay and padding of lossy files are cut, see `gapless`, unless `gapless` is false or
+//! `--no-gapless` is given. `crossfade`, or `--crossfade`, mixes that many seconds of the end
+//! of a track with the start of the next one.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::cast::{self, CastError};
+use crate::collection;
+use crate::ffmpeg;
+use crate::gapless;
+use crate::i18n::tr;
+use crate::replaygain::{self, GainMode};
+use crate::shuffle::{self, Shuffle, Weight};
+use crate::TrackID;
+
+const DEFAULT_FIFO: &str = "/tmp/snapfifo";
+const DEFAULT_FORMAT: &str = "48000:16:2";
+
+pub enum CommandPlayError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    Ffmpeg(ffmpeg::FfmpegError),
+    TrackNotFound(String),
+    PlaylistNotFound(String),
+    CollectionNotFound(String),
+    InvalidSampleFormat(String),
+    InvalidCrossfade(String),
+    Cast(CastError),
+}
+impl From<rusqlite::Error> for CommandPlayError {
+    fn from(err: rusqlite::Error) -> CommandPlayError {
+        CommandPlayError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandPlayError {
+    fn from(err: io::Error) -> CommandPlayError {
+        CommandPlayError::IO(err)
+    }
+}
+impl From<ffmpeg::FfmpegError> for CommandPlayError {
+    fn from(err: ffmpeg::FfmpegError) -> CommandPlayError {
+        CommandPlayError::Ffmpeg(err)
+    }
+}
+impl From<CastError> for CommandPlayError {
+    fn from(err: CastError) -> CommandPlayError {
+        CommandPlayError::Cast(err)
+    }
+}
+impl fmt::Display for CommandPlayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandPlayError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandPlayError::IO(err) => f.write_str(&tr!("play-pipe", err = err)),
+            CommandPlayError::Ffmpeg(err) => write!(f, "{}", err),
+            CommandPlayError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+            CommandPlayError::PlaylistNotFound(value) => {
+                f.write_str(&tr!("playlist-not-found", value = value))
+            }
+            CommandPlayError::CollectionNotFound(name) => {
+                f.write_str(&tr!("collection-not-found", name = name))
+            }
+            CommandPlayError::InvalidSampleFormat(value) => {
+                f.write_str(&tr!("config-invalid-snapcast-format", value = value))
+            }
+            CommandPlayError::InvalidCrossfade(value) => {
+                f.write_str(&tr!("play-invalid-crossfade", value = value))
+            }
+            CommandPlayError::Cast(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// A Snapcast sample format, like "48000:16:2".
+pub struct SampleFormat {
+    pub rate: usize,
+    pub bits: usize,
+    pub channels: usize,
+}
+
+impl SampleFormat {
+    pub fn parse(value: &str) -> Option<SampleFormat> {
+        let mut parts = value.trim().split(':').map(|part| part.parse::<usize>());
+        let format = SampleFormat {
+            rate: parts.next()?.ok()?,
+            bits: parts.next()?.ok()?,
+            channels: parts.next()?.ok()?,
+        };
+        if parts.next().is_some()
+            || format.rate == 0
+            || format.channels == 0
+            || ![16, 24, 32].contains(&format.bits)
+        {
+            return None;
+        }
+
+        Some(format)
+    }
+}
+
+pub struct Track {
+    pub id: TrackID,
+    pub path: String,
+    pub artist: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Returns the IDs of the tracks of a playlist, in order.
+fn playlist_tracks(
+    db: &rusqlite::Connection,
+    value: &str,
+) -> Result<Vec<TrackID>, CommandPlayError> {
+    let id: i64 = match value.parse() {
+        Ok(id) => id,
+        Err(_) => return Err(CommandPlayError::PlaylistNotFound(value.to_owned())),
+    };
+    let exists: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM playlist WHERE id = $id)",
+        [id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(CommandPlayError::PlaylistNotFound(value.to_owned()));
+    }
+
+    let mut stmt = db
+        .prepare("SELECT track_id FROM playlist_track WHERE playlist_id = $id ORDER BY position")?;
+    let ids = stmt
+        .query_map([id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(ids)
+}
+
+/// Returns the tracks to play, skipping those without a file.
+fn load_tracks(
+    db: &rusqlite::Connection,
+    ids: &[TrackID],
+) -> Result<Vec<shuffle::Entry<Track>>, CommandPlayError> {
+    let mut stmt = db.prepare(
+        "SELECT library_path(track.path), artist.name, track.name, track.artist_id, track.album_id,
+                (SELECT AVG(rating) FROM user_track WHERE user_track.track_id = track.id),
+                (SELECT SUM(play_count) FROM user_track WHERE user_track.track_id = track.id)
+         FROM track
+         LEFT JOIN artist ON artist.id = track.artist_id
+         WHERE track.id = $id AND track.path IS NOT NULL AND track.missing_since IS NULL",
+    )?;
+
+    let mut tracks = Vec::new();
+    for id in ids {
+        let mut rows = stmt.query([id])?;
+        if let Some(row) = rows.next()? {
+            let play_count: Option<i64> = row.get(6)?;
+            tracks.push(shuffle::Entry {
+                value: Track {
+                    id: *id,
+                    path: row.get(0)?,
+                    artist: row.get(1)?,
+                    name: row.get(2)?,
+                },
+                artist_id: row.get(3)?,
+                album_id: row.get(4)?,
+                rating: row.get(5)?,
+                play_count: play_count.unwrap_or(0),
+            });
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// Starts decoding a track into the sample format of the pipe.
+fn decode(
+    db: &rusqlite::Connection,
+    track: &Track,
+    format: &SampleFormat,
+    mode: GainMode,
+    gapless: bool,
+) -> Result<process::Child, CommandPlayError> {
+    let path = Path::new(&track.path);
+    let gain_db = replaygain::track_gain(db, track.id as i64, mode)?;
+    let trim = if gapless { gapless::read(path) } else { None };
+
+    Ok(ffmpeg::decode_pcm(
+        path,
+        format.rate,
+        format.bits,
+        format.channels,
+        gain_db,
+        trim.as_ref(),
+    )?)
+}
+
+/// The pipe of snapserver, where the end of a track is mixed with the start of the next one
+/// when crossfading.
+struct Output {
+    pipe: fs::File,
+    /// The bytes of a sample, and of a sample of every channel.
+    sample: usize,
+    frame: usize,
+    /// The bytes of the crossfade.
+    crossfade: usize,
+    /// The end of the last track, waiting for the next one.
+    tail: Vec<u8>,
+}
+
+impl Output {
+    /// Writes the PCM of a track, keeping its end for the next one when crossfading.
+    fn play<R: Read>(&mut self, mut decoder: R) -> io::Result<()> {
+        let fading = mem::take(&mut self.tail);
+        let mut head = Vec::with_capacity(fading.len());
+        let mut held = Vec::new();
+
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let n = decoder.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+
+            let mut data = &buffer[..n];
+            if head.len() < fading.len() {
+                let n = data.len().min(fading.len() - head.len());
+                head.extend_from_slice(&data[..n]);
+                data = &data[n..];
+                if head.len() == fading.len() {
+                    self.pipe
+                        .write_all(&mix(&fading, &head, self.sample, self.frame))?;
+                }
+            }
+
+            held.extend_from_slice(data);
+            if held.len() > self.crossfade {
+                let n = held.len() - self.crossfade;
+                self.pipe.write_all(&held[..n])?;
+                held.drain(..n);
+            }
+        }
+
+        // A track shorter than the crossfade is mixed whole into the end of the last one
+        if head.len() < fading.len() {
+            self.pipe
+                .write_all(&mix(&fading, &head, self.sample, self.frame))?;
+        }
+        self.tail = held;
+
+        Ok(())
+    }
+
+    /// Writes the end of the last track.
+    fn finish(&mut self) -> io::Result<()> {
+        self.pipe.write_all(&mem::take(&mut self.tail))
+    }
+}
+
+/// Mixes `next` into `previous`, fading from one to the other over the length of `previous`.
+fn mix(previous: &[u8], next: &[u8], sample: usize, frame: usize) -> Vec<u8> {
+    let frames = (previous.len() / frame).max(1) as f64;
+    let mut out = Vec::with_capacity(previous.len());
+    for (i, bytes) in previous.chunks_exact(sample).enumerate() {
+        let t = ((i * sample / frame) as f64 + 0.5) / frames;
+        let a = read_sample(bytes);
+        let b = next
+            .get(i * sample..(i + 1) * sample)
+            .map_or(0.0, read_sample);
+        out.extend_from_slice(&write_sample(a * (1.0 - t) + b * t, sample));
+    }
+
+    out
+}
+
+/// Reads a little-endian sample of 2, 3 or 4 bytes.
+fn read_sample(bytes: &[u8]) -> f64 {
+    // In the high bytes, so shifting back extends the sign
+    let mut value = [0; 4];
+    value[4 - bytes.len()..].copy_from_slice(bytes);
+    (i32::from_le_bytes(value) >> (8 * (4 - bytes.len()))) as f64
+}
+
+fn write_sample(value: f64, sample: usize) -> Vec<u8> {
+    let max = ((1i64 << (8 * sample - 1)) - 1) as f64;
+    let value = value.round().clamp(-max - 1.0, max) as i32;
+    value.to_le_bytes()[..sample].to_vec()
+}
+
+//
+// "play" command
+//
+
+pub fn cmd_play(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandPlayError> {
+    let ids = match (args.value_of("playlist"), args.value_of("collection")) {
+        (Some(playlist), _) => playlist_tracks(db, playlist)?,
+        (None, Some(name)) => match collection::find_id(db, name)? {
+            Some(id) => collection::track_ids(db, id)?,
+            None => return Err(CommandPlayError::CollectionNotFound(name.to_owned())),
+        },
+        (None, None) => {
+            let mut ids = Vec::new();
+            for value in args.values_of("track").unwrap_or_default() {
+                match crate::find_track_id(db, value)? {
+                    Some(id) => ids.push(id),
+                    None => return Err(CommandPlayError::TrackNotFound(value.to_owned())),
+                }
+            }
+            ids
+        }
+    };
+
+    let mut tracks = load_tracks(db, &ids)?;
+    if let Some(order) = args.value_of("shuffle").and_then(Shuffle::parse) {
+        let weight = args
+            .value_of("weight")
+            .and_then(Weight::parse)
+            .unwrap_or(Weight::Even);
+        tracks = shuffle::shuffle(tracks, order, weight);
+    }
+
+    if let Some(device) = args.value_of("to") {
+        let tracks: Vec<&Track> = tracks.iter().map(|entry| &entry.value).collect();
+        cast::play(db, device, args.value_of("user"), &tracks)?;
+        return Ok(());
+    }
+
+    let crossfade = match args.value_of("crossfade") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(seconds) => seconds,
+            _ => return Err(CommandPlayError::InvalidCrossfade(value.to_owned())),
+        },
+        None => crate::get_config_usize(db, "crossfade")?.unwrap_or(0),
+    };
+    let gapless = if args.is_present("gapless") {
+        true
+    } else if args.is_present("no-gapless") {
+        false
+    } else {
+        crate::get_config_value(db, "gapless")?.as_deref() != Some("0")
+    };
+
+    let fifo = crate::get_config_value(db, "snapcast_fifo")?
+        .filter(|path| !path.is_empty())
+        .unwrap_or_else(|| DEFAULT_FIFO.to_owned());
+    let format = crate::get_config_value(db, "snapcast_format")?
+        .unwrap_or_else(|| DEFAULT_FORMAT.to_owned());
+    let format = match SampleFormat::parse(&format) {
+        Some(format) => format,
+        None => return Err(CommandPlayError::InvalidSampleFormat(format)),
+    };
+    let mode = GainMode::load(db)?;
+
+    // Opening the pipe waits for snapserver to read it
+    println!("{}", tr!("play-waiting", fifo = fifo));
+    let pipe = fs::OpenOptions::new()
+        .write(true)
+        .open(PathBuf::from(&fifo))?;
+    let sample = format.bits / 8;
+    let mut output = Output {
+        pipe,
+        sample,
+        frame: sample * format.channels,
+        crossfade: crossfade * format.rate * sample * format.channels,
+        tail: Vec::new(),
+    };
+
+    let mut next = None;
+    for (i, entry) in tracks.iter().enumerate() {
+        let track = &entry.value;
+        let mut decoder = match next.take() {
+            Some(decoder) => decoder,
+            None => decode(db, track, &format, mode, gapless)?,
+        };
+        // Started now, the next track is ready to follow this one
+        if let Some(entry) = tracks.get(i + 1) {
+            next = Some(decode(db, &entry.value, &format, mode, gapless)?);
+        }
+
+        println!(
+            "{}",
+            tr!(
+                "play-playing",
+                artist = track.artist.as_deref().unwrap_or_default(),
+                name = track.name.as_deref().unwrap_or_default()
+            )
+        );
+
+        if let Some(stdout) = decoder.stdout.take() {
+            output.play(stdout)?;
+        }
+        ffmpeg::wait(&mut decoder)?;
+    }
+    output.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples() {
+        for sample in [2, 3, 4] {
+            for value in [0.0, 1.0, -1.0, 1000.0, -32768.0] {
+                assert_eq!(read_sample(&write_sample(value, sample)), value);
+            }
+        }
+        assert_eq!(write_sample(40000.0, 2), 32767i16.to_le_bytes());
+        assert_eq!(write_sample(-40000.0, 2), (-32768i16).to_le_bytes());
+        assert_eq!(read_sample(&[0xff, 0xff, 0x7f]), 8388607.0);
+        assert_eq!(read_sample(&[0x00, 0x00, 0x80]), -8388608.0);
+    }
+
+    #[test]
+    fn crossfade() {
+        let pcm = |values: &[i16]| -> Vec<u8> {
+            values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect()
+        };
+
+        // Two frames of two channels, faded at a quarter then three quarters
+        let mixed = mix(&pcm(&[400, 400, 400, 400]), &pcm(&[0, -800, 0, -800]), 2, 4);
+        assert_eq!(mixed, pcm(&[300, 100, 100, -500]));
+
+        // A shorter next track fades in over the start only
+        let mixed = mix(&pcm(&[400, 400]), &pcm(&[800]), 2, 2);
+        assert_eq!(mixed, pcm(&[500, 100]));
+    }
+}
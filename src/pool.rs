@@ -0,0 +1,172 @@
+//! The database connections of the server, kept open from one request to the next.
+//!
+//! Opening a connection costs more than most requests: SQLite reads the schema and the pragmas
+//! are set again each time. Requests take a connection from the pool and give it back once
+//! answered. At most `MAX_CONNECTIONS` are open at once, a request waiting longer than
+//! `WAIT_TIMEOUT` for one is answered with a 503 so that clients retry later instead of piling
+//! up threads. Once they have a connection, requests writing wait for each other with the
+//! busy timeout of `db` while WAL lets the others read.
+//!
+//! Requests writing go through `write`, one after the other. SQLite's busy timeout can't help a
+//! transaction that read before writing: if another connection wrote in between it fails right
+//! away with SQLITE_BUSY. Taking turns in the process, then starting the transaction with the
+//! write lock, leaves the busy timeout for other processes only, like a scan.
+//!
+//! The server has a thread per connection rather than an async runtime: every handler blocks on
+//! SQLite anyway, and a pool of blocking connections is what an async server would need too.
+//!
+//! A connection given back with temporary views, like the ones restricting a user, or inside a
+//! transaction is closed instead of being reused.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::server::Response;
+
+const MAX_CONNECTIONS: usize = 16;
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct State {
+    idle: Vec<rusqlite::Connection>,
+    /// The connections open, idle or not.
+    open: usize,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    idle: Vec::new(),
+    open: 0,
+});
+/// Notified when a connection is given back or closed.
+static RETURNED: Condvar = Condvar::new();
+/// Held by the request writing.
+static WRITER: Mutex<()> = Mutex::new(());
+
+pub enum PoolError {
+    Open(crate::OpenDatabaseError),
+    Busy,
+}
+impl From<crate::OpenDatabaseError> for PoolError {
+    fn from(err: crate::OpenDatabaseError) -> PoolError {
+        PoolError::Open(err)
+    }
+}
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolError::Open(err) => write!(f, "unable to open database, {}", err),
+            PoolError::Busy => write!(f, "every database connection is in use"),
+        }
+    }
+}
+
+fn lock() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A connection of the pool, given back when dropped.
+pub struct Connection {
+    db: Option<rusqlite::Connection>,
+}
+
+impl Deref for Connection {
+    type Target = rusqlite::Connection;
+
+    fn deref(&self) -> &rusqlite::Connection {
+        self.db.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for Connection {
+    fn deref_mut(&mut self) -> &mut rusqlite::Connection {
+        self.db.as_mut().unwrap()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let db = match self.db.take() {
+            Some(db) => db,
+            None => return,
+        };
+        let reusable = db.is_autocommit()
+            && db
+                .query_row(
+                    "SELECT NOT EXISTS(SELECT 1 FROM sqlite_temp_master)",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap_or(false);
+
+        let mut state = lock();
+        if reusable {
+            state.idle.push(db);
+        } else {
+            state.open -= 1;
+        }
+        drop(state);
+
+        RETURNED.notify_one();
+    }
+}
+
+/// Returns an idle connection, or a new one if fewer than `MAX_CONNECTIONS` are open.
+pub fn get() -> Result<Connection, PoolError> {
+    let deadline = Instant::now() + WAIT_TIMEOUT;
+
+    let mut state = lock();
+    loop {
+        if let Some(db) = state.idle.pop() {
+            return Ok(Connection { db: Some(db) });
+        }
+
+        if state.open < MAX_CONNECTIONS {
+            state.open += 1;
+            drop(state);
+
+            // Opened without holding the lock, the others can still take the idle ones
+            return match crate::open_database() {
+                Ok(db) => Ok(Connection { db: Some(db) }),
+                Err(err) => {
+                    lock().open -= 1;
+                    RETURNED.notify_one();
+                    Err(err.into())
+                }
+            };
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(PoolError::Busy);
+        }
+        state = RETURNED
+            .wait_timeout(state, deadline - now)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+    }
+}
+
+/// Runs `f` in a transaction holding the write lock of the database, once the other requests
+/// writing are done. It's rolled back if `f` fails.
+pub fn write<T, E, F>(db: &rusqlite::Connection, f: F) -> Result<T, E>
+where
+    E: From<rusqlite::Error>,
+    F: FnOnce(&rusqlite::Connection) -> Result<T, E>,
+{
+    let _writer = WRITER.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let tx = rusqlite::Transaction::new_unchecked(db, rusqlite::TransactionBehavior::Immediate)?;
+    let value = f(&tx)?;
+    tx.commit()?;
+
+    Ok(value)
+}
+
+/// The response to a request which got no connection, either busy or broken.
+pub fn error_response(err: &PoolError) -> Response {
+    match err {
+        PoolError::Busy => Response::text(503, "server busy").header("Retry-After", "1"),
+        PoolError::Open(_) => Response::text(500, "internal error"),
+    }
+}
@@ -0,0 +1,77 @@
+//! Finding the format of an audio file from its first bytes, so that scans parse it as that
+//! format only and read no more than its tags.
+//!
+//! FLAC metadata blocks and ID3v2 tags are at the start of the file and their parsers stop at
+//! the audio. The tags of MP4 files are in the `moov` atom, which can be after the audio: it's
+//! found by seeking over the atoms before it, see `mp4meta::read_header_atoms`. The comments
+//! of Ogg files are in their first pages, see `ogg::read`. Files of an unknown format are
+//! parsed as every format, like before.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Flac,
+    Id3,
+    Mp4,
+    Ogg,
+    Unknown,
+}
+
+/// Returns the format of the file `reader` reads, moving back to its start.
+pub fn sniff<R: Read + Seek>(reader: &mut R) -> io::Result<Format> {
+    let mut magic = [0; 12];
+    let mut len = 0;
+    while len < magic.len() {
+        match reader.read(&mut magic[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    reader.seek(SeekFrom::Start(0))?;
+
+    let magic = &magic[..len];
+    let format = if magic.starts_with(b"fLaC") {
+        Format::Flac
+    } else if magic.starts_with(b"ID3") {
+        Format::Id3
+    } else if magic.get(4..8) == Some(b"ftyp") {
+        Format::Mp4
+    } else if magic.starts_with(b"OggS") {
+        Format::Ogg
+    } else {
+        Format::Unknown
+    };
+
+    Ok(format)
+}
+
+/// A reader counting the bytes read through it.
+pub struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> CountingReader<R> {
+        CountingReader { inner, count: 0 }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
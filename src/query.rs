@@ -0,0 +1,200 @@
+//! Read-only SQL queries against the database, for reports `zik list` doesn't cover.
+
+use std::fmt;
+
+use rusqlite::types::ValueRef;
+
+use crate::i18n::tr;
+use crate::json;
+
+pub enum CommandQueryError {
+    SQLite(rusqlite::Error),
+    NoDatabasePath,
+    #[cfg(feature = "encryption")]
+    Encryption(crate::encryption::EncryptionError),
+}
+impl From<rusqlite::Error> for CommandQueryError {
+    fn from(err: rusqlite::Error) -> CommandQueryError {
+        CommandQueryError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandQueryError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandQueryError::NoDatabasePath => f.write_str(&tr!("query-no-database-path")),
+            #[cfg(feature = "encryption")]
+            CommandQueryError::Encryption(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Returns a value as text, for the table and CSV output.
+fn to_text(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(n) => n.to_string(),
+        ValueRef::Real(n) => n.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+        ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+    }
+}
+
+fn to_json(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "null".to_owned(),
+        ValueRef::Integer(n) => n.to_string(),
+        ValueRef::Real(n) if n.is_finite() => n.to_string(),
+        ValueRef::Real(_) => "null".to_owned(),
+        ValueRef::Text(text) => json::string(&String::from_utf8_lossy(text)),
+        ValueRef::Blob(blob) => {
+            let hex: String = blob.iter().map(|b| format!("{:02x}", b)).collect();
+            json::string(&hex)
+        }
+    }
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+fn print_table(columns: &[String], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = columns.iter().map(|name| name.chars().count()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let print_row = |values: &[String]| {
+        let cells: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect();
+        println!("{}", cells.join(" | ").trim_end());
+    };
+
+    print_row(columns);
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    println!("{}", separator.join("-+-"));
+    for row in rows {
+        print_row(row);
+    }
+}
+
+/// Prints the statements creating the tables, indexes, triggers and views.
+pub fn print_schema(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let mut stmt = db.prepare(
+        "SELECT sql FROM sqlite_schema
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+         ORDER BY tbl_name, type = 'table' DESC, type, name",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let sql: String = row.get(0)?;
+        println!("{};\n", sql);
+    }
+
+    Ok(())
+}
+
+/// Opens a separate read-only connection to the database of `db`, so no query can change the
+/// library by mistake.
+pub fn open_read_only(
+    db: &rusqlite::Connection,
+) -> Result<rusqlite::Connection, CommandQueryError> {
+    let path = db.path().ok_or(CommandQueryError::NoDatabasePath)?;
+    let db = rusqlite::Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    #[cfg(feature = "encryption")]
+    {
+        crate::encryption::unlock(&db, path).map_err(CommandQueryError::Encryption)?;
+        crate::encryption::register_functions(&db)?;
+    }
+    crate::collation::register(&db)?;
+    crate::library::register_functions(&db)?;
+
+    Ok(db)
+}
+
+/// Prints the rows of `stmt` as a table, CSV, JSON or, with "lines", their values alone
+/// separated by tabs.
+pub fn print_rows(stmt: &mut rusqlite::Statement, format: &str) -> Result<(), CommandQueryError> {
+    let columns: Vec<String> = stmt
+        .column_names()
+        .into_iter()
+        .map(|name| name.to_owned())
+        .collect();
+    let mut rows = stmt.query([])?;
+
+    match format {
+        "json" => {
+            let mut values = Vec::new();
+            while let Some(row) = rows.next()? {
+                let mut fields = Vec::with_capacity(columns.len());
+                for (i, name) in columns.iter().enumerate() {
+                    fields.push((name.as_str(), to_json(row.get_ref(i)?)));
+                }
+                values.push(json::object(&fields));
+            }
+            println!("{}", json::array(&values));
+        }
+        "csv" => {
+            let header: Vec<String> = columns.iter().map(|name| csv_field(name)).collect();
+            println!("{}", header.join(","));
+            while let Some(row) = rows.next()? {
+                let mut fields = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    fields.push(csv_field(&to_text(row.get_ref(i)?)));
+                }
+                println!("{}", fields.join(","));
+            }
+        }
+        "lines" => {
+            while let Some(row) = rows.next()? {
+                let mut fields = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    fields.push(to_text(row.get_ref(i)?));
+                }
+                println!("{}", fields.join("\t"));
+            }
+        }
+        _ => {
+            let mut values = Vec::new();
+            while let Some(row) = rows.next()? {
+                let mut fields = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    fields.push(to_text(row.get_ref(i)?));
+                }
+                values.push(fields);
+            }
+            if !columns.is_empty() {
+                print_table(&columns, &values);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cmd_query(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandQueryError> {
+    let db = open_read_only(db)?;
+
+    if args.is_present("schema") {
+        return Ok(print_schema(&db)?);
+    }
+
+    let mut stmt = db.prepare(args.value_of("sql").unwrap())?;
+    print_rows(&mut stmt, args.value_of("format").unwrap_or("table"))
+}
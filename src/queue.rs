@@ -0,0 +1,149 @@
+//! The play queue of each user, saved by Subsonic clients with `savePlayQueue` so another
+//! client, or the same one after a reboot, picks it up where it was with `getPlayQueue`.
+
+use std::fmt;
+
+use crate::i18n::tr;
+use crate::spoken;
+
+pub enum CommandQueueError {
+    SQLite(rusqlite::Error),
+    UserNotFound(String),
+}
+impl From<rusqlite::Error> for CommandQueueError {
+    fn from(err: rusqlite::Error) -> CommandQueueError {
+        CommandQueueError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandQueueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandQueueError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandQueueError::UserNotFound(name) => {
+                f.write_str(&tr!("user-not-found", name = name))
+            }
+        }
+    }
+}
+
+/// Replaces the play queue of a user, an empty one deletes it. Tracks no longer in the
+/// library are left out.
+pub fn save(
+    db: &rusqlite::Connection,
+    user_id: i64,
+    tracks: &[i64],
+    current: Option<i64>,
+    position_ms: i64,
+    changed_by: Option<&str>,
+) -> rusqlite::Result<()> {
+    // The tracks go with it
+    db.execute("DELETE FROM play_queue WHERE user_id = $id", [user_id])?;
+    if tracks.is_empty() {
+        return Ok(());
+    }
+
+    db.execute(
+        "INSERT INTO play_queue(user_id, current_track_id, position_ms, changed_by)
+         VALUES($user_id, (SELECT id FROM track WHERE id = $current), $position, $changed_by)",
+        rusqlite::params![user_id, current, position_ms.max(0), changed_by],
+    )?;
+
+    let mut stmt = db.prepare(
+        "INSERT INTO play_queue_track(user_id, position, track_id)
+         SELECT $user_id, $position, id FROM track WHERE id = $track_id",
+    )?;
+    for (position, track_id) in tracks.iter().enumerate() {
+        stmt.execute(rusqlite::params![user_id, position, track_id])?;
+    }
+
+    Ok(())
+}
+
+//
+// "queue" command
+//
+
+pub fn cmd_queue(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandQueueError> {
+    let name = args.value_of("user").unwrap();
+    let user_id: i64 = match db.query_row("SELECT id FROM user WHERE name = $name", [name], |row| {
+        row.get(0)
+    }) {
+        Ok(id) => id,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(CommandQueueError::UserNotFound(name.to_owned()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    if args.is_present("clear") {
+        save(db, user_id, &[], None, 0, None)?;
+        return Ok(());
+    }
+
+    let queue = db.query_row(
+        "SELECT current_track_id, position_ms, changed_by,
+                datetime(updated_at, 'unixepoch', 'localtime')
+         FROM play_queue WHERE user_id = $id",
+        [user_id],
+        |row| {
+            let current: Option<i64> = row.get(0)?;
+            let position_ms: i64 = row.get(1)?;
+            let changed_by: Option<String> = row.get(2)?;
+            let updated_at: String = row.get(3)?;
+            Ok((current, position_ms, changed_by, updated_at))
+        },
+    );
+    let (current, position_ms, changed_by, updated_at) = match queue {
+        Ok(queue) => queue,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            println!("{}", tr!("queue-none"));
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    println!(
+        "{}",
+        tr!(
+            "queue-saved",
+            at = updated_at,
+            client = changed_by.unwrap_or_else(|| tr!("queue-unknown-client"))
+        )
+    );
+
+    let mut stmt = db.prepare(
+        "SELECT track.id, artist.name, track.name
+         FROM play_queue_track
+         JOIN track ON track.id = play_queue_track.track_id
+         LEFT JOIN artist ON artist.id = track.artist_id
+         WHERE play_queue_track.user_id = $id
+         ORDER BY play_queue_track.position",
+    )?;
+    let mut rows = stmt.query([user_id])?;
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let artist: Option<String> = row.get(1)?;
+        let title: Option<String> = row.get(2)?;
+
+        let line = format!(
+            "{} - {}",
+            artist.unwrap_or_default(),
+            title.unwrap_or_default()
+        );
+        if Some(id) == current {
+            println!(
+                "> {} ({})",
+                line,
+                tr!("queue-at", position = spoken::format_position(position_ms))
+            );
+        } else {
+            println!("  {}", line);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! Ratings and play counts other players wrote in the tags.
+//!
+//! They're read from the ID3 POPM and PCNT frames, the FMPS tags and the `RATING` tag that
+//! taggers following iTunes write from 0 to 100. A scan seeds them into the stats of the
+//! `tag_stats_user` user, or of every user when it's unset, once per user and track: after
+//! that zik's own stats win, even a rating removed since.
+
+/// Converts a POPM rating, from 1 to 255, to stars the way Windows Media Player does.
+pub fn from_popm(value: u8) -> Option<i64> {
+    match value {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// Parses an FMPS rating, from 0.0 to 1.0.
+pub fn from_fmps(value: &str) -> Option<i64> {
+    let value: f64 = value.trim().parse().ok()?;
+    if !(0.0..=1.0).contains(&value) {
+        return None;
+    }
+
+    Some((value * 5.0).round() as i64).filter(|&stars| stars > 0)
+}
+
+/// Parses a `RATING` tag, from 0 to 100; some taggers write stars directly.
+pub fn from_percent(value: &str) -> Option<i64> {
+    let value: i64 = value.trim().parse().ok()?;
+    match value {
+        1..=5 => Some(value),
+        6..=100 => Some(((value + 10) / 20).max(1)),
+        _ => None,
+    }
+}
+
+/// Parses a play count tag.
+pub fn parse_play_count(value: &str) -> Option<i64> {
+    value.trim().parse().ok().filter(|&n: &i64| n > 0)
+}
+
+/// Parses the big endian counter ending POPM frames and making up PCNT frames.
+fn parse_counter(data: &[u8]) -> Option<i64> {
+    if data.is_empty() || data.len() > 8 {
+        return None;
+    }
+
+    let n = data.iter().fold(0u64, |n, &b| (n << 8) | u64::from(b));
+    i64::try_from(n).ok().filter(|&n| n > 0)
+}
+
+/// Parses the content of a POPM frame: an email, a rating and an optional play counter.
+pub fn parse_popm(data: &[u8]) -> (Option<i64>, Option<i64>) {
+    let rest = match data.iter().position(|&b| b == 0) {
+        Some(end) => &data[end + 1..],
+        None => return (None, None),
+    };
+
+    match rest.split_first() {
+        Some((&rating, counter)) => (from_popm(rating), parse_counter(counter)),
+        None => (None, None),
+    }
+}
+
+/// Parses the content of a PCNT frame.
+pub fn parse_pcnt(data: &[u8]) -> Option<i64> {
+    parse_counter(data)
+}
+
+/// Converts stars to a POPM rating, the inverse of `from_popm`.
+pub fn to_popm(stars: i64) -> u8 {
+    match stars {
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        5 => 255,
+        _ => 0,
+    }
+}
+
+pub fn to_fmps(stars: i64) -> String {
+    format!("{:.1}", stars as f64 / 5.0)
+}
+
+pub fn to_percent(stars: i64) -> String {
+    (stars * 20).to_string()
+}
+
+/// Builds the content of a POPM frame, with a 4 bytes counter.
+pub fn popm_content(email: &str, rating: u8, play_count: i64) -> Vec<u8> {
+    let mut data = Vec::with_capacity(email.len() + 6);
+    data.extend_from_slice(email.as_bytes());
+    data.push(0);
+    data.push(rating);
+    data.extend_from_slice(&(play_count.clamp(0, u32::MAX as i64) as u32).to_be_bytes());
+    data
+}
+
+/// Seeds the stats of a track from its tags, for the users who don't have them yet.
+///
+/// The play count is added to the plays zik already counted, a rating only fills an empty one.
+pub fn seed(
+    db: &rusqlite::Connection,
+    track_id: crate::TrackID,
+    rating: Option<i64>,
+    play_count: Option<i64>,
+    user: Option<&str>,
+) -> rusqlite::Result<usize> {
+    db.execute(
+        "INSERT INTO user_track(user_id, track_id, play_count, rating, tags_imported)
+         SELECT id, $track_id, coalesce($play_count, 0), $rating, 1
+         FROM user
+         WHERE $user IS NULL OR name = $user
+         ON CONFLICT(user_id, track_id) DO UPDATE SET
+           play_count = play_count + excluded.play_count,
+           rating = coalesce(rating, excluded.rating),
+           tags_imported = 1
+         WHERE NOT tags_imported",
+        rusqlite::params![track_id, play_count, rating, user],
+    )
+}
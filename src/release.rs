@@ -0,0 +1,149 @@
+//! Release types of albums: album, EP, single, live or compilation.
+//!
+//! The type comes from the `RELEASETYPE` or MusicBrainz album type tags when a track has
+//! one; albums without are classified at the end of a scan from their title, the number of
+//! their tracks and how many artists they have.
+
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReleaseType {
+    Album,
+    Ep,
+    Single,
+    Live,
+    Compilation,
+}
+
+impl ReleaseType {
+    pub const ALL: [&'static str; 5] = ["album", "ep", "single", "live", "compilation"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseType::Album => "album",
+            ReleaseType::Ep => "ep",
+            ReleaseType::Single => "single",
+            ReleaseType::Live => "live",
+            ReleaseType::Compilation => "compilation",
+        }
+    }
+
+    /// Parses a release type tag.
+    ///
+    /// MusicBrainz has a primary type and secondary types, which taggers write as a list
+    /// like "album; live". A secondary type says more about the release than the primary
+    /// one, so it wins when it's one of ours.
+    pub fn from_tag(value: &str) -> Option<ReleaseType> {
+        let mut primary = None;
+        let mut secondary = None;
+
+        for part in value.split([';', ',', '/', '\0']) {
+            match part.trim().to_lowercase().as_str() {
+                "album" => primary = primary.or(Some(ReleaseType::Album)),
+                "ep" => primary = primary.or(Some(ReleaseType::Ep)),
+                "single" => primary = primary.or(Some(ReleaseType::Single)),
+                "live" => secondary = secondary.or(Some(ReleaseType::Live)),
+                "compilation" | "dj-mix" | "mixtape/street" | "mixtape" => {
+                    secondary = secondary.or(Some(ReleaseType::Compilation))
+                }
+                _ => (),
+            }
+        }
+
+        secondary.or(primary)
+    }
+}
+
+impl fmt::Display for ReleaseType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Guesses the release type of an album from its title.
+fn from_title(title: &str) -> Option<ReleaseType> {
+    let title = title.to_lowercase();
+    let title = title.trim();
+
+    let marked = |marker: &str| {
+        title.ends_with(&format!(" - {}", marker))
+            || title.ends_with(&format!("({})", marker))
+            || title.ends_with(&format!("[{}]", marker))
+    };
+
+    if marked("ep") || title.ends_with(" ep") {
+        Some(ReleaseType::Ep)
+    } else if marked("single") {
+        Some(ReleaseType::Single)
+    } else if marked("live")
+        || title.starts_with("live at ")
+        || title.starts_with("live in ")
+        || title.contains(" live at ")
+        || title.contains(" live in ")
+    {
+        Some(ReleaseType::Live)
+    } else if [
+        "greatest hits",
+        "best of",
+        "anthology",
+        "collection",
+        "compilation",
+    ]
+    .iter()
+    .any(|words| title.contains(words))
+    {
+        Some(ReleaseType::Compilation)
+    } else {
+        None
+    }
+}
+
+/// Guesses the release type of an album from its tracks.
+fn from_tracks(tracks: i64, artists: i64) -> ReleaseType {
+    // Albums of various artists are compilations more often than not
+    if artists >= 3 && artists * 2 > tracks {
+        ReleaseType::Compilation
+    } else if tracks <= 3 {
+        ReleaseType::Single
+    } else if tracks <= 6 {
+        ReleaseType::Ep
+    } else {
+        ReleaseType::Album
+    }
+}
+
+/// Sets the release type of the albums that no track had a tag for.
+pub fn classify_albums(savepoint: &rusqlite::Savepoint) -> rusqlite::Result<()> {
+    let mut stmt = savepoint.prepare(
+        "SELECT album.id, album.name,
+                MAX(COUNT(track.id), coalesce(MAX(track.track_total), 0)),
+                COUNT(DISTINCT track.artist_id)
+         FROM album
+         JOIN track ON track.album_id = album.id
+         WHERE album.release_type IS NULL
+         GROUP BY album.id",
+    )?;
+    let albums = stmt
+        .query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let name: Option<String> = row.get(1)?;
+            let tracks: i64 = row.get(2)?;
+            let artists: i64 = row.get(3)?;
+
+            let release_type = name
+                .as_deref()
+                .and_then(from_title)
+                .unwrap_or_else(|| from_tracks(tracks, artists));
+            Ok((id, release_type))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (id, release_type) in albums {
+        savepoint.execute(
+            "UPDATE album SET release_type = $release_type WHERE id = $id",
+            rusqlite::params![release_type.as_str(), id],
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Volume normalization of the transcodes streamed by `/stream`, from the ReplayGain tags or
+//! the loudness measured by `zik jobs`.
+//!
+//! `replay_gain_mode` picks the track or album gain, or none by default; each stream can ask
+//! for another one with `?replay_gain=`. A track without the gain asked for gets the other
+//! one, or the gain bringing its measured loudness to the ReplayGain 2 reference. The
+//! original files can't be changed, so a normalized stream is always a transcode.
+
+/// The loudness ReplayGain 2 brings tracks to, in LUFS.
+const REFERENCE_LOUDNESS: f64 = -18.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GainMode {
+    Off,
+    Track,
+    Album,
+}
+
+impl GainMode {
+    pub fn parse(value: &str) -> Option<GainMode> {
+        match value {
+            "off" => Some(GainMode::Off),
+            "track" => Some(GainMode::Track),
+            "album" => Some(GainMode::Album),
+            _ => None,
+        }
+    }
+
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<GainMode> {
+        let value = crate::get_config_value(db, "replay_gain_mode")?;
+        Ok(value
+            .as_deref()
+            .and_then(GainMode::parse)
+            .unwrap_or(GainMode::Off))
+    }
+}
+
+/// Returns the gain to apply to a track in dB, with `replay_gain_preamp` added, or none if
+/// it has no gain at all.
+pub fn track_gain(
+    db: &rusqlite::Connection,
+    track_id: i64,
+    mode: GainMode,
+) -> rusqlite::Result<Option<f64>> {
+    if mode == GainMode::Off {
+        return Ok(None);
+    }
+
+    let (track_gain, album_gain, loudness) = db.query_row(
+        "SELECT replay_gain_track, replay_gain_album, loudness FROM track WHERE id = $id",
+        [track_id],
+        |row| {
+            let track_gain: Option<f64> = row.get(0)?;
+            let album_gain: Option<f64> = row.get(1)?;
+            let loudness: Option<f64> = row.get(2)?;
+            Ok((track_gain, album_gain, loudness))
+        },
+    )?;
+
+    let gain = match mode {
+        GainMode::Album => album_gain.or(track_gain),
+        _ => track_gain.or(album_gain),
+    };
+    let gain = gain.or_else(|| loudness.map(|loudness| REFERENCE_LOUDNESS - loudness));
+
+    let preamp = crate::get_config_value(db, "replay_gain_preamp")?
+        .and_then(|value| value.parse::<f64>().ok())
+        .unwrap_or(0.0);
+
+    Ok(gain.map(|gain| gain + preamp))
+}
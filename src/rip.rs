@@ -0,0 +1,240 @@
+//! Ripping audio CDs into the library.
+//!
+//! The disc is read with cdparanoia, or cd-paranoia from libcdio, and looked up on MusicBrainz
+//! by its table of contents. Its tracks are ripped and encoded into `rip_format`, FLAC by
+//! default, then reviewed and filed like `zik import` does, which also indexes them.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::i18n::tr;
+use crate::import::{self, CommandImportError};
+use crate::{ffmpeg, musicbrainz};
+
+/// The programs able to read a disc, tried in order.
+const RIPPERS: [&str; 2] = ["cdparanoia", "cd-paranoia"];
+
+/// The sectors before the first track, which the offsets MusicBrainz expects include.
+const LEAD_IN: usize = 150;
+
+/// How many releases of a disc are looked up and shown.
+const MAX_CANDIDATES: usize = 5;
+
+/// The bitrate of tracks ripped into MP3, in kbit/s.
+const MP3_BITRATE: usize = 320;
+
+/// The formats ripped tracks can be encoded into, those whose tags zik can write.
+pub const FORMATS: [&str; 2] = ["flac", "mp3"];
+
+pub enum CommandRipError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    Ffmpeg(ffmpeg::FfmpegError),
+    Import(CommandImportError),
+    RipperNotFound,
+    Ripper(String),
+    NoAudioTracks,
+}
+impl From<rusqlite::Error> for CommandRipError {
+    fn from(err: rusqlite::Error) -> CommandRipError {
+        CommandRipError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandRipError {
+    fn from(err: io::Error) -> CommandRipError {
+        CommandRipError::IO(err)
+    }
+}
+impl From<ffmpeg::FfmpegError> for CommandRipError {
+    fn from(err: ffmpeg::FfmpegError) -> CommandRipError {
+        CommandRipError::Ffmpeg(err)
+    }
+}
+impl From<CommandImportError> for CommandRipError {
+    fn from(err: CommandImportError) -> CommandRipError {
+        CommandRipError::Import(err)
+    }
+}
+impl fmt::Display for CommandRipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandRipError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandRipError::IO(err) => f.write_str(&tr!("rip-io", err = err)),
+            CommandRipError::Ffmpeg(err) => write!(f, "{}", err),
+            CommandRipError::Import(err) => write!(f, "{}", err),
+            CommandRipError::RipperNotFound => f.write_str(&tr!("rip-ripper-not-found")),
+            CommandRipError::Ripper(err) => f.write_str(&tr!("rip-read-disc", err = err)),
+            CommandRipError::NoAudioTracks => f.write_str(&tr!("rip-no-audio-tracks")),
+        }
+    }
+}
+
+/// An audio track of the disc, in sectors of 1/75th of a second.
+struct TocTrack {
+    number: usize,
+    begin: usize,
+    length: usize,
+}
+
+/// Runs the first ripper installed.
+fn run_ripper(
+    device: Option<&str>,
+    args: &[&std::ffi::OsStr],
+) -> Result<process::Output, CommandRipError> {
+    for ripper in RIPPERS {
+        let mut command = process::Command::new(ripper);
+        if let Some(device) = device {
+            command.arg("-d").arg(device);
+        }
+        let output = match command.args(args).stdin(process::Stdio::null()).output() {
+            Ok(output) => output,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(CommandRipError::IO(err)),
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let last_line = stderr.lines().rev().find(|line| !line.trim().is_empty());
+            return Err(CommandRipError::Ripper(
+                last_line.unwrap_or_default().trim().to_owned(),
+            ));
+        }
+
+        return Ok(output);
+    }
+
+    Err(CommandRipError::RipperNotFound)
+}
+
+/// Parses the table of contents printed by `cdparanoia -Q`, with lines like
+/// "  1.    16503 [03:40.03]        0 [00:00.00]    no   no  2".
+fn parse_toc(output: &str) -> Vec<TocTrack> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let number = fields.next()?.strip_suffix('.')?.parse().ok()?;
+            let length = fields.next()?.parse().ok()?;
+            let begin = fields.nth(1)?.parse().ok()?;
+            Some(TocTrack {
+                number,
+                begin,
+                length,
+            })
+        })
+        .collect()
+}
+
+/// Formats a table of contents the way MusicBrainz looks discs up by.
+fn musicbrainz_toc(tracks: &[TocTrack]) -> String {
+    let first = &tracks[0];
+    let last = &tracks[tracks.len() - 1];
+
+    let mut fields = vec![
+        first.number,
+        last.number,
+        last.begin + last.length + LEAD_IN,
+    ];
+    fields.extend(tracks.iter().map(|track| track.begin + LEAD_IN));
+
+    fields
+        .iter()
+        .map(|field| field.to_string())
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+/// Looks the disc up on MusicBrainz, an error only printed since the tracks can still be
+/// tagged by hand.
+fn find_releases(tracks: &[TocTrack]) -> Vec<musicbrainz::Release> {
+    let ids = match musicbrainz::disc_releases(&musicbrainz_toc(tracks)) {
+        Ok(ids) => ids,
+        Err(err) => {
+            println!("{}", tr!("rip-lookup", err = err));
+            return Vec::new();
+        }
+    };
+
+    let mut releases = Vec::new();
+    for id in ids.iter().take(MAX_CANDIDATES) {
+        match musicbrainz::lookup_release(id) {
+            Ok(release) => releases.push(release),
+            Err(err) => println!("{}", tr!("rip-fetch-release", id = id, err = err)),
+        }
+    }
+
+    releases
+}
+
+/// Rips and encodes the tracks into `dir`.
+fn rip_tracks(
+    device: Option<&str>,
+    tracks: &[TocTrack],
+    dir: &Path,
+    format: &str,
+) -> Result<(), CommandRipError> {
+    for track in tracks {
+        println!(
+            "{}",
+            tr!("rip-track", number = track.number, total = tracks.len())
+        );
+
+        let wav = dir.join(format!("{:02}.wav", track.number));
+        let output = dir.join(format!("{:02}.{}", track.number, format));
+        run_ripper(
+            device,
+            &[
+                "-w".as_ref(),
+                track.number.to_string().as_ref(),
+                wav.as_os_str(),
+            ],
+        )?;
+
+        match format {
+            "mp3" => ffmpeg::transcode(&wav, &output, "mp3", MP3_BITRATE)?,
+            _ => ffmpeg::encode_flac(&wav, &output)?,
+        }
+        fs::remove_file(&wav)?;
+    }
+
+    Ok(())
+}
+
+//
+// "rip" command
+//
+
+pub fn cmd_rip(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandRipError> {
+    let device = args.value_of("device");
+    let format = crate::get_config_value(db, "rip_format")?
+        .filter(|format| FORMATS.contains(&format.as_str()))
+        .unwrap_or_else(|| "flac".to_owned());
+
+    // cdparanoia prints the table of contents on stderr
+    let output = run_ripper(device, &["-Q".as_ref()])?;
+    let tracks = parse_toc(&String::from_utf8_lossy(&output.stderr));
+    if tracks.is_empty() {
+        return Err(CommandRipError::NoAudioTracks);
+    }
+
+    println!("{}", tr!("rip-looking-up", tracks = tracks.len()));
+    let releases = find_releases(&tracks);
+
+    let dir: PathBuf = env::temp_dir().join(format!("zik-rip-{}", process::id()));
+    fs::create_dir_all(&dir)?;
+
+    let result = rip_tracks(device, &tracks, &dir, &format)
+        .and_then(|_| Ok(import::import_ripped(db, &dir, releases)?));
+
+    // Whatever wasn't filed into the library is thrown away
+    let _ = fs::remove_dir_all(&dir);
+
+    result.map(|_| ())
+}
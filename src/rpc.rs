@@ -0,0 +1,672 @@
+//! A JSON-RPC 2.0 interface to the library, for frontends that drive zik without parsing the
+//! output of its commands.
+//!
+//! Requests and responses are one JSON document per line, sent over `rpc.sock` in the data
+//! folder. The socket is served by `zik rpc`, or by `zik daemon` which also keeps the library
+//! up to date. Every connection gets its own thread and its own database connection.
+//!
+//! Like the daemon's control socket, it only exists on Unix.
+
+use std::fmt;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::i18n::tr;
+use crate::{daemon, jobs, json, links, list, notify, secrets, throttle};
+
+const SOCKET_NAME: &str = "rpc.sock";
+
+/// Returns the path of the RPC socket.
+pub fn socket_path() -> Option<PathBuf> {
+    crate::get_data_dir().map(|data_dir| data_dir.join(SOCKET_NAME))
+}
+
+pub enum RpcError {
+    Parse(json::ParseError),
+    InvalidRequest,
+    MethodNotFound(String),
+    MissingParameter(&'static str),
+    InvalidParameter(&'static str),
+    SQLite(rusqlite::Error),
+    Failed(String),
+    IO(io::Error),
+    DataFolderNotFound,
+}
+impl From<rusqlite::Error> for RpcError {
+    fn from(err: rusqlite::Error) -> RpcError {
+        RpcError::SQLite(err)
+    }
+}
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpcError::Parse(err) => write!(f, "{}", err),
+            RpcError::InvalidRequest => write!(f, "not a JSON-RPC 2.0 request"),
+            RpcError::MethodNotFound(name) => write!(f, "method \"{}\" not found", name),
+            RpcError::MissingParameter(name) => write!(f, "required parameter {} is missing", name),
+            RpcError::InvalidParameter(name) => write!(f, "parameter {} is invalid", name),
+            RpcError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            RpcError::Failed(err) => write!(f, "{}", err),
+            RpcError::IO(err) => f.write_str(&tr!("io-error", err = err)),
+            RpcError::DataFolderNotFound => f.write_str(&tr!("data-folder-not-found")),
+        }
+    }
+}
+impl RpcError {
+    /// The error code defined by JSON-RPC 2.0; codes from -32000 on are ours.
+    fn code(&self) -> i64 {
+        match self {
+            RpcError::Parse(_) => -32700,
+            RpcError::InvalidRequest => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::MissingParameter(_) | RpcError::InvalidParameter(_) => -32602,
+            RpcError::SQLite(_) => -32000,
+            RpcError::Failed(_) | RpcError::IO(_) | RpcError::DataFolderNotFound => -32001,
+        }
+    }
+}
+
+/// The methods a client can call, with a short description returned by `methods`.
+const METHODS: &[(&str, &str)] = &[
+    ("methods", "list the available methods"),
+    ("version", "the version of zik"),
+    ("scan", "scan the library"),
+    ("config.list", "all the configuration keys and their values"),
+    ("config.get", "the value of {key}"),
+    ("config.set", "set {key} to {value}"),
+    ("artists.list", "all the artists"),
+    (
+        "albums.list",
+        "albums, optionally of {artist_id} or in {year}",
+    ),
+    (
+        "tracks.list",
+        "tracks, optionally of {album_id} or in {year}",
+    ),
+    ("tracks.get", "the track {id} or {uid}"),
+    (
+        "tracks.chapters",
+        "the chapters of the track {id}, or the songs of a mix",
+    ),
+    ("search", "artists, albums and tracks matching {query}"),
+    (
+        "jobs.status",
+        "the number of queued jobs per kind and state",
+    ),
+    (
+        "jobs.enqueue",
+        "queue {kind} jobs, with an optional {priority}",
+    ),
+];
+
+/// Asks a running daemon to scan the library.
+pub type RequestScan = Arc<dyn Fn() + Send + Sync>;
+
+/// A client connection with its own database connection.
+struct Session {
+    db: rusqlite::Connection,
+    /// Without it the scan runs as part of the request.
+    request_scan: Option<RequestScan>,
+}
+
+fn param<'a>(params: &'a json::Value, name: &'static str) -> Result<&'a json::Value, RpcError> {
+    match params.get(name) {
+        Some(json::Value::Null) | None => Err(RpcError::MissingParameter(name)),
+        Some(value) => Ok(value),
+    }
+}
+
+fn str_param<'a>(params: &'a json::Value, name: &'static str) -> Result<&'a str, RpcError> {
+    param(params, name)?
+        .as_str()
+        .ok_or(RpcError::InvalidParameter(name))
+}
+
+fn opt_i64_param(params: &json::Value, name: &'static str) -> Result<Option<i64>, RpcError> {
+    match param(params, name) {
+        Ok(value) => match value.as_i64() {
+            Some(n) => Ok(Some(n)),
+            None => Err(RpcError::InvalidParameter(name)),
+        },
+        Err(RpcError::MissingParameter(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn year_param(params: &json::Value) -> Result<(Option<i64>, Option<i64>), RpcError> {
+    match param(params, "year") {
+        Ok(value) => {
+            let filter = match value {
+                json::Value::String(value) => list::parse_year_filter(value),
+                value => value.as_i64().map(|year| (year, year)),
+            };
+            match filter {
+                Some((from, to)) => Ok((Some(from), Some(to))),
+                None => Err(RpcError::InvalidParameter("year")),
+            }
+        }
+        Err(RpcError::MissingParameter(_)) => Ok((None, None)),
+        Err(err) => Err(err),
+    }
+}
+
+fn collect_rows<F>(
+    db: &rusqlite::Connection,
+    query: &str,
+    params: impl rusqlite::Params,
+    f: F,
+) -> Result<String, RpcError>
+where
+    F: Fn(&rusqlite::Row) -> rusqlite::Result<String>,
+{
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query(params)?;
+
+    let mut values = Vec::new();
+    while let Some(row) = rows.next()? {
+        values.push(f(row)?);
+    }
+
+    Ok(json::array(&values))
+}
+
+fn artist_object(row: &rusqlite::Row) -> rusqlite::Result<String> {
+    let id: i64 = row.get(0)?;
+    let name: Option<String> = row.get(1)?;
+    let albums: i64 = row.get(2)?;
+    let uid: Option<String> = row.get(3)?;
+
+    Ok(json::object(&[
+        ("id", id.to_string()),
+        ("uid", json::opt_string(uid.as_deref())),
+        ("name", json::opt_string(name.as_deref())),
+        ("albums", albums.to_string()),
+    ]))
+}
+
+const ARTIST_QUERY: &str = "
+    SELECT artist.id, artist.name, COUNT(album.id), artist.uid
+    FROM artist
+    LEFT JOIN album ON album.artist_id = artist.id";
+
+fn album_object(row: &rusqlite::Row) -> rusqlite::Result<String> {
+    let id: i64 = row.get(0)?;
+    let name: Option<String> = row.get(1)?;
+    let artist_id: Option<i64> = row.get(2)?;
+    let artist: Option<String> = row.get(3)?;
+    let year: Option<i64> = row.get(4)?;
+    let tracks: i64 = row.get(5)?;
+    let cover_path: Option<String> = row.get(6)?;
+    let uid: Option<String> = row.get(7)?;
+    let links = links::read(row, 8)?;
+
+    Ok(json::object(&[
+        ("id", id.to_string()),
+        ("uid", json::opt_string(uid.as_deref())),
+        ("name", json::opt_string(name.as_deref())),
+        ("artist_id", json::opt_number(artist_id)),
+        ("artist", json::opt_string(artist.as_deref())),
+        ("year", json::opt_number(year)),
+        ("tracks", tracks.to_string()),
+        ("cover_path", json::opt_string(cover_path.as_deref())),
+        ("links", links::to_json(&links)),
+    ]))
+}
+
+const ALBUM_QUERY: &str = "
+    SELECT album.id, album.name, album.artist_id, artist.name, album.release_year,
+           COUNT(track.id), library_path(album.cover_path), album.uid,
+           album.spotify_url, album.apple_music_url, album.bandcamp_url
+    FROM album
+    LEFT JOIN artist ON artist.id = album.artist_id
+    LEFT JOIN track ON track.album_id = album.id";
+
+fn track_object(row: &rusqlite::Row) -> rusqlite::Result<String> {
+    let id: i64 = row.get(0)?;
+    let name: Option<String> = row.get(1)?;
+    let artist_id: Option<i64> = row.get(2)?;
+    let artist: Option<String> = row.get(3)?;
+    let album_id: Option<i64> = row.get(4)?;
+    let album: Option<String> = row.get(5)?;
+    let number: Option<i64> = row.get(6)?;
+    let disc_number: Option<i64> = row.get(7)?;
+    let year: Option<i64> = row.get(8)?;
+    let genre: Option<String> = row.get(9)?;
+    let path: Option<String> = row.get(10)?;
+    let uid: Option<String> = row.get(11)?;
+    let links = links::read(row, 12)?;
+
+    Ok(json::object(&[
+        ("id", id.to_string()),
+        ("uid", json::opt_string(uid.as_deref())),
+        ("name", json::opt_string(name.as_deref())),
+        ("artist_id", json::opt_number(artist_id)),
+        ("artist", json::opt_string(artist.as_deref())),
+        ("album_id", json::opt_number(album_id)),
+        ("album", json::opt_string(album.as_deref())),
+        ("number", json::opt_number(number)),
+        ("disc_number", json::opt_number(disc_number)),
+        ("year", json::opt_number(year)),
+        ("genre", json::opt_string(genre.as_deref())),
+        ("path", json::opt_string(path.as_deref())),
+        ("links", links::to_json(&links)),
+    ]))
+}
+
+const TRACK_QUERY: &str = "
+    SELECT track.id, track.name, track.artist_id, artist.name, track.album_id, album.name,
+           track.number, track.disc_number, track.release_year, track.genre, library_path(track.path),
+           track.uid, track.spotify_url, track.apple_music_url, track.bandcamp_url
+    FROM track
+    LEFT JOIN artist ON artist.id = track.artist_id
+    LEFT JOIN album ON album.id = track.album_id";
+
+impl Session {
+    fn scan(&mut self) -> Result<String, RpcError> {
+        if let Some(request_scan) = &self.request_scan {
+            request_scan();
+            return Ok(json::object(&[("queued", "true".to_owned())]));
+        }
+
+        match crate::scan_library(&mut self.db, &mut throttle::Throttle::default()) {
+            Ok(summary) => {
+                notify::scan_completed(&self.db, &summary);
+                Ok(json::object(&[("queued", "false".to_owned())]))
+            }
+            Err(err) => Err(RpcError::Failed(err.to_string())),
+        }
+    }
+
+    fn config_list(&self) -> Result<String, RpcError> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT key, CAST(value AS TEXT) FROM config ORDER BY key")?;
+        let mut rows = stmt.query([])?;
+
+        let mut fields = Vec::new();
+        while let Some(row) = rows.next()? {
+            let key: String = row.get(0)?;
+            let value: Option<String> = row.get(1)?;
+            let value = value.map(|value| secrets::mask(&key, value));
+            fields.push((key, json::opt_string(value.as_deref())));
+        }
+
+        let fields: Vec<(&str, String)> = fields
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect();
+        Ok(json::object(&fields))
+    }
+
+    fn config_get(&self, params: &json::Value) -> Result<String, RpcError> {
+        let key = str_param(params, "key")?;
+        let value = crate::get_config_value(&self.db, key)?;
+        let value = value.map(|value| secrets::mask(key, value));
+
+        Ok(json::opt_string(value.as_deref()))
+    }
+
+    fn config_set(&self, params: &json::Value) -> Result<String, RpcError> {
+        let key = str_param(params, "key")?;
+        let value = match param(params, "value")? {
+            json::Value::String(value) => value.clone(),
+            value @ json::Value::Number(_) => value.encode(),
+            _ => return Err(RpcError::InvalidParameter("value")),
+        };
+
+        match crate::set_config(&self.db, key, &value) {
+            Ok(()) => Ok("null".to_owned()),
+            Err(err) => Err(RpcError::Failed(err.to_string())),
+        }
+    }
+
+    fn artists_list(&self) -> Result<String, RpcError> {
+        let query = format!(
+            "{} GROUP BY artist.id ORDER BY artist.sort_name COLLATE natural_sort",
+            ARTIST_QUERY
+        );
+        collect_rows(&self.db, &query, [], artist_object)
+    }
+
+    fn albums_list(&self, params: &json::Value) -> Result<String, RpcError> {
+        let artist_id = opt_i64_param(params, "artist_id")?;
+        let (from, to) = year_param(params)?;
+
+        let query = format!(
+            "{}
+            WHERE ($artist_id IS NULL OR album.artist_id = $artist_id)
+              AND ($from IS NULL OR album.release_year BETWEEN $from AND $to)
+            GROUP BY album.id
+            ORDER BY artist.sort_name COLLATE natural_sort, album.release_year, album.sort_name COLLATE natural_sort",
+            ALBUM_QUERY
+        );
+        collect_rows(
+            &self.db,
+            &query,
+            rusqlite::params![artist_id, from, to],
+            album_object,
+        )
+    }
+
+    fn tracks_list(&self, params: &json::Value) -> Result<String, RpcError> {
+        let album_id = opt_i64_param(params, "album_id")?;
+        let (from, to) = year_param(params)?;
+        let label = match param(params, "label") {
+            Ok(value) => Some(value.as_str().ok_or(RpcError::InvalidParameter("label"))?),
+            Err(_) => None,
+        };
+
+        let query = format!(
+            "{}
+            WHERE ($album_id IS NULL OR track.album_id = $album_id)
+              AND ($from IS NULL OR track.release_year BETWEEN $from AND $to)
+              AND ($label IS NULL OR track.id IN (
+                SELECT track_label.track_id
+                FROM track_label
+                JOIN label ON label.id = track_label.label_id
+                WHERE label.name = $label
+              ))
+            ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort, track.disc_number, track.number",
+            TRACK_QUERY
+        );
+        collect_rows(
+            &self.db,
+            &query,
+            rusqlite::params![album_id, from, to, label],
+            track_object,
+        )
+    }
+
+    /// Returns a track by its `id`, or by its `uid` which survives rescans.
+    fn tracks_get(&self, params: &json::Value) -> Result<String, RpcError> {
+        let result = match param(params, "uid") {
+            Ok(uid) => {
+                let uid = uid.as_str().ok_or(RpcError::InvalidParameter("uid"))?;
+                let query = format!("{} WHERE track.uid = $uid", TRACK_QUERY);
+                self.db.query_row(&query, [uid], track_object)
+            }
+            Err(_) => {
+                let id = match param(params, "id")?.as_i64() {
+                    Some(id) => id,
+                    None => return Err(RpcError::InvalidParameter("id")),
+                };
+                let query = format!("{} WHERE track.id = $id", TRACK_QUERY);
+                self.db.query_row(&query, [id], track_object)
+            }
+        };
+
+        match result {
+            Ok(track) => Ok(track),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok("null".to_owned()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn tracks_chapters(&self, params: &json::Value) -> Result<String, RpcError> {
+        let id = match param(params, "id")?.as_i64() {
+            Some(id) => id,
+            None => return Err(RpcError::InvalidParameter("id")),
+        };
+
+        let query = "
+            SELECT number, start_ms, end_ms, title, artist
+            FROM chapter
+            WHERE track_id = $id
+            ORDER BY number";
+        collect_rows(&self.db, query, [id], |row| {
+            let number: i64 = row.get(0)?;
+            let start_ms: i64 = row.get(1)?;
+            let end_ms: Option<i64> = row.get(2)?;
+            let title: Option<String> = row.get(3)?;
+            let artist: Option<String> = row.get(4)?;
+
+            Ok(json::object(&[
+                ("number", number.to_string()),
+                ("start_ms", start_ms.to_string()),
+                ("end_ms", json::opt_number(end_ms)),
+                ("title", json::opt_string(title.as_deref())),
+                ("artist", json::opt_string(artist.as_deref())),
+            ]))
+        })
+    }
+
+    fn search(&self, params: &json::Value) -> Result<String, RpcError> {
+        let pattern = format!("%{}%", str_param(params, "query")?);
+
+        let query = format!(
+            "{} WHERE artist.name LIKE $pattern GROUP BY artist.id ORDER BY artist.sort_name COLLATE natural_sort",
+            ARTIST_QUERY
+        );
+        let artists = collect_rows(&self.db, &query, [&pattern], artist_object)?;
+
+        let query = format!(
+            "{} WHERE album.name LIKE $pattern GROUP BY album.id ORDER BY album.sort_name COLLATE natural_sort",
+            ALBUM_QUERY
+        );
+        let albums = collect_rows(&self.db, &query, [&pattern], album_object)?;
+
+        let query = format!(
+            "{} WHERE track.name LIKE $pattern ORDER BY track.name COLLATE natural_sort",
+            TRACK_QUERY
+        );
+        let tracks = collect_rows(&self.db, &query, [&pattern], track_object)?;
+
+        Ok(json::object(&[
+            ("artists", artists),
+            ("albums", albums),
+            ("tracks", tracks),
+        ]))
+    }
+
+    fn jobs_status(&self) -> Result<String, RpcError> {
+        collect_rows(
+            &self.db,
+            "SELECT kind, state, COUNT(*) FROM job GROUP BY kind, state ORDER BY kind, state",
+            [],
+            |row| {
+                let kind: String = row.get(0)?;
+                let state: String = row.get(1)?;
+                let count: i64 = row.get(2)?;
+
+                Ok(json::object(&[
+                    ("kind", json::string(&kind)),
+                    ("state", json::string(&state)),
+                    ("count", count.to_string()),
+                ]))
+            },
+        )
+    }
+
+    fn jobs_enqueue(&self, params: &json::Value) -> Result<String, RpcError> {
+        let kind = match jobs::JobKind::from_str(str_param(params, "kind")?) {
+            Some(kind) => kind,
+            None => return Err(RpcError::InvalidParameter("kind")),
+        };
+        let priority =
+            opt_i64_param(params, "priority")?.unwrap_or_else(|| kind.default_priority());
+
+        let n = jobs::enqueue_missing(&self.db, kind, priority)?;
+
+        Ok(json::object(&[("queued", n.to_string())]))
+    }
+
+    fn call(&mut self, method: &str, params: &json::Value) -> Result<String, RpcError> {
+        match method {
+            "methods" => {
+                let methods: Vec<String> = METHODS
+                    .iter()
+                    .map(|(name, description)| {
+                        json::object(&[
+                            ("name", json::string(name)),
+                            ("description", json::string(description)),
+                        ])
+                    })
+                    .collect();
+                Ok(json::array(&methods))
+            }
+            "version" => Ok(json::string(env!("CARGO_PKG_VERSION"))),
+            "scan" => self.scan(),
+            "config.list" => self.config_list(),
+            "config.get" => self.config_get(params),
+            "config.set" => self.config_set(params),
+            "artists.list" => self.artists_list(),
+            "albums.list" => self.albums_list(params),
+            "tracks.list" => self.tracks_list(params),
+            "tracks.get" => self.tracks_get(params),
+            "tracks.chapters" => self.tracks_chapters(params),
+            "search" => self.search(params),
+            "jobs.status" => self.jobs_status(),
+            "jobs.enqueue" => self.jobs_enqueue(params),
+            _ => Err(RpcError::MethodNotFound(method.to_owned())),
+        }
+    }
+
+    /// Answers one line of input; notifications, requests without an id, get no answer.
+    fn handle(&mut self, line: &str) -> Option<String> {
+        let request = match json::parse(line) {
+            Ok(request) => request,
+            Err(err) => return Some(error_response("null", RpcError::Parse(err))),
+        };
+
+        let id = request.get("id").map(json::Value::encode);
+        let method = request.get("method").and_then(json::Value::as_str);
+        let version = request.get("jsonrpc").and_then(json::Value::as_str);
+
+        let method = match (method, version) {
+            (Some(method), Some("2.0")) => method,
+            _ => {
+                let id = id.as_deref().unwrap_or("null");
+                return Some(error_response(id, RpcError::InvalidRequest));
+            }
+        };
+
+        let no_params = json::Value::Object(Vec::new());
+        let params = request.get("params").unwrap_or(&no_params);
+
+        let result = self.call(method, params);
+
+        let id = id?;
+        Some(match result {
+            Ok(result) => json::object(&[
+                ("jsonrpc", json::string("2.0")),
+                ("id", id),
+                ("result", result),
+            ]),
+            Err(err) => error_response(&id, err),
+        })
+    }
+
+    /// Answers requests until `reader` is exhausted.
+    fn serve<R: BufRead, W: Write>(&mut self, reader: R, mut writer: W) -> io::Result<()> {
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle(&line) {
+                writer.write_all(response.as_bytes())?;
+                writer.write_all(b"\n")?;
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn error_response(id: &str, err: RpcError) -> String {
+    json::object(&[
+        ("jsonrpc", json::string("2.0")),
+        ("id", id.to_owned()),
+        (
+            "error",
+            json::object(&[
+                ("code", err.code().to_string()),
+                ("message", json::string(&err.to_string())),
+            ]),
+        ),
+    ])
+}
+
+fn handle_connection(
+    stream: UnixStream,
+    request_scan: Option<RequestScan>,
+) -> Result<(), RpcError> {
+    let db = match crate::open_database() {
+        Ok(db) => db,
+        Err(err) => return Err(RpcError::Failed(err.to_string())),
+    };
+
+    let mut session = Session { db, request_scan };
+    let reader = BufReader::new(stream.try_clone().map_err(RpcError::IO)?);
+
+    session.serve(reader, stream).map_err(RpcError::IO)
+}
+
+/// Accepts connections on `listener` until `stop` is set.
+pub fn serve(
+    listener: UnixListener,
+    stop: Arc<AtomicBool>,
+    request_scan: Option<RequestScan>,
+) -> io::Result<()> {
+    // Same as the HTTP server: poll so the loop notices `stop`
+    listener.set_nonblocking(true)?;
+
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+
+                let request_scan = request_scan.clone();
+                thread::spawn(move || {
+                    if let Err(err) = handle_connection(stream, request_scan) {
+                        println!("{}", tr!("rpc-connection", err = err));
+                    }
+                });
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(err) => println!("{}", tr!("rpc-accept", err = err)),
+        }
+    }
+
+    Ok(())
+}
+
+//
+// "rpc" command
+//
+
+pub fn cmd_rpc(_db: &mut rusqlite::Connection, _args: &clap::ArgMatches) -> Result<(), RpcError> {
+    let socket_path = match socket_path() {
+        Some(path) => path,
+        None => return Err(RpcError::DataFolderNotFound),
+    };
+
+    let listener = match daemon::bind_socket(&socket_path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            return Err(RpcError::Failed(format!(
+                "unable to listen on \"{}\", err: {}",
+                socket_path.display(),
+                err
+            )))
+        }
+    };
+
+    println!("{}", tr!("rpc-listening", path = socket_path.display()));
+
+    // Runs until the process is killed
+    let result = serve(listener, Arc::new(AtomicBool::new(false)), None);
+    let _ = std::fs::remove_file(&socket_path);
+
+    result.map_err(RpcError::IO)
+}
@@ -0,0 +1,50 @@
+//! `zik schema`: the schema of the database this zik creates, for people writing their own
+//! queries against it.
+//!
+//! It's built by applying every migration to an empty in-memory database, so it's always the
+//! schema of this binary, whatever the database it would open. See also `zik query --schema`
+//! for the schema of the database itself.
+
+use std::fmt;
+
+use crate::i18n::tr;
+use crate::InitDatabaseError;
+
+pub enum CommandSchemaError {
+    SQLite(rusqlite::Error),
+    Init(InitDatabaseError),
+}
+impl From<rusqlite::Error> for CommandSchemaError {
+    fn from(err: rusqlite::Error) -> CommandSchemaError {
+        CommandSchemaError::SQLite(err)
+    }
+}
+impl From<InitDatabaseError> for CommandSchemaError {
+    fn from(err: InitDatabaseError) -> CommandSchemaError {
+        CommandSchemaError::Init(err)
+    }
+}
+impl fmt::Display for CommandSchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandSchemaError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandSchemaError::Init(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+//
+// "schema" command
+//
+
+pub fn cmd_schema() -> Result<(), CommandSchemaError> {
+    let mut db = rusqlite::Connection::open_in_memory()?;
+    crate::init_database(&mut db)?;
+
+    let version: i64 = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    println!("-- schema version {}\n", version);
+
+    crate::query::print_schema(&db)?;
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+//! Secrets kept in the keyring of the OS instead of the config table: the Last.fm and
+//! AcoustID API keys, the credentials of a remote library and the passphrase of an encrypted
+//! database, see `encryption`.
+//!
+//! `zik secrets set <name>` stores one in the Secret Service of GNOME Keyring or KWallet on
+//! Linux and the BSDs, the Keychain on macOS or the Credential Manager on Windows, and removes
+//! its plain text copy from the config. A secret missing from the keyring, or without a
+//! keyring at all, is still read from the config, where older versions stored them; `zik
+//! config` can't set them anymore and only shows them masked.
+
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal};
+
+use crate::i18n::tr;
+
+/// The config keys which can be kept in the keyring, and the passphrase of the database.
+pub const NAMES: [&str; 4] = [
+    "lastfm_api_key",
+    "acoustid_api_key",
+    "library_credentials",
+    "database_key",
+];
+
+/// The service the secrets are stored under.
+const SERVICE: &str = "zik";
+
+pub enum CommandSecretsError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    EmptyValue,
+    Keyring(keyring::Error),
+}
+impl From<rusqlite::Error> for CommandSecretsError {
+    fn from(err: rusqlite::Error) -> CommandSecretsError {
+        CommandSecretsError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandSecretsError {
+    fn from(err: io::Error) -> CommandSecretsError {
+        CommandSecretsError::IO(err)
+    }
+}
+impl From<keyring::Error> for CommandSecretsError {
+    fn from(err: keyring::Error) -> CommandSecretsError {
+        CommandSecretsError::Keyring(err)
+    }
+}
+impl fmt::Display for CommandSecretsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandSecretsError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandSecretsError::IO(err) => f.write_str(&tr!("secrets-read", err = err)),
+            CommandSecretsError::EmptyValue => f.write_str(&tr!("secrets-empty-value")),
+            CommandSecretsError::Keyring(err) => f.write_str(&tr!("secrets-keyring", err = err)),
+        }
+    }
+}
+
+/// Returns the secret `name` from the keyring, none if it's not there.
+fn lookup(name: &str) -> keyring::Result<Option<String>> {
+    match keyring::Entry::new(SERVICE, name)?.get_password() {
+        Ok(value) => Ok(Some(value).filter(|value| !value.is_empty())),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the secret `name` from the keyring, none without one.
+pub fn from_keyring(name: &str) -> Option<String> {
+    lookup(name).ok().flatten()
+}
+
+/// Returns `value` for display, masked if `key` is a secret.
+pub fn mask(key: &str, value: String) -> String {
+    if NAMES.contains(&key) && !value.is_empty() {
+        "********".to_owned()
+    } else {
+        value
+    }
+}
+
+/// Returns the secret `name`, from the keyring or else the config.
+pub fn get(db: &rusqlite::Connection, name: &str) -> rusqlite::Result<Option<String>> {
+    if let Some(value) = from_keyring(name) {
+        return Ok(Some(value));
+    }
+    Ok(crate::get_config_value(db, name)?.filter(|value| !value.is_empty()))
+}
+
+//
+// "secrets" command
+//
+
+fn cmd_secrets_set(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSecretsError> {
+    let name = args.value_of("name").unwrap();
+
+    // Not echoed on a terminal, read as a line when piped
+    let value = if io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("{}: ", name))?
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_owned()
+    };
+    if value.is_empty() {
+        return Err(CommandSecretsError::EmptyValue);
+    }
+
+    // Given to the keyring directly, never as the argument of a command
+    keyring::Entry::new(SERVICE, name)?.set_password(&value)?;
+
+    // The plain text copy would be used if the keyring goes away
+    db.execute("DELETE FROM config WHERE key = $key", [name])?;
+
+    println!("{}", tr!("secrets-stored", name = name));
+
+    Ok(())
+}
+
+fn cmd_secrets_remove(args: &clap::ArgMatches) -> Result<(), CommandSecretsError> {
+    let name = args.value_of("name").unwrap();
+
+    keyring::Entry::new(SERVICE, name)?.delete_password()?;
+
+    println!("{}", tr!("secrets-removed", name = name));
+
+    Ok(())
+}
+
+fn cmd_secrets_list(db: &rusqlite::Connection) -> Result<(), CommandSecretsError> {
+    for name in NAMES {
+        // Without a keyring, the secrets can only be in the config
+        let in_keyring = match lookup(name) {
+            Ok(value) => value.is_some(),
+            Err(keyring::Error::NoStorageAccess(_) | keyring::Error::PlatformFailure(_)) => false,
+            Err(err) => return Err(err.into()),
+        };
+        let location = if in_keyring {
+            "keyring"
+        } else if crate::get_config_value(db, name)?.is_some_and(|value| !value.is_empty()) {
+            "config, in plain text"
+        } else {
+            "not set"
+        };
+        println!("{}: {}", name, location);
+    }
+
+    Ok(())
+}
+
+pub fn cmd_secrets(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSecretsError> {
+    match args.subcommand() {
+        Some(("set", sub_args)) => cmd_secrets_set(db, sub_args),
+        Some(("remove", sub_args)) => cmd_secrets_remove(sub_args),
+        Some(("list", _)) => cmd_secrets_list(db),
+        _ => Ok(()),
+    }
+}
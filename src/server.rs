@@ -0,0 +1,346 @@
+//! A small HTTP/1.1 server, just enough to expose the library to clients on the network.
+//!
+//! Every connection gets its own thread, which answers it with a database connection of
+//! `pool`; responses are always sent with `Connection: close`.
+
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::feed;
+use crate::i18n::tr;
+use crate::metrics;
+use crate::stream;
+use crate::subsonic;
+
+const MAX_HEADER_LINES: usize = 100;
+const MAX_FORM_BODY: u64 = 64 * 1024;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub params: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns the first value of the query or form parameter `name`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of a parameter given multiple times, like `id=1&id=2`.
+    pub fn param_values(&self, name: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub enum Body {
+    Bytes(Vec<u8>),
+    File(fs::File, u64),
+    /// A body of unknown length, sent until the reader is exhausted.
+    Stream(Box<dyn Read + Send>),
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16, content_type: &str, body: Vec<u8>) -> Response {
+        Response {
+            status,
+            headers: vec![("Content-Type".to_owned(), content_type.to_owned())],
+            body: Body::Bytes(body),
+        }
+    }
+
+    pub fn file(file: fs::File, len: u64, content_type: &str) -> Response {
+        Response {
+            status: 200,
+            headers: vec![("Content-Type".to_owned(), content_type.to_owned())],
+            body: Body::File(file, len),
+        }
+    }
+
+    pub fn stream(reader: Box<dyn Read + Send>, content_type: &str) -> Response {
+        Response {
+            status: 200,
+            headers: vec![("Content-Type".to_owned(), content_type.to_owned())],
+            body: Body::Stream(reader),
+        }
+    }
+
+    pub fn header(mut self, key: &str, value: &str) -> Response {
+        self.headers.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn text(status: u16, body: &str) -> Response {
+        Response::new(
+            status,
+            "text/plain; charset=utf-8",
+            body.as_bytes().to_vec(),
+        )
+    }
+
+    pub fn not_found() -> Response {
+        Response::text(404, "not found")
+    }
+
+    fn len(&self) -> Option<u64> {
+        match &self.body {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File(_, len) => Some(*len),
+            Body::Stream(_) => None,
+        }
+    }
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        206 => "Partial Content",
+        302 => "Found",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn from_hex(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes a `application/x-www-form-urlencoded` component.
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut buf = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => buf.push(b' '),
+            b'%' if i + 2 < bytes.len() => match (from_hex(bytes[i + 1]), from_hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    buf.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => buf.push(b'%'),
+            },
+            b => buf.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+fn parse_params(query: &str, params: &mut Vec<(String, String)>) {
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params.push((percent_decode(key), percent_decode(value)));
+    }
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+        _ => return Ok(None),
+    };
+
+    let mut headers = Vec::new();
+    for _ in 0..MAX_HEADER_LINES {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+    let mut request = Request {
+        method,
+        path: percent_decode(path),
+        params: Vec::new(),
+        headers,
+    };
+    parse_params(query, &mut request.params);
+
+    // Subsonic clients are allowed to POST their parameters as a form
+    let is_form = request
+        .header("Content-Type")
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    let content_length: u64 = request
+        .header("Content-Length")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+
+    if is_form && content_length > 0 && content_length <= MAX_FORM_BODY {
+        let mut body = String::new();
+        reader.take(content_length).read_to_string(&mut body)?;
+        parse_params(&body, &mut request.params);
+    }
+
+    Ok(Some(request))
+}
+
+fn write_response(stream: &mut TcpStream, request: &Request, response: Response) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nConnection: close\r\n",
+        response.status,
+        status_text(response.status),
+    );
+    // Without a length the end of the body is the end of the connection
+    if let Some(len) = response.len() {
+        head.push_str(&format!("Content-Length: {}\r\n", len));
+    }
+    for (key, value) in &response.headers {
+        head.push_str(key);
+        head.push_str(": ");
+        head.push_str(value);
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+
+    if request.method != "HEAD" {
+        match response.body {
+            Body::Bytes(bytes) => stream.write_all(&bytes)?,
+            Body::File(file, len) => {
+                io::copy(&mut file.take(len), stream)?;
+            }
+            Body::Stream(mut reader) => {
+                io::copy(&mut reader, stream)?;
+            }
+        }
+    }
+
+    stream.flush()
+}
+
+fn route(request: &Request) -> Response {
+    if request.method != "GET" && request.method != "HEAD" && request.method != "POST" {
+        return Response::text(405, "method not allowed");
+    }
+
+    if let Some(endpoint) = request.path.strip_prefix("/rest/") {
+        return subsonic::handle(endpoint.trim_end_matches(".view"), request);
+    }
+    if let Some(track) = request.path.strip_prefix("/stream/") {
+        return stream::handle(track, request);
+    }
+    if request.path == "/metrics" {
+        return metrics::handle(request);
+    }
+    if request.path == "/feed.atom" {
+        return feed::handle(None, request);
+    }
+    if let Some(album) = request.path.strip_prefix("/covers/") {
+        return feed::handle(Some(album), request);
+    }
+
+    Response::not_found()
+}
+
+/// Names the route of a request for the metrics, without the parts that vary.
+fn route_name(path: &str) -> &'static str {
+    if path.starts_with("/rest/") {
+        "rest"
+    } else if path.starts_with("/stream/") {
+        "stream"
+    } else if path == "/metrics" {
+        "metrics"
+    } else if path == "/feed.atom" || path.starts_with("/covers/") {
+        "feed"
+    } else {
+        "other"
+    }
+}
+
+fn handle_connection(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match read_request(&mut reader)? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+
+    let response = route(&request);
+    metrics::record_request(route_name(&request.path), response.status);
+
+    let mut stream = stream;
+    write_response(&mut stream, &request, response)
+}
+
+/// Accepts connections on `listener` until `stop` is set.
+pub fn serve(listener: TcpListener, stop: Arc<AtomicBool>) -> io::Result<()> {
+    // Polling lets the loop notice `stop` without needing a connection to wake it up
+    listener.set_nonblocking(true)?;
+
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                thread::spawn(move || {
+                    if let Err(err) = handle_connection(stream) {
+                        println!("{}", tr!("server-connection", err = err));
+                    }
+                });
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(err) => println!("{}", tr!("server-accept", err = err)),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Shuffles spreading artists and albums out.
+//!
+//! A plain shuffle of a library with a few prolific artists plays them back to back. The smart
+//! shuffle draws a random order, weighted by rating or by how rarely tracks were played if
+//! asked, then takes the tracks in it skipping those of the artist or album just before, as
+//! long as another one is close enough.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How far ahead the smart shuffle looks for a track of another artist.
+const LOOKAHEAD: usize = 64;
+
+/// The stars of the unrated tracks, when weighting by rating.
+const UNRATED_STARS: f64 = 2.5;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Shuffle {
+    Random,
+    Smart,
+}
+
+impl Shuffle {
+    pub const ALL: &'static [&'static str] = &["random", "smart"];
+
+    pub fn parse(value: &str) -> Option<Shuffle> {
+        match value {
+            "random" => Some(Shuffle::Random),
+            "smart" => Some(Shuffle::Smart),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Weight {
+    Even,
+    /// Better rated tracks come first more often.
+    Rating,
+    /// Tracks played less come first more often.
+    Fresh,
+}
+
+impl Weight {
+    pub const ALL: &'static [&'static str] = &["rating", "fresh"];
+
+    pub fn parse(value: &str) -> Option<Weight> {
+        match value {
+            "rating" => Some(Weight::Rating),
+            "fresh" => Some(Weight::Fresh),
+            _ => None,
+        }
+    }
+}
+
+/// A track to shuffle, with what the shuffle looks at.
+pub struct Entry<T> {
+    pub value: T,
+    pub artist_id: Option<i64>,
+    pub album_id: Option<i64>,
+    /// In stars.
+    pub rating: Option<f64>,
+    pub play_count: i64,
+}
+
+impl<T> Entry<T> {
+    fn weight(&self, weight: Weight) -> f64 {
+        match weight {
+            Weight::Even => 1.0,
+            Weight::Rating => self.rating.unwrap_or(UNRATED_STARS).max(0.5),
+            Weight::Fresh => 1.0 / (1 + self.play_count.max(0)) as f64,
+        }
+    }
+}
+
+/// A linear congruential generator seeded with the time, no shuffle needs better.
+struct Random(u64);
+
+impl Random {
+    fn new() -> Random {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Random(seed)
+    }
+
+    /// Returns a number in ]0, 1].
+    fn next(&mut self) -> f64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Draws a random order where an entry is ahead of another as often as its weight is larger,
+/// giving each one a key of `u^(1/weight)`.
+fn weighted_order<T>(entries: Vec<Entry<T>>, weight: Weight) -> Vec<Entry<T>> {
+    let mut random = Random::new();
+    let mut keyed: Vec<(f64, Entry<T>)> = entries
+        .into_iter()
+        .map(|entry| (random.next().powf(1.0 / entry.weight(weight)), entry))
+        .collect();
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    keyed.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// Reorders entries so the same artist, or at least the same album, isn't played twice in a
+/// row when there's another close enough.
+fn spread<T>(entries: Vec<Entry<T>>) -> Vec<Entry<T>> {
+    let mut pending: VecDeque<Entry<T>> = entries.into();
+    let mut order: Vec<Entry<T>> = Vec::with_capacity(pending.len());
+
+    while !pending.is_empty() {
+        let i = match order.last() {
+            Some(last) => {
+                let ahead = || pending.iter().take(LOOKAHEAD);
+                ahead()
+                    .position(|entry| entry.artist_id != last.artist_id)
+                    .or_else(|| ahead().position(|entry| entry.album_id != last.album_id))
+                    .unwrap_or(0)
+            }
+            None => 0,
+        };
+        order.extend(pending.remove(i));
+    }
+
+    order
+}
+
+pub fn shuffle<T>(entries: Vec<Entry<T>>, shuffle: Shuffle, weight: Weight) -> Vec<Entry<T>> {
+    let order = weighted_order(entries, weight);
+    match shuffle {
+        Shuffle::Random => order,
+        Shuffle::Smart => spread(order),
+    }
+}
@@ -0,0 +1,154 @@
+//! Files going with the audio: lyrics (`.lrc`), cue sheets, `.nfo` files and artwork.
+//!
+//! A sidecar named after a track, like `01 Intro.lrc` next to `01 Intro.flac`, belongs to the
+//! track; the others in the folder of an album belong to the album. Scans record them in
+//! `sidecar`, imports carry them along with the audio files they place and `zik list albums`
+//! shows which albums have them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{archive, storage};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Lyrics,
+    Cue,
+    Nfo,
+    Artwork,
+}
+
+impl Kind {
+    pub const ALL: &'static [&'static str] = &["lyrics", "cue", "nfo", "artwork"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Lyrics => "lyrics",
+            Kind::Cue => "cue",
+            Kind::Nfo => "nfo",
+            Kind::Artwork => "artwork",
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Kind> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "lrc" => Some(Kind::Lyrics),
+            "cue" => Some(Kind::Cue),
+            "nfo" => Some(Kind::Nfo),
+            "jpg" | "jpeg" | "png" | "webp" | "gif" => Some(Kind::Artwork),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the sidecars in `dir`, unreadable folders having none.
+fn list_dir(dir: &Path) -> Vec<(Kind, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut sidecars: Vec<(Kind, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| {
+            let path = entry.path();
+            Kind::from_path(&path).map(|kind| (kind, path))
+        })
+        .collect();
+    sidecars.sort_by(|a, b| a.1.cmp(&b.1));
+
+    sidecars
+}
+
+/// Returns the sidecars of the track at `path`, the files next to it with its name.
+pub fn find_for_track(path: &Path) -> Vec<(Kind, PathBuf)> {
+    let (dir, stem) = match (path.parent(), path.file_stem()) {
+        (Some(dir), Some(stem)) => (dir, stem),
+        _ => return Vec::new(),
+    };
+
+    list_dir(dir)
+        .into_iter()
+        .filter(|(_, sidecar)| sidecar.file_stem() == Some(stem) && sidecar != path)
+        .collect()
+}
+
+/// Returns the sidecars of the album whose `tracks` are in `dir`, those not named after one
+/// of them.
+pub fn find_for_album(dir: &Path, tracks: &[PathBuf]) -> Vec<(Kind, PathBuf)> {
+    list_dir(dir)
+        .into_iter()
+        .filter(|(_, sidecar)| {
+            !tracks.iter().any(|track| {
+                track.parent() == Some(dir) && track.file_stem() == sidecar.file_stem()
+            })
+        })
+        .collect()
+}
+
+/// Records the sidecars of the tracks and albums in the library, returning how many there
+/// are.
+pub fn update(db: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    // The tracks of every folder, remote and archived files have no folder to look into
+    let mut folders: HashMap<PathBuf, Vec<(i64, Option<i64>, PathBuf)>> = HashMap::new();
+    {
+        let mut stmt = db.prepare(
+            "SELECT id, album_id, library_path(path) FROM track
+             WHERE path IS NOT NULL AND missing_since IS NULL",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(2)?;
+            if storage::is_remote(&path) || archive::split(Path::new(&path)).is_some() {
+                continue;
+            }
+            let path = PathBuf::from(path);
+            if let Some(dir) = path.parent() {
+                folders.entry(dir.to_path_buf()).or_default().push((
+                    row.get(0)?,
+                    row.get(1)?,
+                    path,
+                ));
+            }
+        }
+    }
+
+    db.execute("DELETE FROM sidecar", [])?;
+
+    let mut count = 0;
+    for (dir, tracks) in &folders {
+        // Loose files of a folder mixing albums belong to none of them
+        let album_id = tracks[0].1.filter(|album_id| {
+            tracks
+                .iter()
+                .all(|(_, track_album_id, _)| track_album_id == &Some(*album_id))
+        });
+
+        for (kind, path) in list_dir(dir) {
+            let track_id = tracks
+                .iter()
+                .find(|(_, _, track)| track.file_stem() == path.file_stem())
+                .map(|(track_id, _, _)| *track_id);
+            if track_id.is_none() && album_id.is_none() {
+                continue;
+            }
+
+            db.execute(
+                "INSERT INTO sidecar(path, kind, track_id, album_id)
+                 VALUES($path, $kind, $track_id, $album_id)",
+                rusqlite::params![
+                    crate::library::relative(&path),
+                    kind.name(),
+                    track_id,
+                    track_id.map_or(album_id, |_| None),
+                ],
+            )?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,503 @@
+//! A static website of the library, to share it read-only with a plain web server.
+//!
+//! Pages are named after the uids of what they show, so links to them survive rescans.
+//! Covers are scaled down to thumbnails with ffmpeg, or copied as they are without it.
+//! `feed.atom` lists the albums added recently.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::collation;
+use crate::export::CommandExportError;
+use crate::feed;
+use crate::ffmpeg::{self, FfmpegError};
+use crate::i18n::tr;
+use crate::links::{self, Service};
+use crate::subsonic::xml_escape;
+use crate::top::format_duration;
+
+const THUMBNAIL_SIZE: usize = 300;
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 60em; margin: 2em auto; padding: 0 1em; }
+a { color: inherit; }
+.muted { color: #777; }
+.albums { display: flex; flex-wrap: wrap; gap: 1.5em; padding: 0; list-style: none; }
+.albums li { width: 150px; }
+.albums img, .placeholder { display: block; width: 150px; height: 150px; object-fit: cover; background: #eee; }
+.cover { width: 300px; height: 300px; object-fit: cover; float: left; margin: 0 2em 2em 0; }
+table { border-collapse: collapse; }
+td { padding: 0.2em 0.8em 0.2em 0; }
+pre { white-space: pre-wrap; clear: both; }
+";
+
+struct Artist {
+    id: i64,
+    uid: String,
+    name: String,
+    sort_name: String,
+}
+
+struct Album {
+    id: i64,
+    uid: String,
+    name: String,
+    artist_id: Option<i64>,
+    year: Option<i64>,
+    release_type: Option<String>,
+    cover_path: Option<String>,
+    links: Vec<(Service, String)>,
+}
+
+struct Track {
+    uid: String,
+    name: String,
+    artist_id: Option<i64>,
+    album_id: Option<i64>,
+    disc_number: Option<i64>,
+    number: Option<i64>,
+    duration_ms: Option<i64>,
+    genre: Option<String>,
+    year: Option<i64>,
+    lyrics: Option<String>,
+    links: Vec<(Service, String)>,
+}
+
+/// What a site export wrote.
+pub struct Summary {
+    pub artists: usize,
+    pub albums: usize,
+    pub tracks: usize,
+}
+
+fn escape(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len());
+    xml_escape(value, &mut buf);
+    buf
+}
+
+/// Wraps the body of a page; `root` is the relative path to the top of the site.
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<link rel=\"stylesheet\" href=\"{root}style.css\">
+<link rel=\"alternate\" type=\"application/atom+xml\" title=\"Recently added albums\" href=\"{root}feed.atom\">
+</head>
+<body>
+<p><a href=\"{root}index.html\">All artists</a></p>
+{body}</body>
+</html>
+",
+        title = escape(title),
+        root = root,
+        body = body,
+    )
+}
+
+fn load_artists(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Artist>> {
+    let mut stmt = db.prepare(
+        "SELECT id, uid, name, sort_name FROM artist
+         WHERE name IS NOT NULL
+         ORDER BY sort_name COLLATE natural_sort",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let name: String = row.get(2)?;
+        let sort_name: Option<String> = row.get(3)?;
+        Ok(Artist {
+            id: row.get(0)?,
+            uid: row.get(1)?,
+            sort_name: sort_name.unwrap_or_else(|| name.clone()),
+            name,
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn load_albums(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Album>> {
+    let mut stmt = db.prepare(
+        "SELECT id, uid, name, artist_id, release_year, release_type, library_path(cover_path),
+                spotify_url, apple_music_url, bandcamp_url
+         FROM album
+         ORDER BY release_year, sort_name COLLATE natural_sort",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let name: Option<String> = row.get(2)?;
+        Ok(Album {
+            id: row.get(0)?,
+            uid: row.get(1)?,
+            name: name.unwrap_or_default(),
+            artist_id: row.get(3)?,
+            year: row.get(4)?,
+            release_type: row.get(5)?,
+            cover_path: row.get(6)?,
+            links: links::read(row, 7)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn load_tracks(db: &rusqlite::Connection) -> rusqlite::Result<Vec<Track>> {
+    let mut stmt = db.prepare(
+        "SELECT uid, name, artist_id, album_id, disc_number, number, duration_ms, genre,
+                release_year, lyrics, spotify_url, apple_music_url, bandcamp_url
+         FROM track
+         ORDER BY album_id, disc_number, number, name COLLATE natural_sort",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let name: Option<String> = row.get(1)?;
+        Ok(Track {
+            uid: row.get(0)?,
+            name: name.unwrap_or_default(),
+            artist_id: row.get(2)?,
+            album_id: row.get(3)?,
+            disc_number: row.get(4)?,
+            number: row.get(5)?,
+            duration_ms: row.get(6)?,
+            genre: row.get(7)?,
+            year: row.get(8)?,
+            lyrics: row.get(9)?,
+            links: links::read(row, 10)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Writes the thumbnail of the cover of album `uid` unless it's already up to date, and
+/// returns its path in the site.
+fn write_thumbnail(
+    dir: &Path,
+    cover: &Path,
+    uid: &str,
+    ffmpeg_missing: &mut bool,
+) -> io::Result<Option<String>> {
+    if !cover.exists() {
+        return Ok(None);
+    }
+
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    let is_fresh = |name: &str| match (modified(cover), modified(&dir.join(name))) {
+        (Ok(cover), Ok(thumbnail)) => thumbnail >= cover,
+        _ => false,
+    };
+
+    let name = format!("covers/{}.jpg", uid);
+    if !*ffmpeg_missing {
+        if is_fresh(&name) {
+            return Ok(Some(name));
+        }
+        match ffmpeg::thumbnail(cover, &dir.join(&name), THUMBNAIL_SIZE) {
+            Ok(()) => return Ok(Some(name)),
+            Err(FfmpegError::NotFound) => {
+                println!("{}", tr!("site-no-ffmpeg"));
+                *ffmpeg_missing = true;
+            }
+            Err(err) => println!("{}", tr!("site-scale", path = cover.display(), err = err)),
+        }
+    }
+
+    // The cover as it is, keeping its format
+    let extension = cover
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "jpg".to_owned());
+    let name = format!("covers/{}.{}", uid, extension);
+    if !is_fresh(&name) {
+        fs::copy(cover, dir.join(&name))?;
+    }
+
+    Ok(Some(name))
+}
+
+/// Links to the same music on streaming services, none without any.
+fn links_paragraph(links: &[(Service, String)]) -> String {
+    if links.is_empty() {
+        return String::new();
+    }
+
+    let links: Vec<String> = links
+        .iter()
+        .map(|(service, url)| format!("<a href=\"{}\">{}</a>", escape(url), service.title()))
+        .collect();
+    format!("<p class=\"muted\">Listen on {}</p>\n", links.join(", "))
+}
+
+fn album_list(albums: &[&Album], thumbnails: &HashMap<i64, String>) -> String {
+    let mut buf = String::from("<ul class=\"albums\">\n");
+    for album in albums {
+        let _ = write!(buf, "<li><a href=\"../albums/{}.html\">", album.uid);
+        match thumbnails.get(&album.id) {
+            Some(thumbnail) => {
+                let _ = write!(buf, "<img src=\"../{}\" alt=\"\">", thumbnail);
+            }
+            None => buf.push_str("<span class=\"placeholder\"></span>"),
+        }
+        let _ = writeln!(
+            buf,
+            "{}</a> <span class=\"muted\">{}</span></li>",
+            escape(&album.name),
+            album.year.map_or(String::new(), |year| year.to_string()),
+        );
+    }
+    buf.push_str("</ul>\n");
+    buf
+}
+
+/// Writes the site into `dir`, over the pages of a previous export.
+///
+/// The feed has the albums added in the last `feed_days` days; `base_url` is where the site
+/// is hosted, for feed readers that need absolute links.
+pub fn write_site(
+    db: &rusqlite::Connection,
+    dir: &Path,
+    feed_days: i64,
+    base_url: Option<&str>,
+) -> Result<Summary, CommandExportError> {
+    let artists = load_artists(db)?;
+    let albums = load_albums(db)?;
+    let tracks = load_tracks(db)?;
+    let locale = collation::Locale::load(db);
+
+    for subdir in ["artists", "albums", "tracks", "covers"] {
+        fs::create_dir_all(dir.join(subdir))?;
+    }
+    fs::write(dir.join("style.css"), STYLE)?;
+
+    let artist_by_id: HashMap<i64, &Artist> =
+        artists.iter().map(|artist| (artist.id, artist)).collect();
+    let album_by_id: HashMap<i64, &Album> = albums.iter().map(|album| (album.id, album)).collect();
+
+    let artist_link = |id: Option<i64>, root: &str| match id.and_then(|id| artist_by_id.get(&id)) {
+        Some(artist) => format!(
+            "<a href=\"{}artists/{}.html\">{}</a>",
+            root,
+            artist.uid,
+            escape(&artist.name)
+        ),
+        None => "Unknown artist".to_owned(),
+    };
+
+    // Covers
+    let mut thumbnails = HashMap::new();
+    let mut ffmpeg_missing = false;
+    for album in &albums {
+        if let Some(cover_path) = &album.cover_path {
+            let cover = Path::new(cover_path);
+            if let Some(name) = write_thumbnail(dir, cover, &album.uid, &mut ffmpeg_missing)? {
+                thumbnails.insert(album.id, name);
+            }
+        }
+    }
+
+    // Index of the artists, by letter
+    let mut body = String::from("<h1>Artists</h1>\n");
+    let mut letter = None;
+    for artist in &artists {
+        let artist_letter = artist
+            .sort_name
+            .chars()
+            .next()
+            .map(|c| collation::index_letter(locale, c));
+        if artist_letter != letter {
+            if letter.is_some() {
+                body.push_str("</ul>\n");
+            }
+            let _ = writeln!(
+                body,
+                "<h2>{}</h2>\n<ul>",
+                escape(artist_letter.as_deref().unwrap_or_default())
+            );
+            letter = artist_letter;
+        }
+        let _ = writeln!(body, "<li>{}</li>", artist_link(Some(artist.id), ""));
+    }
+    if letter.is_some() {
+        body.push_str("</ul>\n");
+    }
+    fs::write(dir.join("index.html"), page("Artists", "", &body))?;
+
+    // Artists
+    for artist in &artists {
+        let artist_albums: Vec<&Album> = albums
+            .iter()
+            .filter(|album| album.artist_id == Some(artist.id))
+            .collect();
+
+        let mut body = format!("<h1>{}</h1>\n", escape(&artist.name));
+        body.push_str(&album_list(&artist_albums, &thumbnails));
+
+        // Tracks on albums of other artists, like featurings and compilations
+        let elsewhere: Vec<&Track> = tracks
+            .iter()
+            .filter(|track| track.artist_id == Some(artist.id))
+            .filter(
+                |track| match track.album_id.and_then(|id| album_by_id.get(&id)) {
+                    Some(album) => album.artist_id != Some(artist.id),
+                    None => true,
+                },
+            )
+            .collect();
+        if !elsewhere.is_empty() {
+            body.push_str("<h2>Appears on</h2>\n<ul>\n");
+            for track in elsewhere {
+                let album = track.album_id.and_then(|id| album_by_id.get(&id));
+                let _ = writeln!(
+                    body,
+                    "<li><a href=\"../tracks/{}.html\">{}</a> <span class=\"muted\">{}</span></li>",
+                    track.uid,
+                    escape(&track.name),
+                    album.map_or(String::new(), |album| escape(&album.name)),
+                );
+            }
+            body.push_str("</ul>\n");
+        }
+
+        fs::write(
+            dir.join("artists").join(format!("{}.html", artist.uid)),
+            page(&artist.name, "../", &body),
+        )?;
+    }
+
+    // Albums
+    for album in &albums {
+        let mut body = String::new();
+        if let Some(thumbnail) = thumbnails.get(&album.id) {
+            let _ = writeln!(
+                body,
+                "<img class=\"cover\" src=\"../{}\" alt=\"\">",
+                thumbnail
+            );
+        }
+        let _ = writeln!(
+            body,
+            "<h1>{}</h1>\n<p>{}</p>\n<p class=\"muted\">{}</p>",
+            escape(&album.name),
+            artist_link(album.artist_id, "../"),
+            [
+                album.year.map(|year| year.to_string()),
+                album.release_type.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<String>>()
+            .join(", "),
+        );
+        body.push_str(&links_paragraph(&album.links));
+
+        body.push_str("<table>\n");
+        for track in tracks
+            .iter()
+            .filter(|track| track.album_id == Some(album.id))
+        {
+            let number = match (track.disc_number, track.number) {
+                (Some(disc), Some(number)) => format!("{}.{}", disc, number),
+                (None, Some(number)) => number.to_string(),
+                _ => String::new(),
+            };
+            // The artist only when it's not the album's, for compilations
+            let artist = if track.artist_id != album.artist_id {
+                format!(
+                    " <span class=\"muted\">{}</span>",
+                    artist_link(track.artist_id, "../")
+                )
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                body,
+                "<tr><td class=\"muted\">{}</td><td><a href=\"../tracks/{}.html\">{}</a>{}</td><td class=\"muted\">{}</td></tr>",
+                number,
+                track.uid,
+                escape(&track.name),
+                artist,
+                track.duration_ms.map_or(String::new(), format_track_duration),
+            );
+        }
+        body.push_str("</table>\n");
+
+        fs::write(
+            dir.join("albums").join(format!("{}.html", album.uid)),
+            page(&album.name, "../", &body),
+        )?;
+    }
+
+    // Tracks
+    for track in &tracks {
+        let album = track.album_id.and_then(|id| album_by_id.get(&id));
+
+        let mut body = String::new();
+        if let Some(thumbnail) = track.album_id.and_then(|id| thumbnails.get(&id)) {
+            let _ = writeln!(
+                body,
+                "<img class=\"cover\" src=\"../{}\" alt=\"\">",
+                thumbnail
+            );
+        }
+        let _ = writeln!(
+            body,
+            "<h1>{}</h1>\n<p>{}</p>",
+            escape(&track.name),
+            artist_link(track.artist_id, "../"),
+        );
+        if let Some(album) = album {
+            let _ = writeln!(
+                body,
+                "<p><a href=\"../albums/{}.html\">{}</a></p>",
+                album.uid,
+                escape(&album.name)
+            );
+        }
+        let details: Vec<String> = [
+            track.year.map(|year| year.to_string()),
+            track.genre.as_deref().map(escape),
+            track.duration_ms.map(format_track_duration),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let _ = writeln!(body, "<p class=\"muted\">{}</p>", details.join(", "));
+        body.push_str(&links_paragraph(&track.links));
+        if let Some(lyrics) = &track.lyrics {
+            let _ = writeln!(body, "<pre>{}</pre>", escape(lyrics));
+        }
+
+        fs::write(
+            dir.join("tracks").join(format!("{}.html", track.uid)),
+            page(&track.name, "../", &body),
+        )?;
+    }
+
+    let recent = feed::recent_albums(db, feed_days)?;
+    let atom = feed::atom(&recent, base_url, |album| {
+        (
+            Some(format!("albums/{}.html", album.uid)),
+            thumbnails.get(&album.id).cloned(),
+        )
+    });
+    fs::write(dir.join("feed.atom"), atom)?;
+
+    Ok(Summary {
+        artists: artists.len(),
+        albums: albums.len(),
+        tracks: tracks.len(),
+    })
+}
+
+/// Formats the duration of a track like "4:05", or like "1h 02m" for long mixes.
+fn format_track_duration(ms: i64) -> String {
+    let seconds = ms / 1000;
+    if seconds >= 3600 {
+        format_duration(ms)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
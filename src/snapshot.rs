@@ -0,0 +1,408 @@
+//! Snapshots of the database and the differences between them.
+//!
+//! A snapshot is a plain copy of the database in the `snapshots` folder of the data folder;
+//! diffing one against the current database is a quick way to check a scan or a migration
+//! of the library to another disk didn't lose anything.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::i18n::tr;
+
+/// The first schema version with track paths, which identify tracks across snapshots.
+const MIN_SCHEMA_VERSION: i64 = 5;
+
+pub enum CommandSnapshotError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    DataFolderNotFound,
+    AlreadyExists(String),
+    NotFound(String),
+    TooOld(String),
+}
+impl From<rusqlite::Error> for CommandSnapshotError {
+    fn from(err: rusqlite::Error) -> CommandSnapshotError {
+        CommandSnapshotError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandSnapshotError {
+    fn from(err: io::Error) -> CommandSnapshotError {
+        CommandSnapshotError::IO(err)
+    }
+}
+impl fmt::Display for CommandSnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandSnapshotError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandSnapshotError::IO(err) => f.write_str(&tr!("io-error", err = err)),
+            CommandSnapshotError::DataFolderNotFound => f.write_str(&tr!("data-folder-not-found")),
+            CommandSnapshotError::AlreadyExists(name) => {
+                f.write_str(&tr!("snapshot-already-exists", name = name))
+            }
+            CommandSnapshotError::NotFound(name) => {
+                f.write_str(&tr!("snapshot-not-found", name = name))
+            }
+            CommandSnapshotError::TooOld(name) => {
+                f.write_str(&tr!("snapshot-too-old", name = name))
+            }
+        }
+    }
+}
+
+fn snapshots_dir() -> Result<PathBuf, CommandSnapshotError> {
+    match crate::get_data_dir() {
+        Some(data_dir) => Ok(data_dir.join("snapshots")),
+        None => Err(CommandSnapshotError::DataFolderNotFound),
+    }
+}
+
+/// Resolves a snapshot name, or the path of any database file.
+fn resolve(name: &str) -> Result<PathBuf, CommandSnapshotError> {
+    let path = snapshots_dir()?.join(format!("{}.db", name));
+    if path.is_file() {
+        return Ok(path);
+    }
+
+    let path = Path::new(name);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+
+    Err(CommandSnapshotError::NotFound(name.to_owned()))
+}
+
+//
+// "snapshot" command
+//
+
+fn cmd_snapshot_create(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSnapshotError> {
+    let name = args.value_of("name").unwrap();
+
+    let dir = snapshots_dir()?;
+    fs::create_dir_all(&dir)?;
+
+    let path = dir.join(format!("{}.db", name));
+    if path.exists() {
+        return Err(CommandSnapshotError::AlreadyExists(name.to_owned()));
+    }
+
+    db.execute("VACUUM INTO $path", [path.to_string_lossy()])?;
+
+    println!(
+        "{}",
+        tr!("snapshot-created", name = name, path = path.display())
+    );
+
+    Ok(())
+}
+
+fn cmd_snapshot_list() -> Result<(), CommandSnapshotError> {
+    let dir = snapshots_dir()?;
+
+    let mut names = Vec::new();
+    if dir.is_dir() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("db") {
+                continue;
+            }
+            if let Some(name) = path.file_stem() {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+    names.sort();
+
+    if names.is_empty() {
+        println!("{}", tr!("snapshot-none"));
+    }
+    for name in names {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+fn cmd_snapshot_remove(args: &clap::ArgMatches) -> Result<(), CommandSnapshotError> {
+    let name = args.value_of("name").unwrap();
+
+    let path = snapshots_dir()?.join(format!("{}.db", name));
+    if !path.is_file() {
+        return Err(CommandSnapshotError::NotFound(name.to_owned()));
+    }
+    fs::remove_file(&path)?;
+
+    println!("{}", tr!("snapshot-removed", name = name));
+
+    Ok(())
+}
+
+pub fn cmd_snapshot(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSnapshotError> {
+    match args.subcommand() {
+        Some(("create", sub_args)) => cmd_snapshot_create(db, sub_args),
+        Some(("list", _)) => cmd_snapshot_list(),
+        Some(("remove", sub_args)) => cmd_snapshot_remove(sub_args),
+        _ => Ok(()),
+    }
+}
+
+//
+// "diff" command
+//
+
+struct Track {
+    name: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    number: Option<i64>,
+    disc_number: Option<i64>,
+    year: Option<i64>,
+    genre: Option<String>,
+    hash: Option<String>,
+}
+
+#[derive(PartialEq)]
+struct Album {
+    year: Option<i64>,
+    tracks: i64,
+}
+
+/// Tracks keyed by path, albums keyed by artist and name.
+struct Library {
+    tracks: BTreeMap<String, Track>,
+    albums: BTreeMap<(String, String), Album>,
+}
+
+/// Loads the library stored in the attached database `schema`.
+///
+/// Only columns present since `MIN_SCHEMA_VERSION` are read, so snapshots taken by older
+/// versions of zik can still be compared.
+fn load_library(db: &rusqlite::Connection, schema: &str) -> rusqlite::Result<Library> {
+    let mut tracks = BTreeMap::new();
+    {
+        let query = format!(
+            // Older snapshots have absolute paths, which are left as they are
+            "SELECT library_path(track.path), track.name, artist.name, album.name, track.number,
+                    track.disc_number, track.release_year, track.genre, track.hash
+             FROM {schema}.track
+             LEFT JOIN {schema}.artist ON artist.id = track.artist_id
+             LEFT JOIN {schema}.album ON album.id = track.album_id
+             WHERE track.path IS NOT NULL",
+            schema = schema,
+        );
+        let mut stmt = db.prepare(&query)?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            tracks.insert(
+                row.get(0)?,
+                Track {
+                    name: row.get(1)?,
+                    artist: row.get(2)?,
+                    album: row.get(3)?,
+                    number: row.get(4)?,
+                    disc_number: row.get(5)?,
+                    year: row.get(6)?,
+                    genre: row.get(7)?,
+                    hash: row.get(8)?,
+                },
+            );
+        }
+    }
+
+    let mut albums = BTreeMap::new();
+    {
+        let query = format!(
+            "SELECT artist.name, album.name, album.release_year, COUNT(track.id)
+             FROM {schema}.album
+             LEFT JOIN {schema}.artist ON artist.id = album.artist_id
+             LEFT JOIN {schema}.track ON track.album_id = album.id
+             GROUP BY album.id",
+            schema = schema,
+        );
+        let mut stmt = db.prepare(&query)?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let artist: Option<String> = row.get(0)?;
+            let name: Option<String> = row.get(1)?;
+            albums.insert(
+                (artist.unwrap_or_default(), name.unwrap_or_default()),
+                Album {
+                    year: row.get(2)?,
+                    tracks: row.get(3)?,
+                },
+            );
+        }
+    }
+
+    Ok(Library { tracks, albums })
+}
+
+fn attach(db: &rusqlite::Connection, name: &str, schema: &str) -> Result<(), CommandSnapshotError> {
+    let path = resolve(name)?;
+
+    db.execute(
+        &format!("ATTACH DATABASE $path AS {}", schema),
+        [path.to_string_lossy()],
+    )?;
+
+    let version: i64 = db.query_row(&format!("PRAGMA {}.user_version", schema), [], |row| {
+        row.get(0)
+    })?;
+    if version < MIN_SCHEMA_VERSION {
+        return Err(CommandSnapshotError::TooOld(name.to_owned()));
+    }
+
+    Ok(())
+}
+
+fn describe_value<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", value),
+        None => "none".to_owned(),
+    }
+}
+
+/// Lists the fields that differ between two versions of a track.
+fn track_changes(old: &Track, new: &Track) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    let mut compare = |field: &str, old: String, new: String| {
+        if old != new {
+            changes.push(format!("{} {} -> {}", field, old, new));
+        }
+    };
+
+    compare("name", describe_value(&old.name), describe_value(&new.name));
+    compare(
+        "artist",
+        describe_value(&old.artist),
+        describe_value(&new.artist),
+    );
+    compare(
+        "album",
+        describe_value(&old.album),
+        describe_value(&new.album),
+    );
+    compare(
+        "number",
+        describe_value(&old.number),
+        describe_value(&new.number),
+    );
+    compare(
+        "disc",
+        describe_value(&old.disc_number),
+        describe_value(&new.disc_number),
+    );
+    compare("year", describe_value(&old.year), describe_value(&new.year));
+    compare(
+        "genre",
+        describe_value(&old.genre),
+        describe_value(&new.genre),
+    );
+
+    // A missing hash only means the job didn't run yet
+    if old.hash.is_some() && new.hash.is_some() && old.hash != new.hash {
+        changes.push("content changed".to_owned());
+    }
+
+    changes
+}
+
+pub fn cmd_diff(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSnapshotError> {
+    let from = args.value_of("from").unwrap();
+    attach(db, from, "snapshot_from")?;
+    let old = load_library(db, "snapshot_from")?;
+
+    let new = match args.value_of("to") {
+        Some(to) => {
+            attach(db, to, "snapshot_to")?;
+            load_library(db, "snapshot_to")?
+        }
+        None => load_library(db, "main")?,
+    };
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+
+    for ((artist, name), album) in &new.albums {
+        match old.albums.get(&(artist.clone(), name.clone())) {
+            None => {
+                println!("+ album {} - {}", artist, name);
+                added += 1;
+            }
+            Some(old_album) if old_album != album => {
+                let mut changes = Vec::new();
+                if old_album.year != album.year {
+                    changes.push(format!(
+                        "year {} -> {}",
+                        describe_value(&old_album.year),
+                        describe_value(&album.year)
+                    ));
+                }
+                if old_album.tracks != album.tracks {
+                    changes.push(format!("tracks {} -> {}", old_album.tracks, album.tracks));
+                }
+                println!("~ album {} - {}: {}", artist, name, changes.join(", "));
+                changed += 1;
+            }
+            Some(_) => (),
+        }
+    }
+    for (artist, name) in old.albums.keys() {
+        if !new.albums.contains_key(&(artist.clone(), name.clone())) {
+            println!("- album {} - {}", artist, name);
+            removed += 1;
+        }
+    }
+    let albums = (added, removed, changed);
+
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+
+    for (path, track) in &new.tracks {
+        match old.tracks.get(path) {
+            None => {
+                println!("+ track {}", path);
+                added += 1;
+            }
+            Some(old_track) => {
+                let changes = track_changes(old_track, track);
+                if !changes.is_empty() {
+                    println!("~ track {}: {}", path, changes.join(", "));
+                    changed += 1;
+                }
+            }
+        }
+    }
+    for path in old.tracks.keys() {
+        if !new.tracks.contains_key(path) {
+            println!("- track {}", path);
+            removed += 1;
+        }
+    }
+
+    println!(
+        "{}",
+        tr!(
+            "snapshot-diff-summary",
+            albums_added = albums.0,
+            albums_removed = albums.1,
+            albums_changed = albums.2,
+            tracks_added = added,
+            tracks_removed = removed,
+            tracks_changed = changed
+        )
+    );
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Where tracks come from, like "bandcamp", "CD rip" or "vinyl rip 24/96", and when they were
+//! first seen by a scan.
+//!
+//! Imports set the source of the files they place, with `--source`, the store of a profile or
+//! "CD rip" for `zik rip`. Other tracks get theirs from the `source_rules` config key, patterns
+//! of paths in the library, with `*` and `?` wildcards, tried in order after every scan:
+//! `zik config source_rules "Vinyl/*=vinyl rip 24/96;Bandcamp/*=bandcamp"`. A source is never
+//! changed by a rule once set.
+
+use std::path::PathBuf;
+
+use crate::i18n::tr;
+use crate::ignore;
+
+/// The source of the tracks ripped with `zik rip`.
+pub const CD_RIP: &str = "CD rip";
+
+pub struct SourceRules {
+    rules: Vec<(Vec<char>, String)>,
+}
+
+impl SourceRules {
+    /// Parses rules like "Vinyl/*=vinyl rip 24/96;Bandcamp/*=bandcamp".
+    pub fn parse(value: &str) -> Result<SourceRules, String> {
+        let mut rules = Vec::new();
+        for part in value
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let (pattern, source) = match part.split_once('=') {
+                Some((pattern, source)) => (pattern.trim(), source.trim()),
+                None => return Err(tr!("source-invalid-rule", value = part)),
+            };
+            if pattern.is_empty() || source.is_empty() {
+                return Err(tr!("source-invalid-rule", value = part));
+            }
+            rules.push((
+                pattern.trim_matches('/').to_lowercase().chars().collect(),
+                source.to_owned(),
+            ));
+        }
+
+        Ok(SourceRules { rules })
+    }
+
+    fn load(db: &rusqlite::Connection) -> rusqlite::Result<SourceRules> {
+        let value = crate::get_config_value(db, "source_rules")?;
+        // Validated when set
+        Ok(SourceRules::parse(value.as_deref().unwrap_or_default())
+            .unwrap_or(SourceRules { rules: Vec::new() }))
+    }
+
+    /// Returns the source of the first rule matching `path`, relative to the library.
+    fn find(&self, path: &str) -> Option<&str> {
+        let path: Vec<char> = path.to_lowercase().chars().collect();
+        self.rules
+            .iter()
+            .find(|(pattern, _)| ignore::matches(pattern, &path))
+            .map(|(_, source)| source.as_str())
+    }
+}
+
+/// Sets the source of the tracks without one from the rules, returning how many were set.
+pub fn apply_rules(db: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    let rules = SourceRules::load(db)?;
+    let library = match crate::library::location(db)? {
+        Some(library) if !rules.rules.is_empty() => library,
+        _ => return Ok(0),
+    };
+    let library = library.trim_end_matches('/');
+
+    let tracks: Vec<(i64, String)> = {
+        let mut stmt = db.prepare(
+            "SELECT id, library_path(path) FROM track WHERE source IS NULL AND path IS NOT NULL",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    let mut updated = 0;
+    for (id, path) in tracks {
+        let relative = match path
+            .strip_prefix(library)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(relative) => relative,
+            None => continue,
+        };
+        if let Some(source) = rules.find(relative) {
+            db.execute(
+                "UPDATE track SET source = $source WHERE id = $id",
+                rusqlite::params![source, id],
+            )?;
+            updated += 1;
+        }
+    }
+
+    Ok(updated)
+}
+
+/// Sets the source of the tracks of the files just imported.
+pub fn set_for_paths(
+    db: &rusqlite::Connection,
+    paths: &[PathBuf],
+    source: &str,
+) -> rusqlite::Result<()> {
+    for path in paths {
+        db.execute(
+            "UPDATE track SET source = $source WHERE path = $path",
+            rusqlite::params![source, crate::library::relative(path)],
+        )?;
+    }
+
+    Ok(())
+}
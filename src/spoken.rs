@@ -0,0 +1,577 @@
+//! The "spoken word" mode for audiobooks and podcasts.
+//!
+//! Folders of the library listed in the `spoken_word` configuration key hold spoken word
+//! tracks: their chapters are read at scan time, a resume position can be stored for each
+//! of them, and they're left out of random selections unless `shuffle_spoken_word` is set.
+//!
+//! Chapters are read from ID3v2 `CHAP` frames and from the Nero `chpl` atom of MP4 files;
+//! QuickTime chapter tracks aren't supported. They're stored for music too, where they're
+//! the songs of a mix.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::i18n::tr;
+use crate::tracklist;
+use crate::TrackID;
+
+pub struct Chapter {
+    pub start_ms: u64,
+    pub end_ms: Option<u64>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+//
+// ID3v2 chapters
+//
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, b| (acc << 7) | (*b as usize & 0x7f))
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Decodes the content of an ID3v2 text frame.
+fn decode_id3_text(data: &[u8]) -> Option<String> {
+    let (encoding, text) = data.split_first()?;
+
+    let text = match encoding {
+        0 => text.iter().map(|b| *b as char).collect(),
+        1 | 2 => {
+            let (big_endian, text) = match text {
+                [0xff, 0xfe, rest @ ..] => (false, rest),
+                [0xfe, 0xff, rest @ ..] => (true, rest),
+                _ => (*encoding == 2, text),
+            };
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|pair| {
+                    if big_endian {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+
+    let text = text.trim_end_matches('\0').trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_owned())
+    }
+}
+
+/// Iterates over the frames of an ID3v2 tag body as (id, content) pairs.
+fn id3_frames(data: &[u8], version: u8) -> Vec<(&[u8], &[u8])> {
+    let mut frames = Vec::new();
+
+    let mut pos = 0;
+    while pos + 10 <= data.len() {
+        let id = &data[pos..pos + 4];
+        // The rest of the tag is padding
+        if id[0] == 0 {
+            break;
+        }
+
+        let size = if version >= 4 {
+            syncsafe(&data[pos + 4..pos + 8])
+        } else {
+            be32(&data[pos + 4..pos + 8]) as usize
+        };
+
+        let start = pos + 10;
+        let end = match start.checked_add(size) {
+            Some(end) if end <= data.len() => end,
+            _ => break,
+        };
+        frames.push((id, &data[start..end]));
+
+        pos = end;
+    }
+
+    frames
+}
+
+fn parse_id3_chapter(content: &[u8], version: u8) -> Option<Chapter> {
+    // The element ID comes first, then the start and end times and byte offsets
+    let element_id_len = content.iter().position(|b| *b == 0)?;
+    let times = content.get(element_id_len + 1..element_id_len + 17)?;
+
+    let frames = id3_frames(&content[element_id_len + 17..], version);
+    let text = |frame_id: &[u8]| {
+        frames
+            .iter()
+            .find(|(id, _)| *id == frame_id)
+            .and_then(|(_, content)| decode_id3_text(content))
+    };
+
+    Some(Chapter {
+        start_ms: be32(&times[0..4]) as u64,
+        end_ms: Some(be32(&times[4..8]) as u64),
+        title: text(b"TIT2"),
+        artist: text(b"TPE1"),
+    })
+}
+
+fn read_id3_chapters(file: &mut fs::File) -> io::Result<Vec<Chapter>> {
+    let mut header = [0; 10];
+    file.seek(io::SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+
+    let version = header[3];
+    let flags = header[5];
+    if version < 3 {
+        // ID3v2.2 has no chapters
+        return Ok(Vec::new());
+    }
+
+    let mut data = vec![0; syncsafe(&header[6..10])];
+    file.read_exact(&mut data)?;
+
+    let mut body = &data[..];
+    if flags & 0x40 != 0 && body.len() >= 4 {
+        let extended_size = if version >= 4 {
+            syncsafe(&body[0..4])
+        } else {
+            be32(&body[0..4]) as usize + 4
+        };
+        body = body.get(extended_size..).unwrap_or_default();
+    }
+
+    let mut chapters: Vec<Chapter> = id3_frames(body, version)
+        .into_iter()
+        .filter(|(id, _)| *id == b"CHAP")
+        .filter_map(|(_, content)| parse_id3_chapter(content, version))
+        .collect();
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+
+    Ok(chapters)
+}
+
+//
+// MP4 chapters
+//
+
+/// Returns the content of the first box of type `kind` in `data`.
+fn find_mp4_box<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = be32(&data[pos..pos + 4]) as usize;
+        let (header_len, size) = match size {
+            0 => (8, data.len() - pos),
+            1 => {
+                let large = data.get(pos + 8..pos + 16)?;
+                let large = u64::from_be_bytes(large.try_into().ok()?) as usize;
+                (16, large)
+            }
+            n => (8, n),
+        };
+        if size < header_len || pos + size > data.len() {
+            return None;
+        }
+
+        if &data[pos + 4..pos + 8] == kind {
+            return Some(&data[pos + header_len..pos + size]);
+        }
+        pos += size;
+    }
+
+    None
+}
+
+/// Parses a Nero `chpl` atom, whose start times are in 100ns units.
+fn parse_chpl(data: &[u8]) -> Option<Vec<Chapter>> {
+    let version = *data.first()?;
+    let mut pos = if version > 0 { 8 } else { 4 };
+
+    let count = *data.get(pos)? as usize;
+    pos += 1;
+
+    let mut chapters: Vec<Chapter> = Vec::with_capacity(count);
+    for _ in 0..count {
+        let start = u64::from_be_bytes(data.get(pos..pos + 8)?.try_into().ok()?);
+        let title_len = *data.get(pos + 8)? as usize;
+        let title = data.get(pos + 9..pos + 9 + title_len)?;
+        pos += 9 + title_len;
+
+        let start_ms = start / 10_000;
+        if let Some(previous) = chapters.last_mut() {
+            previous.end_ms = Some(start_ms);
+        }
+
+        let title = String::from_utf8_lossy(title).trim().to_owned();
+        chapters.push(Chapter {
+            start_ms,
+            end_ms: None,
+            title: if title.is_empty() { None } else { Some(title) },
+            artist: None,
+        });
+    }
+
+    Some(chapters)
+}
+
+fn read_mp4_chapters(file: &mut fs::File) -> io::Result<Vec<Chapter>> {
+    let len = file.metadata()?.len();
+
+    // The moov box can be anywhere, often after the media data
+    let mut pos = 0;
+    while pos + 8 <= len {
+        let mut header = [0; 16];
+        file.seek(io::SeekFrom::Start(pos))?;
+        file.read_exact(&mut header[..8])?;
+
+        let (header_len, size) = match be32(&header[0..4]) {
+            0 => (8, len - pos),
+            1 => {
+                file.read_exact(&mut header[8..16])?;
+                (16, u64::from_be_bytes(header[8..16].try_into().unwrap()))
+            }
+            n => (8, n as u64),
+        };
+        if size < header_len || pos + size > len {
+            break;
+        }
+
+        if &header[4..8] == b"moov" {
+            let mut data = vec![0; (size - header_len) as usize];
+            file.read_exact(&mut data)?;
+
+            let chapters = find_mp4_box(&data, b"udta")
+                .and_then(|udta| find_mp4_box(udta, b"chpl"))
+                .and_then(parse_chpl)
+                .unwrap_or_default();
+            return Ok(chapters);
+        }
+
+        pos += size;
+    }
+
+    Ok(Vec::new())
+}
+
+/// Reads the chapter markers of an MP3 or MP4 file.
+pub fn read_chapters(path: &Path) -> io::Result<Vec<Chapter>> {
+    let mut file = fs::File::open(path)?;
+
+    let mut magic = [0; 8];
+    if file.read(&mut magic)? < magic.len() {
+        return Ok(Vec::new());
+    }
+
+    if &magic[0..3] == b"ID3" {
+        read_id3_chapters(&mut file)
+    } else if &magic[4..8] == b"ftyp" {
+        read_mp4_chapters(&mut file)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+//
+// Scan
+//
+
+/// The folders holding spoken word tracks.
+pub struct SpokenWordFolders {
+    folders: Vec<PathBuf>,
+}
+
+impl SpokenWordFolders {
+    /// Resolves the folders of the `spoken_word` configuration key against the library.
+    pub fn load(db: &rusqlite::Connection, library: &Path) -> rusqlite::Result<SpokenWordFolders> {
+        let folders = crate::get_config_value(db, "spoken_word")?
+            .unwrap_or_default()
+            .split(':')
+            .map(str::trim)
+            .filter(|folder| !folder.is_empty())
+            .map(|folder| library.join(folder))
+            .collect();
+
+        Ok(SpokenWordFolders { folders })
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.folders.iter().any(|folder| path.starts_with(folder))
+    }
+}
+
+pub enum SpokenWordError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+}
+impl From<rusqlite::Error> for SpokenWordError {
+    fn from(err: rusqlite::Error) -> SpokenWordError {
+        SpokenWordError::SQLite(err)
+    }
+}
+impl From<io::Error> for SpokenWordError {
+    fn from(err: io::Error) -> SpokenWordError {
+        SpokenWordError::IO(err)
+    }
+}
+impl fmt::Display for SpokenWordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpokenWordError::SQLite(err) => write!(f, "{}", err),
+            SpokenWordError::IO(err) => f.write_str(&tr!("spoken-read-chapters", err = err)),
+        }
+    }
+}
+
+/// Marks a scanned track as spoken word or not, and stores its chapters.
+///
+/// Without chapters in the file, the tracklist next to it is used. Returns the number of
+/// chapters found.
+pub fn update_track(
+    db: &rusqlite::Connection,
+    track_id: TrackID,
+    path: &Path,
+    spoken_word: bool,
+) -> Result<usize, SpokenWordError> {
+    db.execute(
+        "UPDATE track SET spoken_word = $spoken_word WHERE id = $id",
+        rusqlite::params![spoken_word, track_id],
+    )?;
+    db.execute("DELETE FROM chapter WHERE track_id = $id", [track_id])?;
+
+    let mut chapters = match read_chapters(path) {
+        Ok(chapters) => chapters,
+        // Music rarely has chapters, it's not worth failing the scan over
+        Err(_) if !spoken_word => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if chapters.is_empty() {
+        chapters = tracklist::for_track(path);
+    }
+
+    // The songs of a mix are usually titled "Artist - Title"
+    if !spoken_word {
+        for chapter in chapters
+            .iter_mut()
+            .filter(|chapter| chapter.artist.is_none())
+        {
+            if let Some((artist, title)) =
+                chapter.title.as_deref().and_then(tracklist::split_artist)
+            {
+                chapter.artist = Some(artist);
+                chapter.title = Some(title);
+            }
+        }
+    }
+
+    let mut stmt = db.prepare(
+        "INSERT INTO chapter(track_id, number, start_ms, end_ms, title, artist)
+         VALUES($track_id, $number, $start_ms, $end_ms, $title, $artist)",
+    )?;
+    for (i, chapter) in chapters.iter().enumerate() {
+        stmt.execute(rusqlite::params![
+            track_id,
+            i + 1,
+            chapter.start_ms as i64,
+            chapter.end_ms.map(|ms| ms as i64),
+            chapter.title,
+            chapter.artist,
+        ])?;
+    }
+
+    Ok(chapters.len())
+}
+
+//
+// "spoken" command
+//
+
+pub enum CommandSpokenError {
+    SQLite(rusqlite::Error),
+    TrackNotFound(String),
+    InvalidPosition(String),
+}
+impl From<rusqlite::Error> for CommandSpokenError {
+    fn from(err: rusqlite::Error) -> CommandSpokenError {
+        CommandSpokenError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandSpokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandSpokenError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandSpokenError::TrackNotFound(value) => {
+                f.write_str(&tr!("track-id-not-found", value = value))
+            }
+            CommandSpokenError::InvalidPosition(value) => {
+                f.write_str(&tr!("spoken-invalid-position", value = value))
+            }
+        }
+    }
+}
+
+/// Parses a position given in seconds or as "h:mm:ss" / "m:ss", into milliseconds.
+fn parse_position(value: &str) -> Option<u64> {
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let n: f64 = part.trim().parse().ok()?;
+        if n < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + n;
+    }
+
+    Some((seconds * 1000.0).round() as u64)
+}
+
+/// Formats milliseconds as "h:mm:ss".
+pub fn format_position(ms: i64) -> String {
+    let seconds = ms / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+fn get_track_id(db: &rusqlite::Connection, value: &str) -> Result<TrackID, CommandSpokenError> {
+    match crate::find_track_id(db, value)? {
+        Some(id) => Ok(id),
+        None => Err(CommandSpokenError::TrackNotFound(value.to_owned())),
+    }
+}
+
+fn cmd_spoken_list(db: &rusqlite::Connection) -> Result<(), CommandSpokenError> {
+    let query = "
+        SELECT track.id, track.name, artist.name, resume_position.position_ms,
+               (SELECT COUNT(*) FROM chapter WHERE chapter.track_id = track.id)
+        FROM track
+        LEFT JOIN artist ON artist.id = track.artist_id
+        LEFT JOIN resume_position ON resume_position.track_id = track.id
+        WHERE track.spoken_word
+        ORDER BY artist.name COLLATE natural_sort, track.name COLLATE natural_sort";
+
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let artist: Option<String> = row.get(2)?;
+        let position: Option<i64> = row.get(3)?;
+        let chapters: i64 = row.get(4)?;
+
+        println!(
+            "{} - {} (id={}), {}{}",
+            artist.unwrap_or_default(),
+            name.unwrap_or_default(),
+            id,
+            tr!("spoken-chapters", chapters = chapters),
+            position
+                .map(|ms| format!(
+                    ", {}",
+                    tr!("spoken-resume-at", position = format_position(ms))
+                ))
+                .unwrap_or_default(),
+        );
+    }
+
+    Ok(())
+}
+
+fn cmd_spoken_chapters(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSpokenError> {
+    let track_id = get_track_id(db, args.value_of("track").unwrap())?;
+
+    let mut stmt = db.prepare(
+        "SELECT number, start_ms, title, artist FROM chapter WHERE track_id = $id ORDER BY number",
+    )?;
+    let mut rows = stmt.query([track_id])?;
+
+    while let Some(row) = rows.next()? {
+        let number: i64 = row.get(0)?;
+        let start_ms: i64 = row.get(1)?;
+        let title: Option<String> = row.get(2)?;
+        let artist: Option<String> = row.get(3)?;
+
+        let title = match artist {
+            Some(artist) => format!("{} - {}", artist, title.unwrap_or_default()),
+            None => title.unwrap_or_default(),
+        };
+        println!("{:>3}. {} {}", number, format_position(start_ms), title);
+    }
+
+    Ok(())
+}
+
+fn cmd_spoken_resume(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSpokenError> {
+    let track_id = get_track_id(db, args.value_of("track").unwrap())?;
+
+    if args.is_present("clear") {
+        db.execute(
+            "DELETE FROM resume_position WHERE track_id = $id",
+            [track_id],
+        )?;
+        return Ok(());
+    }
+
+    match args.value_of("position") {
+        Some(value) => {
+            let position = match parse_position(value) {
+                Some(position) => position,
+                None => return Err(CommandSpokenError::InvalidPosition(value.to_owned())),
+            };
+
+            db.execute(
+                "INSERT INTO resume_position(track_id, position_ms) VALUES($id, $position)
+                 ON CONFLICT(track_id) DO UPDATE SET
+                   position_ms = excluded.position_ms,
+                   updated_at = unixepoch()",
+                rusqlite::params![track_id, position as i64],
+            )?;
+        }
+        None => {
+            let result = db.query_row(
+                "SELECT position_ms FROM resume_position WHERE track_id = $id",
+                [track_id],
+                |row| row.get(0),
+            );
+            match result {
+                Ok(position) => println!("{}", format_position(position)),
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    println!("{}", tr!("spoken-no-position"))
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cmd_spoken(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandSpokenError> {
+    match args.subcommand() {
+        Some(("list", _)) => cmd_spoken_list(db),
+        Some(("chapters", sub_args)) => cmd_spoken_chapters(db, sub_args),
+        Some(("resume", sub_args)) => cmd_spoken_resume(db, sub_args),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,494 @@
+//! The `/stream/{track}` endpoint: the file of a track with support for range requests, or
+//! a transcode of it with `?format=opus&bitrate=128` for clients that can't play the
+//! original. Transcodes can be normalized, see `replaygain`.
+//!
+//! Transcodes are made on the fly by ffmpeg. Finished ones are kept in a small in-memory
+//! LRU cache so that seeking in them, which clients do with range requests, doesn't start
+//! a new transcode every time.
+
+use std::fs;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use crate::archive;
+use crate::ffmpeg;
+use crate::i18n::tr;
+use crate::pool;
+use crate::replaygain::{self, GainMode};
+use crate::server::{Request, Response};
+use crate::storage;
+use crate::subsonic;
+use crate::user;
+
+/// How much memory the cached transcodes can use in total.
+const CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Transcodes bigger than this aren't cached, they would evict everything else.
+const MAX_CACHED_TRANSCODE: usize = CACHE_SIZE / 4;
+
+#[derive(Clone, PartialEq)]
+struct CacheKey {
+    track_id: i64,
+    format: String,
+    bitrate: usize,
+    gain_db: Option<f64>,
+}
+
+/// The transcode cache, least recently used first.
+struct TranscodeCache {
+    entries: Vec<(CacheKey, Arc<Vec<u8>>)>,
+    size: usize,
+}
+
+impl TranscodeCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<Vec<u8>>> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(i);
+        let data = entry.1.clone();
+        self.entries.push(entry);
+        Some(data)
+    }
+
+    fn insert(&mut self, key: CacheKey, data: Vec<u8>) {
+        if let Some(i) = self.entries.iter().position(|(k, _)| *k == key) {
+            let (_, old) = self.entries.remove(i);
+            self.size -= old.len();
+        }
+
+        while self.size + data.len() > CACHE_SIZE && !self.entries.is_empty() {
+            let (_, evicted) = self.entries.remove(0);
+            self.size -= evicted.len();
+        }
+
+        self.size += data.len();
+        self.entries.push((key, Arc::new(data)));
+    }
+}
+
+static CACHE: Mutex<TranscodeCache> = Mutex::new(TranscodeCache {
+    entries: Vec::new(),
+    size: 0,
+});
+
+/// The output of a running transcode, copied into the cache once it's complete.
+struct Transcoding {
+    key: CacheKey,
+    child: process::Child,
+    stdout: process::ChildStdout,
+    buf: Option<Vec<u8>>,
+}
+
+impl Read for Transcoding {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(out)?;
+
+        if n == 0 {
+            // Only a transcode that ran to completion can be served again
+            if let Some(buf) = self.buf.take() {
+                if self.child.wait()?.success() {
+                    if let Ok(mut cache) = CACHE.lock() {
+                        cache.insert(self.key.clone(), buf);
+                    }
+                }
+            }
+        } else if let Some(buf) = &mut self.buf {
+            if buf.len() + n > MAX_CACHED_TRANSCODE {
+                self.buf = None;
+            } else {
+                buf.extend_from_slice(&out[..n]);
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+impl Drop for Transcoding {
+    fn drop(&mut self) {
+        // The client went away before the end, there's no point in finishing
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Range {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header into an inclusive range of bytes of a body of `len` bytes.
+///
+/// Only single ranges are supported; anything else, or an invalid range, gets the whole body,
+/// as allowed by RFC 9110.
+fn parse_range(header: Option<&str>, len: u64) -> Range {
+    // Only digits, where parse would take a sign
+    let position = |value: &str| -> Option<u64> {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        value.parse().ok()
+    };
+
+    let spec = match header.and_then(|value| value.trim().strip_prefix("bytes=")) {
+        Some(spec) if !spec.contains(',') => spec,
+        _ => return Range::Full,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => return Range::Full,
+    };
+
+    let (start, end) = if start.is_empty() {
+        // A suffix range, the last `end` bytes
+        match position(end) {
+            Some(0) => return Range::Unsatisfiable,
+            Some(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+            None => return Range::Full,
+        }
+    } else {
+        let start = match position(start) {
+            Some(start) => start,
+            None => return Range::Full,
+        };
+        let end = if end.is_empty() {
+            len.saturating_sub(1)
+        } else {
+            match position(end) {
+                // The last byte before the first is no range at all
+                Some(end) if end < start => return Range::Full,
+                Some(end) => end.min(len.saturating_sub(1)),
+                None => return Range::Full,
+            }
+        };
+        (start, end)
+    };
+
+    if len == 0 || start >= len {
+        return Range::Unsatisfiable;
+    }
+
+    Range::Partial(start, end)
+}
+
+fn unsatisfiable(len: u64) -> Response {
+    Response::text(416, "range not satisfiable")
+        .header("Content-Range", &format!("bytes */{}", len))
+}
+
+/// Serves a file, or the part of it asked for by the `Range` header of the request.
+pub fn file_response(
+    db: &rusqlite::Connection,
+    path: &Path,
+    request: &Request,
+) -> io::Result<Response> {
+    // A track of a remote library is downloaded as it's sent, without seeking, unless the
+    // client can fetch it itself
+    if let Some(url) = path.to_str().filter(|url| storage::is_remote(url)) {
+        let storage = storage::load(db, url)
+            .map_err(io::Error::other)?
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        if let Some(location) = storage.presign(url) {
+            return Ok(Response::new(302, "text/plain", Vec::new()).header("Location", &location));
+        }
+        let reader = storage.open(url)?;
+        return Ok(Response::stream(reader, subsonic::content_type_for(path))
+            .header("Accept-Ranges", "none"));
+    }
+
+    // A track inside an archive is read out of it whole
+    if let Some((archive_path, name)) = archive::split(path).filter(|_| !path.exists()) {
+        let data = archive::read_file(&archive_path, &name)?;
+        return Ok(cached_response(
+            &data,
+            subsonic::content_type_for(path),
+            request,
+        ));
+    }
+
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let content_type = subsonic::content_type_for(path);
+
+    let response = match parse_range(request.header("Range"), len) {
+        Range::Full => Response::file(file, len, content_type),
+        Range::Partial(start, end) => {
+            file.seek(io::SeekFrom::Start(start))?;
+
+            let mut response = Response::file(file, end - start + 1, content_type)
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, end, len));
+            response.status = 206;
+            response
+        }
+        Range::Unsatisfiable => return Ok(unsatisfiable(len)),
+    };
+
+    Ok(response.header("Accept-Ranges", "bytes"))
+}
+
+/// Serves a cached transcode, or the part of it asked for by the `Range` header.
+fn cached_response(data: &[u8], content_type: &str, request: &Request) -> Response {
+    let len = data.len() as u64;
+
+    let response = match parse_range(request.header("Range"), len) {
+        Range::Full => Response::new(200, content_type, data.to_vec()),
+        Range::Partial(start, end) => {
+            let part = data[start as usize..=end as usize].to_vec();
+            Response::new(206, content_type, part)
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, end, len))
+        }
+        Range::Unsatisfiable => return unsatisfiable(len),
+    };
+
+    response.header("Accept-Ranges", "bytes")
+}
+
+fn transcode_response(
+    path: &Path,
+    key: CacheKey,
+    request: &Request,
+) -> Result<Response, ffmpeg::FfmpegError> {
+    let content_type = ffmpeg::stream_content_type(&key.format).unwrap_or_default();
+
+    let cached = CACHE.lock().ok().and_then(|mut cache| cache.get(&key));
+    if let Some(data) = cached {
+        return Ok(cached_response(&data, content_type, request));
+    }
+
+    let mut child = ffmpeg::transcode_stream(path, &key.format, key.bitrate, key.gain_db)?;
+    let stdout = match child.stdout.take() {
+        Some(stdout) => stdout,
+        None => {
+            let _ = child.kill();
+            return Err(ffmpeg::FfmpegError::Failed("no output".to_owned()));
+        }
+    };
+
+    let reader = Transcoding {
+        key,
+        child,
+        stdout,
+        buf: Some(Vec::new()),
+    };
+
+    // The length isn't known until the end, so seeking only works once it's cached
+    Ok(Response::stream(Box::new(reader), content_type).header("Accept-Ranges", "none"))
+}
+
+struct Track {
+    path: Option<String>,
+    transcode_path: Option<String>,
+}
+
+fn stream_track(
+    db: &rusqlite::Connection,
+    track_id: i64,
+    request: &Request,
+) -> Result<Response, Response> {
+    let track = db.query_row(
+        "SELECT library_path(path), transcode_path FROM track WHERE id = $id",
+        [track_id],
+        |row| {
+            Ok(Track {
+                path: row.get(0)?,
+                transcode_path: row.get(1)?,
+            })
+        },
+    );
+    let track = match track {
+        Ok(track) => track,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(Response::not_found()),
+        Err(err) => {
+            println!("{}", tr!("stream-get-track", id = track_id, err = err));
+            return Err(Response::text(500, "internal error"));
+        }
+    };
+    let path = match track.path {
+        Some(path) => PathBuf::from(path),
+        None => return Err(Response::not_found()),
+    };
+    // ffmpeg can't read the tracks of a remote library, they're always sent as they are
+    if storage::is_remote(&path.to_string_lossy()) {
+        return file_response(db, &path, request)
+            .map_err(|_| Response::text(404, "track file not found"));
+    }
+
+    let format = match request.param("format") {
+        Some("raw") | None => None,
+        Some(format) => match ffmpeg::codec_for(format) {
+            Some((_, extension)) => Some((format, extension)),
+            None => return Err(Response::text(400, "unknown format")),
+        },
+    };
+    let bitrate = match request.param("bitrate") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Err(Response::text(400, "invalid bitrate")),
+        },
+        None => None,
+    };
+    let mode = match request.param("replay_gain") {
+        Some(value) => match GainMode::parse(value) {
+            Some(mode) => Ok(mode),
+            None => return Err(Response::text(400, "invalid replay_gain")),
+        },
+        None => GainMode::load(db),
+    };
+    let gain_db = match mode.and_then(|mode| replaygain::track_gain(db, track_id, mode)) {
+        Ok(gain_db) => gain_db,
+        Err(err) => {
+            println!("{}", tr!("stream-get-gain", id = track_id, err = err));
+            return Err(Response::text(500, "internal error"));
+        }
+    };
+
+    let (format, extension) = match format {
+        Some(format) => format,
+        None => {
+            return file_response(db, &path, request)
+                .map_err(|_| Response::text(404, "track file not found"))
+        }
+    };
+
+    // A file already in the right format is served as is, unless it has to be normalized
+    let as_is = bitrate.is_none() && gain_db.is_none();
+    let has_extension = |path: &Path| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+    };
+    if as_is && has_extension(&path) {
+        return file_response(db, &path, request)
+            .map_err(|_| Response::text(404, "track file not found"));
+    }
+    if let Some(transcode_path) = track.transcode_path.map(PathBuf::from) {
+        if as_is && has_extension(&transcode_path) && transcode_path.exists() {
+            return file_response(db, &transcode_path, request)
+                .map_err(|_| Response::text(404, "track file not found"));
+        }
+    }
+
+    let bitrate = match bitrate {
+        Some(bitrate) => bitrate,
+        None => crate::get_config_usize(db, "transcode_bitrate")
+            .ok()
+            .flatten()
+            .unwrap_or(128),
+    };
+    let key = CacheKey {
+        track_id,
+        format: format.to_owned(),
+        bitrate,
+        gain_db,
+    };
+
+    transcode_response(&path, key, request).map_err(|err| {
+        println!("{}", tr!("stream-transcode", id = track_id, err = err));
+        Response::text(503, "unable to transcode")
+    })
+}
+
+/// Answers a request to `/stream/<track id>`.
+pub fn handle(track: &str, request: &Request) -> Response {
+    let track_id: i64 = match track.trim_end_matches('/').parse() {
+        Ok(id) => id,
+        Err(_) => return Response::not_found(),
+    };
+
+    let db = match pool::get() {
+        Ok(db) => db,
+        Err(err) => {
+            println!("{}", tr!("stream-connection", err = err));
+            return pool::error_response(&err);
+        }
+    };
+
+    match user::authenticate(&db, request) {
+        Ok(_) => (),
+        Err(err @ (user::AuthError::SQLite(_) | user::AuthError::Key(_))) => {
+            println!("{}", tr!("stream-auth", err = err));
+            return Response::text(500, "internal error");
+        }
+        Err(err) => {
+            return Response::text(401, &err.to_string())
+                .header("WWW-Authenticate", "Basic realm=\"zik\"")
+        }
+    }
+
+    match stream_track(&db, track_id, request) {
+        Ok(response) | Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(header: &str, len: u64) -> Range {
+        parse_range(Some(header), len)
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(parse_range(None, 100), Range::Full);
+        assert_eq!(range("bytes=0-0", 100), Range::Partial(0, 0));
+        assert_eq!(range("bytes=10-19", 100), Range::Partial(10, 19));
+        assert_eq!(range(" bytes= 10 - 19 ", 100), Range::Partial(10, 19));
+
+        // Open-ended, up to the end
+        assert_eq!(range("bytes=10-", 100), Range::Partial(10, 99));
+        // Past the end, up to the end
+        assert_eq!(range("bytes=90-1000", 100), Range::Partial(90, 99));
+        assert_eq!(range("bytes=99-99", 100), Range::Partial(99, 99));
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(range("bytes=-10", 100), Range::Partial(90, 99));
+        assert_eq!(range("bytes=-100", 100), Range::Partial(0, 99));
+        // Longer than the body, all of it
+        assert_eq!(range("bytes=-500", 100), Range::Partial(0, 99));
+        assert_eq!(range("bytes=-0", 100), Range::Unsatisfiable);
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(range("bytes=100-", 100), Range::Unsatisfiable);
+        assert_eq!(range("bytes=100-200", 100), Range::Unsatisfiable);
+        assert_eq!(range("bytes=500-", 100), Range::Unsatisfiable);
+
+        // Nothing of an empty file can be asked for
+        assert_eq!(range("bytes=0-", 0), Range::Unsatisfiable);
+        assert_eq!(range("bytes=0-10", 0), Range::Unsatisfiable);
+        assert_eq!(range("bytes=-10", 0), Range::Unsatisfiable);
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        // Several ranges get the whole body
+        assert_eq!(range("bytes=0-9,20-29", 100), Range::Full);
+        assert_eq!(range("bytes=0-9, -10", 100), Range::Full);
+
+        // So do invalid ones
+        for header in [
+            "",
+            "bytes=",
+            "bytes=-",
+            "bytes=10",
+            "bytes=19-10",
+            "bytes=a-b",
+            "bytes=1-x",
+            "bytes=-x",
+            "bytes=+1-2",
+            "bytes=1--2",
+            "bytes=18446744073709551616-",
+            "items=0-9",
+            "0-9",
+        ] {
+            assert_eq!(range(header, 100), Range::Full, "{}", header);
+        }
+    }
+}
@@ -0,0 +1,1895 @@
+//! The part of the Subsonic API that clients need to browse, search and play the library.
+//!
+//! Responses are XML by default and JSON when the client passes `f=json`. IDs are the
+//! row IDs of the matching table; cover art IDs are album IDs, or artist IDs prefixed with
+//! `ar-` for the images of artists.
+//!
+//! The library is music folder 1 and each collection one more, folder `id + 1` for the
+//! collection `id`; endpoints taking `musicFolderId` only return what it holds.
+
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::collation;
+use crate::collection;
+use crate::enrich;
+use crate::i18n::tr;
+use crate::json;
+use crate::lyrics;
+use crate::mood;
+use crate::pool;
+use crate::queue;
+use crate::server::{Request, Response};
+use crate::stream;
+use crate::user::{self, User};
+
+const API_VERSION: &str = "1.16.1";
+
+enum Value {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Str(value.to_owned())
+    }
+}
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Str(value)
+    }
+}
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Int(value)
+    }
+}
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Float(value)
+    }
+}
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+impl Value {
+    fn write_xml(&self, buf: &mut String) {
+        match self {
+            Value::Str(value) => xml_escape(value, buf),
+            Value::Int(value) => {
+                let _ = write!(buf, "{}", value);
+            }
+            Value::Float(value) => {
+                let _ = write!(buf, "{}", value);
+            }
+            Value::Bool(value) => {
+                let _ = write!(buf, "{}", value);
+            }
+        }
+    }
+
+    fn to_json(&self) -> String {
+        match self {
+            Value::Str(value) => json::string(value),
+            Value::Int(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::Bool(value) => value.to_string(),
+        }
+    }
+}
+
+enum Child {
+    One(Element),
+    Many(&'static str, Vec<Element>),
+    /// A list of plain values, like the versions of an OpenSubsonic extension.
+    Values(&'static str, Vec<Value>),
+    /// A child element in XML, a plain field in JSON.
+    Text(&'static str, String),
+}
+
+/// A node of a response, rendered either as an XML element or as a JSON object.
+///
+/// Lists are kept apart from single children because JSON clients expect an array even
+/// when a list has a single element.
+struct Element {
+    name: &'static str,
+    attrs: Vec<(&'static str, Value)>,
+    children: Vec<Child>,
+    /// The text content in XML, the `value` field in JSON.
+    text: Option<String>,
+}
+
+impl Element {
+    fn new(name: &'static str) -> Element {
+        Element {
+            name,
+            attrs: Vec::new(),
+            children: Vec::new(),
+            text: None,
+        }
+    }
+
+    fn attr<V: Into<Value>>(mut self, key: &'static str, value: V) -> Element {
+        self.attrs.push((key, value.into()));
+        self
+    }
+
+    fn opt_attr<V: Into<Value>>(self, key: &'static str, value: Option<V>) -> Element {
+        match value {
+            Some(value) => self.attr(key, value),
+            None => self,
+        }
+    }
+
+    fn child(mut self, child: Element) -> Element {
+        self.children.push(Child::One(child));
+        self
+    }
+
+    fn list(mut self, name: &'static str, items: Vec<Element>) -> Element {
+        self.children.push(Child::Many(name, items));
+        self
+    }
+
+    fn values<V: Into<Value>>(mut self, name: &'static str, values: Vec<V>) -> Element {
+        let values = values.into_iter().map(Into::into).collect();
+        self.children.push(Child::Values(name, values));
+        self
+    }
+
+    fn opt_text_child(mut self, name: &'static str, text: Option<String>) -> Element {
+        if let Some(text) = text {
+            self.children.push(Child::Text(name, text));
+        }
+        self
+    }
+
+    fn text(mut self, text: String) -> Element {
+        self.text = Some(text);
+        self
+    }
+
+    fn write_xml(&self, buf: &mut String) {
+        buf.push('<');
+        buf.push_str(self.name);
+        for (key, value) in &self.attrs {
+            let _ = write!(buf, " {}=\"", key);
+            value.write_xml(buf);
+            buf.push('"');
+        }
+
+        if self.children.is_empty() && self.text.is_none() {
+            buf.push_str("/>");
+            return;
+        }
+
+        buf.push('>');
+        for child in &self.children {
+            match child {
+                Child::One(element) => element.write_xml(buf),
+                Child::Many(_, elements) => {
+                    for element in elements {
+                        element.write_xml(buf);
+                    }
+                }
+                Child::Values(name, values) => {
+                    for value in values {
+                        let _ = write!(buf, "<{}>", name);
+                        value.write_xml(buf);
+                        let _ = write!(buf, "</{}>", name);
+                    }
+                }
+                Child::Text(name, text) => {
+                    let _ = write!(buf, "<{}>", name);
+                    xml_escape(text, buf);
+                    let _ = write!(buf, "</{}>", name);
+                }
+            }
+        }
+        if let Some(text) = &self.text {
+            xml_escape(text, buf);
+        }
+        let _ = write!(buf, "</{}>", self.name);
+    }
+
+    fn to_json(&self) -> String {
+        let mut fields: Vec<(&str, String)> = Vec::new();
+
+        for (key, value) in &self.attrs {
+            fields.push((key, value.to_json()));
+        }
+        for child in &self.children {
+            match child {
+                Child::One(element) => fields.push((element.name, element.to_json())),
+                Child::Many(name, elements) => {
+                    let values: Vec<String> = elements.iter().map(Element::to_json).collect();
+                    fields.push((name, json::array(&values)));
+                }
+                Child::Values(name, values) => {
+                    let values: Vec<String> = values.iter().map(Value::to_json).collect();
+                    fields.push((name, json::array(&values)));
+                }
+                Child::Text(name, text) => fields.push((name, json::string(text))),
+            }
+        }
+        if let Some(text) = &self.text {
+            fields.push(("value", json::string(text)));
+        }
+
+        json::object(&fields)
+    }
+}
+
+pub fn xml_escape(value: &str, buf: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            '\'' => buf.push_str("&apos;"),
+            c => buf.push(c),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Xml,
+    Json,
+}
+
+impl Format {
+    fn from_request(request: &Request) -> Format {
+        match request.param("f") {
+            Some("json") => Format::Json,
+            _ => Format::Xml,
+        }
+    }
+}
+
+/// Wraps `element` in the `subsonic-response` envelope.
+fn envelope(format: Format, status: &str, element: Option<Element>) -> Response {
+    let mut root = Element::new("subsonic-response")
+        .attr("status", status)
+        .attr("version", API_VERSION)
+        .attr("type", "zik")
+        .attr("serverVersion", env!("CARGO_PKG_VERSION"))
+        .attr("openSubsonic", true);
+    if let Some(element) = element {
+        root = root.child(element);
+    }
+
+    match format {
+        Format::Xml => {
+            let mut buf = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            root = root.attr("xmlns", "http://subsonic.org/restapi");
+            root.write_xml(&mut buf);
+            Response::new(200, "text/xml; charset=utf-8", buf.into_bytes())
+        }
+        Format::Json => {
+            let body = json::object(&[("subsonic-response", root.to_json())]);
+            Response::new(200, "application/json", body.into_bytes())
+        }
+    }
+}
+
+pub enum ApiError {
+    SQLite(rusqlite::Error),
+    Database(pool::PoolError),
+    IO(io::Error),
+    MissingParameter(&'static str),
+    InvalidParameter(&'static str),
+    NotFound(&'static str),
+    UnknownEndpoint(String),
+    Auth(user::AuthError),
+    NoUser,
+    NotAllowed,
+}
+impl From<rusqlite::Error> for ApiError {
+    fn from(err: rusqlite::Error) -> ApiError {
+        ApiError::SQLite(err)
+    }
+}
+impl From<pool::PoolError> for ApiError {
+    fn from(err: pool::PoolError) -> ApiError {
+        ApiError::Database(err)
+    }
+}
+impl From<user::AuthError> for ApiError {
+    fn from(err: user::AuthError) -> ApiError {
+        match err {
+            user::AuthError::SQLite(err) => ApiError::SQLite(err),
+            err => ApiError::Auth(err),
+        }
+    }
+}
+impl From<io::Error> for ApiError {
+    fn from(err: io::Error) -> ApiError {
+        ApiError::IO(err)
+    }
+}
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            ApiError::Database(err) => write!(f, "{}", err),
+            ApiError::IO(err) => f.write_str(&tr!("io-error", err = err)),
+            ApiError::MissingParameter(name) => write!(f, "required parameter {} is missing", name),
+            ApiError::InvalidParameter(name) => write!(f, "parameter {} is invalid", name),
+            ApiError::NotFound(what) => write!(f, "{} not found", what),
+            ApiError::UnknownEndpoint(name) => write!(f, "unknown endpoint {}", name),
+            ApiError::Auth(err) => write!(f, "{}", err),
+            ApiError::NoUser => write!(
+                f,
+                "this needs a user, add one with `zik user add` and log in with it"
+            ),
+            ApiError::NotAllowed => write!(f, "user is not allowed to do this"),
+        }
+    }
+}
+impl ApiError {
+    /// The error code defined by the Subsonic API.
+    fn code(&self) -> i64 {
+        match self {
+            ApiError::MissingParameter(_) | ApiError::InvalidParameter(_) => 10,
+            ApiError::Auth(user::AuthError::MissingCredentials) => 10,
+            ApiError::Auth(user::AuthError::Key(_)) => 0,
+            ApiError::Auth(_) => 40,
+            ApiError::NoUser | ApiError::NotAllowed => 50,
+            ApiError::NotFound(_) | ApiError::UnknownEndpoint(_) => 70,
+            _ => 0,
+        }
+    }
+}
+
+fn get_id(request: &Request) -> Result<i64, ApiError> {
+    match request.param("id") {
+        Some(value) => value.parse().map_err(|_| ApiError::InvalidParameter("id")),
+        None => Err(ApiError::MissingParameter("id")),
+    }
+}
+
+fn get_number(request: &Request, name: &'static str, default: i64) -> Result<i64, ApiError> {
+    match request.param(name) {
+        Some(value) => value.parse().map_err(|_| ApiError::InvalidParameter(name)),
+        None => Ok(default),
+    }
+}
+
+fn get_optional_number(request: &Request, name: &'static str) -> Result<Option<i64>, ApiError> {
+    match request.param(name) {
+        Some(value) => match value.parse() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(ApiError::InvalidParameter(name)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns the MIME type of an audio or image file based on its extension.
+pub fn content_type_for(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "flac" => "audio/flac",
+        "mp3" => "audio/mpeg",
+        "m4a" | "mp4" => "audio/mp4",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/ogg; codecs=opus",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        _ => "application/octet-stream",
+    }
+}
+
+fn library_path(db: &rusqlite::Connection) -> rusqlite::Result<Option<PathBuf>> {
+    Ok(crate::library::location(db)?.map(PathBuf::from))
+}
+
+const ALBUM_QUERY: &str = "
+    SELECT album.id, album.name, album.artist_id, artist.name, album.release_year, album.cover_path,
+           COUNT(track.id), MIN(track.genre), album.release_type
+    FROM album
+    LEFT JOIN artist ON artist.id = album.artist_id
+    LEFT JOIN track ON track.album_id = album.id";
+
+fn album_element(row: &rusqlite::Row) -> rusqlite::Result<Element> {
+    let id: i64 = row.get(0)?;
+    let name: Option<String> = row.get(1)?;
+    let artist_id: Option<i64> = row.get(2)?;
+    let artist: Option<String> = row.get(3)?;
+    let year: Option<i64> = row.get(4)?;
+    let cover_path: Option<String> = row.get(5)?;
+    let song_count: i64 = row.get(6)?;
+    let genre: Option<String> = row.get(7)?;
+    let release_type: Option<String> = row.get(8)?;
+
+    let element = Element::new("album")
+        .attr("id", id.to_string())
+        .attr("name", name.clone().unwrap_or_default())
+        .attr("title", name.unwrap_or_default())
+        .attr("isDir", true)
+        .opt_attr("artist", artist)
+        .opt_attr("artistId", artist_id.map(|id| id.to_string()))
+        .opt_attr("parent", artist_id.map(|id| id.to_string()))
+        .attr("songCount", song_count)
+        .opt_attr("year", year)
+        .opt_attr("genre", genre)
+        .opt_attr("coverArt", cover_path.map(|_| id.to_string()));
+
+    Ok(match release_type {
+        Some(release_type) => element
+            .attr("isCompilation", release_type == "compilation")
+            .values("releaseTypes", vec![release_type]),
+        None => element,
+    })
+}
+
+const SONG_QUERY: &str = "
+    SELECT track.id, track.name, track.album_id, album.name, track.artist_id, artist.name,
+           track.number, track.disc_number, track.release_year, track.genre, library_path(track.path),
+           album.cover_path, track.spoken_word,
+           (SELECT group_concat(id || char(31) || name, char(30)) FROM (
+              SELECT a.id, a.name FROM track_artist
+              JOIN artist a ON a.id = track_artist.artist_id
+              WHERE track_artist.track_id = track.id
+              ORDER BY track_artist.position
+           )),
+           song_user.rating, song_user.play_count,
+           strftime('%Y-%m-%dT%H:%M:%SZ', song_user.starred_at, 'unixepoch'),
+           strftime('%Y-%m-%dT%H:%M:%SZ', song_user.last_played_at, 'unixepoch'),
+           (SELECT AVG(rating) FROM user_track WHERE user_track.track_id = track.id)
+    FROM track
+    LEFT JOIN album ON album.id = track.album_id
+    LEFT JOIN artist ON artist.id = track.artist_id
+    LEFT JOIN user_track song_user
+      ON song_user.track_id = track.id AND song_user.user_id = $user_id";
+
+/// The ID of the user for `SONG_QUERY`, whose `$user_id` is always its first parameter.
+fn user_id(user: Option<&User>) -> Option<i64> {
+    user.map(|user| user.id)
+}
+
+/// Parses the artists of a track, as aggregated by `SONG_QUERY`.
+fn parse_track_artists(value: &str) -> Vec<(String, String)> {
+    value
+        .split('\x1e')
+        .filter_map(|artist| artist.split_once('\x1f'))
+        .map(|(id, name)| (id.to_owned(), name.to_owned()))
+        .collect()
+}
+
+fn song_element(row: &rusqlite::Row, library: Option<&Path>) -> rusqlite::Result<Element> {
+    let id: i64 = row.get(0)?;
+    let name: Option<String> = row.get(1)?;
+    let album_id: Option<i64> = row.get(2)?;
+    let album: Option<String> = row.get(3)?;
+    let artist_id: Option<i64> = row.get(4)?;
+    let artist: Option<String> = row.get(5)?;
+    let number: Option<i64> = row.get(6)?;
+    let disc_number: Option<i64> = row.get(7)?;
+    let year: Option<i64> = row.get(8)?;
+    let genre: Option<String> = row.get(9)?;
+    let path: Option<String> = row.get(10)?;
+    let cover_path: Option<String> = row.get(11)?;
+    let spoken_word: bool = row.get(12)?;
+    let track_artists: Option<String> = row.get(13)?;
+    let user_rating: Option<i64> = row.get(14)?;
+    let play_count: Option<i64> = row.get(15)?;
+    let starred: Option<String> = row.get(16)?;
+    let played: Option<String> = row.get(17)?;
+    let average_rating: Option<f64> = row.get(18)?;
+
+    // OpenSubsonic clients show every artist of a track, not just the main one
+    let mut artists = track_artists
+        .as_deref()
+        .map(parse_track_artists)
+        .unwrap_or_default();
+    if artists.is_empty() {
+        if let (Some(id), Some(name)) = (artist_id, &artist) {
+            artists.push((id.to_string(), name.clone()));
+        }
+    }
+    let display_artist = artists
+        .iter()
+        .map(|(_, name)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let artists = artists
+        .into_iter()
+        .map(|(id, name)| Element::new("artists").attr("id", id).attr("name", name))
+        .collect();
+    let genres = genre
+        .iter()
+        .map(|genre| Element::new("genres").attr("name", genre.as_str()))
+        .collect();
+
+    let mut element = Element::new("song")
+        .attr("id", id.to_string())
+        .attr("isDir", false)
+        .attr("type", if spoken_word { "audiobook" } else { "music" })
+        .attr("title", name.unwrap_or_default())
+        .opt_attr("parent", album_id.map(|id| id.to_string()))
+        .opt_attr("albumId", album_id.map(|id| id.to_string()))
+        .opt_attr("album", album)
+        .opt_attr("artistId", artist_id.map(|id| id.to_string()))
+        .opt_attr("artist", artist)
+        .opt_attr("track", number.filter(|n| *n > 0))
+        .opt_attr("discNumber", disc_number)
+        .opt_attr("year", year)
+        .opt_attr("genre", genre)
+        .opt_attr(
+            "coverArt",
+            cover_path.and(album_id).map(|id| id.to_string()),
+        )
+        .attr("displayArtist", display_artist)
+        .list("artists", artists)
+        .list("genres", genres)
+        .opt_attr("userRating", user_rating)
+        .opt_attr("averageRating", average_rating)
+        .opt_attr("playCount", play_count)
+        .opt_attr("played", played)
+        .opt_attr("starred", starred);
+
+    if let Some(path) = path.map(PathBuf::from) {
+        if let Ok(metadata) = fs::metadata(&path) {
+            element = element.attr("size", metadata.len() as i64);
+        }
+        if let Some(suffix) = path.extension().and_then(|ext| ext.to_str()) {
+            element = element.attr("suffix", suffix.to_lowercase());
+        }
+        element = element.attr("contentType", content_type_for(&path));
+
+        // Clients only use the path for display, it doesn't have to be absolute
+        let relative = library
+            .and_then(|library| path.strip_prefix(library).ok())
+            .unwrap_or(&path);
+        element = element.attr("path", relative.to_string_lossy().into_owned());
+    }
+
+    Ok(element)
+}
+
+/// Returns the name of the collection of the `musicFolderId` of `request`, none for the whole
+/// library.
+fn music_folder_collection(
+    db: &rusqlite::Connection,
+    request: &Request,
+) -> Result<Option<String>, ApiError> {
+    let id = match get_optional_number(request, "musicFolderId")? {
+        Some(id) if id > 1 => id - 1,
+        _ => return Ok(None),
+    };
+
+    match db.query_row("SELECT name FROM collection WHERE id = $id", [id], |row| {
+        row.get(0)
+    }) {
+        Ok(name) => Ok(Some(name)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(ApiError::NotFound("music folder")),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn get_music_folders(db: &rusqlite::Connection) -> Result<Option<Element>, ApiError> {
+    let name = library_path(db)?
+        .and_then(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| "Library".to_owned());
+
+    let mut folders = vec![Element::new("musicFolder").attr("id", 1).attr("name", name)];
+
+    let mut stmt =
+        db.prepare("SELECT id, name FROM collection ORDER BY name COLLATE natural_sort")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: String = row.get(1)?;
+
+        folders.push(
+            Element::new("musicFolder")
+                .attr("id", id + 1)
+                .attr("name", name),
+        );
+    }
+
+    Ok(Some(
+        Element::new("musicFolders").list("musicFolder", folders),
+    ))
+}
+
+fn index_name(locale: collation::Locale, name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => collation::index_letter(locale, c),
+        _ => "#".to_owned(),
+    }
+}
+
+fn get_artists(db: &rusqlite::Connection, request: &Request) -> Result<Option<Element>, ApiError> {
+    let collection = music_folder_collection(db, request)?;
+
+    // In a collection, only the artists of its albums
+    let query = format!(
+        "
+        SELECT artist.id, artist.name, COUNT(album.id), artist.sort_name, artist_image.path
+        FROM artist
+        LEFT JOIN album ON album.artist_id = artist.id AND {}
+        LEFT JOIN artist_image ON artist_image.artist_id = artist.id
+        GROUP BY artist.id
+        HAVING $collection IS NULL OR COUNT(album.id) > 0
+        ORDER BY artist.sort_name COLLATE natural_sort",
+        collection::ALBUM_FILTER
+    );
+
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query([collection])?;
+
+    let locale = collation::Locale::load(db);
+    let articles = collation::IgnoredArticles::load(db)?;
+    let mut indexes: Vec<(String, Vec<Element>)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let album_count: i64 = row.get(2)?;
+        let sort_name: Option<String> = row.get(3)?;
+        let image_path: Option<String> = row.get(4)?;
+
+        let name = name.unwrap_or_default();
+        let index = index_name(locale, sort_name.as_deref().unwrap_or(&name));
+
+        let artist = Element::new("artist")
+            .attr("id", id.to_string())
+            .attr("name", name)
+            .opt_attr("coverArt", image_path.map(|_| format!("ar-{}", id)))
+            .attr("albumCount", album_count);
+
+        match indexes.last_mut() {
+            Some((last, artists)) if *last == index => artists.push(artist),
+            _ => indexes.push((index, vec![artist])),
+        }
+    }
+
+    let indexes = indexes
+        .into_iter()
+        .map(|(name, artists)| {
+            Element::new("index")
+                .attr("name", name)
+                .list("artist", artists)
+        })
+        .collect();
+
+    Ok(Some(
+        Element::new("artists")
+            .attr("ignoredArticles", articles.to_subsonic())
+            .list("index", indexes),
+    ))
+}
+
+fn get_artist(db: &rusqlite::Connection, request: &Request) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+
+    let (name, image_path): (Option<String>, Option<String>) = match db.query_row(
+        "SELECT artist.name, artist_image.path
+         FROM artist
+         LEFT JOIN artist_image ON artist_image.artist_id = artist.id
+         WHERE artist.id = $id",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ) {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound("artist")),
+        Err(err) => return Err(err.into()),
+    };
+
+    let query = format!(
+        "{} WHERE album.artist_id = $id GROUP BY album.id ORDER BY album.release_year, album.sort_name COLLATE natural_sort",
+        ALBUM_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query([id])?;
+
+    let mut albums = Vec::new();
+    while let Some(row) = rows.next()? {
+        albums.push(album_element(row)?);
+    }
+
+    let name = name.unwrap_or_default();
+    let image_url = enrich::get_info(db, &name)?.and_then(|info| info.image_url);
+
+    Ok(Some(
+        Element::new("artist")
+            .attr("id", id.to_string())
+            .attr("name", name)
+            .opt_attr("coverArt", image_path.map(|_| format!("ar-{}", id)))
+            .opt_attr("artistImageUrl", image_url)
+            .attr("albumCount", albums.len() as i64)
+            .list("album", albums),
+    ))
+}
+
+/// Answers both getArtistInfo and getArtistInfo2 with what `zik enrich artists` fetched.
+fn get_artist_info(
+    db: &rusqlite::Connection,
+    request: &Request,
+    element: &'static str,
+) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+
+    let name: String = match db.query_row("SELECT name FROM artist WHERE id = $id", [id], |row| {
+        row.get(0)
+    }) {
+        Ok(name) => name,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound("artist")),
+        Err(err) => return Err(err.into()),
+    };
+
+    let info = match enrich::get_info(db, &name)? {
+        Some(info) => info,
+        None => return Ok(Some(Element::new(element))),
+    };
+
+    // Only the similar artists that are in the library can be listed
+    let count = get_number(request, "count", 20)?.clamp(0, 100) as usize;
+    let mut similar = Vec::new();
+    let mut stmt = db.prepare(
+        "SELECT artist.id, artist.name, COUNT(album.id)
+         FROM artist
+         LEFT JOIN album ON album.artist_id = artist.id
+         WHERE artist.name = $name COLLATE NOCASE
+         GROUP BY artist.id",
+    )?;
+    for similar_name in &info.similar {
+        if similar.len() >= count {
+            break;
+        }
+        let mut rows = stmt.query([similar_name])?;
+        if let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let album_count: i64 = row.get(2)?;
+            similar.push(
+                Element::new("similarArtist")
+                    .attr("id", id.to_string())
+                    .attr("name", name)
+                    .attr("albumCount", album_count),
+            );
+        }
+    }
+
+    let lastfm_url = info
+        .links
+        .iter()
+        .find(|(kind, _)| kind == "lastfm")
+        .map(|(_, url)| url.clone());
+
+    Ok(Some(
+        Element::new(element)
+            .opt_text_child("biography", info.bio)
+            .opt_text_child("musicBrainzId", info.musicbrainz_id)
+            .opt_text_child("lastFmUrl", lastfm_url)
+            .opt_text_child("smallImageUrl", info.image_url.clone())
+            .opt_text_child("mediumImageUrl", info.image_url.clone())
+            .opt_text_child("largeImageUrl", info.image_url)
+            .list("similarArtist", similar),
+    ))
+}
+
+fn get_album(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+    let library = library_path(db)?;
+
+    let query = format!("{} WHERE album.id = $id GROUP BY album.id", ALBUM_QUERY);
+    let album = match db.query_row(&query, [id], album_element) {
+        Ok(album) => album,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound("album")),
+        Err(err) => return Err(err.into()),
+    };
+
+    let query = format!(
+        "{} WHERE track.album_id = $id ORDER BY track.disc_number, track.number",
+        SONG_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params![user_id(user), id])?;
+
+    let mut songs = Vec::new();
+    while let Some(row) = rows.next()? {
+        songs.push(song_element(row, library.as_deref())?);
+    }
+
+    Ok(Some(album.list("song", songs)))
+}
+
+fn get_song(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+    let library = library_path(db)?;
+
+    let query = format!("{} WHERE track.id = $id", SONG_QUERY);
+    let params = rusqlite::params![user_id(user), id];
+    match db.query_row(&query, params, |row| song_element(row, library.as_deref())) {
+        Ok(song) => Ok(Some(song)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(ApiError::NotFound("song")),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn get_album_list2(
+    db: &rusqlite::Connection,
+    request: &Request,
+) -> Result<Option<Element>, ApiError> {
+    let size = get_number(request, "size", 10)?.clamp(0, 500);
+    let offset = get_number(request, "offset", 0)?.max(0);
+
+    let (filter, order) = match request.param("type") {
+        Some("random") => (
+            "($shuffle_spoken_word OR NOT EXISTS (
+              SELECT 1 FROM track t WHERE t.album_id = album.id AND t.spoken_word
+            ))",
+            "RANDOM()",
+        ),
+        Some("newest") => ("1", "album.id DESC"),
+        Some("alphabeticalByName") => ("1", "album.sort_name COLLATE natural_sort"),
+        Some("alphabeticalByArtist") => (
+            "1",
+            "artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort",
+        ),
+        Some("byYear") => (
+            "album.release_year BETWEEN MIN($from, $to) AND MAX($from, $to)",
+            "CASE WHEN $from <= $to THEN album.release_year ELSE -album.release_year END",
+        ),
+        Some("byGenre") => (
+            "EXISTS (SELECT 1 FROM track t WHERE t.album_id = album.id AND t.genre = $genre)",
+            "album.sort_name COLLATE natural_sort",
+        ),
+        Some(_) => return Err(ApiError::InvalidParameter("type")),
+        None => return Err(ApiError::MissingParameter("type")),
+    };
+
+    let from = get_number(request, "fromYear", 0)?;
+    let to = get_number(request, "toYear", i64::MAX)?;
+
+    let collection = music_folder_collection(db, request)?;
+
+    let query = format!(
+        "{} WHERE {} AND {} GROUP BY album.id ORDER BY {} LIMIT $size OFFSET $offset",
+        ALBUM_QUERY,
+        filter,
+        collection::ALBUM_FILTER,
+        order
+    );
+    let mut stmt = db.prepare(&query)?;
+
+    let mut params: Vec<(&str, &dyn rusqlite::ToSql)> = vec![
+        ("$size", &size),
+        ("$offset", &offset),
+        ("$collection", &collection),
+    ];
+    if query.contains("$from") {
+        params.push(("$from", &from));
+        params.push(("$to", &to));
+    }
+    let genre = request.param("genre");
+    if query.contains("$genre") {
+        params.push(("$genre", &genre));
+    }
+    let shuffle_spoken_word = crate::get_config_bool(db, "shuffle_spoken_word")?;
+    if query.contains("$shuffle_spoken_word") {
+        params.push(("$shuffle_spoken_word", &shuffle_spoken_word));
+    }
+    let mut rows = stmt.query(params.as_slice())?;
+
+    let mut albums = Vec::new();
+    while let Some(row) = rows.next()? {
+        albums.push(album_element(row)?);
+    }
+
+    Ok(Some(Element::new("albumList2").list("album", albums)))
+}
+
+/// Besides the standard filters, `mood` only picks tracks of a mood and `energy` of an energy
+/// level (7) or range (5-8).
+fn get_random_songs(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let size = get_number(request, "size", 10)?.clamp(0, 500);
+    let from = get_optional_number(request, "fromYear")?;
+    let to = get_optional_number(request, "toYear")?;
+    let genre = request.param("genre");
+    let mood = request.param("mood");
+    let (energy_from, energy_to) = match request.param("energy") {
+        Some(value) => match mood::parse_energy_filter(value) {
+            Some((from, to)) => (Some(from), Some(to)),
+            None => return Err(ApiError::InvalidParameter("energy")),
+        },
+        None => (None, None),
+    };
+    let shuffle_spoken_word = crate::get_config_bool(db, "shuffle_spoken_word")?;
+    let collection = music_folder_collection(db, request)?;
+    let library = library_path(db)?;
+
+    let query = format!(
+        "{} WHERE track.missing_since IS NULL
+              AND ($shuffle_spoken_word OR NOT track.spoken_word)
+              AND ($from IS NULL OR track.release_year >= $from)
+              AND ($to IS NULL OR track.release_year <= $to)
+              AND ($genre IS NULL OR track.genre = $genre)
+              AND {}
+              AND {}
+              AND {}
+            ORDER BY RANDOM()
+            LIMIT $size",
+        SONG_QUERY,
+        mood::MOOD_FILTER,
+        mood::ENERGY_FILTER,
+        collection::TRACK_FILTER,
+    );
+    let mut stmt = db.prepare(&query)?;
+    let params = rusqlite::params![
+        user_id(user),
+        shuffle_spoken_word,
+        from,
+        to,
+        genre,
+        mood,
+        energy_from,
+        energy_to,
+        collection,
+        size
+    ];
+    let mut rows = stmt.query(params)?;
+
+    let mut songs = Vec::new();
+    while let Some(row) = rows.next()? {
+        songs.push(song_element(row, library.as_deref())?);
+    }
+
+    Ok(Some(Element::new("randomSongs").list("song", songs)))
+}
+
+fn search3(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let query = request.param("query").unwrap_or("").trim_matches('"');
+    let pattern = format!("%{}%", query);
+    let library = library_path(db)?;
+
+    let artist_count = get_number(request, "artistCount", 20)?.clamp(0, 500);
+    let artist_offset = get_number(request, "artistOffset", 0)?.max(0);
+    let album_count = get_number(request, "albumCount", 20)?.clamp(0, 500);
+    let album_offset = get_number(request, "albumOffset", 0)?.max(0);
+    let song_count = get_number(request, "songCount", 20)?.clamp(0, 500);
+    let song_offset = get_number(request, "songOffset", 0)?.max(0);
+    let collection = music_folder_collection(db, request)?;
+
+    let mut artists = Vec::new();
+    {
+        let query = format!(
+            "
+            SELECT artist.id, artist.name, COUNT(album.id)
+            FROM artist
+            LEFT JOIN album ON album.artist_id = artist.id AND {}
+            WHERE artist.name LIKE $pattern
+            GROUP BY artist.id
+            HAVING $collection IS NULL OR COUNT(album.id) > 0
+            ORDER BY artist.sort_name COLLATE natural_sort
+            LIMIT $count OFFSET $offset",
+            collection::ALBUM_FILTER
+        );
+        let mut stmt = db.prepare(&query)?;
+        let mut rows = stmt.query(rusqlite::params![
+            collection,
+            pattern,
+            artist_count,
+            artist_offset
+        ])?;
+        while let Some(row) = rows.next()? {
+            let id: i64 = row.get(0)?;
+            let name: Option<String> = row.get(1)?;
+            let album_count: i64 = row.get(2)?;
+
+            artists.push(
+                Element::new("artist")
+                    .attr("id", id.to_string())
+                    .attr("name", name.unwrap_or_default())
+                    .attr("albumCount", album_count),
+            );
+        }
+    }
+
+    let mut albums = Vec::new();
+    {
+        let query = format!(
+            "{} WHERE album.name LIKE $pattern AND {} GROUP BY album.id ORDER BY album.sort_name COLLATE natural_sort LIMIT $count OFFSET $offset",
+            ALBUM_QUERY,
+            collection::ALBUM_FILTER
+        );
+        let mut stmt = db.prepare(&query)?;
+        let mut rows = stmt.query(rusqlite::params![
+            pattern,
+            collection,
+            album_count,
+            album_offset
+        ])?;
+        while let Some(row) = rows.next()? {
+            albums.push(album_element(row)?);
+        }
+    }
+
+    let mut songs = Vec::new();
+    {
+        let query = format!(
+            "{} WHERE track.name LIKE $pattern AND {} ORDER BY track.name COLLATE natural_sort LIMIT $count OFFSET $offset",
+            SONG_QUERY,
+            collection::TRACK_FILTER
+        );
+        let mut stmt = db.prepare(&query)?;
+        let mut rows = stmt.query(rusqlite::params![
+            user_id(user),
+            pattern,
+            collection,
+            song_count,
+            song_offset
+        ])?;
+        while let Some(row) = rows.next()? {
+            songs.push(song_element(row, library.as_deref())?);
+        }
+    }
+
+    Ok(Some(
+        Element::new("searchResult3")
+            .list("artist", artists)
+            .list("album", albums)
+            .list("song", songs),
+    ))
+}
+
+/// Bookmarks are the resume positions of spoken word tracks.
+fn get_bookmarks(
+    db: &rusqlite::Connection,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let username = user.map(|user| user.name.as_str()).unwrap_or_default();
+    let library = library_path(db)?;
+    let song_query = format!("{} WHERE track.id = $id", SONG_QUERY);
+
+    let mut stmt = db.prepare(
+        "SELECT track_id, position_ms, comment,
+                strftime('%Y-%m-%dT%H:%M:%SZ', created_at, 'unixepoch'),
+                strftime('%Y-%m-%dT%H:%M:%SZ', updated_at, 'unixepoch')
+         FROM resume_position
+         ORDER BY updated_at DESC",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut bookmarks = Vec::new();
+    while let Some(row) = rows.next()? {
+        let track_id: i64 = row.get(0)?;
+        let position: i64 = row.get(1)?;
+        let comment: Option<String> = row.get(2)?;
+        let created: String = row.get(3)?;
+        let changed: String = row.get(4)?;
+
+        let params = rusqlite::params![user_id(user), track_id];
+        let mut entry = db.query_row(&song_query, params, |row| {
+            song_element(row, library.as_deref())
+        })?;
+        entry.name = "entry";
+
+        bookmarks.push(
+            Element::new("bookmark")
+                .attr("position", position)
+                .attr("username", username)
+                .opt_attr("comment", comment)
+                .attr("created", created)
+                .attr("changed", changed)
+                .child(entry),
+        );
+    }
+
+    Ok(Some(Element::new("bookmarks").list("bookmark", bookmarks)))
+}
+
+fn create_bookmark(
+    db: &rusqlite::Connection,
+    request: &Request,
+) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+    let position = match request.param("position") {
+        Some(value) => value
+            .parse::<i64>()
+            .map_err(|_| ApiError::InvalidParameter("position"))?,
+        None => return Err(ApiError::MissingParameter("position")),
+    };
+
+    let n = db.execute(
+        "INSERT INTO resume_position(track_id, position_ms, comment)
+         SELECT id, $position, $comment FROM track WHERE id = $id
+         ON CONFLICT(track_id) DO UPDATE SET
+           position_ms = excluded.position_ms,
+           comment = excluded.comment,
+           updated_at = unixepoch()",
+        rusqlite::params![position, request.param("comment"), id],
+    )?;
+    if n == 0 {
+        return Err(ApiError::NotFound("song"));
+    }
+
+    Ok(None)
+}
+
+fn delete_bookmark(
+    db: &rusqlite::Connection,
+    request: &Request,
+) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+
+    db.execute("DELETE FROM resume_position WHERE track_id = $id", [id])?;
+
+    Ok(None)
+}
+
+//
+// Per-user data
+//
+
+fn get_play_queue(
+    db: &rusqlite::Connection,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let library = library_path(db)?;
+
+    let queue = db.query_row(
+        "SELECT current_track_id, position_ms, changed_by,
+                strftime('%Y-%m-%dT%H:%M:%SZ', updated_at, 'unixepoch')
+         FROM play_queue WHERE user_id = $id",
+        [user.id],
+        |row| {
+            Ok(Element::new("playQueue")
+                .opt_attr(
+                    "current",
+                    row.get::<_, Option<i64>>(0)?.map(|id| id.to_string()),
+                )
+                .attr("position", row.get::<_, i64>(1)?)
+                .attr("username", user.name.as_str())
+                .attr("changed", row.get::<_, String>(3)?)
+                .attr(
+                    "changedBy",
+                    row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                ))
+        },
+    );
+    let queue = match queue {
+        Ok(queue) => queue,
+        // Clients take an empty response for no queue
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let query = format!(
+        "{} JOIN play_queue_track queue ON queue.track_id = track.id
+         WHERE queue.user_id = $queue_user_id
+         ORDER BY queue.position",
+        SONG_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params![user.id, user.id])?;
+
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut entry = song_element(row, library.as_deref())?;
+        entry.name = "entry";
+        entries.push(entry);
+    }
+
+    Ok(Some(queue.list("entry", entries)))
+}
+
+/// Saves the queue given as `id`, with the track playing as `current` and where it is as
+/// `position`. No `id` clears the queue.
+fn save_play_queue(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let tracks = get_ids(request, "id")?;
+    let current = get_optional_number(request, "current")?;
+    let position = get_number(request, "position", 0)?;
+
+    queue::save(db, user.id, &tracks, current, position, request.param("c"))?;
+
+    Ok(None)
+}
+
+fn require_user(user: Option<&User>) -> Result<&User, ApiError> {
+    user.ok_or(ApiError::NoUser)
+}
+
+/// Returns the IDs given as `name`, which can be repeated.
+fn get_ids(request: &Request, name: &'static str) -> Result<Vec<i64>, ApiError> {
+    request
+        .param_values(name)
+        .into_iter()
+        .map(|value| value.parse().map_err(|_| ApiError::InvalidParameter(name)))
+        .collect()
+}
+
+fn scrobble(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+
+    // A "now playing" notification isn't a play
+    if request.param("submission") == Some("false") {
+        return Ok(None);
+    }
+
+    // Clients queuing scrobbles while offline send when each play happened
+    let times = get_ids(request, "time")?;
+
+    for (i, id) in get_ids(request, "id")?.into_iter().enumerate() {
+        let played_at = times.get(i).map(|time| time / 1000);
+
+        db.execute(
+            "INSERT INTO user_track(user_id, track_id, play_count, last_played_at)
+             SELECT $user_id, id, 1, coalesce($played_at, unixepoch()) FROM track WHERE id = $id
+             ON CONFLICT(user_id, track_id) DO UPDATE SET
+               play_count = play_count + 1,
+               last_played_at = max(coalesce(last_played_at, 0), excluded.last_played_at)",
+            rusqlite::params![user.id, played_at, id],
+        )?;
+        db.execute(
+            "INSERT INTO play(user_id, track_id, played_at)
+             SELECT $user_id, id, coalesce($played_at, unixepoch()) FROM track WHERE id = $id",
+            rusqlite::params![user.id, played_at, id],
+        )?;
+    }
+
+    Ok(None)
+}
+
+fn set_rating(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let id = get_id(request)?;
+    let rating = match request.param("rating") {
+        Some(value) => match value.parse::<i64>() {
+            Ok(rating) if (0..=5).contains(&rating) => rating,
+            _ => return Err(ApiError::InvalidParameter("rating")),
+        },
+        None => return Err(ApiError::MissingParameter("rating")),
+    };
+
+    // A rating of zero removes it
+    db.execute(
+        "INSERT INTO user_track(user_id, track_id, rating)
+         SELECT $user_id, id, nullif($rating, 0) FROM track WHERE id = $id
+         ON CONFLICT(user_id, track_id) DO UPDATE SET rating = excluded.rating",
+        [user.id, rating, id],
+    )?;
+
+    Ok(None)
+}
+
+fn star(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+    starred: bool,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+
+    // Only songs can be starred, albumId and artistId are ignored
+    for id in get_ids(request, "id")? {
+        db.execute(
+            "INSERT INTO user_track(user_id, track_id, starred_at)
+             SELECT $user_id, id, CASE WHEN $starred THEN unixepoch() END FROM track WHERE id = $id
+             ON CONFLICT(user_id, track_id) DO UPDATE SET starred_at = excluded.starred_at",
+            rusqlite::params![user.id, starred, id],
+        )?;
+    }
+
+    Ok(None)
+}
+
+fn get_starred2(
+    db: &rusqlite::Connection,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let library = library_path(db)?;
+
+    let query = format!(
+        "{} WHERE song_user.starred_at IS NOT NULL ORDER BY song_user.starred_at DESC",
+        SONG_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query([user.id])?;
+
+    let mut songs = Vec::new();
+    while let Some(row) = rows.next()? {
+        songs.push(song_element(row, library.as_deref())?);
+    }
+
+    Ok(Some(
+        Element::new("starred2")
+            .list("artist", Vec::new())
+            .list("album", Vec::new())
+            .list("song", songs),
+    ))
+}
+
+const PLAYLIST_QUERY: &str = "
+    SELECT playlist.id, playlist.name, playlist.comment, user.name, playlist.public,
+           (SELECT COUNT(*) FROM playlist_track WHERE playlist_track.playlist_id = playlist.id),
+           strftime('%Y-%m-%dT%H:%M:%SZ', playlist.created_at, 'unixepoch'),
+           strftime('%Y-%m-%dT%H:%M:%SZ', playlist.updated_at, 'unixepoch'),
+           playlist.user_id
+    FROM playlist
+    JOIN user ON user.id = playlist.user_id";
+
+fn playlist_element(row: &rusqlite::Row) -> rusqlite::Result<Element> {
+    let id: i64 = row.get(0)?;
+    let name: String = row.get(1)?;
+    let comment: Option<String> = row.get(2)?;
+    let owner: String = row.get(3)?;
+    let public: bool = row.get(4)?;
+    let song_count: i64 = row.get(5)?;
+    let created: String = row.get(6)?;
+    let changed: String = row.get(7)?;
+
+    Ok(Element::new("playlist")
+        .attr("id", id.to_string())
+        .attr("name", name)
+        .opt_attr("comment", comment)
+        .attr("owner", owner)
+        .attr("public", public)
+        .attr("songCount", song_count)
+        .attr("duration", 0)
+        .attr("created", created)
+        .attr("changed", changed))
+}
+
+/// Returns the playlist `id` with its songs, if `user` can see it.
+fn playlist_with_songs(
+    db: &rusqlite::Connection,
+    id: i64,
+    user: &User,
+) -> Result<Element, ApiError> {
+    let query = format!(
+        "{} WHERE playlist.id = $id AND (playlist.user_id = $user_id OR playlist.public)",
+        PLAYLIST_QUERY
+    );
+    let playlist = match db.query_row(&query, [id, user.id], playlist_element) {
+        Ok(playlist) => playlist,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound("playlist")),
+        Err(err) => return Err(err.into()),
+    };
+
+    let library = library_path(db)?;
+    let query = format!(
+        "{} JOIN playlist_track ON playlist_track.track_id = track.id
+         WHERE playlist_track.playlist_id = $id
+         ORDER BY playlist_track.position",
+        SONG_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query([user.id, id])?;
+
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut entry = song_element(row, library.as_deref())?;
+        entry.name = "entry";
+        entries.push(entry);
+    }
+
+    Ok(playlist.list("entry", entries))
+}
+
+/// Returns the songs of a playlist `user` is allowed to edit, with whether they see each, as a
+/// restricted user doesn't see the songs outside their collections.
+fn editable_playlist_tracks(
+    db: &rusqlite::Connection,
+    id: i64,
+    user: &User,
+) -> Result<Vec<(i64, bool)>, ApiError> {
+    let owner: i64 =
+        match db.query_row("SELECT user_id FROM playlist WHERE id = $id", [id], |row| {
+            row.get(0)
+        }) {
+            Ok(owner) => owner,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(ApiError::NotFound("playlist"))
+            }
+            Err(err) => return Err(err.into()),
+        };
+    if owner != user.id && !user.admin {
+        return Err(ApiError::NotAllowed);
+    }
+
+    let mut stmt = db.prepare(
+        "SELECT track_id, track_id IN (SELECT id FROM track)
+         FROM playlist_track WHERE playlist_id = $id ORDER BY position",
+    )?;
+    let tracks = stmt
+        .query_map([id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(i64, bool)>>>()?;
+
+    Ok(tracks)
+}
+
+/// Returns the songs of `ids` the user sees, dropping the others.
+fn visible_tracks(db: &rusqlite::Connection, ids: &[i64]) -> rusqlite::Result<Vec<i64>> {
+    let mut stmt = db.prepare("SELECT EXISTS(SELECT 1 FROM track WHERE id = $id)")?;
+    let mut tracks = Vec::with_capacity(ids.len());
+    for &id in ids {
+        if stmt.query_row([id], |row| row.get(0))? {
+            tracks.push(id);
+        }
+    }
+
+    Ok(tracks)
+}
+
+/// Replaces the songs of a playlist with `tracks`, which may be songs the user doesn't see
+/// kept from it: the caller checks the new ones with `visible_tracks`.
+fn set_playlist_tracks(db: &rusqlite::Connection, id: i64, tracks: &[i64]) -> rusqlite::Result<()> {
+    db.execute("DELETE FROM playlist_track WHERE playlist_id = $id", [id])?;
+
+    let mut stmt = db.prepare(
+        "INSERT INTO playlist_track(playlist_id, position, track_id)
+         SELECT $playlist_id, $position, id FROM main.track WHERE id = $track_id",
+    )?;
+    for (position, track_id) in tracks.iter().enumerate() {
+        stmt.execute(rusqlite::params![id, position, track_id])?;
+    }
+
+    db.execute(
+        "UPDATE playlist SET updated_at = unixepoch() WHERE id = $id",
+        [id],
+    )?;
+
+    Ok(())
+}
+
+fn get_playlists(
+    db: &rusqlite::Connection,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+
+    let query = format!(
+        "{} WHERE playlist.user_id = $user_id OR playlist.public ORDER BY playlist.name COLLATE natural_sort",
+        PLAYLIST_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query([user.id])?;
+
+    let mut playlists = Vec::new();
+    while let Some(row) = rows.next()? {
+        playlists.push(playlist_element(row)?);
+    }
+
+    Ok(Some(Element::new("playlists").list("playlist", playlists)))
+}
+
+fn get_playlist(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let id = get_id(request)?;
+
+    Ok(Some(playlist_with_songs(db, id, user)?))
+}
+
+/// Creates a playlist, or replaces the songs of an existing one when given `playlistId`. The
+/// songs the user doesn't see are kept, after the new ones.
+fn create_playlist(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let mut tracks = visible_tracks(db, &get_ids(request, "songId")?)?;
+
+    let id = match request.param("playlistId") {
+        Some(value) => {
+            let id = value
+                .parse()
+                .map_err(|_| ApiError::InvalidParameter("playlistId"))?;
+            let hidden = editable_playlist_tracks(db, id, user)?
+                .into_iter()
+                .filter(|(_, visible)| !visible)
+                .map(|(track_id, _)| track_id);
+            tracks.extend(hidden);
+            id
+        }
+        None => {
+            let name = match request.param("name") {
+                Some(name) => name,
+                None => return Err(ApiError::MissingParameter("name")),
+            };
+            db.query_row(
+                "INSERT INTO playlist(user_id, name) VALUES($user_id, $name) RETURNING id",
+                rusqlite::params![user.id, name],
+                |row| row.get(0),
+            )?
+        }
+    };
+
+    set_playlist_tracks(db, id, &tracks)?;
+
+    Ok(Some(playlist_with_songs(db, id, user)?))
+}
+
+fn update_playlist(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let id = match request.param("playlistId") {
+        Some(value) => value
+            .parse()
+            .map_err(|_| ApiError::InvalidParameter("playlistId"))?,
+        None => return Err(ApiError::MissingParameter("playlistId")),
+    };
+
+    let tracks = editable_playlist_tracks(db, id, user)?;
+
+    if let Some(name) = request.param("name") {
+        db.execute(
+            "UPDATE playlist SET name = $name WHERE id = $id",
+            rusqlite::params![name, id],
+        )?;
+    }
+    if let Some(comment) = request.param("comment") {
+        db.execute(
+            "UPDATE playlist SET comment = $comment WHERE id = $id",
+            rusqlite::params![comment, id],
+        )?;
+    }
+    if let Some(public) = request.param("public") {
+        db.execute(
+            "UPDATE playlist SET public = $public WHERE id = $id",
+            rusqlite::params![public == "true", id],
+        )?;
+    }
+
+    let removed = get_ids(request, "songIndexToRemove")?;
+    let added = get_ids(request, "songIdToAdd")?;
+    if removed.is_empty() && added.is_empty() {
+        db.execute(
+            "UPDATE playlist SET updated_at = unixepoch() WHERE id = $id",
+            [id],
+        )?;
+        return Ok(None);
+    }
+
+    // Indexes are of the songs the user sees, before any change, so remove from the end first
+    let visible: Vec<usize> = tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, visible))| *visible)
+        .map(|(position, _)| position)
+        .collect();
+    let mut removed: Vec<usize> = removed
+        .into_iter()
+        .filter_map(|index| usize::try_from(index).ok())
+        .filter_map(|index| visible.get(index).copied())
+        .collect();
+    removed.sort_unstable();
+    removed.dedup();
+
+    let mut tracks: Vec<i64> = tracks.into_iter().map(|(track_id, _)| track_id).collect();
+    for position in removed.into_iter().rev() {
+        tracks.remove(position);
+    }
+    tracks.extend(visible_tracks(db, &added)?);
+
+    set_playlist_tracks(db, id, &tracks)?;
+
+    Ok(None)
+}
+
+fn delete_playlist(
+    db: &rusqlite::Connection,
+    request: &Request,
+    user: Option<&User>,
+) -> Result<Option<Element>, ApiError> {
+    let user = require_user(user)?;
+    let id = get_id(request)?;
+
+    editable_playlist_tracks(db, id, user)?;
+
+    db.execute("DELETE FROM playlist_track WHERE playlist_id = $id", [id])?;
+    db.execute("DELETE FROM playlist WHERE id = $id", [id])?;
+
+    Ok(None)
+}
+
+//
+// OpenSubsonic
+//
+
+/// The OpenSubsonic extensions supported, with their versions.
+const EXTENSIONS: &[(&str, &[i64])] = &[("formPost", &[1]), ("songLyrics", &[1])];
+
+fn get_open_subsonic_extensions() -> Option<Element> {
+    let extensions = EXTENSIONS
+        .iter()
+        .map(|(name, versions)| {
+            Element::new("openSubsonicExtensions")
+                .attr("name", *name)
+                .values("versions", versions.to_vec())
+        })
+        .collect();
+
+    Some(Element::new("openSubsonicExtensions").list("openSubsonicExtensions", extensions))
+}
+
+struct LyricsTrack {
+    title: Option<String>,
+    artist: Option<String>,
+    path: Option<String>,
+    lyrics: Option<String>,
+}
+
+// The lyrics in the tags first, then the fetched ones
+const LYRICS_QUERY: &str = "
+    SELECT track.name, artist.name, library_path(track.path),
+           coalesce(track.lyrics, track_lyrics.synced, track_lyrics.plain)
+    FROM track
+    LEFT JOIN artist ON artist.id = track.artist_id
+    LEFT JOIN track_lyrics ON track_lyrics.track_id = track.id";
+
+fn lyrics_track(row: &rusqlite::Row) -> rusqlite::Result<LyricsTrack> {
+    Ok(LyricsTrack {
+        title: row.get(0)?,
+        artist: row.get(1)?,
+        path: row.get(2)?,
+        lyrics: row.get(3)?,
+    })
+}
+
+impl LyricsTrack {
+    fn lyrics(&self) -> Option<lyrics::Lyrics> {
+        let path = self.path.as_deref().map(Path::new)?;
+        lyrics::for_track(path, self.lyrics.as_deref())
+    }
+}
+
+fn get_lyrics_by_song_id(
+    db: &rusqlite::Connection,
+    request: &Request,
+) -> Result<Option<Element>, ApiError> {
+    let id = get_id(request)?;
+
+    let query = format!("{} WHERE track.id = $id", LYRICS_QUERY);
+    let track = match db.query_row(&query, [id], lyrics_track) {
+        Ok(track) => track,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound("song")),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut structured = Vec::new();
+    if let Some(lyrics) = track.lyrics() {
+        let synced = lyrics.synced();
+        let lines = lyrics
+            .lines
+            .into_iter()
+            .map(|line| {
+                Element::new("line")
+                    .opt_attr("start", line.start)
+                    .text(line.text)
+            })
+            .collect();
+
+        structured.push(
+            Element::new("structuredLyrics")
+                .opt_attr("displayArtist", track.artist)
+                .opt_attr("displayTitle", track.title)
+                .attr("lang", lyrics.lang.unwrap_or_else(|| "und".to_owned()))
+                .attr("synced", synced)
+                .list("line", lines),
+        );
+    }
+
+    Ok(Some(
+        Element::new("lyricsList").list("structuredLyrics", structured),
+    ))
+}
+
+/// The original Subsonic endpoint, which finds the song by its artist and title.
+fn get_lyrics(db: &rusqlite::Connection, request: &Request) -> Result<Option<Element>, ApiError> {
+    let artist = request.param("artist");
+    let title = request.param("title");
+
+    let query = format!(
+        "{} WHERE ($title IS NULL OR track.name = $title COLLATE NOCASE)
+             AND ($artist IS NULL OR artist.name = $artist COLLATE NOCASE)
+         LIMIT 20",
+        LYRICS_QUERY
+    );
+    let mut stmt = db.prepare(&query)?;
+    let tracks = stmt
+        .query_map([title, artist], lyrics_track)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // Without any criteria every track matches, that's not a search
+    let found = if artist.is_none() && title.is_none() {
+        None
+    } else {
+        tracks
+            .into_iter()
+            .find_map(|track| track.lyrics().map(|lyrics| (track, lyrics)))
+    };
+
+    let element = match found {
+        Some((track, lyrics)) => Element::new("lyrics")
+            .opt_attr("artist", track.artist)
+            .opt_attr("title", track.title)
+            .text(lyrics.plain_text()),
+        None => Element::new("lyrics"),
+    };
+
+    Ok(Some(element))
+}
+
+fn stream(db: &rusqlite::Connection, request: &Request) -> Result<Response, ApiError> {
+    let id = get_id(request)?;
+
+    let path: Option<String> = match db.query_row(
+        "SELECT library_path(path) FROM track WHERE id = $id",
+        [id],
+        |row| row.get(0),
+    ) {
+        Ok(path) => path,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound("song")),
+        Err(err) => return Err(err.into()),
+    };
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => return Err(ApiError::NotFound("song file")),
+    };
+
+    Ok(stream::file_response(db, &path, request)?)
+}
+
+fn get_cover_art(db: &rusqlite::Connection, request: &Request) -> Result<Response, ApiError> {
+    let (query, not_found, id) = match request.param("id") {
+        Some(value) => match value.strip_prefix("ar-") {
+            Some(artist_id) => (
+                "SELECT library_path(path) FROM artist_image WHERE artist_id = $id",
+                "artist image",
+                artist_id,
+            ),
+            None => (
+                "SELECT library_path(cover_path) FROM album WHERE id = $id",
+                "album",
+                value,
+            ),
+        },
+        None => return Err(ApiError::MissingParameter("id")),
+    };
+    let id: i64 = id.parse().map_err(|_| ApiError::InvalidParameter("id"))?;
+
+    let path: Option<String> = match db.query_row(query, [id], |row| row.get(0)) {
+        Ok(path) => path,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(ApiError::NotFound(not_found)),
+        Err(err) => return Err(err.into()),
+    };
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => return Err(ApiError::NotFound("cover art")),
+    };
+
+    let data = fs::read(&path)?;
+
+    Ok(Response::new(200, content_type_for(&path), data))
+}
+
+fn dispatch(endpoint: &str, request: &Request, format: Format) -> Result<Response, ApiError> {
+    // Clients probe for the extensions before logging in
+    if endpoint == "getOpenSubsonicExtensions" {
+        return Ok(envelope(format, "ok", get_open_subsonic_extensions()));
+    }
+
+    let db = pool::get()?;
+    let user = user::authenticate(&db, request)?;
+    let user = user.as_ref();
+
+    let element = match endpoint {
+        "ping" => None,
+        "getLicense" => Some(Element::new("license").attr("valid", true)),
+        "getMusicFolders" => get_music_folders(&db)?,
+        "getArtists" => get_artists(&db, request)?,
+        "getArtist" => get_artist(&db, request)?,
+        "getArtistInfo" => get_artist_info(&db, request, "artistInfo")?,
+        "getArtistInfo2" => get_artist_info(&db, request, "artistInfo2")?,
+        "getAlbum" => get_album(&db, request, user)?,
+        "getSong" => get_song(&db, request, user)?,
+        "getAlbumList2" => get_album_list2(&db, request)?,
+        "getRandomSongs" => get_random_songs(&db, request, user)?,
+        "search3" => search3(&db, request, user)?,
+        "scrobble" => pool::write(&db, |db| scrobble(db, request, user))?,
+        "setRating" => pool::write(&db, |db| set_rating(db, request, user))?,
+        "star" => pool::write(&db, |db| star(db, request, user, true))?,
+        "unstar" => pool::write(&db, |db| star(db, request, user, false))?,
+        "getStarred2" => get_starred2(&db, user)?,
+        "getPlaylists" => get_playlists(&db, user)?,
+        "getPlaylist" => get_playlist(&db, request, user)?,
+        "createPlaylist" => pool::write(&db, |db| create_playlist(db, request, user))?,
+        "updatePlaylist" => pool::write(&db, |db| update_playlist(db, request, user))?,
+        "deletePlaylist" => pool::write(&db, |db| delete_playlist(db, request, user))?,
+        "getLyrics" => get_lyrics(&db, request)?,
+        "getLyricsBySongId" => get_lyrics_by_song_id(&db, request)?,
+        "getBookmarks" => get_bookmarks(&db, user)?,
+        "getPlayQueue" => get_play_queue(&db, user)?,
+        "savePlayQueue" => pool::write(&db, |db| save_play_queue(db, request, user))?,
+        "createBookmark" => pool::write(&db, |db| create_bookmark(db, request))?,
+        "deleteBookmark" => pool::write(&db, |db| delete_bookmark(db, request))?,
+        "stream" | "download" => return stream(&db, request),
+        "getCoverArt" => return get_cover_art(&db, request),
+        _ => return Err(ApiError::UnknownEndpoint(endpoint.to_owned())),
+    };
+
+    Ok(envelope(format, "ok", element))
+}
+
+/// Answers a request to `/rest/<endpoint>`.
+pub fn handle(endpoint: &str, request: &Request) -> Response {
+    let format = Format::from_request(request);
+
+    match dispatch(endpoint, request, format) {
+        Ok(response) => response,
+        // Clients retry on HTTP errors, not on API ones
+        Err(ApiError::Database(pool::PoolError::Busy)) => {
+            pool::error_response(&pool::PoolError::Busy)
+        }
+        Err(err) => {
+            if err.code() == 0 {
+                println!("{}", tr!("subsonic-failed", endpoint = endpoint, err = err));
+            }
+
+            let error = Element::new("error")
+                .attr("code", err.code())
+                .attr("message", err.to_string());
+            envelope(format, "failed", Some(error))
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Just enough of the systemd service protocol for `zik daemon` to run as a proper unit:
+//! socket activation and `sd_notify` readiness, status and watchdog messages.
+//!
+//! A socket activated setup looks like this, with `zik.socket`:
+//!
+//! ```text
+//! [Socket]
+//! ListenStream=127.0.0.1:4533
+//! ListenStream=%h/.local/share/zik/daemon.sock
+//! FileDescriptorName=http
+//! FileDescriptorName=control
+//! ```
+//!
+//! and `zik.service`:
+//!
+//! ```text
+//! [Service]
+//! Type=notify
+//! ExecStart=/usr/bin/zik daemon
+//! WatchdogSec=60
+//! ```
+//!
+//! Everything here is a no-op when not started by systemd, and it only exists on Unix.
+
+use std::env;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::time::Duration;
+
+/// The first file descriptor passed by systemd, `SD_LISTEN_FDS_START`.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Returns the sockets passed by systemd with their `FileDescriptorName`.
+///
+/// The environment variables are removed so that child processes don't mistake them as
+/// their own.
+pub fn listen_fds() -> Vec<(String, RawFd)> {
+    let for_us = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .is_some_and(|pid| pid == process::id());
+    let count: RawFd = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    if !for_us {
+        return Vec::new();
+    }
+
+    let mut names = names.split(':');
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            let name = names.next().unwrap_or("unknown").to_owned();
+            (name, fd)
+        })
+        .collect()
+}
+
+/// Sends a state change like `READY=1` to the service manager.
+///
+/// Returns false if the process wasn't started with `Type=notify`.
+pub fn notify(state: &str) -> io::Result<bool> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(false),
+    };
+
+    let socket = UnixDatagram::unbound()?;
+
+    let path = path.to_string_lossy();
+    match path.strip_prefix('@') {
+        // Abstract sockets only exist on Linux
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path.as_ref())?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Returns how often `WATCHDOG=1` must be sent, if the unit has a watchdog.
+///
+/// This is half of `WatchdogSec`, as recommended by `sd_watchdog_enabled(3)`.
+pub fn watchdog_interval() -> Option<Duration> {
+    let for_us = match env::var("WATCHDOG_PID") {
+        Ok(value) => value.parse::<u32>().ok() == Some(process::id()),
+        Err(_) => true,
+    };
+    if !for_us {
+        return None;
+    }
+
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+        .map(|usec| Duration::from_micros(usec / 2))
+}
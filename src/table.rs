@@ -0,0 +1,137 @@
+//! Listings as aligned columns when written to a terminal, with a bold header and colored
+//! columns unless `--no-color` is given or `NO_COLOR` is set.
+//!
+//! Output that isn't a terminal keeps the plain one line per item format of each listing,
+//! scripts parse it.
+
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `--no-color`.
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Default,
+    Dim,
+    Cyan,
+    Green,
+    Yellow,
+}
+
+impl Color {
+    fn code(self) -> Option<&'static str> {
+        match self {
+            Color::Default => None,
+            Color::Dim => Some("2"),
+            Color::Cyan => Some("36"),
+            Color::Green => Some("32"),
+            Color::Yellow => Some("33"),
+        }
+    }
+}
+
+pub fn disable_color() {
+    NO_COLOR.store(true, Ordering::Relaxed);
+}
+
+/// Returns true if listings should be printed as tables, stdout being a terminal.
+pub fn is_enabled() -> bool {
+    io::stdout().is_terminal()
+}
+
+fn use_color() -> bool {
+    !NO_COLOR.load(Ordering::Relaxed) && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+}
+
+struct Column {
+    header: String,
+    color: Color,
+    right: bool,
+}
+
+#[derive(Default)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Adds a column aligned to the left.
+    pub fn column(mut self, header: String, color: Color) -> Table {
+        self.columns.push(Column {
+            header,
+            color,
+            right: false,
+        });
+        self
+    }
+
+    /// Adds a column aligned to the right, for numbers.
+    pub fn number_column(mut self, header: String) -> Table {
+        self.columns.push(Column {
+            header,
+            color: Color::Default,
+            right: true,
+        });
+        self
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn print(&self) {
+        let color = use_color();
+        let mut widths: Vec<usize> = self
+            .columns
+            .iter()
+            .map(|column| column.header.chars().count())
+            .collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let header: Vec<String> = self
+            .columns
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| pad(&column.header, *width, column.right))
+            .collect();
+        let header = header.join("  ");
+        if color {
+            println!("\x1b[1m{}\x1b[0m", header.trim_end());
+        } else {
+            println!("{}", header.trim_end());
+        }
+
+        for row in &self.rows {
+            let cells: Vec<String> = self
+                .columns
+                .iter()
+                .zip(&widths)
+                .zip(row)
+                .map(|((column, width), cell)| {
+                    let cell = pad(cell, *width, column.right);
+                    match column.color.code() {
+                        Some(code) if color => format!("\x1b[{}m{}\x1b[0m", code, cell),
+                        _ => cell,
+                    }
+                })
+                .collect();
+            println!("{}", cells.join("  ").trim_end());
+        }
+    }
+}
+
+fn pad(value: &str, width: usize, right: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(value.chars().count()));
+    if right {
+        padding + value
+    } else {
+        value.to_owned() + &padding
+    }
+}
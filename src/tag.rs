@@ -0,0 +1,328 @@
+//! Writing zik's ratings and play counts into the tags, so they survive moving to another player.
+//!
+//! MP3 files get a POPM frame plus the FMPS_Rating and FMPS_Playcount TXXX frames, FLAC files
+//! the FMPS_RATING, FMPS_PLAYCOUNT and RATING comments; MP4 files can't be written.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::i18n::tr;
+use crate::jobs;
+use crate::ratings;
+
+/// The POPM email most players read the rating of.
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+
+pub enum CommandTagError {
+    SQLite(rusqlite::Error),
+    UserRequired,
+    UserNotFound(String),
+}
+impl From<rusqlite::Error> for CommandTagError {
+    fn from(err: rusqlite::Error) -> CommandTagError {
+        CommandTagError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandTagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandTagError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandTagError::UserRequired => f.write_str(&tr!("tag-user-required")),
+            CommandTagError::UserNotFound(name) => f.write_str(&tr!("user-not-found", name = name)),
+        }
+    }
+}
+
+struct Stats {
+    track_id: crate::TrackID,
+    path: String,
+    rating: Option<i64>,
+    play_count: i64,
+}
+
+enum SyncResult {
+    Updated,
+    UpToDate,
+    Unsupported,
+}
+
+enum SyncError {
+    Id3(id3::Error),
+    Flac(metaflac::Error),
+}
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::Id3(err) => f.write_str(&tr!("tag-id3-error", err = err)),
+            SyncError::Flac(err) => f.write_str(&tr!("tag-flac-error", err = err)),
+        }
+    }
+}
+
+/// The user whose stats are written: the one asked for, the one the tags are imported for,
+/// or the only one.
+fn find_user(db: &rusqlite::Connection, name: Option<&str>) -> Result<i64, CommandTagError> {
+    let name = match name {
+        Some(name) => Some(name.to_owned()),
+        None => crate::get_config_value(db, "tag_stats_user")?.filter(|name| !name.is_empty()),
+    };
+
+    match name {
+        Some(name) => {
+            match db.query_row("SELECT id FROM user WHERE name = $name", [&name], |row| {
+                row.get(0)
+            }) {
+                Ok(id) => Ok(id),
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    Err(CommandTagError::UserNotFound(name))
+                }
+                Err(err) => Err(err.into()),
+            }
+        }
+        None => {
+            let ids: Vec<i64> = {
+                let mut stmt = db.prepare("SELECT id FROM user LIMIT 2")?;
+                let rows = stmt.query_map([], |row| row.get(0))?;
+                rows.collect::<rusqlite::Result<_>>()?
+            };
+            match ids.as_slice() {
+                [id] => Ok(*id),
+                _ => Err(CommandTagError::UserRequired),
+            }
+        }
+    }
+}
+
+fn sync_id3(path: &Path, stats: &Stats, dry_run: bool) -> Result<SyncResult, SyncError> {
+    let mut tag = id3::Tag::read_from_path(path).map_err(SyncError::Id3)?;
+    let mut changed = false;
+
+    // Frames of other players are kept, only ours is replaced
+    let popm = ratings::popm_content(
+        POPM_EMAIL,
+        stats.rating.map_or(0, ratings::to_popm),
+        stats.play_count,
+    );
+    let existing: Vec<Vec<u8>> = tag
+        .frames()
+        .filter(|frame| frame.id() == "POPM")
+        .filter_map(|frame| match frame.content() {
+            id3::frame::Content::Unknown(data) => Some(data.clone()),
+            _ => None,
+        })
+        .collect();
+    let prefix = [POPM_EMAIL.as_bytes(), &[0]].concat();
+    if !existing.contains(&popm) {
+        tag.remove("POPM");
+        for data in existing
+            .into_iter()
+            .filter(|data| !data.starts_with(&prefix))
+        {
+            tag.add_frame(id3::Frame::with_content(
+                "POPM",
+                id3::frame::Content::Unknown(data),
+            ));
+        }
+        tag.add_frame(id3::Frame::with_content(
+            "POPM",
+            id3::frame::Content::Unknown(popm),
+        ));
+        changed = true;
+    }
+
+    let texts = [
+        ("FMPS_Rating", stats.rating.map(ratings::to_fmps)),
+        (
+            "FMPS_Playcount",
+            Some(stats.play_count.to_string()).filter(|_| stats.play_count > 0),
+        ),
+    ];
+    for (description, value) in texts {
+        let value = match value {
+            Some(value) => value,
+            None => continue,
+        };
+        let current = tag
+            .extended_texts()
+            .find(|text| text.description == description)
+            .map(|text| text.value.clone());
+        if current.as_ref() != Some(&value) {
+            tag.remove_extended_text(Some(description), None);
+            tag.add_extended_text(description, value);
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return Ok(SyncResult::UpToDate);
+    }
+    if !dry_run {
+        tag.write_to_path(path, id3::Version::Id3v24)
+            .map_err(SyncError::Id3)?;
+    }
+
+    Ok(SyncResult::Updated)
+}
+
+fn sync_flac(path: &Path, stats: &Stats, dry_run: bool) -> Result<SyncResult, SyncError> {
+    let mut tag = metaflac::Tag::read_from_path(path).map_err(SyncError::Flac)?;
+    let mut changed = false;
+
+    let comments = [
+        ("FMPS_RATING", stats.rating.map(ratings::to_fmps)),
+        ("RATING", stats.rating.map(ratings::to_percent)),
+        (
+            "FMPS_PLAYCOUNT",
+            Some(stats.play_count.to_string()).filter(|_| stats.play_count > 0),
+        ),
+    ];
+    for (key, value) in comments {
+        let value = match value {
+            Some(value) => value,
+            None => continue,
+        };
+        let current = tag
+            .get_vorbis(key)
+            .and_then(|mut values| values.next().map(|value| value.to_owned()));
+        if current.as_ref() != Some(&value) {
+            tag.set_vorbis(key, vec![value]);
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return Ok(SyncResult::UpToDate);
+    }
+    if !dry_run {
+        tag.save().map_err(SyncError::Flac)?;
+    }
+
+    Ok(SyncResult::Updated)
+}
+
+fn sync_file(stats: &Stats, dry_run: bool) -> Result<SyncResult, SyncError> {
+    let path = Path::new(&stats.path);
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("mp3") => sync_id3(path, stats, dry_run),
+        Some("flac") => sync_flac(path, stats, dry_run),
+        _ => Ok(SyncResult::Unsupported),
+    }
+}
+
+//
+// "tag" command
+//
+
+fn cmd_tag_sync_stats(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandTagError> {
+    let user_id = find_user(db, args.value_of("user"))?;
+    let dry_run = args.is_present("dry-run");
+
+    let all_stats: Vec<Stats> = {
+        let mut stmt = db.prepare(
+            "SELECT track.id, library_path(track.path), user_track.rating, user_track.play_count
+             FROM user_track
+             JOIN track ON track.id = user_track.track_id
+             WHERE user_track.user_id = $user_id
+               AND (user_track.rating IS NOT NULL OR user_track.play_count > 0)
+               AND track.path IS NOT NULL
+               AND track.missing_since IS NULL
+             ORDER BY track.path",
+        )?;
+        let rows = stmt.query_map([user_id], |row| {
+            Ok(Stats {
+                track_id: row.get(0)?,
+                path: row.get(1)?,
+                rating: row.get(2)?,
+                play_count: row.get(3)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+
+    let (mut updated, mut up_to_date, mut unsupported, mut failed) = (0, 0, 0, 0);
+
+    for stats in &all_stats {
+        match sync_file(stats, dry_run) {
+            Ok(SyncResult::Updated) => {
+                updated += 1;
+                let rating = stats
+                    .rating
+                    .map_or("no rating".to_owned(), |stars| format!("{} stars", stars));
+                if dry_run {
+                    println!(
+                        "{}",
+                        tr!(
+                            "tag-would-update",
+                            path = stats.path,
+                            rating = rating,
+                            plays = stats.play_count
+                        )
+                    );
+                    continue;
+                }
+                println!(
+                    "{}",
+                    tr!(
+                        "tag-updated",
+                        path = stats.path,
+                        rating = rating,
+                        plays = stats.play_count
+                    )
+                );
+
+                // The content changed so the hash did, and the next scan mustn't import
+                // these stats back on top of themselves
+                db.execute(
+                    "UPDATE track SET hash = NULL WHERE id = $id",
+                    [stats.track_id],
+                )?;
+                db.execute(
+                    "UPDATE user_track SET tags_imported = 1
+                     WHERE user_id = $user_id AND track_id = $track_id",
+                    rusqlite::params![user_id, stats.track_id],
+                )?;
+                jobs::enqueue_for_track(db, stats.track_id)?;
+            }
+            Ok(SyncResult::UpToDate) => up_to_date += 1,
+            Ok(SyncResult::Unsupported) => unsupported += 1,
+            Err(err) => {
+                failed += 1;
+                println!("{}", tr!("tag-write-failed", path = stats.path, err = err));
+            }
+        }
+    }
+
+    println!(
+        "{}",
+        tr!(
+            if dry_run {
+                "tag-summary-dry-run"
+            } else {
+                "tag-summary"
+            },
+            updated = updated,
+            up_to_date = up_to_date,
+            unsupported = unsupported,
+            failed = failed
+        )
+    );
+
+    Ok(())
+}
+
+pub fn cmd_tag(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandTagError> {
+    match args.subcommand() {
+        Some(("sync-stats", sub_args)) => cmd_tag_sync_stats(db, sub_args),
+        _ => Ok(()),
+    }
+}
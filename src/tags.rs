@@ -0,0 +1,501 @@
+//! The tags of every format read the same way, by field.
+//!
+//! Each format maps the fields to its own frames, atoms or comments in a `TagReader`
+//! implementation, and `Metadata::from_tag` parses them once for all of them. FLAC files are in
+//! `vorbis`, ID3 and MP4 ones here.
+//!
+//! The `tag_fields` config key reads more fields into `track_field`, for tag conventions zik
+//! knows nothing about: `zik config tag_fields "mood=MOOD,TXXX:MY_MOOD;energy=ENERGY"`. A key
+//! is a Vorbis comment, an ID3 TXXX description and an MP4 freeform name at once, unless
+//! prefixed by `VORBIS:`, `TXXX:`, `ID3:` for another ID3 frame, or `MP4:`.
+
+use std::fmt;
+
+use crate::genre;
+use crate::i18n::tr;
+use crate::mp4meta::FreeformTags;
+use crate::ratings;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Date,
+    OriginalDate,
+    TrackNumber,
+    TrackTotal,
+    DiscNumber,
+    DiscTotal,
+    Genre,
+    Comment,
+    Lyrics,
+    ReleaseType,
+    Compilation,
+    FmpsRating,
+    Rating,
+    FmpsPlayCount,
+    MusicBrainzAlbumId,
+    MusicBrainzArtistId,
+    Label,
+    ReplayGainTrack,
+    ReplayGainAlbum,
+    Mood,
+    Energy,
+}
+
+/// Where a field of `tag_fields` is read from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CustomKey {
+    /// A Vorbis comment, an ID3 TXXX frame or an MP4 freeform atom.
+    Any(String),
+    Vorbis(String),
+    Id3Frame(String),
+    Id3Extended(String),
+    Mp4Freeform(String),
+}
+
+impl CustomKey {
+    fn parse(value: &str) -> Result<CustomKey, String> {
+        let value = value.trim();
+        let (prefix, key) = match value.split_once(':') {
+            Some((prefix, key)) => (Some(prefix), key.trim()),
+            None => (None, value),
+        };
+        if key.is_empty() {
+            return Err(tr!("tags-empty-key", value = value));
+        }
+
+        match prefix.map(|prefix| prefix.trim().to_uppercase()).as_deref() {
+            None => Ok(CustomKey::Any(key.to_owned())),
+            Some("VORBIS") => Ok(CustomKey::Vorbis(key.to_uppercase())),
+            Some("TXXX") => Ok(CustomKey::Id3Extended(key.to_owned())),
+            Some("ID3") if key.len() == 4 => Ok(CustomKey::Id3Frame(key.to_uppercase())),
+            Some("ID3") => Err(tr!("tags-invalid-id3-frame", key = key)),
+            Some("MP4") => Ok(CustomKey::Mp4Freeform(key.to_owned())),
+            Some(prefix) => Err(tr!("tags-unknown-prefix", prefix = prefix)),
+        }
+    }
+}
+
+/// The fields read from the keys of `tag_fields`, by name.
+#[derive(Default)]
+pub struct CustomFields {
+    fields: Vec<(String, Vec<CustomKey>)>,
+}
+
+impl CustomFields {
+    pub fn parse(value: &str) -> Result<CustomFields, String> {
+        let mut fields = Vec::new();
+        for mapping in value
+            .split(';')
+            .filter(|mapping| !mapping.trim().is_empty())
+        {
+            let (name, keys) = match mapping.split_once('=') {
+                Some((name, keys)) => (name.trim().to_lowercase(), keys),
+                None => return Err(tr!("tags-no-keys", mapping = mapping)),
+            };
+            if name.is_empty() {
+                return Err(tr!("tags-no-name", mapping = mapping));
+            }
+
+            let keys = keys
+                .split(',')
+                .map(CustomKey::parse)
+                .collect::<Result<Vec<CustomKey>, String>>()?;
+            fields.push((name, keys));
+        }
+
+        Ok(CustomFields { fields })
+    }
+
+    /// Loads the fields of `tag_fields`, an invalid value reads none.
+    pub fn load(db: &rusqlite::Connection) -> rusqlite::Result<CustomFields> {
+        let value = crate::get_config_value(db, "tag_fields")?;
+        Ok(value
+            .and_then(|value| CustomFields::parse(&value).ok())
+            .unwrap_or_default())
+    }
+
+    /// Returns the values of the fields in a tag, by field name.
+    pub fn read(&self, tag: &dyn TagReader) -> Vec<(String, String)> {
+        let mut values = Vec::new();
+        for (name, keys) in &self.fields {
+            let found = keys
+                .iter()
+                .map(|key| non_empty(tag.get_custom(key)))
+                .find(|found| !found.is_empty())
+                .unwrap_or_default();
+            values.extend(found.into_iter().map(|value| (name.clone(), value)));
+        }
+        values
+    }
+}
+
+pub trait TagReader {
+    /// Returns every value of a field, most fields have only one.
+    fn get_all(&self, field: Field) -> Vec<String>;
+
+    /// Returns every value of a key of `tag_fields`, none if it's for another format.
+    fn get_custom(&self, key: &CustomKey) -> Vec<String>;
+
+    fn duration_ms(&self) -> Option<i64>;
+
+    fn get(&self, field: Field) -> Option<String> {
+        self.get_all(field).into_iter().next()
+    }
+
+    /// Returns a number and the total it's out of, like a track number. Both can be in the
+    /// number, as "3/12", the total field wins.
+    fn get_number_pair(&self, number: Field, total: Field) -> (Option<usize>, Option<usize>) {
+        let (number, pair_total) = self
+            .get(number)
+            .map_or((None, None), |value| crate::parse_number_pair(&value));
+        let total = self
+            .get(total)
+            .and_then(|value| value.trim().parse().ok())
+            .or(pair_total);
+
+        (number, total)
+    }
+
+    /// Returns the rating other players wrote, in stars.
+    fn rating(&self) -> Option<i64> {
+        self.get(Field::FmpsRating)
+            .and_then(|value| ratings::from_fmps(&value))
+            .or_else(|| {
+                self.get(Field::Rating)
+                    .and_then(|value| ratings::from_percent(&value))
+            })
+    }
+
+    fn play_count(&self) -> Option<i64> {
+        self.get(Field::FmpsPlayCount)
+            .and_then(|value| ratings::parse_play_count(&value))
+    }
+}
+
+/// Returns the non empty values, trimmed.
+fn non_empty<I, S>(values: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    values
+        .into_iter()
+        .map(|value| value.as_ref().trim().to_owned())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+//
+// ID3
+//
+
+/// Returns the main ID3 comment.
+///
+/// Comments with a description are mostly private data of other taggers, like iTunes'
+/// "iTunNORM" or "iTunSMPB", so the comment without one is preferred.
+fn id3_comment(tag: &id3::Tag) -> Option<&str> {
+    let comments = || {
+        tag.comments()
+            .filter(|comment| !comment.text.trim().is_empty())
+    };
+
+    comments()
+        .find(|comment| comment.description.is_empty())
+        .or_else(|| comments().find(|comment| !comment.description.starts_with("iTun")))
+        .map(|comment| comment.text.as_str())
+}
+
+/// Returns the value of the first TXXX frame with one of `descriptions`, which taggers use for
+/// anything ID3 has no frame for.
+fn id3_extended_text<'a>(tag: &'a id3::Tag, descriptions: &[&str]) -> Option<&'a str> {
+    descriptions.iter().find_map(|description| {
+        tag.extended_texts()
+            .find(|text| text.description.eq_ignore_ascii_case(description))
+            .map(|text| text.value.as_str())
+    })
+}
+
+/// Returns the value of the first text frame with one of `ids`.
+fn id3_text<'a>(tag: &'a id3::Tag, ids: &[&str]) -> Option<&'a str> {
+    ids.iter().find_map(|id| {
+        tag.frames()
+            .filter(|frame| frame.id() == *id)
+            .find_map(|frame| match frame.content() {
+                id3::frame::Content::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+    })
+}
+
+/// Returns the first raw content of the `id` frames.
+fn id3_unknown_frame<'a>(tag: &'a id3::Tag, id: &str) -> Option<&'a [u8]> {
+    tag.frames()
+        .filter(|frame| frame.id() == id)
+        .find_map(|frame| match frame.content() {
+            id3::frame::Content::Unknown(data) => Some(data.as_slice()),
+            _ => None,
+        })
+}
+
+impl TagReader for id3::Tag {
+    fn get_all(&self, field: Field) -> Vec<String> {
+        let number = |n: Option<u32>| n.map(|n| vec![n.to_string()]).unwrap_or_default();
+
+        // Borrowed from the frames, copied once trimmed
+        let value = match field {
+            Field::Title => self.title(),
+            // ID3v2.4 separates the values of a text frame with NUL
+            Field::Artist => {
+                return non_empty(
+                    self.artist()
+                        .map(|value| value.split('\0'))
+                        .into_iter()
+                        .flatten(),
+                )
+            }
+            Field::Album => self.album(),
+            Field::AlbumArtist => self.album_artist(),
+            Field::Date => {
+                return self
+                    .year()
+                    .map(|year| vec![year.to_string()])
+                    .unwrap_or_default()
+            }
+            // TORY is the ID3v2.3 frame
+            Field::OriginalDate => id3_text(self, &["TDOR", "TORY"]),
+            Field::TrackNumber => return number(self.track()),
+            Field::TrackTotal => return number(self.total_tracks()),
+            Field::DiscNumber => return number(self.disc()),
+            Field::DiscTotal => return number(self.total_discs()),
+            Field::Genre => self.genre(),
+            Field::Comment => id3_comment(self),
+            Field::Lyrics => self.lyrics().next().map(|lyrics| lyrics.text.as_str()),
+            Field::ReleaseType => {
+                id3_extended_text(self, &["RELEASETYPE", "MusicBrainz Album Type"])
+            }
+            Field::Compilation => id3_text(self, &["TCMP"]),
+            Field::FmpsRating => id3_extended_text(self, &["FMPS_Rating"]),
+            Field::Rating => id3_extended_text(self, &["RATING"]),
+            Field::FmpsPlayCount => id3_extended_text(self, &["FMPS_Playcount"]),
+            Field::MusicBrainzAlbumId => id3_extended_text(self, &["MusicBrainz Album Id"]),
+            Field::MusicBrainzArtistId => id3_extended_text(self, &["MusicBrainz Artist Id"]),
+            Field::Label => {
+                id3_text(self, &["TPUB"]).or_else(|| id3_extended_text(self, &["LABEL"]))
+            }
+            Field::ReplayGainTrack => id3_extended_text(self, &["REPLAYGAIN_TRACK_GAIN"]),
+            Field::ReplayGainAlbum => id3_extended_text(self, &["REPLAYGAIN_ALBUM_GAIN"]),
+            Field::Mood => {
+                return non_empty(
+                    id3_text(self, &["TMOO"])
+                        .or_else(|| id3_extended_text(self, &["MOOD"]))
+                        .into_iter()
+                        .flat_map(|value| value.split('\0')),
+                )
+            }
+            Field::Energy => id3_extended_text(self, &["ENERGY", "EnergyLevel"]),
+        };
+
+        non_empty(value)
+    }
+
+    fn get_custom(&self, key: &CustomKey) -> Vec<String> {
+        let value = match key {
+            CustomKey::Any(description) | CustomKey::Id3Extended(description) => {
+                id3_extended_text(self, &[description])
+            }
+            CustomKey::Id3Frame(id) => id3_text(self, &[id]),
+            CustomKey::Vorbis(_) | CustomKey::Mp4Freeform(_) => None,
+        };
+
+        value
+            .map(|value| value.split('\0').map(|value| value.to_owned()).collect())
+            .unwrap_or_default()
+    }
+
+    /// From the TLEN frame, the only place an ID3 tag has it.
+    fn duration_ms(&self) -> Option<i64> {
+        self.duration().map(i64::from)
+    }
+
+    fn rating(&self) -> Option<i64> {
+        self.get(Field::FmpsRating)
+            .and_then(|value| ratings::from_fmps(&value))
+            .or_else(|| {
+                id3_unknown_frame(self, "POPM").and_then(|data| ratings::parse_popm(data).0)
+            })
+            .or_else(|| {
+                self.get(Field::Rating)
+                    .and_then(|value| ratings::from_percent(&value))
+            })
+    }
+
+    fn play_count(&self) -> Option<i64> {
+        self.get(Field::FmpsPlayCount)
+            .and_then(|value| ratings::parse_play_count(&value))
+            .or_else(|| {
+                id3_unknown_frame(self, "POPM").and_then(|data| ratings::parse_popm(data).1)
+            })
+            .or_else(|| id3_unknown_frame(self, "PCNT").and_then(ratings::parse_pcnt))
+    }
+}
+
+//
+// MP4
+//
+
+/// The tags of an MP4 file: the standard iTunes atoms read by mp4parse, and the freeform ones.
+pub struct Mp4Tag {
+    metadata: mp4parse::MetadataBox,
+    freeform: FreeformTags,
+    duration_ms: Option<i64>,
+}
+
+impl Mp4Tag {
+    pub fn new(
+        metadata: mp4parse::MetadataBox,
+        freeform: FreeformTags,
+        duration_ms: Option<i64>,
+    ) -> Mp4Tag {
+        Mp4Tag {
+            metadata,
+            freeform,
+            duration_ms,
+        }
+    }
+
+    fn genre(&self) -> Option<&str> {
+        match &self.metadata.genre {
+            // The gnre atom stores the ID3v1 genre index plus one
+            Some(mp4parse::Genre::StandardGenre(n)) => {
+                (*n as usize).checked_sub(1).and_then(genre::id3v1_genre)
+            }
+            Some(mp4parse::Genre::CustomGenre(value)) => mp4_string(Some(value)),
+            None => None,
+        }
+    }
+}
+
+fn mp4_string(value: Option<&mp4parse::TryString>) -> Option<&str> {
+    value.and_then(|value| std::str::from_utf8(value).ok())
+}
+
+impl TagReader for Mp4Tag {
+    fn get_all(&self, field: Field) -> Vec<String> {
+        let metadata = &self.metadata;
+        let number = |n: Option<u8>| n.map(|n| vec![n.to_string()]).unwrap_or_default();
+
+        // Borrowed from the atoms, copied once trimmed
+        let value = match field {
+            Field::Title => mp4_string(metadata.title.as_ref()),
+            Field::Artist => mp4_string(metadata.artist.as_ref()),
+            Field::Album => mp4_string(metadata.album.as_ref()),
+            Field::AlbumArtist => mp4_string(metadata.album_artist.as_ref()),
+            Field::Date => mp4_string(metadata.year.as_ref()),
+            Field::OriginalDate => self.freeform.get(&["ORIGINALDATE", "originalyear"]),
+            Field::TrackNumber => return number(metadata.track_number),
+            Field::TrackTotal => return number(metadata.total_tracks),
+            Field::DiscNumber => return number(metadata.disc_number),
+            Field::DiscTotal => return number(metadata.total_discs),
+            Field::Genre => self.genre(),
+            Field::Comment => mp4_string(metadata.comment.as_ref()),
+            Field::Lyrics => mp4_string(metadata.lyrics.as_ref()),
+            Field::ReleaseType => {
+                return self
+                    .freeform
+                    .get_all(&["RELEASETYPE", "MusicBrainz Album Type"])
+            }
+            Field::Compilation => metadata
+                .compilation
+                .map(|compilation| if compilation { "1" } else { "0" }),
+            Field::FmpsRating => self.freeform.get(&["FMPS_Rating"]),
+            Field::Rating => self.freeform.get(&["RATING"]),
+            Field::FmpsPlayCount => self.freeform.get(&["FMPS_Playcount"]),
+            Field::MusicBrainzAlbumId => self.freeform.get(&["MusicBrainz Album Id"]),
+            Field::MusicBrainzArtistId => self.freeform.get(&["MusicBrainz Artist Id"]),
+            Field::Label => self.freeform.get(&["LABEL", "publisher"]),
+            Field::ReplayGainTrack => self.freeform.get(&["replaygain_track_gain"]),
+            Field::ReplayGainAlbum => self.freeform.get(&["replaygain_album_gain"]),
+            Field::Mood => return self.freeform.get_all(&["MOOD"]),
+            Field::Energy => self.freeform.get(&["ENERGY", "EnergyLevel"]),
+        };
+
+        non_empty(value)
+    }
+
+    fn get_custom(&self, key: &CustomKey) -> Vec<String> {
+        match key {
+            CustomKey::Any(name) | CustomKey::Mp4Freeform(name) => self.freeform.get_all(&[name]),
+            _ => Vec::new(),
+        }
+    }
+
+    fn duration_ms(&self) -> Option<i64> {
+        self.duration_ms
+    }
+}
+
+//
+// "fields" command
+//
+
+pub enum CommandFieldsError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for CommandFieldsError {
+    fn from(err: rusqlite::Error) -> CommandFieldsError {
+        CommandFieldsError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandFieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandFieldsError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+        }
+    }
+}
+
+pub fn cmd_fields(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandFieldsError> {
+    let name = args.value_of("name").map(|name| name.to_lowercase());
+
+    // The values of a field, or the fields themselves
+    let query = match name {
+        Some(_) => {
+            "SELECT value, COUNT(*) FROM track_field
+             WHERE name = $name
+             GROUP BY value
+             ORDER BY COUNT(*) DESC, value COLLATE natural_sort"
+        }
+        None => {
+            "SELECT name, COUNT(DISTINCT track_id) FROM track_field
+             WHERE $name IS NULL
+             GROUP BY name
+             ORDER BY name"
+        }
+    };
+    let mut stmt = db.prepare(query)?;
+    let mut rows = stmt.query([&name])?;
+
+    let mut n = 0;
+    while let Some(row) = rows.next()? {
+        let value: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        println!("{} ({})", value, tr!("tags-tracks", count = count));
+        n += 1;
+    }
+
+    if n == 0 {
+        match name {
+            Some(name) => println!("{}", tr!("tags-no-field", name = name)),
+            None => println!("{}", tr!("tags-no-custom-fields")),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+//! Slowing a scan down so a rescan in the background doesn't drain a laptop's battery or make
+//! it sluggish: `zik scan --throttle --max-rate 20 --sleep 10`.
+
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::i18n::tr;
+
+/// Limits how fast a scan goes through the files, no limit by default.
+#[derive(Default)]
+pub struct Throttle {
+    /// The minimum time between two files, from `--max-rate`.
+    interval: Option<Duration>,
+    /// A pause after every file, from `--sleep`.
+    sleep: Option<Duration>,
+    last_file: Option<Instant>,
+}
+
+impl Throttle {
+    pub fn new(max_rate: Option<f64>, sleep: Option<Duration>) -> Throttle {
+        Throttle {
+            interval: max_rate.map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            sleep,
+            last_file: None,
+        }
+    }
+
+    /// Waits until the next file can be read.
+    pub fn wait(&mut self) {
+        if let Some(last_file) = self.last_file {
+            if let Some(sleep) = self.sleep {
+                thread::sleep(sleep);
+            }
+            if let Some(interval) = self.interval {
+                let elapsed = last_file.elapsed();
+                if elapsed < interval {
+                    thread::sleep(interval - elapsed);
+                }
+            }
+        }
+        self.last_file = Some(Instant::now());
+    }
+}
+
+/// Gives the process the idle I/O class and the lowest CPU priority, with `ionice` and
+/// `renice` since there's no binding to their system calls here.
+#[cfg(target_os = "linux")]
+pub fn lower_priority() {
+    let pid = std::process::id().to_string();
+    let commands: [(&str, &[&str]); 2] = [
+        ("ionice", &["-c", "3", "-p", &pid]),
+        ("renice", &["-n", "19", "-p", &pid]),
+    ];
+
+    for (program, args) in commands {
+        let result = Command::new(program)
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .status();
+        match result {
+            Ok(status) if status.success() => (),
+            Ok(status) => println!(
+                "{}",
+                tr!("throttle-failed", program = program, status = status)
+            ),
+            Err(err) => println!("{}", tr!("throttle-error", program = program, err = err)),
+        }
+    }
+}
+
+/// Gives the process the lowest CPU priority, I/O priorities being a Linux thing.
+#[cfg(not(target_os = "linux"))]
+pub fn lower_priority() {
+    let pid = std::process::id().to_string();
+    let result = Command::new("renice")
+        .args(["-n", "19", "-p", &pid])
+        .stdout(std::process::Stdio::null())
+        .status();
+    if let Err(err) = result {
+        println!("{}", tr!("throttle-error", program = "renice", err = err));
+    }
+}
@@ -0,0 +1,118 @@
+//! Tracks whose file is gone are never deleted by a scan, only marked with `missing_since`,
+//! so a library whose NAS was unmounted during a scan keeps its play counts and ratings.
+//!
+//! These tombstones are purged after `tombstone_ttl` days, 30 by default; `zik restore`
+//! lists them and brings back the ones that shouldn't be purged.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::i18n::tr;
+
+const DEFAULT_TTL_DAYS: usize = 30;
+
+pub enum CommandRestoreError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for CommandRestoreError {
+    fn from(err: rusqlite::Error) -> CommandRestoreError {
+        CommandRestoreError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandRestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandRestoreError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+        }
+    }
+}
+
+/// Deletes the tracks missing for longer than `tombstone_ttl` days, with everything attached
+/// to them, returning how many were deleted.
+pub fn purge(db: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    let ttl_days = crate::get_config_usize(db, "tombstone_ttl")?.unwrap_or(DEFAULT_TTL_DAYS);
+    if ttl_days == 0 {
+        return Ok(0);
+    }
+
+    // Everything referencing the tracks is deleted with them
+    let purged = db.execute(
+        "DELETE FROM track
+         WHERE missing_since IS NOT NULL AND missing_since < unixepoch() - $ttl * 86400",
+        [ttl_days as i64],
+    )?;
+
+    Ok(purged)
+}
+
+fn print_tombstones(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    let mut stmt = db.prepare(
+        "SELECT library_path(path), date(missing_since, 'unixepoch')
+         FROM track
+         WHERE missing_since IS NOT NULL
+         ORDER BY path",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut n = 0;
+    while let Some(row) = rows.next()? {
+        let path: Option<String> = row.get(0)?;
+        let since: String = row.get(1)?;
+        println!(
+            "{}\t{}",
+            path.unwrap_or_default(),
+            tr!("tombstones-missing-since", since = since)
+        );
+        n += 1;
+    }
+
+    if n == 0 {
+        println!("{}", tr!("tombstones-none"));
+    }
+
+    Ok(())
+}
+
+//
+// "restore" command
+//
+
+pub fn cmd_restore(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandRestoreError> {
+    let paths: Vec<&str> = args.values_of("path").unwrap_or_default().collect();
+    let all = args.is_present("all");
+
+    if paths.is_empty() && !all {
+        print_tombstones(db)?;
+        return Ok(());
+    }
+
+    let restored = if all {
+        db.execute(
+            "UPDATE track SET missing_since = NULL WHERE missing_since IS NOT NULL",
+            [],
+        )?
+    } else {
+        let savepoint = db.savepoint()?;
+        let mut restored = 0;
+        for path in paths {
+            let path = crate::library::relative(&crate::canonical_path(Path::new(path)));
+            // A folder restores every track under it
+            let folder = format!("{}/", path.trim_end_matches('/'));
+            restored += savepoint.execute(
+                "UPDATE track SET missing_since = NULL
+                 WHERE missing_since IS NOT NULL
+                   AND (path = $path OR substr(path, 1, length($folder)) = $folder)",
+                rusqlite::params![path, folder],
+            )?;
+        }
+        savepoint.commit()?;
+        restored
+    };
+
+    println!("{}", tr!("tombstones-restored", tracks = restored));
+
+    Ok(())
+}
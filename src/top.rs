@@ -0,0 +1,188 @@
+//! Charts of the most played artists, albums and tracks, from the plays scrobbled through the
+//! Subsonic API.
+
+use std::fmt;
+
+use crate::i18n::tr;
+use crate::json;
+
+pub enum CommandTopError {
+    SQLite(rusqlite::Error),
+    InvalidSince(String),
+    InvalidLimit(String),
+    UserNotFound(String),
+    CountOfTracks,
+}
+impl From<rusqlite::Error> for CommandTopError {
+    fn from(err: rusqlite::Error) -> CommandTopError {
+        CommandTopError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandTopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandTopError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandTopError::InvalidSince(value) => {
+                f.write_str(&tr!("invalid-period", value = value))
+            }
+            CommandTopError::InvalidLimit(value) => {
+                f.write_str(&tr!("invalid-limit", value = value))
+            }
+            CommandTopError::UserNotFound(name) => f.write_str(&tr!("user-not-found", name = name)),
+            CommandTopError::CountOfTracks => f.write_str(&tr!("top-count-of-tracks")),
+        }
+    }
+}
+
+/// Parses a period like "90d" into seconds.
+pub fn parse_since(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let unit = value.chars().last()?;
+    let number: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+
+    let days = match unit {
+        'd' => number,
+        'w' => number * 7,
+        'm' => number * 30,
+        'y' => number * 365,
+        _ => return None,
+    };
+    if days <= 0 {
+        return None;
+    }
+
+    Some(days * 24 * 3600)
+}
+
+/// Formats a listening time like "12h 05m".
+pub fn format_duration(ms: i64) -> String {
+    let minutes = ms / 60_000;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+//
+// "top" command
+//
+
+pub fn cmd_top(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandTopError> {
+    let kind = args.value_of("kind").unwrap();
+    let by = args.value_of("by").unwrap_or("playcount");
+
+    let since = match args.value_of("since") {
+        Some(value) => match parse_since(value) {
+            Some(seconds) => Some(seconds),
+            None => return Err(CommandTopError::InvalidSince(value.to_owned())),
+        },
+        None => None,
+    };
+    let limit: i64 = match args.value_of("limit") {
+        Some(value) => match value.parse() {
+            Ok(limit) if limit > 0 => limit,
+            _ => return Err(CommandTopError::InvalidLimit(value.to_owned())),
+        },
+        None => 10,
+    };
+
+    let user = args.value_of("user");
+    if let Some(name) = user {
+        let exists: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM user WHERE name = $name)",
+            [name],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(CommandTopError::UserNotFound(name.to_owned()));
+        }
+    }
+
+    // What's ranked, its name and the artist shown next to it
+    let (group, name, artist) = match kind {
+        "artists" => ("track.artist_id", "artist.name", "NULL"),
+        "albums" => ("track.album_id", "album.name", "album_artist.name"),
+        _ => ("track.id", "track.name", "artist.name"),
+    };
+    let order = match by {
+        "duration" => "duration_ms",
+        "count" if kind == "tracks" => return Err(CommandTopError::CountOfTracks),
+        "count" => "tracks",
+        _ => "plays",
+    };
+
+    let query = format!(
+        "SELECT {group}, {name}, {artist},
+                COUNT(*) AS plays,
+                coalesce(SUM(track.duration_ms), 0) AS duration_ms,
+                COUNT(DISTINCT track.id) AS tracks
+         FROM play
+         JOIN track ON track.id = play.track_id
+         LEFT JOIN artist ON artist.id = track.artist_id
+         LEFT JOIN album ON album.id = track.album_id
+         LEFT JOIN artist album_artist ON album_artist.id = album.artist_id
+         WHERE {group} IS NOT NULL
+           AND ($since IS NULL OR play.played_at >= unixepoch() - $since)
+           AND ($user IS NULL OR play.user_id = (SELECT id FROM user WHERE name = $user))
+         GROUP BY {group}
+         ORDER BY {order} DESC, plays DESC, {name} COLLATE natural_sort
+         LIMIT $limit",
+        group = group,
+        name = name,
+        artist = artist,
+        order = order,
+    );
+
+    let mut stmt = db.prepare(&query)?;
+    let mut rows = stmt.query(rusqlite::params![since, user, limit])?;
+
+    let json = args.is_present("json");
+    let mut values = Vec::new();
+    let mut rank = 0;
+
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let name: Option<String> = row.get(1)?;
+        let artist: Option<String> = row.get(2)?;
+        let plays: i64 = row.get(3)?;
+        let duration_ms: i64 = row.get(4)?;
+        let tracks: i64 = row.get(5)?;
+        rank += 1;
+
+        if json {
+            values.push(json::object(&[
+                ("rank", rank.to_string()),
+                ("id", id.to_string()),
+                ("name", json::opt_string(name.as_deref())),
+                ("artist", json::opt_string(artist.as_deref())),
+                ("plays", plays.to_string()),
+                ("duration_ms", duration_ms.to_string()),
+                ("tracks", tracks.to_string()),
+            ]));
+            continue;
+        }
+
+        let value = match order {
+            "duration_ms" => format_duration(duration_ms),
+            "tracks" => format!("{} tracks", tracks),
+            _ => format!("{} plays", plays),
+        };
+        let name = name.unwrap_or_default();
+        match artist {
+            Some(artist) => println!("{:>3}. {:>10}  {} - {}", rank, value, artist, name),
+            None => println!("{:>3}. {:>10}  {}", rank, value, name),
+        }
+    }
+
+    if json {
+        println!("{}", json::array(&values));
+    } else if rank == 0 {
+        println!("{}", tr!("top-no-plays"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,255 @@
+//! Listings of the files of albums with their sizes and SHA-1, for cross-seeding and
+//! verification tools to match torrents against without hashing the library themselves.
+//!
+//! An album is the folder holding all its tracks, listed whole, artwork and rip logs
+//! included, since a torrent of it has them too; files are in the order torrents list them,
+//! by path. The SHA-1 of tracks is kept in `track_sha1` until their size or modification
+//! time changes, other files are small enough to hash every time. With a piece length, the
+//! BitTorrent v1 pieces of the folder as a single torrent are listed too, which means reading
+//! every file.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::{archive, hash, json, storage};
+
+/// A file of an album folder.
+struct File {
+    path: PathBuf,
+    size: u64,
+    modified_at: i64,
+    track_id: Option<i64>,
+    sha1: Option<String>,
+}
+
+/// The BitTorrent v1 pieces of files read one after the other.
+struct Pieces {
+    length: usize,
+    current: hash::Sha1,
+    current_len: usize,
+    hashes: Vec<u8>,
+}
+
+impl Pieces {
+    fn new(length: usize) -> Pieces {
+        Pieces {
+            length,
+            current: hash::Sha1::new(),
+            current_len: 0,
+            hashes: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = (self.length - self.current_len).min(data.len());
+            self.current.update(&data[..n]);
+            self.current_len += n;
+            data = &data[n..];
+
+            if self.current_len == self.length {
+                let piece = std::mem::replace(&mut self.current, hash::Sha1::new());
+                self.hashes.extend_from_slice(&piece.finish());
+                self.current_len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.current_len > 0 {
+            self.hashes.extend_from_slice(&self.current.finish());
+        }
+        self.hashes
+    }
+}
+
+/// Reads a file once for its SHA-1 and the pieces it's part of.
+fn read_file(path: &Path, mut pieces: Option<&mut Pieces>) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = hash::Sha1::new();
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        if let Some(pieces) = pieces.as_deref_mut() {
+            pieces.update(&buf[..n]);
+        }
+    }
+
+    Ok(hash::to_hex(&hasher.finish()))
+}
+
+fn modified_at(metadata: &fs::Metadata) -> i64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
+
+/// Returns the folder of the album, the deepest one holding all its tracks, none if some are
+/// in archives or remote.
+fn album_folder(db: &rusqlite::Connection, album_id: i64) -> rusqlite::Result<Option<PathBuf>> {
+    let mut stmt = db.prepare(
+        "SELECT library_path(path) FROM track
+         WHERE album_id = $id AND path IS NOT NULL AND missing_since IS NULL",
+    )?;
+    let paths = stmt
+        .query_map([album_id], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut folder: Option<PathBuf> = None;
+    for path in &paths {
+        if storage::is_remote(path) || archive::split(Path::new(path)).is_some() {
+            return Ok(None);
+        }
+        let parent = match Path::new(path).parent() {
+            Some(parent) => parent,
+            None => return Ok(None),
+        };
+        folder = Some(match folder {
+            None => parent.to_path_buf(),
+            Some(folder) => folder
+                .ancestors()
+                .find(|ancestor| parent.starts_with(ancestor))
+                .unwrap_or(Path::new("/"))
+                .to_path_buf(),
+        });
+    }
+
+    Ok(folder)
+}
+
+/// Lists the files of `folder` by path, with the cached SHA-1 of the tracks still valid.
+fn list_files(db: &rusqlite::Connection, folder: &Path) -> io::Result<Vec<File>> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(folder).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(io::Error::other)?;
+        let path = entry.into_path();
+
+        let cached = db
+            .query_row(
+                "SELECT track.id, track_sha1.size, track_sha1.modified_at, track_sha1.sha1
+                 FROM track
+                 LEFT JOIN track_sha1 ON track_sha1.track_id = track.id
+                 WHERE track.path = $path",
+                [crate::library::relative(&path)],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, Option<i64>>(1)?,
+                        row.get::<_, Option<i64>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                },
+            )
+            .map(Some)
+            .or_else(|err| match err {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                err => Err(err),
+            })
+            .map_err(io::Error::other)?;
+
+        let size = metadata.len();
+        let modified_at = modified_at(&metadata);
+        let (track_id, sha1) = match cached {
+            Some((track_id, Some(cached_size), Some(cached_modified_at), sha1))
+                if cached_size as u64 == size && cached_modified_at == modified_at =>
+            {
+                (Some(track_id), sha1)
+            }
+            Some((track_id, ..)) => (Some(track_id), None),
+            None => (None, None),
+        };
+
+        files.push(File {
+            path,
+            size,
+            modified_at,
+            track_id,
+            sha1,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Returns the listing of the album as JSON, with its pieces if `piece_length` is given.
+pub fn album_listing(
+    db: &rusqlite::Connection,
+    album_id: i64,
+    piece_length: Option<usize>,
+) -> io::Result<Option<String>> {
+    let to_io = io::Error::other;
+
+    let (artist, album): (Option<String>, Option<String>) = match db.query_row(
+        "SELECT artist.name, album.name
+         FROM album LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE album.id = $id",
+        [album_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ) {
+        Ok(names) => names,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(err) => return Err(to_io(err)),
+    };
+    let folder = match album_folder(db, album_id).map_err(to_io)? {
+        Some(folder) => folder,
+        None => return Ok(None),
+    };
+
+    let mut files = list_files(db, &folder)?;
+    let mut pieces = piece_length.map(Pieces::new);
+    for file in &mut files {
+        if file.sha1.is_some() && pieces.is_none() {
+            continue;
+        }
+        let sha1 = read_file(&file.path, pieces.as_mut())?;
+
+        if let Some(track_id) = file.track_id {
+            db.execute(
+                "INSERT OR REPLACE INTO track_sha1(track_id, size, modified_at, sha1)
+                 VALUES($track_id, $size, $modified_at, $sha1)",
+                rusqlite::params![track_id, file.size as i64, file.modified_at, sha1],
+            )
+            .map_err(to_io)?;
+        }
+        file.sha1 = Some(sha1);
+    }
+
+    let values: Vec<String> = files
+        .iter()
+        .map(|file| {
+            let path = file.path.strip_prefix(&folder).unwrap_or(&file.path);
+            json::object(&[
+                ("path", json::string(&path.to_string_lossy())),
+                ("size", file.size.to_string()),
+                ("sha1", json::opt_string(file.sha1.as_deref())),
+            ])
+        })
+        .collect();
+
+    let mut fields = vec![
+        ("id", album_id.to_string()),
+        ("artist", json::opt_string(artist.as_deref())),
+        ("album", json::opt_string(album.as_deref())),
+        ("folder", json::string(&folder.to_string_lossy())),
+        ("files", json::array(&values)),
+    ];
+    if let (Some(length), Some(pieces)) = (piece_length, pieces) {
+        fields.push(("piece_length", length.to_string()));
+        fields.push(("pieces", json::string(&hash::to_hex(&pieces.finish()))));
+    }
+
+    Ok(Some(json::object(&fields)))
+}
@@ -0,0 +1,205 @@
+//! Tracklists of long mixes, from a cue sheet or a text file next to them.
+//!
+//! A DJ mix or a live set is often a single file. Its tracklist gives the start of each
+//! song in it, which is stored like the chapters of an audiobook so players and M3U
+//! exports can jump straight to a song.
+//!
+//! Text tracklists have one song per line behind its start time, like
+//! `12:34 Artist - Title` or `[1:02:03] Artist - Title`.
+
+use std::fs;
+use std::path::Path;
+
+use crate::spoken::Chapter;
+
+/// Sets the end of each chapter to the start of the next one.
+fn set_ends(chapters: &mut [Chapter]) {
+    for i in 1..chapters.len() {
+        if chapters[i - 1].end_ms.is_none() {
+            chapters[i - 1].end_ms = Some(chapters[i].start_ms);
+        }
+    }
+}
+
+/// Splits a chapter title like "Artist - Title".
+pub fn split_artist(title: &str) -> Option<(String, String)> {
+    let (artist, title) = title
+        .split_once(" - ")
+        .or_else(|| title.split_once(" – "))?;
+    let (artist, title) = (artist.trim(), title.trim());
+    if artist.is_empty() || title.is_empty() {
+        return None;
+    }
+
+    Some((artist.to_owned(), title.to_owned()))
+}
+
+//
+// Cue sheets
+//
+
+/// Parses a `mm:ss:ff` cue sheet time, whose frames are 1/75 of a second.
+fn parse_cue_time(value: &str) -> Option<u64> {
+    let mut parts = value.split(':');
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    let frames: u64 = parts.next()?.parse().ok()?;
+
+    Some(minutes * 60_000 + seconds * 1000 + frames * 1000 / 75)
+}
+
+fn cue_value(value: &str) -> Option<String> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+        .trim();
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+/// Parses a cue sheet describing a single file.
+fn parse_cue(content: &str) -> Vec<Chapter> {
+    let mut chapters = Vec::new();
+    let mut files = 0;
+    let mut in_track = false;
+    let mut title = None;
+    let mut artist = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match command {
+            "FILE" => files += 1,
+            "TRACK" => {
+                in_track = true;
+                title = None;
+                artist = None;
+            }
+            "TITLE" if in_track => title = cue_value(rest),
+            "PERFORMER" if in_track => artist = cue_value(rest),
+            "INDEX" if in_track => {
+                let start = match rest.trim().split_once(' ') {
+                    Some(("01", time)) => parse_cue_time(time.trim()),
+                    _ => None,
+                };
+                if let Some(start_ms) = start {
+                    chapters.push(Chapter {
+                        start_ms,
+                        end_ms: None,
+                        title: title.take(),
+                        artist: artist.take(),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // A cue sheet of an album ripped to one file per track isn't a tracklist
+    if files > 1 {
+        return Vec::new();
+    }
+
+    set_ends(&mut chapters);
+    chapters
+}
+
+//
+// Text tracklists
+//
+
+/// Parses a `mm:ss` or `h:mm:ss` time into milliseconds.
+fn parse_time(value: &str) -> Option<u64> {
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return None;
+    }
+
+    let mut seconds = 0;
+    for part in parts {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+
+    Some(seconds * 1000)
+}
+
+/// Parses a line like "01. [12:34] Artist - Title" into its start time and title.
+fn parse_text_line(line: &str) -> Option<(u64, &str)> {
+    let mut line = line.trim();
+
+    // Numbering in front of the time
+    if let Some((number, rest)) = line.split_once(['.', ')']) {
+        if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) {
+            line = rest.trim_start();
+        }
+    }
+
+    let line = line.strip_prefix(['[', '(']).unwrap_or(line);
+    let end = line
+        .find(|c: char| !(c.is_ascii_digit() || c == ':'))
+        .unwrap_or(line.len());
+    let start = parse_time(&line[..end])?;
+
+    let title = line[end..]
+        .trim_start_matches([']', ')'])
+        .trim_start_matches([' ', '\t', '-', '–', '|'])
+        .trim();
+
+    Some((start, title))
+}
+
+/// Parses a text tracklist, or returns nothing if the file doesn't look like one.
+fn parse_text(content: &str) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = content
+        .lines()
+        .filter_map(parse_text_line)
+        .map(|(start_ms, title)| {
+            let (artist, title) = match split_artist(title) {
+                Some((artist, title)) => (Some(artist), Some(title)),
+                None if title.is_empty() => (None, None),
+                None => (None, Some(title.to_owned())),
+            };
+
+            Chapter {
+                start_ms,
+                end_ms: None,
+                title,
+                artist,
+            }
+        })
+        .collect();
+
+    if chapters.len() < 2 {
+        return Vec::new();
+    }
+
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+    set_ends(&mut chapters);
+    chapters
+}
+
+/// Returns the tracklist next to the track at `path`, from a `.cue` or a `.txt` file with
+/// the same name.
+pub fn for_track(path: &Path) -> Vec<Chapter> {
+    if let Ok(content) = fs::read_to_string(path.with_extension("cue")) {
+        let chapters = parse_cue(&content);
+        if !chapters.is_empty() {
+            return chapters;
+        }
+    }
+
+    match fs::read_to_string(path.with_extension("txt")) {
+        Ok(content) => parse_text(&content),
+        Err(_) => Vec::new(),
+    }
+}
@@ -0,0 +1,144 @@
+//! The files a scan couldn't index, listed by `zik unreadable` until the next complete scan.
+//!
+//! Files and folders zik has no permission to read, often a NAS share exported with the wrong
+//! owner, are told apart from audio files in a format zik can't parse. The tracks of files zik
+//! may not read anymore are kept as they were instead of being marked missing: fixing the
+//! permissions and scanning again brings them back untouched.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::i18n::tr;
+
+#[derive(Clone, Copy)]
+pub enum Reason {
+    PermissionDenied,
+    Unsupported,
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Reason::PermissionDenied => "permission",
+            Reason::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// Starts telling what the scan couldn't read from what the previous one couldn't.
+pub fn begin(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    db.execute("DROP TABLE IF EXISTS temp.scanned_unreadable", [])?;
+    db.execute(
+        "CREATE TEMP TABLE scanned_unreadable(path TEXT PRIMARY KEY)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Forgets what the previous scan couldn't read and this one could, once every file was seen,
+/// only under `scope` when the scan was limited to a folder.
+pub fn forget_readable(db: &rusqlite::Connection, scope: Option<&str>) -> rusqlite::Result<()> {
+    db.execute(
+        "DELETE FROM unreadable_file
+         WHERE path NOT IN (SELECT path FROM temp.scanned_unreadable)
+           AND in_folder(library_path(path), $scope)",
+        [scope],
+    )?;
+    db.execute("DROP TABLE temp.scanned_unreadable", [])?;
+
+    Ok(())
+}
+
+pub fn record(
+    db: &rusqlite::Connection,
+    path: &Path,
+    folder: bool,
+    reason: Reason,
+) -> rusqlite::Result<()> {
+    db.execute(
+        "INSERT OR REPLACE INTO unreadable_file(path, folder, reason)
+         VALUES($path, $folder, $reason)",
+        rusqlite::params![crate::library::relative(path), folder, reason.as_str()],
+    )?;
+    db.execute(
+        "INSERT OR IGNORE INTO temp.scanned_unreadable(path) VALUES($path)",
+        [crate::library::relative(path)],
+    )?;
+
+    Ok(())
+}
+
+/// Keeps the track of the file `path`, or the tracks under the folder `path`, from being
+/// marked missing by the scan.
+pub fn keep_tracks(db: &rusqlite::Connection, path: &Path, folder: bool) -> rusqlite::Result<()> {
+    let query = if folder {
+        "INSERT OR IGNORE INTO temp.scanned_track(id)
+         SELECT id FROM track WHERE substr(path, 1, length($path) + 1) = $path || '/'"
+    } else {
+        "INSERT OR IGNORE INTO temp.scanned_track(id) SELECT id FROM track WHERE path = $path"
+    };
+    db.execute(query, [crate::library::relative(path)])?;
+
+    Ok(())
+}
+
+//
+// "unreadable" command
+//
+
+pub enum CommandUnreadableError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for CommandUnreadableError {
+    fn from(err: rusqlite::Error) -> CommandUnreadableError {
+        CommandUnreadableError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandUnreadableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandUnreadableError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+        }
+    }
+}
+
+pub fn cmd_unreadable(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUnreadableError> {
+    let reason = if args.is_present("unsupported") {
+        Reason::Unsupported
+    } else {
+        Reason::PermissionDenied
+    };
+
+    let mut stmt = db.prepare(
+        "SELECT library_path(path), folder FROM unreadable_file
+         WHERE reason = $reason ORDER BY path",
+    )?;
+    let mut rows = stmt.query([reason.as_str()])?;
+
+    let mut n = 0;
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(0)?;
+        let folder: bool = row.get(1)?;
+
+        // Folders end with a slash, so that it's clear everything under them was skipped
+        if folder {
+            println!("{}/", path);
+        } else {
+            println!("{}", path);
+        }
+        n += 1;
+    }
+
+    if n == 0 {
+        match reason {
+            Reason::PermissionDenied => println!("{}", tr!("unreadable-none")),
+            Reason::Unsupported => println!("{}", tr!("unreadable-none-unsupported")),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//! Albums worth buying again in better quality, `zik upgrades`: those mixing lossless and lossy
+//! files, and those only in MP3 of a low bitrate, the most played first.
+//!
+//! Bitrates aren't read from the files, they're estimated from their size and duration, so
+//! large embedded artwork makes an MP3 look a bit better than it is. M4A files can hold ALAC
+//! as well as AAC and count as neither lossless nor lossy.
+
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::i18n::tr;
+use crate::json;
+
+pub enum CommandUpgradesError {
+    SQLite(rusqlite::Error),
+    InvalidBitrate(String),
+    InvalidLimit(String),
+}
+impl From<rusqlite::Error> for CommandUpgradesError {
+    fn from(err: rusqlite::Error) -> CommandUpgradesError {
+        CommandUpgradesError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandUpgradesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandUpgradesError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandUpgradesError::InvalidBitrate(value) => {
+                f.write_str(&tr!("upgrades-invalid-bitrate", value = value))
+            }
+            CommandUpgradesError::InvalidLimit(value) => {
+                f.write_str(&tr!("invalid-limit", value = value))
+            }
+        }
+    }
+}
+
+const LOSSLESS: [&str; 6] = ["flac", "wav", "aiff", "aif", "ape", "wv"];
+const LOSSY: [&str; 5] = ["mp3", "ogg", "opus", "aac", "wma"];
+
+#[derive(Default)]
+struct Album {
+    id: i64,
+    name: String,
+    tracks: usize,
+    lossless: usize,
+    lossy: usize,
+    formats: BTreeSet<String>,
+    /// The MP3 tracks whose size is known, with their total size in bytes and duration.
+    mp3: usize,
+    mp3_bytes: u64,
+    mp3_ms: i64,
+    plays: i64,
+}
+
+impl Album {
+    /// The average bitrate of its MP3 tracks in kbit/s, if they're all of known size.
+    fn mp3_bitrate(&self) -> Option<i64> {
+        if self.mp3 < self.tracks || self.mp3_ms <= 0 {
+            return None;
+        }
+        Some((self.mp3_bytes * 8 / self.mp3_ms as u64) as i64)
+    }
+
+    /// Why the album is worth upgrading, if it is.
+    fn reason(&self, below: i64) -> Option<String> {
+        if self.lossless > 0 && self.lossy > 0 {
+            let formats: Vec<&str> = self.formats.iter().map(String::as_str).collect();
+            return Some(format!("mixes {}", formats.join(", ")));
+        }
+        match self.mp3_bitrate() {
+            Some(bitrate) if bitrate < below => Some(format!("MP3 at {} kbit/s", bitrate)),
+            _ => None,
+        }
+    }
+}
+
+//
+// "upgrades" command
+//
+
+pub fn cmd_upgrades(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUpgradesError> {
+    let below = args.value_of("below").unwrap();
+    let below: i64 = match below.parse() {
+        Ok(n) if n > 0 => n,
+        _ => return Err(CommandUpgradesError::InvalidBitrate(below.to_owned())),
+    };
+    let limit = match args.value_of("limit") {
+        Some(value) => match value.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => return Err(CommandUpgradesError::InvalidLimit(value.to_owned())),
+        },
+        None => None,
+    };
+    let by_plays = args.value_of("by") != Some("name");
+
+    let mut stmt = db.prepare(
+        "SELECT album.id, album_artist.name, album.name, library_path(track.path),
+                track.duration_ms,
+                (SELECT SUM(play_count) FROM user_track WHERE user_track.track_id = track.id)
+         FROM track
+         JOIN album ON album.id = track.album_id
+         LEFT JOIN artist album_artist ON album_artist.id = album.artist_id
+         WHERE track.missing_since IS NULL AND track.path IS NOT NULL
+         ORDER BY album_artist.sort_name COLLATE natural_sort,
+                  album.sort_name COLLATE natural_sort, album.id",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut albums: Vec<Album> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let album_id: i64 = row.get(0)?;
+        let path: String = row.get(3)?;
+        let duration_ms: Option<i64> = row.get(4)?;
+        let plays: Option<i64> = row.get(5)?;
+
+        if albums.last().map(|album| album.id) != Some(album_id) {
+            let artist: Option<String> = row.get(1)?;
+            let name: Option<String> = row.get(2)?;
+            albums.push(Album {
+                id: album_id,
+                name: format!(
+                    "{} - {}",
+                    artist.unwrap_or_default(),
+                    name.unwrap_or_default()
+                ),
+                ..Album::default()
+            });
+        }
+        let album = albums.last_mut().unwrap();
+
+        let path = Path::new(&path);
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        album.tracks += 1;
+        album.plays += plays.unwrap_or(0);
+        if LOSSLESS.contains(&extension.as_str()) {
+            album.lossless += 1;
+        } else if LOSSY.contains(&extension.as_str()) {
+            album.lossy += 1;
+        }
+        if extension == "mp3" {
+            // Tracks in archives or remote libraries have no size to estimate from
+            if let (Ok(metadata), Some(duration_ms)) = (fs::metadata(path), duration_ms) {
+                album.mp3 += 1;
+                album.mp3_bytes += metadata.len();
+                album.mp3_ms += duration_ms;
+            }
+        }
+        if !extension.is_empty() {
+            album.formats.insert(extension);
+        }
+    }
+
+    let mut candidates: Vec<(Album, String)> = albums
+        .into_iter()
+        .filter_map(|album| album.reason(below).map(|reason| (album, reason)))
+        .collect();
+    if by_plays {
+        candidates.sort_by_key(|(album, _)| Reverse(album.plays));
+    }
+    if let Some(limit) = limit {
+        candidates.truncate(limit);
+    }
+
+    if args.is_present("json") {
+        let values: Vec<String> = candidates
+            .iter()
+            .map(|(album, reason)| {
+                json::object(&[
+                    ("id", album.id.to_string()),
+                    ("name", json::string(&album.name)),
+                    ("plays", album.plays.to_string()),
+                    ("reason", json::string(reason)),
+                    ("bitrate", json::opt_number(album.mp3_bitrate())),
+                ])
+            })
+            .collect();
+        println!("{}", json::array(&values));
+        return Ok(());
+    }
+
+    if candidates.is_empty() {
+        println!("{}", tr!("upgrades-none"));
+    }
+    for (album, reason) in &candidates {
+        println!(
+            "{}  {}: {}",
+            tr!("upgrades-plays", plays = format!("{:>6}", album.plays)),
+            album.name,
+            reason
+        );
+    }
+
+    Ok(())
+}
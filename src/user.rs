@@ -0,0 +1,773 @@
+//! Users of the server, and how requests are authenticated.
+//!
+//! The server stays open as long as no user exists. Once one is added, every request needs
+//! either HTTP basic authentication or the Subsonic `u` parameter with `p` or `t` and `s`.
+//!
+//! Subsonic token authentication sends `md5(password + salt)`, which can only be checked by
+//! knowing the password, so passwords can't be hashed. They're encrypted with
+//! ChaCha20-Poly1305 instead, with a random key kept in a file next to the database that only
+//! its owner can read: a copy or an export of the database alone doesn't reveal them. Losing
+//! the key means setting every password again with `zik user passwd`.
+//!
+//! A user can be restricted to collections, like a kids account only seeing "kids". Their
+//! requests only see the tracks of these collections, with their albums and artists: temporary
+//! views named like the tables shadow them on the connection the request is answered with, so
+//! that every query of the API is restricted without having to think about it.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use subtle::ConstantTimeEq;
+
+use crate::collection;
+use crate::hash;
+use crate::http;
+use crate::i18n::tr;
+use crate::server::Request;
+
+pub struct User {
+    pub id: i64,
+    pub name: String,
+    pub admin: bool,
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    SQLite(rusqlite::Error),
+    IO(PathBuf, io::Error),
+    InvalidKey(PathBuf),
+    /// The password of the user can't be decrypted with the key.
+    WrongKey(String),
+}
+impl From<rusqlite::Error> for KeyError {
+    fn from(err: rusqlite::Error) -> KeyError {
+        KeyError::SQLite(err)
+    }
+}
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            KeyError::IO(path, err) => {
+                f.write_str(&tr!("user-key-io", path = path.display(), err = err))
+            }
+            KeyError::InvalidKey(path) => {
+                f.write_str(&tr!("user-key-invalid", path = path.display()))
+            }
+            KeyError::WrongKey(name) => f.write_str(&tr!("user-key-wrong", name = name)),
+        }
+    }
+}
+
+/// The key of the in-memory database, which doesn't outlive the process either.
+static MEMORY_KEY: OnceLock<Key> = OnceLock::new();
+
+/// Returns the cipher the passwords of `db` are encrypted with, creating its key the first
+/// time.
+fn password_cipher(db: &rusqlite::Connection) -> Result<ChaCha20Poly1305, KeyError> {
+    let file: String = db.query_row(
+        "SELECT file FROM pragma_database_list WHERE name = 'main'",
+        [],
+        |row| row.get(0),
+    )?;
+    if file.is_empty() {
+        let key = MEMORY_KEY.get_or_init(|| ChaCha20Poly1305::generate_key(&mut OsRng));
+        return Ok(ChaCha20Poly1305::new(key));
+    }
+
+    let path = PathBuf::from(format!("{}.key", file));
+    let key = match create_key_file(&path) {
+        Ok(key) => key,
+        // Another zik created it first
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            let mut key = Vec::new();
+            fs::File::open(&path)
+                .and_then(|mut file| file.read_to_end(&mut key))
+                .map_err(|err| KeyError::IO(path.clone(), err))?;
+            if key.len() != 32 {
+                return Err(KeyError::InvalidKey(path));
+            }
+            *Key::from_slice(&key)
+        }
+        Err(err) => return Err(KeyError::IO(path, err)),
+    };
+
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+/// Writes a new random key at `path`, failing if there's already a file.
+fn create_key_file(path: &std::path::Path) -> io::Result<Key> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+
+    let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+    file.write_all(&key)?;
+    file.sync_all()?;
+
+    Ok(key)
+}
+
+/// Returns the password of the user `name` encrypted, as the hex encoded nonce followed by
+/// the ciphertext. The name is authenticated with it, so the password of a user can't be
+/// given to another.
+fn encrypt_password(cipher: &ChaCha20Poly1305, name: &str, password: &str) -> String {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: password.as_bytes(),
+        aad: name.as_bytes(),
+    };
+    // Only fails on messages of gigabytes
+    let ciphertext = cipher.encrypt(&nonce, payload).unwrap();
+
+    hash::to_hex(&[nonce.as_slice(), &ciphertext].concat())
+}
+
+fn decrypt_password(cipher: &ChaCha20Poly1305, name: &str, value: &str) -> Option<String> {
+    let bytes = decode_hex(value)?;
+    if bytes.len() < 12 {
+        return None;
+    }
+    let (nonce, ciphertext) = bytes.split_at(12);
+    let payload = Payload {
+        msg: ciphertext,
+        aad: name.as_bytes(),
+    };
+    let password = cipher.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+
+    String::from_utf8(password).ok()
+}
+
+/// Encrypts the passwords stored before they were encrypted.
+pub fn encrypt_passwords(db: &rusqlite::Connection) -> Result<(), KeyError> {
+    let mut stmt = db.prepare("SELECT id, name, password FROM user WHERE NOT encrypted")?;
+    let users: Vec<(i64, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let cipher = password_cipher(db)?;
+    for (id, name, password) in users {
+        db.execute(
+            "UPDATE user SET password = $password, encrypted = 1
+             WHERE id = $id AND NOT encrypted",
+            rusqlite::params![encrypt_password(&cipher, &name, &password), id],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub enum AuthError {
+    SQLite(rusqlite::Error),
+    Key(KeyError),
+    MissingCredentials,
+    WrongCredentials,
+}
+impl From<rusqlite::Error> for AuthError {
+    fn from(err: rusqlite::Error) -> AuthError {
+        AuthError::SQLite(err)
+    }
+}
+impl From<KeyError> for AuthError {
+    fn from(err: KeyError) -> AuthError {
+        AuthError::Key(err)
+    }
+}
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            AuthError::Key(err) => write!(f, "{}", err),
+            AuthError::MissingCredentials => write!(f, "authentication required"),
+            AuthError::WrongCredentials => write!(f, "wrong username or password"),
+        }
+    }
+}
+
+enum Credentials {
+    Password(String),
+    /// A Subsonic token, `md5(password + salt)`.
+    Token(String, String),
+}
+
+fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    let data = value
+        .strip_suffix("==")
+        .or_else(|| value.strip_suffix('='))
+        .unwrap_or(value);
+    // Padding only ever completes the last group of 4, and a single character left is no byte
+    let padded = data.len() != value.len();
+    if (padded && !value.len().is_multiple_of(4)) || data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut buf = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut n_bits = 0;
+
+    for c in data.bytes() {
+        let digit = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+
+        bits = (bits << 6) | digit as u32;
+        n_bits += 6;
+        if n_bits >= 8 {
+            n_bits -= 8;
+            buf.push((bits >> n_bits) as u8);
+        }
+    }
+
+    Some(buf)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Subsonic clients can send the password hex encoded, as `enc:<hex>`.
+fn decode_password(value: &str) -> Option<String> {
+    match value.strip_prefix("enc:") {
+        Some(hex) => String::from_utf8(decode_hex(hex)?).ok(),
+        None => Some(value.to_owned()),
+    }
+}
+
+fn credentials(request: &Request) -> Result<(String, Credentials), AuthError> {
+    if let Some(value) = request.header("Authorization") {
+        let decoded = value
+            .strip_prefix("Basic ")
+            .and_then(|value| decode_base64(value.trim()))
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+
+        return match decoded.as_deref().and_then(|value| value.split_once(':')) {
+            Some((name, password)) => {
+                Ok((name.to_owned(), Credentials::Password(password.to_owned())))
+            }
+            None => Err(AuthError::WrongCredentials),
+        };
+    }
+
+    let name = match request.param("u") {
+        Some(name) => name.to_owned(),
+        None => return Err(AuthError::MissingCredentials),
+    };
+
+    match (request.param("p"), request.param("t"), request.param("s")) {
+        (Some(password), _, _) => match decode_password(password) {
+            Some(password) => Ok((name, Credentials::Password(password))),
+            None => Err(AuthError::WrongCredentials),
+        },
+        (None, Some(token), Some(salt)) => Ok((
+            name,
+            Credentials::Token(token.to_lowercase(), salt.to_owned()),
+        )),
+        _ => Err(AuthError::MissingCredentials),
+    }
+}
+
+/// Shadows the tables of the library on `db` with views of what the user `user_id` sees, the
+/// tracks of their collections. Views in the main schema, like `v_tracks`, still see
+/// everything.
+fn restrict(db: &rusqlite::Connection, user_id: i64) -> rusqlite::Result<()> {
+    db.execute_batch(&format!(
+        "CREATE TEMP VIEW collection AS
+         SELECT * FROM main.collection
+         WHERE id IN (SELECT collection_id FROM main.user_collection WHERE user_id = {user_id});
+         CREATE TEMP VIEW track AS
+         SELECT * FROM main.track
+         WHERE id IN (
+           SELECT track_id FROM main.v_collection_tracks
+           WHERE collection_id IN (SELECT id FROM temp.collection)
+         );
+         CREATE TEMP VIEW album AS
+         SELECT * FROM main.album WHERE id IN (SELECT album_id FROM temp.track);
+         CREATE TEMP VIEW artist AS
+         SELECT * FROM main.artist
+         WHERE id IN (
+           SELECT artist_id FROM temp.track
+           UNION
+           SELECT artist_id FROM temp.album
+           UNION
+           SELECT artist_id FROM main.track_artist WHERE track_id IN (SELECT id FROM temp.track)
+         );
+         CREATE TEMP VIEW artist_image AS
+         SELECT * FROM main.artist_image WHERE artist_id IN (SELECT id FROM temp.artist);",
+        user_id = user_id
+    ))
+}
+
+/// Returns whether the server has users, and so requests need to be authenticated.
+pub fn has_users(db: &rusqlite::Connection) -> rusqlite::Result<bool> {
+    db.query_row("SELECT EXISTS(SELECT 1 FROM user)", [], |row| row.get(0))
+}
+
+/// Returns the `u`, `t` and `s` query parameters authenticating as `name` with a new token,
+/// for URLs given to devices like casting targets. None if there's no such user.
+pub fn token_params(
+    db: &rusqlite::Connection,
+    name: &str,
+) -> Result<Option<Vec<String>>, KeyError> {
+    let result = db.query_row(
+        "SELECT password, encrypted FROM user WHERE name = $name",
+        [name],
+        |row| Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?)),
+    );
+    let (password, encrypted) = match result {
+        Ok(result) => result,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    let password = if encrypted {
+        let cipher = password_cipher(db)?;
+        decrypt_password(&cipher, name, &password)
+            .ok_or_else(|| KeyError::WrongKey(name.to_owned()))?
+    } else {
+        password
+    };
+
+    // A nonce is 12 random bytes, as good a salt as any
+    let salt = hash::to_hex(&ChaCha20Poly1305::generate_nonce(&mut OsRng));
+    let token = hash::to_hex(&hash::md5(format!("{}{}", password, salt).as_bytes()));
+
+    Ok(Some(vec![
+        format!("u={}", http::percent_encode(name)),
+        format!("t={}", token),
+        format!("s={}", salt),
+    ]))
+}
+
+/// Returns the user making `request`, or None if the server has no users. The library `db`
+/// sees is restricted to what the user can see from then on.
+pub fn authenticate(
+    db: &rusqlite::Connection,
+    request: &Request,
+) -> Result<Option<User>, AuthError> {
+    if !has_users(db)? {
+        return Ok(None);
+    }
+
+    let (name, credentials) = credentials(request)?;
+
+    let result = db.query_row(
+        "SELECT id, name, password, encrypted, admin, restricted FROM user WHERE name = $name",
+        [&name],
+        |row| {
+            let user = User {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                admin: row.get(4)?,
+            };
+            let password: String = row.get(2)?;
+            let encrypted: bool = row.get(3)?;
+            let restricted: bool = row.get(5)?;
+            Ok((user, password, encrypted, restricted))
+        },
+    );
+    let (user, password, encrypted, restricted) = match result {
+        Ok(result) => result,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(AuthError::WrongCredentials),
+        Err(err) => return Err(err.into()),
+    };
+
+    let password = if encrypted {
+        let cipher = password_cipher(db)?;
+        decrypt_password(&cipher, &user.name, &password)
+            .ok_or_else(|| KeyError::WrongKey(user.name.clone()))?
+    } else {
+        password
+    };
+
+    // Compared in constant time, so that how long it takes tells nothing about the password
+    let valid = match credentials {
+        Credentials::Password(given) => given.as_bytes().ct_eq(password.as_bytes()),
+        Credentials::Token(token, salt) => {
+            let expected = hash::to_hex(&hash::md5(format!("{}{}", password, salt).as_bytes()));
+            token.as_bytes().ct_eq(expected.as_bytes())
+        }
+    };
+    if !bool::from(valid) {
+        return Err(AuthError::WrongCredentials);
+    }
+
+    if restricted {
+        restrict(db, user.id)?;
+    }
+
+    Ok(Some(user))
+}
+
+//
+// "user" command
+//
+
+pub enum CommandUserError {
+    SQLite(rusqlite::Error),
+    IO(io::Error),
+    Key(KeyError),
+    AlreadyExists(String),
+    NotFound(String),
+    EmptyPassword,
+    CollectionNotFound(String),
+}
+impl From<rusqlite::Error> for CommandUserError {
+    fn from(err: rusqlite::Error) -> CommandUserError {
+        CommandUserError::SQLite(err)
+    }
+}
+impl From<io::Error> for CommandUserError {
+    fn from(err: io::Error) -> CommandUserError {
+        CommandUserError::IO(err)
+    }
+}
+impl From<KeyError> for CommandUserError {
+    fn from(err: KeyError) -> CommandUserError {
+        CommandUserError::Key(err)
+    }
+}
+impl fmt::Display for CommandUserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandUserError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandUserError::IO(err) => f.write_str(&tr!("user-read-password", err = err)),
+            CommandUserError::Key(err) => write!(f, "{}", err),
+            CommandUserError::AlreadyExists(name) => {
+                f.write_str(&tr!("user-already-exists", name = name))
+            }
+            CommandUserError::NotFound(name) => f.write_str(&tr!("user-not-found", name = name)),
+            CommandUserError::EmptyPassword => f.write_str(&tr!("user-empty-password")),
+            CommandUserError::CollectionNotFound(name) => {
+                f.write_str(&tr!("collection-not-found", name = name))
+            }
+        }
+    }
+}
+
+/// Reads a password from the terminal without echoing it, or from the standard input when
+/// it's piped.
+fn read_password() -> Result<String, CommandUserError> {
+    let password = if io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("{} ", tr!("user-password-prompt")))?
+    } else {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        line.trim_end_matches(['\r', '\n']).to_owned()
+    };
+    if password.is_empty() {
+        return Err(CommandUserError::EmptyPassword);
+    }
+
+    Ok(password)
+}
+
+/// Returns the IDs of the collections `args` names.
+fn find_collections(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<Vec<i64>, CommandUserError> {
+    let mut ids = Vec::new();
+    for name in args.values_of("collection").unwrap_or_default() {
+        match collection::find_id(db, name)? {
+            Some(id) => ids.push(id),
+            None => return Err(CommandUserError::CollectionNotFound(name.to_owned())),
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Restricts the user `user_id` to the collections `collection_ids`, or lets them see the
+/// whole library if there are none.
+fn set_collections(
+    db: &rusqlite::Connection,
+    user_id: i64,
+    collection_ids: &[i64],
+) -> rusqlite::Result<()> {
+    db.execute(
+        "DELETE FROM user_collection WHERE user_id = $user_id",
+        [user_id],
+    )?;
+    for collection_id in collection_ids {
+        db.execute(
+            "INSERT OR IGNORE INTO user_collection(user_id, collection_id)
+             VALUES($user_id, $collection_id)",
+            [user_id, *collection_id],
+        )?;
+    }
+    db.execute(
+        "UPDATE user SET restricted = $restricted WHERE id = $id",
+        rusqlite::params![!collection_ids.is_empty(), user_id],
+    )?;
+
+    Ok(())
+}
+
+fn find_user_id(db: &rusqlite::Connection, name: &str) -> Result<i64, CommandUserError> {
+    match db.query_row("SELECT id FROM user WHERE name = $name", [name], |row| {
+        row.get(0)
+    }) {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            Err(CommandUserError::NotFound(name.to_owned()))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn cmd_user_add(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUserError> {
+    let name = args.value_of("name").unwrap();
+
+    let exists: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM user WHERE name = $name)",
+        [name],
+        |row| row.get(0),
+    )?;
+    if exists {
+        return Err(CommandUserError::AlreadyExists(name.to_owned()));
+    }
+
+    let collection_ids = find_collections(db, args)?;
+
+    let password = read_password()?;
+    let password = encrypt_password(&password_cipher(db)?, name, &password);
+
+    let savepoint = db.savepoint()?;
+
+    savepoint.execute(
+        "INSERT INTO user(name, password, encrypted, admin)
+         VALUES($name, $password, 1, $admin)",
+        rusqlite::params![name, password, args.is_present("admin")],
+    )?;
+    set_collections(&savepoint, savepoint.last_insert_rowid(), &collection_ids)?;
+
+    savepoint.commit()?;
+
+    println!("{}", tr!("user-added", name = name));
+
+    Ok(())
+}
+
+fn cmd_user_remove(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUserError> {
+    let name = args.value_of("name").unwrap();
+
+    let savepoint = db.savepoint()?;
+
+    let id: i64 =
+        match savepoint.query_row("SELECT id FROM user WHERE name = $name", [name], |row| {
+            row.get(0)
+        }) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                return Err(CommandUserError::NotFound(name.to_owned()))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+    // Their playlists, ratings and plays go with them
+    savepoint.execute("DELETE FROM user WHERE id = $id", [id])?;
+
+    savepoint.commit()?;
+
+    println!("{}", tr!("user-removed", name = name));
+
+    Ok(())
+}
+
+fn cmd_user_passwd(
+    db: &rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUserError> {
+    let name = args.value_of("name").unwrap();
+
+    let exists: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM user WHERE name = $name)",
+        [name],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(CommandUserError::NotFound(name.to_owned()));
+    }
+
+    let password = read_password()?;
+    let password = encrypt_password(&password_cipher(db)?, name, &password);
+
+    db.execute(
+        "UPDATE user SET password = $password, encrypted = 1 WHERE name = $name",
+        [&password, name],
+    )?;
+
+    Ok(())
+}
+
+fn cmd_user_restrict(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUserError> {
+    let name = args.value_of("name").unwrap();
+    let user_id = find_user_id(db, name)?;
+    let collection_ids = find_collections(db, args)?;
+
+    let savepoint = db.savepoint()?;
+    set_collections(&savepoint, user_id, &collection_ids)?;
+    savepoint.commit()?;
+
+    Ok(())
+}
+
+fn cmd_user_unrestrict(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUserError> {
+    let name = args.value_of("name").unwrap();
+    let user_id = find_user_id(db, name)?;
+
+    let savepoint = db.savepoint()?;
+    set_collections(&savepoint, user_id, &[])?;
+    savepoint.commit()?;
+
+    Ok(())
+}
+
+fn cmd_user_list(db: &rusqlite::Connection) -> Result<(), CommandUserError> {
+    let mut stmt = db.prepare(
+        "SELECT user.name, user.admin, user.restricted,
+                (SELECT group_concat(name, ', ') FROM (
+                   SELECT collection.name FROM user_collection
+                   JOIN collection ON collection.id = user_collection.collection_id
+                   WHERE user_collection.user_id = user.id
+                   ORDER BY collection.name COLLATE natural_sort
+                 ))
+         FROM user
+         ORDER BY user.name",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let admin: bool = row.get(1)?;
+        let restricted: bool = row.get(2)?;
+        let collections: Option<String> = row.get(3)?;
+
+        let mut notes = Vec::new();
+        if admin {
+            notes.push(tr!("user-admin"));
+        }
+        if restricted {
+            // Without collections left, they see nothing
+            notes.push(match collections {
+                Some(collections) => tr!("user-only", collections = collections),
+                None => tr!("user-only-nothing"),
+            });
+        }
+
+        if notes.is_empty() {
+            println!("{}", name);
+        } else {
+            println!("{} ({})", name, notes.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn cmd_user(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandUserError> {
+    match args.subcommand() {
+        Some(("add", sub_args)) => cmd_user_add(db, sub_args),
+        Some(("remove", sub_args)) => cmd_user_remove(db, sub_args),
+        Some(("passwd", sub_args)) => cmd_user_passwd(db, sub_args),
+        Some(("restrict", sub_args)) => cmd_user_restrict(db, sub_args),
+        Some(("unrestrict", sub_args)) => cmd_user_unrestrict(db, sub_args),
+        Some(("list", _)) => cmd_user_list(db),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_base64_padded_and_unpadded() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("aA==").unwrap(), b"h");
+        assert_eq!(decode_base64("aA").unwrap(), b"h");
+        assert_eq!(decode_base64("aGk=").unwrap(), b"hi");
+        assert_eq!(decode_base64("aGk").unwrap(), b"hi");
+        assert_eq!(decode_base64("aGk/").unwrap(), [0x68, 0x69, 0x3f]);
+        assert_eq!(decode_base64("YWxpY2U6czNjcmV0").unwrap(), b"alice:s3cret");
+    }
+
+    #[test]
+    fn decode_base64_malformed() {
+        for value in [
+            "a", "aGk*", "aG=k", "=aGk", "aGk===", "aGk==", "aA=", "aGk=aGk=", "aGk\n", "aGké",
+        ] {
+            assert_eq!(decode_base64(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn decode_password_hex() {
+        assert_eq!(decode_password("s3cret").unwrap(), "s3cret");
+        assert_eq!(decode_password("enc:733363726574").unwrap(), "s3cret");
+        assert_eq!(decode_password("enc:").unwrap(), "");
+        assert_eq!(decode_password("enc:73336"), None);
+        assert_eq!(decode_password("enc:7g"), None);
+        assert_eq!(decode_password("enc:ff"), None);
+        assert_eq!(decode_password("enc:é1"), None);
+    }
+
+    #[test]
+    fn encrypted_password_round_trip() {
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&[7; 32]));
+
+        let encrypted = encrypt_password(&cipher, "alice", "s3cret");
+        assert!(!encrypted.contains(&hash::to_hex(b"s3cret")));
+        assert_eq!(
+            decrypt_password(&cipher, "alice", &encrypted).unwrap(),
+            "s3cret"
+        );
+
+        // Bound to the user, the key and the ciphertext
+        assert_eq!(decrypt_password(&cipher, "bob", &encrypted), None);
+        let other = ChaCha20Poly1305::new(Key::from_slice(&[8; 32]));
+        assert_eq!(decrypt_password(&other, "alice", &encrypted), None);
+        let mut tampered = encrypted.into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'0' { b'1' } else { b'0' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert_eq!(decrypt_password(&cipher, "alice", &tampered), None);
+        assert_eq!(decrypt_password(&cipher, "alice", "00"), None);
+    }
+}
@@ -0,0 +1,258 @@
+//! Checks of the audio itself, which reading tags never notices: `zik verify --decode` decodes
+//! every track with ffmpeg, failing on decoder errors, and compares the samples of FLAC files
+//! with the MD5 of their STREAMINFO, catching truncated or corrupt rips.
+//!
+//! The result is stored on the track, and only tracks never verified or modified since are
+//! decoded again unless `--all` is given. Without `--decode`, the failures found are listed.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use crate::i18n::tr;
+use crate::{archive, ffmpeg, hash, jobs, storage};
+
+pub enum CommandVerifyError {
+    SQLite(rusqlite::Error),
+    Ffmpeg(ffmpeg::FfmpegError),
+    InvalidWorkers(String),
+}
+impl From<rusqlite::Error> for CommandVerifyError {
+    fn from(err: rusqlite::Error) -> CommandVerifyError {
+        CommandVerifyError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandVerifyError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandVerifyError::Ffmpeg(err) => write!(f, "{}", err),
+            CommandVerifyError::InvalidWorkers(value) => {
+                f.write_str(&tr!("invalid-workers", value = value))
+            }
+        }
+    }
+}
+
+/// The bits per sample and MD5 of the samples from the STREAMINFO of a FLAC file, no MD5 if
+/// the encoder didn't compute one.
+fn flac_stream_info(path: &Path) -> io::Result<(usize, Option<[u8; 16]>)> {
+    let mut header = [0; 4 + 4 + 34];
+    fs::File::open(path)?.read_exact(&mut header)?;
+    // STREAMINFO is always the first metadata block
+    if &header[..4] != b"fLaC" || header[4] & 0x7f != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            tr!("verify-no-streaminfo"),
+        ));
+    }
+
+    let info = &header[8..];
+    let bits = (((info[12] & 0x01) << 4) | (info[13] >> 4)) as usize + 1;
+    let mut md5 = [0; 16];
+    md5.copy_from_slice(&info[18..34]);
+
+    Ok((bits, Some(md5).filter(|md5| md5.iter().any(|b| *b != 0))))
+}
+
+/// Decodes a track, returning what's wrong with it.
+fn verify(path: &Path) -> Result<Option<String>, ffmpeg::FfmpegError> {
+    let is_flac = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("flac"));
+
+    let (bits, expected) = if is_flac {
+        match flac_stream_info(path) {
+            Ok((bits, md5)) => (Some(bits).filter(|_| md5.is_some()), md5),
+            Err(err) => return Ok(Some(tr!("verify-streaminfo-error", err = err))),
+        }
+    } else {
+        (None, None)
+    };
+
+    match ffmpeg::decode_check(path, bits) {
+        Ok(Some(md5)) if expected.is_some_and(|expected| hash::to_hex(&expected) != md5) => {
+            Ok(Some(tr!("verify-md5-mismatch")))
+        }
+        Ok(_) => Ok(None),
+        Err(ffmpeg::FfmpegError::Failed(err)) => Ok(Some(err)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the tracks to decode: all of them with `all`, otherwise those never verified or
+/// modified since.
+fn tracks_to_verify(
+    db: &rusqlite::Connection,
+    all: bool,
+) -> rusqlite::Result<VecDeque<(i64, PathBuf)>> {
+    let mut stmt = db.prepare(
+        "SELECT id, library_path(path), verified_at FROM track
+         WHERE path IS NOT NULL AND missing_since IS NULL
+         ORDER BY path",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut tracks = VecDeque::new();
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(1)?;
+        let verified_at: Option<i64> = row.get(2)?;
+        // ffmpeg can't read inside archives, and remote files would be downloaded whole
+        if storage::is_remote(&path) || archive::split(Path::new(&path)).is_some() {
+            continue;
+        }
+
+        let modified_at = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs() as i64);
+        let up_to_date = match (verified_at, modified_at) {
+            (Some(verified_at), Some(modified_at)) => verified_at >= modified_at,
+            _ => false,
+        };
+        if all || !up_to_date {
+            tracks.push_back((row.get(0)?, PathBuf::from(path)));
+        }
+    }
+
+    Ok(tracks)
+}
+
+//
+// "verify" command
+//
+
+fn cmd_verify_decode(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandVerifyError> {
+    let workers = match args.value_of("workers") {
+        Some(value) => match value.parse() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(CommandVerifyError::InvalidWorkers(value.to_owned())),
+        },
+        None => jobs::default_workers(db)?,
+    };
+
+    let tracks = tracks_to_verify(db, args.is_present("all"))?;
+    if tracks.is_empty() {
+        println!("{}", tr!("verify-up-to-date"));
+        return Ok(());
+    }
+    let workers = workers.min(tracks.len());
+    println!(
+        "{}",
+        tr!("verify-start", tracks = tracks.len(), workers = workers)
+    );
+
+    let queue = Mutex::new(tracks);
+    let (results_tx, results_rx) = mpsc::channel();
+
+    thread::scope(|scope| -> Result<(), CommandVerifyError> {
+        for _ in 0..workers {
+            let queue = &queue;
+            let results_tx = results_tx.clone();
+
+            scope.spawn(move || loop {
+                let (id, path) = match queue.lock().unwrap().pop_front() {
+                    Some(track) => track,
+                    None => break,
+                };
+
+                let result = verify(&path);
+                // Without ffmpeg nothing else can be verified
+                if result.is_err() {
+                    queue.lock().unwrap().clear();
+                }
+                if results_tx.send((id, path, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(results_tx);
+
+        let (mut verified, mut failed) = (0, 0);
+        let mut fatal = None;
+        for (id, path, result) in results_rx {
+            let error = match result {
+                Ok(error) => error,
+                Err(err) => {
+                    fatal.get_or_insert(err);
+                    continue;
+                }
+            };
+
+            db.execute(
+                "UPDATE track SET verified_at = unixepoch(), verify_error = $error WHERE id = $id",
+                rusqlite::params![error, id],
+            )?;
+            verified += 1;
+            if let Some(error) = error {
+                failed += 1;
+                println!("{}: {}", path.display(), error);
+            }
+        }
+
+        if let Some(err) = fatal {
+            return Err(CommandVerifyError::Ffmpeg(err));
+        }
+        println!(
+            "{}",
+            tr!("verify-done", verified = verified, failed = failed)
+        );
+
+        Ok(())
+    })
+}
+
+fn cmd_verify_report(db: &rusqlite::Connection) -> Result<(), CommandVerifyError> {
+    let mut stmt = db.prepare(
+        "SELECT library_path(path), verify_error FROM track
+         WHERE verify_error IS NOT NULL AND missing_since IS NULL
+         ORDER BY path",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut failed = 0;
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(0)?;
+        let error: String = row.get(1)?;
+        println!("{}: {}", path, error);
+        failed += 1;
+    }
+
+    let verified: i64 = db.query_row(
+        "SELECT COUNT(*) FROM track WHERE verified_at IS NOT NULL AND missing_since IS NULL",
+        [],
+        |row| row.get(0),
+    )?;
+    let pending = tracks_to_verify(db, false)?.len();
+    println!(
+        "{}",
+        tr!(
+            "verify-report",
+            verified = verified,
+            failed = failed,
+            pending = pending
+        )
+    );
+
+    Ok(())
+}
+
+pub fn cmd_verify(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandVerifyError> {
+    if args.is_present("decode") {
+        cmd_verify_decode(db, args)
+    } else {
+        cmd_verify_report(db)
+    }
+}
@@ -0,0 +1,78 @@
+//! The Vorbis comments of FLAC files, the ones of Ogg files are read with the same keys.
+//!
+//! Few keys are standard and taggers don't agree on the others: track totals are written as
+//! TRACKTOTAL by some and TOTALTRACKS by others, the year as DATE or YEAR. Each field is read
+//! from the first of its keys present in the file, the most common one first.
+
+use crate::tags::{CustomKey, Field, TagReader};
+
+/// Returns the keys a field can be written as.
+pub fn keys(field: Field) -> &'static [&'static str] {
+    match field {
+        Field::Title => &["TITLE"],
+        Field::Artist => &["ARTIST"],
+        Field::Album => &["ALBUM"],
+        Field::AlbumArtist => &["ALBUMARTIST", "ALBUM ARTIST", "ALBUM_ARTIST"],
+        Field::Date => &["DATE", "YEAR"],
+        Field::OriginalDate => &["ORIGINALDATE", "ORIGINALYEAR", "ORIGINAL_YEAR"],
+        Field::TrackNumber => &["TRACKNUMBER", "TRACK_NUMBER", "TRACK"],
+        Field::TrackTotal => &["TRACKTOTAL", "TOTALTRACKS", "TRACK_TOTAL", "TOTAL_TRACKS"],
+        Field::DiscNumber => &["DISCNUMBER", "DISC_NUMBER", "DISC"],
+        Field::DiscTotal => &["DISCTOTAL", "TOTALDISCS", "DISC_TOTAL", "TOTAL_DISCS"],
+        Field::Genre => &["GENRE"],
+        Field::Comment => &["COMMENT", "DESCRIPTION"],
+        Field::Lyrics => &["LYRICS", "UNSYNCEDLYRICS"],
+        Field::ReleaseType => &["RELEASETYPE", "MUSICBRAINZ_ALBUMTYPE"],
+        Field::Compilation => &["COMPILATION"],
+        Field::FmpsRating => &["FMPS_RATING"],
+        Field::Rating => &["RATING"],
+        Field::FmpsPlayCount => &["FMPS_PLAYCOUNT"],
+        Field::MusicBrainzAlbumId => &["MUSICBRAINZ_ALBUMID"],
+        Field::MusicBrainzArtistId => &["MUSICBRAINZ_ARTISTID"],
+        Field::Label => &["LABEL", "ORGANIZATION", "PUBLISHER"],
+        Field::ReplayGainTrack => &["REPLAYGAIN_TRACK_GAIN"],
+        Field::ReplayGainAlbum => &["REPLAYGAIN_ALBUM_GAIN"],
+        Field::Mood => &["MOOD"],
+        Field::Energy => &["ENERGY", "ENERGYLEVEL"],
+    }
+}
+
+impl TagReader for metaflac::Tag {
+    /// Returns every value of the first of the field's keys with a value.
+    fn get_all(&self, field: Field) -> Vec<String> {
+        for key in keys(field) {
+            let values: Vec<String> = match self.get_vorbis(key) {
+                Some(iter) => iter
+                    .map(str::trim)
+                    .filter(|value| !value.is_empty())
+                    .map(|value| value.to_owned())
+                    .collect(),
+                None => continue,
+            };
+            if !values.is_empty() {
+                return values;
+            }
+        }
+
+        Vec::new()
+    }
+
+    fn get_custom(&self, key: &CustomKey) -> Vec<String> {
+        let key = match key {
+            CustomKey::Any(key) | CustomKey::Vorbis(key) => key.to_uppercase(),
+            _ => return Vec::new(),
+        };
+        match self.get_vorbis(&key) {
+            Some(iter) => iter.map(|value| value.to_owned()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn duration_ms(&self) -> Option<i64> {
+        let info = self.get_streaminfo()?;
+        if info.sample_rate == 0 {
+            return None;
+        }
+        Some((info.total_samples * 1000 / info.sample_rate as u64) as i64)
+    }
+}
@@ -0,0 +1,393 @@
+//! The year in review: what was played the most in a year, and what was played for the
+//! first time.
+
+use std::fmt::{self, Write};
+
+use crate::i18n::tr;
+use crate::json;
+use crate::subsonic::xml_escape;
+use crate::top::format_duration;
+
+pub enum CommandWrappedError {
+    SQLite(rusqlite::Error),
+    InvalidYear(String),
+    UserNotFound(String),
+}
+impl From<rusqlite::Error> for CommandWrappedError {
+    fn from(err: rusqlite::Error) -> CommandWrappedError {
+        CommandWrappedError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandWrappedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandWrappedError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+            CommandWrappedError::InvalidYear(value) => {
+                f.write_str(&tr!("wrapped-invalid-year", value = value))
+            }
+            CommandWrappedError::UserNotFound(name) => {
+                f.write_str(&tr!("user-not-found", name = name))
+            }
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    artist: Option<String>,
+    plays: i64,
+}
+
+struct Wrapped {
+    year: i64,
+    plays: i64,
+    duration_ms: i64,
+    tracks: i64,
+    artists: i64,
+    top_artists: Vec<Entry>,
+    top_albums: Vec<Entry>,
+    top_tracks: Vec<Entry>,
+    /// Artists played for the first time this year.
+    discoveries: Vec<Entry>,
+    genres: Vec<Entry>,
+}
+
+/// The plays of the year, of everyone or of `$user`.
+const PLAYS_OF_YEAR: &str = "
+    FROM play
+    JOIN track ON track.id = play.track_id
+    LEFT JOIN artist ON artist.id = track.artist_id
+    LEFT JOIN album ON album.id = track.album_id
+    LEFT JOIN artist album_artist ON album_artist.id = album.artist_id
+    WHERE play.played_at >= $start AND play.played_at < $end
+      AND ($user IS NULL OR play.user_id = (SELECT id FROM user WHERE name = $user))";
+
+fn load_entries(
+    db: &rusqlite::Connection,
+    select: &str,
+    rest: &str,
+    params: (i64, i64, Option<&str>),
+) -> rusqlite::Result<Vec<Entry>> {
+    let query = format!(
+        "SELECT {}, COUNT(*) AS plays {} {}",
+        select, PLAYS_OF_YEAR, rest
+    );
+
+    let mut stmt = db.prepare(&query)?;
+    let rows = stmt.query_map(rusqlite::params![params.0, params.1, params.2], |row| {
+        let name: Option<String> = row.get(0)?;
+        Ok(Entry {
+            name: name.unwrap_or_default(),
+            artist: row.get(1)?,
+            plays: row.get(2)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
+fn load(db: &rusqlite::Connection, year: i64, user: Option<&str>) -> rusqlite::Result<Wrapped> {
+    // Years start at midnight local time
+    let (start, end): (i64, i64) = db.query_row(
+        "SELECT unixepoch(printf('%04d-01-01', $year), 'utc'),
+                unixepoch(printf('%04d-01-01', $year + 1), 'utc')",
+        [year],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let params = (start, end, user);
+
+    let (plays, duration_ms, tracks, artists) = db.query_row(
+        &format!(
+            "SELECT COUNT(*), coalesce(SUM(track.duration_ms), 0),
+                    COUNT(DISTINCT track.id), COUNT(DISTINCT track.artist_id) {}",
+            PLAYS_OF_YEAR
+        ),
+        rusqlite::params![start, end, user],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+
+    let top = "ORDER BY plays DESC, 1 COLLATE natural_sort LIMIT 5";
+
+    Ok(Wrapped {
+        year,
+        plays,
+        duration_ms,
+        tracks,
+        artists,
+        top_artists: load_entries(
+            db,
+            "artist.name, NULL",
+            &format!("AND artist.id IS NOT NULL GROUP BY artist.id {}", top),
+            params,
+        )?,
+        top_albums: load_entries(
+            db,
+            "album.name, album_artist.name",
+            &format!("AND album.id IS NOT NULL GROUP BY album.id {}", top),
+            params,
+        )?,
+        top_tracks: load_entries(
+            db,
+            "track.name, artist.name",
+            &format!("GROUP BY track.id {}", top),
+            params,
+        )?,
+        // Matched by name, an artist left without tracks is deleted and gets a new id if it comes back
+        discoveries: load_entries(
+            db,
+            "artist.name, NULL",
+            &format!(
+                "AND artist.id IS NOT NULL
+                 GROUP BY artist.id
+                 HAVING NOT EXISTS(
+                   SELECT 1
+                   FROM play earlier
+                   JOIN track earlier_track ON earlier_track.id = earlier.track_id
+                   JOIN artist earlier_artist ON earlier_artist.id = earlier_track.artist_id
+                   WHERE earlier_artist.name = artist.name
+                     AND earlier.played_at < $start
+                     AND ($user IS NULL OR earlier.user_id = play.user_id)
+                 )
+                 {}",
+                top
+            ),
+            params,
+        )?,
+        genres: load_entries(
+            db,
+            "coalesce(track.genre, 'Unknown'), NULL",
+            "GROUP BY 1 ORDER BY plays DESC, 1 COLLATE natural_sort LIMIT 10",
+            params,
+        )?,
+    })
+}
+
+fn entry_title(entry: &Entry) -> String {
+    match &entry.artist {
+        Some(artist) => format!("{} - {}", artist, entry.name),
+        None => entry.name.clone(),
+    }
+}
+
+fn percent(plays: i64, total: i64) -> i64 {
+    if total == 0 {
+        0
+    } else {
+        (plays * 100 + total / 2) / total
+    }
+}
+
+/// The sections of the report, with the message of their title.
+fn sections(wrapped: &Wrapped) -> [(&'static str, &'static str, &[Entry]); 4] {
+    [
+        ("top_artists", "wrapped-top-artists", &wrapped.top_artists),
+        ("top_albums", "wrapped-top-albums", &wrapped.top_albums),
+        ("top_tracks", "wrapped-top-tracks", &wrapped.top_tracks),
+        ("discoveries", "wrapped-discoveries", &wrapped.discoveries),
+    ]
+}
+
+fn to_text(wrapped: &Wrapped) -> String {
+    let mut buf = String::new();
+
+    let _ = writeln!(buf, "{}\n", tr!("wrapped-title", year = wrapped.year));
+    let _ = writeln!(
+        buf,
+        "{}",
+        tr!(
+            "wrapped-plays",
+            plays = wrapped.plays,
+            duration = format_duration(wrapped.duration_ms)
+        )
+    );
+    let _ = writeln!(
+        buf,
+        "{}",
+        tr!(
+            "wrapped-tracks",
+            tracks = wrapped.tracks,
+            artists = wrapped.artists
+        )
+    );
+
+    for (_, title, entries) in sections(wrapped) {
+        if entries.is_empty() {
+            continue;
+        }
+        let _ = writeln!(buf, "\n{}", tr!(title));
+        for (i, entry) in entries.iter().enumerate() {
+            let _ = writeln!(
+                buf,
+                "{:>3}. {} ({})",
+                i + 1,
+                entry_title(entry),
+                tr!("wrapped-entry-plays", plays = entry.plays)
+            );
+        }
+    }
+
+    if !wrapped.genres.is_empty() {
+        let _ = writeln!(buf, "\n{}", tr!("wrapped-genres"));
+        for genre in &wrapped.genres {
+            let _ = writeln!(
+                buf,
+                "{:>4}%  {}",
+                percent(genre.plays, wrapped.plays),
+                genre.name
+            );
+        }
+    }
+
+    buf
+}
+
+fn to_json(wrapped: &Wrapped) -> String {
+    let entries = |entries: &[Entry]| {
+        let values: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                json::object(&[
+                    ("name", json::string(&entry.name)),
+                    ("artist", json::opt_string(entry.artist.as_deref())),
+                    ("plays", entry.plays.to_string()),
+                ])
+            })
+            .collect();
+        json::array(&values)
+    };
+
+    let mut fields = vec![
+        ("year", wrapped.year.to_string()),
+        ("plays", wrapped.plays.to_string()),
+        ("duration_ms", wrapped.duration_ms.to_string()),
+        ("tracks", wrapped.tracks.to_string()),
+        ("artists", wrapped.artists.to_string()),
+    ];
+    for (key, _, section) in sections(wrapped) {
+        fields.push((key, entries(section)));
+    }
+
+    let genres: Vec<String> = wrapped
+        .genres
+        .iter()
+        .map(|genre| {
+            json::object(&[
+                ("name", json::string(&genre.name)),
+                ("plays", genre.plays.to_string()),
+                ("percent", percent(genre.plays, wrapped.plays).to_string()),
+            ])
+        })
+        .collect();
+    fields.push(("genres", json::array(&genres)));
+
+    json::object(&fields)
+}
+
+fn to_html(wrapped: &Wrapped) -> String {
+    let escape = |value: &str| {
+        let mut buf = String::new();
+        xml_escape(value, &mut buf);
+        buf
+    };
+
+    let mut buf = String::new();
+    let _ = write!(
+        buf,
+        "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; }}
+.stats {{ font-size: 1.3em; }}
+.plays {{ color: #777; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class=\"stats\">{plays}<br>{tracks}</p>
+",
+        title = escape(&tr!("wrapped-title", year = wrapped.year)),
+        plays = escape(&tr!(
+            "wrapped-plays",
+            plays = wrapped.plays,
+            duration = format_duration(wrapped.duration_ms)
+        )),
+        tracks = escape(&tr!(
+            "wrapped-tracks",
+            tracks = wrapped.tracks,
+            artists = wrapped.artists
+        )),
+    );
+
+    for (_, title, entries) in sections(wrapped) {
+        if entries.is_empty() {
+            continue;
+        }
+        let _ = writeln!(buf, "<h2>{}</h2>\n<ol>", escape(&tr!(title)));
+        for entry in entries {
+            let _ = writeln!(
+                buf,
+                "<li>{} <span class=\"plays\">{}</span></li>",
+                escape(&entry_title(entry)),
+                escape(&tr!("wrapped-entry-plays", plays = entry.plays))
+            );
+        }
+        buf.push_str("</ol>\n");
+    }
+
+    if !wrapped.genres.is_empty() {
+        let _ = writeln!(buf, "<h2>{}</h2>\n<ul>", escape(&tr!("wrapped-genres")));
+        for genre in &wrapped.genres {
+            let _ = writeln!(
+                buf,
+                "<li>{} <span class=\"plays\">{}%</span></li>",
+                escape(&genre.name),
+                percent(genre.plays, wrapped.plays)
+            );
+        }
+        buf.push_str("</ul>\n");
+    }
+
+    buf.push_str("</body>\n</html>\n");
+    buf
+}
+
+//
+// "wrapped" command
+//
+
+pub fn cmd_wrapped(
+    db: &mut rusqlite::Connection,
+    args: &clap::ArgMatches,
+) -> Result<(), CommandWrappedError> {
+    let value = args.value_of("year").unwrap();
+    let year: i64 = match value.parse() {
+        Ok(year) if (1000..=9998).contains(&year) => year,
+        _ => return Err(CommandWrappedError::InvalidYear(value.to_owned())),
+    };
+
+    let user = args.value_of("user");
+    if let Some(name) = user {
+        let exists: bool = db.query_row(
+            "SELECT EXISTS(SELECT 1 FROM user WHERE name = $name)",
+            [name],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(CommandWrappedError::UserNotFound(name.to_owned()));
+        }
+    }
+
+    let wrapped = load(db, year, user)?;
+
+    match args.value_of("format").unwrap_or("text") {
+        "json" => println!("{}", to_json(&wrapped)),
+        "html" => print!("{}", to_html(&wrapped)),
+        _ if wrapped.plays == 0 => println!("{}", tr!("wrapped-no-plays", year = year)),
+        _ => print!("{}", to_text(&wrapped)),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! The year of an album, computed from all its tracks once a scan is done rather than taken
+//! from the first one scanned.
+//!
+//! The year is the most common one among the tracks, the earliest on a tie; the original
+//! year, from the ORIGINALDATE or TDOR tags of reissues, is the earliest one. `zik years`
+//! lists the albums whose tracks disagree, which is usually a tagging mistake.
+
+use std::fmt;
+
+use crate::i18n::tr;
+
+pub enum CommandYearsError {
+    SQLite(rusqlite::Error),
+}
+impl From<rusqlite::Error> for CommandYearsError {
+    fn from(err: rusqlite::Error) -> CommandYearsError {
+        CommandYearsError::SQLite(err)
+    }
+}
+impl fmt::Display for CommandYearsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandYearsError::SQLite(err) => f.write_str(&tr!("sqlite-error", err = err)),
+        }
+    }
+}
+
+/// Sets the years of every album with tracks from the years of its tracks.
+pub fn update_albums(db: &rusqlite::Connection) -> rusqlite::Result<()> {
+    db.execute(
+        "UPDATE album
+         SET (release_year, year) = (
+               SELECT track.release_year, MIN(track.year)
+               FROM track
+               WHERE track.album_id = album.id
+                 AND track.release_year IS NOT NULL
+                 AND track.missing_since IS NULL
+               GROUP BY track.release_year
+               ORDER BY COUNT(*) DESC, track.release_year
+               LIMIT 1
+             ),
+             original_year = (
+               SELECT MIN(track.original_year)
+               FROM track
+               WHERE track.album_id = album.id AND track.missing_since IS NULL
+             )
+         WHERE EXISTS(
+           SELECT 1 FROM track WHERE track.album_id = album.id AND track.missing_since IS NULL
+         )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+//
+// "years" command
+//
+
+pub fn cmd_years(
+    db: &mut rusqlite::Connection,
+    _args: &clap::ArgMatches,
+) -> Result<(), CommandYearsError> {
+    let mut stmt = db.prepare(
+        "SELECT album.id, artist.name, album.name, track.release_year, COUNT(*)
+         FROM track
+         JOIN album ON album.id = track.album_id
+         LEFT JOIN artist ON artist.id = album.artist_id
+         WHERE track.missing_since IS NULL
+           AND track.release_year IS NOT NULL
+           AND album.id IN (
+             SELECT album_id
+             FROM track
+             WHERE missing_since IS NULL AND release_year IS NOT NULL
+             GROUP BY album_id
+             HAVING COUNT(DISTINCT release_year) > 1
+           )
+         GROUP BY album.id, track.release_year
+         ORDER BY artist.sort_name COLLATE natural_sort, album.sort_name COLLATE natural_sort,
+                  album.id, track.release_year",
+    )?;
+    let mut rows = stmt.query([])?;
+
+    let mut conflicts: Vec<(i64, String, Vec<String>)> = Vec::new();
+    while let Some(row) = rows.next()? {
+        let album_id: i64 = row.get(0)?;
+        let year: i64 = row.get(3)?;
+        let tracks: i64 = row.get(4)?;
+
+        if conflicts.last().map(|(id, _, _)| *id) != Some(album_id) {
+            let artist: Option<String> = row.get(1)?;
+            let album: Option<String> = row.get(2)?;
+            conflicts.push((
+                album_id,
+                format!(
+                    "{} - {}",
+                    artist.unwrap_or_default(),
+                    album.unwrap_or_default()
+                ),
+                Vec::new(),
+            ));
+        }
+
+        let (_, _, years) = conflicts.last_mut().unwrap();
+        years.push(format!(
+            "{} ({})",
+            year,
+            tr!("years-tracks", tracks = tracks)
+        ));
+    }
+
+    if conflicts.is_empty() {
+        println!("{}", tr!("years-none"));
+    }
+    for (_, album, years) in conflicts {
+        println!("{}: {}", album, years.join(", "));
+    }
+
+    Ok(())
+}
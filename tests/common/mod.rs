@@ -0,0 +1,105 @@
+//! Helpers of the integration tests: tiny fixture files, a throwaway data folder, running zik
+//! and comparing its output to golden files.
+//!
+//! Set `ZIK_UPDATE_GOLDEN=1` to write the golden files instead of comparing to them.
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[path = "../../src/fixtures.rs"]
+mod fixtures;
+
+pub use fixtures::{flac, mp3, mp4, ogg};
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A folder removed when dropped, holding a library and zik's data.
+pub struct TestDir {
+    pub path: PathBuf,
+}
+
+impl TestDir {
+    pub fn new(name: &str) -> TestDir {
+        let path = env::temp_dir().join(format!(
+            "zik-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("library")).unwrap();
+
+        // Scans store paths with their folders resolved, /tmp is a symlink on macOS
+        TestDir {
+            path: fs::canonicalize(path).unwrap(),
+        }
+    }
+
+    pub fn library(&self) -> PathBuf {
+        self.path.join("library")
+    }
+
+    pub fn database(&self) -> PathBuf {
+        self.path.join("data").join("data.db")
+    }
+
+    /// Runs zik with its data in this folder, returning its output.
+    pub fn zik(&self, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_zik-rust"))
+            .arg("--data-dir")
+            .arg(self.path.join("data"))
+            .args(args)
+            // The golden files are in English, whatever the language of the machine
+            .env("LC_ALL", "C")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "zik {:?} failed", args);
+
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    pub fn open_database(&self) -> rusqlite::Connection {
+        rusqlite::Connection::open(self.database()).unwrap()
+    }
+
+    /// Writes a fixture at `relative_path` in the library.
+    pub fn add_file(&self, relative_path: &str, data: &[u8]) -> PathBuf {
+        let path = self.library().join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, data).unwrap();
+        path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Compares `actual` to the golden file `tests/golden/<name>.txt`.
+pub fn assert_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.txt", name));
+
+    if env::var_os("ZIK_UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        expected == actual,
+        "{} differs from the golden file, run with ZIK_UPDATE_GOLDEN=1 to update it\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    );
+}
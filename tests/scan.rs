@@ -0,0 +1,511 @@
+//! Scans of a library of fixture files, checked against golden dumps of the database.
+
+mod common;
+
+use common::TestDir;
+
+/// Dumps what a scan indexed, in an order not depending on the order files are walked in.
+fn dump(test_dir: &TestDir) -> String {
+    let db = test_dir.open_database();
+    let library = format!("{}/", test_dir.library().display());
+    let mut buf = String::new();
+
+    let mut stmt = db.prepare("SELECT name FROM artist ORDER BY name").unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    while let Some(row) = rows.next().unwrap() {
+        let name: String = row.get(0).unwrap();
+        buf.push_str(&format!("artist {}\n", name));
+    }
+
+    let mut stmt = db
+        .prepare(
+            "SELECT album.name, album.year, artist.name
+             FROM album
+             LEFT JOIN artist ON artist.id = album.artist_id
+             ORDER BY album.name",
+        )
+        .unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    while let Some(row) = rows.next().unwrap() {
+        let name: String = row.get(0).unwrap();
+        let year: Option<String> = row.get(1).unwrap();
+        let artist: Option<String> = row.get(2).unwrap();
+        buf.push_str(&format!(
+            "album {} | {} | {}\n",
+            name,
+            year.unwrap_or_default(),
+            artist.unwrap_or_default()
+        ));
+    }
+
+    let mut stmt = db
+        .prepare(
+            "SELECT track.path, track.name, artist.name, album.name, track.number,
+                    track.track_total, track.year, track.genre, track.duration_ms
+             FROM track
+             LEFT JOIN artist ON artist.id = track.artist_id
+             LEFT JOIN album ON album.id = track.album_id
+             ORDER BY track.path",
+        )
+        .unwrap();
+    let mut rows = stmt.query([]).unwrap();
+    while let Some(row) = rows.next().unwrap() {
+        let path: String = row.get(0).unwrap();
+        let name: Option<String> = row.get(1).unwrap();
+        let artist: Option<String> = row.get(2).unwrap();
+        let album: Option<String> = row.get(3).unwrap();
+        let number: Option<i64> = row.get(4).unwrap();
+        let track_total: Option<i64> = row.get(5).unwrap();
+        let year: Option<String> = row.get(6).unwrap();
+        let genre: Option<String> = row.get(7).unwrap();
+        let duration_ms: Option<i64> = row.get(8).unwrap();
+        buf.push_str(&format!(
+            "track {} | {} | {} | {} | {}/{} | {} | {} | {}ms\n",
+            path.strip_prefix(&library).unwrap_or(&path),
+            name.unwrap_or_default(),
+            artist.unwrap_or_default(),
+            album.unwrap_or_default(),
+            number.unwrap_or_default(),
+            track_total.unwrap_or_default(),
+            year.unwrap_or_default(),
+            genre.unwrap_or_default(),
+            duration_ms.unwrap_or_default(),
+        ));
+    }
+
+    buf
+}
+
+fn add_fixtures(test_dir: &TestDir) {
+    test_dir.add_file(
+        "Flac Artist/Flac Album/01 First.flac",
+        &common::flac(
+            1500,
+            &[
+                ("ARTIST", "Flac Artist"),
+                ("ALBUM", "Flac Album"),
+                ("DATE", "1999-03-21"),
+                ("TITLE", "First"),
+                ("TRACKNUMBER", "1/2"),
+                ("GENRE", "hiphop"),
+            ],
+        ),
+    );
+    test_dir.add_file(
+        "Flac Artist/Flac Album/02 Second.flac",
+        &common::flac(
+            2000,
+            &[
+                ("ARTIST", "Flac Artist"),
+                ("ALBUM", "Flac Album"),
+                ("DATE", "1999-03-21"),
+                ("TITLE", "Second"),
+                ("TRACKNUMBER", "2/2"),
+                ("GENRE", "hiphop"),
+            ],
+        ),
+    );
+    test_dir.add_file(
+        "Mp3 Artist/Mp3 Album/01 Third.mp3",
+        &common::mp3(&[
+            ("TPE1", "Mp3 Artist"),
+            ("TALB", "Mp3 Album"),
+            ("TYER", "2004"),
+            ("TIT2", "Third"),
+            ("TRCK", "1/1"),
+            ("TCON", "(17)"),
+            ("TLEN", "3000"),
+        ]),
+    );
+    test_dir.add_file("Mp3 Artist/Mp3 Album/notes.txt", b"not a track");
+    test_dir.add_file(
+        "Mp4 Artist/Mp4 Album/01 Fourth.m4a",
+        &common::mp4(
+            2500,
+            &[
+                ("\u{a9}ART", "Mp4 Artist"),
+                ("\u{a9}alb", "Mp4 Album"),
+                ("\u{a9}day", "2010"),
+                ("\u{a9}nam", "Fourth"),
+                ("trkn", "1/1"),
+                ("\u{a9}gen", "Jazz"),
+            ],
+        ),
+    );
+    test_dir.add_file(
+        "Ogg Artist/Ogg Album/01 Fifth.ogg",
+        &common::ogg(
+            4000,
+            &[
+                ("ARTIST", "Ogg Artist"),
+                ("ALBUM", "Ogg Album"),
+                ("DATE", "2015-06-01"),
+                ("TITLE", "Fifth"),
+                ("TRACKNUMBER", "1"),
+                ("TRACKTOTAL", "3"),
+                ("GENRE", "Electronic"),
+            ],
+        ),
+    );
+}
+
+#[test]
+fn scan_fixture_library() {
+    let test_dir = TestDir::new("scan");
+    add_fixtures(&test_dir);
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+
+    common::assert_golden("scan", &dump(&test_dir));
+}
+
+#[test]
+fn rescan_marks_removed_files_missing() {
+    let test_dir = TestDir::new("rescan");
+    add_fixtures(&test_dir);
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+    std::fs::remove_file(library.join("Flac Artist/Flac Album/02 Second.flac")).unwrap();
+    let output = test_dir.zik(&["scan", library.to_str().unwrap()]);
+    assert!(output.contains("4 files indexed, 0 added, 0 updated, 1 removed"));
+
+    let db = test_dir.open_database();
+    let missing: Vec<String> = {
+        let mut stmt = db
+            .prepare("SELECT path FROM track WHERE missing_since IS NOT NULL")
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    };
+    assert_eq!(
+        missing,
+        vec![library
+            .join("Flac Artist/Flac Album/02 Second.flac")
+            .display()
+            .to_string()]
+    );
+}
+
+#[test]
+fn moved_file_keeps_its_track_without_hash() {
+    let test_dir = TestDir::new("move");
+    add_fixtures(&test_dir);
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+
+    // Nothing was hashed since no job ran, the move is found from the tags
+    let track_id = |db: &rusqlite::Connection| -> i64 {
+        db.query_row("SELECT id FROM track WHERE name = 'Third'", [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    };
+    let id = track_id(&test_dir.open_database());
+
+    std::fs::create_dir_all(library.join("Moved")).unwrap();
+    std::fs::rename(
+        library.join("Mp3 Artist/Mp3 Album/01 Third.mp3"),
+        library.join("Moved/Third.mp3"),
+    )
+    .unwrap();
+    let output = test_dir.zik(&["scan", library.to_str().unwrap()]);
+    assert!(output.contains("moved from a missing file, keeping the track"));
+
+    let db = test_dir.open_database();
+    assert_eq!(track_id(&db), id);
+    let path: String = db
+        .query_row("SELECT path FROM track WHERE id = $id", [id], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(path, library.join("Moved/Third.mp3").display().to_string());
+}
+
+#[test]
+fn rescan_keeps_ids() {
+    let test_dir = TestDir::new("ids");
+    add_fixtures(&test_dir);
+
+    let ids = |db: &rusqlite::Connection| -> Vec<(i64, i64, i64)> {
+        let mut stmt = db
+            .prepare("SELECT id, artist_id, album_id FROM track ORDER BY path")
+            .unwrap();
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    };
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+    let before = ids(&test_dir.open_database());
+
+    let output = test_dir.zik(&["scan", library.to_str().unwrap()]);
+    assert!(output.contains("5 files indexed, 0 added, 0 updated, 0 removed"));
+    assert_eq!(ids(&test_dir.open_database()), before);
+}
+
+#[test]
+fn relocated_library_keeps_its_tracks() {
+    let test_dir = TestDir::new("relocate");
+    add_fixtures(&test_dir);
+
+    let library = test_dir.library();
+    test_dir.zik(&["config", "library", library.to_str().unwrap()]);
+    test_dir.zik(&["scan"]);
+
+    // Paths are stored relative to the library
+    let track = |db: &rusqlite::Connection| -> (i64, String) {
+        db.query_row(
+            "SELECT id, path FROM track WHERE name = 'Third'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    };
+    let (id, path) = track(&test_dir.open_database());
+    assert_eq!(path, "Mp3 Artist/Mp3 Album/01 Third.mp3");
+
+    // The same library mounted somewhere else
+    let moved = test_dir.path.join("moved");
+    std::fs::rename(&library, &moved).unwrap();
+    test_dir.zik(&["library", "relocate", moved.to_str().unwrap()]);
+    let output = test_dir.zik(&["scan"]);
+    assert!(output.contains("5 files indexed, 0 added, 0 updated, 0 removed"));
+    assert_eq!(track(&test_dir.open_database()), (id, path));
+}
+
+#[test]
+fn scan_in_memory_leaves_the_database() {
+    let test_dir = TestDir::new("memory");
+    add_fixtures(&test_dir);
+
+    let summary = match zik_rust::scan_in_memory(&test_dir.library()) {
+        Ok((db, summary)) => {
+            let tracks: usize = db
+                .query_row("SELECT COUNT(*) FROM track", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(tracks, 5);
+            summary
+        }
+        Err(err) => panic!("scan failed: {}", err),
+    };
+    assert_eq!((summary.indexed, summary.added, summary.removed), (5, 5, 0));
+    assert_eq!(summary.new_albums.len(), 4);
+    assert!(!test_dir.database().exists());
+}
+
+#[test]
+fn scan_of_a_subfolder_leaves_the_rest_of_the_library() {
+    let test_dir = TestDir::new("subfolder");
+    add_fixtures(&test_dir);
+    test_dir.add_file("Ogg Artist/Ogg Album/02 Broken.ogg", b"OggS but no pages");
+
+    let library = test_dir.library();
+    test_dir.zik(&["config", "library", library.to_str().unwrap()]);
+    test_dir.zik(&["scan"]);
+    let indexed = dump(&test_dir);
+
+    let missing = |test_dir: &TestDir| -> Vec<String> {
+        let db = test_dir.open_database();
+        let mut stmt = db
+            .prepare("SELECT path FROM track WHERE missing_since IS NOT NULL ORDER BY path")
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    };
+
+    // Only what's gone from the folder scanned is missing
+    std::fs::remove_file(library.join("Flac Artist/Flac Album/02 Second.flac")).unwrap();
+    std::fs::remove_file(library.join("Mp4 Artist/Mp4 Album/01 Fourth.m4a")).unwrap();
+    let output = test_dir.zik(&["scan", library.join("Flac Artist").to_str().unwrap()]);
+    assert!(output.contains("1 files indexed, 0 added, 0 updated, 1 removed"));
+    assert_eq!(
+        missing(&test_dir),
+        vec!["Flac Artist/Flac Album/02 Second.flac".to_owned()]
+    );
+    assert_eq!(dump(&test_dir), indexed);
+    let output = test_dir.zik(&["unreadable", "--unsupported"]);
+    assert!(output.contains("Ogg Artist/Ogg Album/02 Broken.ogg"));
+
+    // The whole library again
+    let output = test_dir.zik(&["scan"]);
+    assert!(output.contains("3 files indexed, 0 added, 0 updated, 1 removed"));
+    assert_eq!(missing(&test_dir).len(), 2);
+}
+
+#[test]
+fn batched_rescan_keeps_unreadable_files() {
+    let test_dir = TestDir::new("batches");
+    for (number, title) in ["One", "Two", "Three"].iter().enumerate() {
+        test_dir.add_file(
+            &format!("Artist/Album/0{} {}.ogg", number + 1, title),
+            &common::ogg(
+                1000,
+                &[("ARTIST", "Artist"), ("ALBUM", "Album"), ("TITLE", title)],
+            ),
+        );
+    }
+    test_dir.add_file("Artist/Album/04 Broken.ogg", b"OggS but no pages");
+
+    // Every track is committed apart, what the previous scan couldn't read is only forgotten
+    // at the end
+    let library = test_dir.library();
+    test_dir.zik(&["config", "scan_batch_size", "1"]);
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+    let output = test_dir.zik(&["scan", library.to_str().unwrap()]);
+    assert!(output.contains("3 files indexed, 0 added, 0 updated, 0 removed"));
+
+    let output = test_dir.zik(&["unreadable", "--unsupported"]);
+    assert!(output.contains("Artist/Album/04 Broken.ogg"));
+
+    std::fs::remove_file(library.join("Artist/Album/04 Broken.ogg")).unwrap();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+    let output = test_dir.zik(&["unreadable", "--unsupported"]);
+    assert!(output.contains("no unsupported files"));
+}
+
+#[test]
+fn tracks_with_the_same_title_are_kept_apart() {
+    let test_dir = TestDir::new("same-title");
+    for album in ["First Album", "Second Album"] {
+        test_dir.add_file(
+            &format!("Artist/{}/01 Intro.flac", album),
+            &common::flac(
+                1000,
+                &[("ARTIST", "Artist"), ("ALBUM", album), ("TITLE", "Intro")],
+            ),
+        );
+    }
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+
+    let db = test_dir.open_database();
+    let count: i64 = db
+        .query_row(
+            "SELECT COUNT(*) FROM track WHERE name = 'Intro'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn albums_of_two_artists_with_the_same_name_are_kept_apart() {
+    let test_dir = TestDir::new("same-album");
+    for artist in ["First Artist", "Second Artist"] {
+        test_dir.add_file(
+            &format!("{}/Greatest Hits/01 Hit.flac", artist),
+            &common::flac(
+                1000,
+                &[
+                    ("ARTIST", artist),
+                    ("ALBUM", "Greatest Hits"),
+                    ("TITLE", "Hit"),
+                ],
+            ),
+        );
+    }
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+
+    let db = test_dir.open_database();
+    let (albums, artists): (i64, i64) = db
+        .query_row(
+            "SELECT COUNT(*), COUNT(DISTINCT artist_id) FROM album WHERE name = 'Greatest Hits'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((albums, artists), (2, 2));
+
+    let own_albums: i64 = db
+        .query_row(
+            "SELECT COUNT(*) FROM track JOIN album ON album.id = track.album_id
+             WHERE album.artist_id = track.artist_id",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(own_albums, 2);
+}
+
+// Symbolic links to folders need privileges on Windows
+#[cfg(unix)]
+#[test]
+fn symlinked_file_is_indexed_once() {
+    let test_dir = TestDir::new("symlink");
+    add_fixtures(&test_dir);
+
+    let library = test_dir.library();
+    std::os::unix::fs::symlink(library.join("Mp3 Artist"), library.join("Linked")).unwrap();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+
+    let db = test_dir.open_database();
+    let aliases: Vec<String> = {
+        let mut stmt = db
+            .prepare(
+                "SELECT track_alias.path
+                 FROM track_alias
+                 JOIN track ON track.id = track_alias.track_id
+                 WHERE track.name = 'Third'",
+            )
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    };
+    assert_eq!(
+        aliases,
+        vec![library
+            .join("Linked/Mp3 Album/01 Third.mp3")
+            .display()
+            .to_string()]
+    );
+}
+
+#[test]
+fn flac_key_synonyms_are_read() {
+    let test_dir = TestDir::new("vorbis-synonyms");
+    test_dir.add_file(
+        "Artist/Album/03 Song.flac",
+        &common::flac(
+            1000,
+            &[
+                ("ARTIST", "Artist"),
+                ("ALBUM", "Album"),
+                ("TITLE", "Song"),
+                ("YEAR", "2004"),
+                ("TRACK_NUMBER", "3"),
+                ("TOTALTRACKS", "9"),
+                ("DISCNUMBER", "2/2"),
+            ],
+        ),
+    );
+
+    let library = test_dir.library();
+    test_dir.zik(&["scan", library.to_str().unwrap()]);
+
+    let db = test_dir.open_database();
+    let row: (i64, i64, i64, i64, String) = db
+        .query_row(
+            "SELECT number, track_total, disc_number, disc_total, year FROM track",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert_eq!(row, (3, 9, 2, 2, "2004".to_owned()));
+}